@@ -6,8 +6,10 @@
 	context::Context,
 	debug::{self, DebugCommand},
 	federation::{self, FederationCommand},
+	jobs::{self, JobsCommand},
 	media::{self, MediaCommand},
 	query::{self, QueryCommand},
+	reports::{self, ReportsCommand},
 	room::{self, RoomCommand},
 	server::{self, ServerCommand},
 	token::{self, TokenCommand},
@@ -52,6 +54,14 @@ pub(super) enum AdminCommand {
 	#[command(subcommand)]
 	/// - Commands for managing registration tokens
 	Token(TokenCommand),
+
+	#[command(subcommand)]
+	/// - Commands for reviewing reports of rooms and events
+	Reports(ReportsCommand),
+
+	#[command(subcommand)]
+	/// - Commands for inspecting background jobs
+	Jobs(JobsCommand),
 }
 
 #[tracing::instrument(skip_all, name = "command")]
@@ -68,5 +78,7 @@ pub(super) async fn process(command: AdminCommand, context: &Context<'_>) -> Res
 		| Debug(command) => debug::process(command, context).await,
 		| Query(command) => query::process(command, context).await,
 		| Token(command) => token::process(command, context).await,
+		| Reports(command) => reports::process(command, context).await,
+		| Jobs(command) => jobs::process(command, context).await,
 	}
 }
@@ -5,7 +5,7 @@
 	io::{AsyncWriteExt, BufWriter},
 	lock::Mutex,
 };
-use ruma::EventId;
+use ruma::{EventId, UserId};
 use tuwunel_core::Result;
 use tuwunel_service::Services;
 
@@ -14,6 +14,9 @@ pub(crate) struct Context<'a> {
 	pub(crate) body: &'a [&'a str],
 	pub(crate) timer: SystemTime,
 	pub(crate) reply_id: Option<&'a EventId>,
+
+	/// Admin who issued the command; None from the console or the config.
+	pub(crate) sender: Option<&'a UserId>,
 	pub(crate) output: Mutex<BufWriter<Vec<u8>>>,
 }
 
@@ -1,6 +1,9 @@
+use std::fmt::Write;
+
 use futures::StreamExt;
-use ruma::{OwnedRoomId, OwnedServerName, OwnedUserId};
-use tuwunel_core::{Err, Result};
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedServerName, OwnedUserId};
+use tuwunel_core::{Err, Result, utils::time};
+use tuwunel_service::server_keys::keys_expired;
 
 use crate::{admin_command, get_room_info};
 
@@ -99,3 +102,47 @@ pub(super) async fn remote_user_in_rooms(&self, user_id: OwnedUserId) -> Result
 	self.write_str(&format!("Rooms {user_id} shares with us ({num}):\n```\n{body}\n```",))
 		.await
 }
+
+#[admin_command]
+pub(super) async fn signing_keys(&self, server_name: OwnedServerName, refetch: bool) -> Result {
+	let server_keys = &self.services.server_keys;
+	let keys = if refetch {
+		server_keys
+			.refetch_signing_keys(&server_name)
+			.await?
+	} else {
+		server_keys.signing_keys_for(&server_name).await?
+	};
+
+	let format_ts = |ts: MilliSecondsSinceUnixEpoch| {
+		ts.to_system_time()
+			.map(|ts| time::format(ts, "%+"))
+			.unwrap_or_else(|| ts.get().to_string())
+	};
+
+	let status = if !keys_expired(&keys) {
+		"valid"
+	} else if server_keys.expired_keys_accepted(&keys) {
+		"expired, accepted under key_expiry_policy"
+	} else {
+		"expired, rejected under key_expiry_policy"
+	};
+
+	let mut out = String::new();
+	writeln!(
+		out,
+		"Signing keys of {server_name} valid until {} ({status}):\n",
+		format_ts(keys.valid_until_ts)
+	)?;
+	writeln!(out, "| Key ID | Expired | Public Key |")?;
+	writeln!(out, "| --- | --- | --- |")?;
+	for (key_id, key) in &keys.verify_keys {
+		writeln!(out, "| {key_id} | | {} |", key.key)?;
+	}
+
+	for (key_id, key) in &keys.old_verify_keys {
+		writeln!(out, "| {key_id} | {} | {} |", format_ts(key.expired_ts), key.key)?;
+	}
+
+	self.write_str(&out).await
+}
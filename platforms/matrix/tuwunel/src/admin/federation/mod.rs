@@ -35,6 +35,17 @@ pub(super) enum FederationCommand {
 		server_name: OwnedServerName,
 	},
 
+	/// - Shows the cached signing keys of a server and their validity
+	///
+	/// With --refetch the cached keys are discarded and fetched again from
+	/// the server, else from the trusted key servers, before being shown.
+	SigningKeys {
+		server_name: OwnedServerName,
+
+		#[arg(long)]
+		refetch: bool,
+	},
+
 	/// - Lists all the rooms we share/track with the specified *remote* user
 	RemoteUserInRooms {
 		user_id: OwnedUserId,
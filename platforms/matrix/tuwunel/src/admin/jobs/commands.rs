@@ -0,0 +1,59 @@
+use std::{fmt::Write, time::Duration};
+
+use futures::StreamExt;
+use tuwunel_core::{
+	Err, Result,
+	utils::{ReadyExt, time},
+};
+use tuwunel_service::jobs::State;
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn list(&self, all: bool) -> Result {
+	let jobs: Vec<_> = self
+		.services
+		.jobs
+		.jobs()
+		.ready_filter(|(_, job)| all || matches!(job.state, State::Queued | State::Running))
+		.collect()
+		.await;
+
+	if jobs.is_empty() {
+		return Err!("No jobs queued or running.");
+	}
+
+	let mut out = String::new();
+	writeln!(out, "| ID | Task | State | Attempts | Created | Result |")?;
+	writeln!(out, "| --- | --- | --- | --- | --- | --- |")?;
+	for (id, job) in jobs {
+		let created = time::timepoint_from_epoch(Duration::from_secs(job.created_at))?;
+		writeln!(
+			out,
+			"| {id} | {} | {} | {} | {} | {} |",
+			job.task,
+			job.state,
+			job.attempts,
+			time::format(created, "%+"),
+			job.result.as_deref().unwrap_or_default(),
+		)?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn cancel(&self, id: u64) -> Result {
+	self.services.jobs.cancel(id).await?;
+
+	self.write_str(&format!("Job {id} cancelled."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn retry(&self, id: u64) -> Result {
+	self.services.jobs.retry(id).await?;
+
+	self.write_str(&format!("Job {id} queued again."))
+		.await
+}
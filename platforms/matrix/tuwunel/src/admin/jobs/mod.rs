@@ -0,0 +1,27 @@
+mod commands;
+
+use clap::Subcommand;
+use tuwunel_core::Result;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum JobsCommand {
+	/// - List the background jobs queued or running
+	List {
+		/// Also list the jobs which finished in the last week.
+		#[arg(short, long)]
+		all: bool,
+	},
+
+	/// - Cancel a queued or running job
+	Cancel {
+		id: u64,
+	},
+
+	/// - Queue a failed or cancelled job again
+	Retry {
+		id: u64,
+	},
+}
@@ -1,11 +1,19 @@
-use std::time::Duration;
+use std::{fmt::Write, time::Duration};
 
-use ruma::{Mxc, OwnedEventId, OwnedMxcUri, OwnedServerName};
+use futures::StreamExt;
+use ruma::{Mxc, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedServerName};
 use tuwunel_core::{
 	Err, Result, debug, debug_info, debug_warn, error, info, trace,
-	utils::time::parse_timepoint_ago, warn,
+	utils::{
+		bytes::pretty,
+		time::{parse_duration, parse_timepoint_ago},
+	},
+	warn,
+};
+use tuwunel_service::{
+	jobs::Task,
+	media::{Dim, Owner},
 };
-use tuwunel_service::media::Dim;
 
 use crate::{admin_command, utils::parse_local_user_id};
 
@@ -330,6 +338,175 @@ pub(super) async fn delete_all_from_server(
 		.await
 }
 
+#[admin_command]
+pub(super) async fn usage(
+	&self,
+	user: Option<String>,
+	server: Option<OwnedServerName>,
+	room: Option<OwnedRoomId>,
+	limit: usize,
+) -> Result {
+	let pretty = |bytes: u64| pretty(bytes.try_into().unwrap_or(usize::MAX));
+
+	let owner = match (user, server, room) {
+		| (Some(user), None, None) =>
+			Some(Owner::User(parse_local_user_id(self.services, &user)?)),
+		| (None, Some(server), None) => Some(Owner::Server(server)),
+		| (None, None, Some(room)) => Some(Owner::Room(room)),
+		| (None, None, None) => None,
+		| _ => {
+			return Err!("Please specify only one of a user, a server or a room.");
+		},
+	};
+
+	if let Some(owner) = owner {
+		let bytes = pretty(self.services.media.usage(&owner).await);
+		return match owner {
+			| Owner::User(user_id) =>
+				self.write_str(&format!("{user_id}: {bytes}"))
+					.await,
+			| Owner::Server(server_name) =>
+				self.write_str(&format!("{server_name}: {bytes}"))
+					.await,
+			| Owner::Room(room_id) =>
+				self.write_str(&format!("{room_id}: {bytes}"))
+					.await,
+		};
+	}
+
+	let mut users: Vec<_> = self.services.media.users_usage().collect().await;
+	users.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+	let mut servers: Vec<_> = self
+		.services
+		.media
+		.servers_usage()
+		.collect()
+		.await;
+	servers.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+	let mut rooms: Vec<_> = self.services.media.rooms_usage().collect().await;
+	rooms.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+	let quota = self.services.server.config.media_quota_user_bytes;
+	let mut out = String::new();
+	writeln!(out, "Local users ({} total):", users.len())?;
+	for (user_id, bytes) in users.iter().take(limit) {
+		match quota {
+			| 0 => writeln!(out, "- {user_id}: {}", pretty(*bytes))?,
+			| quota => writeln!(out, "- {user_id}: {} of {}", pretty(*bytes), pretty(quota))?,
+		}
+	}
+
+	writeln!(out, "\nRemote servers ({} total):", servers.len())?;
+	for (server_name, bytes) in servers.iter().take(limit) {
+		writeln!(out, "- {server_name}: {}", pretty(*bytes))?;
+	}
+
+	let room_quota = self.services.server.config.media_quota_room_bytes;
+	writeln!(out, "\nRooms ({} total):", rooms.len())?;
+	for (room_id, bytes) in rooms.iter().take(limit) {
+		match room_quota {
+			| 0 => writeln!(out, "- {room_id}: {}", pretty(*bytes))?,
+			| quota => writeln!(out, "- {room_id}: {} of {}", pretty(*bytes), pretty(quota))?,
+		}
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn recount_usage(&self) -> Result {
+	let count = self.services.media.recount_usage().await?;
+
+	self.write_str(&format!("Recounted usage of {count} media files."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn purge_remote_media(&self, duration: Option<String>) -> Result {
+	let task = match duration {
+		| Some(duration) => Task::PurgeRemoteMedia {
+			max_age: parse_duration(&duration)?.as_secs(),
+		},
+		| None if self
+			.services
+			.server
+			.config
+			.media_retention_remote_seconds
+			== 0 =>
+			return Err!(
+				"media_retention_remote_seconds is not configured; please specify a duration."
+			),
+		| None => Task::MediaRetention,
+	};
+
+	let id = self.services.jobs.enqueue(task);
+
+	self.write_str(&format!("Queued job {id} to purge remote media; see `!admin jobs list`."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn quarantine(&self, mxc: OwnedMxcUri) -> Result {
+	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
+	self.services
+		.media
+		.quarantine(&mxc, &self.services.globals.server_user);
+
+	self.write_str(&format!("Quarantined {mxc}."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn release(&self, mxc: OwnedMxcUri) -> Result {
+	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
+	self.services.media.release(&mxc).await;
+
+	self.write_str(&format!("Released {mxc} and cleared its flags."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn flag_nsfw(&self, mxc: OwnedMxcUri, clear: bool) -> Result {
+	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
+	self.services.media.set_nsfw(&mxc, !clear).await;
+
+	let action = if clear {
+		"Cleared the NSFW flag of"
+	} else {
+		"Flagged as NSFW"
+	};
+	self.write_str(&format!("{action} {mxc}.")).await
+}
+
+#[admin_command]
+pub(super) async fn list_flagged(&self) -> Result {
+	let flagged = self.services.media.flagged().await;
+	if flagged.is_empty() {
+		return self.write_str("No media is flagged.").await;
+	}
+
+	let mut out =
+		String::from("| MXC | Quarantined by | NSFW | Reports |\n| --- | --- | --- | --- |\n");
+	for (mxc, flagged) in &flagged {
+		let quarantined_by = flagged
+			.quarantined_by
+			.as_ref()
+			.map(ToString::to_string)
+			.unwrap_or_default();
+
+		writeln!(
+			out,
+			"| {mxc} | {quarantined_by} | {} | {} |",
+			if flagged.flags.nsfw { "yes" } else { "" },
+			flagged.flags.reports,
+		)?;
+	}
+
+	self.write_str(&out).await
+}
+
 #[admin_command]
 pub(super) async fn get_file_info(&self, mxc: OwnedMxcUri) -> Result {
 	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
@@ -2,7 +2,7 @@
 mod commands;
 
 use clap::Subcommand;
-use ruma::{OwnedEventId, OwnedMxcUri, OwnedServerName};
+use ruma::{OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedServerName};
 use tuwunel_core::Result;
 
 use crate::admin_command_dispatch;
@@ -64,6 +64,63 @@ pub(super) enum MediaCommand {
 		yes_i_want_to_delete_local_media: bool,
 	},
 
+	/// - Shows the disk usage of media per local user, remote server and room,
+	///   largest first, or of a single one of them.
+	Usage {
+		/// Local user to show usage for
+		#[arg(long)]
+		user: Option<String>,
+
+		/// Remote server to show usage for
+		#[arg(long)]
+		server: Option<OwnedServerName>,
+
+		/// Room to show usage for
+		#[arg(long)]
+		room: Option<OwnedRoomId>,
+
+		/// Maximum number of users, servers and rooms listed
+		#[arg(short, long, default_value("20"))]
+		limit: usize,
+	},
+
+	/// - Recounts the disk usage of all media from the media directory.
+	RecountUsage,
+
+	/// - Quarantines media: it is kept on disk but not served to clients nor
+	///   fetched again from its origin until released.
+	Quarantine {
+		/// The MXC URL to quarantine
+		mxc: OwnedMxcUri,
+	},
+
+	/// - Releases media from quarantine and clears its flags after review.
+	Release {
+		/// The MXC URL to release
+		mxc: OwnedMxcUri,
+	},
+
+	/// - Flags media as NSFW, or clears the flag.
+	FlagNsfw {
+		/// The MXC URL to flag
+		mxc: OwnedMxcUri,
+
+		/// Clear the flag instead
+		#[arg(long)]
+		clear: bool,
+	},
+
+	/// - Lists media quarantined or flagged for review: as NSFW, or as
+	///   referenced by reported events.
+	ListFlagged,
+
+	/// - Queues a job deleting remote media older than [duration], or older
+	///   than `media_retention_remote_seconds` when no duration is given.
+	PurgeRemoteMedia {
+		/// - The relative time (e.g. 30s, 5m, 7d)
+		duration: Option<String>,
+	},
+
 	GetFileInfo {
 		/// The MXC URL to lookup info for.
 		mxc: OwnedMxcUri,
@@ -9,8 +9,10 @@
 pub(crate) mod appservice;
 pub(crate) mod debug;
 pub(crate) mod federation;
+pub(crate) mod jobs;
 pub(crate) mod media;
 pub(crate) mod query;
+pub(crate) mod reports;
 pub(crate) mod room;
 pub(crate) mod server;
 pub(crate) mod token;
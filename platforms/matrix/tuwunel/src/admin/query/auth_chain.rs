@@ -0,0 +1,71 @@
+use clap::Subcommand;
+use ruma::{EventId, RoomOrAliasId};
+use tuwunel_core::{Err, Result, utils::bytes::pretty};
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+/// Query the auth chain cache
+pub(crate) enum AuthChainCommand {
+	/// - Show the number and size of cached auth chains and the hits and misses
+	///   of the cache since startup
+	Stats,
+
+	/// - Remove the cached auth chains starting from an event, or from any
+	///   event of a room
+	Invalidate {
+		/// Event ID, room ID or room alias
+		target: String,
+	},
+}
+
+#[admin_command]
+pub(super) async fn stats(&self) -> Result {
+	let stats = self.services.auth_chain.cache_stats().await?;
+	let lookups = stats.hits.saturating_add(stats.misses);
+	let hit_rate = stats
+		.hits
+		.saturating_mul(100)
+		.checked_div(lookups)
+		.map_or_else(|| "n/a".to_owned(), |percent| format!("{percent}%"));
+
+	self.write_str(&format!(
+		"| Table | Entries | Size |\n| ----- | ------- | ---- |\n| authchainkey_authchain | {} \
+		 | {} |\n| shorteventid_authchain | {} | {} |\n\nHits: {} Misses: {} Hit rate: \
+		 {hit_rate}",
+		stats.chunk_entries,
+		pretty(stats.chunk_bytes),
+		stats.event_entries,
+		pretty(stats.event_bytes),
+		stats.hits,
+		stats.misses,
+	))
+	.await
+}
+
+#[admin_command]
+pub(super) async fn invalidate(&self, target: String) -> Result {
+	let removed = if let Ok(event_id) = EventId::parse(&target) {
+		self.services
+			.auth_chain
+			.invalidate_event(&event_id)
+			.await?
+	} else if let Ok(room_id) = RoomOrAliasId::parse(&target) {
+		let room_id = self
+			.services
+			.alias
+			.maybe_resolve(&room_id)
+			.await?;
+
+		self.services
+			.auth_chain
+			.invalidate_room(&room_id)
+			.await?
+	} else {
+		return Err!("{target} is not an event ID, room ID or room alias.");
+	};
+
+	self.write_str(&format!("Removed {removed} cached auth chains."))
+		.await
+}
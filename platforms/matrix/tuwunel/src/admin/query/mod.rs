@@ -1,5 +1,6 @@
 mod account_data;
 mod appservice;
+mod auth_chain;
 mod globals;
 mod oauth;
 mod presence;
@@ -18,11 +19,12 @@
 use tuwunel_core::Result;
 
 use self::{
-	account_data::AccountDataCommand, appservice::AppserviceCommand, globals::GlobalsCommand,
-	oauth::OauthCommand, presence::PresenceCommand, pusher::PusherCommand, raw::RawCommand,
-	resolver::ResolverCommand, room_alias::RoomAliasCommand,
-	room_state_cache::RoomStateCacheCommand, room_timeline::RoomTimelineCommand,
-	sending::SendingCommand, short::ShortCommand, sync::SyncCommand, users::UsersCommand,
+	account_data::AccountDataCommand, appservice::AppserviceCommand,
+	auth_chain::AuthChainCommand, globals::GlobalsCommand, oauth::OauthCommand,
+	presence::PresenceCommand, pusher::PusherCommand, raw::RawCommand, resolver::ResolverCommand,
+	room_alias::RoomAliasCommand, room_state_cache::RoomStateCacheCommand,
+	room_timeline::RoomTimelineCommand, sending::SendingCommand, short::ShortCommand,
+	sync::SyncCommand, users::UsersCommand,
 };
 use crate::admin_command_dispatch;
 
@@ -38,6 +40,10 @@ pub(super) enum QueryCommand {
 	#[command(subcommand)]
 	Appservice(AppserviceCommand),
 
+	/// - rooms/auth_chain cache statistics and invalidation
+	#[command(subcommand)]
+	AuthChain(AuthChainCommand),
+
 	/// - presence.rs iterators and getters
 	#[command(subcommand)]
 	Presence(PresenceCommand),
@@ -1,6 +1,14 @@
+use std::fmt::Write;
+
 use clap::Subcommand;
-use ruma::OwnedUserId;
-use tuwunel_core::Result;
+use futures::StreamExt;
+use ruma::{
+	OwnedEventId, OwnedUserId,
+	events::{AnySyncTimelineEvent, GlobalAccountDataEventType, push_rules::PushRulesEvent},
+	push::{AnyPushRuleRef, FlattenedJson, Ruleset},
+	serde::Raw,
+};
+use tuwunel_core::{Result, matrix::Event};
 use tuwunel_macros::{admin_command, admin_command_dispatch};
 
 #[admin_command_dispatch]
@@ -20,6 +28,23 @@ pub(crate) enum PusherCommand {
 		/// Pushkey
 		pushkey: String,
 	},
+
+	/// - Returns the pushes which were given up on after failing to be
+	///   delivered, optionally only those of a user.
+	DeadLetters {
+		/// Full user ID
+		user_id: Option<OwnedUserId>,
+	},
+
+	/// - Evaluates the push rules of a user against a stored event, showing
+	///   which conditions passed, which rule matched and its actions.
+	Explain {
+		/// Full user ID
+		user_id: OwnedUserId,
+
+		/// Event ID
+		event_id: OwnedEventId,
+	},
 }
 
 #[admin_command]
@@ -54,3 +79,96 @@ pub(super) async fn remove_pusher(&self, user_id: OwnedUserId, pushkey: String)
 
 	self.write_str(message).await
 }
+
+#[admin_command]
+pub(super) async fn dead_letters(&self, user_id: Option<OwnedUserId>) -> Result {
+	let timer = tokio::time::Instant::now();
+	let results: Vec<_> = self
+		.services
+		.pusher
+		.dead_letters(user_id.as_deref())
+		.collect()
+		.await;
+	let query_time = timer.elapsed();
+
+	self.write_string(format!("Query completed in {query_time:?}:\n\n```rs\n{results:#?}```"))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn explain(&self, user_id: OwnedUserId, event_id: OwnedEventId) -> Result {
+	let pdu = self.services.timeline.get_pdu(&event_id).await?;
+	let ruleset = self
+		.services
+		.account_data
+		.get_global::<PushRulesEvent>(&user_id, GlobalAccountDataEventType::PushRules)
+		.await
+		.map_or_else(|_| Ruleset::server_default(&user_id), |ev| ev.content.global);
+
+	let power_levels = self
+		.services
+		.state_accessor
+		.get_power_levels(pdu.room_id())
+		.await
+		.ok();
+
+	let ctx = self
+		.services
+		.pusher
+		.push_context(&user_id, power_levels.as_ref(), pdu.room_id())
+		.await;
+
+	let serialized: Raw<AnySyncTimelineEvent> = pdu.to_format();
+	let event = FlattenedJson::from_raw(&serialized);
+
+	let mut out = String::new();
+	writeln!(out, "| Kind | Rule | Enabled | Conditions | Matched |")?;
+	writeln!(out, "| ---- | ---- | ------- | ---------- | ------- |")?;
+
+	let mut matched = None;
+	for rule in ruleset.iter() {
+		let (kind, conditions) = match rule {
+			| AnyPushRuleRef::Override(rule) => ("override", Some(&rule.conditions)),
+			| AnyPushRuleRef::Content(_) => ("content", None),
+			| AnyPushRuleRef::PostContent(rule) => ("postcontent", Some(&rule.conditions)),
+			| AnyPushRuleRef::Room(_) => ("room", None),
+			| AnyPushRuleRef::Sender(_) => ("sender", None),
+			| AnyPushRuleRef::Underride(rule) => ("underride", Some(&rule.conditions)),
+		};
+
+		let mut evaluated = Vec::new();
+		for condition in conditions.into_iter().flatten() {
+			let passed = condition.applies(&event, &ctx).await;
+			evaluated.push(format!("{}: `{condition:?}`", if passed { "pass" } else { "fail" }));
+		}
+
+		if let AnyPushRuleRef::Content(rule) = rule {
+			evaluated.push(format!("pattern: `{}`", rule.pattern));
+		}
+
+		let applies = rule.enabled() && rule.applies(&event, &ctx).await;
+		if applies && matched.is_none() {
+			matched = Some(rule);
+		}
+
+		writeln!(
+			out,
+			"| {kind} | {} | {} | {} | {applies} |",
+			rule.rule_id(),
+			rule.enabled(),
+			evaluated.join("<br>"),
+		)?;
+	}
+
+	match matched {
+		| Some(rule) => writeln!(
+			out,
+			"\nMatched rule `{}` with actions:\n\n```rs\n{:#?}\n```",
+			rule.rule_id(),
+			rule.actions(),
+		)?,
+		| None => writeln!(out, "\nNo rule matched; the event does not notify.")?,
+	}
+
+	self.write_str(&out).await
+}
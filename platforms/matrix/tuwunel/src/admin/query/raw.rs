@@ -9,6 +9,7 @@
 	itertools::Itertools,
 	utils::{
 		TryReadyExt,
+		bytes::pretty,
 		math::Expected,
 		stream::{IterStream, ReadyExt, TryIgnore, TryParallelExt},
 		string::EMPTY,
@@ -212,8 +213,10 @@ pub(super) async fn raw_compact(
 		.into_iter()
 		.try_stream()
 		.paralleln_and_then(runtime, parallelism, move |map| {
+			let before = map.sst_files_size()?;
 			map.compact_blocking(options.clone())?;
-			Ok(map.name().to_owned())
+			let after = map.sst_files_size()?;
+			Ok((map.name().to_owned(), size(before), size(after)))
 		})
 		.collect::<Vec<_>>();
 
@@ -530,3 +533,5 @@ fn encode(data: &[u8]) -> String {
 
 	res
 }
+
+fn size(bytes: u64) -> String { pretty(bytes.try_into().unwrap_or(usize::MAX)) }
@@ -18,6 +18,12 @@ pub(crate) enum ResolverCommand {
 	OverridesCache {
 		name: Option<String>,
 	},
+
+	/// Query the connection attempts and failures per address family of the
+	/// federation destinations resolving to both IPv6 and IPv4
+	AddressFamilies {
+		name: Option<String>,
+	},
 }
 
 #[admin_command]
@@ -74,3 +80,38 @@ async fn overrides_cache(&self, server_name: Option<String>) -> Result {
 
 	Ok(())
 }
+
+#[admin_command]
+async fn address_families(&self, name: Option<String>) -> Result {
+	writeln!(
+		self,
+		"| Name | IPv6 attempts | IPv6 failures | IPv4 attempts | IPv4 failures | Last winner |"
+	)
+	.await?;
+	writeln!(
+		self,
+		"| ---- | -------------:| -------------:| -------------:| -------------:| ----------- |"
+	)
+	.await?;
+
+	let mut stats = self.services.resolver.eyeballs.stats();
+	stats.sort_by(|(a, _), (b, _)| a.cmp(b));
+	for (host, stats) in stats {
+		if name.as_ref().is_some_and(|name| *name != host) {
+			continue;
+		}
+
+		let winner = stats
+			.winner
+			.map(|(family, _)| family.to_string())
+			.unwrap_or_default();
+
+		self.write_str(&format!(
+			"| {host} | {} | {} | {} | {} | {winner} |\n",
+			stats.ipv6_attempts, stats.ipv6_failures, stats.ipv4_attempts, stats.ipv4_failures,
+		))
+		.await?;
+	}
+
+	Ok(())
+}
@@ -1,7 +1,31 @@
-use clap::Subcommand;
+use std::{fmt::Write, path::PathBuf, time::SystemTime};
+
+use clap::{Subcommand, ValueEnum};
 use futures::StreamExt;
-use ruma::{OwnedRoomId, OwnedServerName, OwnedUserId};
-use tuwunel_core::Result;
+use ruma::{
+	MilliSecondsSinceUnixEpoch, Mxc, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId,
+	OwnedServerName, OwnedUserId, RoomOrAliasId, UInt, UserId,
+	events::{
+		StateEventType,
+		room::{
+			member::{MembershipState, RoomMemberEventContent},
+			message::{FileInfo, FileMessageEventContent, MessageType, RoomMessageEventContent},
+			power_levels::UserPowerLevel,
+		},
+	},
+};
+use serde::Serialize;
+use tokio::fs;
+use tuwunel_core::{
+	Event, Result,
+	utils::{
+		self,
+		content_disposition::make_content_disposition,
+		stream::{ReadyExt, TryIgnore},
+		time,
+	},
+};
+use tuwunel_service::{Services, media::MXC_LENGTH};
 
 use crate::Context;
 
@@ -78,6 +102,43 @@ pub(crate) enum RoomStateCacheCommand {
 	UserMemberships {
 		user_id: OwnedUserId,
 	},
+
+	/// Export the members of a room with their display name, membership, join
+	/// time and power level. The export is written to the file given by
+	/// --output on the server, or else uploaded to the admin room.
+	ExportMembers {
+		room_id: OwnedRoomOrAliasId,
+
+		#[arg(long, value_enum, default_value = "csv")]
+		format: ExportFormat,
+
+		/// Path of the file to write on the server.
+		#[arg(short, long)]
+		output: Option<PathBuf>,
+	},
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum ExportFormat {
+	/// Comma-separated values with a header row
+	Csv,
+
+	/// JSON array of members
+	Json,
+}
+
+/// A room member as exported by `export-members`.
+#[derive(Debug, Serialize)]
+struct Member {
+	user_id: OwnedUserId,
+	displayname: Option<String>,
+	membership: MembershipState,
+
+	/// Time of the member event which joined the user, for joined members.
+	joined_at: Option<String>,
+
+	/// The user's power level; `infinite` for the room's creators.
+	power_level: String,
 }
 
 pub(super) async fn process(subcommand: RoomStateCacheCommand, context: &Context<'_>) -> Result {
@@ -326,6 +387,8 @@ pub(super) async fn process(subcommand: RoomStateCacheCommand, context: &Context
 				))
 				.await
 		},
+		| RoomStateCacheCommand::ExportMembers { room_id, format, output } =>
+			export_members(context, &room_id, format, output).await,
 		| RoomStateCacheCommand::UserMemberships { user_id } => {
 			let timer = tokio::time::Instant::now();
 			let results = services
@@ -344,3 +407,162 @@ pub(super) async fn process(subcommand: RoomStateCacheCommand, context: &Context
 		},
 	}
 }
+
+async fn export_members(
+	context: &Context<'_>,
+	room_id: &RoomOrAliasId,
+	format: ExportFormat,
+	output: Option<PathBuf>,
+) -> Result {
+	let services = context.services;
+	let room_id = services.alias.maybe_resolve(room_id).await?;
+	let power_levels = services
+		.state_accessor
+		.get_power_levels(&room_id)
+		.await?;
+
+	let members: Vec<Member> = services
+		.state_accessor
+		.room_state_type_pdus(&room_id, &StateEventType::RoomMember)
+		.ignore_err()
+		.ready_filter_map(|pdu| {
+			let user_id = UserId::parse(pdu.state_key()?).ok()?;
+			let content: RoomMemberEventContent = pdu.get_content().ok()?;
+			let joined = (content.membership == MembershipState::Join).then(|| {
+				let replaces_state = pdu.get_unsigned_property("replaces_state").ok();
+				(pdu.origin_server_ts(), replaces_state)
+			});
+
+			let power_level = match power_levels.for_user(&user_id) {
+				| UserPowerLevel::Infinite => "infinite".to_owned(),
+				| UserPowerLevel::Int(level) => level.to_string(),
+			};
+
+			let member = Member {
+				user_id,
+				displayname: content.displayname,
+				membership: content.membership,
+				joined_at: None,
+				power_level,
+			};
+
+			Some((member, joined))
+		})
+		.then(async |(mut member, joined)| {
+			if let Some((origin_server_ts, replaces_state)) = joined {
+				member.joined_at = joined_at(services, origin_server_ts, replaces_state)
+					.await
+					.map(|ts| time::format(ts, "%+"));
+			}
+
+			member
+		})
+		.collect()
+		.await;
+
+	let (body, content_type, extension) = match format {
+		| ExportFormat::Json =>
+			(serde_json::to_string_pretty(&members)?, "application/json", "json"),
+		| ExportFormat::Csv => (members_csv(&members)?, "text/csv", "csv"),
+	};
+
+	if let Some(output) = output {
+		fs::write(&output, &body).await?;
+
+		return context
+			.write_str(&format!("Exported {} members to {}.", members.len(), output.display()))
+			.await;
+	}
+
+	let filename = format!("members-{room_id}.{extension}");
+	let mxc = Mxc {
+		server_name: services.globals.server_name(),
+		media_id: &utils::random_string(MXC_LENGTH),
+	};
+
+	let content_disposition = make_content_disposition(None, Some(content_type), Some(&filename));
+	services
+		.media
+		.create(
+			&mxc,
+			Some(&services.globals.server_user),
+			Some(&content_disposition),
+			Some(content_type),
+			body.as_bytes(),
+		)
+		.await?;
+
+	let mut info = FileInfo::new();
+	info.mimetype = Some(content_type.to_owned());
+	info.size = UInt::new(body.len().try_into()?);
+
+	let file =
+		FileMessageEventContent::plain(filename, mxc.to_string().into()).info(Box::new(info));
+	services
+		.admin
+		.send_message(RoomMessageEventContent::new(MessageType::File(file)))
+		.await?;
+
+	context
+		.write_str(&format!("Exported {} members to {mxc}.", members.len()))
+		.await
+}
+
+/// Time at which a joined member joined: that of the earliest of the join
+/// events their membership went through without leaving, so that changes of
+/// their profile do not count as joining again.
+async fn joined_at(
+	services: &Services,
+	mut origin_server_ts: MilliSecondsSinceUnixEpoch,
+	mut replaces_state: Option<OwnedEventId>,
+) -> Option<SystemTime> {
+	while let Some(event_id) = replaces_state.take() {
+		let Ok(pdu) = services.timeline.get_pdu(&event_id).await else {
+			break;
+		};
+
+		if pdu
+			.get_content::<RoomMemberEventContent>()
+			.is_ok_and(|content| content.membership == MembershipState::Join)
+		{
+			origin_server_ts = pdu.origin_server_ts();
+			replaces_state = pdu.get_unsigned_property("replaces_state").ok();
+		}
+	}
+
+	origin_server_ts.to_system_time()
+}
+
+fn members_csv(members: &[Member]) -> Result<String> {
+	let field = |value: &str| {
+		// A leading formula character is escaped so that spreadsheets do not
+		// evaluate the cell.
+		let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+			format!("'{value}")
+		} else {
+			value.to_owned()
+		};
+
+		if value.contains([',', '"', '\n', '\r']) {
+			format!("\"{}\"", value.replace('"', "\"\""))
+		} else {
+			value
+		}
+	};
+
+	let mut out = String::new();
+	writeln!(out, "user_id,displayname,membership,joined_at,power_level")?;
+	for member in members {
+		writeln!(
+			out,
+			"{},{},{},{},{}",
+			field(member.user_id.as_str()),
+			field(member.displayname.as_deref().unwrap_or_default()),
+			field(member.membership.as_str()),
+			member.joined_at.as_deref().unwrap_or_default(),
+			member.power_level,
+		)?;
+	}
+
+	Ok(out)
+}
@@ -1,7 +1,16 @@
-use clap::Subcommand;
-use futures::TryStreamExt;
-use ruma::OwnedRoomOrAliasId;
-use tuwunel_core::{PduCount, Result, utils::stream::TryTools};
+use std::{collections::HashSet, fmt::Write};
+
+use clap::{Subcommand, ValueEnum};
+use futures::{StreamExt, TryStreamExt};
+use ruma::{EventId, OwnedRoomOrAliasId};
+use serde_json::json;
+use tuwunel_core::{
+	Event, PduCount, PduEvent, Result,
+	utils::{
+		TryReadyExt,
+		stream::{IterStream, TryTools},
+	},
+};
 
 use crate::{admin_command, admin_command_dispatch};
 
@@ -21,6 +30,30 @@ pub(crate) enum RoomTimelineCommand {
 	Last {
 		room_id: OwnedRoomOrAliasId,
 	},
+
+	/// Render the recent event graph of a room for visualization.
+	Graph {
+		room_id: OwnedRoomOrAliasId,
+
+		/// Only include events after this timeline count.
+		#[arg(long)]
+		since: Option<String>,
+
+		#[arg(short, long)]
+		limit: Option<usize>,
+
+		#[arg(long, value_enum, default_value = "dot")]
+		format: GraphFormat,
+	},
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum GraphFormat {
+	/// Graphviz DOT
+	Dot,
+
+	/// JSON nodes and edges
+	Json,
 }
 
 #[admin_command]
@@ -65,3 +98,129 @@ pub(super) async fn pdus(
 
 	self.write_str(&format!("{result:#?}")).await
 }
+
+#[admin_command]
+pub(super) async fn graph(
+	&self,
+	room_id: OwnedRoomOrAliasId,
+	since: Option<String>,
+	limit: Option<usize>,
+	format: GraphFormat,
+) -> Result {
+	let room_id = self
+		.services
+		.alias
+		.maybe_resolve(&room_id)
+		.await?;
+
+	let since: Option<PduCount> = since.as_deref().map(str::parse).transpose()?;
+
+	let pdus: Vec<(PduCount, PduEvent)> = self
+		.services
+		.timeline
+		.pdus_rev(None, &room_id, None)
+		.ready_try_take_while(|(count, _)| Ok(since.is_none_or(|since| *count > since)))
+		.try_take(limit.unwrap_or(100))
+		.try_collect()
+		.await?;
+
+	let known: HashSet<&EventId> = pdus
+		.iter()
+		.map(|(_, pdu)| pdu.event_id())
+		.collect();
+
+	let missing: Vec<&EventId> = pdus
+		.iter()
+		.flat_map(|(_, pdu)| pdu.prev_events())
+		.filter(|prev| !known.contains(prev))
+		.collect::<HashSet<_>>()
+		.into_iter()
+		.collect();
+
+	let nodes: Vec<_> = pdus
+		.iter()
+		.stream()
+		.then(async |(count, pdu)| {
+			json!({
+				"event_id": pdu.event_id(),
+				"count": count.to_string(),
+				"type": pdu.kind(),
+				"sender": pdu.sender(),
+				"depth": pdu.depth,
+				"state_key": pdu.state_key(),
+				"rejected": pdu.rejected,
+				"soft_failed": self.services.pdu_metadata.is_event_soft_failed(pdu.event_id()).await,
+				"prev_events": pdu.prev_events().collect::<Vec<_>>(),
+			})
+		})
+		.collect()
+		.await;
+
+	let missing: Vec<_> = missing
+		.into_iter()
+		.stream()
+		.then(async |event_id| {
+			json!({
+				"event_id": event_id,
+				"soft_failed": self.services.pdu_metadata.is_event_soft_failed(event_id).await,
+			})
+		})
+		.collect()
+		.await;
+
+	let out = match format {
+		| GraphFormat::Json => {
+			let graph = json!({ "room_id": room_id, "nodes": nodes, "missing": missing });
+			format!("```json\n{}\n```", serde_json::to_string_pretty(&graph)?)
+		},
+		| GraphFormat::Dot => {
+			let mut dot = format!("digraph \"{room_id}\" {{\n\trankdir=BT;\n");
+			for node in &nodes {
+				let state = node["state_key"]
+					.as_str()
+					.map(|state_key| format!("\\nstate_key={state_key}"))
+					.unwrap_or_default();
+
+				let style = match (node["rejected"].as_bool(), node["soft_failed"].as_bool()) {
+					| (Some(true), _) => ", color=red",
+					| (_, Some(true)) => ", color=orange",
+					| _ if !state.is_empty() => ", shape=box",
+					| _ => "",
+				};
+
+				writeln!(
+					dot,
+					"\t{} [label=\"{}\\n{}\\n{}\\ndepth={}{}\"{style}];",
+					node["event_id"],
+					node["count"].as_str().unwrap_or_default(),
+					node["type"].as_str().unwrap_or_default(),
+					node["sender"].as_str().unwrap_or_default(),
+					node["depth"],
+					state.replace('"', "\\\""),
+				)?;
+
+				for prev in node["prev_events"]
+					.as_array()
+					.into_iter()
+					.flatten()
+				{
+					writeln!(dot, "\t{} -> {prev};", node["event_id"])?;
+				}
+			}
+
+			for node in &missing {
+				let color = match node["soft_failed"].as_bool() {
+					| Some(true) => "orange",
+					| _ => "grey",
+				};
+
+				writeln!(dot, "\t{} [style=dashed, color={color}];", node["event_id"])?;
+			}
+
+			dot.push('}');
+			format!("```dot\n{dot}\n```")
+		},
+	};
+
+	self.write_str(&out).await
+}
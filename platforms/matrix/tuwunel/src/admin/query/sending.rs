@@ -1,7 +1,9 @@
-use clap::Subcommand;
+use std::{fmt::Write, time::Duration};
+
+use clap::{Subcommand, ValueEnum};
 use futures::StreamExt;
 use ruma::{OwnedServerName, OwnedUserId};
-use tuwunel_core::{Err, Result};
+use tuwunel_core::{Err, Result, utils::time};
 use tuwunel_service::sending::Destination;
 
 use crate::Context;
@@ -59,6 +61,30 @@ pub(crate) enum SendingCommand {
 	GetLatestEduCount {
 		server_name: OwnedServerName,
 	},
+
+	/// - Puts the servers matching a pattern into audit mode, or takes them out
+	///   of it
+	///
+	/// Transactions to audited servers are composed and logged, with the IDs
+	/// of their PDUs and the types of their EDUs, but not sent. The events are
+	/// dropped from the queue as if they had been sent, so that the receipts,
+	/// presence and device list changes they carried are not sent afterwards.
+	/// The pattern is a regular expression matched against the server name.
+	Audit {
+		server: String,
+
+		#[arg(value_enum)]
+		state: AuditState,
+	},
+
+	/// - Lists the patterns of the servers in audit mode
+	ListAudited,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum AuditState {
+	On,
+	Off,
 }
 
 /// All the getters and iterators in key_value/sending.rs
@@ -221,6 +247,44 @@ pub(super) async fn process(subcommand: SendingCommand, context: &Context<'_>) -
 				))
 				.await
 		},
+		| SendingCommand::Audit { server, state } => {
+			let audit = matches!(state, AuditState::On);
+			services.sending.set_audit(&server, audit).await?;
+
+			context
+				.write_str(&if audit {
+					format!("Transactions to servers matching {server:?} are now only logged.")
+				} else {
+					format!("Transactions to servers matching {server:?} are sent again.")
+				})
+				.await
+		},
+		| SendingCommand::ListAudited => {
+			let audited: Vec<_> = services
+				.sending
+				.audited()
+				.map(|(pattern, since)| (pattern.to_owned(), since))
+				.collect()
+				.await;
+
+			if audited.is_empty() {
+				return context
+					.write_str("No servers are in audit mode.")
+					.await;
+			}
+
+			let mut out = String::new();
+			writeln!(out, "| Pattern | Since |")?;
+			writeln!(out, "| ------- | ----- |")?;
+			for (pattern, since) in audited {
+				let since = time::timepoint_from_epoch(Duration::from_secs(since))?;
+				let since = time::format(since, "%+");
+
+				writeln!(out, "| `{pattern}` | {since} |")?;
+			}
+
+			context.write_str(&out).await
+		},
 		| SendingCommand::GetLatestEduCount { server_name } => {
 			let timer = tokio::time::Instant::now();
 			let results = services
@@ -1,6 +1,6 @@
 use clap::Subcommand;
 use ruma::{OwnedDeviceId, OwnedUserId};
-use tuwunel_core::Result;
+use tuwunel_core::{Result, utils::bytes::pretty};
 use tuwunel_service::sync::into_connection_key;
 
 use crate::{admin_command, admin_command_dispatch};
@@ -25,6 +25,10 @@ pub(crate) enum SyncCommand {
 		device_id: Option<OwnedDeviceId>,
 		conn_id: Option<String>,
 	},
+
+	/// Remove stored connections of deleted users or devices, and forget rooms
+	/// the user has long since left.
+	Prune,
 }
 
 #[admin_command]
@@ -80,3 +84,18 @@ pub(super) async fn drop_connections(
 
 	Ok(())
 }
+
+#[admin_command]
+pub(super) async fn prune(&self) -> Result {
+	let timer = tokio::time::Instant::now();
+	let pruned = self.services.sync.prune_connections().await;
+	let query_time = timer.elapsed();
+
+	self.write_str(&format!(
+		"Pruned {} connections and {} rooms, reclaiming {} in {query_time:?}.",
+		pruned.connections,
+		pruned.rooms,
+		pretty(pruned.bytes),
+	))
+	.await
+}
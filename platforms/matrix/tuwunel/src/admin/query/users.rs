@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
+
 use clap::Subcommand;
 use futures::stream::StreamExt;
 use ruma::{OwnedDeviceId, OwnedRoomId, OwnedUserId};
-use tuwunel_core::Result;
+use tuwunel_core::{Result, utils::ReadyExt};
 
 use crate::{admin_command, admin_command_dispatch};
 
@@ -57,6 +59,11 @@ pub(crate) enum UsersCommand {
 		device_id: OwnedDeviceId,
 	},
 
+	CountToDeviceEvents {
+		user_id: OwnedUserId,
+		device_id: Option<OwnedDeviceId>,
+	},
+
 	GetLatestBackup {
 		user_id: OwnedUserId,
 	},
@@ -405,3 +412,48 @@ async fn get_to_device_events(&self, user_id: OwnedUserId, device_id: OwnedDevic
 	self.write_str(&format!("Query completed in {query_time:?}:\n\n```rs\n{result:#?}\n```"))
 		.await
 }
+
+#[admin_command]
+async fn count_to_device_events(
+	&self,
+	user_id: OwnedUserId,
+	device_id: Option<OwnedDeviceId>,
+) -> Result {
+	let timer = tokio::time::Instant::now();
+	let device_ids: Vec<OwnedDeviceId> = match device_id {
+		| Some(device_id) => vec![device_id],
+		| None =>
+			self.services
+				.users
+				.all_device_ids(&user_id)
+				.map(ToOwned::to_owned)
+				.collect()
+				.await,
+	};
+
+	let mut result: BTreeMap<OwnedDeviceId, BTreeMap<String, usize>> = BTreeMap::new();
+	for device_id in device_ids {
+		let counts = self
+			.services
+			.users
+			.get_to_device_events(&user_id, &device_id, None, None)
+			.ready_fold(BTreeMap::new(), |mut counts: BTreeMap<String, usize>, (_, event)| {
+				let kind = event
+					.get_field::<String>("type")
+					.ok()
+					.flatten()
+					.unwrap_or_default();
+
+				let count = counts.entry(kind).or_default();
+				*count = count.saturating_add(1);
+				counts
+			})
+			.await;
+
+		result.insert(device_id, counts);
+	}
+	let query_time = timer.elapsed();
+
+	self.write_str(&format!("Query completed in {query_time:?}:\n\n```rs\n{result:#?}\n```"))
+		.await
+}
@@ -0,0 +1,76 @@
+use std::{fmt::Write, time::Duration};
+
+use futures::StreamExt;
+use tuwunel_core::{
+	Err, Result,
+	utils::{ReadyExt, time},
+};
+use tuwunel_service::reports::describe;
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn list(&self, all: bool) -> Result {
+	let reports: Vec<_> = self
+		.services
+		.reports
+		.reports()
+		.ready_filter(|(_, report)| all || report.resolution.is_none())
+		.collect()
+		.await;
+
+	if reports.is_empty() {
+		return Err!("No reports awaiting review.");
+	}
+
+	let mut out = String::new();
+	writeln!(out, "| ID | Received | Reporter | Room | Event | Score | Reason | Resolved |")?;
+	writeln!(out, "| --- | --- | --- | --- | --- | --- | --- | --- |")?;
+	for (id, report) in reports {
+		let received = time::timepoint_from_epoch(Duration::from_secs(report.received_at))?;
+		writeln!(
+			out,
+			"| {id} | {} | {} | {} | {} | {} | {} | {} |",
+			time::format(received, "%+"),
+			report.reporter,
+			report.room_id,
+			report
+				.event_id
+				.as_ref()
+				.map(ToString::to_string)
+				.unwrap_or_default(),
+			report
+				.score
+				.map(|score| score.to_string())
+				.unwrap_or_default(),
+			report.reason.as_deref().unwrap_or_default(),
+			if report.resolution.is_some() { "yes" } else { "no" },
+		)?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn show(&self, id: u64) -> Result {
+	let report = self.services.reports.get(id).await?;
+
+	let mut out = describe(id, &report);
+	if let Some(resolution) = &report.resolution {
+		let resolved = time::timepoint_from_epoch(Duration::from_secs(resolution.resolved_at))?;
+		write!(out, "\n\nResolved: {}", time::format(resolved, "%+"))?;
+		if let Some(note) = &resolution.note {
+			write!(out, "\nNote: {note}")?;
+		}
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn resolve(&self, id: u64, note: Option<String>) -> Result {
+	self.services.reports.resolve(id, note).await?;
+
+	self.write_str(&format!("Report {id} resolved."))
+		.await
+}
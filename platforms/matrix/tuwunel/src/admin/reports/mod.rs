@@ -0,0 +1,30 @@
+mod commands;
+
+use clap::Subcommand;
+use tuwunel_core::Result;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum ReportsCommand {
+	/// - List the reports of rooms and events awaiting review
+	List {
+		/// Also list the reports already resolved.
+		#[arg(short, long)]
+		all: bool,
+	},
+
+	/// - Show a report in full
+	Show {
+		id: u64,
+	},
+
+	/// - Mark a report resolved
+	Resolve {
+		id: u64,
+
+		/// Note on the action taken.
+		note: Option<String>,
+	},
+}
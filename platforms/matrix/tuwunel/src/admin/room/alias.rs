@@ -2,8 +2,8 @@
 
 use clap::Subcommand;
 use futures::StreamExt;
-use ruma::{OwnedRoomAliasId, OwnedRoomId};
-use tuwunel_core::{Err, Result};
+use ruma::{OwnedRoomAliasId, OwnedRoomId, OwnedServerName, UserId, events::StateEventType};
+use tuwunel_core::{Err, Result, matrix::Event};
 
 use crate::Context;
 
@@ -28,6 +28,25 @@ pub(crate) enum RoomAliasCommand {
 		room_alias_localpart: String,
 	},
 
+	/// - Point a local alias at another room, keeping who created it
+	///
+	/// The admin issuing the command must be joined to the room and allowed
+	/// to set its canonical alias.
+	Transfer {
+		/// The alias localpart to move (`alias`, not `#alias:servername.tld`)
+		room_alias_localpart: String,
+
+		/// The room id to point the alias at
+		room_id: OwnedRoomId,
+	},
+
+	/// - Remove the local aliases of all rooms created on a server
+	BulkRemove {
+		/// The server whose rooms lose their aliases
+		#[arg(long)]
+		server: OwnedServerName,
+	},
+
 	/// - Show which room is using an alias
 	Which {
 		/// The alias localpart to look up (`alias`, not
@@ -44,11 +63,12 @@ pub(crate) enum RoomAliasCommand {
 
 pub(super) async fn process(command: RoomAliasCommand, context: &Context<'_>) -> Result {
 	let services = context.services;
-	let server_user = &services.globals.server_user;
+	let server_user: &UserId = &services.globals.server_user;
 
 	match command {
 		| RoomAliasCommand::Set { ref room_alias_localpart, .. }
 		| RoomAliasCommand::Remove { ref room_alias_localpart }
+		| RoomAliasCommand::Transfer { ref room_alias_localpart, .. }
 		| RoomAliasCommand::Which { ref room_alias_localpart } => {
 			let room_alias_str =
 				format!("#{}:{}", room_alias_localpart, services.globals.server_name());
@@ -129,9 +149,74 @@ pub(super) async fn process(command: RoomAliasCommand, context: &Context<'_>) ->
 								.await,
 					}
 				},
-				| RoomAliasCommand::List { .. } => unreachable!(),
+				| RoomAliasCommand::Transfer { room_id, .. } => {
+					let sender = context.sender.unwrap_or(server_user);
+					match services
+						.alias
+						.repoint_alias(&room_alias, &room_id, sender)
+						.await
+					{
+						| Err(err) => Err!("Failed to transfer alias: {err}"),
+						| Ok(old_room_id) =>
+							context
+								.write_str(&format!(
+									"Moved {room_alias} from {old_room_id} to {room_id}"
+								))
+								.await,
+					}
+				},
+				| RoomAliasCommand::List { .. } | RoomAliasCommand::BulkRemove { .. } =>
+					unreachable!(),
 			}
 		},
+		| RoomAliasCommand::BulkRemove { server } => {
+			let aliases: Vec<(OwnedRoomId, String)> = services
+				.alias
+				.all_local_aliases()
+				.map(|(room_id, localpart)| (room_id.into(), localpart.into()))
+				.collect()
+				.await;
+
+			let server_name = services.globals.server_name();
+			let (mut removed, mut failed) = (0_usize, 0_usize);
+			for (room_id, localpart) in aliases {
+				let created_on_server = services
+					.state_accessor
+					.room_state_get(&room_id, &StateEventType::RoomCreate, "")
+					.await
+					.is_ok_and(|create| create.sender().server_name() == server);
+
+				if !created_on_server {
+					continue;
+				}
+
+				let Ok(room_alias) =
+					OwnedRoomAliasId::parse(format!("#{localpart}:{server_name}"))
+				else {
+					continue;
+				};
+
+				match services
+					.alias
+					.remove_alias(&room_alias, server_user)
+					.await
+				{
+					| Ok(()) => removed = removed.saturating_add(1),
+					| Err(err) => {
+						failed = failed.saturating_add(1);
+						context
+							.write_str(&format!("Failed to remove {room_alias}: {err}\n"))
+							.await?;
+					},
+				}
+			}
+
+			context
+				.write_str(&format!(
+					"Removed {removed} aliases of rooms created on {server}, {failed} failed."
+				))
+				.await
+		},
 		| RoomAliasCommand::List { room_id } =>
 			if let Some(room_id) = room_id {
 				let aliases: Vec<OwnedRoomAliasId> = services
@@ -1,6 +1,29 @@
-use futures::StreamExt;
-use ruma::OwnedRoomId;
-use tuwunel_core::{Err, Result};
+use std::{
+	collections::{BTreeSet, HashMap},
+	fmt::Write,
+	path::PathBuf,
+	pin::pin,
+	time::Duration,
+};
+
+use futures::{Stream, StreamExt};
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId,
+	UInt, events::room::redaction::RoomRedactionEventContent,
+};
+use tuwunel_core::{
+	Err, Result, err,
+	matrix::{
+		Event,
+		pdu::{PduBuilder, PduCount, PduEvent},
+	},
+	utils::time,
+};
+use tuwunel_service::{
+	Services,
+	jobs::Task,
+	rooms::{export::Options, state_compressor::HashSetCompressStateEvent},
+};
 
 use crate::{PAGE_SIZE, admin_command, get_room_info};
 
@@ -84,3 +107,486 @@ pub(super) async fn delete_room(&self, room_id: OwnedRoomId, force: bool) -> Res
 
 	Ok(())
 }
+
+#[admin_command]
+pub(super) async fn purge_history(
+	&self,
+	room_id: OwnedRoomId,
+	before: Option<u64>,
+	keep_state: bool,
+) -> Result {
+	if let Some(before) = before {
+		if !self.services.metadata.exists(&room_id).await {
+			return Err!("Room {room_id} is not known to this server.");
+		}
+
+		let id = self.services.jobs.enqueue(Task::PurgeHistory {
+			room_id: room_id.clone(),
+			before,
+			keep_state,
+		});
+
+		return self
+			.write_str(&format!(
+				"Queued job {id} to purge the history of {room_id} sent before {before}; see \
+				 `!admin jobs list`."
+			))
+			.await;
+	}
+
+	let Some(max_lifetime) = self
+		.services
+		.retention
+		.max_lifetime(&room_id)
+		.await
+	else {
+		return Err!("Room {room_id} has no retention policy and there is no server default.");
+	};
+
+	let purged = self
+		.services
+		.retention
+		.purge_room(&room_id)
+		.await?;
+
+	let max_lifetime = time::pretty(max_lifetime);
+	self.write_str(&format!("Purged {purged} messages older than {max_lifetime} from {room_id}."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn purge_remote_events(
+	&self,
+	room_id: OwnedRoomId,
+	server_name: OwnedServerName,
+	since: Option<u64>,
+	redact: bool,
+) -> Result {
+	if self.services.globals.server_is_ours(&server_name) {
+		return Err!("{server_name} is this server; use purge-user-events for local users.");
+	}
+
+	if !self.services.metadata.exists(&room_id).await {
+		return Err!("Room {room_id} is not known to this server.");
+	}
+
+	if redact {
+		can_redact_check(self.services, &room_id).await?;
+	}
+
+	let reason = redact.then(|| {
+		format!(
+			"The administrator(s) of {} have removed messages from {server_name}.",
+			self.services.globals.server_name()
+		)
+	});
+
+	let pdus = self
+		.services
+		.timeline
+		.server_pdus(&room_id, &server_name, since_ts(since)?);
+
+	let (event_ids, redacted) =
+		select_pdus(self.services, &room_id, pdus, reason.as_deref()).await?;
+
+	let redacted = redact
+		.then(|| format!(" after sending {redacted} redactions"))
+		.unwrap_or_default();
+
+	let id = self
+		.services
+		.jobs
+		.enqueue(Task::PurgeServerEvents {
+			room_id: room_id.clone(),
+			server_name: server_name.clone(),
+			event_ids,
+		});
+
+	self.write_str(&format!(
+		"Queued job {id} to purge the messages from {server_name} in {room_id}{redacted}; see \
+		 `!admin jobs list`."
+	))
+	.await
+}
+
+#[admin_command]
+pub(super) async fn purge_user_events(
+	&self,
+	room_id: OwnedRoomId,
+	user_id: OwnedUserId,
+	since: Option<u64>,
+	redact: bool,
+) -> Result {
+	if !self.services.metadata.exists(&room_id).await {
+		return Err!("Room {room_id} is not known to this server.");
+	}
+
+	if redact {
+		can_redact_check(self.services, &room_id).await?;
+	}
+
+	let reason = redact.then(|| {
+		format!(
+			"The administrator(s) of {} have removed messages from {user_id}.",
+			self.services.globals.server_name()
+		)
+	});
+
+	let pdus = self
+		.services
+		.timeline
+		.user_pdus(&room_id, &user_id, since_ts(since)?);
+
+	let (event_ids, redacted) =
+		select_pdus(self.services, &room_id, pdus, reason.as_deref()).await?;
+
+	let redacted = redact
+		.then(|| format!(" after sending {redacted} redactions"))
+		.unwrap_or_default();
+
+	let id = self.services.jobs.enqueue(Task::PurgeUserEvents {
+		room_id: room_id.clone(),
+		user_id: user_id.clone(),
+		event_ids,
+	});
+
+	self.write_str(&format!(
+		"Queued job {id} to purge the messages from {user_id} in {room_id}{redacted}; see \
+		 `!admin jobs list`."
+	))
+	.await
+}
+
+/// Fails unless the server user is in the room with the power to redact the
+/// messages of others, so that no redaction is sent when some would fail.
+async fn can_redact_check(services: &Services, room_id: &RoomId) -> Result {
+	let server_user = &services.globals.server_user;
+	if !services
+		.state_cache
+		.is_joined(server_user, room_id)
+		.await
+	{
+		return Err!("{server_user} is not in {room_id}, so it cannot redact messages there.");
+	}
+
+	let can_redact = services
+		.state_accessor
+		.get_power_levels(room_id)
+		.await
+		.is_ok_and(|power_levels| power_levels.user_can_redact_event_of_other(server_user));
+
+	if !can_redact {
+		return Err!(
+			"{server_user} lacks the power to redact the messages of others in {room_id}."
+		);
+	}
+
+	Ok(())
+}
+
+/// Takes the ids of the pdus to purge, sending a redaction from the server user
+/// with `reason`, when given, for each not yet redacted. Returns the ids and
+/// the number of redactions sent. On failure nothing is purged; the redactions
+/// already sent are reported, and running the command again resumes with the
+/// messages not yet redacted.
+async fn select_pdus<S>(
+	services: &Services,
+	room_id: &RoomId,
+	pdus: S,
+	reason: Option<&str>,
+) -> Result<(Vec<OwnedEventId>, usize)>
+where
+	S: Stream<Item = (PduCount, PduEvent)> + Send,
+{
+	let server_user = &services.globals.server_user;
+	let (mut event_ids, mut redacted) = (Vec::new(), 0_usize);
+
+	let mut pdus = pin!(pdus);
+	while let Some((_, pdu)) = pdus.next().await {
+		if let Some(reason) = reason
+			&& !pdu.is_redacted()
+		{
+			let state_lock = services.state.mutex.lock(room_id).await;
+			let result = services
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder {
+						redacts: Some(pdu.event_id().to_owned()),
+						..PduBuilder::timeline(&RoomRedactionEventContent {
+							redacts: Some(pdu.event_id().to_owned()),
+							reason: Some(reason.to_owned()),
+						})
+					},
+					server_user,
+					room_id,
+					&state_lock,
+				)
+				.await;
+
+			if let Err(e) = result {
+				return Err!(
+					"Sent {redacted} redactions, then failed to redact {}: {e}. Nothing was \
+					 purged; run the command again to redact the remaining messages and purge \
+					 them.",
+					pdu.event_id()
+				);
+			}
+
+			redacted = redacted.saturating_add(1);
+		}
+
+		event_ids.push(pdu.event_id().to_owned());
+	}
+
+	Ok((event_ids, redacted))
+}
+
+fn since_ts(since: Option<u64>) -> Result<Option<MilliSecondsSinceUnixEpoch>> {
+	Ok(since
+		.map(UInt::try_from)
+		.transpose()?
+		.map(MilliSecondsSinceUnixEpoch))
+}
+
+#[admin_command]
+pub(super) async fn rebuild_state_cache(
+	&self,
+	room_id: Option<OwnedRoomId>,
+	all: bool,
+) -> Result {
+	let rooms: Vec<OwnedRoomId> = match room_id {
+		| Some(room_id) if !all => vec![room_id],
+		| _ =>
+			self.services
+				.metadata
+				.iter_ids()
+				.map(ToOwned::to_owned)
+				.collect()
+				.await,
+	};
+
+	let (mut members, mut corrected, mut failed) = (0_usize, 0_usize, 0_usize);
+	for room_id in &rooms {
+		match self
+			.services
+			.state_cache
+			.rebuild_room(room_id)
+			.await
+		{
+			| Ok(rebuilt) => {
+				members = members.saturating_add(rebuilt.members);
+				corrected = corrected.saturating_add(rebuilt.corrected);
+			},
+			| Err(e) => {
+				failed = failed.saturating_add(1);
+				self.write_str(&format!("Failed to rebuild {room_id}: {e}\n"))
+					.await?;
+			},
+		}
+	}
+
+	self.write_str(&format!(
+		"Rebuilt the memberships of {} rooms: corrected {corrected} of {members} members.",
+		rooms.len().saturating_sub(failed),
+	))
+	.await
+}
+
+#[admin_command]
+pub(super) async fn fix_state(&self, room_id: OwnedRoomId, dry_run: bool) -> Result {
+	if !self.services.metadata.exists(&room_id).await {
+		return Err!("Room {room_id} is not known to this server.");
+	}
+
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+
+	let current_shortstatehash = self
+		.services
+		.state
+		.get_room_shortstatehash(&room_id)
+		.await?;
+
+	let current: HashMap<_, _> = self
+		.services
+		.state_accessor
+		.state_full_ids(current_shortstatehash)
+		.collect()
+		.await;
+
+	let resolved = self
+		.services
+		.event_handler
+		.resolve_room_state(&room_id)
+		.await?;
+
+	let changed: BTreeSet<u64> = current
+		.keys()
+		.chain(resolved.keys())
+		.filter(|shortstatekey| current.get(shortstatekey) != resolved.get(shortstatekey))
+		.copied()
+		.collect();
+
+	if changed.is_empty() {
+		return self
+			.write_str(&format!(
+				"The current state of {room_id} is consistent with its forward extremities."
+			))
+			.await;
+	}
+
+	let mut diff = String::new();
+	writeln!(diff, "| Type | State key | Current | Resolved |")?;
+	writeln!(diff, "| --- | --- | --- | --- |")?;
+	for shortstatekey in &changed {
+		let (event_type, state_key) = self
+			.services
+			.short
+			.get_statekey_from_short(*shortstatekey)
+			.await?;
+
+		let event_id = |state: &HashMap<u64, OwnedEventId>| {
+			state
+				.get(shortstatekey)
+				.map(ToString::to_string)
+				.unwrap_or_default()
+		};
+
+		writeln!(
+			diff,
+			"| {event_type} | {state_key} | {} | {} |",
+			event_id(&current),
+			event_id(&resolved),
+		)?;
+	}
+
+	if dry_run {
+		return self
+			.write_str(&format!(
+				"{} state entries of {room_id} would change:\n\n{diff}",
+				changed.len()
+			))
+			.await;
+	}
+
+	let compressed = self
+		.services
+		.event_handler
+		.compress_state(&resolved)
+		.await;
+
+	let HashSetCompressStateEvent { shortstatehash, added, removed } = self
+		.services
+		.state_compressor
+		.save_state(&room_id, compressed)
+		.await?;
+
+	self.services
+		.state
+		.force_state(&room_id, shortstatehash, added, removed, &state_lock)
+		.await?;
+
+	self.write_str(&format!("Fixed {} state entries of {room_id}:\n\n{diff}", changed.len()))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn recalculate_summary(&self, room_id: OwnedRoomId) -> Result {
+	if !self.services.metadata.exists(&room_id).await {
+		return Err!("Room {room_id} is not known to this server.");
+	}
+
+	let summary = self
+		.services
+		.state_accessor
+		.recalculate_summary(&room_id)
+		.await;
+
+	let heroes = summary
+		.heroes
+		.iter()
+		.map(ToString::to_string)
+		.collect::<Vec<_>>()
+		.join(", ");
+
+	self.write_str(&format!(
+		"Recalculated the summary of {room_id}: {} joined, {} invited, heroes: {heroes}",
+		summary.joined_member_count, summary.invited_member_count,
+	))
+	.await
+}
+
+#[admin_command]
+pub(super) async fn export_room(
+	&self,
+	room_id: OwnedRoomId,
+	path: PathBuf,
+	pseudonymize: bool,
+	salt: Option<String>,
+	limit: Option<usize>,
+	visible_to: Option<OwnedUserId>,
+) -> Result {
+	let options = Options {
+		pseudonymize,
+		salt: salt.as_deref(),
+		limit,
+		visible_to: visible_to.as_deref(),
+	};
+
+	let export = self
+		.services
+		.export
+		.export_room(&room_id, &options)
+		.await?;
+
+	let json = serde_json::to_vec_pretty(&export)?;
+	tokio::fs::write(&path, json)
+		.await
+		.map_err(|e| err!("Failed to write {}: {e}", path.display()))?;
+
+	self.write_str(&format!(
+		"Exported {room_id} with {} state events, {} timeline events and {} media{} to {}.",
+		export.state.len(),
+		export.timeline.len(),
+		export.media.len(),
+		if export.pseudonymized { ", pseudonymized," } else { "" },
+		path.display(),
+	))
+	.await
+}
+
+#[admin_command]
+pub(super) async fn state_bloat(&self, top: usize, by_growth: bool) -> Result {
+	let mut samples: Vec<_> = self
+		.services
+		.state_bloat
+		.samples()
+		.collect()
+		.await;
+
+	if samples.is_empty() {
+		return Err!("No room state has been sampled yet.");
+	}
+
+	if by_growth {
+		samples.sort_by_key(|(_, sample)| sample.growth);
+	} else {
+		samples.sort_by_key(|(_, sample)| sample.state_events);
+	}
+
+	let mut out = String::new();
+	writeln!(out, "| Room | State entries | Layers | Growth per hour | Sampled |")?;
+	writeln!(out, "| --- | --- | --- | --- | --- |")?;
+	for (room_id, sample) in samples.iter().rev().take(top) {
+		let sampled_at = time::timepoint_from_epoch(Duration::from_secs(sample.sampled_at))?;
+		writeln!(
+			out,
+			"| {room_id} | {} | {} | {} | {} |",
+			sample.state_events,
+			sample.layers,
+			sample.growth,
+			time::format(sampled_at, "%+"),
+		)?;
+	}
+
+	self.write_str(&out).await
+}
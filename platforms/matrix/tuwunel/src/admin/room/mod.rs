@@ -4,8 +4,10 @@
 mod info;
 mod moderation;
 
+use std::path::PathBuf;
+
 use clap::Subcommand;
-use ruma::OwnedRoomId;
+use ruma::{OwnedRoomId, OwnedServerName, OwnedUserId};
 use tuwunel_core::Result;
 
 use self::{
@@ -57,6 +59,153 @@ pub(super) enum RoomCommand {
 		room_id: OwnedRoomId,
 	},
 
+	/// - Purge the messages of a room older than its retention policy, or sent
+	///   before a time
+	///
+	/// The policy is the room's `m.room.retention` state event, otherwise
+	/// `room_retention_default_max_lifetime`. State events and the latest
+	/// event of the room are kept.
+	///
+	/// With --before, the history sent before then is purged along with the
+	/// read receipts pointing into it, and the room is no longer backfilled
+	/// so that clients reach the end of its history there. The current state
+	/// and the latest events are kept; other state events are taken out of
+	/// the timeline, unless --keep-state. This is done by a background job,
+	/// which resumes after a restart.
+	PurgeHistory {
+		room_id: OwnedRoomId,
+
+		/// Purge the history sent before this time, in milliseconds since the
+		/// Unix epoch
+		#[arg(long)]
+		before: Option<u64>,
+
+		/// With --before, keep the state events in the timeline
+		#[arg(long, requires = "before")]
+		keep_state: bool,
+	},
+
+	/// - Purge the messages of a room sent by users of a remote server
+	///
+	/// Meant for cleaning up after a spam wave over federation. The messages
+	/// are removed from the timeline, search index, relations and
+	/// notifications; state events and the latest events of the room are
+	/// kept. With --redact,
+	/// redactions are first sent from the server user so that other servers
+	/// and clients drop the messages too. The purge itself is done by a
+	/// background job, which resumes after a restart.
+	PurgeRemoteEvents {
+		room_id: OwnedRoomId,
+
+		server_name: OwnedServerName,
+
+		/// Only messages sent at or after this time, in milliseconds since the
+		/// Unix epoch
+		#[arg(long)]
+		since: Option<u64>,
+
+		#[arg(long)]
+		redact: bool,
+	},
+
+	/// - Purge the messages of a room sent by a user
+	///
+	/// Like purge-remote-events, for a single local or remote user.
+	PurgeUserEvents {
+		room_id: OwnedRoomId,
+
+		user_id: OwnedUserId,
+
+		/// Only messages sent at or after this time, in milliseconds since the
+		/// Unix epoch
+		#[arg(long)]
+		since: Option<u64>,
+
+		#[arg(long)]
+		redact: bool,
+	},
+
+	/// - Recalculates the cached member counts and heroes of a room
+	///
+	/// These are updated on membership changes; this is only needed should
+	/// they have become inconsistent with the room's members.
+	RecalculateSummary {
+		room_id: OwnedRoomId,
+	},
+
+	/// - Rebuilds the recorded memberships of rooms from their current state
+	///
+	/// The joined, invited, knocked and left users and the servers in a room
+	/// are recomputed from the membership events of its current state, should
+	/// they have drifted from it.
+	RebuildStateCache {
+		#[arg(required_unless_present = "all")]
+		room_id: Option<OwnedRoomId>,
+
+		/// Rebuild every room known to the server
+		#[arg(long, conflicts_with = "room_id")]
+		all: bool,
+	},
+
+	/// - Recomputes a room's current state from its forward extremities
+	///
+	/// The states at the forward extremities are resolved anew and replace the
+	/// current state, should it have become inconsistent, e.g. after a
+	/// federation bug. The entries which differ are listed.
+	FixState {
+		room_id: OwnedRoomId,
+
+		/// Only list the entries which would change
+		#[arg(long)]
+		dry_run: bool,
+	},
+
+	/// - Writes a room's current state and timeline to a JSON file
+	///
+	/// With --pseudonymize, user IDs are replaced by pseudonyms and display
+	/// names and avatars are stripped from membership events, so the export
+	/// can be shared with moderators outside the server. The media referenced
+	/// by the exported events are listed alongside them.
+	ExportRoom {
+		room_id: OwnedRoomId,
+
+		/// File to write, on the server's filesystem
+		#[arg(long)]
+		path: PathBuf,
+
+		#[arg(long)]
+		pseudonymize: bool,
+
+		/// Secret mixed into the pseudonyms, so that exports made with
+		/// different salts cannot be correlated
+		#[arg(long, requires = "pseudonymize")]
+		salt: Option<String>,
+
+		/// Number of most recent timeline events to export
+		#[arg(long)]
+		limit: Option<usize>,
+
+		/// Only export the events, and the media they reference, which this
+		/// user could see by the room's history visibility at the time
+		#[arg(long)]
+		visible_to: Option<OwnedUserId>,
+	},
+
+	/// - List the rooms with the largest state and how fast it grows
+	///
+	/// Sizes are those of the last sample taken every
+	/// `state_bloat_check_interval`; growth is in state entries per hour since
+	/// the sample before it.
+	StateBloat {
+		/// Number of rooms to list
+		#[arg(long, default_value = "10")]
+		top: usize,
+
+		/// Rank rooms by their growth rather than the size of their state
+		#[arg(long)]
+		by_growth: bool,
+	},
+
 	/// - Delete room
 	DeleteRoom {
 		room_id: OwnedRoomId,
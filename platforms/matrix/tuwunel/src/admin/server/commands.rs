@@ -1,6 +1,6 @@
 use std::{fmt::Write, path::PathBuf, sync::Arc};
 
-use futures::TryStreamExt;
+use futures::{StreamExt, TryStreamExt};
 use tuwunel_core::{
 	Err, Result, info,
 	utils::{stream::IterStream, time},
@@ -77,6 +77,103 @@ pub(super) async fn memory_usage(&self) -> Result {
 	.await
 }
 
+#[admin_command]
+pub(super) async fn slow_requests(&self, clear: bool) -> Result {
+	let slow_requests = &self.services.server.metrics.slow_requests;
+	let mut requests = slow_requests.list();
+	if clear {
+		slow_requests.clear();
+	}
+
+	if requests.is_empty() {
+		return self.write_str("No slow requests.").await;
+	}
+
+	requests.sort_by(|a, b| b.budget.elapsed.cmp(&a.budget.elapsed));
+
+	let mut out = String::new();
+	writeln!(out, "| Received | Request | Status | Time | DB reads | Allocated |")?;
+	writeln!(out, "| -------- | ------- | ------ | ---- | -------- | --------- |")?;
+	for request in requests {
+		let received = time::format(request.received, "%+");
+		let allocated = request
+			.budget
+			.allocated
+			.map_or_else(|| "-".to_owned(), |bytes| format!("{} KiB", bytes / 1024));
+
+		writeln!(
+			out,
+			"| {received} | {} {} | {} | {:?} | {} | {allocated} |",
+			request.method,
+			request.path,
+			request.status,
+			request.budget.elapsed,
+			request.budget.db_reads,
+		)?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn rate_limit_offenders(&self, clear: Option<String>) -> Result {
+	let ratelimit = &self.services.ratelimit;
+	if let Some(key) = clear {
+		ratelimit.clear_offender(&key);
+		return self
+			.write_str(&format!("Cleared rate limit offender {key}."))
+			.await;
+	}
+
+	let offenders: Vec<_> = ratelimit.offenders().collect().await;
+	if offenders.is_empty() {
+		return self.write_str("No rate limit offenders.").await;
+	}
+
+	let mut out = String::new();
+	writeln!(out, "| Offender | Refused until | Times |")?;
+	writeln!(out, "| -------- | ------------- | ----- |")?;
+	for (key, offender) in offenders {
+		let until = time::format(offender.until, "%+");
+		writeln!(out, "| {key} | {until} | {} |", offender.count)?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn signing_keys(&self) -> Result {
+	let server_keys = &self.services.server_keys;
+	let (key_id, verify_key) = server_keys.active_verify_key();
+
+	let mut out = String::new();
+	writeln!(out, "| Key ID | Public key | Expired |")?;
+	writeln!(out, "| ------ | ---------- | ------- |")?;
+	writeln!(out, "| {key_id} | {} | active |", verify_key.key.encode())?;
+	for (key_id, old) in server_keys.old_verify_keys().iter().rev() {
+		let expired = old
+			.expired_ts
+			.to_system_time()
+			.map_or_else(|| "-".to_owned(), |ts| time::format(ts, "%+"));
+
+		writeln!(out, "| {key_id} | {} | {expired} |", old.key.encode())?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn rotate_signing_key(&self) -> Result {
+	let previous = self.services.server_keys.active_key_id();
+	let key_id = self.services.server_keys.rotate_keypair()?;
+
+	self.write_str(&format!(
+		"Rotated the signing key from {previous} to {key_id}. Remote servers will fetch the new \
+		 key as they need it; {previous} remains published as an old verify key."
+	))
+	.await
+}
+
 #[admin_command]
 pub(super) async fn clear_caches(&self) -> Result {
 	self.services.clear_cache().await;
@@ -36,6 +36,31 @@ pub(super) enum ServerCommand {
 	/// - Print database memory usage statistics
 	MemoryUsage,
 
+	/// - List the most recent requests which exceeded a slow request threshold,
+	///   slowest first
+	SlowRequests {
+		/// Forget the slow requests after listing them.
+		#[arg(long)]
+		clear: bool,
+	},
+
+	/// - List the users and addresses recorded as rate limit offenders
+	RateLimitOffenders {
+		/// Forget the offender with this user ID or address, lifting its
+		/// penalty, instead of listing them.
+		#[arg(long)]
+		clear: Option<String>,
+	},
+
+	/// - Show the server's active signing key and the keys it rotated out
+	SigningKeys,
+
+	/// - Replace the server's signing key with a new one
+	///
+	/// The previous key is kept as an old verify key, expiring now, so events
+	/// and requests it signed can still be verified.
+	RotateSigningKey,
+
 	/// - Clears all of Tuwunel's caches
 	ClearCaches,
 
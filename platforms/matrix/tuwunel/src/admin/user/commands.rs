@@ -1,10 +1,12 @@
-use std::{cmp, collections::BTreeMap};
+use std::{cmp, collections::BTreeMap, fmt::Write, path::PathBuf, time::Duration};
 
 use futures::{FutureExt, StreamExt, TryStreamExt};
 use ruma::{
-	Int, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId, UserId,
+	DeviceId, Int, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId,
+	UserId,
 	events::{
-		RoomAccountDataEventType, StateEventType,
+		GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
+		push_rules::PushRulesEventContent,
 		room::{
 			power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent, UserPowerLevel},
 			redaction::RoomRedactionEventContent,
@@ -13,19 +15,24 @@
 	},
 	uint,
 };
+use serde_json::json;
 use tuwunel_core::{
-	Err, Result, debug_warn, info,
+	Err, Result, debug_warn, err, info,
 	matrix::{Event, pdu::PduBuilder},
-	utils::{self, ReadyExt, stream::IterStream},
+	utils::{self, ReadyExt, stream::IterStream, time},
+};
+use tuwunel_database::Deserialized;
+use tuwunel_service::{
+	Services,
+	users::{Export as AccountExport, Register},
 };
-use tuwunel_service::{Services, users::Register};
 
 use crate::{
 	admin_command, get_room_info,
 	utils::{parse_active_local_user_id, parse_local_user_id, parse_user_id},
 };
 
-const AUTO_GEN_PASSWORD_LENGTH: usize = 25;
+pub(super) const AUTO_GEN_PASSWORD_LENGTH: usize = 25;
 const BULK_JOIN_REASON: &str = "Bulk force joining this room as initiated by the server admin.";
 
 #[admin_command]
@@ -46,7 +53,12 @@ pub(super) async fn list_users(&self) -> Result {
 }
 
 #[admin_command]
-pub(super) async fn create_user(&self, username: String, password: Option<String>) -> Result {
+pub(super) async fn create_user(
+	&self,
+	username: String,
+	password: Option<String>,
+	allow_reserved: bool,
+) -> Result {
 	// Validate user id
 	let user_id = parse_local_user_id(self.services, &username)?;
 
@@ -60,6 +72,17 @@ pub(super) async fn create_user(&self, username: String, password: Option<String
 		return Err!("User {user_id} already exists");
 	}
 
+	if !allow_reserved
+		&& self
+			.services
+			.users
+			.is_reserved_localpart(user_id.localpart())
+	{
+		return Err!(
+			"Username {user_id} is reserved; pass --allow-reserved to create it anyway."
+		);
+	}
+
 	let password = password.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
 
 	self.services
@@ -92,6 +115,148 @@ pub(super) async fn deactivate(&self, no_leave_rooms: bool, user_id: String) ->
 		.await
 }
 
+#[admin_command]
+pub(super) async fn suspend(&self, user_id: String) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if user_id == self.services.globals.server_user {
+		return Err!("Not allowed to suspend the server service account.");
+	}
+
+	if !self.services.users.exists(&user_id).await {
+		return Err!("User {user_id} does not exist.");
+	}
+
+	if self.services.users.is_suspended(&user_id).await {
+		return Err!("User {user_id} is already suspended.");
+	}
+
+	self.services.users.suspend(&user_id);
+	info!("User {user_id} has been suspended by an admin");
+
+	self.write_str(&format!("User {user_id} has been suspended."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn unsuspend(&self, user_id: String) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self.services.users.is_suspended(&user_id).await {
+		return Err!("User {user_id} is not suspended.");
+	}
+
+	self.services.users.unsuspend(&user_id);
+	info!("User {user_id} has been unsuspended by an admin");
+
+	self.write_str(&format!("User {user_id} is no longer suspended."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn list_suspended(&self) -> Result {
+	let suspended: Vec<_> = self
+		.services
+		.users
+		.suspended()
+		.map(|(user_id, suspended_at)| (user_id.to_owned(), suspended_at))
+		.collect()
+		.await;
+
+	if suspended.is_empty() {
+		return self.write_str("No users are suspended.").await;
+	}
+
+	let mut out = String::new();
+	writeln!(out, "| User | Suspended |")?;
+	writeln!(out, "| ---- | --------- |")?;
+	for (user_id, suspended_at) in suspended {
+		let suspended_at = time::timepoint_from_epoch(Duration::from_secs(suspended_at))?;
+		let suspended_at = time::format(suspended_at, "%+");
+
+		writeln!(out, "| {user_id} | {suspended_at} |")?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn shadow_ban(&self, user_id: String) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if user_id == self.services.globals.server_user {
+		return Err!("Not allowed to shadow-ban the server service account.");
+	}
+
+	if !self.services.users.exists(&user_id).await {
+		return Err!("User {user_id} does not exist.");
+	}
+
+	if self
+		.services
+		.users
+		.is_shadow_banned(&user_id)
+		.await
+	{
+		return Err!("User {user_id} is already shadow-banned.");
+	}
+
+	self.services.users.shadow_ban(&user_id);
+	info!("User {user_id} has been shadow-banned by an admin");
+
+	self.write_str(&format!("User {user_id} has been shadow-banned."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn unshadow_ban(&self, user_id: String) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	if !self
+		.services
+		.users
+		.is_shadow_banned(&user_id)
+		.await
+	{
+		return Err!("User {user_id} is not shadow-banned.");
+	}
+
+	self.services.users.unshadow_ban(&user_id);
+	info!("User {user_id} has been unshadow-banned by an admin");
+
+	self.write_str(&format!("User {user_id} is no longer shadow-banned."))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn list_shadow_banned(&self) -> Result {
+	let shadow_banned: Vec<_> = self
+		.services
+		.users
+		.shadow_banned()
+		.map(|(user_id, shadow_banned_at)| (user_id.to_owned(), shadow_banned_at))
+		.collect()
+		.await;
+
+	if shadow_banned.is_empty() {
+		return self
+			.write_str("No users are shadow-banned.")
+			.await;
+	}
+
+	let mut out = String::new();
+	writeln!(out, "| User | Shadow-banned |")?;
+	writeln!(out, "| ---- | ------------- |")?;
+	for (user_id, shadow_banned_at) in shadow_banned {
+		let shadow_banned_at = time::timepoint_from_epoch(Duration::from_secs(shadow_banned_at))?;
+		let shadow_banned_at = time::format(shadow_banned_at, "%+");
+
+		writeln!(out, "| {user_id} | {shadow_banned_at} |")?;
+	}
+
+	self.write_str(&out).await
+}
+
 #[admin_command]
 pub(super) async fn delete_device(
 	&self,
@@ -111,6 +276,208 @@ pub(super) async fn delete_device(
 		.await
 }
 
+#[admin_command]
+pub(super) async fn sessions(&self, user_id: String) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let devices: Vec<_> = self
+		.services
+		.users
+		.all_devices_metadata(&user_id)
+		.collect()
+		.await;
+
+	if devices.is_empty() {
+		return Err!("User {user_id} has no devices.");
+	}
+
+	let timestamp = |secs: u64| -> Result<String> {
+		let timepoint = time::timepoint_from_epoch(Duration::from_secs(secs))?;
+		Ok(time::format(timepoint, "%+"))
+	};
+
+	let mut out = String::new();
+	writeln!(
+		out,
+		"| Device | Display name | Created | Last seen | Last IP | Location | User agent | \
+		 Token |"
+	)?;
+	writeln!(out, "| --- | --- | --- | --- | --- | --- | --- | --- |")?;
+	for device in devices {
+		let device_id = &device.device_id;
+		let session = self
+			.services
+			.users
+			.get_session(&user_id, device_id)
+			.await
+			.unwrap_or_default();
+
+		let token_kind = self
+			.services
+			.users
+			.token_kind(&user_id, device_id)
+			.await;
+
+		let created = session
+			.created_at
+			.map(timestamp)
+			.transpose()?
+			.unwrap_or_else(|| "unknown".to_owned());
+
+		let last_seen = match session.last_seen_at {
+			| 0 => "unknown".to_owned(),
+			| secs => timestamp(secs)?,
+		};
+
+		let last_ip = session
+			.last_seen_ip
+			.map(|ip| ip.to_string())
+			.or(device.last_seen_ip)
+			.unwrap_or_default();
+
+		let location = session
+			.last_seen_ip
+			.and_then(|ip| self.services.geoip.locate(ip))
+			.unwrap_or_default();
+
+		writeln!(
+			out,
+			"| {device_id} | {} | {created} | {last_seen} | {last_ip} | {location} | {} | \
+			 {token_kind} |",
+			device.display_name.as_deref().unwrap_or_default(),
+			session.user_agent.as_deref().unwrap_or_default(),
+		)?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn client_versions(&self, user_id: String) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let devices: Vec<_> = self
+		.services
+		.users
+		.all_devices_metadata(&user_id)
+		.collect()
+		.await;
+
+	if devices.is_empty() {
+		return Err!("User {user_id} has no devices.");
+	}
+
+	let timestamp = |secs: u64| -> Result<String> {
+		let timepoint = time::timepoint_from_epoch(Duration::from_secs(secs))?;
+		Ok(time::format(timepoint, "%+"))
+	};
+
+	let mut out = String::new();
+	writeln!(out, "| Device | User agent | API versions | Fetched versions | Last seen |")?;
+	writeln!(out, "| --- | --- | --- | --- | --- |")?;
+	for device in devices {
+		let device_id = &device.device_id;
+		let session = self
+			.services
+			.users
+			.get_session(&user_id, device_id)
+			.await
+			.unwrap_or_default();
+
+		let api_versions = session
+			.api_versions
+			.iter()
+			.map(String::as_str)
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		let fetched_versions = session
+			.versions_fetched_at
+			.map(timestamp)
+			.transpose()?
+			.unwrap_or_else(|| "never".to_owned());
+
+		let last_seen = match session.last_seen_at {
+			| 0 => "unknown".to_owned(),
+			| secs => timestamp(secs)?,
+		};
+
+		writeln!(
+			out,
+			"| {device_id} | {} | {api_versions} | {fetched_versions} | {last_seen} |",
+			session.user_agent.as_deref().unwrap_or_default(),
+		)?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn list_openid_tokens(&self, user_id: String) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let tokens = self.services.users.openid_tokens(&user_id).await;
+	if tokens.is_empty() {
+		return Err!("User {user_id} has no unexpired OpenID tokens.");
+	}
+
+	let timestamp = |secs: u64| -> Result<String> {
+		let timepoint = time::timepoint_from_epoch(Duration::from_secs(secs))?;
+		Ok(time::format(timepoint, "%+"))
+	};
+
+	let mut out = String::new();
+	writeln!(
+		out,
+		"| Token | Device | Created | Expires | Exchanges | Last exchanged | Integration IP | \
+		 Integration user agent |"
+	)?;
+	writeln!(out, "| --- | --- | --- | --- | --- | --- | --- | --- |")?;
+	for (prefix, token) in tokens {
+		let last_exchanged = token
+			.last_exchanged_at
+			.map(timestamp)
+			.transpose()?
+			.unwrap_or_default();
+
+		writeln!(
+			out,
+			"| {prefix}… | {} | {} | {} | {} | {last_exchanged} | {} | {} |",
+			token
+				.device_id
+				.as_deref()
+				.map_or("unknown", DeviceId::as_str),
+			timestamp(token.created_at)?,
+			timestamp(token.expires_at)?,
+			token.exchanges,
+			token
+				.last_exchanged_ip
+				.map(|ip| ip.to_string())
+				.unwrap_or_default(),
+			token
+				.last_exchanged_user_agent
+				.as_deref()
+				.unwrap_or_default(),
+		)?;
+	}
+
+	self.write_str(&out).await
+}
+
+#[admin_command]
+pub(super) async fn revoke_openid_tokens(
+	&self,
+	user_id: String,
+	token_prefix: Option<String>,
+) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let revoked = self
+		.services
+		.users
+		.revoke_openid_tokens(&user_id, token_prefix.as_deref())
+		.await;
+
+	self.write_str(&format!("Revoked {revoked} OpenID tokens of {user_id}."))
+		.await
+}
+
 #[admin_command]
 pub(super) async fn reset_password(&self, username: String, password: Option<String>) -> Result {
 	let user_id = parse_local_user_id(self.services, &username)?;
@@ -233,6 +600,12 @@ async fn deactivate_user(services: &Services, user_id: &UserId, no_leave_rooms:
 			.boxed()
 			.await?;
 	} else {
+		// Only a user keeping their rooms keeps the notice with them.
+		services
+			.server_notices
+			.notify_deactivation(user_id)
+			.await;
+
 		services.users.deactivate_account(user_id).await?;
 	}
 
@@ -269,6 +642,113 @@ pub(super) async fn list_joined_rooms(&self, user_id: String) -> Result {
 		.await
 }
 
+#[admin_command]
+pub(super) async fn export_account(&self, user_id: String, path: PathBuf) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	let export = self
+		.services
+		.users
+		.export_account(&user_id)
+		.await?;
+
+	let json = serde_json::to_vec_pretty(&export)?;
+	tokio::fs::write(&path, json)
+		.await
+		.map_err(|e| err!("Failed to write {}: {e}", path.display()))?;
+
+	self.write_str(&format!(
+		"Exported {user_id} with {} devices, {} joined rooms and {} filters to {}.",
+		export.devices.len(),
+		export.rooms.join.len(),
+		export.filters.len(),
+		path.display(),
+	))
+	.await
+}
+
+#[admin_command]
+pub(super) async fn import_account(
+	&self,
+	path: PathBuf,
+	user_id: Option<String>,
+	no_join_rooms: bool,
+) -> Result {
+	let json = tokio::fs::read(&path)
+		.await
+		.map_err(|e| err!("Failed to read {}: {e}", path.display()))?;
+
+	let export: AccountExport = serde_json::from_slice(&json)
+		.map_err(|e| err!("Invalid account export in {}: {e}", path.display()))?;
+
+	let user_id = user_id
+		.as_deref()
+		.unwrap_or_else(|| export.user_id.localpart());
+
+	let user_id = parse_local_user_id(self.services, user_id)?;
+
+	if user_id == self.services.globals.server_user {
+		return Err!("Not allowed to import into the server service account.");
+	}
+
+	if !self.services.users.exists(&user_id).await {
+		let password = utils::random_string(AUTO_GEN_PASSWORD_LENGTH);
+		self.services
+			.users
+			.full_register(Register {
+				user_id: Some(&user_id),
+				password: Some(&password),
+				..Default::default()
+			})
+			.await?;
+
+		writeln!(self, "Created user {user_id} with password: `{password}`").await?;
+	}
+
+	let imported = self
+		.services
+		.users
+		.import_account(&user_id, &export)
+		.await?;
+
+	writeln!(
+		self,
+		"Imported {} account data events, {} devices and {} filters into {user_id}.",
+		imported.account_data, imported.devices, imported.filters,
+	)
+	.await?;
+
+	if no_join_rooms {
+		return Ok(());
+	}
+
+	let mut joined = 0_usize;
+	for room_id in &export.rooms.join {
+		let servers: Vec<_> = room_id
+			.server_name()
+			.into_iter()
+			.chain(Some(export.user_id.server_name()))
+			.map(ToOwned::to_owned)
+			.collect();
+
+		let state_lock = self.services.state.mutex.lock(room_id).await;
+		let result = self
+			.services
+			.membership
+			.join(&user_id, room_id, None, None, &servers, false, &state_lock)
+			.boxed()
+			.await;
+
+		drop(state_lock);
+		match result {
+			| Ok(()) => joined = joined.saturating_add(1),
+			| Err(e) => writeln!(self, "Failed to join {room_id}: {e}").await?,
+		}
+	}
+
+	write!(self, "Joined {joined} of {} rooms.", export.rooms.join.len()).await
+}
+
 #[admin_command]
 pub(super) async fn force_join_list_of_local_users(
 	&self,
@@ -732,6 +1212,20 @@ pub(super) async fn make_user_admin(&self, user_id: String) -> Result {
 		.await
 }
 
+#[admin_command]
+pub(super) async fn notice(&self, user_id: String, message: Vec<String>) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	let event_id = self
+		.services
+		.server_notices
+		.send_text(&user_id, &message.join(" "))
+		.await?;
+
+	self.write_str(&format!("Sent server notice {event_id} to {user_id}."))
+		.await
+}
+
 #[admin_command]
 pub(super) async fn put_room_tag(
 	&self,
@@ -828,6 +1322,81 @@ pub(super) async fn get_room_tags(&self, user_id: String, room_id: OwnedRoomId)
 		.await
 }
 
+#[admin_command]
+pub(super) async fn get_account_data(
+	&self,
+	user_id: String,
+	kind: String,
+	room_id: Option<OwnedRoomId>,
+) -> Result {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+
+	let event: serde_json::Value = self
+		.services
+		.account_data
+		.get_raw(room_id.as_deref(), &user_id, &kind)
+		.await
+		.deserialized()
+		.map_err(|_| err!("No {kind} account data found for {user_id}."))?;
+
+	let content = event
+		.get("content")
+		.unwrap_or(&serde_json::Value::Null);
+
+	let json_text = serde_json::to_string_pretty(content)?;
+	self.write_str(&format!("```json\n{json_text}\n```"))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn set_account_data(
+	&self,
+	user_id: String,
+	kind: String,
+	room_id: Option<OwnedRoomId>,
+) -> Result {
+	if self.body.len() < 2
+		|| !self.body[0].trim().starts_with("```")
+		|| self.body.last().unwrap_or(&"").trim() != "```"
+	{
+		return Err!("Expected code block in command body. Add --help for details.");
+	}
+
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	if !self.services.users.exists(&user_id).await {
+		return Err!("User {user_id} does not exist.");
+	}
+
+	let string = self.body[1..self.body.len().saturating_sub(1)].join("\n");
+	let content: serde_json::Value =
+		serde_json::from_str(&string).map_err(|e| err!("Invalid json in command body: {e}"))?;
+
+	if !content.is_object() {
+		return Err!("Account data content must be a JSON object.");
+	}
+
+	if kind == GlobalAccountDataEventType::PushRules.to_cow_str() {
+		serde_json::from_value::<PushRulesEventContent>(content.clone())
+			.map_err(|e| err!("Invalid push rules: {e}"))?;
+	}
+
+	self.services
+		.account_data
+		.update(
+			room_id.as_deref(),
+			&user_id,
+			kind.as_str().into(),
+			&json!({
+				"type": kind,
+				"content": content,
+			}),
+		)
+		.await?;
+
+	self.write_str(&format!("Successfully updated {kind} account data for {user_id}."))
+		.await
+}
+
 #[admin_command]
 pub(super) async fn redact_event(&self, event_id: OwnedEventId) -> Result {
 	let Ok(event) = self
@@ -0,0 +1,275 @@
+//! Bulk creation of accounts from CSV or JSON, for migrations from other
+//! homeservers or authentication systems.
+
+use std::{collections::HashSet, fmt::Write, mem::take, path::PathBuf};
+
+use ruma::{OwnedUserId, thirdparty::Medium};
+use serde::Deserialize;
+use serde_json::{Map, Value as JsonValue};
+use tuwunel_core::{
+	Err, Result, err,
+	utils::{self, time::now_millis},
+};
+use tuwunel_service::{Services, users::Register};
+
+use super::commands::AUTO_GEN_PASSWORD_LENGTH;
+use crate::{admin_command, utils::parse_local_user_id};
+
+/// Number of accounts created between progress reports.
+const BATCH_SIZE: usize = 100;
+
+/// A row of the imported file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImportUser {
+	localpart: String,
+	displayname: Option<String>,
+	email: Option<String>,
+
+	/// Argon2 PHC string or bcrypt hash of the user's password.
+	password_hash: Option<String>,
+
+	/// Subject of the user at the `--sso-provider`.
+	sso_subject: Option<String>,
+}
+
+#[admin_command]
+pub(super) async fn import(
+	&self,
+	path: PathBuf,
+	sso_provider: Option<String>,
+	allow_reserved: bool,
+	dry_run: bool,
+) -> Result {
+	let file = tokio::fs::read_to_string(&path)
+		.await
+		.map_err(|e| err!("Failed to read {}: {e}", path.display()))?;
+
+	let is_json = path
+		.extension()
+		.is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+	let users: Vec<ImportUser> = if is_json {
+		serde_json::from_str(&file)
+			.map_err(|e| err!("Invalid user list in {}: {e}", path.display()))?
+	} else {
+		parse_csv(&file)?
+	};
+
+	let provider = match sso_provider.as_deref() {
+		| Some(id) => Some(self.services.oauth.providers.get(id).await?),
+		| None => None,
+	};
+
+	// Every row is checked before any account is created.
+	let mut seen = HashSet::new();
+	let mut valid = Vec::with_capacity(users.len());
+	let mut errors = String::new();
+	for (row, user) in users.iter().enumerate() {
+		let row = row.saturating_add(1);
+		match check_user(self.services, user, provider.is_some(), allow_reserved, &mut seen).await
+		{
+			| Ok(user_id) => valid.push((row, user_id, user)),
+			| Err(e) => writeln!(errors, "- row {row}: {e}")?,
+		}
+	}
+
+	let invalid = users.len().saturating_sub(valid.len());
+	if invalid > 0 {
+		return Err!(
+			"{invalid} of {} users are invalid; none were imported:\n{errors}",
+			users.len()
+		);
+	}
+
+	if dry_run {
+		return self
+			.write_str(&format!(
+				"{} users would be imported from {}.",
+				valid.len(),
+				path.display()
+			))
+			.await;
+	}
+
+	let total = valid.len();
+	let mut created = 0_usize;
+	let mut generated = Vec::new();
+	let mut email_errors = Vec::new();
+	for batch in valid.chunks(BATCH_SIZE) {
+		for (row, user_id, user) in batch {
+			let password = match (&user.password_hash, &user.sso_subject) {
+				| (None, None) => Some(utils::random_string(AUTO_GEN_PASSWORD_LENGTH)),
+				| (_, Some(_)) => Some("*".to_owned()),
+				| (Some(_), None) => None,
+			};
+
+			self.services
+				.users
+				.full_register(Register {
+					user_id: Some(user_id),
+					password: password.as_deref(),
+					origin: user.sso_subject.is_some().then_some("sso"),
+					displayname: user.displayname.as_deref(),
+					omit_displayname_suffix: user.displayname.is_some(),
+					..Default::default()
+				})
+				.await
+				.map_err(|e| err!("Failed to create {user_id} after {created} users: {e}"))?;
+
+			if let Some(password_hash) = &user.password_hash {
+				self.services
+					.users
+					.set_password_hash(user_id, password_hash)?;
+			}
+
+			if let (Some(provider), Some(sub)) = (&provider, &user.sso_subject) {
+				self.services
+					.oauth
+					.sessions
+					.associate(provider, user_id, sub)
+					.await?;
+			}
+
+			if let Some(email) = &user.email
+				&& let Err(e) = self
+					.services
+					.users
+					.add_threepid(user_id, &Medium::Email, email, now_millis())
+					.await
+			{
+				email_errors.push((row, user_id, email, e));
+			}
+
+			if let (None, None) = (&user.password_hash, &user.sso_subject) {
+				generated.extend(password.map(|password| (user_id, password)));
+			}
+
+			created = created.saturating_add(1);
+		}
+
+		writeln!(self, "Imported {created}/{total} users...").await?;
+	}
+
+	let mut out = format!("Imported {created} users from {}.\n", path.display());
+	if !email_errors.is_empty() {
+		writeln!(out, "\n{} email addresses were not imported:", email_errors.len())?;
+		for (row, user_id, email, e) in email_errors {
+			writeln!(out, "- row {row}: {email} of {user_id}: {e}")?;
+		}
+	}
+
+	if !generated.is_empty() {
+		writeln!(out, "\n| User | Generated password |")?;
+		writeln!(out, "| ---- | ------------------ |")?;
+		for (user_id, password) in generated {
+			writeln!(out, "| {user_id} | `{password}` |")?;
+		}
+	}
+
+	self.write_str(&out).await
+}
+
+/// Checks a user can be imported. Returns its ID.
+async fn check_user(
+	services: &Services,
+	user: &ImportUser,
+	has_provider: bool,
+	allow_reserved: bool,
+	seen: &mut HashSet<OwnedUserId>,
+) -> Result<OwnedUserId> {
+	let user_id = parse_local_user_id(services, &user.localpart)?;
+	if let Err(e) = user_id.validate_strict() {
+		return Err!("{user_id} contains disallowed characters or spaces: {e}");
+	}
+
+	if !seen.insert(user_id.clone()) {
+		return Err!("{user_id} appears more than once");
+	}
+
+	if services.users.exists(&user_id).await {
+		return Err!("{user_id} already exists");
+	}
+
+	if !allow_reserved
+		&& services
+			.users
+			.is_reserved_localpart(user_id.localpart())
+	{
+		return Err!("{user_id} is reserved; pass --allow-reserved to import it anyway");
+	}
+
+	match (&user.password_hash, &user.sso_subject) {
+		| (Some(_), Some(_)) => Err!("{user_id} has both a password hash and an SSO subject"),
+		| (Some(hash), None) if !utils::hash::is_password_hash(hash) =>
+			Err!("{user_id} has a password hash which is not Argon2 nor bcrypt"),
+		| (None, Some(_)) if !has_provider =>
+			Err!("{user_id} has an SSO subject but no --sso-provider was given"),
+		| _ => Ok(user_id),
+	}
+}
+
+/// Parses CSV with a header row naming the fields of [`ImportUser`]. Fields may
+/// be quoted, with the quotes inside them doubled; empty fields are omitted.
+fn parse_csv(text: &str) -> Result<Vec<ImportUser>> {
+	let mut records = csv_records(text).into_iter();
+	let Some(header) = records.next() else {
+		return Ok(Vec::new());
+	};
+
+	records
+		.enumerate()
+		.map(|(row, record)| {
+			let row = row.saturating_add(1);
+			if record.len() != header.len() {
+				return Err!(
+					"Row {row} has {} fields but the header has {}",
+					record.len(),
+					header.len()
+				);
+			}
+
+			let fields: Map<String, JsonValue> = header
+				.iter()
+				.zip(record)
+				.filter(|(_, value)| !value.is_empty())
+				.map(|(name, value)| (name.trim().to_owned(), JsonValue::String(value)))
+				.collect();
+
+			serde_json::from_value(fields.into()).map_err(|e| err!("Row {row}: {e}"))
+		})
+		.collect()
+}
+
+/// Splits CSV into records of fields, skipping blank lines.
+fn csv_records(text: &str) -> Vec<Vec<String>> {
+	let mut records = Vec::new();
+	let mut record = Vec::new();
+	let mut field = String::new();
+	let mut quoted = false;
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			| '"' if quoted && chars.peek() == Some(&'"') => {
+				chars.next();
+				field.push('"');
+			},
+			| '"' => quoted = !quoted,
+			| ',' if !quoted => record.push(take(&mut field)),
+			| '\r' if !quoted => {},
+			| '\n' if !quoted => {
+				record.push(take(&mut field));
+				records.push(take(&mut record));
+			},
+			| c => field.push(c),
+		}
+	}
+
+	if !field.is_empty() || !record.is_empty() {
+		record.push(field);
+		records.push(record);
+	}
+
+	records.retain(|record| record.iter().any(|field| !field.is_empty()));
+	records
+}
@@ -1,4 +1,7 @@
 mod commands;
+mod import;
+
+use std::path::PathBuf;
 
 use clap::Subcommand;
 use ruma::{OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId};
@@ -16,6 +19,10 @@ pub(super) enum UserCommand {
 		username: String,
 		/// Password of the new user, if unspecified one is generated
 		password: Option<String>,
+
+		/// Create the user even though its username is reserved
+		#[arg(long)]
+		allow_reserved: bool,
 	},
 
 	/// - Reset user password
@@ -36,6 +43,41 @@ pub(super) enum UserCommand {
 		user_id: String,
 	},
 
+	/// - Suspend a user (MSC3823)
+	///
+	/// The user can still sign in, sync and read their rooms, but every
+	/// request writing to them is refused until they are unsuspended. Unlike
+	/// deactivation, this is reversible and keeps the account intact.
+	Suspend {
+		user_id: String,
+	},
+
+	/// - Lift the suspension of a user
+	Unsuspend {
+		user_id: String,
+	},
+
+	/// - List suspended users with when they were suspended
+	ListSuspended,
+
+	/// - Shadow-ban a user
+	///
+	/// The user's events are accepted and stored as usual, but not sent to
+	/// other servers nor pushed to other local users. Their invites of remote
+	/// users, typing, receipts and presence are not sent either. Unlike
+	/// suspension, the user is not told.
+	ShadowBan {
+		user_id: String,
+	},
+
+	/// - Lift the shadow-ban of a user
+	UnshadowBan {
+		user_id: String,
+	},
+
+	/// - List shadow-banned users with when they were shadow-banned
+	ListShadowBanned,
+
 	/// - Deactivate a list of users
 	///
 	/// Recommended to use in conjunction with list-local-users.
@@ -65,6 +107,35 @@ pub(super) enum UserCommand {
 		device_id: OwnedDeviceId,
 	},
 
+	/// - List a user's devices with when they were created and last seen, the
+	///   address and user agent they were last seen with, and how they
+	///   authenticate.
+	Sessions {
+		user_id: String,
+	},
+
+	/// - List the versions of the client API each of a user's devices made
+	///   requests with, including the unstable features, and when they last
+	///   asked for the versions supported by the server.
+	ClientVersions {
+		user_id: String,
+	},
+
+	/// - List a user's unexpired OpenID tokens, by the start of the token, with
+	///   the device which requested them and the address and user agent of the
+	///   integration which last exchanged them.
+	ListOpenidTokens {
+		user_id: String,
+	},
+
+	/// - Revoke a user's OpenID tokens, or only the one starting with the given
+	///   prefix.
+	RevokeOpenidTokens {
+		user_id: String,
+
+		token_prefix: Option<String>,
+	},
+
 	/// - List local users by recent activity.
 	LastActive {
 		#[arg(short, long)]
@@ -111,6 +182,18 @@ pub(super) enum UserCommand {
 		user_id: String,
 	},
 
+	/// - Sends a server notice to a local user
+	///
+	/// The notice is posted by the server user in the user's server notices
+	/// room, which is created and the user invited to it if needed. The
+	/// message is Markdown.
+	Notice {
+		user_id: String,
+
+		#[arg(required = true, trailing_var_arg = true)]
+		message: Vec<String>,
+	},
+
 	/// - Puts a room tag for the specified user and room ID.
 	///
 	/// This is primarily useful if you'd like to set your admin room
@@ -137,6 +220,87 @@ pub(super) enum UserCommand {
 		room_id: OwnedRoomId,
 	},
 
+	/// - Gets the content of an account data event of the specified user,
+	///   global unless a room ID is given
+	GetAccountData {
+		user_id: String,
+		/// Account data event type, e.g. `m.push_rules` or `m.direct`
+		kind: String,
+		room_id: Option<OwnedRoomId>,
+	},
+
+	/// - Replaces the content of an account data event of the specified user,
+	///   global unless a room ID is given
+	///
+	/// The new content is a JSON object provided in a Markdown code block below
+	/// the command. The change is delivered to the user's clients on their next
+	/// sync. An empty object clears the event.
+	SetAccountData {
+		user_id: String,
+		/// Account data event type, e.g. `m.push_rules` or `m.direct`
+		kind: String,
+		room_id: Option<OwnedRoomId>,
+	},
+
+	/// - Writes a user's profile, account data, devices, room memberships and
+	///   filters to a JSON file
+	///
+	/// Push rules are included with the account data. Passwords, access
+	/// tokens and encryption keys are not exported.
+	ExportAccount {
+		user_id: String,
+
+		/// File to write, on the server's filesystem
+		#[arg(long)]
+		path: PathBuf,
+	},
+
+	/// - Recreates an account from a JSON file written by export-account
+	///
+	/// The account is created with a generated password if it does not exist.
+	/// Devices are restored without their sessions. Rooms the account was
+	/// joined to are joined again unless --no-join-rooms is given.
+	ImportAccount {
+		/// File to read, on the server's filesystem
+		#[arg(long)]
+		path: PathBuf,
+
+		/// Local user to import into instead of the exported user's localpart
+		#[arg(long)]
+		user_id: Option<String>,
+
+		#[arg(long)]
+		no_join_rooms: bool,
+	},
+
+	/// - Creates accounts in bulk from a CSV or JSON file
+	///
+	/// Each user has a `localpart`, and optionally a `displayname`, an
+	/// `email`, a `password_hash` (Argon2 PHC string or bcrypt) or an
+	/// `sso_subject` at the --sso-provider. CSV files have a header row naming
+	/// these columns; JSON files (by their extension) hold an array of
+	/// objects. Users with neither a password hash nor an SSO subject are
+	/// given a generated password. Nothing is imported unless every user is
+	/// valid. Email addresses associated with another user are not imported
+	/// and are listed in the report.
+	Import {
+		/// File to read, on the server's filesystem
+		#[arg(long)]
+		path: PathBuf,
+
+		/// Identity provider the SSO subjects belong to
+		#[arg(long)]
+		sso_provider: Option<String>,
+
+		/// Import users even though their usernames are reserved
+		#[arg(long)]
+		allow_reserved: bool,
+
+		/// Only check the users, without creating them
+		#[arg(long)]
+		dry_run: bool,
+	},
+
 	/// - Attempts to forcefully redact the specified event ID from the sender
 	///   user
 	///
@@ -1,11 +1,17 @@
-use axum::extract::State;
+use axum::{
+	extract::{Query, State},
+	response::{Html, IntoResponse, Redirect, Response},
+};
 use axum_client_ip::InsecureClientIp;
 use futures::{FutureExt, StreamExt};
 use ruma::api::client::account::{
-	ThirdPartyIdRemovalStatus, change_password, deactivate, get_3pids,
-	request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn, whoami,
+	ThirdPartyIdRemovalStatus, add_3pid, change_password, deactivate, delete_3pid, get_3pids,
+	request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn,
+	request_password_change_token_via_email, whoami,
 };
+use serde::Deserialize;
 use tuwunel_core::{Err, Result, err, info, utils::ReadyExt};
+use url::Url;
 
 use crate::{Ruma, router::auth_uiaa};
 
@@ -13,7 +19,8 @@
 ///
 /// Changes the password of this account.
 ///
-/// - Requires UIAA to verify user password
+/// - Requires UIAA to verify user password, or without an access token, an
+///   email address associated with the user
 /// - Changes the password of the sender user
 /// - The password hash is calculated using argon2 with 32 character salt, the
 ///   plain password is
@@ -123,17 +130,60 @@ pub(crate) async fn deactivate_route(
 /// # `GET _matrix/client/v3/account/3pid`
 ///
 /// Get a list of third party identifiers associated with this account.
-///
-/// - Currently always returns empty list
 pub(crate) async fn third_party_route(
+	State(services): State<crate::State>,
 	body: Ruma<get_3pids::v3::Request>,
 ) -> Result<get_3pids::v3::Response> {
-	let _sender_user = body
-		.sender_user
-		.as_ref()
-		.expect("user is authenticated");
+	let threepids = services
+		.users
+		.threepids(body.sender_user())
+		.collect()
+		.await;
+
+	Ok(get_3pids::v3::Response::new(threepids))
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/add`
+///
+/// Associates an email address the user verified with their account.
+///
+/// - Requires UIAA to verify the user, except for appservices
+pub(crate) async fn add_3pid_route(
+	State(services): State<crate::State>,
+	body: Ruma<add_3pid::v3::Request>,
+) -> Result<add_3pid::v3::Response> {
+	let ref sender_user = match body.appservice_info {
+		| Some(_) => body.sender_user().to_owned(),
+		| None => auth_uiaa(&services, &body).await?,
+	};
+
+	services
+		.threepid
+		.bind_email(sender_user, body.sid.as_str(), body.client_secret.as_str())
+		.await?;
+
+	Ok(add_3pid::v3::Response::new())
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/delete`
+///
+/// Removes the association of an address with the user's account. Addresses
+/// are never bound on identity servers.
+pub(crate) async fn delete_3pid_route(
+	State(services): State<crate::State>,
+	body: Ruma<delete_3pid::v3::Request>,
+) -> Result<delete_3pid::v3::Response> {
+	if !services
+		.users
+		.remove_threepid(body.sender_user(), &body.medium, &body.address)
+		.await
+	{
+		return Err!(Request(ThreepidNotFound(
+			"The address is not associated with your account."
+		)));
+	}
 
-	Ok(get_3pids::v3::Response::new(Vec::new()))
+	Ok(delete_3pid::v3::Response::new(ThirdPartyIdRemovalStatus::NoSupport))
 }
 
 /// # `POST /_matrix/client/v3/account/3pid/email/requestToken`
@@ -141,12 +191,108 @@ pub(crate) async fn third_party_route(
 /// "This API should be used to request validation tokens when adding an email
 /// address to an account"
 ///
-/// - 403 signals that The homeserver does not allow the third party identifier
-///   as a contact option.
+/// - 400 signals that the address is associated with a user already.
+/// - 403 signals that the homeserver does not send emails.
+/// - 429 signals that too many emails were sent to the address or requested
+///   from the client's address.
 pub(crate) async fn request_3pid_management_token_via_email_route(
-	_body: Ruma<request_3pid_management_token_via_email::v3::Request>,
+	State(services): State<crate::State>,
+	body: Ruma<request_3pid_management_token_via_email::v3::Request>,
 ) -> Result<request_3pid_management_token_via_email::v3::Response> {
-	Err!(Request(ThreepidDenied("Third party identifiers are not implemented")))
+	if services
+		.threepid
+		.user_by_email(&body.email)
+		.await
+		.is_ok()
+	{
+		return Err!(Request(ThreepidInUse("The email address is already in use.")));
+	}
+
+	let sid = services
+		.threepid
+		.request_email_token(
+			&body.client_secret,
+			&body.email,
+			body.send_attempt,
+			body.next_link.as_deref(),
+		)
+		.await?;
+
+	Ok(request_3pid_management_token_via_email::v3::Response::new(sid.try_into()?))
+}
+
+/// # `POST /_matrix/client/v3/account/password/email/requestToken`
+///
+/// Sends a link to an email address associated with an account, with which
+/// the user proves they own the account to reset its password.
+///
+/// - 400 signals that the address is not associated with any user.
+/// - 403 signals that the homeserver does not send emails, or that the account
+///   signs in through SSO, JWT or LDAP and has no password to reset.
+/// - 429 signals that too many emails were sent to the address or requested
+///   from the client's address.
+pub(crate) async fn request_password_change_token_via_email_route(
+	State(services): State<crate::State>,
+	body: Ruma<request_password_change_token_via_email::v3::Request>,
+) -> Result<request_password_change_token_via_email::v3::Response> {
+	let Ok(user_id) = services.threepid.user_by_email(&body.email).await else {
+		return Err!(Request(ThreepidNotFound(
+			"The email address is not associated with any user."
+		)));
+	};
+
+	// Accounts signing in through single sign-on are not given a password
+	// through their email address, which would bypass the identity provider.
+	if !services.users.has_password(&user_id).await {
+		return Err!(Request(Forbidden("This account has no password to reset.")));
+	}
+
+	let sid = services
+		.threepid
+		.request_email_token(
+			&body.client_secret,
+			&body.email,
+			body.send_attempt,
+			body.next_link.as_deref(),
+		)
+		.await?;
+
+	Ok(request_password_change_token_via_email::v3::Response::new(sid.try_into()?))
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ValidateEmailQuery {
+	sid: String,
+	client_secret: String,
+	token: String,
+}
+
+/// # `GET /_tuwunel/email/validate`
+///
+/// Link sent to an email address to verify it. Sends the user on to the
+/// `next_link` of the client, if any.
+pub(crate) async fn validate_email_route(
+	State(services): State<crate::State>,
+	Query(query): Query<ValidateEmailQuery>,
+) -> Result<Response> {
+	let next_link = services
+		.threepid
+		.validate_email_token(&query.sid, &query.client_secret, &query.token)
+		.await?;
+
+	if let Some(next_link) = next_link
+		.as_deref()
+		.and_then(|link| Url::parse(link).ok())
+		.filter(|url| matches!(url.scheme(), "http" | "https"))
+	{
+		return Ok(Redirect::to(next_link.as_str()).into_response());
+	}
+
+	Ok(Html(
+		"<!DOCTYPE html><html><head><title>Email address verified</title></head><body><p>Your \
+		 email address was verified. Return to your app to continue.</p></body></html>",
+	)
+	.into_response())
 }
 
 /// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
@@ -33,6 +33,8 @@ pub(crate) async fn create_alias_route(
 		return Err!(Request(Forbidden("Room alias is forbidden.")));
 	}
 
+	services.sanitize.check_alias(&body.room_alias)?;
+
 	if services
 		.alias
 		.resolve_local_alias(&body.room_alias)
@@ -1,8 +1,15 @@
-use axum::extract::State;
+use axum::{
+	Json,
+	extract::{Query, State},
+	response::IntoResponse,
+};
+use futures::StreamExt;
 use ruma::api::{appservice::ping, client::appservice::request_ping};
+use serde::Deserialize;
+use serde_json::json;
 use tuwunel_core::{Err, Result, err};
 
-use crate::Ruma;
+use crate::{Ruma, router::Sender};
 
 /// # `POST /_matrix/client/v1/appservice/{appserviceId}/ping`
 ///
@@ -46,3 +53,55 @@ pub(crate) async fn appservice_ping(
 
 	Ok(request_ping::v1::Response { duration: timer.elapsed() })
 }
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeviceListsQuery {
+	/// Position returned as `next_batch` by the previous request; from the
+	/// beginning when absent.
+	#[serde(default)]
+	from: u64,
+}
+
+/// # `GET /_tuwunel/appservice/v1/device_lists`
+///
+/// Users of interest to the calling appservice whose device lists changed
+/// since `from`. Only appservices receiving ephemeral data are tracked. The
+/// changes up to `from` are forgotten, as the appservice has seen them.
+/// Authenticated with the appservice's `as_token`.
+pub(crate) async fn get_appservice_device_lists_route(
+	State(services): State<crate::State>,
+	Sender { appservice_info, .. }: Sender,
+	Query(query): Query<DeviceListsQuery>,
+) -> Result<impl IntoResponse> {
+	let Some(appservice_info) = appservice_info else {
+		return Err!(Request(Forbidden("This endpoint can only be called by appservices.")));
+	};
+
+	if !appservice_info.registration.receive_ephemeral {
+		return Err!(Request(Forbidden(
+			"Device list changes are only tracked for appservices receiving ephemeral data."
+		)));
+	}
+
+	services
+		.appservice
+		.prune_device_list_changes(&appservice_info.registration.id, query.from)
+		.await;
+
+	let next_batch = services.globals.wait_pending().await?;
+
+	let mut changed: Vec<_> = services
+		.appservice
+		.device_list_changes(&appservice_info.registration.id, query.from, Some(next_batch))
+		.map(|(user_id, _)| user_id.to_owned())
+		.collect()
+		.await;
+
+	changed.sort_unstable();
+	changed.dedup();
+
+	Ok(Json(json!({
+		"changed": changed,
+		"next_batch": next_batch,
+	})))
+}
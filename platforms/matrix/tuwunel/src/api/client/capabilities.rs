@@ -2,7 +2,7 @@
 
 use axum::extract::State;
 use ruma::{
-	RoomVersionId,
+	EventEncryptionAlgorithm, RoomVersionId,
 	api::client::discovery::{
 		get_capabilities,
 		get_capabilities::v3::{
@@ -39,11 +39,13 @@ pub(crate) async fn get_capabilities_route(
 			.clone(),
 	};
 
-	// we do not implement 3PID stuff
-	capabilities.thirdparty_id_changes = ThirdPartyIdChangesCapability { enabled: false };
+	// Email addresses can be changed once the server can verify them.
+	capabilities.thirdparty_id_changes =
+		ThirdPartyIdChangesCapability { enabled: services.mailer.enabled() };
 
+	// Tokens minted by an existing session are only accepted with token login.
 	capabilities.get_login_token = GetLoginTokenCapability {
-		enabled: services.server.config.login_via_existing_session,
+		enabled: services.config.login_via_existing_session && services.config.login_via_token,
 	};
 
 	capabilities.profile_fields = ProfileFieldsCapability::new(true).into();
@@ -53,5 +55,26 @@ pub(crate) async fn get_capabilities_route(
 		json!({"enabled": services.config.forget_forced_upon_leave}),
 	)?;
 
+	let encryption = &services.config.encryption;
+	let default_room_type = services
+		.config
+		.encryption_enabled_by_default_for_room_type
+		.as_deref();
+
+	let allow_encryption = services.config.allow_encryption;
+	let all_rooms = allow_encryption && matches!(default_room_type, Some("all" | "invite"));
+	let private_rooms = all_rooms || (allow_encryption && encryption.default_for_private_rooms);
+	capabilities.set(
+		"chat.tuwunel.default_encryption",
+		json!({
+			"private_rooms": private_rooms,
+			"all_rooms": all_rooms,
+			"algorithm": encryption
+				.algorithm
+				.clone()
+				.unwrap_or(EventEncryptionAlgorithm::MegolmV1AesSha2),
+		}),
+	)?;
+
 	Ok(get_capabilities::v3::Response { capabilities })
 }
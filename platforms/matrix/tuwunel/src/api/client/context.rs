@@ -3,7 +3,11 @@
 	FutureExt, StreamExt, TryFutureExt, TryStreamExt,
 	future::{join, join3, try_join3},
 };
-use ruma::{OwnedEventId, UserId, api::client::context::get_context, events::StateEventType};
+use ruma::{
+	OwnedEventId, UserId,
+	api::{Direction, client::context::get_context},
+	events::StateEventType,
+};
 use tuwunel_core::{
 	Err, Event, Result, at, debug_warn, err, ref_at,
 	utils::{
@@ -186,15 +190,21 @@ pub(crate) async fn get_context_route(
 			.last()
 			.map(at!(0))
 			.or(Some(base_count))
-			.as_ref()
-			.map(ToString::to_string),
+			.map(|count| {
+				services
+					.globals
+					.pagination_token(count, Direction::Backward)
+			}),
 
 		end: events_after
 			.last()
 			.map(at!(0))
 			.or(Some(base_count))
-			.as_ref()
-			.map(ToString::to_string),
+			.map(|count| {
+				services
+					.globals
+					.pagination_token(count, Direction::Forward)
+			}),
 
 		events_before: events_before
 			.into_iter()
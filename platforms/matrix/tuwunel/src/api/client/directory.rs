@@ -7,7 +7,7 @@
 	future::{join, join4, join5},
 };
 use ruma::{
-	OwnedRoomId, RoomId, ServerName, UInt, UserId,
+	OwnedRoomAliasId, OwnedRoomId, RoomId, ServerName, UInt, UserId,
 	api::{
 		client::{
 			directory::{
@@ -28,10 +28,10 @@
 	utils::{
 		TryFutureExtExt,
 		math::Expected,
-		stream::{IterStream, ReadyExt, WidebandExt},
+		stream::{IterStream, WidebandExt},
 	},
 };
-use tuwunel_service::Services;
+use tuwunel_service::{Services, rooms::directory::listing_key};
 
 use crate::Ruma;
 
@@ -212,7 +212,7 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 	limit: Option<UInt>,
 	since: Option<&str>,
 	filter: &Filter,
-	_network: &RoomNetwork,
+	network: &RoomNetwork,
 ) -> Result<get_public_rooms_filtered::v3::Response> {
 	if let Some(other_server) =
 		server.filter(|server_name| !services.globals.server_is_ours(server_name))
@@ -228,7 +228,7 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 						generic_search_term: filter.generic_search_term.clone(),
 						room_types: filter.room_types.clone(),
 					},
-					room_network: RoomNetwork::Matrix,
+					room_network: network.clone(),
 				},
 			)
 			.await?;
@@ -265,11 +265,68 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 		}
 	}
 
+	// No third-party networks are bridged into this server's directory.
+	if matches!(network, RoomNetwork::ThirdParty(_)) {
+		return Ok(get_public_rooms_filtered::v3::Response {
+			chunk: Vec::new(),
+			prev_batch: None,
+			next_batch: None,
+			total_room_count_estimate: Some(uint!(0)),
+		});
+	}
+
+	// Pages are listed from the listing cached for the filter, so that rooms are
+	// neither repeated nor skipped as their member counts change.
+	let key = listing_key(filter);
+	let listing = match services.directory.listing(key) {
+		| Some(listing) => listing,
+		| None => {
+			let room_ids = matching_public_rooms(services, filter).await;
+			services.directory.cache_listing(key, room_ids)
+		},
+	};
+
+	let total = listing.room_ids.len();
+	let total_room_count_estimate = UInt::try_from(total)
+		.unwrap_or_else(|_| uint!(0))
+		.into();
+
+	let chunk: Vec<_> = listing
+		.room_ids
+		.iter()
+		.skip(num_since)
+		.take(limit)
+		.cloned()
+		.stream()
+		.then(|room_id| public_rooms_chunk(services, room_id))
+		.collect()
+		.await;
+
+	let prev_batch = num_since.ne(&0).then(|| format!("p{num_since}"));
+
+	let next_batch = num_since
+		.saturating_add(chunk.len())
+		.lt(&total)
+		.then(|| format!("n{}", num_since.expected_add(limit)));
+
+	Ok(get_public_rooms_filtered::v3::Response {
+		chunk,
+		prev_batch,
+		next_batch,
+		total_room_count_estimate,
+	})
+}
+
+/// Rooms of the directory matching the filter, the most joined first. Only the
+/// state the filter looks at is loaded; chunks are built for the listed pages
+/// alone.
+async fn matching_public_rooms(services: &Services, filter: &Filter) -> Vec<OwnedRoomId> {
 	let search_term = filter
 		.generic_search_term
 		.as_deref()
 		.map(str::to_lowercase);
 
+	let search_term = search_term.as_deref();
 	let search_room_id = filter
 		.generic_search_term
 		.as_deref()
@@ -284,79 +341,86 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 		.stream()
 		.flatten();
 
-	let mut all_rooms: Vec<PublicRoomsChunk> = services
+	let mut rooms: Vec<(u64, OwnedRoomId)> = services
 		.directory
 		.public_rooms()
 		.map(ToOwned::to_owned)
 		.chain(meta_public_rooms)
-		.wide_then(|room_id| public_rooms_chunk(services, room_id))
-		.ready_filter_map(|chunk| {
-			if !filter.room_types.is_empty()
-				&& !filter
-					.room_types
-					.contains(&RoomTypeFilter::from(chunk.room_type.clone()))
-			{
+		.wide_filter_map(|room_id| async move {
+			if !matches_filter(services, &room_id, filter, search_room_id, search_term).await {
 				return None;
 			}
 
-			if let Some(query) = search_room_id
-				&& chunk.room_id.as_str().contains(query) {
-					return Some(chunk);
-				}
-
-			if let Some(query) = search_term.as_deref() {
-				if let Some(name) = &chunk.name
-					&& name.as_str().to_lowercase().contains(query) {
-						return Some(chunk);
-					}
-
-				if let Some(topic) = &chunk.topic
-					&& topic.to_lowercase().contains(query) {
-						return Some(chunk);
-					}
-
-				if let Some(canonical_alias) = &chunk.canonical_alias
-					&& canonical_alias.as_str().to_lowercase().contains(query) {
-						return Some(chunk);
-					}
-
-				return None;
-			}
+			let joined = services
+				.state_cache
+				.room_joined_count(&room_id)
+				.await
+				.unwrap_or(0);
 
-			// No search term
-			Some(chunk)
+			Some((joined, room_id))
 		})
 		// We need to collect all, so we can sort by member count
 		.collect()
 		.await;
 
-	all_rooms.sort_by_key(|r| cmp::Reverse(r.num_joined_members));
+	rooms.sort_by_key(|(joined, _)| cmp::Reverse(*joined));
+	rooms
+		.into_iter()
+		.map(|(_, room_id)| room_id)
+		.collect()
+}
 
-	let total_room_count_estimate = UInt::try_from(all_rooms.len())
-		.unwrap_or_else(|_| uint!(0))
-		.into();
+/// Whether a room matches the filter: its type is among those requested, and
+/// its ID, name, topic or listed alias contains the search term.
+async fn matches_filter(
+	services: &Services,
+	room_id: &RoomId,
+	filter: &Filter,
+	search_room_id: Option<&str>,
+	search_term: Option<&str>,
+) -> bool {
+	if !filter.room_types.is_empty() {
+		let room_type = services
+			.state_accessor
+			.get_room_type(room_id)
+			.await
+			.ok();
 
-	let chunk: Vec<_> = all_rooms
-		.into_iter()
-		.skip(num_since)
-		.take(limit)
-		.collect();
+		if !filter
+			.room_types
+			.contains(&RoomTypeFilter::from(room_type))
+		{
+			return false;
+		}
+	}
 
-	let prev_batch = num_since
-		.ne(&0)
-		.then_some(format!("p{num_since}"));
+	if search_room_id.is_some_and(|query| room_id.as_str().contains(query)) {
+		return true;
+	}
 
-	let next_batch = chunk
-		.len()
-		.ge(&limit)
-		.then_some(format!("n{}", num_since.expected_add(limit)));
+	let Some(query) = search_term else {
+		// No search term
+		return true;
+	};
 
-	Ok(get_public_rooms_filtered::v3::Response {
-		chunk,
-		prev_batch,
-		next_batch,
-		total_room_count_estimate,
-	})
+	let contains = |text: &str| text.to_lowercase().contains(query);
+	if let Ok(name) = services.state_accessor.get_name(room_id).await
+		&& contains(&name)
+	{
+		return true;
+	}
+
+	if let Ok(topic) = services
+		.state_accessor
+		.get_room_topic(room_id)
+		.await && contains(&topic)
+	{
+		return true;
+	}
+
+	listed_alias(services, room_id)
+		.await
+		.is_some_and(|alias| contains(alias.as_str()))
 }
 
 /// Check whether the user can publish to the room directory via power levels of
@@ -394,21 +458,7 @@ async fn public_rooms_chunk(services: &Services, room_id: OwnedRoomId) -> Public
 		.get_room_type(&room_id)
 		.ok();
 
-	let canonical_alias = services
-		.state_accessor
-		.get_canonical_alias(&room_id)
-		.ok()
-		.then(async |alias| {
-			if let Some(alias) = alias
-				&& services.globals.alias_is_local(&alias)
-				&& let Ok(alias_room_id) = services.alias.resolve_local_alias(&alias).await
-				&& alias_room_id == room_id
-			{
-				Some(alias)
-			} else {
-				None
-			}
-		});
+	let canonical_alias = listed_alias(services, &room_id);
 
 	let avatar_url = services
 		.state_accessor
@@ -465,6 +515,24 @@ async fn public_rooms_chunk(services: &Services, room_id: OwnedRoomId) -> Public
 	}
 }
 
+/// The canonical alias of a room, when it is local and resolves to the room.
+async fn listed_alias(services: &Services, room_id: &RoomId) -> Option<OwnedRoomAliasId> {
+	let alias = services
+		.state_accessor
+		.get_canonical_alias(room_id)
+		.await
+		.ok()
+		.filter(|alias| services.globals.alias_is_local(alias))?;
+
+	let alias_room_id = services
+		.alias
+		.resolve_local_alias(&alias)
+		.await
+		.ok()?;
+
+	(alias_room_id == room_id).then_some(alias)
+}
+
 fn check_server_banned(services: &Services, server: Option<&ServerName>) -> Result {
 	let Some(server) = server else {
 		return Ok(());
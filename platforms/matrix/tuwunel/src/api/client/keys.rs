@@ -1,7 +1,7 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 
 use axum::extract::State;
-use futures::{StreamExt, stream::FuturesUnordered};
+use futures::{StreamExt, TryFutureExt, stream::FuturesUnordered};
 use ruma::{
 	CanonicalJsonObject, CanonicalJsonValue, OneTimeKeyAlgorithm, OwnedDeviceId, OwnedUserId,
 	UserId,
@@ -22,7 +22,7 @@
 };
 use tuwunel_service::{Services, users::parse_master_key};
 
-use crate::{Ruma, router::auth_uiaa};
+use crate::{Ruma, router::auth_cross_signing_reset};
 
 /// # `POST /_matrix/client/r0/keys/upload`
 ///
@@ -49,6 +49,16 @@ pub(crate) async fn upload_keys_route(
 		.add_one_time_keys(sender_user, sender_device, one_time_keys)
 		.await?;
 
+	let fallback_keys = body
+		.fallback_keys
+		.iter()
+		.map(|(id, val)| (id.as_ref(), val));
+
+	services
+		.users
+		.add_fallback_keys(sender_user, sender_device, fallback_keys)
+		.await?;
+
 	if let Some(device_keys) = &body.device_keys {
 		let deser_device_keys = device_keys.deserialize().map_err(|e| {
 			err!(Request(BadJson(debug_warn!(
@@ -172,7 +182,7 @@ pub(crate) async fn upload_signing_keys_route(
 			debug!("Skipping UIA in accordance with MSC3967, user had no existing keys");
 		},
 		| _ => {
-			let authed_user = auth_uiaa(&services, &body).await?;
+			let authed_user = auth_cross_signing_reset(&services, &body).await?;
 			assert_eq!(
 				body.sender_user(),
 				authed_user,
@@ -604,11 +614,17 @@ pub(crate) async fn claim_keys_helper(
 
 		let mut container = BTreeMap::new();
 		for (device_id, key_algorithm) in map {
-			if let Ok(one_time_keys) = services
+			let one_time_key = services
 				.users
 				.take_one_time_key(user_id, device_id, key_algorithm)
-				.await
-			{
+				.or_else(|_| {
+					services
+						.users
+						.take_fallback_key(user_id, device_id, key_algorithm)
+				})
+				.await;
+
+			if let Ok(one_time_keys) = one_time_key {
 				let mut c = BTreeMap::new();
 				c.insert(one_time_keys.0, one_time_keys.1);
 				container.insert(device_id.clone(), c);
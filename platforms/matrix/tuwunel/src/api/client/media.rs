@@ -61,6 +61,11 @@ pub(crate) async fn create_content_route(
 		media_id: &utils::random_string(MXC_LENGTH),
 	};
 
+	services
+		.media
+		.check_user_quota(user, body.file.len())
+		.await?;
+
 	services
 		.media
 		.create(mxc, Some(user), Some(&content_disposition), content_type, &body.file)
@@ -79,14 +79,30 @@ pub(crate) async fn get_message_events_route(
 	let from: PduCount = body
 		.from
 		.as_deref()
-		.map(str::parse)
+		.map(|from| {
+			services
+				.globals
+				.parse_pagination_token(from, body.dir)
+		})
 		.transpose()?
-		.unwrap_or_else(|| match body.dir {
-			| Direction::Forward => PduCount::min(),
-			| Direction::Backward => PduCount::max(),
-		});
-
-	let to: Option<PduCount> = body.to.as_deref().map(str::parse).flat_ok();
+		.map_or_else(
+			|| match body.dir {
+				| Direction::Forward => PduCount::min(),
+				| Direction::Backward => PduCount::max(),
+			},
+			|from| from.pos,
+		);
+
+	let to: Option<PduCount> = body
+		.to
+		.as_deref()
+		.map(|to| {
+			services
+				.globals
+				.parse_pagination_bound(to, body.dir)
+		})
+		.flat_ok()
+		.map(|to| to.pos);
 
 	let limit: usize = body
 		.limit
@@ -158,8 +174,8 @@ pub(crate) async fn get_message_events_route(
 		.collect();
 
 	Ok(get_message_events::v3::Response {
-		start: from.to_string(),
-		end: next_token.as_ref().map(ToString::to_string),
+		start: services.globals.pagination_token(from, body.dir),
+		end: next_token.map(|count| services.globals.pagination_token(count, body.dir)),
 		chunk,
 		state,
 	})
@@ -0,0 +1,108 @@
+use std::sync::atomic::Ordering;
+
+use axum::{extract::State, response::IntoResponse};
+use http::header;
+use tuwunel_core::{Result, metrics::prometheus::Encoder};
+
+/// Content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// # `GET /metrics`
+///
+/// Server metrics in the Prometheus text format. Only routed when
+/// `metrics_endpoint` is enabled.
+pub(crate) async fn get_metrics_route(
+	State(services): State<crate::State>,
+) -> Result<impl IntoResponse> {
+	let metrics = &services.server.metrics;
+	let mut out = Encoder::new();
+
+	out.counter(
+		"tuwunel_requests_total",
+		"HTTP requests received.",
+		metrics.requests_count.load(Ordering::Relaxed),
+	);
+	out.gauge(
+		"tuwunel_requests_active",
+		"HTTP requests being handled.",
+		metrics
+			.requests_handle_active
+			.load(Ordering::Relaxed),
+	);
+	out.counter(
+		"tuwunel_requests_panic_total",
+		"HTTP requests which panicked.",
+		metrics.requests_panic.load(Ordering::Relaxed),
+	);
+	out.histogram(
+		"tuwunel_sync_duration_seconds",
+		"Time taken to answer sync requests, including long-polling.",
+		&metrics.sync_latency,
+	);
+	out.labeled_counter(
+		"tuwunel_federation_transactions_total",
+		"Federation transactions sent to remote servers.",
+		"result",
+		[
+			(
+				"success",
+				metrics
+					.federation_send_success
+					.load(Ordering::Relaxed),
+			),
+			(
+				"failure",
+				metrics
+					.federation_send_failure
+					.load(Ordering::Relaxed),
+			),
+		],
+	);
+	out.labeled_counter(
+		"tuwunel_push_notifications_total",
+		"Notifications delivered to push gateways.",
+		"result",
+		[
+			("success", metrics.pusher_success.load(Ordering::Relaxed)),
+			("failure", metrics.pusher_failure.load(Ordering::Relaxed)),
+		],
+	);
+	out.labeled_counter(
+		"tuwunel_db_reads_total",
+		"Keys queried by point-reads of each database map.",
+		"map",
+		services
+			.db
+			.iter()
+			.map(|(name, map)| (*name, map.reads())),
+	);
+	out.labeled_counter(
+		"tuwunel_db_writes_total",
+		"Keys written or removed in each database map.",
+		"map",
+		services
+			.db
+			.iter()
+			.map(|(name, map)| (*name, map.writes())),
+	);
+	out.labeled_gauge(
+		"tuwunel_db_pool_requests_active",
+		"Requests of each class in the database pool and not yet answered.",
+		"class",
+		services
+			.db
+			.read_class_utilization()
+			.map(|(class, active, _)| (class, active)),
+	);
+	out.labeled_counter(
+		"tuwunel_db_pool_requests_total",
+		"Requests of each class submitted to the database pool.",
+		"class",
+		services
+			.db
+			.read_class_utilization()
+			.map(|(class, _, total)| (class, total)),
+	);
+
+	Ok(([(header::CONTENT_TYPE, CONTENT_TYPE)], out.finish()))
+}
@@ -15,6 +15,7 @@
 pub(super) mod media_legacy;
 pub(super) mod membership;
 pub(super) mod message;
+pub(super) mod metrics;
 pub(super) mod openid;
 pub(super) mod presence;
 pub(super) mod profile;
@@ -23,13 +24,17 @@
 pub(super) mod redact;
 pub(super) mod register;
 pub(super) mod relations;
+pub(super) mod rendezvous;
 pub(super) mod report;
 pub(super) mod room;
 pub(super) mod search;
 pub(super) mod send;
 pub(super) mod session;
+pub(super) mod share_link;
 pub(super) mod space;
 pub(super) mod state;
+pub(super) mod state_filtered;
+pub(super) mod synapse_admin;
 pub(super) mod sync;
 pub(super) mod tag;
 pub(super) mod thirdparty;
@@ -61,6 +66,7 @@
 pub(super) use media_legacy::*;
 pub(super) use membership::*;
 pub(super) use message::*;
+pub(super) use metrics::*;
 pub(super) use openid::*;
 pub(super) use presence::*;
 pub(super) use profile::*;
@@ -69,13 +75,17 @@
 pub(super) use redact::*;
 pub(super) use register::*;
 pub(super) use relations::*;
+pub(super) use rendezvous::*;
 pub(super) use report::*;
 pub(super) use room::*;
 pub(super) use search::*;
 pub(super) use send::*;
 pub(super) use session::*;
+pub(super) use share_link::*;
 pub(super) use space::*;
 pub(super) use state::*;
+pub(super) use state_filtered::*;
+pub(super) use synapse_admin::*;
 pub(super) use sync::*;
 pub(super) use tag::*;
 pub(super) use thirdparty::*;
@@ -6,7 +6,6 @@
 	future::{join, join4},
 };
 use ruma::{
-	OwnedRoomId,
 	api::{
 		client::profile::{
 			get_avatar_url, get_display_name, get_profile, set_avatar_url, set_display_name,
@@ -34,17 +33,12 @@ pub(crate) async fn set_displayname_route(
 		return Err!(Request(Forbidden("You cannot update the profile of another user")));
 	}
 
-	let all_joined_rooms: Vec<OwnedRoomId> = services
-		.state_cache
-		.rooms_joined(&body.user_id)
-		.map(ToOwned::to_owned)
-		.collect()
-		.await;
+	let profile_rooms = services.users.profile_rooms(&body.user_id).await;
 
 	services
 		.users
-		.update_displayname(&body.user_id, body.displayname.as_deref(), &all_joined_rooms)
-		.await;
+		.update_displayname(&body.user_id, body.displayname.as_deref(), &profile_rooms)
+		.await?;
 
 	// Presence update
 	services
@@ -129,12 +123,7 @@ pub(crate) async fn set_avatar_url_route(
 		return Err!(Request(Forbidden("You cannot update the profile of another user")));
 	}
 
-	let all_joined_rooms: Vec<OwnedRoomId> = services
-		.state_cache
-		.rooms_joined(&body.user_id)
-		.map(ToOwned::to_owned)
-		.collect()
-		.await;
+	let profile_rooms = services.users.profile_rooms(&body.user_id).await;
 
 	services
 		.users
@@ -142,7 +131,7 @@ pub(crate) async fn set_avatar_url_route(
 			&body.user_id,
 			body.avatar_url.as_deref(),
 			body.blurhash.as_deref(),
-			&all_joined_rooms,
+			&profile_rooms,
 		)
 		.await;
 
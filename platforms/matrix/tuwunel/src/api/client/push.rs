@@ -2,12 +2,16 @@
 use futures::StreamExt;
 use ruma::{
 	CanonicalJsonObject, CanonicalJsonValue, MilliSecondsSinceUnixEpoch,
-	api::client::{
-		error::ErrorKind,
-		push::{
-			delete_pushrule, get_notifications, get_pushers, get_pushrule, get_pushrule_actions,
-			get_pushrule_enabled, get_pushrules_all, get_pushrules_global_scope, set_pusher,
-			set_pushrule, set_pushrule_actions, set_pushrule_enabled,
+	api::{
+		Direction,
+		client::{
+			error::ErrorKind,
+			push::{
+				delete_pushrule, get_notifications, get_pushers, get_pushrule,
+				get_pushrule_actions, get_pushrule_enabled, get_pushrules_all,
+				get_pushrules_global_scope, set_pusher, set_pushrule, set_pushrule_actions,
+				set_pushrule_enabled,
+			},
 		},
 	},
 	events::{
@@ -22,10 +26,7 @@
 use tuwunel_core::{
 	Err, Error, Result, at, err,
 	matrix::{Event, PduId},
-	utils::{
-		stream::{ReadyExt, WidebandExt},
-		string::to_small_string,
-	},
+	utils::stream::{ReadyExt, WidebandExt},
 };
 use tuwunel_service::Services;
 
@@ -47,9 +48,13 @@ pub(crate) async fn get_notifications_route(
 		.body
 		.from
 		.as_deref()
-		.map(str::parse)
-		.transpose()
-		.map_err(|e| err!(Request(InvalidParam("Invalid `from' parameter: {e}"))))?;
+		.map(|from| {
+			services
+				.globals
+				.parse_pagination_token(from, Direction::Backward)
+		})
+		.transpose()?
+		.map(|from| from.pos);
 
 	let limit: usize = body
 		.body
@@ -121,7 +126,12 @@ pub(crate) async fn get_notifications_route(
 		.await;
 
 	Ok(get_notifications::v3::Response {
-		next_token: next_token.map(to_small_string),
+		next_token: next_token.map(|count| {
+			services
+				.globals
+				.pagination_token(count, Direction::Backward)
+				.into()
+		}),
 		notifications,
 	})
 }
@@ -8,8 +8,9 @@
 		account::{
 			check_registration_token_validity, get_username_availability,
 			register::{self, LoginType, RegistrationKind},
+			request_registration_token_via_email,
 		},
-		uiaa::{AuthFlow, AuthType, UiaaInfo},
+		uiaa::{AuthData, AuthFlow, AuthType, EmailIdentity, UiaaInfo},
 	},
 };
 use tuwunel_core::{Err, Error, Result, debug_info, debug_warn, info, utils};
@@ -56,6 +57,14 @@ pub(crate) async fn get_register_available_route(
 		return Err!(Request(Forbidden("Username is forbidden")));
 	}
 
+	if body.appservice_info.is_none()
+		&& services
+			.users
+			.is_reserved_localpart(&body.username)
+	{
+		return Err!(Request(Forbidden("Username is reserved by the server.")));
+	}
+
 	// don't force the username lowercase if it's from matrix-appservice-irc
 	let body_username = if is_matrix_appservice_irc {
 		body.username.clone()
@@ -143,6 +152,12 @@ pub(crate) async fn register_route(
 		.as_deref()
 		.unwrap_or("");
 
+	if services.oauth.delegated() && body.appservice_info.is_none() {
+		return Err!(Request(Unrecognized(
+			"Registration is delegated to an OpenID Connect provider."
+		)));
+	}
+
 	if !services.config.allow_registration && body.appservice_info.is_none() {
 		info!(
 			%is_guest,
@@ -190,6 +205,13 @@ pub(crate) async fn register_route(
 				return Err!(Request(Forbidden("Username is forbidden")));
 			}
 
+			if body.appservice_info.is_none()
+				&& services.users.is_reserved_localpart(username)
+				&& !emergency_mode_enabled
+			{
+				return Err!(Request(Forbidden("Username is reserved by the server.")));
+			}
+
 			// don't force the username lowercase if it's from matrix-appservice-irc
 			let body_username = if is_matrix_appservice_irc {
 				username.clone()
@@ -289,6 +311,16 @@ pub(crate) async fn register_route(
 		body.appservice_info.is_some() || is_guest
 	};
 
+	if services.config.registration_requires_email {
+		// The address is verified last, so that it is known once the other
+		// stages are complete and the user is created.
+		for flow in &mut uiaainfo.flows {
+			flow.stages
+				.retain(|stage| *stage != AuthType::Dummy);
+			flow.stages.push(AuthType::EmailIdentity);
+		}
+	}
+
 	if !skip_auth {
 		match &body.auth {
 			| Some(auth) => {
@@ -326,9 +358,24 @@ pub(crate) async fn register_route(
 		}
 	}
 
+	// The address is associated before the user is created, ending its session,
+	// so that a concurrent registration with the same session fails first.
+	let email_creds = match &body.auth {
+		| Some(AuthData::EmailIdentity(EmailIdentity { thirdparty_id_creds, .. })) =>
+			Some(thirdparty_id_creds),
+		| _ => None,
+	};
+
+	if let Some(creds) = email_creds {
+		services
+			.threepid
+			.bind_email(&user_id, creds.sid.as_str(), creds.client_secret.as_str())
+			.await?;
+	}
+
 	let password = if is_guest { None } else { body.password.as_deref() };
 
-	services
+	let registered = services
 		.users
 		.full_register(Register {
 			user_id: Some(&user_id),
@@ -338,7 +385,21 @@ pub(crate) async fn register_route(
 			grant_first_user_admin: true,
 			..Default::default()
 		})
-		.await?;
+		.await;
+
+	if registered.is_err() && email_creds.is_some() {
+		services.users.remove_threepids(&user_id).await;
+	}
+
+	registered?;
+
+	if services.config.shadow_ban_registrations
+		&& body.appservice_info.is_none()
+		&& !services.admin.user_is_admin(&user_id).await
+	{
+		services.users.shadow_ban(&user_id);
+		info!(%user_id, "Shadow-banned newly registered user");
+	}
 
 	if (!is_guest && body.inhibit_login)
 		|| body
@@ -409,6 +470,45 @@ pub(crate) async fn register_route(
 	})
 }
 
+/// # `POST /_matrix/client/v3/register/email/requestToken`
+///
+/// Sends a link to an email address to verify it, for the
+/// `m.login.email.identity` stage of registration.
+///
+/// - 400 signals that the address is associated with a user already.
+/// - 403 signals that the homeserver does not send emails.
+/// - 429 signals that too many emails were sent to the address or requested
+///   from the client's address.
+pub(crate) async fn request_registration_token_via_email_route(
+	State(services): State<crate::State>,
+	body: Ruma<request_registration_token_via_email::v3::Request>,
+) -> Result<request_registration_token_via_email::v3::Response> {
+	if !services.config.allow_registration || services.oauth.delegated() {
+		return Err!(Request(Forbidden("Registration has been disabled.")));
+	}
+
+	if services
+		.threepid
+		.user_by_email(&body.email)
+		.await
+		.is_ok()
+	{
+		return Err!(Request(ThreepidInUse("The email address is already in use.")));
+	}
+
+	let sid = services
+		.threepid
+		.request_email_token(
+			&body.client_secret,
+			&body.email,
+			body.send_attempt,
+			body.next_link.as_deref(),
+		)
+		.await?;
+
+	Ok(request_registration_token_via_email::v3::Response::new(sid.try_into()?))
+}
+
 /// # `GET /_matrix/client/v1/register/m.login.registration_token/validity`
 ///
 /// Checks if the provided registration token is valid at the time of checking
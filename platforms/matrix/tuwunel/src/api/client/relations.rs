@@ -128,9 +128,15 @@ async fn paginate_relations_with_filter(
 	recurse: bool,
 	dir: Direction,
 ) -> Result<get_relating_events::v1::Response> {
-	let from: Option<PduCount> = from.map(str::parse).transpose()?;
+	let from: Option<PduCount> = from
+		.map(|from| services.globals.parse_pagination_token(from, dir))
+		.transpose()?
+		.map(|from| from.pos);
 
-	let to: Option<PduCount> = to.map(str::parse).flat_ok();
+	let to: Option<PduCount> = to
+		.map(|to| services.globals.parse_pagination_bound(to, dir))
+		.flat_ok()
+		.map(|to| to.pos);
 
 	// Spec (v1.10) recommends depth of at least 3
 	let max_depth: usize = if recurse { 3 } else { 0 };
@@ -223,15 +229,13 @@ async fn paginate_relations_with_filter(
 		next_batch: events
 			.last()
 			.map(at!(1))
-			.as_ref()
-			.map(ToString::to_string),
+			.map(|count| services.globals.pagination_token(count, dir)),
 
 		prev_batch: events
 			.first()
 			.map(at!(1))
 			.or(from)
-			.as_ref()
-			.map(ToString::to_string),
+			.map(|count| services.globals.pagination_token(count, dir)),
 
 		chunk: events
 			.into_iter()
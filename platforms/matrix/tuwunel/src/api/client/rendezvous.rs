@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use axum::{
+	Json,
+	body::Bytes,
+	extract::{Path, State},
+	response::{IntoResponse, Response},
+};
+use axum_extra::{
+	TypedHeader,
+	headers::{CacheControl, ETag, Expires, IfMatch, IfNoneMatch, LastModified},
+};
+use http::{HeaderMap, StatusCode, header};
+use serde_json::json;
+use tuwunel_core::{Err, Result, err, utils::time::timepoint_from_epoch};
+use tuwunel_service::{
+	Services,
+	rendezvous::{MAX_PAYLOAD, Session},
+};
+use url::Url;
+
+const RENDEZVOUS_PATH: &str = "/_matrix/client/unstable/org.matrix.msc4108/rendezvous";
+
+type SessionHeaders = (
+	TypedHeader<ETag>,
+	TypedHeader<Expires>,
+	TypedHeader<LastModified>,
+	TypedHeader<CacheControl>,
+);
+
+/// # `POST /_matrix/client/unstable/org.matrix.msc4108/rendezvous`
+///
+/// Open a rendezvous session holding the body, for signing in with a QR code
+/// (MSC4108). Responds with the URL of the session.
+pub(crate) async fn create_rendezvous_route(
+	State(services): State<crate::State>,
+	headers: HeaderMap,
+	body: Bytes,
+) -> Result<impl IntoResponse> {
+	let (content_type, data) = payload(&services, &headers, &body)?;
+	let (id, session) = services.rendezvous.create(&content_type, data)?;
+
+	let url = session_url(&services, &id)?;
+
+	Ok((StatusCode::CREATED, session_headers(&session)?, Json(json!({ "url": url }))))
+}
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// The payload of a rendezvous session; not modified when it still matches
+/// `If-None-Match`.
+pub(crate) async fn get_rendezvous_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+	if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Response> {
+	check_enabled(&services)?;
+
+	let session = services.rendezvous.get(&id).await?;
+	let headers = session_headers(&session)?;
+
+	if let Some(TypedHeader(if_none_match)) = if_none_match
+		&& !if_none_match.precondition_passes(&headers.0.0)
+	{
+		return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+	}
+
+	Ok((headers, [(header::CONTENT_TYPE, session.content_type)], session.data).into_response())
+}
+
+/// # `PUT /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// Replace the payload of a rendezvous session, provided it still matches
+/// `If-Match`.
+pub(crate) async fn update_rendezvous_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+	if_match: Option<TypedHeader<IfMatch>>,
+	headers: HeaderMap,
+	body: Bytes,
+) -> Result<Response> {
+	let Some(TypedHeader(if_match)) = if_match else {
+		return Err!(Request(MissingParam("Missing If-Match header.")));
+	};
+
+	let (content_type, data) = payload(&services, &headers, &body)?;
+	let precondition =
+		|session: &Session| etag(session).is_ok_and(|etag| if_match.precondition_passes(&etag));
+
+	let Some(session) = services
+		.rendezvous
+		.update(&id, precondition, &content_type, data)
+		.await?
+	else {
+		return Ok(concurrent_write());
+	};
+
+	Ok((StatusCode::ACCEPTED, session_headers(&session)?).into_response())
+}
+
+/// # `DELETE /_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}`
+///
+/// Close a rendezvous session.
+pub(crate) async fn delete_rendezvous_route(
+	State(services): State<crate::State>,
+	Path(id): Path<String>,
+) -> Result<impl IntoResponse> {
+	check_enabled(&services)?;
+
+	services.rendezvous.get(&id).await?;
+	services.rendezvous.delete(&id);
+
+	Ok(StatusCode::NO_CONTENT)
+}
+
+/// The content type and body of a request, which must be text of at most
+/// `MAX_PAYLOAD` bytes.
+fn payload(services: &Services, headers: &HeaderMap, body: &Bytes) -> Result<(String, String)> {
+	check_enabled(services)?;
+
+	if body.len() > MAX_PAYLOAD {
+		return Err!(Request(TooLarge(
+			"Rendezvous payloads are limited to {MAX_PAYLOAD} bytes."
+		)));
+	}
+
+	let content_type = headers
+		.get(header::CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.unwrap_or("text/plain")
+		.to_owned();
+
+	let data = String::from_utf8(body.to_vec())
+		.map_err(|_| err!(Request(InvalidParam("Rendezvous payload is not text."))))?;
+
+	Ok((content_type, data))
+}
+
+fn check_enabled(services: &Services) -> Result {
+	if !services.rendezvous.enabled() {
+		return Err!(Request(NotFound("Signing in with a QR code is not enabled.")));
+	}
+
+	Ok(())
+}
+
+fn session_url(services: &Services, id: &str) -> Result<Url> {
+	let mut url = match services.config.well_known.client.clone() {
+		| Some(url) => url,
+		| None => Url::parse(&format!("https://{}", services.globals.server_name()))?,
+	};
+
+	url.set_path(&format!("{RENDEZVOUS_PATH}/{id}"));
+
+	Ok(url)
+}
+
+fn session_headers(session: &Session) -> Result<SessionHeaders> {
+	let time = |secs| timepoint_from_epoch(Duration::from_secs(secs));
+
+	Ok((
+		TypedHeader(etag(session)?),
+		TypedHeader(Expires::from(time(session.expires_at)?)),
+		TypedHeader(LastModified::from(time(session.last_modified)?)),
+		TypedHeader(CacheControl::new().with_no_store()),
+	))
+}
+
+fn etag(session: &Session) -> Result<ETag> {
+	format!("\"{}\"", session.etag)
+		.parse()
+		.map_err(|e| err!("Invalid ETag: {e:?}"))
+}
+
+fn concurrent_write() -> Response {
+	let body = json!({
+		"errcode": "M_CONCURRENT_WRITE",
+		"error": "The rendezvous session was modified since it was read.",
+	});
+
+	(StatusCode::PRECONDITION_FAILED, Json(body)).into_response()
+}
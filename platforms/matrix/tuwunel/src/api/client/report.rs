@@ -3,7 +3,6 @@
 use ruma::{
 	EventId, RoomId, UserId,
 	api::client::room::{report_content, report_room},
-	events::room::message,
 	int,
 };
 use tuwunel_core::{Err, Result, debug_info, info, matrix::pdu::PduEvent, utils::ReadyExt};
@@ -46,18 +45,10 @@ pub(crate) async fn report_room_route(
 		)));
 	}
 
-	// send admin room message that we received the report with an @room ping for
-	// urgency
 	services
-		.admin
-		.send_message(message::RoomMessageEventContent::text_markdown(format!(
-			"@room Room report received from {} -\n\nRoom ID: {}\n\nReport Reason: {}",
-			sender_user.to_owned(),
-			body.room_id,
-			body.reason,
-		)))
-		.await
-		.ok();
+		.reports
+		.report(sender_user, body.room_id.clone(), None, None, Some(body.reason.clone()))
+		.await;
 
 	Ok(report_room::v3::Response {})
 }
@@ -98,22 +89,16 @@ pub(crate) async fn report_event_route(
 	)
 	.await?;
 
-	// send admin room message that we received the report with an @room ping for
-	// urgency
 	services
-		.admin
-		.send_message(message::RoomMessageEventContent::text_markdown(format!(
-			"@room Event report received from {} -\n\nEvent ID: {}\nRoom ID: {}\nSent By: \
-			 {}\n\nReport Score: {}\nReport Reason: {}",
-			sender_user.to_owned(),
-			pdu.event_id,
-			pdu.room_id,
-			pdu.sender,
-			body.score.unwrap_or_else(|| ruma::Int::from(0)),
-			body.reason.as_deref().unwrap_or("")
-		)))
-		.await
-		.ok();
+		.reports
+		.report(
+			sender_user,
+			pdu.room_id.clone(),
+			Some((pdu.event_id.clone(), pdu.sender.clone())),
+			body.score.map(Into::into),
+			body.reason.clone(),
+		)
+		.await;
 
 	Ok(report_content::v3::Response {})
 }
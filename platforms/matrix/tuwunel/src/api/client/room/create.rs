@@ -4,7 +4,7 @@
 use futures::FutureExt;
 use ruma::{
 	CanonicalJsonObject, EventEncryptionAlgorithm, Int, OwnedRoomAliasId, OwnedRoomId,
-	OwnedUserId, RoomId, RoomVersionId,
+	OwnedUserId, RoomId, RoomVersionId, UInt,
 	api::client::room::{
 		self, create_room,
 		create_room::v3::{CreationContent, RoomPreset},
@@ -61,6 +61,7 @@ pub(crate) async fn create_room_route(
 ) -> Result<create_room::v3::Response> {
 	can_create_room_check(&services, &body).await?;
 	can_publish_directory_check(&services, &body).await?;
+	room_text_check(&services, &body)?;
 
 	// Figure out preset. We need it for preset specific events
 	let preset = body
@@ -263,6 +264,7 @@ pub(crate) async fn create_room_route(
 
 	// 6. Events listed in initial_state
 	let mut is_encrypted = false;
+	let mut encryption_opted_out = false;
 	for event in &body.initial_state {
 		let mut pdu_builder = event
 			.deserialize_as_unchecked::<PduBuilder>()
@@ -272,6 +274,16 @@ pub(crate) async fn create_room_route(
 
 		debug_info!("Room creation initial state event: {event:?}");
 
+		// An encryption event without content opts the room out of the encryption
+		// enabled by default, unless the server forces it for every room.
+		if pdu_builder.event_type == TimelineEventType::RoomEncryption
+			&& serde_json::from_str::<JsonObject>(pdu_builder.content.get())
+				.is_ok_and(|content| content.is_empty())
+		{
+			encryption_opted_out = true;
+			continue;
+		}
+
 		// client/appservice workaround: if a user sends an initial_state event with a
 		// state event in there with the content of literally `{}` (not null or empty
 		// string), let's just skip it over and warn.
@@ -313,11 +325,25 @@ pub(crate) async fn create_room_route(
 			.as_deref()
 			.unwrap_or("off");
 
-		let invite = matches!(config, "invite");
+		let encryption = &services.config.encryption;
+		let forced = matches!(config, "all");
+		let private = matches!(config, "invite") || encryption.default_for_private_rooms;
 		let always = matches!(config, "all" | "invite");
-		if always || (invite && matches!(preset, PrivateChat | TrustedPrivateChat)) {
-			let algorithm = EventEncryptionAlgorithm::MegolmV1AesSha2;
-			let content = RoomEncryptionEventContent::new(algorithm);
+		let default = always || (private && matches!(preset, PrivateChat | TrustedPrivateChat));
+		if forced || (default && !encryption_opted_out) {
+			let algorithm = encryption
+				.algorithm
+				.clone()
+				.unwrap_or(EventEncryptionAlgorithm::MegolmV1AesSha2);
+
+			let mut content = RoomEncryptionEventContent::new(algorithm);
+			content.rotation_period_ms = encryption
+				.rotation_period_ms
+				.map(UInt::new_saturating);
+			content.rotation_period_msgs = encryption
+				.rotation_period_msgs
+				.map(UInt::new_saturating);
+
 			services
 				.timeline
 				.build_and_append_pdu(
@@ -727,6 +753,8 @@ async fn room_alias_check(
 			))))
 		})?;
 
+	services.sanitize.check_alias(&full_room_alias)?;
+
 	if services
 		.alias
 		.resolve_local_alias(&full_room_alias)
@@ -797,6 +825,31 @@ async fn custom_room_id_check(services: &Services, custom_room_id: &str) -> Resu
 	Ok(room_id)
 }
 
+/// Refuses names and topics beyond the configured lengths, whether given
+/// directly or as initial state, before the room is created.
+fn room_text_check(services: &Services, body: &Ruma<create_room::v3::Request>) -> Result {
+	if let Some(name) = &body.name {
+		services.sanitize.room_name(name)?;
+	}
+
+	if let Some(topic) = &body.topic {
+		services.sanitize.room_topic(topic)?;
+	}
+
+	body.initial_state
+		.iter()
+		.filter_map(|event| {
+			event
+				.deserialize_as_unchecked::<PduBuilder>()
+				.ok()
+		})
+		.try_for_each(|pdu_builder| {
+			services
+				.sanitize
+				.check_content(&pdu_builder.event_type, &pdu_builder.content)
+		})
+}
+
 async fn can_publish_directory_check(
 	services: &Services,
 	body: &Ruma<create_room::v3::Request>,
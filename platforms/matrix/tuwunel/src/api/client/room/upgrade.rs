@@ -3,11 +3,13 @@
 use axum::extract::State;
 use futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt};
 use ruma::{
-	CanonicalJsonObject, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId, UserId,
+	CanonicalJsonObject, OwnedEventId, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId,
+	RoomVersionId, UserId,
 	api::client::room::upgrade_room::v3,
 	events::{
 		StateEventType, TimelineEventType,
 		room::{
+			canonical_alias::RoomCanonicalAliasEventContent,
 			create::PreviousRoom,
 			member::{MembershipState, RoomMemberEventContent},
 			power_levels::RoomPowerLevelsEventContent,
@@ -65,8 +67,8 @@ struct RoomUpgradeContext<'a> {
 /// - Creates a replacement room
 /// - Sends a tombstone event into the current room
 /// - Sender user joins the room
-/// - Transfers some state events
-/// - Moves local aliases
+/// - Transfers some state events and bans
+/// - Moves local aliases and the canonical alias
 /// - Modifies old room power levels to prevent users from speaking
 #[tracing::instrument(level = "debug")]
 pub(crate) async fn upgrade_room_route(
@@ -316,8 +318,12 @@ async fn transfer_room(&self) -> Result {
 
 	self.move_state_events().await?;
 
+	self.move_bans().await?;
+
 	self.move_local_aliases().await?;
 
+	self.move_canonical_alias().await?;
+
 	self.tombstone_old_room().await?;
 
 	// After commitment to the tombstone above no more errors can propagate.
@@ -438,32 +444,120 @@ fn rebuild_state_event<Pdu: Event>(&self, event: &Pdu) -> Result<PduBuilder> {
 	})
 }
 
-// Moves any local aliases to the new room
+// Replicate bans to the new room so banned users cannot join it instead
 #[implement(RoomUpgradeContext, params = "<'_>")]
 #[tracing::instrument(level = "debug")]
-async fn move_local_aliases(&self) -> Result {
+async fn move_bans(&self) -> Result {
 	self.services
-		.alias
-		.local_aliases_for_room(self.old_room_id)
-		.filter_map(|alias| {
-			self.services
-				.alias
-				.remove_alias(alias, self.sender_user)
-				.inspect_err(move |e| error!(?alias, ?self, "Failed to remove alias: {e}"))
-				.map_ok(move |()| alias)
-				.ok()
+		.state_accessor
+		.room_state_type_pdus(self.old_room_id, &StateEventType::RoomMember)
+		.ready_filter_map(Result::ok)
+		.ready_filter_map(|event| {
+			let content: RoomMemberEventContent = event.get_content().ok()?;
+			let user_id = event.state_key()?.to_owned();
+
+			(content.membership == MembershipState::Ban).then_some((user_id, content.reason))
 		})
-		.ready_for_each(|alias| {
+		.map(Ok)
+		.try_for_each(async |(user_id, reason)| {
 			self.services
-				.alias
-				.set_alias(alias, self.new_room_id, self.sender_user)
-				.inspect_err(|e| error!(?self, "Failed to add alias: {e}"))
-				.ok();
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder::state(user_id.as_str(), &RoomMemberEventContent {
+						reason,
+						..RoomMemberEventContent::new(MembershipState::Ban)
+					}),
+					self.sender_user,
+					self.new_room_id,
+					self.new_state_lock,
+				)
+				.inspect_err(|e| {
+					error!(?user_id, ?self, "Failed to transfer ban on upgrade: {e}");
+				})
+				.map_ok(|_| ())
+				.await
 		})
-		.map(Ok)
 		.await
 }
 
+// Moves any local aliases to the new room
+#[implement(RoomUpgradeContext, params = "<'_>")]
+#[tracing::instrument(level = "debug")]
+async fn move_local_aliases(&self) -> Result {
+	let aliases: Vec<OwnedRoomAliasId> = self
+		.services
+		.alias
+		.local_aliases_for_room(self.old_room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for alias in &aliases {
+		self.services
+			.alias
+			.repoint_alias(alias, self.new_room_id, self.sender_user)
+			.await
+			.inspect_err(|e| error!(?alias, ?self, "Failed to move alias: {e}"))
+			.ok();
+	}
+
+	Ok(())
+}
+
+// Replicate the canonical alias to the new room, where its local aliases now
+// point, and remove the local aliases from the old room's.
+#[implement(RoomUpgradeContext, params = "<'_>")]
+#[tracing::instrument(level = "debug")]
+async fn move_canonical_alias(&self) -> Result {
+	let Ok(content) = self
+		.services
+		.state_accessor
+		.room_state_get_content::<RoomCanonicalAliasEventContent>(
+			self.old_room_id,
+			&StateEventType::RoomCanonicalAlias,
+			"",
+		)
+		.await
+	else {
+		return Ok(());
+	};
+
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(StateKey::new(), &content),
+			self.sender_user,
+			self.new_room_id,
+			self.new_state_lock,
+		)
+		.await?;
+
+	let is_local = |alias: &OwnedRoomAliasId| self.services.globals.alias_is_local(alias);
+	if !content.alias.iter().any(is_local) && !content.alt_aliases.iter().any(is_local) {
+		return Ok(());
+	}
+
+	let mut old_content = content;
+	old_content.alias = old_content.alias.filter(|alias| !is_local(alias));
+	old_content
+		.alt_aliases
+		.retain(|alias| !is_local(alias));
+
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(StateKey::new(), &old_content),
+			self.sender_user,
+			self.old_room_id,
+			self.old_state_lock,
+		)
+		.await
+		.inspect_err(|e| error!(?self, "Failed to remove aliases from old room: {e}"))
+		.ok();
+
+	Ok(())
+}
+
 // Send a m.room.tombstone event to the old room to indicate that it is not
 // intended to be used any further Fail if the sender does not have the required
 // permissions.
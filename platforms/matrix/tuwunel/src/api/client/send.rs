@@ -5,8 +5,9 @@
 	api::client::message::send_message_event,
 	events::{MessageLikeEventType, room::redaction::RoomRedactionEventContent},
 };
-use serde_json::from_str;
+use serde_json::{Value as JsonValue, from_str};
 use tuwunel_core::{Err, Result, err, matrix::pdu::PduBuilder, utils, warn};
+use tuwunel_service::media::referenced_media;
 
 use crate::Ruma;
 
@@ -99,6 +100,20 @@ pub(crate) async fn send_message_event_route(
 	let content = from_str(body.body.body.json().get())
 		.map_err(|e| err!(Request(BadJson("Invalid JSON body: {e}"))))?;
 
+	// Attachments count against the media quota of the room
+	let media = match body.event_type {
+		| MessageLikeEventType::RoomMessage | MessageLikeEventType::Sticker =>
+			from_str::<JsonValue>(body.body.body.json().get())
+				.map(|content| referenced_media(&content))
+				.unwrap_or_default(),
+		| _ => Vec::new(),
+	};
+
+	services
+		.media
+		.check_room_quota(&body.room_id, &media)
+		.await?;
+
 	let event_id = services
 		.timeline
 		.build_and_append_pdu(
@@ -115,6 +130,11 @@ pub(crate) async fn send_message_event_route(
 		)
 		.await?;
 
+	services
+		.media
+		.add_room_media(&body.room_id, &media)
+		.await;
+
 	services.transaction_ids.add_txnid(
 		sender_user,
 		sender_device,
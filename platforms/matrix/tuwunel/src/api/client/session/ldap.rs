@@ -35,11 +35,12 @@ pub(super) async fn ldap_login(
 		},
 	};
 
-	let user_id = services
+	let groups = services
 		.users
 		.auth_ldap(&user_dn, password)
-		.await
-		.map(|()| lowercased_user_id.to_owned())?;
+		.await?;
+
+	let user_id = lowercased_user_id.to_owned();
 
 	// LDAP users are automatically created on first login attempt. This is a very
 	// common feature that can be seen on many services using a LDAP provider for
@@ -60,8 +61,16 @@ pub(super) async fn ldap_login(
 			.await?;
 	}
 
-	// only perform admin add/remove check if admin_filter is set
-	if !services.config.ldap.admin_filter.is_empty() {
+	let config = &services.config.ldap;
+	let is_ldap_admin = is_ldap_admin
+		|| config.admin_groups.iter().any(|admin_group| {
+			groups
+				.iter()
+				.any(|group| group.eq_ignore_ascii_case(admin_group))
+		});
+
+	// only perform admin add/remove check if admin_filter or admin_groups is set
+	if !config.admin_filter.is_empty() || !config.admin_groups.is_empty() {
 		let is_tuwunel_admin = services
 			.admin
 			.user_is_admin(lowercased_user_id)
@@ -81,5 +90,10 @@ pub(super) async fn ldap_login(
 		}
 	}
 
+	services
+		.users
+		.apply_ldap_group_power_levels(lowercased_user_id, &groups)
+		.await;
+
 	Ok(user_id)
 }
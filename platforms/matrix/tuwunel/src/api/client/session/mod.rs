@@ -22,14 +22,17 @@
 		v3::{DiscoveryInfo, HomeserverInfo, LoginInfo},
 	},
 };
-use tuwunel_core::{Err, Result, info, utils::stream::ReadyExt};
+use tuwunel_core::{Err, Result, info};
 use tuwunel_service::users::device::generate_refresh_token;
 
 use self::{ldap::ldap_login, password::password_login};
 pub(crate) use self::{
 	logout::{logout_all_route, logout_route},
 	refresh::refresh_token_route,
-	sso::{sso_callback_route, sso_login_route, sso_login_with_provider_route},
+	sso::{
+		cross_signing_reset_approved_route, cross_signing_reset_route, cross_signing_reset_url,
+		reset_provider, sso_callback_route, sso_login_route, sso_login_with_provider_route,
+	},
 	token::login_token_route,
 };
 use super::TOKEN_LENGTH;
@@ -63,6 +66,13 @@ pub(crate) async fn get_login_types_route(
 		})
 		.collect();
 
+	// Clients sign in with the provider authentication is delegated to.
+	if services.oauth.delegated() {
+		return Ok(get_login_types::v3::Response {
+			flows: vec![LoginType::ApplicationService(ApplicationServiceLoginType::default())],
+		});
+	}
+
 	let flows = [
 		LoginType::ApplicationService(ApplicationServiceLoginType::default()),
 		LoginType::Jwt(JwtLoginType::default()),
@@ -93,6 +103,7 @@ pub(crate) async fn get_login_types_route(
 /// - The user needs to authenticate using their password (or if enabled using a
 ///   json web token)
 /// - If `device_id` is known: invalidates old access token of that device
+/// - If `device_id` is the dehydrated device: claims it as a regular device
 /// - If `device_id` is unknown: creates a new device
 /// - Returns access token that is associated with the user and device
 ///
@@ -105,6 +116,13 @@ pub(crate) async fn login_route(
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<login::v3::Request>,
 ) -> Result<login::v3::Response> {
+	if services.oauth.delegated() && !matches!(body.login_info, LoginInfo::ApplicationService(_))
+	{
+		return Err!(Request(Unrecognized(
+			"Authentication is delegated to an OpenID Connect provider."
+		)));
+	}
+
 	// Validate login method
 	let user_id = match &body.login_info {
 		| LoginInfo::Password(info) => password::handle_login(&services, &body, info).await?,
@@ -129,39 +147,38 @@ pub(crate) async fn login_route(
 	// Generate a new refresh_token if requested by client
 	let refresh_token = expires_in.is_some().then(generate_refresh_token);
 
-	// Determine if device_id was provided and exists in the db for this user
-	let device_id = if let Some(device_id) = &body.device_id
+	// Logging in with the ID of an existing device takes it over; see
+	// `create_device`.
+	let takeover = match &body.device_id {
+		| Some(device_id) =>
+			services
+				.users
+				.device_exists(&user_id, device_id)
+				.await,
+		| None => false,
+	};
+
+	let device_id = services
+		.users
+		.create_device(
+			&user_id,
+			body.device_id.as_deref(),
+			(Some(&access_token), expires_in),
+			refresh_token.as_deref(),
+			body.initial_device_display_name.as_deref(),
+			Some(client.to_string()),
+		)
+		.await?;
+
+	// Logging in with the dehydrated device's ID rehydrates it.
+	if takeover
 		&& services
 			.users
-			.all_device_ids(&user_id)
-			.ready_any(|v| v == device_id)
+			.claim_dehydrated_device(&user_id, &device_id)
 			.await
 	{
-		services
-			.users
-			.set_access_token(
-				&user_id,
-				device_id,
-				&access_token,
-				expires_in,
-				refresh_token.as_deref(),
-			)
-			.await?;
-
-		device_id.clone()
-	} else {
-		services
-			.users
-			.create_device(
-				&user_id,
-				body.device_id.as_deref(),
-				(Some(&access_token), expires_in),
-				refresh_token.as_deref(),
-				body.initial_device_display_name.as_deref(),
-				Some(client.to_string()),
-			)
-			.await?
-	};
+		info!("{user_id} rehydrated device {device_id}");
+	}
 
 	info!("{user_id} logged in");
 
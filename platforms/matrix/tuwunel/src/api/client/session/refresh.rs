@@ -1,8 +1,11 @@
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
-use ruma::api::client::session::refresh_token::v3::{Request, Response};
-use tuwunel_core::{Err, Result, debug_info, err};
-use tuwunel_service::users::device::generate_refresh_token;
+use ruma::api::client::{
+	error::ErrorKind,
+	session::refresh_token::v3::{Request, Response},
+};
+use tuwunel_core::{Err, Error, Result, debug_info, err, utils::result::LogErr, warn};
+use tuwunel_service::users::device::{RefreshTokenUse, generate_refresh_token};
 
 use crate::Ruma;
 
@@ -29,26 +32,50 @@ pub(crate) async fn refresh_token_route(
 		.await
 		.map_err(|e| err!(Request(Forbidden("Refresh token is unrecognized: {e}"))))?;
 
+	match services
+		.users
+		.check_refresh_token(&user_id, &device_id, &refresh_token_claim)
+		.await
+	{
+		| RefreshTokenUse::Current | RefreshTokenUse::Grace => {},
+		| RefreshTokenUse::Reused if services.config.refresh_token_reuse_soft_logout => {
+			warn!(?user_id, ?device_id, "Refresh token reused; soft-logging out the device.");
+			services
+				.users
+				.soft_logout(&user_id, &device_id)
+				.await
+				.log_err()
+				.ok();
+
+			return Err(Error::BadRequest(
+				ErrorKind::UnknownToken { soft_logout: true },
+				"Refresh token was already used.",
+			));
+		},
+		| RefreshTokenUse::Reused | RefreshTokenUse::Unknown => {
+			return Err!(Request(Forbidden("Refresh token is no longer valid.")));
+		},
+	}
+
 	// New tokens
-	let refresh_token = Some(generate_refresh_token());
+	let refresh_token = generate_refresh_token();
 	let (access_token, expires_in_ms) = services.users.generate_access_token(true);
 
 	services
 		.users
-		.set_access_token(
-			&user_id,
-			&device_id,
-			&access_token,
-			expires_in_ms,
-			refresh_token.as_deref(),
-		)
+		.rotate_refresh_token(&user_id, &device_id, &refresh_token)
+		.await?;
+
+	services
+		.users
+		.set_access_token(&user_id, &device_id, &access_token, expires_in_ms, None)
 		.await?;
 
 	debug_info!(?user_id, ?device_id, ?expires_in_ms, "refreshed their access_token",);
 
 	Ok(Response {
 		access_token,
-		refresh_token,
+		refresh_token: Some(refresh_token),
 		expires_in_ms,
 	})
 }
@@ -1,14 +1,22 @@
 use std::{borrow::Cow, net::IpAddr, time::Duration};
 
-use axum::extract::State;
+use axum::{
+	extract::{Path, State},
+	response::{Html, IntoResponse},
+};
 use axum_client_ip::InsecureClientIp;
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as b64};
 use futures::{FutureExt, StreamExt, TryFutureExt, future::try_join};
+use http::{
+	HeaderMap, StatusCode,
+	header::{LOCATION, SET_COOKIE},
+};
 use reqwest::header::{CONTENT_TYPE, HeaderValue};
 use ruma::{
-	Mxc, OwnedMxcUri, OwnedRoomId, OwnedUserId, ServerName, UserId,
+	Mxc, OwnedMxcUri, OwnedUserId, ServerName, UserId,
 	api::client::session::{sso_callback, sso_login, sso_login_with_provider},
+	thirdparty::Medium,
 };
 use serde::{Deserialize, Serialize};
 use tuwunel_core::{
@@ -19,7 +27,7 @@
 	itertools::Itertools,
 	utils,
 	utils::{
-		OptionExt,
+		OptionExt, ReadyExt,
 		content_disposition::make_content_disposition,
 		hash::sha256,
 		result::{FlatOk, LogErr},
@@ -99,7 +107,7 @@ pub(crate) async fn sso_login_route(
 
 	let redirect_url = body.body.redirect_url;
 
-	handle_sso_login(&services, &client, default_idp_id, redirect_url, None)
+	handle_sso_login(&services, &client, default_idp_id, redirect_url, None, None)
 		.map_ok(|response| sso_login::v3::Response {
 			location: response.location,
 			cookie: response.cookie,
@@ -131,7 +139,7 @@ pub(crate) async fn sso_login_with_provider_route(
 	let redirect_url = body.body.redirect_url;
 	let login_token = body.body.login_token;
 
-	handle_sso_login(&services, &client, idp_id, redirect_url, login_token).await
+	handle_sso_login(&services, &client, idp_id, redirect_url, login_token, None).await
 }
 
 async fn handle_sso_login(
@@ -140,6 +148,7 @@ async fn handle_sso_login(
 	idp_id: String,
 	redirect_url: String,
 	login_token: Option<String>,
+	cross_signing_reset: Option<String>,
 ) -> Result<sso_login_with_provider::v3::Response> {
 	let Ok(redirect_url) = redirect_url.parse::<Url>() else {
 		return Err!(Request(InvalidParam("Invalid redirect_url")));
@@ -224,12 +233,17 @@ async fn handle_sso_login(
 			.map(timepoint_from_now)
 			.transpose()?,
 
-		user_id: login_token
-			.as_deref()
-			.map_async(|token| services.users.find_from_login_token(token))
-			.map(FlatOk::flat_ok)
-			.await,
+		user_id: match cross_signing_reset.as_deref() {
+			| Some(token) => services.uiaa.cross_signing_reset_user(token),
+			| None =>
+				login_token
+					.as_deref()
+					.map_async(|token| services.users.find_from_login_token(token))
+					.map(FlatOk::flat_ok)
+					.await,
+		},
 
+		cross_signing_reset,
 		..Default::default()
 	};
 
@@ -372,6 +386,20 @@ pub(crate) async fn sso_callback_route(
 		| Err(_) => (None, None),
 	};
 
+	let cookie = Cookie::build((GRANT_SESSION_COOKIE, EMPTY))
+		.removal()
+		.build()
+		.to_string()
+		.into();
+
+	if let Some(token) = session.cross_signing_reset.as_deref() {
+		let location =
+			approve_cross_signing_reset(&services, &session, token, old_user_id.as_deref())
+				.await?;
+
+		return Ok(sso_callback::unstable::Response { location, cookie: Some(cookie) });
+	}
+
 	// Update the session with userinfo
 	let session = Session {
 		user_info: Some(userinfo.clone()),
@@ -409,12 +437,6 @@ pub(crate) async fn sso_callback_route(
 		return Err!(Request(UserDeactivated("This user has been deactivated.")));
 	}
 
-	let cookie = Cookie::build((GRANT_SESSION_COOKIE, EMPTY))
-		.removal()
-		.build()
-		.to_string()
-		.into();
-
 	// Determine the next provider to chain after this one.
 	let next_idp_url = services
 		.config
@@ -483,6 +505,19 @@ async fn register_user(
 		})
 		.await?;
 
+	// The address is associated with the user only when the provider verified it.
+	if let Some(email) = userinfo
+		.email
+		.as_deref()
+		.filter(|_| userinfo.email_verified == Some(true))
+		&& let Err(e) = services
+			.users
+			.add_threepid(user_id, &Medium::Email, email, utils::time::now_millis())
+			.await
+	{
+		debug_warn!(%user_id, "Email address of {idp} not associated: {e}", idp = provider.id());
+	}
+
 	if let Some(avatar_url) = userinfo
 		.avatar_url
 		.as_deref()
@@ -549,22 +584,132 @@ async fn set_avatar(
 		.create(&mxc, Some(user_id), Some(&content_disposition), content_type.as_deref(), &bytes)
 		.await?;
 
-	let all_joined_rooms: Vec<OwnedRoomId> = services
-		.state_cache
-		.rooms_joined(user_id)
-		.map(ToOwned::to_owned)
-		.collect()
-		.await;
+	let profile_rooms = services.users.profile_rooms(user_id).await;
 
 	let mxc_uri: OwnedMxcUri = mxc.to_string().into();
 	services
 		.users
-		.update_avatar_url(user_id, Some(&mxc_uri), None, &all_joined_rooms)
+		.update_avatar_url(user_id, Some(&mxc_uri), None, &profile_rooms)
 		.await;
 
 	Ok(())
 }
 
+/// # `GET /_tuwunel/cross_signing_reset/{token}`
+///
+/// Sends the user to sign in at their identity provider to approve the reset
+/// of their cross-signing keys (MSC2965).
+#[tracing::instrument(name = "cross_signing_reset", level = "debug", skip_all, fields(%client))]
+pub(crate) async fn cross_signing_reset_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	Path(token): Path<String>,
+) -> Result<impl IntoResponse> {
+	let user_id = services
+		.uiaa
+		.cross_signing_reset_user(&token)
+		.ok_or_else(|| err!(Request(NotFound("No cross-signing reset is awaiting approval."))))?;
+
+	let provider = reset_provider(&services, &user_id).await?;
+	let approved_url = cross_signing_reset_url(&provider, "approved")?;
+	let response = handle_sso_login(
+		&services,
+		&client,
+		provider.id().to_owned(),
+		approved_url.into(),
+		None,
+		Some(token),
+	)
+	.await?;
+
+	let mut headers = HeaderMap::new();
+	headers.insert(LOCATION, HeaderValue::from_str(&response.location)?);
+	if let Some(cookie) = response.cookie {
+		headers.insert(SET_COOKIE, HeaderValue::from_str(&cookie)?);
+	}
+
+	Ok((StatusCode::FOUND, headers))
+}
+
+/// # `GET /_tuwunel/cross_signing_reset/approved`
+///
+/// Where users are returned after approving a cross-signing reset.
+pub(crate) async fn cross_signing_reset_approved_route() -> impl IntoResponse {
+	Html(
+		"<!DOCTYPE html><html><head><title>Cross-signing reset \
+		 approved</title></head><body><p>The reset of your cross-signing keys was approved. \
+		 Return to your app to finish it.</p></body></html>",
+	)
+}
+
+/// The provider a user approves a cross-signing reset at: the provider
+/// authentication is delegated to, or else the one they signed in with.
+pub(crate) async fn reset_provider(services: &Services, user_id: &UserId) -> Result<Provider> {
+	if services.oauth.delegated() {
+		return services.oauth.delegated_provider().await;
+	}
+
+	let idp_id = services
+		.oauth
+		.sessions
+		.get_by_user(user_id)
+		.ready_filter_map(|session| session.ok()?.idp_id)
+		.next()
+		.await
+		.ok_or_else(|| err!(Request(Forbidden("{user_id} has not signed in with SSO."))))?;
+
+	services.oauth.providers.get(&idp_id).await
+}
+
+/// URL of the cross-signing reset endpoint with the token, on the host the
+/// provider returns users to.
+pub(crate) fn cross_signing_reset_url(provider: &Provider, token: &str) -> Result<Url> {
+	let mut url = provider.callback_url.clone().ok_or_else(|| {
+		err!(Config("callback_url", "Missing required IdentityProvider config"))
+	})?;
+
+	url.set_path(&format!("/_tuwunel/cross_signing_reset/{token}"));
+	url.set_query(None);
+
+	Ok(url)
+}
+
+/// Approves the cross-signing reset under the token when the identity signed in
+/// with is associated with the user who requested it. Returns where to send
+/// the user next.
+async fn approve_cross_signing_reset(
+	services: &Services,
+	session: &Session,
+	token: &str,
+	identity_user_id: Option<&UserId>,
+) -> Result<String> {
+	let Some(user_id) = session.user_id.as_deref() else {
+		return Err!(Request(Forbidden("No cross-signing reset is awaiting approval.")));
+	};
+
+	if identity_user_id != Some(user_id) {
+		return Err!(Request(Forbidden(
+			"The identity signed in with is not associated with {user_id}."
+		)));
+	}
+
+	services
+		.uiaa
+		.approve_cross_signing_reset(token, user_id)?;
+
+	if let Some(sess_id) = session.sess_id.as_deref() {
+		services.oauth.sessions.delete(sess_id).await;
+	}
+
+	info!(%user_id, "Approved cross-signing reset");
+
+	session
+		.redirect_url
+		.as_ref()
+		.map(ToString::to_string)
+		.ok_or_else(|| err!(Request(InvalidParam("Missing redirect URL in session data"))))
+}
+
 #[tracing::instrument(
 	level = "debug",
 	ret(level = "debug")
@@ -661,6 +806,11 @@ async fn try_user_id(
 		return None;
 	}
 
+	if services.users.is_reserved_localpart(username) {
+		warn!(?username, "Username reserved.");
+		return None;
+	}
+
 	if services.users.exists(&user_id).await {
 		debug_warn!(?username, "Username exists.");
 
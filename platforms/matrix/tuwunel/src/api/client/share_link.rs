@@ -0,0 +1,202 @@
+use std::time::{Duration, SystemTime};
+
+use axum::{
+	Json,
+	extract::{Path, State},
+	response::IntoResponse,
+};
+use axum_client_ip::InsecureClientIp;
+use futures::{FutureExt, StreamExt};
+use ruma::{OwnedRoomId, OwnedUserId};
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, json};
+use tuwunel_core::{Err, Result, utils::time::duration_since_epoch};
+use tuwunel_service::rooms::share_links::ShareLink;
+
+use super::banned_room_check;
+use crate::router::{Sender, Signed};
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateShareLinkBody {
+	/// Milliseconds until the link expires; never when absent.
+	expires_in_ms: Option<u64>,
+
+	/// Number of redemptions after which the link stops working.
+	max_uses: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InviteShareLinkBody {
+	user_id: OwnedUserId,
+}
+
+/// # `POST /_tuwunel/client/v1/rooms/{room_id}/share_links`
+///
+/// Mint a link granting entry to an invite-only room. Requires the power to
+/// invite.
+pub(crate) async fn create_share_link_route(
+	State(services): State<crate::State>,
+	Sender { user: sender_user, .. }: Sender,
+	Path(room_id): Path<OwnedRoomId>,
+	Json(body): Json<CreateShareLinkBody>,
+) -> Result<impl IntoResponse> {
+	let (id, token) = services
+		.share_links
+		.create(
+			&room_id,
+			&sender_user,
+			body.expires_in_ms.map(Duration::from_millis),
+			body.max_uses,
+		)
+		.await?;
+
+	let link = services.share_links.get(&id).await?;
+
+	Ok(Json(share_link_json(&id, &token, &link, None)))
+}
+
+/// # `GET /_tuwunel/client/v1/rooms/{room_id}/share_links`
+///
+/// List the links minted for a room along with who redeemed them. Requires the
+/// power to invite.
+pub(crate) async fn get_share_links_route(
+	State(services): State<crate::State>,
+	Sender { user: sender_user, .. }: Sender,
+	Path(room_id): Path<OwnedRoomId>,
+) -> Result<impl IntoResponse> {
+	services
+		.share_links
+		.check_moderator(&room_id, &sender_user)
+		.await?;
+
+	let share_links: Vec<_> = services
+		.share_links
+		.room_links(&room_id)
+		.then(async |(id, link)| {
+			let redemptions: Vec<_> = services
+				.share_links
+				.redemptions(&id)
+				.map(|redemption| {
+					json!({
+						"user_id": redemption.user_id,
+						"redeemed_at": redemption.redeemed_at,
+					})
+				})
+				.collect()
+				.await;
+
+			let token = services.share_links.token(&id);
+
+			share_link_json(&id, &token, &link, Some(redemptions))
+		})
+		.collect()
+		.await;
+
+	Ok(Json(json!({ "share_links": share_links })))
+}
+
+/// # `DELETE /_tuwunel/client/v1/rooms/{room_id}/share_links/{id}`
+///
+/// Revoke a link. Requires the power to invite.
+pub(crate) async fn revoke_share_link_route(
+	State(services): State<crate::State>,
+	Sender { user: sender_user, .. }: Sender,
+	Path((room_id, id)): Path<(OwnedRoomId, String)>,
+) -> Result<impl IntoResponse> {
+	services
+		.share_links
+		.check_moderator(&room_id, &sender_user)
+		.await?;
+
+	services.share_links.revoke(&room_id, &id).await?;
+
+	Ok(Json(json!({})))
+}
+
+/// # `POST /_tuwunel/client/v1/share_links/{token}/join`
+///
+/// Join the room of a link as the authenticated local user.
+#[tracing::instrument(skip_all, fields(%client), name = "share_link_join")]
+pub(crate) async fn join_share_link_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	Sender { user: sender_user, .. }: Sender,
+	Path(token): Path<String>,
+) -> Result<impl IntoResponse> {
+	let room_id = services.share_links.room_id(&token).await?;
+
+	banned_room_check(&services, &sender_user, &room_id, None, client).await?;
+
+	services
+		.share_links
+		.redeem(&token, &sender_user)
+		.await?;
+
+	let state_lock = services.state.mutex.lock(&room_id).await;
+
+	services
+		.membership
+		.join(&sender_user, &room_id, None, None, &[], false, &state_lock)
+		.boxed()
+		.await?;
+
+	drop(state_lock);
+
+	Ok(Json(json!({ "room_id": room_id })))
+}
+
+/// # `POST /_tuwunel/share_links/{token}/invite`
+///
+/// Invite a user of another server to the room of a link. They are left to
+/// accept the invite from their own server. The request is signed by the
+/// user's server. Local users join with `join_share_link_route` instead.
+pub(crate) async fn invite_share_link_route(
+	State(services): State<crate::State>,
+	Path(token): Path<String>,
+	Signed { body, origin }: Signed<InviteShareLinkBody>,
+) -> Result<impl IntoResponse> {
+	if services.globals.user_is_local(&body.user_id) {
+		return Err!(Request(Forbidden("Local users must join with their access token.")));
+	}
+
+	if body.user_id.server_name() != origin {
+		return Err!(Request(Forbidden("Servers may only invite their own users.")));
+	}
+
+	let room_id = services
+		.share_links
+		.redeem(&token, &body.user_id)
+		.await?;
+
+	Ok(Json(json!({ "room_id": room_id })))
+}
+
+fn share_link_json(
+	id: &str,
+	token: &str,
+	link: &ShareLink,
+	redemptions: Option<Vec<JsonValue>>,
+) -> JsonValue {
+	let millis = |time: SystemTime| {
+		u64::try_from(duration_since_epoch(time).as_millis()).unwrap_or(u64::MAX)
+	};
+
+	let mut json = json!({
+		"id": id,
+		"token": token,
+		"room_id": link.room_id,
+		"creator": link.creator,
+		"created_at": millis(link.created_at),
+		"expires_at": link.expires_at.map(millis),
+		"max_uses": link.max_uses,
+		"uses": link.uses,
+		"revoked_at": link.revoked_at.map(millis),
+		"valid": link.is_valid(),
+	});
+
+	if let Some(redemptions) = redemptions {
+		json["redemptions"] = redemptions.into();
+	}
+
+	json
+}
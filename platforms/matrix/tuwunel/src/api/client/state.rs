@@ -269,6 +269,11 @@ async fn allowed_to_send_state_event(
 				},
 			}
 		},
+		| StateEventType::RoomName | StateEventType::RoomTopic => {
+			services
+				.sanitize
+				.check_content(&event_type.to_string().into(), json.json())?;
+		},
 		| StateEventType::RoomEncryption =>
 		// Forbid m.room.encryption if encryption is disabled
 			if !services.config.allow_encryption {
@@ -0,0 +1,108 @@
+use axum::{
+	Json,
+	extract::{Path, State},
+	response::IntoResponse,
+};
+use ruma::{
+	OwnedRoomId, RoomId, UserId,
+	events::{AnyStateEvent, StateEventType},
+	serde::Raw,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tuwunel_core::{Err, Result, matrix::Event};
+use tuwunel_service::{
+	Services,
+	rooms::{short::ShortStateHash, state_accessor::StateFilter},
+};
+
+use crate::router::Sender;
+
+/// Most filters accepted in a single request.
+const MAX_FILTERS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct StateFilteredBody {
+	filters: Vec<StateFilter>,
+}
+
+/// # `POST /_tuwunel/client/v1/rooms/{room_id}/state_filtered`
+///
+/// Get the events of the room state matching any of the `(type, state_key)`
+/// filters in one request. A `state_key` ending with `*` matches the state keys
+/// starting with what precedes it. Authenticated with an access token, or with
+/// an appservice's `as_token` acting as `user_id`. Users who left the room get
+/// the state as of when they left.
+pub(crate) async fn get_state_filtered_route(
+	State(services): State<crate::State>,
+	Sender { user: sender_user, .. }: Sender,
+	Path(room_id): Path<OwnedRoomId>,
+	Json(body): Json<StateFilteredBody>,
+) -> Result<impl IntoResponse> {
+	if body.filters.len() > MAX_FILTERS {
+		return Err!(Request(InvalidParam("At most {MAX_FILTERS} filters are accepted.")));
+	}
+
+	let state = match visible_state(&services, &sender_user, &room_id).await? {
+		| None =>
+			services
+				.state_accessor
+				.room_state_get_filtered(&room_id, &body.filters)
+				.await?,
+
+		| Some(shortstatehash) =>
+			services
+				.state_accessor
+				.state_get_filtered(shortstatehash, &body.filters)
+				.await?,
+	};
+
+	let state: Vec<Raw<AnyStateEvent>> = state
+		.into_iter()
+		.map(Event::into_format)
+		.collect();
+
+	Ok(Json(json!({ "state": state })))
+}
+
+/// The state of the room the user may see: None for the current state, while
+/// joined or when the room is world-readable; else the state at their leave,
+/// when they could see the state then.
+async fn visible_state(
+	services: &Services,
+	user_id: &UserId,
+	room_id: &RoomId,
+) -> Result<Option<ShortStateHash>> {
+	if services
+		.state_cache
+		.is_joined(user_id, room_id)
+		.await || services
+		.state_accessor
+		.is_world_readable(room_id)
+		.await
+	{
+		return Ok(None);
+	}
+
+	if !services
+		.state_cache
+		.is_left(user_id, room_id)
+		.await || !services
+		.state_accessor
+		.user_can_see_state_events(user_id, room_id)
+		.await
+	{
+		return Err!(Request(Forbidden("You don't have permission to view the room state.")));
+	}
+
+	let leave_event_id = services
+		.state_accessor
+		.room_state_get_id(room_id, &StateEventType::RoomMember, user_id.as_str())
+		.await?;
+
+	services
+		.state
+		.pdu_shortstatehash(&leave_event_id)
+		.await
+		.map(Some)
+}
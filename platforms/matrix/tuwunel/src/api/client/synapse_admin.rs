@@ -0,0 +1,453 @@
+//! A subset of the Synapse admin API, for the dashboards which only speak it.
+//! Each handler requires the access token of a server admin, extracted as an
+//! [`AdminUser`].
+
+use axum::{
+	Json,
+	extract::{Path, Query, State},
+	response::IntoResponse,
+};
+use futures::{FutureExt, StreamExt};
+use ruma::{Mxc, OwnedMxcUri, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, UserId};
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, json};
+use tuwunel_core::{Err, Result, info, matrix::Event};
+use tuwunel_service::Services;
+
+use crate::router::AdminUser;
+
+/// Number of entries returned per page when the client does not ask for a
+/// limit.
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ListUsersQuery {
+	from: Option<usize>,
+	limit: Option<usize>,
+
+	/// Substring of the user ID or display name to filter on.
+	name: Option<String>,
+
+	/// Whether deactivated users are listed too.
+	#[serde(default)]
+	deactivated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ListRoomsQuery {
+	from: Option<usize>,
+	limit: Option<usize>,
+
+	/// Substring of the room ID, name or canonical alias to filter on.
+	search_term: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct DeleteRoomBody {
+	/// Whether the room is banned so it cannot be joined again.
+	#[serde(default)]
+	block: bool,
+
+	/// Whether to delete the room even if local users fail to leave it.
+	#[serde(default)]
+	force_purge: bool,
+}
+
+/// # `GET /_synapse/admin/v2/users`
+///
+/// List the local users, sorted by user ID.
+pub(crate) async fn synapse_list_users_route(
+	State(services): State<crate::State>,
+	AdminUser(_): AdminUser,
+	Query(query): Query<ListUsersQuery>,
+) -> Result<impl IntoResponse> {
+	let name = query.name.as_deref().map(str::to_lowercase);
+	let mut users: Vec<JsonValue> = services
+		.users
+		.stream()
+		.filter(|user_id| {
+			let local = services.globals.user_is_local(user_id);
+			async move { local }
+		})
+		.then(|user_id| user_json(&services, user_id))
+		.filter(|user| {
+			let deactivated = user["deactivated"].as_bool().unwrap_or(false);
+			let matches = name.as_deref().is_none_or(|name| {
+				["name", "displayname"].iter().any(|field| {
+					user[field]
+						.as_str()
+						.is_some_and(|value| value.to_lowercase().contains(name))
+				})
+			});
+
+			let keep = matches && (query.deactivated || !deactivated);
+			async move { keep }
+		})
+		.collect()
+		.await;
+
+	users.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+	let total = users.len();
+	let (users, next_token) = paginate(users, query.from, query.limit);
+
+	Ok(Json(json!({
+		"users": users,
+		"next_token": next_token.map(|from| from.to_string()),
+		"total": total,
+	})))
+}
+
+/// # `GET /_synapse/admin/v2/users/{user_id}`
+pub(crate) async fn synapse_get_user_route(
+	State(services): State<crate::State>,
+	AdminUser(_): AdminUser,
+	Path(user_id): Path<OwnedUserId>,
+) -> Result<impl IntoResponse> {
+	if !services.globals.user_is_local(&user_id) || !services.users.exists(&user_id).await {
+		return Err!(Request(NotFound("User not found.")));
+	}
+
+	Ok(Json(user_json(&services, &user_id).await))
+}
+
+/// # `POST /_synapse/admin/v1/deactivate/{user_id}`
+///
+/// Deactivate a local user, making them leave all their rooms.
+pub(crate) async fn synapse_deactivate_user_route(
+	State(services): State<crate::State>,
+	AdminUser(admin): AdminUser,
+	Path(user_id): Path<OwnedUserId>,
+) -> Result<impl IntoResponse> {
+	if !services.globals.user_is_local(&user_id) {
+		return Err!(Request(InvalidParam("Can only deactivate local users.")));
+	}
+
+	if !services.users.exists(&user_id).await {
+		return Err!(Request(NotFound("User not found.")));
+	}
+
+	if user_id == services.globals.server_user {
+		return Err!(Request(Forbidden("Cannot deactivate the server service account.")));
+	}
+
+	info!(%admin, %user_id, "Deactivating user through the admin API");
+	services
+		.deactivate
+		.full_deactivate(&user_id)
+		.boxed()
+		.await?;
+
+	Ok(Json(json!({ "id_server_unbind_result": "success" })))
+}
+
+/// # `GET /_synapse/admin/v1/rooms`
+///
+/// List the rooms known to the server, sorted by name.
+pub(crate) async fn synapse_list_rooms_route(
+	State(services): State<crate::State>,
+	AdminUser(_): AdminUser,
+	Query(query): Query<ListRoomsQuery>,
+) -> Result<impl IntoResponse> {
+	let search_term = query
+		.search_term
+		.as_deref()
+		.map(str::to_lowercase);
+
+	let mut rooms: Vec<JsonValue> = services
+		.metadata
+		.iter_ids()
+		.then(|room_id| room_json(&services, room_id))
+		.filter(|room| {
+			let matches = search_term.as_deref().is_none_or(|term| {
+				["room_id", "name", "canonical_alias"]
+					.iter()
+					.any(|field| {
+						room[field]
+							.as_str()
+							.is_some_and(|value| value.to_lowercase().contains(term))
+					})
+			});
+
+			async move { matches }
+		})
+		.collect()
+		.await;
+
+	rooms.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+	let offset = query.from.unwrap_or(0);
+	let total_rooms = rooms.len();
+	let (rooms, next_batch) = paginate(rooms, query.from, query.limit);
+
+	Ok(Json(json!({
+		"rooms": rooms,
+		"offset": offset,
+		"total_rooms": total_rooms,
+		"next_batch": next_batch,
+	})))
+}
+
+/// # `GET /_synapse/admin/v1/rooms/{room_id}`
+pub(crate) async fn synapse_get_room_route(
+	State(services): State<crate::State>,
+	AdminUser(_): AdminUser,
+	Path(room_id): Path<OwnedRoomId>,
+) -> Result<impl IntoResponse> {
+	if !services.metadata.exists(&room_id).await {
+		return Err!(Request(NotFound("Room not found.")));
+	}
+
+	Ok(Json(room_json(&services, &room_id).await))
+}
+
+/// # `DELETE /_synapse/admin/v1/rooms/{room_id}`
+///
+/// Make the local users leave the room and delete it from the database,
+/// optionally banning it.
+pub(crate) async fn synapse_delete_room_route(
+	State(services): State<crate::State>,
+	AdminUser(admin): AdminUser,
+	Path(room_id): Path<OwnedRoomId>,
+	body: Option<Json<DeleteRoomBody>>,
+) -> Result<impl IntoResponse> {
+	let body = body.map(|Json(body)| body).unwrap_or_default();
+
+	if services.admin.is_admin_room(&room_id).await {
+		return Err!(Request(Forbidden("Cannot delete the admin room.")));
+	}
+
+	if !services.metadata.exists(&room_id).await {
+		return Err!(Request(NotFound("Room not found.")));
+	}
+
+	let kicked_users: Vec<OwnedUserId> = services
+		.state_cache
+		.local_users_in_room(&room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let local_aliases: Vec<_> = services
+		.alias
+		.local_aliases_for_room(&room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	info!(%admin, %room_id, block = body.block, "Deleting room through the admin API");
+	if body.block {
+		services.metadata.ban_room(&room_id);
+	}
+
+	let state_lock = services.state.mutex.lock(&room_id).await;
+	let failed_to_kick_users = services
+		.delete
+		.delete_room(&room_id, body.force_purge, state_lock)
+		.boxed()
+		.await?;
+
+	let kicked_users = kicked(kicked_users, &failed_to_kick_users);
+
+	Ok(Json(json!({
+		"kicked_users": kicked_users,
+		"failed_to_kick_users": failed_to_kick_users,
+		"local_aliases": local_aliases,
+		"new_room_id": null,
+	})))
+}
+
+/// # `POST /_synapse/admin/v1/media/quarantine/{server_name}/{media_id}`
+pub(crate) async fn synapse_quarantine_media_route(
+	State(services): State<crate::State>,
+	AdminUser(admin): AdminUser,
+	Path((server_name, media_id)): Path<(OwnedServerName, String)>,
+) -> Result<impl IntoResponse> {
+	let mxc = Mxc {
+		server_name: &server_name,
+		media_id: &media_id,
+	};
+
+	services.media.quarantine(&mxc, &admin);
+
+	Ok(Json(json!({})))
+}
+
+/// # `POST /_synapse/admin/v1/media/unquarantine/{server_name}/{media_id}`
+pub(crate) async fn synapse_unquarantine_media_route(
+	State(services): State<crate::State>,
+	AdminUser(_): AdminUser,
+	Path((server_name, media_id)): Path<(OwnedServerName, String)>,
+) -> Result<impl IntoResponse> {
+	let mxc = Mxc {
+		server_name: &server_name,
+		media_id: &media_id,
+	};
+
+	services.media.unquarantine(&mxc);
+
+	Ok(Json(json!({})))
+}
+
+/// # `POST /_synapse/admin/v1/user/{user_id}/media/quarantine`
+///
+/// Quarantine all the media uploaded by a local user.
+pub(crate) async fn synapse_quarantine_user_media_route(
+	State(services): State<crate::State>,
+	AdminUser(admin): AdminUser,
+	Path(user_id): Path<OwnedUserId>,
+) -> Result<impl IntoResponse> {
+	let num_quarantined = services
+		.media
+		.quarantine_from_user(&user_id, &admin)
+		.await;
+
+	Ok(Json(json!({ "num_quarantined": num_quarantined })))
+}
+
+async fn user_json(services: &Services, user_id: &UserId) -> JsonValue {
+	let displayname = services.users.displayname(user_id).await.ok();
+	let avatar_url: Option<OwnedMxcUri> = services.users.avatar_url(user_id).await.ok();
+	let deactivated = services
+		.users
+		.is_deactivated(user_id)
+		.await
+		.unwrap_or(false);
+	let shadow_banned = services.users.is_shadow_banned(user_id).await;
+	let threepids: Vec<_> = services.users.threepids(user_id).collect().await;
+
+	json!({
+		"name": user_id,
+		"displayname": displayname,
+		"avatar_url": avatar_url,
+		"admin": services.admin.user_is_admin(user_id).await,
+		"deactivated": deactivated,
+		"is_guest": false,
+		"user_type": null,
+		"shadow_banned": shadow_banned,
+		"erased": false,
+		"threepids": threepids,
+		"external_ids": [],
+	})
+}
+
+async fn room_json(services: &Services, room_id: &RoomId) -> JsonValue {
+	let create = services
+		.state_accessor
+		.get_create(room_id)
+		.await
+		.ok();
+	let rules = services
+		.state
+		.get_room_version_rules(room_id)
+		.await
+		.ok();
+
+	let creator = create
+		.as_ref()
+		.zip(rules.as_ref())
+		.and_then(|(create, rules)| create.creator(&rules.authorization).ok())
+		.map(|creator| creator.into_owned())
+		.or_else(|| {
+			create
+				.as_ref()
+				.map(|create| create.sender().to_owned())
+		});
+
+	let federatable = create
+		.as_ref()
+		.and_then(|create| create.federate().ok())
+		.unwrap_or(true);
+
+	let joined_local_members = services
+		.state_cache
+		.local_users_in_room(room_id)
+		.count()
+		.await;
+
+	let state_events = services
+		.state_bloat
+		.get_sample(room_id)
+		.await
+		.map_or(0, |sample| sample.state_events);
+
+	json!({
+		"room_id": room_id,
+		"name": services.state_accessor.get_name(room_id).await.ok(),
+		"canonical_alias": services.state_accessor.get_canonical_alias(room_id).await.ok(),
+		"joined_members": services.state_cache.room_joined_count(room_id).await.unwrap_or(0),
+		"joined_local_members": joined_local_members,
+		"version": services.state.get_room_version(room_id).await.ok(),
+		"creator": creator,
+		"encryption": services
+			.state_accessor
+			.get_room_encryption(room_id)
+			.await
+			.ok(),
+		"federatable": federatable,
+		"public": services.directory.is_public_room(room_id).await,
+		"join_rules": services.state_accessor.get_join_rules(room_id).await.kind(),
+		"guest_access": services
+			.state_accessor
+			.guest_can_join(room_id)
+			.await
+			.then_some("can_join"),
+		"room_type": services.state_accessor.get_room_type(room_id).await.ok(),
+		"state_events": state_events,
+	})
+}
+
+/// Returns the page of `entries` starting at `from`, and where the next page
+/// starts if there is one.
+fn paginate<T>(
+	entries: Vec<T>,
+	from: Option<usize>,
+	limit: Option<usize>,
+) -> (Vec<T>, Option<usize>) {
+	let from = from.unwrap_or(0);
+	let limit = limit.unwrap_or(DEFAULT_LIMIT);
+	let total = entries.len();
+	let page: Vec<T> = entries
+		.into_iter()
+		.skip(from)
+		.take(limit)
+		.collect();
+
+	let next = from.saturating_add(page.len());
+	(page, (next < total).then_some(next))
+}
+
+/// The users made to leave: those who were in the room and did not fail to.
+fn kicked(members: Vec<OwnedUserId>, failed: &[OwnedUserId]) -> Vec<OwnedUserId> {
+	members
+		.into_iter()
+		.filter(|user_id| !failed.contains(user_id))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::{OwnedUserId, owned_user_id};
+
+	use super::{kicked, paginate};
+
+	#[test]
+	fn paginate_pages() {
+		let entries: Vec<_> = (0..5).collect();
+
+		assert_eq!(paginate(entries.clone(), None, Some(2)), (vec![0, 1], Some(2)));
+		assert_eq!(paginate(entries.clone(), Some(2), Some(2)), (vec![2, 3], Some(4)));
+		assert_eq!(paginate(entries.clone(), Some(4), Some(2)), (vec![4], None));
+		assert_eq!(paginate(entries, Some(9), None), (vec![], None));
+	}
+
+	#[test]
+	fn kicked_excludes_failed() {
+		let alice: OwnedUserId = owned_user_id!("@alice:example.com");
+		let bob: OwnedUserId = owned_user_id!("@bob:example.com");
+
+		let members = vec![alice.clone(), bob.clone()];
+		assert_eq!(kicked(members.clone(), &[bob]), vec![alice]);
+		assert_eq!(kicked(members.clone(), &[]), members);
+	}
+}
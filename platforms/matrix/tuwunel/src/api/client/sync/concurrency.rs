@@ -0,0 +1,89 @@
+//! Number of rooms a sync loads at once, adapted to how quickly they load and
+//! to how busy the database is.
+
+use std::{
+	sync::atomic::{AtomicUsize, Ordering},
+	time::{Duration, Instant},
+};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tuwunel_core::utils::stream::automatic_width;
+use tuwunel_service::Services;
+
+pub(super) struct Concurrency<'a> {
+	services: &'a Services,
+	semaphore: Semaphore,
+	limit: AtomicUsize,
+	min: usize,
+	max: usize,
+	budget: Duration,
+}
+
+impl<'a> Concurrency<'a> {
+	pub(super) fn new(services: &'a Services) -> Self {
+		let config = &services.config;
+		let min = automatic_width();
+		let max = match config.sync_room_concurrency_max {
+			| 0 => min.saturating_mul(8),
+			| max => max.max(min),
+		};
+
+		Self {
+			services,
+			semaphore: Semaphore::new(min),
+			limit: AtomicUsize::new(min),
+			min,
+			max,
+			budget: Duration::from_millis(config.sync_room_budget_ms),
+		}
+	}
+
+	/// Most rooms which may be loaded at once.
+	#[inline]
+	pub(super) fn max(&self) -> usize { self.max }
+
+	/// Loads a room once fewer rooms than the current limit are loading,
+	/// then adapts the limit to how long it took.
+	pub(super) async fn run<F: Future>(&self, load: F) -> F::Output {
+		let permit = self
+			.semaphore
+			.acquire()
+			.await
+			.expect("semaphore is never closed");
+
+		let started = Instant::now();
+		let output = load.await;
+		self.adapt(started.elapsed(), permit);
+
+		output
+	}
+
+	/// Grows the limit by one after a room loaded within the budget while the
+	/// database queues are less than half full; shrinks it by one otherwise.
+	fn adapt(&self, elapsed: Duration, permit: SemaphorePermit<'_>) {
+		let (queued, capacity) = self.services.db.queue_depth();
+		let congested = elapsed > self.budget || queued.saturating_mul(2) > capacity;
+
+		let update = |limit: usize| {
+			if congested {
+				(limit > self.min).then(|| limit.saturating_sub(1))
+			} else {
+				(limit < self.max).then(|| limit.saturating_add(1))
+			}
+		};
+
+		if self
+			.limit
+			.fetch_update(Ordering::Relaxed, Ordering::Relaxed, update)
+			.is_err()
+		{
+			return;
+		}
+
+		if congested {
+			permit.forget();
+		} else {
+			self.semaphore.add_permits(1);
+		}
+	}
+}
@@ -1,3 +1,4 @@
+mod concurrency;
 mod v3;
 mod v5;
 
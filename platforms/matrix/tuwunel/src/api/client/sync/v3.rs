@@ -11,14 +11,17 @@
 };
 use ruma::{
 	DeviceId, EventId, OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
-	api::client::{
-		filter::FilterDefinition,
-		sync::sync_events::{
-			self, DeviceLists, UnreadNotificationsCount,
-			v3::{
-				Ephemeral, Filter, GlobalAccountData, InviteState, InvitedRoom, JoinedRoom,
-				KnockState, KnockedRoom, LeftRoom, Presence, RoomAccountData, RoomSummary, Rooms,
-				State as RoomState, StateEvents, Timeline, ToDevice,
+	api::{
+		Direction,
+		client::{
+			filter::FilterDefinition,
+			sync::sync_events::{
+				self, DeviceLists, UnreadNotificationsCount,
+				v3::{
+					Ephemeral, Filter, GlobalAccountData, InviteState, InvitedRoom, JoinedRoom,
+					KnockState, KnockedRoom, LeftRoom, Presence, RoomAccountData, RoomSummary,
+					Rooms, State as RoomState, StateEvents, Timeline, ToDevice,
+				},
 			},
 		},
 	},
@@ -55,7 +58,6 @@
 		option::OptionExt,
 		result::MapExpect,
 		stream::{BroadbandExt, Tools, TryExpect, WidebandExt},
-		string::to_small_string,
 	},
 };
 use tuwunel_service::{
@@ -67,7 +69,7 @@
 	},
 };
 
-use super::{load_timeline, share_encrypted_room};
+use super::{concurrency::Concurrency, load_timeline, share_encrypted_room};
 use crate::{Ruma, client::ignored_filter};
 
 #[derive(Default)]
@@ -128,6 +130,7 @@ pub(crate) async fn sync_events_route(
 	State(services): State<crate::State>,
 	body: Ruma<sync_events::v3::Request>,
 ) -> Result<sync_events::v3::Response> {
+	let _timer = services.server.metrics.sync_latency.timer();
 	let sender_user = body.sender_user();
 	let sender_device = body.sender_device.as_deref();
 
@@ -158,13 +161,17 @@ pub(crate) async fn sync_events_route(
 
 	let (filter, ..) = join3(filter, ping_presence, note_sync).await;
 
-	let mut since = body
+	let mut since: u64 = body
 		.body
 		.since
 		.as_deref()
-		.map(str::parse)
-		.flat_ok()
-		.unwrap_or(0);
+		.map(|since| {
+			services
+				.globals
+				.parse_pagination_token(since, Direction::Forward)
+		})
+		.transpose()?
+		.map_or(0, |since| since.pos);
 
 	let timeout = body
 		.body
@@ -257,7 +264,20 @@ async fn build_empty_response(
 			.await
 			.unwrap_or_default(),
 
-		..sync_events::v3::Response::new(to_small_string(next_batch))
+		device_unused_fallback_key_types: sender_device
+			.map_async(|sender_device| {
+				services
+					.users
+					.unused_fallback_key_types(sender_user, sender_device)
+			})
+			.await,
+
+		..sync_events::v3::Response::new(
+			services
+				.globals
+				.pagination_token(next_batch, Direction::Forward)
+				.into(),
+		)
 	}
 }
 
@@ -280,13 +300,14 @@ async fn build_sync_events(
 	full_state: bool,
 	filter: &FilterDefinition,
 ) -> Result<sync_events::v3::Response> {
+	let concurrency = Concurrency::new(services);
 	let joined_rooms = services
 		.state_cache
 		.rooms_joined(sender_user)
 		.ready_filter(|&room_id| filter.room.matches(room_id))
 		.map(ToOwned::to_owned)
-		.broad_filter_map(|room_id| {
-			load_joined_room(
+		.broadn_filter_map(concurrency.max(), |room_id| {
+			let load = load_joined_room(
 				services,
 				sender_user,
 				sender_device,
@@ -297,7 +318,9 @@ async fn build_sync_events(
 				filter,
 			)
 			.map_ok(move |(joined_room, dlu, jeu)| (room_id, joined_room, dlu, jeu))
-			.ok()
+			.ok();
+
+			concurrency.run(load)
 		})
 		.ready_fold(
 			(BTreeMap::new(), HashSet::new(), HashSet::new()),
@@ -415,6 +438,12 @@ async fn build_sync_events(
 			.count_one_time_keys(sender_user, sender_device)
 	});
 
+	let device_unused_fallback_key_types = sender_device.map_async(|sender_device| {
+		services
+			.users
+			.unused_fallback_key_types(sender_user, sender_device)
+	});
+
 	// Remove all to-device events the device received *last time*
 	let remove_to_device_events = sender_device.map_async(|sender_device| {
 		services
@@ -426,7 +455,7 @@ async fn build_sync_events(
 		account_data,
 		keys_changed,
 		presence_updates,
-		(_, to_device_events, device_one_time_keys_count),
+		(_, to_device_events, device_one_time_keys_count, device_unused_fallback_key_types),
 		(
 			(joined_rooms, mut device_list_updates, left_encrypted_users),
 			left_rooms,
@@ -437,7 +466,12 @@ async fn build_sync_events(
 		account_data,
 		keys_changed,
 		presence_updates,
-		join3(remove_to_device_events, to_device_events, device_one_time_keys_count),
+		join4(
+			remove_to_device_events,
+			to_device_events,
+			device_one_time_keys_count,
+			device_unused_fallback_key_types,
+		),
 		join4(joined_rooms, left_rooms, invited_rooms, knocked_rooms),
 	)
 	.boxed()
@@ -474,9 +508,11 @@ async fn build_sync_events(
 			changed: device_list_updates.into_iter().collect(),
 		},
 		device_one_time_keys_count: device_one_time_keys_count.unwrap_or_default(),
-		// Fallback keys are not yet supported
-		device_unused_fallback_key_types: None,
-		next_batch: to_small_string(next_batch),
+		device_unused_fallback_key_types,
+		next_batch: services
+			.globals
+			.pagination_token(next_batch, Direction::Forward)
+			.into(),
 		presence: Presence { events: presence_events },
 		rooms: Rooms {
 			leave: left_rooms,
@@ -507,6 +543,12 @@ async fn process_presence_updates(
 				.state_cache
 				.user_sees_user(syncing_user, user_id)
 		})
+		.filter(|(user_id, ..)| {
+			services
+				.users
+				.user_is_ignored(user_id, syncing_user)
+				.map(is_false!())
+		})
 		.filter_map(|(user_id, _, presence_bytes)| {
 			services
 				.presence
@@ -594,7 +636,11 @@ async fn handle_left_room(
 			timeline: Timeline {
 				limited: false,
 				events: Default::default(),
-				prev_batch: Some(left_count.to_string()),
+				prev_batch: Some(
+					services
+						.globals
+						.pagination_token(left_count, Direction::Backward),
+				),
 			},
 		}));
 	}
@@ -703,8 +749,11 @@ async fn load_left_room(
 		.filter(|_| timeline_limit > 0)
 		.map(at!(0))
 		.or(left_prev_batch)
-		.as_ref()
-		.map(ToString::to_string);
+		.map(|count| {
+			services
+				.globals
+				.pagination_token(count, Direction::Backward)
+		});
 
 	let timeline_events = timeline_pdus
 		.into_iter()
@@ -865,18 +914,14 @@ async fn load_joined_room(
 		initial.then_async(|| services.lazy_loading.reset(lazy_loading_context));
 
 	lazy_load_reset.await;
-	let witness = lazy_loading_enabled.then_async(|| {
-		let witness: Witness = timeline_pdus
+	let witness = lazy_loading_enabled.then(|| -> Witness {
+		timeline_pdus
 			.iter()
 			.map(ref_at!(1))
 			.map(Event::sender)
 			.map(Into::into)
 			.chain(receipt_events.keys().map(Into::into))
-			.collect();
-
-		services
-			.lazy_loading
-			.witness_retain(witness, lazy_loading_context)
+			.collect()
 	});
 
 	let sender_joined_count = timeline_changed.then_async(|| {
@@ -906,11 +951,9 @@ async fn load_joined_room(
 	let (
 		(last_privateread_update, last_notification_read),
 		(sender_joined_count, since_encryption),
-		witness,
-	) = join3(
+	) = join(
 		join(last_privateread_update, last_notification_read),
 		join(sender_joined_count, since_encryption),
-		witness,
 	)
 	.await;
 
@@ -930,6 +973,7 @@ async fn load_joined_room(
 			current_shortstatehash,
 			joined_since_last_sync,
 			witness.as_ref(),
+			lazy_loading_context,
 		)
 	});
 
@@ -1004,16 +1048,16 @@ async fn load_joined_room(
 	let typing_events = services
 		.typing
 		.last_typing_update(room_id)
-		.and_then(async |count| {
-			if count <= since {
-				return Ok(Vec::<Raw<AnySyncEphemeralRoomEvent>>::new());
-			}
-
-			let typings = typings_event_for_user(services, room_id, sender_user).await?;
-
-			Ok(vec![serde_json::from_str(&serde_json::to_string(&typings)?)?])
-		})
-		.unwrap_or(Vec::new());
+		.gt(&since)
+		.then_async(|| typings_event_for_user(services, room_id, sender_user))
+		.map(|typings| {
+			typings
+				.and_then(Result::ok)
+				.and_then(|typings| serde_json::to_string(&typings).ok())
+				.and_then(|typings| serde_json::from_str(&typings).ok())
+				.map(|event: Raw<AnySyncEphemeralRoomEvent>| vec![event])
+				.unwrap_or_default()
+		});
 
 	let keys_changed = services
 		.users
@@ -1139,7 +1183,11 @@ async fn load_joined_room(
 		},
 		timeline: Timeline {
 			limited: limited || joined_since_last_sync,
-			prev_batch: prev_batch.as_ref().map(ToString::to_string),
+			prev_batch: prev_batch.map(|count| {
+				services
+					.globals
+					.pagination_token(count, Direction::Backward)
+			}),
 			events: room_events
 				.into_iter()
 				.map(Event::into_format)
@@ -1177,6 +1225,7 @@ async fn calculate_state_changes<'a>(
 	current_shortstatehash: ShortStateHash,
 	joined_since_last_sync: bool,
 	witness: Option<&'a Witness>,
+	lazy_loading_context: &lazy_loading::Context<'_>,
 ) -> Result<StateChanges> {
 	let incremental = !full_state && !joined_since_last_sync && since_shortstatehash.is_some();
 
@@ -1195,13 +1244,21 @@ async fn calculate_state_changes<'a>(
 			.ok()
 	};
 
-	let lazy_state_ids = witness.map_async(|witness| {
-		witness
+	// Memberships of the witnessed senders, less those already sent to the
+	// device unless they changed since.
+	let lazy_state_ids = witness.map_async(async |witness| {
+		let shorteventids = witness
 			.iter()
 			.stream()
 			.ready_filter(|&user_id| user_id != sender_user)
 			.broad_filter_map(|user_id| state_get_shorteventid(user_id))
-			.into_future()
+			.collect()
+			.await;
+
+		services
+			.lazy_loading
+			.retain_unsent(shorteventids, lazy_loading_context)
+			.await
 	});
 
 	let state_diff_ids = incremental.then_async(|| {
@@ -1227,7 +1284,12 @@ async fn calculate_state_changes<'a>(
 		.broad_filter_map(async |(shortstatekey, shorteventid)| {
 			lazy_filter(services, sender_user, witness, shortstatekey, shorteventid).await
 		})
-		.chain(lazy_state_ids.stream())
+		.chain(
+			lazy_state_ids
+				.map(Option::unwrap_or_default)
+				.map(IterStream::stream)
+				.flatten_stream(),
+		)
 		.broad_filter_map(|shorteventid| {
 			services
 				.timeline
@@ -1281,85 +1343,29 @@ async fn calculate_counts(
 	room_id: &RoomId,
 	sender_user: &UserId,
 ) -> (Option<u64>, Option<u64>, Option<Vec<OwnedUserId>>) {
-	let joined_member_count = services
-		.state_cache
-		.room_joined_count(room_id)
-		.unwrap_or(0);
-
-	let invited_member_count = services
-		.state_cache
-		.room_invited_count(room_id)
-		.unwrap_or(0);
-
-	let (joined_member_count, invited_member_count) =
-		join(joined_member_count, invited_member_count).await;
-
-	let small_room = joined_member_count.saturating_add(invited_member_count) <= 5;
-
-	let heroes = services
-		.config
-		.calculate_heroes
-		.and_is(small_room)
-		.then_async(|| calculate_heroes(services, room_id, sender_user));
-
-	(Some(joined_member_count), Some(invited_member_count), heroes.await)
-}
-
-async fn calculate_heroes(
-	services: &Services,
-	room_id: &RoomId,
-	sender_user: &UserId,
-) -> Vec<OwnedUserId> {
-	services
+	let summary = services
 		.state_accessor
-		.room_state_type_pdus(room_id, &StateEventType::RoomMember)
-		.ready_filter_map(Result::ok)
-		.fold_default(|heroes: Vec<_>, pdu| {
-			fold_hero(heroes, services, room_id, sender_user, pdu)
-		})
-		.await
-}
-
-async fn fold_hero<Pdu: Event>(
-	mut heroes: Vec<OwnedUserId>,
-	services: &Services,
-	room_id: &RoomId,
-	sender_user: &UserId,
-	pdu: Pdu,
-) -> Vec<OwnedUserId> {
-	let Some(user_id): Option<&UserId> = pdu.state_key().map(TryInto::try_into).flat_ok() else {
-		return heroes;
-	};
-
-	if user_id == sender_user {
-		return heroes;
-	}
-
-	let Ok(content): Result<RoomMemberEventContent, _> = pdu.get_content() else {
-		return heroes;
-	};
-
-	// The membership was and still is invite or join
-	if !matches!(content.membership, MembershipState::Join | MembershipState::Invite) {
-		return heroes;
-	}
+		.cached_summary(room_id)
+		.await;
 
-	if heroes.iter().any(is_equal_to!(user_id)) {
-		return heroes;
-	}
+	let heroes = services.config.calculate_heroes.then_async(|| {
+		services
+			.state_accessor
+			.room_summary(room_id, sender_user, true)
+			.map(|summary| {
+				summary
+					.heroes
+					.into_iter()
+					.map(|hero| hero.user_id)
+					.collect()
+			})
+	});
 
-	let (is_invited, is_joined) = join(
-		services.state_cache.is_invited(user_id, room_id),
-		services.state_cache.is_joined(user_id, room_id),
+	(
+		Some(summary.joined_member_count),
+		Some(summary.invited_member_count),
+		heroes.await,
 	)
-	.await;
-
-	if !is_joined && is_invited {
-		return heroes;
-	}
-
-	heroes.push(user_id.to_owned());
-	heroes
 }
 
 async fn typings_event_for_user(
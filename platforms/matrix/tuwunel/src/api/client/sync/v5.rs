@@ -12,7 +12,10 @@
 };
 use ruma::{
 	DeviceId, OwnedRoomId, UserId,
-	api::client::sync::sync_events::v5::{ListId, Request, Response, response},
+	api::{
+		Direction,
+		client::sync::sync_events::v5::{ListId, Request, Response, response},
+	},
 	events::room::member::MembershipState,
 };
 use tokio::time::{Instant, timeout_at};
@@ -78,14 +81,20 @@ pub(crate) async fn sync_events_v5_route(
 	State(ref services): State<crate::State>,
 	body: Ruma<Request>,
 ) -> Result<Response> {
+	let _timer = services.server.metrics.sync_latency.timer();
 	let sender_user = body.sender_user();
 	let sender_device = body.sender_device.as_deref();
 	let request = &body.body;
-	let since = request
+	let since: u64 = request
 		.pos
-		.as_ref()
-		.and_then(|string| string.parse().ok())
-		.unwrap_or(0);
+		.as_deref()
+		.map(|pos| {
+			services
+				.globals
+				.parse_pagination_token(pos, Direction::Forward)
+		})
+		.transpose()?
+		.map_or(0, |pos| pos.pos);
 
 	let timeout = request
 		.timeout
@@ -138,12 +147,6 @@ pub(crate) async fn sync_events_v5_route(
 		"Request should either be advancing or replaying the since token."
 	);
 
-	// Update parameters regardless of replay or advance
-	conn.next_batch = services.globals.wait_pending().await?;
-	conn.globalsince = since.min(conn.next_batch);
-	conn.update_cache(request);
-	conn.update_rooms_prologue(retarding.then_some(since));
-
 	let mut response = Response {
 		txn_id: request.txn_id.clone(),
 		lists: Default::default(),
@@ -156,6 +159,40 @@ pub(crate) async fn sync_events_v5_route(
 		.checked_add(Duration::from_millis(timeout))
 		.expect("configuration must limit maximum timeout");
 
+	// Update parameters regardless of replay or advance
+	let params_changed = conn.update_cache(
+		request,
+		services.config.sync_max_room_subscriptions,
+		services.config.sync_subscription_expiry_requests,
+	)?;
+
+	// Nothing synced to the connection changed since its last response, whose
+	// lists and rooms therefore still stand; wait for a change without loading
+	// any rooms.
+	if let Some(watcher) = conn
+		.watcher
+		.clone()
+		.filter(|watcher| advancing && !params_changed && !watcher.changed())
+		&& (timeout == 0
+			|| services.server.is_stopping()
+			|| timeout_at(stop_at, services.sync.wait(watcher))
+				.boxed()
+				.await
+				.is_err())
+	{
+		response.pos = services
+			.globals
+			.pagination_token(conn.next_batch, Direction::Forward)
+			.into();
+		trace!(conn.next_batch, "unchanged; empty response {response:?}");
+		conn.store(&services.sync, &conn_key);
+		return Ok(response);
+	}
+
+	conn.next_batch = services.globals.wait_pending().await?;
+	conn.globalsince = since.min(conn.next_batch);
+	conn.update_rooms_prologue(retarding.then_some(since));
+
 	let sync_info = SyncInfo { services, sender_user, sender_device };
 	loop {
 		debug_assert!(
@@ -164,12 +201,12 @@ pub(crate) async fn sync_events_v5_route(
 		);
 
 		let window;
-		let watchers = services.sync.watch(
-			sender_user,
-			sender_device,
-			services.state_cache.rooms_joined(sender_user),
-		);
+		let watcher = services
+			.sync
+			.watcher(sender_user, sender_device, services.state_cache.rooms_joined(sender_user))
+			.await;
 
+		conn.watcher = Some(watcher.clone());
 		conn.next_batch = services.globals.wait_pending().await?;
 		(window, response.lists) = selector::selector(&mut conn, sync_info)
 			.boxed()
@@ -187,7 +224,10 @@ pub(crate) async fn sync_events_v5_route(
 			conn.update_rooms_epilogue(window.keys().map(AsRef::as_ref));
 
 			if !is_empty_response(&response) {
-				response.pos = conn.next_batch.to_string().into();
+				response.pos = services
+					.globals
+					.pagination_token(conn.next_batch, Direction::Forward)
+					.into();
 				trace!(conn.globalsince, conn.next_batch, "response {response:?}");
 				conn.store(&services.sync, &conn_key);
 				return Ok(response);
@@ -196,12 +236,15 @@ pub(crate) async fn sync_events_v5_route(
 
 		if timeout == 0
 			|| services.server.is_stopping()
-			|| timeout_at(stop_at, watchers)
+			|| timeout_at(stop_at, services.sync.wait(watcher))
 				.boxed()
 				.await
 				.is_err()
 		{
-			response.pos = conn.next_batch.to_string().into();
+			response.pos = services
+				.globals
+				.pagination_token(conn.next_batch, Direction::Forward)
+				.into();
 			trace!(conn.globalsince, conn.next_batch, "timeout; empty response {response:?}");
 			conn.store(&services.sync, &conn_key);
 			return Ok(response);
@@ -4,14 +4,17 @@
 mod to_device;
 mod typing;
 
-use std::fmt::Debug;
+use std::{collections::BTreeSet, fmt::Debug};
 
-use futures::{FutureExt, future::join5};
+use futures::{FutureExt, StreamExt, future::join5};
 use ruma::{
 	RoomId,
 	api::client::sync::sync_events::v5::{ListId, request::ExtensionRoomConfig, response},
 };
-use tuwunel_core::{Result, apply, at, extract_variant, utils::BoolExt};
+use tuwunel_core::{
+	Result, apply, at, extract_variant,
+	utils::{BoolExt, IterStream},
+};
 use tuwunel_service::sync::Connection;
 
 use super::{SyncInfo, Window, share_encrypted_room};
@@ -140,3 +143,23 @@ fn selector<'a, ListIter, SubsIter>(
 		.chain(rooms_explicit)
 		.chain(rooms_selected)
 }
+
+/// Joined rooms of a reloaded connection, to be replayed by the receipts and
+/// typing extensions in case the client missed updates to them while the
+/// connection was not loaded.
+async fn replay_rooms<'a>(
+	SyncInfo { services, sender_user, .. }: SyncInfo<'a>,
+	conn: &'a Connection,
+) -> BTreeSet<&'a RoomId> {
+	conn.replay_rooms()
+		.stream()
+		.filter_map(async |room_id| {
+			services
+				.state_cache
+				.is_joined(sender_user, room_id)
+				.await
+				.then_some(room_id)
+		})
+		.collect()
+		.await
+}
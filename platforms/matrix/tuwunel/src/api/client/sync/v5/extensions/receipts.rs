@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use futures::{FutureExt, StreamExt};
 use ruma::{
 	OwnedRoomId, RoomId,
@@ -11,7 +13,7 @@
 };
 use tuwunel_service::{rooms::read_receipt::pack_receipts, sync::Room};
 
-use super::{Connection, SyncInfo, Window, selector};
+use super::{Connection, SyncInfo, Window, replay_rooms, selector};
 
 #[tracing::instrument(name = "receipts", level = "trace", skip_all)]
 pub(super) async fn collect(
@@ -35,7 +37,14 @@ pub(super) async fn collect(
 		.as_deref()
 		.map(<[_]>::iter);
 
+	// Rooms of a reloaded connection are replayed from their last sync, in
+	// case the client missed receipts while the connection was not loaded.
+	let replay = replay_rooms(sync_info, conn).await;
+
 	let rooms = selector(sync_info, conn, window, implicit, explicit)
+		.chain(replay)
+		.collect::<BTreeSet<_>>()
+		.into_iter()
 		.stream()
 		.broad_filter_map(|room_id| collect_room(sync_info, conn, window, room_id))
 		.collect()
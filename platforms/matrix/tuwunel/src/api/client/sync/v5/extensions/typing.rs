@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use futures::{FutureExt, StreamExt, TryFutureExt};
 use ruma::{
@@ -11,7 +11,7 @@
 	utils::{IterStream, ReadyExt},
 };
 
-use super::{Connection, SyncInfo, Window, selector};
+use super::{Connection, SyncInfo, Window, replay_rooms, selector};
 
 #[tracing::instrument(name = "typing", level = "trace", skip_all, ret)]
 pub(super) async fn collect(
@@ -37,7 +37,15 @@ pub(super) async fn collect(
 		.as_deref()
 		.map(<[_]>::iter);
 
+	// Rooms of a reloaded connection are sent even when nobody is typing, so
+	// that the client clears whoever it last saw typing before the connection
+	// was unloaded.
+	let replay = replay_rooms(sync_info, conn).await;
+
 	selector(sync_info, conn, window, implicit, explicit)
+		.chain(replay.iter().copied())
+		.collect::<BTreeSet<_>>()
+		.into_iter()
 		.stream()
 		.filter_map(async |room_id| {
 			services
@@ -46,7 +54,7 @@ pub(super) async fn collect(
 				.inspect_err(|e| debug_error!(%room_id, "Failed to get typing events: {e}"))
 				.await
 				.ok()
-				.filter(|users| !users.is_empty())
+				.filter(|users| !users.is_empty() || replay.contains(room_id))
 				.map(|users| (room_id, users))
 		})
 		.ready_filter_map(|(room_id, users)| {
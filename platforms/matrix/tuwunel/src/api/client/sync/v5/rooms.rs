@@ -1,17 +1,17 @@
-use std::{
-	cmp::Ordering,
-	collections::{BTreeMap, HashSet},
-};
+use std::collections::{BTreeMap, HashSet};
 
 use futures::{
 	FutureExt, StreamExt, TryFutureExt, TryStreamExt,
 	future::{join, join3, join4},
 };
 use ruma::{
-	JsOption, MxcUri, OwnedMxcUri, OwnedRoomId, RoomId, UserId,
-	api::client::sync::sync_events::{
-		UnreadNotificationsCount,
-		v5::{DisplayName, response, response::Heroes},
+	JsOption, OwnedRoomId,
+	api::{
+		Direction,
+		client::sync::sync_events::{
+			UnreadNotificationsCount,
+			v5::{response, response::Heroes},
+		},
 	},
 	events::{
 		StateEventType,
@@ -30,7 +30,7 @@
 		stream::BroadbandExt,
 	},
 };
-use tuwunel_service::{Services, sync::Room};
+use tuwunel_service::sync::Room;
 
 use super::{super::load_timeline, Connection, SyncInfo, Window, WindowRoom};
 use crate::client::ignored_filter;
@@ -100,7 +100,12 @@ async fn handle_room(
 			initial: roomsince.eq(&0).then_some(true),
 			lists: lists.clone(),
 			membership: membership.clone(),
-			prev_batch: Some(conn.next_batch.to_string().into()),
+			prev_batch: Some(
+				services
+					.globals
+					.pagination_token(conn.next_batch, Direction::Backward)
+					.into(),
+			),
 			limited: true,
 			required_state: vec![
 				services
@@ -155,8 +160,11 @@ async fn handle_room(
 		.first()
 		.map(at!(0))
 		.map(PduCount::into_unsigned)
-		.as_ref()
-		.map(ToString::to_string);
+		.map(|count| {
+			services
+				.globals
+				.pagination_token(count, Direction::Backward)
+		});
 
 	let bump_stamp = timeline_pdus
 		.iter()
@@ -251,18 +259,11 @@ async fn handle_room(
 			.ok()
 	});
 
-	let room_name = services
-		.state_accessor
-		.get_name(room_id)
-		.map_ok(Into::into)
-		.map(Result::ok);
-
-	let room_avatar = services
-		.state_accessor
-		.get_avatar(room_id)
-		.map_ok(|content| content.url)
-		.ok()
-		.map(Option::flatten);
+	let summary = services.state_accessor.room_summary(
+		room_id,
+		sender_user,
+		services.config.calculate_heroes,
+	);
 
 	let highlight_count = services
 		.pusher
@@ -307,12 +308,12 @@ async fn handle_room(
 		.map(Event::into_format)
 		.collect();
 
-	let meta = join3(room_name, room_avatar, is_dm);
+	let meta = join(summary, is_dm);
 	let events = join4(timeline, num_live, required_state, invite_state);
 	let member_counts = join(joined_count, invited_count);
 	let notification_counts = join3(highlight_count, notification_count, last_read_count);
 	let (
-		(room_name, room_avatar, is_dm),
+		(summary, is_dm),
 		(timeline, num_live, required_state, invite_state),
 		(joined_count, invited_count),
 		(highlight_count, notification_count, _last_notification_read),
@@ -320,31 +321,24 @@ async fn handle_room(
 		.boxed()
 		.await;
 
-	let heroes = services
-		.config
-		.calculate_heroes
-		.then_async(|| {
-			calculate_heroes(
-				services,
-				sender_user,
-				room_id,
-				room_name.as_ref(),
-				room_avatar.as_deref(),
-			)
+	let heroes: Heroes = summary
+		.heroes
+		.into_iter()
+		.map(|hero| response::Hero {
+			user_id: hero.user_id,
+			name: hero.name.map(Into::into),
+			avatar: hero.avatar,
 		})
-		.await
-		.unwrap_or_default();
-
-	let (heroes, heroes_name, heroes_avatar) = heroes;
+		.collect();
 
 	Ok(response::Room {
 		initial: roomsince.eq(&0).then_some(true),
 		lists: lists.clone(),
 		membership: membership.clone(),
-		name: room_name.or(heroes_name),
-		avatar: JsOption::from_option(room_avatar.or(heroes_avatar)),
+		name: summary.name.map(Into::into),
+		avatar: JsOption::from_option(summary.avatar),
 		is_dm,
-		heroes,
+		heroes: (!heroes.is_empty()).then_some(heroes),
 		required_state,
 		invite_state: invite_state.flatten(),
 		prev_batch: prev_batch.as_deref().map(Into::into),
@@ -357,90 +351,3 @@ async fn handle_room(
 		unread_notifications: UnreadNotificationsCount { highlight_count, notification_count },
 	})
 }
-
-#[tracing::instrument(name = "heroes", level = "trace", skip_all)]
-async fn calculate_heroes(
-	services: &Services,
-	sender_user: &UserId,
-	room_id: &RoomId,
-	room_name: Option<&DisplayName>,
-	room_avatar: Option<&MxcUri>,
-) -> (Option<Heroes>, Option<DisplayName>, Option<OwnedMxcUri>) {
-	const MAX_HEROES: usize = 5;
-
-	let heroes: Heroes = services
-		.state_cache
-		.room_members(room_id)
-		.ready_filter(|&member| member != sender_user)
-		.ready_filter_map(|member| room_name.is_none().then_some(member))
-		.map(ToOwned::to_owned)
-		.broadn_filter_map(MAX_HEROES, async |user_id| {
-			let content = services
-				.state_accessor
-				.get_member(room_id, &user_id)
-				.await
-				.ok()?;
-
-			let name = content
-				.displayname
-				.is_none()
-				.then_async(|| services.users.displayname(&user_id).ok());
-
-			let avatar = content
-				.avatar_url
-				.is_none()
-				.then_async(|| services.users.avatar_url(&user_id).ok());
-
-			let (name, avatar) = join(name, avatar).await;
-			let hero = response::Hero {
-				user_id,
-				avatar: avatar.unwrap_or(content.avatar_url),
-				name: name
-					.unwrap_or(content.displayname)
-					.map(Into::into),
-			};
-
-			Some(hero)
-		})
-		.take(MAX_HEROES)
-		.collect()
-		.await;
-
-	let hero_name = match heroes.len().cmp(&(1_usize)) {
-		| Ordering::Less => None,
-		| Ordering::Equal => Some(
-			heroes[0]
-				.name
-				.clone()
-				.unwrap_or_else(|| heroes[0].user_id.as_str().into()),
-		),
-		| Ordering::Greater => {
-			let firsts = heroes[1..]
-				.iter()
-				.map(|h| {
-					h.name
-						.clone()
-						.unwrap_or_else(|| h.user_id.as_str().into())
-				})
-				.collect::<Vec<_>>()
-				.join(", ");
-
-			let last = heroes[0]
-				.name
-				.clone()
-				.unwrap_or_else(|| heroes[0].user_id.as_str().into());
-
-			Some(format!("{firsts} and {last}")).map(Into::into)
-		},
-	};
-
-	let heroes_avatar = (room_avatar.is_none() && room_name.is_none())
-		.then(|| {
-			heroes
-				.first()
-				.and_then(|hero| hero.avatar.clone())
-		})
-		.flatten();
-
-	(Some(heroes), hero_name, heroes_avatar)
-}
@@ -1,6 +1,9 @@
 use axum::extract::State;
 use futures::{StreamExt, TryStreamExt};
-use ruma::{api::client::threads::get_threads, uint};
+use ruma::{
+	api::{Direction, client::threads::get_threads},
+	uint,
+};
 use tuwunel_core::{
 	Result, at,
 	matrix::{
@@ -27,9 +30,13 @@ pub(crate) async fn get_threads_route(
 	let from: PduCount = body
 		.from
 		.as_deref()
-		.map(str::parse)
+		.map(|from| {
+			services
+				.globals
+				.parse_pagination_token(from, Direction::Backward)
+		})
 		.transpose()?
-		.unwrap_or_else(PduCount::max);
+		.map_or_else(PduCount::max, |from| from.pos);
 
 	let threads: Vec<(PduCount, PduEvent)> = services
 		.threads
@@ -50,8 +57,11 @@ pub(crate) async fn get_threads_route(
 			.last()
 			.filter(|_| threads.len() >= limit)
 			.map(at!(0))
-			.as_ref()
-			.map(ToString::to_string),
+			.map(|count| {
+				services
+					.globals
+					.pagination_token(count, Direction::Backward)
+			}),
 
 		chunk: threads
 			.into_iter()
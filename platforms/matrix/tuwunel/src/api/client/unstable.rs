@@ -131,37 +131,30 @@ pub(crate) async fn set_profile_field_route(
 
 	match &body.value {
 		| ProfileFieldValue::DisplayName(displayname) => {
-			let all_joined_rooms: Vec<OwnedRoomId> = services
-				.state_cache
-				.rooms_joined(&body.user_id)
-				.map(Into::into)
-				.collect()
-				.await;
+			let profile_rooms = services.users.profile_rooms(&body.user_id).await;
 
 			services
 				.users
-				.update_displayname(&body.user_id, Some(displayname), &all_joined_rooms)
-				.await;
+				.update_displayname(&body.user_id, Some(displayname), &profile_rooms)
+				.await?;
 		},
 		| ProfileFieldValue::AvatarUrl(avatar_url) => {
-			let all_joined_rooms: Vec<OwnedRoomId> = services
-				.state_cache
-				.rooms_joined(&body.user_id)
-				.map(Into::into)
-				.collect()
-				.await;
+			let profile_rooms = services.users.profile_rooms(&body.user_id).await;
 
 			services
 				.users
-				.update_avatar_url(&body.user_id, Some(avatar_url), None, &all_joined_rooms)
+				.update_avatar_url(&body.user_id, Some(avatar_url), None, &profile_rooms)
 				.await;
 		},
 		| _ => {
-			services.users.set_profile_key(
-				&body.user_id,
-				body.value.field_name().as_str(),
-				Some(&body.value.value()),
-			);
+			services
+				.users
+				.update_profile_key(
+					&body.user_id,
+					body.value.field_name().as_str(),
+					Some(&body.value.value()),
+				)
+				.await?;
 		},
 	}
 
@@ -191,35 +184,26 @@ pub(crate) async fn delete_profile_field_route(
 
 	match body.field {
 		| ProfileFieldName::DisplayName => {
-			let all_joined_rooms: Vec<OwnedRoomId> = services
-				.state_cache
-				.rooms_joined(&body.user_id)
-				.map(Into::into)
-				.collect()
-				.await;
+			let profile_rooms = services.users.profile_rooms(&body.user_id).await;
 
 			services
 				.users
-				.update_displayname(&body.user_id, None, &all_joined_rooms)
-				.await;
+				.update_displayname(&body.user_id, None, &profile_rooms)
+				.await?;
 		},
 		| ProfileFieldName::AvatarUrl => {
-			let all_joined_rooms: Vec<OwnedRoomId> = services
-				.state_cache
-				.rooms_joined(&body.user_id)
-				.map(Into::into)
-				.collect()
-				.await;
+			let profile_rooms = services.users.profile_rooms(&body.user_id).await;
 
 			services
 				.users
-				.update_avatar_url(&body.user_id, None, None, &all_joined_rooms)
+				.update_avatar_url(&body.user_id, None, None, &profile_rooms)
 				.await;
 		},
 		| _ => {
 			services
 				.users
-				.set_profile_key(&body.user_id, body.field.as_str(), None);
+				.update_profile_key(&body.user_id, body.field.as_str(), None)
+				.await?;
 		},
 	}
 
@@ -20,9 +20,10 @@
 /// Note: Unstable features are used while developing new features. Clients
 /// should avoid using unstable features in their stable releases
 pub(crate) async fn get_supported_versions_route(
+	State(services): State<crate::State>,
 	_body: Ruma<get_supported_versions::Request>,
 ) -> Result<get_supported_versions::Response> {
-	let resp = get_supported_versions::Response {
+	let mut resp = get_supported_versions::Response {
 		versions: vec![
 			"r0.0.1".to_owned(),
 			"r0.1.0".to_owned(),
@@ -63,6 +64,19 @@ pub(crate) async fn get_supported_versions_route(
 		]),
 	};
 
+	if services.oauth.delegated() {
+		resp.unstable_features.extend([
+			("org.matrix.msc2965".to_owned(), true), /* OIDC-aware clients (https://github.com/matrix-org/matrix-spec-proposals/pull/2965) */
+			("org.matrix.msc3861".to_owned(), true), /* delegated OIDC authentication (https://github.com/matrix-org/matrix-spec-proposals/pull/3861) */
+		]);
+	}
+
+	if services.rendezvous.enabled() {
+		resp.unstable_features.extend([
+			("org.matrix.msc4108".to_owned(), true), /* sign in with QR code (https://github.com/matrix-org/matrix-spec-proposals/pull/4108) */
+		]);
+	}
+
 	Ok(resp)
 }
 
@@ -1,10 +1,14 @@
 use axum::{Json, extract::State, response::IntoResponse};
-use ruma::api::client::discovery::{
-	discover_homeserver::{self, HomeserverInfo, RtcFocusInfo},
-	discover_support::{self, Contact},
+use ruma::api::{
+	OutgoingResponse,
+	client::discovery::{
+		discover_homeserver::{self, HomeserverInfo, RtcFocusInfo},
+		discover_support::{self, Contact},
+	},
 };
-use serde_json::Value as JsonValue;
+use serde_json::{Value as JsonValue, json};
 use tuwunel_core::{Err, Result, err, error::inspect_log};
+use tuwunel_service::Services;
 
 use crate::Ruma;
 
@@ -16,6 +20,74 @@ pub(crate) async fn well_known_client(
 	State(services): State<crate::State>,
 	_body: Ruma<discover_homeserver::Request>,
 ) -> Result<discover_homeserver::Response> {
+	client_discovery(&services)
+}
+
+/// # `GET /.well-known/matrix/client`
+///
+/// In place of `well_known_client` when authentication is delegated; adds the
+/// issuer and account management URL of the provider (MSC2965).
+pub(crate) async fn well_known_client_delegated(
+	State(services): State<crate::State>,
+) -> Result<impl IntoResponse> {
+	let response = client_discovery(&services)?
+		.try_into_http_response::<Vec<u8>>()
+		.map_err(|e| err!("Failed to serialize discovery: {e}"))?;
+
+	let mut discovery: JsonValue = serde_json::from_slice(response.body())?;
+	discovery["org.matrix.msc2965.authentication"] = delegated_authentication(&services).await?;
+
+	Ok(Json(discovery))
+}
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc2965/auth_issuer`
+///
+/// The issuer of the provider authentication is delegated to (MSC2965).
+pub(crate) async fn auth_issuer_route(
+	State(services): State<crate::State>,
+) -> Result<impl IntoResponse> {
+	let provider = services.oauth.delegated_provider().await?;
+
+	Ok(Json(json!({ "issuer": provider.issuer_url })))
+}
+
+/// # `GET /_matrix/client/v1/auth_metadata`
+///
+/// The OpenID Connect discovery document of the provider authentication is
+/// delegated to (MSC2965).
+pub(crate) async fn auth_metadata_route(
+	State(services): State<crate::State>,
+) -> Result<impl IntoResponse> {
+	let provider = services.oauth.delegated_provider().await?;
+	let mut metadata = services
+		.oauth
+		.providers
+		.discover(&provider)
+		.await?;
+
+	// Advertise the account management page of the provider (MSC4191) unless
+	// the provider already does.
+	if let Some(url) = &services.config.auth_delegation_account_url
+		&& let Some(metadata) = metadata.as_object_mut()
+	{
+		metadata
+			.entry("account_management_uri")
+			.or_insert_with(|| json!(url));
+	}
+
+	Ok(Json(metadata))
+}
+
+async fn delegated_authentication(services: &Services) -> Result<JsonValue> {
+	let provider = services.oauth.delegated_provider().await?;
+
+	Ok(json!({
+		"issuer": provider.issuer_url,
+		"account": services.config.auth_delegation_account_url,
+	}))
+}
+
+fn client_discovery(services: &Services) -> Result<discover_homeserver::Response> {
 	let homeserver = HomeserverInfo {
 		base_url: match services.server.config.well_known.client.as_ref() {
 			| Some(url) => url.to_string(),
@@ -1,24 +1,33 @@
 mod args;
 mod auth;
+mod disabled;
 mod handler;
+mod media;
+mod ratelimit;
 mod request;
 mod response;
 pub mod state;
+mod synapse_admin;
 
 use std::str::FromStr;
 
 use axum::{
 	Router,
 	response::{IntoResponse, Redirect},
-	routing::{any, get, post},
+	routing::{any, delete, get, post, put},
 };
 use http::{Uri, uri};
 use tuwunel_core::{Server, err};
 
 use self::handler::RouterExt;
 pub(super) use self::{
-	args::Args as Ruma, auth::auth_uiaa, response::RumaResponse, state::State,
+	args::{Args as Ruma, Sender, Signed},
+	auth::{auth_cross_signing_reset, auth_uiaa},
+	response::RumaResponse,
+	state::State,
+	synapse_admin::AdminUser,
 };
+pub use self::{disabled::disabled_endpoints, media::media_stream, ratelimit::ratelimit};
 use crate::{client, server};
 
 pub fn build(router: Router<State>, server: &Server) -> Router<State> {
@@ -34,6 +43,7 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_supported_versions_route)
 		.ruma_route(&client::get_register_available_route)
 		.ruma_route(&client::register_route)
+		.ruma_route(&client::request_registration_token_via_email_route)
 		.ruma_route(&client::get_login_types_route)
 		.ruma_route(&client::login_route)
 		.ruma_route(&client::login_token_route)
@@ -41,14 +51,23 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::sso_login_route)
 		.ruma_route(&client::sso_login_with_provider_route)
 		.ruma_route(&client::sso_callback_route)
+		.route(
+			"/_tuwunel/cross_signing_reset/approved",
+			get(client::cross_signing_reset_approved_route),
+		)
+		.route("/_tuwunel/cross_signing_reset/{token}", get(client::cross_signing_reset_route))
 		.ruma_route(&client::whoami_route)
 		.ruma_route(&client::logout_route)
 		.ruma_route(&client::logout_all_route)
 		.ruma_route(&client::change_password_route)
+		.ruma_route(&client::request_password_change_token_via_email_route)
 		.ruma_route(&client::deactivate_route)
 		.ruma_route(&client::third_party_route)
+		.ruma_route(&client::add_3pid_route)
+		.ruma_route(&client::delete_3pid_route)
 		.ruma_route(&client::request_3pid_management_token_via_email_route)
 		.ruma_route(&client::request_3pid_management_token_via_msisdn_route)
+		.route("/_tuwunel/email/validate", get(client::validate_email_route))
 		.ruma_route(&client::check_registration_token_validity)
 		.ruma_route(&client::get_notifications_route)
 		.ruma_route(&client::get_capabilities_route)
@@ -193,8 +212,41 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			get(client::get_room_summary_legacy)
 		)
 		.ruma_route(&client::well_known_support)
-		.ruma_route(&client::well_known_client)
 		.route("/_tuwunel/server_version", get(client::tuwunel_server_version))
+		.route(
+			"/_matrix/client/unstable/org.matrix.msc4108/rendezvous",
+			post(client::create_rendezvous_route)
+		)
+		.route(
+			"/_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}",
+			get(client::get_rendezvous_route)
+				.put(client::update_rendezvous_route)
+				.delete(client::delete_rendezvous_route)
+		)
+		.route(
+			"/_tuwunel/client/v1/rooms/{room_id}/share_links",
+			get(client::get_share_links_route).post(client::create_share_link_route)
+		)
+		.route(
+			"/_tuwunel/client/v1/rooms/{room_id}/share_links/{id}",
+			delete(client::revoke_share_link_route)
+		)
+		.route(
+			"/_tuwunel/client/v1/rooms/{room_id}/state_filtered",
+			post(client::get_state_filtered_route)
+		)
+		.route(
+			"/_tuwunel/client/v1/share_links/{token}/join",
+			post(client::join_share_link_route)
+		)
+		.route(
+			"/_tuwunel/share_links/{token}/invite",
+			post(client::invite_share_link_route)
+		)
+		.route(
+			"/_tuwunel/appservice/v1/device_lists",
+			get(client::get_appservice_device_lists_route)
+		)
 		.ruma_route(&client::room_initial_sync_route)
 		.route("/client/server.json", get(client::syncv3_client_server_json));
 
@@ -245,6 +297,53 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			.route("/_tuwunel/local_user_count", any(federation_disabled));
 	}
 
+	if config.auth_delegation_provider.is_some() {
+		router = router
+			.route("/.well-known/matrix/client", get(client::well_known_client_delegated))
+			.route(
+				"/_matrix/client/unstable/org.matrix.msc2965/auth_issuer",
+				get(client::auth_issuer_route),
+			)
+			.route(
+				"/_matrix/client/unstable/org.matrix.msc2965/auth_metadata",
+				get(client::auth_metadata_route),
+			)
+			.route("/_matrix/client/v1/auth_metadata", get(client::auth_metadata_route));
+	} else {
+		router = router.ruma_route(&client::well_known_client);
+	}
+
+	if config.metrics_endpoint {
+		router = router.route("/metrics", get(client::get_metrics_route));
+	}
+
+	if config.synapse_admin_api {
+		router = router
+			.route("/_synapse/admin/v2/users", get(client::synapse_list_users_route))
+			.route("/_synapse/admin/v2/users/{user_id}", get(client::synapse_get_user_route))
+			.route(
+				"/_synapse/admin/v1/deactivate/{user_id}",
+				post(client::synapse_deactivate_user_route),
+			)
+			.route("/_synapse/admin/v1/rooms", get(client::synapse_list_rooms_route))
+			.route(
+				"/_synapse/admin/v1/rooms/{room_id}",
+				get(client::synapse_get_room_route).delete(client::synapse_delete_room_route),
+			)
+			.route(
+				"/_synapse/admin/v1/media/quarantine/{server_name}/{media_id}",
+				post(client::synapse_quarantine_media_route),
+			)
+			.route(
+				"/_synapse/admin/v1/media/unquarantine/{server_name}/{media_id}",
+				post(client::synapse_unquarantine_media_route),
+			)
+			.route(
+				"/_synapse/admin/v1/user/{user_id}/media/quarantine",
+				post(client::synapse_quarantine_user_media_route),
+			);
+	}
+
 	if config.allow_legacy_media {
 		router = router
 			.ruma_route(&client::get_media_config_legacy_route)
@@ -1,12 +1,17 @@
 use std::{fmt::Debug, mem, ops::Deref};
 
-use axum::{body::Body, extract::FromRequest};
+use axum::{
+	body::Body,
+	extract::{FromRequest, FromRequestParts},
+};
 use axum_extra::extract::cookie::CookieJar;
 use bytes::{BufMut, Bytes, BytesMut};
+use http::request::Parts;
 use ruma::{
 	CanonicalJsonObject, CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedServerName,
 	OwnedUserId, ServerName, UserId, api::IncomingRequest,
 };
+use serde::de::DeserializeOwned;
 use tuwunel_core::{Error, Result, debug_warn, err, trace, utils::string::EMPTY};
 use tuwunel_service::{Services, appservice::RegistrationInfo};
 
@@ -38,6 +43,10 @@ pub(crate) struct Args<T> {
 	/// None when not an appservice.
 	pub(crate) appservice_info: Option<RegistrationInfo>,
 
+	/// Local user authentication by an access token of the provider
+	/// authentication is delegated to.
+	pub(crate) delegated: bool,
+
 	/// Parsed JSON content.
 	/// None when body is not a valid string
 	pub(crate) json_body: Option<CanonicalJsonValue>,
@@ -119,11 +128,85 @@ async fn from_request(
 			sender_user: auth.sender_user,
 			sender_device: auth.sender_device,
 			appservice_info: auth.appservice_info,
+			delegated: auth.delegated,
 			json_body,
 		})
 	}
 }
 
+/// Extractor authenticating the sender of a request to a route outside of the
+/// ruma endpoint definitions, as `Args` does for endpoints requiring an access
+/// token.
+#[derive(Debug)]
+pub(crate) struct Sender {
+	/// Local user authentication: user_id; for appservices, the user it acts
+	/// as.
+	pub(crate) user: OwnedUserId,
+
+	/// Local user authentication: device_id.
+	/// None when an appservice.
+	pub(crate) device: Option<OwnedDeviceId>,
+
+	/// Appservice authentication; registration info.
+	/// None when not an appservice.
+	pub(crate) appservice_info: Option<RegistrationInfo>,
+}
+
+impl FromRequestParts<State> for Sender {
+	type Rejection = Error;
+
+	async fn from_request_parts(parts: &mut Parts, services: &State) -> Result<Self> {
+		let auth = auth::auth_user(services, parts).await?;
+
+		Ok(Self {
+			user: auth
+				.sender_user
+				.expect("user must be authenticated by auth_user"),
+			device: auth.sender_device,
+			appservice_info: auth.appservice_info,
+		})
+	}
+}
+
+/// Extractor for the JSON body of a request to a route outside of the ruma
+/// endpoint definitions made by another server, authenticated by its X-Matrix
+/// signatures.
+#[derive(Debug)]
+pub(crate) struct Signed<T> {
+	/// Request body
+	pub(crate) body: T,
+
+	/// Federation server authentication: X-Matrix origin
+	pub(crate) origin: OwnedServerName,
+}
+
+impl<T> FromRequest<State, Body> for Signed<T>
+where
+	T: DeserializeOwned,
+{
+	type Rejection = Error;
+
+	async fn from_request(
+		request: hyper::Request<Body>,
+		services: &State,
+	) -> Result<Self, Self::Rejection> {
+		let mut request = request::from(services, request).await?;
+		let json_body = serde_json::from_slice::<CanonicalJsonValue>(&request.body)
+			.map_err(|e| err!(Request(NotJson("Failed to parse body: {e}"))))?;
+
+		let auth = auth::auth_server(services, &mut request, Some(&json_body)).await?;
+		let body = serde_json::from_slice(&request.body)
+			.map_err(|e| err!(Request(BadJson(debug_warn!("{e}")))))?;
+
+		Ok(Self {
+			body,
+			origin: auth
+				.origin
+				.expect("server must be authenticated by auth_server"),
+		})
+	}
+}
+
 fn make_body<T>(
 	services: &Services,
 	request: &mut Request,
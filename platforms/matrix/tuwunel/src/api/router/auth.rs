@@ -5,6 +5,7 @@
 use std::{fmt::Debug, time::SystemTime};
 
 use axum::RequestPartsExt;
+use axum_client_ip::InsecureClientIp;
 use axum_extra::{
 	TypedHeader,
 	headers::{Authorization, authorization::Bearer},
@@ -17,30 +18,46 @@
 	},
 	pin_mut,
 };
+use http::{Method, header::USER_AGENT, request::Parts};
 use ruma::{
-	CanonicalJsonValue, OwnedDeviceId, OwnedServerName, OwnedUserId,
+	CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedServerName, OwnedUserId, UserId,
 	api::{
 		AuthScheme, IncomingRequest, Metadata,
 		client::{
-			directory::get_public_rooms,
+			account::{change_password, deactivate},
+			device::{delete_device, delete_devices},
+			directory::{get_public_rooms, get_public_rooms_filtered},
 			error::ErrorKind,
+			filter::create_filter,
+			keys::{claim_keys, get_keys, upload_keys},
+			membership::{forget_room, leave_room},
 			profile::{
 				get_avatar_url, get_display_name, get_profile, get_profile_field,
 				get_timezone_key,
 			},
+			read_marker::set_read_marker,
+			receipt::create_receipt,
+			redact::redact_event,
+			search::search_events,
+			session::{logout, logout_all},
+			user_directory::search_users,
 			voip::get_turn_server_info,
 		},
 		federation::openid::get_openid_userinfo,
 	},
 };
-use tuwunel_core::{Err, Error, Result, is_less_than, utils::result::LogDebugErr};
+use tuwunel_core::{Err, Error, Result, err, is_less_than};
 use tuwunel_service::{Services, appservice::RegistrationInfo};
 
-pub(crate) use self::uiaa::auth_uiaa;
-use self::{appservice::auth_appservice, server::auth_server};
-use super::request::Request;
+use self::appservice::auth_appservice;
+pub(super) use self::server::auth_server;
+pub(crate) use self::uiaa::{auth_cross_signing_reset, auth_uiaa};
+use super::{
+	ratelimit,
+	request::{QueryParams, Request},
+};
 
-enum Token {
+pub(super) enum Token {
 	Appservice(Box<RegistrationInfo>),
 	User((OwnedUserId, OwnedDeviceId, Option<SystemTime>)),
 	Expired((OwnedUserId, OwnedDeviceId)),
@@ -54,6 +71,7 @@ pub(super) struct Auth {
 	pub(super) sender_user: Option<OwnedUserId>,
 	pub(super) sender_device: Option<OwnedDeviceId>,
 	pub(super) appservice_info: Option<RegistrationInfo>,
+	pub(super) delegated: bool,
 	pub(super) _expires_at: Option<SystemTime>,
 }
 
@@ -77,27 +95,13 @@ pub(super) async fn auth(
 	use ErrorKind::UnknownToken;
 	use Token::{Appservice, Expired, Invalid, User};
 
-	let bearer: Option<TypedHeader<Authorization<Bearer>>> =
-		request.parts.extract().await.unwrap_or(None);
-
-	let token = match &bearer {
-		| Some(TypedHeader(Authorization(bearer))) => Some(bearer.token()),
-		| None => request.query.access_token.as_deref(),
-	};
-
-	let token = match find_token(services, token).await? {
-		| User((user_id, device_id, expires_at))
-			if expires_at.is_some_and(is_less_than!(SystemTime::now())) =>
-			Expired((user_id, device_id)),
-
-		| token => token,
-	};
+	let (token, delegated) = request_token(services, &mut request.parts, &request.query).await?;
 
 	if metadata.authentication == AuthScheme::None {
 		check_auth_still_required(services, metadata, &token)?;
 	}
 
-	match (metadata.authentication, token) {
+	let auth = match (metadata.authentication, token) {
 		| (AuthScheme::None, Invalid)
 			if request.query.access_token.is_some()
 				&& metadata == &get_openid_userinfo::v1::Request::METADATA =>
@@ -111,16 +115,11 @@ pub(super) async fn auth(
 		| (_, Invalid) =>
 			Err(BadRequest(UnknownToken { soft_logout: false }, "Unknown access token.")),
 
-		| (_, Expired((user_id, device_id))) => {
-			services
-				.users
-				.remove_access_token(&user_id, &device_id)
-				.await
-				.log_debug_err()
-				.ok();
-
-			Err(BadRequest(UnknownToken { soft_logout: true }, "Expired access token."))
-		},
+		// The expired token is kept until the device is refreshed or logged in
+		// again, so that the client remains soft-logged-out rather than being
+		// told its token is unknown.
+		| (_, Expired(_)) =>
+			Err(BadRequest(UnknownToken { soft_logout: true }, "Expired access token.")),
 
 		| (AppserviceToken, User(_)) => {
 			Err!(Request(Unauthorized("Appservice tokens must be used on this endpoint.")))
@@ -132,25 +131,36 @@ pub(super) async fn auth(
 
 		| (ServerSignatures, Token::None) => Ok(auth_server(services, request, json_body).await?),
 
-		| (AccessToken, Appservice(info)) => Ok(auth_appservice(services, request, info).await?),
+		| (AccessToken, Appservice(info)) =>
+			Ok(auth_appservice(services, &request.query, info).await?),
 
 		| (AccessToken | AppserviceToken, Token::None) => match metadata {
 			| &get_turn_server_info::v3::Request::METADATA
 				if services.server.config.turn_allow_guests =>
 				Ok(Auth::default()),
 
+			// Users who forgot their password reset it by verifying their email
+			// address instead.
+			| &change_password::v3::Request::METADATA if services.mailer.enabled() =>
+				Ok(Auth::default()),
+
 			| _ => Err!(Request(MissingToken("Missing access token."))),
 		},
 
 		| (
 			AccessToken | AccessTokenOptional | AppserviceTokenOptional | AuthScheme::None,
 			User(user),
-		) => Ok(Auth {
-			sender_user: Some(user.0),
-			sender_device: Some(user.1),
-			_expires_at: user.2,
-			..Auth::default()
-		}),
+		) => {
+			record_session(services, &mut request.parts, &user.0, &user.1).await;
+
+			Ok(Auth {
+				sender_user: Some(user.0),
+				sender_device: Some(user.1),
+				delegated,
+				_expires_at: user.2,
+				..Auth::default()
+			})
+		},
 
 		| (
 			AccessTokenOptional | AppserviceTokenOptional | AppserviceToken | AuthScheme::None,
@@ -160,8 +170,129 @@ pub(super) async fn auth(
 			..Auth::default()
 		}),
 
-		| (AccessTokenOptional | AppserviceTokenOptional | AuthScheme::None, Token::None) =>
-			Ok(Auth::default()),
+		| (AccessTokenOptional | AppserviceTokenOptional | AuthScheme::None, Token::None) => {
+			record_versions_fetched(services, &mut request.parts).await;
+
+			Ok(Auth::default())
+		},
+	}?;
+
+	if let Some(user_id) = &auth.sender_user {
+		check_suspended(services, metadata, user_id).await?;
+	}
+
+	ratelimit::check_user(services, &mut request.parts, &auth).await?;
+
+	Ok(auth)
+}
+
+/// Authenticates a request to a route outside of the ruma endpoint definitions
+/// as `auth` does for endpoints requiring an access token: a local user, or an
+/// appservice acting as one of its users. Suspended users may only read.
+pub(super) async fn auth_user(services: &Services, parts: &mut Parts) -> Result<Auth> {
+	use Error::BadRequest;
+	use ErrorKind::UnknownToken;
+
+	let query: QueryParams = serde_html_form::from_str(parts.uri.query().unwrap_or_default())
+		.map_err(|e| err!(Request(Unknown("Failed to read query parameters: {e}"))))?;
+
+	let (token, delegated) = request_token(services, parts, &query).await?;
+	let auth = match token {
+		| Token::Invalid =>
+			Err(BadRequest(UnknownToken { soft_logout: false }, "Unknown access token.")),
+
+		| Token::Expired(_) =>
+			Err(BadRequest(UnknownToken { soft_logout: true }, "Expired access token.")),
+
+		| Token::None => Err!(Request(MissingToken("Missing access token."))),
+
+		| Token::Appservice(info) => auth_appservice(services, &query, info).await,
+
+		| Token::User((user_id, device_id, expires_at)) => {
+			record_session(services, parts, &user_id, &device_id).await;
+
+			Ok(Auth {
+				sender_user: Some(user_id),
+				sender_device: Some(device_id),
+				delegated,
+				_expires_at: expires_at,
+				..Auth::default()
+			})
+		},
+	}?;
+
+	let reads = parts.method == Method::GET || parts.method == Method::HEAD;
+	if let Some(user_id) = &auth.sender_user
+		&& !reads
+		&& services.users.is_suspended(user_id).await
+	{
+		return Err!(Request(UserSuspended("Your account is suspended.")));
+	}
+
+	ratelimit::check_user(services, parts, &auth).await?;
+
+	Ok(auth)
+}
+
+/// The token of a request, from its `Authorization` header or else its
+/// `access_token` query parameter; expired when past its expiry.
+async fn request_token(
+	services: &Services,
+	parts: &mut Parts,
+	query: &QueryParams,
+) -> Result<(Token, bool)> {
+	let bearer: Option<TypedHeader<Authorization<Bearer>>> =
+		parts.extract().await.unwrap_or(None);
+
+	let token = match &bearer {
+		| Some(TypedHeader(Authorization(bearer))) => Some(bearer.token()),
+		| None => query.access_token.as_deref(),
+	};
+
+	let (token, delegated) = find_user_token(services, token).await?;
+	let token = match token {
+		| Token::User((user_id, device_id, expires_at))
+			if expires_at.is_some_and(is_less_than!(SystemTime::now())) =>
+			Token::Expired((user_id, device_id)),
+
+		| token => token,
+	};
+
+	Ok((token, delegated))
+}
+
+/// Suspended users (MSC3823) may read but not write. Besides reading, they may
+/// leave rooms, redact their events, manage their devices and keys, log out
+/// and deactivate.
+async fn check_suspended(services: &Services, metadata: &Metadata, user_id: &UserId) -> Result {
+	if metadata.method == Method::GET || metadata.method == Method::HEAD {
+		return Ok(());
+	}
+
+	match metadata {
+		| &claim_keys::v3::Request::METADATA
+		| &create_filter::v3::Request::METADATA
+		| &create_receipt::v3::Request::METADATA
+		| &deactivate::v3::Request::METADATA
+		| &delete_device::v3::Request::METADATA
+		| &delete_devices::v3::Request::METADATA
+		| &forget_room::v3::Request::METADATA
+		| &get_keys::v3::Request::METADATA
+		| &get_public_rooms_filtered::v3::Request::METADATA
+		| &leave_room::v3::Request::METADATA
+		| &logout::v3::Request::METADATA
+		| &logout_all::v3::Request::METADATA
+		| &redact_event::v3::Request::METADATA
+		| &search_events::v3::Request::METADATA
+		| &search_users::v3::Request::METADATA
+		| &set_read_marker::v3::Request::METADATA
+		| &upload_keys::v3::Request::METADATA => Ok(()),
+
+		| _ if services.users.is_suspended(user_id).await => {
+			Err!(Request(UserSuspended("Your account is suspended.")))
+		},
+
+		| _ => Ok(()),
 	}
 }
 
@@ -203,9 +334,77 @@ fn check_auth_still_required(services: &Services, metadata: &Metadata, token: &T
 	}
 }
 
-async fn find_token(services: &Services, token: Option<&str>) -> Result<Token> {
+/// Records the address, user agent and version of the client API the device
+/// was seen with.
+async fn record_session(
+	services: &Services,
+	parts: &mut Parts,
+	user_id: &UserId,
+	device_id: &DeviceId,
+) {
+	let Ok(InsecureClientIp(ip)) = parts.extract().await else {
+		return;
+	};
+
+	let user_agent = parts
+		.headers
+		.get(USER_AGENT)
+		.and_then(|user_agent| user_agent.to_str().ok());
+
+	let path = parts.uri.path();
+	let versions = is_versions(path);
+	let api_version = client_api_version(path);
+
+	services
+		.users
+		.record_session(user_id, device_id, ip, user_agent, api_version.as_deref(), versions)
+		.await;
+}
+
+/// Records a request for the versions supported by the server made without
+/// authentication, which clients usually make before authenticating.
+async fn record_versions_fetched(services: &Services, parts: &mut Parts) {
+	if !is_versions(parts.uri.path()) {
+		return;
+	}
+
+	let Ok(InsecureClientIp(ip)) = parts.extract().await else {
+		return;
+	};
+
+	let user_agent = parts
+		.headers
+		.get(USER_AGENT)
+		.and_then(|user_agent| user_agent.to_str().ok());
+
+	services
+		.users
+		.record_versions_fetched(ip, user_agent);
+}
+
+fn is_versions(path: &str) -> bool { path.trim_end_matches('/') == "/_matrix/client/versions" }
+
+/// The version of the client API in the path of a request, e.g. `v3`; for the
+/// unstable endpoints of a feature, `unstable/` and the namespace of the
+/// feature, e.g. `unstable/org.matrix.msc3575`.
+fn client_api_version(path: &str) -> Option<String> {
+	let mut segments = path.strip_prefix("/_matrix/client/")?.split('/');
+
+	match segments.next()? {
+		| "unstable" => match segments.next() {
+			| Some(feature) if feature.contains('.') => Some(format!("unstable/{feature}")),
+			| _ => Some("unstable".to_owned()),
+		},
+		| "versions" => None,
+		| version => Some(version.to_owned()),
+	}
+}
+
+/// Finds the token, and whether the provider authentication is delegated to
+/// issued it.
+async fn find_user_token(services: &Services, token: Option<&str>) -> Result<(Token, bool)> {
 	let Some(token) = token else {
-		return Ok(Token::None);
+		return Ok((Token::None, false));
 	};
 
 	let user_token = services
@@ -222,7 +421,24 @@ async fn find_token(services: &Services, token: Option<&str>) -> Result<Token> {
 	pin_mut!(user_token, appservice_token);
 	match select_ok([Left(user_token), Right(appservice_token)]).await {
 		| Err(e) if !e.is_not_found() => Err(e),
-		| Ok((token, _)) => Ok(token),
+		| Ok((token, _)) => Ok((token, false)),
+		| _ if services.oauth.delegated() => find_delegated_token(services, token)
+			.await
+			.map(|token| (token, true)),
+		| _ => Ok((Token::Invalid, false)),
+	}
+}
+
+/// Tokens issued by the provider authentication is delegated to are not known
+/// to us until introspected.
+async fn find_delegated_token(services: &Services, token: &str) -> Result<Token> {
+	match services
+		.oauth
+		.find_from_delegated_token(token)
+		.await
+	{
+		| Err(e) if !e.is_not_found() => Err(e),
+		| Ok(user) => Ok(Token::User(user)),
 		| _ => Ok(Token::Invalid),
 	}
 }
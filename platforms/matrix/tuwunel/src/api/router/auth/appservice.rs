@@ -2,11 +2,11 @@
 use tuwunel_core::{Err, Result};
 use tuwunel_service::{Services, appservice::RegistrationInfo};
 
-use super::{Auth, Request};
+use super::{Auth, QueryParams};
 
 pub(super) async fn auth_appservice(
 	services: &Services,
-	request: &Request,
+	query: &QueryParams,
 	info: Box<RegistrationInfo>,
 ) -> Result<Auth> {
 	let user_id_default = || {
@@ -16,8 +16,7 @@ pub(super) async fn auth_appservice(
 		)
 	};
 
-	let Ok(user_id) = request
-		.query
+	let Ok(user_id) = query
 		.user_id
 		.as_deref()
 		.map_or_else(user_id_default, OwnedUserId::parse)
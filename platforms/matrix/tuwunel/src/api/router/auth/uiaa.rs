@@ -2,29 +2,35 @@
 	CanonicalJsonValue, OwnedUserId,
 	api::{
 		IncomingRequest,
-		client::uiaa::{AuthData, AuthFlow, AuthType, Jwt, UiaaInfo},
+		client::uiaa::{AuthData, AuthFlow, AuthType, EmailIdentity, Jwt, UiaaInfo},
 	},
 };
+use serde_json::{json, value::to_raw_value};
 use tuwunel_core::{Err, Error, Result, err, is_equal_to, utils};
-use tuwunel_service::{Services, uiaa::SESSION_ID_LENGTH};
+use tuwunel_service::{
+	Services,
+	uiaa::{CROSS_SIGNING_RESET, SESSION_ID_LENGTH},
+};
 
-use crate::{Ruma, client::jwt};
+use crate::{
+	Ruma,
+	client::{cross_signing_reset_url, jwt, reset_provider},
+};
 
 pub(crate) async fn auth_uiaa<T>(services: &Services, body: &Ruma<T>) -> Result<OwnedUserId>
 where
 	T: IncomingRequest + Send + Sync,
 {
-	let sender_device = body.sender_device()?;
-
-	let flows = [
+	let mut flows = vec![
 		AuthFlow::new([AuthType::Password].into()),
 		AuthFlow::new([AuthType::Jwt].into()),
 	];
 
-	let mut uiaainfo = UiaaInfo {
-		flows: flows.into(),
-		..Default::default()
-	};
+	if services.mailer.enabled() {
+		flows.push(AuthFlow::new([AuthType::EmailIdentity].into()));
+	}
+
+	let mut uiaainfo = UiaaInfo { flows, ..Default::default() };
 
 	match body
 		.json_body
@@ -45,12 +51,42 @@ pub(crate) async fn auth_uiaa<T>(services: &Services, body: &Ruma<T>) -> Result<
 			// Success!
 			Ok(sender_user)
 		},
+		| Some(AuthData::EmailIdentity(EmailIdentity { ref thirdparty_id_creds, .. }))
+			if body.sender_user.is_none() =>
+		{
+			// Resetting a forgotten password: the user is the one the verified
+			// address is associated with. The session authenticates only once.
+			let sid = thirdparty_id_creds.sid.as_str();
+			let email = services
+				.threepid
+				.claim_email(sid, thirdparty_id_creds.client_secret.as_str())
+				.await?;
+
+			let sender_user = services
+				.threepid
+				.user_by_email(&email)
+				.await
+				.map_err(|_| {
+					err!(Request(ThreepidNotFound(
+						"The email address is not associated with a user."
+					)))
+				})?;
+
+			if !services.users.has_password(&sender_user).await {
+				return Err!(Request(Forbidden("This account has no password to reset.")));
+			}
+
+			// Success!
+			Ok(sender_user)
+		},
 		| Some(ref auth) => {
 			let sender_user = body
 				.sender_user
 				.as_deref()
 				.ok_or_else(|| err!(Request(MissingToken("Missing access token."))))?;
 
+			let sender_device = body.sender_device()?;
+
 			let (worked, uiaainfo) = services
 				.uiaa
 				.try_auth(sender_user, sender_device, auth, &uiaainfo)
@@ -65,21 +101,22 @@ pub(crate) async fn auth_uiaa<T>(services: &Services, body: &Ruma<T>) -> Result<
 		},
 		| _ => match body.json_body {
 			| Some(ref json) => {
-				let sender_user = body
-					.sender_user
-					.as_deref()
-					.ok_or_else(|| err!(Request(MissingToken("Missing access token."))))?;
-
-				// Skip UIAA for SSO/OIDC users.
-				if services
-					.users
-					.origin(sender_user)
-					.await
-					.is_ok_and(is_equal_to!("sso"))
-				{
+				// Without an access token, users can only verify an address associated
+				// with them.
+				let Some(sender_user) = body.sender_user.as_deref() else {
+					uiaainfo.flows = vec![AuthFlow::new([AuthType::EmailIdentity].into())];
+					uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+
+					return Err(Error::Uiaa(uiaainfo));
+				};
+
+				// Skip UIAA for SSO/OIDC users, and for requests the provider
+				// authentication is delegated to authenticated.
+				if authenticates_with_provider(services, body).await {
 					return Ok(sender_user.to_owned());
 				}
 
+				let sender_device = body.sender_device()?;
 				uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 				services
 					.uiaa
@@ -91,3 +128,66 @@ pub(crate) async fn auth_uiaa<T>(services: &Services, body: &Ruma<T>) -> Result<
 		},
 	}
 }
+
+/// Authenticates replacing the cross-signing keys of the sender. Users who
+/// authenticate with an identity provider, and so have no password, approve it
+/// there through the `org.matrix.cross_signing_reset` stage (MSC2965) before
+/// retrying the request; other users go through UIAA.
+pub(crate) async fn auth_cross_signing_reset<T>(
+	services: &Services,
+	body: &Ruma<T>,
+) -> Result<OwnedUserId>
+where
+	T: IncomingRequest + Send + Sync,
+{
+	let sender_user = body
+		.sender_user
+		.as_deref()
+		.ok_or_else(|| err!(Request(MissingToken("Missing access token."))))?;
+
+	if !authenticates_with_provider(services, body).await {
+		return auth_uiaa(services, body).await;
+	}
+
+	if services
+		.uiaa
+		.take_cross_signing_reset(sender_user)
+	{
+		return Ok(sender_user.to_owned());
+	}
+
+	let provider = reset_provider(services, sender_user).await?;
+	let token = services
+		.uiaa
+		.request_cross_signing_reset(sender_user);
+
+	let url = cross_signing_reset_url(&provider, &token)?;
+	let params = json!({ CROSS_SIGNING_RESET: { "url": url } });
+	let uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow::new(vec![AuthType::from(CROSS_SIGNING_RESET)])],
+		session: Some(token),
+		params: Some(to_raw_value(&params)?),
+		..Default::default()
+	};
+
+	Err(Error::Uiaa(uiaainfo))
+}
+
+/// Whether the sender authenticates with an identity provider rather than a
+/// password: they signed in with SSO, or the access token of the request was
+/// issued by the provider authentication is delegated to.
+async fn authenticates_with_provider<T>(services: &Services, body: &Ruma<T>) -> bool {
+	if body.delegated {
+		return true;
+	}
+
+	let Some(sender_user) = body.sender_user.as_deref() else {
+		return false;
+	};
+
+	services
+		.users
+		.origin(sender_user)
+		.await
+		.is_ok_and(is_equal_to!("sso"))
+}
@@ -0,0 +1,57 @@
+use axum::{
+	body::Body,
+	extract::{MatchedPath, State},
+	response::Response,
+};
+use http::{Request, StatusCode};
+use ruma::api::client::error::ErrorKind;
+use tuwunel_core::{Error, Result};
+
+/// Answers requests to the endpoint groups named by `disabled_endpoints` as if
+/// the endpoints did not exist.
+pub async fn disabled_endpoints(
+	State(services): State<crate::State>,
+	request: Request<Body>,
+	next: axum::middleware::Next,
+) -> Result<Response> {
+	let disabled = &services.server.config.disabled_endpoints;
+	if disabled.is_empty() {
+		return Ok(next.run(request).await);
+	}
+
+	let path = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map_or_else(|| request.uri().path(), MatchedPath::as_str);
+
+	if disabled.iter().any(|group| in_group(group, path)) {
+		return Err(Error::Request(
+			ErrorKind::Unrecognized,
+			"Not Found".into(),
+			StatusCode::NOT_FOUND,
+		));
+	}
+
+	Ok(next.run(request).await)
+}
+
+/// Whether the path is of an endpoint in the group; see `ENDPOINT_GROUPS`.
+fn in_group(group: &str, path: &str) -> bool {
+	match group {
+		| "room_directory" =>
+			path.ends_with("/publicRooms") || path.contains("/directory/list/room/"),
+		| "user_directory" => path.ends_with("/user_directory/search"),
+		| "url_preview" => path.ends_with("/preview_url"),
+		| "openid" => path.contains("/openid/"),
+		| "3pid" =>
+			path.contains("/3pid")
+				|| path.starts_with("/_tuwunel/email/")
+				|| (path.starts_with("/_matrix/client/") && path.ends_with("/requestToken")),
+		| "search" =>
+			path.starts_with("/_matrix/client/")
+				&& path.ends_with("/search")
+				&& !path.contains("/user_directory/"),
+		| "thirdparty" => path.contains("/thirdparty/"),
+		| _ => false,
+	}
+}
@@ -0,0 +1,244 @@
+use std::ops::RangeInclusive;
+
+use axum::{
+	body::Body,
+	extract::{MatchedPath, State},
+	response::{IntoResponse, Response},
+};
+use futures::TryStreamExt;
+use http::{
+	HeaderName, HeaderValue, Method, Request, StatusCode,
+	header::{
+		ACCEPT_RANGES, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+	},
+	request::Parts,
+};
+use ruma::{Mxc, ServerName, api::client::media::create_content};
+use serde::Deserialize;
+use tuwunel_core::{
+	Result, err,
+	utils::{self, content_disposition::make_content_disposition},
+};
+use tuwunel_service::media::{CACHE_CONTROL_IMMUTABLE, CORP_CROSS_ORIGIN, FileRange, MXC_LENGTH};
+
+use super::{RumaResponse, auth};
+
+const CROSS_ORIGIN_RESOURCE_POLICY: HeaderName =
+	HeaderName::from_static("cross-origin-resource-policy");
+
+#[derive(Deserialize)]
+struct UploadQuery {
+	filename: Option<String>,
+
+	#[serde(default, rename = "xyz.amorgan.generate_blurhash")]
+	generate_blurhash: bool,
+}
+
+/// Streams media to and from disk where the routes would hold whole files in
+/// memory: uploads by users are written as they are received, and downloads of
+/// local media with a `Range` header read only the range. Other requests are
+/// left to the routes. Requests are authenticated as the routes would, within
+/// the rate limits and disabled endpoints applied by the outer layers.
+pub async fn media_stream(
+	State(services): State<crate::State>,
+	request: Request<Body>,
+	next: axum::middleware::Next,
+) -> Result<Response> {
+	let path = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map_or_else(|| request.uri().path(), MatchedPath::as_str);
+
+	let is_upload = *request.method() == Method::POST
+		&& path.starts_with("/_matrix/media/")
+		&& path.ends_with("/upload");
+
+	let is_download = *request.method() == Method::GET
+		&& (path.starts_with("/_matrix/client/") || path.starts_with("/_matrix/media/"))
+		&& path.contains("/download/");
+
+	if is_upload {
+		return upload(&services, request, next).await;
+	}
+
+	if !is_download {
+		return Ok(next.run(request).await);
+	}
+
+	let (mut parts, body) = request.into_parts();
+	let mut response = match range(&services, &mut parts).await? {
+		| Some(response) => response,
+		| None => next.run(Request::from_parts(parts, body)).await,
+	};
+
+	if response.status().is_success() {
+		response
+			.headers_mut()
+			.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+	}
+
+	Ok(response)
+}
+
+async fn upload(
+	services: &crate::State,
+	request: Request<Body>,
+	next: axum::middleware::Next,
+) -> Result<Response> {
+	// Appservices upload on behalf of their users; their uploads are buffered.
+	let (mut parts, body) = request.into_parts();
+	let auth = auth::auth_user(services, &mut parts).await?;
+	let (Some(user_id), None) = (auth.sender_user, auth.appservice_info) else {
+		return Ok(next.run(Request::from_parts(parts, body)).await);
+	};
+
+	let query: UploadQuery = serde_html_form::from_str(parts.uri.query().unwrap_or_default())
+		.map_err(|e| err!(Request(InvalidParam("Invalid query: {e}"))))?;
+
+	let content_type = parts
+		.headers
+		.get(CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.map(ToOwned::to_owned);
+
+	let filename = query.filename.as_deref();
+	let content_type = content_type.as_deref();
+	let content_disposition = make_content_disposition(None, content_type, filename);
+	let ref mxc = Mxc {
+		server_name: services.globals.server_name(),
+		media_id: &utils::random_string(MXC_LENGTH),
+	};
+
+	let body = body
+		.into_data_stream()
+		.map_err(|e| err!(Request(Unknown("Failed to receive upload: {e}"))));
+
+	services
+		.media
+		.create_stream(
+			mxc,
+			Some(&user_id),
+			Some(&content_disposition),
+			content_type,
+			body,
+			services.server.config.max_request_size,
+		)
+		.await?;
+
+	let blurhash = if query.generate_blurhash {
+		services
+			.media
+			.get(mxc)
+			.await?
+			.and_then(|file| file.content)
+			.and_then(|file| {
+				services
+					.media
+					.create_blurhash(&file, content_type, filename)
+					.ok()
+					.flatten()
+			})
+	} else {
+		None
+	};
+
+	Ok(RumaResponse(create_content::v3::Response {
+		content_uri: mxc.to_string().into(),
+		blurhash,
+	})
+	.into_response())
+}
+
+/// Answers a download of local media with a single `Range` of bytes with the
+/// range, read from disk as it is sent. None when the request is to be left to
+/// the route, which answers with the whole file: unauthenticated downloads of
+/// frozen legacy media, and requests failing authentication.
+async fn range(services: &crate::State, parts: &mut Parts) -> Result<Option<Response>> {
+	let Some(range) = parts
+		.headers
+		.get(RANGE)
+		.and_then(|value| value.to_str().ok())
+		.and_then(parse_range)
+	else {
+		return Ok(None);
+	};
+
+	let path = parts.uri.path().to_owned();
+	let mut segments = path
+		.split_once("/download/")
+		.map(|(_, rest)| rest.split('/'))
+		.into_iter()
+		.flatten();
+
+	let (Some(server_name), Some(media_id)) = (segments.next(), segments.next()) else {
+		return Ok(None);
+	};
+
+	let filename = segments.next();
+	let Ok(server_name) = ServerName::parse(server_name) else {
+		return Ok(None);
+	};
+
+	if !services.globals.server_is_ours(&server_name) {
+		return Ok(None);
+	}
+
+	let authenticated = path.starts_with("/_matrix/client/");
+	if !authenticated && services.server.config.freeze_legacy_media {
+		return Ok(None);
+	}
+
+	if authenticated && auth::auth_user(services, parts).await.is_err() {
+		return Ok(None);
+	}
+
+	let mxc = Mxc { server_name: &server_name, media_id };
+	let Some(FileRange { meta, content, range, len }) =
+		services.media.get_range(&mxc, range).await?
+	else {
+		return Ok(None);
+	};
+
+	let content_disposition = make_content_disposition(
+		meta.content_disposition.as_ref(),
+		meta.content_type.as_deref(),
+		filename,
+	);
+
+	let content_range = format!("bytes {}-{}/{len}", range.start(), range.end());
+	let content_type = meta
+		.content_type
+		.unwrap_or_else(|| "application/octet-stream".to_owned());
+
+	let response = (
+		StatusCode::PARTIAL_CONTENT,
+		[
+			(CONTENT_TYPE, content_type),
+			(CONTENT_RANGE, content_range),
+			(CONTENT_DISPOSITION, content_disposition.to_string()),
+			(CACHE_CONTROL, CACHE_CONTROL_IMMUTABLE.to_owned()),
+			(CROSS_ORIGIN_RESOURCE_POLICY, CORP_CROSS_ORIGIN.to_owned()),
+		],
+		Body::from_stream(content),
+	);
+
+	Ok(Some(response.into_response()))
+}
+
+/// Parses a `Range` header of a single range of bytes: `bytes=start-end` or
+/// `bytes=start-`. Suffix ranges and multiple ranges are left to the route,
+/// which answers with the whole file.
+fn parse_range(header: &str) -> Option<RangeInclusive<u64>> {
+	let (start, end) = header
+		.strip_prefix("bytes=")?
+		.trim()
+		.split_once('-')?;
+
+	let start = start.parse().ok()?;
+	let end = match end {
+		| "" => u64::MAX,
+		| end => end.parse().ok()?,
+	};
+
+	(start <= end).then_some(start..=end)
+}
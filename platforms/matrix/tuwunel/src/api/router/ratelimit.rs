@@ -0,0 +1,170 @@
+use axum::{
+	RequestPartsExt,
+	body::Body,
+	extract::{MatchedPath, State},
+	response::Response,
+};
+use axum_client_ip::SecureClientIp;
+use http::{Method, Request, StatusCode, request::Parts};
+use ruma::api::client::error::{ErrorKind, RetryAfter};
+use tuwunel_core::{Error, Result, debug_warn};
+use tuwunel_service::{
+	Services,
+	ratelimit::{Class, Key},
+};
+
+use super::auth::Auth;
+
+/// Refuses requests over the rate limit of their class of endpoint, before
+/// they are handled. Limits per address apply here, to the address taken from
+/// `client_ip_source`; limits per user apply once the request is authenticated
+/// (see [`check_user`]).
+pub async fn ratelimit(
+	State(services): State<crate::State>,
+	SecureClientIp(client): SecureClientIp,
+	request: Request<Body>,
+	next: axum::middleware::Next,
+) -> Result<Response> {
+	let path = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map_or_else(|| request.uri().path(), MatchedPath::as_str);
+
+	match classify(request.method(), path) {
+		| Some(class) if !by_user(class) => check(&services, class, &Key::Ip(client)).await?,
+		| _ => {},
+	}
+
+	Ok(next.run(request).await)
+}
+
+/// Applies the limits per user to an authenticated request. Appservices are
+/// exempt unless their registration sets `rate_limited`; requests without a
+/// user are limited by address.
+pub(super) async fn check_user(services: &Services, parts: &mut Parts, auth: &Auth) -> Result {
+	let path = parts
+		.extensions
+		.get::<MatchedPath>()
+		.map_or_else(|| parts.uri.path(), MatchedPath::as_str);
+
+	let Some(class) = classify(&parts.method, path).filter(|&class| by_user(class)) else {
+		return Ok(());
+	};
+
+	let key = match (&auth.appservice_info, &auth.sender_user) {
+		| (Some(info), _) if !info.registration.rate_limited.unwrap_or(false) => return Ok(()),
+		| (_, Some(user_id)) => Key::User(user_id.clone()),
+		| _ => match parts.extract::<SecureClientIp>().await {
+			| Ok(SecureClientIp(client)) => Key::Ip(client),
+			| Err(_) => return Ok(()),
+		},
+	};
+
+	check(services, class, &key).await
+}
+
+async fn check(services: &Services, class: Class, key: &Key) -> Result {
+	let Err(retry_after) = services.ratelimit.check(class, key).await else {
+		return Ok(());
+	};
+
+	debug_warn!(%key, ?class, ?retry_after, "Rate limit exceeded");
+	Err(Error::Request(
+		ErrorKind::LimitExceeded {
+			retry_after: Some(RetryAfter::Delay(retry_after)),
+		},
+		"Too many requests.".into(),
+		StatusCode::TOO_MANY_REQUESTS,
+	))
+}
+
+/// Whether the limit of the class applies to the user making the request
+/// rather than their address.
+fn by_user(class: Class) -> bool { matches!(class, Class::Join | Class::Messages) }
+
+fn classify(method: &Method, path: &str) -> Option<Class> {
+	if path.starts_with("/_matrix/federation/") || path.starts_with("/_tuwunel/share_links/") {
+		return Some(Class::Federation);
+	}
+
+	if !path.starts_with("/_matrix/client/") && !path.starts_with("/_tuwunel/client/") {
+		return None;
+	}
+
+	match *method {
+		| Method::POST if path.ends_with("/login") => Some(Class::Login),
+		| Method::POST if path.ends_with("/register") => Some(Class::Register),
+		| Method::POST if path.ends_with("/email/requestToken") => Some(Class::Email),
+		| Method::POST if path.ends_with("/org.matrix.msc4108/rendezvous") =>
+			Some(Class::Rendezvous),
+		| Method::POST
+			if path.ends_with("/join") || path.contains("/join/") || path.contains("/knock/") =>
+			Some(Class::Join),
+		| Method::GET if path.ends_with("/messages") => Some(Class::Messages),
+		| _ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use http::Method;
+	use tuwunel_service::ratelimit::Class;
+
+	use super::{by_user, classify};
+
+	#[test]
+	fn classify_client() {
+		let post = |path| classify(&Method::POST, path);
+
+		assert_eq!(post("/_matrix/client/v3/login"), Some(Class::Login));
+		assert_eq!(post("/_matrix/client/v3/register"), Some(Class::Register));
+		assert_eq!(post("/_matrix/client/v3/join/{room_id_or_alias}"), Some(Class::Join));
+		assert_eq!(post("/_matrix/client/v3/rooms/{room_id}/join"), Some(Class::Join));
+		assert_eq!(post("/_matrix/client/v3/knock/{room_id_or_alias}"), Some(Class::Join));
+		assert_eq!(post("/_tuwunel/client/v1/share_links/{token}/join"), Some(Class::Join));
+		assert_eq!(post("/_matrix/client/v3/register/email/requestToken"), Some(Class::Email));
+		assert_eq!(
+			post("/_matrix/client/v3/account/password/email/requestToken"),
+			Some(Class::Email)
+		);
+		assert_eq!(
+			post("/_matrix/client/unstable/org.matrix.msc4108/rendezvous"),
+			Some(Class::Rendezvous)
+		);
+		assert_eq!(
+			classify(&Method::PUT, "/_matrix/client/unstable/org.matrix.msc4108/rendezvous/{id}"),
+			None
+		);
+		assert_eq!(post("/_matrix/client/v3/register/msisdn/requestToken"), None);
+		assert_eq!(post("/_matrix/client/v3/rooms/{room_id}/leave"), None);
+		assert_eq!(
+			classify(&Method::GET, "/_matrix/client/v3/rooms/{room_id}/messages"),
+			Some(Class::Messages)
+		);
+		assert_eq!(classify(&Method::GET, "/_matrix/client/v3/login"), None);
+	}
+
+	#[test]
+	fn classify_federation() {
+		let get = |path| classify(&Method::GET, path);
+
+		assert_eq!(get("/_matrix/federation/v1/version"), Some(Class::Federation));
+		assert_eq!(
+			classify(&Method::POST, "/_tuwunel/share_links/{token}/invite"),
+			Some(Class::Federation)
+		);
+		assert_eq!(get("/_matrix/key/v2/server"), None);
+		assert_eq!(get("/_matrix/media/v3/config"), None);
+	}
+
+	#[test]
+	fn keyed_by_user() {
+		assert!(by_user(Class::Join));
+		assert!(by_user(Class::Messages));
+		assert!(!by_user(Class::Login));
+		assert!(!by_user(Class::Register));
+		assert!(!by_user(Class::Federation));
+		assert!(!by_user(Class::Email));
+		assert!(!by_user(Class::Rendezvous));
+	}
+}
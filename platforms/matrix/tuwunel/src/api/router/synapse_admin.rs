@@ -0,0 +1,30 @@
+use axum::extract::FromRequestParts;
+use http::request::Parts;
+use ruma::OwnedUserId;
+use tuwunel_core::{Err, Error, Result};
+
+use super::auth;
+use crate::State;
+
+/// Extractor for the server admin on whose behalf a Synapse admin API request
+/// is made. Refuses requests which do not carry the access token of a server
+/// admin.
+#[derive(Clone, Debug)]
+pub(crate) struct AdminUser(pub(crate) OwnedUserId);
+
+impl FromRequestParts<State> for AdminUser {
+	type Rejection = Error;
+
+	async fn from_request_parts(parts: &mut Parts, services: &State) -> Result<Self> {
+		let auth = auth::auth_user(services, parts).await?;
+		let user_id = auth
+			.sender_user
+			.expect("user must be authenticated by auth_user");
+
+		if auth.appservice_info.is_some() || !services.admin.user_is_admin(&user_id).await {
+			return Err!(Request(Forbidden("Only server admins can use the admin API.")));
+		}
+
+		Ok(Self(user_id))
+	}
+}
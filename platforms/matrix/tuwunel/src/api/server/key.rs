@@ -1,4 +1,5 @@
 use std::{
+	collections::BTreeMap,
 	mem::take,
 	time::{Duration, SystemTime},
 };
@@ -26,21 +27,23 @@ pub(crate) async fn get_server_keys_route(
 	State(services): State<crate::State>,
 ) -> Result<impl IntoResponse> {
 	let server_name = services.globals.server_name();
-	let active_key_id = services.server_keys.active_key_id();
+	let (active_key_id, verify_key) = services.server_keys.active_verify_key();
 	let mut all_keys = services
 		.server_keys
 		.verify_keys_for(server_name)
 		.await;
 
-	let verify_keys = all_keys
-		.remove_entry(active_key_id)
-		.expect("active verify_key is missing");
-
-	let old_verify_keys = all_keys
+	all_keys.remove(&active_key_id);
+	let mut old_verify_keys: BTreeMap<_, _> = all_keys
 		.into_iter()
 		.map(|(id, key)| (id, OldVerifyKey::new(expires_ts(), key.key)))
 		.collect();
 
+	// Keys rotated out keep the time they expired at.
+	old_verify_keys.extend(services.server_keys.old_verify_keys());
+
+	let verify_keys = (active_key_id, verify_key);
+
 	let server_key = ServerSigningKeys {
 		verify_keys: [verify_keys].into(),
 		old_verify_keys,
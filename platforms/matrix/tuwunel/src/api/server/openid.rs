@@ -1,4 +1,6 @@
 use axum::extract::State;
+use axum_client_ip::InsecureClientIp;
+use axum_extra::{TypedHeader, headers::UserAgent};
 use ruma::api::federation::openid::get_openid_userinfo;
 use tuwunel_core::Result;
 
@@ -6,15 +8,23 @@
 
 /// # `GET /_matrix/federation/v1/openid/userinfo`
 ///
-/// Get information about the user that generated the OpenID token.
+/// Get information about the user that generated the OpenID token. The
+/// address and user agent of the integration asking are recorded with the
+/// token.
 pub(crate) async fn get_openid_userinfo_route(
 	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	user_agent: Option<TypedHeader<UserAgent>>,
 	body: Ruma<get_openid_userinfo::v1::Request>,
 ) -> Result<get_openid_userinfo::v1::Response> {
+	let user_agent = user_agent
+		.as_ref()
+		.map(|TypedHeader(ua)| ua.as_str());
+
 	Ok(get_openid_userinfo::v1::Response::new(
 		services
 			.users
-			.find_from_openid_token(&body.access_token)
+			.exchange_openid_token(&body.access_token, client, user_agent)
 			.await?,
 	))
 }
@@ -1,13 +1,15 @@
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
 use ruma::{
+	ServerName,
 	api::{
 		client::error::ErrorKind,
 		federation::directory::{get_public_rooms, get_public_rooms_filtered},
 	},
 	directory::Filter,
 };
-use tuwunel_core::{Error, Result};
+use tuwunel_core::{Err, Error, Result};
+use tuwunel_service::Services;
 
 use crate::Ruma;
 
@@ -28,6 +30,8 @@ pub(crate) async fn get_public_rooms_filtered_route(
 		return Err(Error::BadRequest(ErrorKind::forbidden(), "Room directory is not public"));
 	}
 
+	check_server_allowed(&services, body.origin())?;
+
 	let response = crate::client::get_public_rooms_filtered_helper(
 		&services,
 		None,
@@ -65,6 +69,8 @@ pub(crate) async fn get_public_rooms_route(
 		return Err(Error::BadRequest(ErrorKind::forbidden(), "Room directory is not public"));
 	}
 
+	check_server_allowed(&services, body.origin())?;
+
 	let response = crate::client::get_public_rooms_filtered_helper(
 		&services,
 		None,
@@ -85,3 +91,17 @@ pub(crate) async fn get_public_rooms_route(
 		total_room_count_estimate: response.total_room_count_estimate,
 	})
 }
+
+/// Rejects servers not in `public_room_directory_federation_allowlist`, unless
+/// it is empty.
+fn check_server_allowed(services: &Services, origin: &ServerName) -> Result {
+	let allowlist = &services
+		.config
+		.public_room_directory_federation_allowlist;
+
+	if !allowlist.is_empty() && !allowlist.is_match(origin.host()) {
+		return Err!(Request(Forbidden("Room directory is not public to this server")));
+	}
+
+	Ok(())
+}
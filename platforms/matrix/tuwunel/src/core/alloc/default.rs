@@ -10,3 +10,7 @@ pub fn memory_stats(_opts: &str) -> Option<String> { None }
 /// Always returns None
 #[must_use]
 pub fn memory_usage() -> Option<String> { None }
+
+/// Always returns None
+#[must_use]
+pub fn thread_allocated() -> Option<u64> { None }
@@ -109,6 +109,11 @@ pub fn memory_usage() -> Option<String> {
 //#[cfg(not(feature = "jemalloc_stats"))]
 pub fn memory_usage() -> Option<String> { None }
 
+/// Total bytes allocated by the calling thread since it started.
+#[inline]
+#[must_use]
+pub fn thread_allocated() -> Option<u64> { Some(this_thread::allocated()) }
+
 pub fn memory_stats(opts: &str) -> Option<String> {
 	const MAX_LENGTH: usize = 1_048_576;
 
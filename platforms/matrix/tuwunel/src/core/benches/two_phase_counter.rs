@@ -0,0 +1,69 @@
+use std::{
+	hint::black_box,
+	sync::{
+		Arc,
+		atomic::{AtomicU64, Ordering},
+	},
+	thread,
+};
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use tuwunel_core::{Result, utils::two_phase_counter::Counter};
+
+criterion_group!(benches, concurrent_sends);
+
+criterion_main!(benches);
+
+type Callback = Box<dyn Fn(u64) -> Result + Send + Sync>;
+
+/// Sequence numbers drawn by each sender per iteration.
+const SENDS: u64 = 1024;
+
+/// Senders drawing and retiring sequence numbers at once, as with concurrent
+/// sends to many rooms; compares one shard to several.
+fn concurrent_sends(c: &mut Criterion) {
+	let senders = thread::available_parallelism().map_or(4, |n| n.get().max(4));
+	let mut group = c.benchmark_group("two_phase_counter");
+	group.throughput(Throughput::Elements(
+		SENDS.saturating_mul(u64::try_from(senders).unwrap_or(1)),
+	));
+
+	for shards in [1, 4, 16] {
+		group.bench_with_input(BenchmarkId::new("shards", shards), &shards, |b, &shards| {
+			let counter = counter(shards);
+			b.iter(|| {
+				thread::scope(|s| {
+					for _ in 0..senders {
+						s.spawn(|| {
+							for _ in 0..SENDS {
+								let permit = counter.next().expect("sequence number");
+								black_box(*permit);
+							}
+						});
+					}
+				});
+			});
+		});
+	}
+
+	group.finish();
+}
+
+fn counter(shards: usize) -> Arc<Counter<Callback>> {
+	// Stand-ins for persisting the counter and notifying waiters.
+	let stored = Arc::new(AtomicU64::new(0));
+	let retired = Arc::new(AtomicU64::new(0));
+
+	Counter::with_shards(
+		0,
+		shards,
+		Box::new(move |count| {
+			stored.store(count, Ordering::Release);
+			Ok(())
+		}),
+		Box::new(move |count| {
+			retired.fetch_max(count, Ordering::AcqRel);
+			Ok(())
+		}),
+	)
+}
@@ -3,7 +3,7 @@
 use either::Either;
 use itertools::Itertools;
 
-use super::{DEPRECATED_KEYS, IdentityProvider};
+use super::{DEPRECATED_KEYS, ENDPOINT_GROUPS, IdentityProvider, LDAP_SYNC_REMOVED_ACTIONS};
 use crate::{Config, Err, Result, debug, debug_info, error, warn};
 
 /// Performs check() with additional checks specific to reloading old config
@@ -29,6 +29,25 @@ pub fn check(config: &Config) -> Result {
 	warn_deprecated(config);
 	warn_unknown_key(config)?;
 
+	if let Some(group) = config
+		.disabled_endpoints
+		.iter()
+		.find(|group| !ENDPOINT_GROUPS.contains(&group.as_str()))
+	{
+		return Err!(Config(
+			"disabled_endpoints",
+			"Unknown endpoint group {group:?}; expected any of {ENDPOINT_GROUPS:?}"
+		));
+	}
+
+	let action = &config.ldap.sync_removed_action;
+	if !LDAP_SYNC_REMOVED_ACTIONS.contains(&action.as_str()) {
+		return Err!(Config(
+			"ldap.sync_removed_action",
+			"Unknown action {action:?}; expected any of {LDAP_SYNC_REMOVED_ACTIONS:?}"
+		));
+	}
+
 	if config.sentry && config.sentry_endpoint.is_none() {
 		return Err!(Config(
 			"sentry_endpoint",
@@ -206,10 +225,27 @@ pub fn check(config: &Config) -> Result {
 		}
 	}
 
+	if config.registration_requires_email && config.smtp.url.is_none() {
+		return Err!(Config(
+			"registration_requires_email",
+			"Email addresses cannot be verified without the url of an SMTP server in the \
+			 [global.smtp] section."
+		));
+	}
+
+	if config.login_via_qr_code && (!config.login_via_existing_session || !config.login_via_token)
+	{
+		return Err!(Config(
+			"login_via_qr_code",
+			"Signing in with a QR code requires login_via_existing_session and login_via_token."
+		));
+	}
+
 	if config.allow_registration
 		&& !config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
 		&& config.registration_token_file.is_none()
+		&& !config.registration_requires_email
 	{
 		return Err!(Config(
 			"registration_token",
@@ -227,6 +263,7 @@ pub fn check(config: &Config) -> Result {
 		&& config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
 		&& config.registration_token_file.is_none()
+		&& !config.registration_requires_email
 	{
 		warn!(
 			"Open registration is enabled via setting \
@@ -294,6 +331,14 @@ pub fn check(config: &Config) -> Result {
 		));
 	}
 
+	if !matches!(config.key_expiry_policy.as_str(), "strict" | "grace") {
+		return Err!(Config(
+			"key_expiry_policy",
+			"Key expiry policy {:?} is not \"strict\" or \"grace\"",
+			config.key_expiry_policy
+		));
+	}
+
 	if !config.supported_room_version(&config.default_room_version) {
 		return Err!(Config(
 			"default_room_version",
@@ -402,7 +447,7 @@ fn warn_unknown_key(config: &Config) -> Result {
 		.catchall
 		.keys()
 		.filter_map(|key| {
-			if key == "config" {
+			if key == "config" || super::secret::is_indirection(key) {
 				None
 			} else {
 				if config.error_on_unknown_config_opts {
@@ -1,5 +1,5 @@
 use reqwest::{Proxy, Url};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::Result;
 
@@ -28,7 +28,7 @@
 /// be used if it was included because of a more specific rule than it was
 /// excluded. In the above example, the proxy would be used for
 /// `ordinary.onion`, `matrix.myspecial.onion`, but not `hello.myspecial.onion`.
-#[derive(Clone, Default, Debug, Deserialize)]
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ProxyConfig {
 	#[default]
@@ -55,7 +55,7 @@ pub fn to_proxy(&self) -> Result<Option<Proxy>> {
 	}
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PartialProxyConfig {
 	#[serde(deserialize_with = "crate::utils::deserialize_from_str")]
 	url: Url,
@@ -139,6 +139,18 @@ fn from_str(s: &str) -> Result<Self, Self::Err> {
 		})
 	}
 }
+impl Serialize for WildCardedDomain {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: serde::ser::Serializer,
+	{
+		match self {
+			| Self::WildCard => serializer.serialize_str("*"),
+			| Self::WildCarded(d) => serializer.collect_str(&format_args!("*{d}")),
+			| Self::Exact(d) => serializer.serialize_str(d),
+		}
+	}
+}
 impl<'de> Deserialize<'de> for WildCardedDomain {
 	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
 	where
@@ -0,0 +1,127 @@
+//! Indirection of secret options, so that secrets can stay out of the config
+//! file: any of [`SECRETS`] may instead be set as `<option>_env`, the name of
+//! an environment variable holding it, or `<option>_file`, the path of a file
+//! holding it. They are resolved when the config is loaded; a secret set more
+//! than one way is refused.
+
+use figment::{
+	Figment,
+	providers::Serialized,
+	value::{Dict, Value},
+};
+
+use crate::{Err, Result, err};
+
+/// Secret options by their path in the config; `*` stands for any entry of a
+/// table, such as each identity provider.
+const SECRETS: &[&str] = &[
+	"emergency_password",
+	"registration_token",
+	"sentry_endpoint",
+	"turn_password",
+	"turn_secret",
+	"appservice.*.as_token",
+	"appservice.*.hs_token",
+	"directory_publication.*.access_token",
+	"identity_provider.*.client_secret",
+	"jwt.key",
+	"smtp.url",
+];
+
+/// Options of their own naming the file of a secret, which is read again while
+/// the server runs; the indirection leaves them alone.
+const FILE_OPTIONS: &[&str] =
+	&["client_secret_file", "registration_token_file", "turn_secret_file"];
+
+/// The config with the secrets given indirectly resolved.
+pub(super) fn resolve(raw_config: &Figment) -> Result<Figment> {
+	let mut config: Dict = raw_config
+		.extract()
+		.map_err(|e| err!("There was a problem with your configuration file: {e}"))?;
+
+	let mut resolved = raw_config.clone();
+	for secret in SECRETS {
+		let (section, key) = match secret.split_once('.') {
+			| Some((section, path)) => (Some(section), path),
+			| None => (None, *secret),
+		};
+
+		let changed = match section {
+			| None => resolve_key(&mut config, key)?,
+			| Some(section) => match config.get_mut(section) {
+				| Some(value) => resolve_path(value, key)?,
+				| None => false,
+			},
+		};
+
+		if changed {
+			let top = section.unwrap_or(key);
+			resolved = resolved.merge(Serialized::global(top, &config[top]));
+		}
+	}
+
+	Ok(resolved)
+}
+
+/// Whether a top-level option only gives a secret indirectly.
+#[must_use]
+pub(super) fn is_indirection(key: &str) -> bool {
+	key.strip_suffix("_env")
+		.or_else(|| key.strip_suffix("_file"))
+		.is_some_and(|base| SECRETS.contains(&base))
+}
+
+fn resolve_path(value: &mut Value, path: &str) -> Result<bool> {
+	let Value::Dict(_, dict) = value else {
+		return Ok(false);
+	};
+
+	match path.split_once('.') {
+		| None => resolve_key(dict, path),
+		| Some(("*", rest)) => dict
+			.values_mut()
+			.try_fold(false, |changed, value| Ok(resolve_path(value, rest)? || changed)),
+		| Some((section, rest)) => match dict.get_mut(section) {
+			| Some(value) => resolve_path(value, rest),
+			| None => Ok(false),
+		},
+	}
+}
+
+fn resolve_key(dict: &mut Dict, key: &str) -> Result<bool> {
+	let env = format!("{key}_env");
+	let file = format!("{key}_file");
+	let file = (!FILE_OPTIONS.contains(&file.as_str())).then_some(file);
+
+	let env = dict.get(&env).and_then(Value::as_str);
+	let file = file
+		.as_ref()
+		.and_then(|file| dict.get(file))
+		.and_then(Value::as_str);
+
+	let secret = match (env, file) {
+		| (None, None) => return Ok(false),
+		| (Some(_), Some(_)) => {
+			return Err!("Set only one of `{key}_env` and `{key}_file` in your config.");
+		},
+		| (Some(var), None) => std::env::var(var).map_err(|e| {
+			err!("Failed to read `{key}` from the environment variable `{var}`: {e}")
+		})?,
+		| (None, Some(path)) => std::fs::read_to_string(path)
+			.map_err(|e| err!("Failed to read `{key}` from the file `{path}`: {e}"))?
+			.trim_end_matches(['\r', '\n'])
+			.to_owned(),
+	};
+
+	if dict.contains_key(key) {
+		return Err!("Set `{key}` either directly or indirectly in your config, not both.");
+	}
+
+	if secret.is_empty() {
+		return Err!("The secret `{key}` given indirectly in your config is empty.");
+	}
+
+	dict.insert(key.to_owned(), Value::from(secret));
+
+	Ok(true)
+}
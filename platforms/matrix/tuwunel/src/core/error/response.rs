@@ -73,6 +73,7 @@ pub(super) fn bad_request_code(kind: &ErrorKind) -> StatusCode {
 		| GuestAccessForbidden
 		| ThreepidAuthFailed
 		| UserDeactivated
+		| UserSuspended
 		| ThreepidDenied
 		| WrongRoomKeysVersion { .. }
 		| Forbidden { .. } => StatusCode::FORBIDDEN,
@@ -0,0 +1,130 @@
+use std::{
+	cell::Cell,
+	collections::VecDeque,
+	future::{Future, poll_fn},
+	pin::pin,
+	sync::Mutex,
+	time::{Duration, Instant, SystemTime},
+};
+
+use crate::alloc;
+
+thread_local! {
+	/// Database reads made by the calling thread.
+	static DB_READS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Resources consumed by a unit of work such as a request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Budget {
+	/// Wall time from the first poll until completion.
+	pub elapsed: Duration,
+
+	/// Database reads made while polled.
+	pub db_reads: u64,
+
+	/// Bytes allocated while polled; None when the allocator does not count
+	/// them. Allocations by other threads on behalf of the work, such as
+	/// database pool workers, are not included.
+	pub allocated: Option<u64>,
+}
+
+/// A request which exceeded a threshold of the slow request log.
+#[derive(Clone, Debug)]
+pub struct SlowRequest {
+	pub received: SystemTime,
+	pub method: String,
+	pub path: String,
+	pub status: u16,
+	pub budget: Budget,
+}
+
+/// The most recent slow requests, oldest first.
+#[derive(Debug, Default)]
+pub struct SlowRequests {
+	entries: Mutex<VecDeque<SlowRequest>>,
+}
+
+/// Counts database reads against the calling thread.
+#[inline]
+pub fn count_db_reads(num: u64) { DB_READS.set(DB_READS.get().saturating_add(num)); }
+
+/// Database reads made by the calling thread since it started.
+#[inline]
+#[must_use]
+pub fn db_reads() -> u64 { DB_READS.get() }
+
+impl Budget {
+	/// Drives `fut` to completion, measuring the resources it consumes. The
+	/// per-thread counters are sampled around each poll so the measurement
+	/// follows the task across worker threads.
+	pub async fn measure<F: Future>(fut: F) -> (F::Output, Self) {
+		let mut fut = pin!(fut);
+		let mut budget = Self {
+			allocated: alloc::thread_allocated().map(|_| 0),
+			..Default::default()
+		};
+
+		let started = Instant::now();
+		let output = poll_fn(|cx| {
+			let (reads, allocated) = (db_reads(), alloc::thread_allocated());
+			let poll = fut.as_mut().poll(cx);
+
+			budget.db_reads = budget
+				.db_reads
+				.saturating_add(db_reads().saturating_sub(reads));
+
+			budget.allocated = budget
+				.allocated
+				.zip(alloc::thread_allocated().zip(allocated))
+				.map(|(total, (after, before))| {
+					total.saturating_add(after.saturating_sub(before))
+				});
+
+			poll
+		})
+		.await;
+
+		budget.elapsed = started.elapsed();
+		(output, budget)
+	}
+
+	/// Whether any of the thresholds is exceeded; a threshold of zero is not
+	/// considered.
+	#[must_use]
+	pub fn exceeds(&self, elapsed: Duration, db_reads: u64, allocated: u64) -> bool {
+		(!elapsed.is_zero() && self.elapsed > elapsed)
+			|| (db_reads > 0 && self.db_reads > db_reads)
+			|| (allocated > 0
+				&& self
+					.allocated
+					.is_some_and(|bytes| bytes > allocated))
+	}
+}
+
+impl SlowRequests {
+	/// Keeps a slow request, forgetting the oldest beyond `capacity`.
+	pub fn push(&self, request: SlowRequest, capacity: usize) {
+		let mut entries = self.entries.lock().expect("locked");
+		while entries.len() >= capacity.max(1) {
+			entries.pop_front();
+		}
+
+		if capacity > 0 {
+			entries.push_back(request);
+		}
+	}
+
+	/// The kept slow requests, oldest first.
+	#[must_use]
+	pub fn list(&self) -> Vec<SlowRequest> {
+		self.entries
+			.lock()
+			.expect("locked")
+			.iter()
+			.cloned()
+			.collect()
+	}
+
+	pub fn clear(&self) { self.entries.lock().expect("locked").clear(); }
+}
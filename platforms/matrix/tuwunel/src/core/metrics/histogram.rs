@@ -0,0 +1,69 @@
+use std::{
+	sync::atomic::{AtomicU64, Ordering},
+	time::{Duration, Instant},
+};
+
+/// Upper bounds in seconds of the buckets durations are sorted into.
+pub const BUCKETS: [f64; 12] =
+	[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Distribution of durations in the manner of a Prometheus histogram.
+#[derive(Debug, Default)]
+pub struct Histogram {
+	/// Non-cumulative count of observations for each of `BUCKETS`; the last
+	/// slot counts observations beyond the largest bound.
+	buckets: [AtomicU64; BUCKETS.len() + 1],
+	sum_micros: AtomicU64,
+	count: AtomicU64,
+}
+
+/// Observes the time elapsed since its creation when dropped.
+pub struct Timer<'a> {
+	histogram: &'a Histogram,
+	started: Instant,
+}
+
+impl Histogram {
+	pub fn observe(&self, duration: Duration) {
+		let secs = duration.as_secs_f64();
+		let bucket = BUCKETS
+			.iter()
+			.position(|&bound| secs <= bound)
+			.unwrap_or(BUCKETS.len());
+
+		let micros = duration
+			.as_micros()
+			.try_into()
+			.unwrap_or(u64::MAX);
+		self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+		self.sum_micros
+			.fetch_add(micros, Ordering::Relaxed);
+		self.count.fetch_add(1, Ordering::Relaxed);
+	}
+
+	#[must_use]
+	pub fn timer(&self) -> Timer<'_> { Timer { histogram: self, started: Instant::now() } }
+
+	/// Cumulative count of observations at or below each of `BUCKETS`.
+	pub fn cumulative(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+		BUCKETS
+			.iter()
+			.zip(self.buckets.iter())
+			.scan(0_u64, |total, (&bound, count)| {
+				*total = total.saturating_add(count.load(Ordering::Relaxed));
+				Some((bound, *total))
+			})
+	}
+
+	#[must_use]
+	pub fn sum(&self) -> Duration {
+		Duration::from_micros(self.sum_micros.load(Ordering::Relaxed))
+	}
+
+	#[must_use]
+	pub fn count(&self) -> u64 { self.count.load(Ordering::Relaxed) }
+}
+
+impl Drop for Timer<'_> {
+	fn drop(&mut self) { self.histogram.observe(self.started.elapsed()); }
+}
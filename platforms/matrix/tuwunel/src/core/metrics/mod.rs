@@ -1,3 +1,7 @@
+mod budget;
+mod histogram;
+pub mod prometheus;
+
 use std::sync::atomic::{AtomicU32, AtomicU64};
 
 use tokio::runtime;
@@ -5,6 +9,11 @@
 #[cfg(tokio_unstable)]
 use tokio_metrics::{RuntimeIntervals, RuntimeMonitor};
 
+pub use self::{
+	budget::{Budget, SlowRequest, SlowRequests, count_db_reads, db_reads},
+	histogram::{BUCKETS, Histogram, Timer},
+};
+
 pub struct Metrics {
 	_runtime: Option<runtime::Handle>,
 
@@ -23,6 +32,20 @@ pub struct Metrics {
 	pub requests_handle_finished: AtomicU64,
 	pub requests_handle_active: AtomicU32,
 	pub requests_panic: AtomicU32,
+
+	/// Time taken to answer sync requests, including long-polling.
+	pub sync_latency: Histogram,
+
+	/// Requests which exceeded a threshold of the slow request log.
+	pub slow_requests: SlowRequests,
+
+	/// Federation transactions sent to remote servers.
+	pub federation_send_success: AtomicU64,
+	pub federation_send_failure: AtomicU64,
+
+	/// Notifications delivered to push gateways.
+	pub pusher_success: AtomicU64,
+	pub pusher_failure: AtomicU64,
 }
 
 impl Metrics {
@@ -53,6 +76,16 @@ pub fn new(runtime: Option<runtime::Handle>) -> Self {
 			requests_handle_finished: AtomicU64::new(0),
 			requests_handle_active: AtomicU32::new(0),
 			requests_panic: AtomicU32::new(0),
+
+			sync_latency: Histogram::default(),
+
+			slow_requests: SlowRequests::default(),
+
+			federation_send_success: AtomicU64::new(0),
+			federation_send_failure: AtomicU64::new(0),
+
+			pusher_success: AtomicU64::new(0),
+			pusher_failure: AtomicU64::new(0),
 		}
 	}
 
@@ -0,0 +1,85 @@
+//! Rendering of metrics in the Prometheus text exposition format.
+
+use std::fmt::{Display, Write};
+
+use super::Histogram;
+
+/// Accumulates metric families into the text format.
+#[derive(Debug, Default)]
+pub struct Encoder {
+	out: String,
+}
+
+impl Encoder {
+	#[must_use]
+	pub fn new() -> Self { Self::default() }
+
+	pub fn counter(&mut self, name: &str, help: &str, value: impl Display) {
+		self.header(name, help, "counter");
+		self.sample(name, "", value);
+	}
+
+	pub fn gauge(&mut self, name: &str, help: &str, value: impl Display) {
+		self.header(name, help, "gauge");
+		self.sample(name, "", value);
+	}
+
+	/// Counter with one sample per value of a single label.
+	pub fn labeled_counter<'a, I, V>(&mut self, name: &str, help: &str, label: &str, samples: I)
+	where
+		I: IntoIterator<Item = (&'a str, V)>,
+		V: Display,
+	{
+		self.header(name, help, "counter");
+		for (value, sample) in samples {
+			let labels = format!("{{{label}=\"{}\"}}", escape(value));
+			self.sample(name, &labels, sample);
+		}
+	}
+
+	/// Gauge with one sample per value of a single label.
+	pub fn labeled_gauge<'a, I, V>(&mut self, name: &str, help: &str, label: &str, samples: I)
+	where
+		I: IntoIterator<Item = (&'a str, V)>,
+		V: Display,
+	{
+		self.header(name, help, "gauge");
+		for (value, sample) in samples {
+			let labels = format!("{{{label}=\"{}\"}}", escape(value));
+			self.sample(name, &labels, sample);
+		}
+	}
+
+	pub fn histogram(&mut self, name: &str, help: &str, histogram: &Histogram) {
+		self.header(name, help, "histogram");
+
+		let bucket = format!("{name}_bucket");
+		for (bound, count) in histogram.cumulative() {
+			self.sample(&bucket, &format!("{{le=\"{bound}\"}}"), count);
+		}
+
+		self.sample(&bucket, "{le=\"+Inf\"}", histogram.count());
+		self.sample(&format!("{name}_sum"), "", histogram.sum().as_secs_f64());
+		self.sample(&format!("{name}_count"), "", histogram.count());
+	}
+
+	#[must_use]
+	pub fn finish(self) -> String { self.out }
+
+	fn header(&mut self, name: &str, help: &str, kind: &str) {
+		writeln!(self.out, "# HELP {name} {help}").expect("write to string");
+		writeln!(self.out, "# TYPE {name} {kind}").expect("write to string");
+	}
+
+	fn sample(&mut self, name: &str, labels: &str, value: impl Display) {
+		writeln!(self.out, "{name}{labels} {value}").expect("write to string");
+	}
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
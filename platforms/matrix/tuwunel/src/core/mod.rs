@@ -9,6 +9,7 @@
 pub mod matrix;
 pub mod metrics;
 pub mod mods;
+pub mod pagination;
 pub mod server;
 pub mod utils;
 
@@ -28,7 +29,7 @@
 pub use matrix::{
 	Event, EventTypeExt, Pdu, PduCount, PduEvent, PduId, RoomVersion, pdu, state_res,
 };
-pub use server::Server;
+pub use server::{Phase, Server};
 pub use utils::{ctor, dtor, implement, result, result::Result};
 
 pub use crate as tuwunel_core;
@@ -0,0 +1,209 @@
+//! Pagination tokens handed to clients.
+//!
+//! A token records a stream position, the direction it was issued for and
+//! when it was issued, followed by an HMAC over all of it:
+//! `{VERSION}.{dir}.{created}.{pos}.{mac}`. Positions are rendered with their
+//! `Display` implementation, so a token issued for one position type can be
+//! read back as any other sharing its textual form, e.g. a sync `next_batch`
+//! is a valid `from` for `/messages` forwards. Tokens are only accepted for
+//! the direction they were issued for, except as the `to` bound.
+
+use std::{fmt::Display, str::FromStr};
+
+use ring::hmac;
+use ruma::{
+	api::Direction,
+	serde::{Base64, base64::UrlSafe},
+};
+
+use crate::{Err, Result, err, utils::time::now_secs};
+
+/// Leading component of every signed token; bumped when the layout changes.
+pub const VERSION: &str = "p1";
+
+const SEP: char = '.';
+
+/// Key tokens are signed with.
+pub type Key = hmac::Key;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Token<T> {
+	/// Stream position.
+	pub pos: T,
+
+	/// Direction the token was issued for.
+	pub dir: Direction,
+
+	/// Seconds since the unix epoch when the token was issued; zero for
+	/// unsigned legacy tokens.
+	pub created: u64,
+}
+
+/// Create a signing key from a secret.
+#[must_use]
+pub fn key(secret: &[u8]) -> Key { hmac::Key::new(hmac::HMAC_SHA256, secret) }
+
+/// Whether `token` has the form of a signed token, as opposed to a legacy raw
+/// position.
+#[inline]
+#[must_use]
+pub fn is_signed(token: &str) -> bool {
+	token
+		.split_once(SEP)
+		.is_some_and(|(version, _)| version == VERSION)
+}
+
+impl<T> Token<T> {
+	#[must_use]
+	pub fn new(pos: T, dir: Direction) -> Self { Self { pos, dir, created: now_secs() } }
+
+	/// Interpret a legacy token which is only the raw position. The result
+	/// carries no integrity guarantee.
+	pub fn legacy(token: &str, dir: Direction) -> Result<Self>
+	where
+		T: FromStr,
+	{
+		let pos = token
+			.parse()
+			.map_err(|_| err!(Request(InvalidParam("Invalid pagination token."))))?;
+
+		Ok(Self { pos, dir, created: 0 })
+	}
+
+	/// Verify and decode a signed token.
+	pub fn verify(token: &str, key: &Key) -> Result<Self>
+	where
+		T: FromStr,
+	{
+		let Some((payload, mac)) = token.rsplit_once(SEP) else {
+			return Err!(Request(InvalidParam("Invalid pagination token.")));
+		};
+
+		let mac = Base64::<UrlSafe>::parse(mac)
+			.map_err(|_| err!(Request(InvalidParam("Invalid pagination token."))))?;
+
+		hmac::verify(key, payload.as_bytes(), mac.as_bytes())
+			.map_err(|_| err!(Request(InvalidParam("Pagination token failed verification."))))?;
+
+		let mut parts = payload.splitn(4, SEP);
+		let (Some(VERSION), Some(dir), Some(created), Some(pos)) =
+			(parts.next(), parts.next(), parts.next(), parts.next())
+		else {
+			return Err!(Request(InvalidParam("Unsupported pagination token.")));
+		};
+
+		let dir = match dir {
+			| "f" => Direction::Forward,
+			| "b" => Direction::Backward,
+			| _ => return Err!(Request(InvalidParam("Invalid pagination token direction."))),
+		};
+
+		let created = created
+			.parse()
+			.map_err(|_| err!(Request(InvalidParam("Invalid pagination token."))))?;
+
+		let pos = pos
+			.parse()
+			.map_err(|_| err!(Request(InvalidParam("Invalid pagination token position."))))?;
+
+		Ok(Self { pos, dir, created })
+	}
+
+	/// Ensure the token was issued for paginating in `dir`. Legacy tokens carry
+	/// the direction they were parsed for and always pass.
+	pub fn check_dir(self, dir: Direction) -> Result<Self> {
+		if self.dir != dir {
+			return Err!(Request(InvalidParam(
+				"Pagination token was issued for the other direction."
+			)));
+		}
+
+		Ok(self)
+	}
+
+	/// Encode and sign the token.
+	#[must_use]
+	pub fn sign(&self, key: &Key) -> String
+	where
+		T: Display,
+	{
+		let dir = match self.dir {
+			| Direction::Forward => "f",
+			| Direction::Backward => "b",
+		};
+
+		let payload = format!("{VERSION}{SEP}{dir}{SEP}{}{SEP}{}", self.created, self.pos);
+		let mac = hmac::sign(key, payload.as_bytes());
+		let mac = Base64::<UrlSafe, _>::new(mac.as_ref()).encode();
+
+		format!("{payload}{SEP}{mac}")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::api::Direction;
+
+	use super::{Token, is_signed, key};
+	use crate::PduCount;
+
+	#[test]
+	fn round_trip() {
+		let key = key(b"secret");
+		let token = Token::new(PduCount::Backfilled(-42), Direction::Backward);
+		let signed = token.sign(&key);
+
+		assert!(is_signed(&signed));
+		assert_eq!(Token::verify(&signed, &key).ok(), Some(token));
+	}
+
+	#[test]
+	fn position_types_interchange() {
+		let key = key(b"secret");
+		let signed = Token::new(1234_u64, Direction::Forward).sign(&key);
+		let token: Token<PduCount> = Token::verify(&signed, &key).expect("valid token");
+
+		assert_eq!(token.pos, PduCount::Normal(1234));
+	}
+
+	#[test]
+	fn forged_position_rejected() {
+		let key = key(b"secret");
+		let signed = Token::new(10_u64, Direction::Forward).sign(&key);
+		let forged = signed.replacen(".10.", ".99.", 1);
+
+		assert!(Token::<u64>::verify(&forged, &key).is_err());
+	}
+
+	#[test]
+	fn other_key_rejected() {
+		let signed = Token::new(10_u64, Direction::Forward).sign(&key(b"secret"));
+
+		assert!(Token::<u64>::verify(&signed, &key(b"other")).is_err());
+	}
+
+	#[test]
+	fn direction_checked() {
+		let key = key(b"secret");
+		let signed = Token::new(10_u64, Direction::Forward).sign(&key);
+		let token: Token<u64> = Token::verify(&signed, &key).expect("valid token");
+
+		assert!(
+			token
+				.clone()
+				.check_dir(Direction::Forward)
+				.is_ok()
+		);
+		assert!(token.check_dir(Direction::Backward).is_err());
+	}
+
+	#[test]
+	fn legacy() {
+		assert!(!is_signed("1234"));
+		assert!(!is_signed("-1234"));
+
+		let token: Token<u64> = Token::legacy("1234", Direction::Forward).expect("valid token");
+		assert_eq!(token.pos, 1234);
+		assert_eq!(token.created, 0);
+	}
+}
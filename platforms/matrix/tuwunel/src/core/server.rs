@@ -1,7 +1,7 @@
 use std::{
 	sync::{
 		Arc,
-		atomic::{AtomicBool, Ordering},
+		atomic::{AtomicBool, AtomicU8, Ordering},
 	},
 	time::SystemTime,
 };
@@ -45,6 +45,28 @@ pub struct Server {
 
 	/// Metrics subsystem state
 	pub metrics: Metrics,
+
+	/// Startup phase; see `Phase`.
+	phase: AtomicU8,
+
+	/// Percent of the database migrations completed.
+	migration_progress: AtomicU8,
+}
+
+/// Phase of the server reported by health checks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phase {
+	/// Opening the database and starting services.
+	Starting,
+
+	/// Migrating the database; see `Server::migration_progress`.
+	Migrating,
+
+	/// Serving requests.
+	Ready,
+
+	/// Shutting down, reloading or restarting.
+	Stopping,
 }
 
 impl Server {
@@ -61,6 +83,8 @@ pub fn new(config: Config, runtime: Option<runtime::Handle>, log: Logging) -> Se
 			signal: broadcast::channel::<&'static str>(1).0,
 			log,
 			metrics: Metrics::new(runtime),
+			phase: AtomicU8::new(Phase::Starting.index()),
+			migration_progress: AtomicU8::new(0),
 		}
 	}
 
@@ -146,4 +170,67 @@ pub fn is_restarting(&self) -> bool { self.restarting.load(Ordering::Relaxed) }
 
 	#[inline]
 	pub fn is_ours(&self, name: &str) -> bool { name == self.config.server_name }
+
+	pub fn set_phase(&self, phase: Phase) {
+		if phase == Phase::Migrating {
+			self.migration_progress
+				.store(0, Ordering::Relaxed);
+		}
+
+		self.phase.store(phase.index(), Ordering::Release);
+	}
+
+	/// Current phase; stopping as soon as shutdown is requested.
+	#[must_use]
+	pub fn phase(&self) -> Phase {
+		if self.is_stopping() {
+			return Phase::Stopping;
+		}
+
+		match self.phase.load(Ordering::Acquire) {
+			| 0 => Phase::Starting,
+			| 1 => Phase::Migrating,
+			| 2 => Phase::Ready,
+			| _ => Phase::Stopping,
+		}
+	}
+
+	/// Records that `done` of `total` migration steps have completed.
+	pub fn set_migration_progress(&self, done: usize, total: usize) {
+		let percent = done
+			.saturating_mul(100)
+			.checked_div(total)
+			.unwrap_or(100)
+			.min(100);
+
+		let percent = u8::try_from(percent).unwrap_or(100);
+		self.migration_progress
+			.store(percent, Ordering::Relaxed);
+	}
+
+	/// Percent of the database migrations completed.
+	#[inline]
+	#[must_use]
+	pub fn migration_progress(&self) -> u8 { self.migration_progress.load(Ordering::Relaxed) }
+}
+
+impl Phase {
+	#[must_use]
+	pub fn as_str(self) -> &'static str {
+		match self {
+			| Self::Starting => "starting",
+			| Self::Migrating => "migrating",
+			| Self::Ready => "ready",
+			| Self::Stopping => "stopping",
+		}
+	}
+
+	const fn index(self) -> u8 {
+		match self {
+			| Self::Starting => 0,
+			| Self::Migrating => 1,
+			| Self::Ready => 2,
+			| Self::Stopping => 3,
+		}
+	}
 }
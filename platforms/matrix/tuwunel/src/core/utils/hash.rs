@@ -1,10 +1,24 @@
 mod argon;
+mod bcrypt;
 pub mod sha256;
 
 use crate::Result;
 
+/// Verifies a password against its Argon2 hash, or a bcrypt hash imported from
+/// another system.
 pub fn verify_password(password: &str, password_hash: &str) -> Result {
+	if bcrypt::is_hash(password_hash) {
+		return bcrypt::verify_password(password, password_hash);
+	}
+
 	argon::verify_password(password, password_hash)
 }
 
 pub fn password(password: &str) -> Result<String> { argon::password(password) }
+
+/// Whether a string is an Argon2 PHC string or a bcrypt hash which passwords
+/// can be verified against.
+#[must_use]
+pub fn is_password_hash(password_hash: &str) -> bool {
+	argon::is_hash(password_hash) || bcrypt::is_hash(password_hash)
+}
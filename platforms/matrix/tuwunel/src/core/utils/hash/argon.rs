@@ -44,6 +44,11 @@ pub(super) fn verify_password(password: &str, password_hash: &str) -> Result {
 		.map_err(map_err)
 }
 
+pub(super) fn is_hash(password_hash: &str) -> bool {
+	PasswordHash::new(password_hash)
+		.is_ok_and(|hash| hash.algorithm.as_str().starts_with("argon2"))
+}
+
 fn map_err(e: password_hash::Error) -> Error { err!("{e}") }
 
 #[cfg(test)]
@@ -0,0 +1,33 @@
+use crate::{Result, err};
+
+/// Prefixes of the modular crypt formats of bcrypt hashes.
+const PREFIXES: [&str; 3] = ["$2a$", "$2b$", "$2y$"];
+
+pub(super) fn is_hash(password_hash: &str) -> bool {
+	PREFIXES
+		.iter()
+		.any(|prefix| password_hash.starts_with(prefix))
+		&& password_hash
+			.parse::<::bcrypt::HashParts>()
+			.is_ok()
+}
+
+pub(super) fn verify_password(password: &str, password_hash: &str) -> Result {
+	match ::bcrypt::verify(password, password_hash) {
+		| Ok(true) => Ok(()),
+		| Ok(false) => Err(err!("invalid password")),
+		| Err(e) => Err(err!("{e}")),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	#[test]
+	fn bcrypt_verify() {
+		use crate::utils::hash;
+		let digest = ::bcrypt::hash("temp123", 4).expect("digest");
+		assert!(hash::is_password_hash(&digest));
+		hash::verify_password("temp123", &digest).expect("verified");
+		hash::verify_password("temp321", &digest).expect_err("unverified");
+	}
+}
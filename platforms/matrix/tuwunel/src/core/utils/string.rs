@@ -1,5 +1,6 @@
 mod between;
 pub mod de;
+pub mod sanitize;
 mod split;
 mod tests;
 mod unquote;
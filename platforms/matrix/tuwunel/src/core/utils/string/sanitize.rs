@@ -0,0 +1,80 @@
+//! Normalization of text chosen by users and shown to others, such as display
+//! names and room names, against invisible or disruptive characters.
+
+/// Characters without width or meaning of their own, which only serve to make
+/// names invisible or look alike.
+const INVISIBLE: [char; 4] = ['\u{200B}', '\u{2060}', '\u{FEFF}', '\u{180E}'];
+
+/// Zero-width joiners, which combine emoji and shape some scripts; one is
+/// kept of consecutive ones.
+const JOINERS: [char; 2] = ['\u{200C}', '\u{200D}'];
+
+/// Removes control characters, except newlines and tabs when `multiline`, and
+/// invisible characters; collapses runs of zero-width joiners into one and
+/// trims them and whitespace from the ends.
+#[must_use]
+pub fn sanitize(s: &str, multiline: bool) -> String {
+	let mut out = String::with_capacity(s.len());
+	let mut joined = false;
+	for c in s.chars() {
+		if c.is_control() && !(multiline && matches!(c, '\n' | '\t')) {
+			continue;
+		}
+
+		if INVISIBLE.contains(&c) || (joined && JOINERS.contains(&c)) {
+			continue;
+		}
+
+		joined = JOINERS.contains(&c);
+		out.push(c);
+	}
+
+	let trimmed = out.trim_matches(|c: char| c.is_whitespace() || JOINERS.contains(&c));
+	if trimmed.len() == out.len() {
+		out
+	} else {
+		trimmed.to_owned()
+	}
+}
+
+/// Whether the text consists only of emoji, apart from whitespace. Empty text
+/// does not.
+#[must_use]
+pub fn is_emoji_only(s: &str) -> bool {
+	let mut emoji = false;
+	for c in s.chars().filter(|c| !c.is_whitespace()) {
+		match c {
+			// Modifiers and joiners of emoji
+			| '\u{200D}' | '\u{20E3}' | '\u{FE0E}' | '\u{FE0F}' | '\u{E0020}'..='\u{E007F}' => {},
+			| '\u{00A9}'
+			| '\u{00AE}'
+			| '\u{203C}'
+			| '\u{2049}'
+			| '\u{2122}'
+			| '\u{2139}'
+			| '\u{2194}'..='\u{21AA}'
+			| '\u{2300}'..='\u{23FF}'
+			| '\u{24C2}'
+			| '\u{25A0}'..='\u{27BF}'
+			| '\u{2934}'..='\u{2935}'
+			| '\u{2B00}'..='\u{2BFF}'
+			| '\u{3030}'
+			| '\u{303D}'
+			| '\u{3297}'
+			| '\u{3299}'
+			| '\u{1F000}'..='\u{1FAFF}' => emoji = true,
+			| _ => return false,
+		}
+	}
+
+	emoji
+}
+
+/// The text cut to at most `max` characters.
+#[must_use]
+pub fn truncate_chars(s: &str, max: usize) -> &str {
+	s.char_indices()
+		.nth(max)
+		.and_then(|(end, _)| s.get(..end))
+		.unwrap_or(s)
+}
@@ -68,3 +68,48 @@ fn between_infallible() {
 	assert_eq!("\"foo".between_infallible(("\"", "\"")), "\"foo");
 	assert_eq!("foo".between_infallible(("\"", "\"")), "foo");
 }
+
+#[test]
+fn sanitize_control() {
+	use super::sanitize::sanitize;
+
+	assert_eq!(sanitize("foo\u{7}\nbar\u{1b}", false), "foobar");
+	assert_eq!(sanitize("foo\u{7}\n\tbar", true), "foo\n\tbar");
+	assert_eq!(sanitize("  foo bar \n", true), "foo bar");
+}
+
+#[test]
+fn sanitize_zero_width() {
+	use super::sanitize::sanitize;
+
+	assert_eq!(sanitize("f\u{200B}o\u{FEFF}o", false), "foo");
+	assert_eq!(
+		sanitize("\u{200D}\u{200D}foo\u{200C}\u{200C}\u{200D}bar\u{200D}", false),
+		"foo\u{200C}bar"
+	);
+	assert_eq!(sanitize("\u{200B}\u{200B}\u{2060}", false), "");
+
+	// Family emoji keep their joiners
+	let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+	assert_eq!(sanitize(family, false), family);
+}
+
+#[test]
+fn emoji_only() {
+	use super::sanitize::is_emoji_only;
+
+	assert!(is_emoji_only("\u{1F600}"));
+	assert!(is_emoji_only("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467} \u{2764}\u{FE0F}"));
+	assert!(!is_emoji_only("foo \u{1F600}"));
+	assert!(!is_emoji_only("\u{FE0F}"));
+	assert!(!is_emoji_only(""));
+}
+
+#[test]
+fn truncate_chars() {
+	use super::sanitize::truncate_chars;
+
+	assert_eq!(truncate_chars("foobar", 3), "foo");
+	assert_eq!(truncate_chars("foo", 3), "foo");
+	assert_eq!(truncate_chars("\u{e9}\u{e9}\u{e9}\u{e9}", 2), "\u{e9}\u{e9}");
+}
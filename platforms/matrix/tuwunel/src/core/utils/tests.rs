@@ -317,3 +317,66 @@ async fn set_difference_sorted_stream2() {
 	println!("{r:?}");
 	assert!(r.eq(&["aaa", "eee", "hhh"]));
 }
+
+#[test]
+fn two_phase_counter_rollback() {
+	use std::sync::{
+		Arc,
+		atomic::{AtomicBool, AtomicU64, Ordering},
+	};
+
+	use crate::{Err, Result, utils::two_phase_counter::Counter};
+
+	type Callback = Box<dyn Fn(u64) -> Result + Send + Sync>;
+
+	for shards in [1, 4] {
+		let failing = Arc::new(AtomicBool::new(false));
+		let released = Arc::new(AtomicU64::new(0));
+		let counter = Counter::<Callback>::with_shards(
+			0,
+			shards,
+			Box::new({
+				let failing = failing.clone();
+				move |_| {
+					if failing.load(Ordering::SeqCst) {
+						return Err!("commit failed");
+					}
+
+					Ok(())
+				}
+			}),
+			Box::new({
+				let released = released.clone();
+				move |count| {
+					released.store(count, Ordering::SeqCst);
+					Ok(())
+				}
+			}),
+		);
+
+		drop(counter.next().expect("sequence number"));
+		assert_eq!(counter.current(), 1);
+		assert_eq!(released.load(Ordering::SeqCst), 1);
+
+		// Numbers already persisted are still drawn; the first which is not
+		// fails without being dispatched.
+		failing.store(true, Ordering::SeqCst);
+		let mut permits = Vec::new();
+		while let Ok(permit) = counter.next() {
+			permits.push(permit);
+		}
+
+		let dispatched = counter.dispatched();
+		let drawn = u64::try_from(permits.len()).expect("permits");
+		assert_eq!(dispatched, drawn.saturating_add(1));
+		assert_eq!(counter.current(), 1);
+
+		drop(permits);
+		assert_eq!(counter.current(), dispatched);
+		assert_eq!(released.load(Ordering::SeqCst), dispatched);
+
+		failing.store(false, Ordering::SeqCst);
+		let next = counter.next().expect("sequence number");
+		assert_eq!(*next, dispatched.saturating_add(1));
+	}
+}
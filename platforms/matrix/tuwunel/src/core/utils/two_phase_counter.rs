@@ -3,7 +3,10 @@
 use std::{
 	collections::VecDeque,
 	ops::{Deref, Range},
-	sync::{Arc, RwLock},
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+	},
 };
 
 use crate::{Result, checked, is_equal_to};
@@ -22,25 +25,44 @@
 /// value, but that value has no Pdu found because its write has not been
 /// completed with global visibility. Client-sync will then move on to the next
 /// counter value having missed the data from the current one.
+///
+/// Contention: every writer draws a number and every retirement advances the
+/// retirement value. The pending numbers are divided among shards so that
+/// concurrent writers do not all wait on one lock, and retirements are
+/// combined: when several complete at once, one of them computes and
+/// publishes the retirement value on behalf of the others.
 pub struct Counter<F: Fn(u64) -> Result + Sync> {
-	/// Self is intended to be Arc<Counter> with inner state mutable via Lock.
-	inner: RwLock<State<F>>,
-}
-
-/// Inner protected state for Two-Phase Counter.
-pub struct State<F: Fn(u64) -> Result + Sync> {
 	/// Monotonic counter. The next sequence number is drawn by adding one to
-	/// this value. That number will be persisted and added to `pending`.
-	dispatched: u64,
+	/// this value. That number will be persisted and added to a shard.
+	dispatched: AtomicU64,
+
+	/// Highest sequence number persisted by `commit`. Numbers are persisted
+	/// in blocks of one per shard, so that writers do not persist every one.
+	reserved: AtomicU64,
+
+	/// Held while persisting the next block of sequence numbers.
+	reserving: Mutex<()>,
 
 	/// Callback to persist the next sequence number drawn from `dispatched`.
 	/// This prevents pending numbers from being reused after server restart.
 	commit: F,
 
-	/// List of pending sequence numbers. One less than the minimum value in
-	/// this list is the "retirement" sequence number where all writes have
+	/// Lists of pending sequence numbers. One less than the minimum value in
+	/// all lists is the "retirement" sequence number where all writes have
 	/// completed and all reads are globally visible.
-	pending: VecDeque<u64>,
+	shards: Box<[Mutex<VecDeque<u64>>]>,
+
+	/// Shard to which the next sequence number is added.
+	next_shard: AtomicUsize,
+
+	/// Held by the retirement publishing on behalf of the others.
+	publisher: Mutex<()>,
+
+	/// Set when a retirement has yet to be seen by the publisher.
+	dirty: AtomicBool,
+
+	/// Last retirement value published.
+	released: AtomicU64,
 
 	/// Callback to notify updates of the retirement value. This is likely
 	/// called from the destructor of a permit/guard; try not to panic.
@@ -57,6 +79,9 @@ pub struct Permit<F: Fn(u64) -> Result + Sync> {
 
 	/// Sequence number of this permit.
 	id: u64,
+
+	/// Shard the sequence number is pending in.
+	shard: usize,
 }
 
 impl<F: Fn(u64) -> Result + Sync> Counter<F> {
@@ -64,135 +89,205 @@ impl<F: Fn(u64) -> Result + Sync> Counter<F> {
 	/// considered retired, and the next sequence number dispatched will be one
 	/// greater.
 	pub fn new(init: u64, commit: F, release: F) -> Arc<Self> {
+		Self::with_shards(init, 1, commit, release)
+	}
+
+	/// Construct a new Two-Phase counter state dividing pending sequence
+	/// numbers among `shards`. With one shard every number is persisted as it
+	/// is drawn; with more, up to `shards` numbers drawn but not used may be
+	/// skipped after a restart.
+	pub fn with_shards(init: u64, shards: usize, commit: F, release: F) -> Arc<Self> {
+		let shards = shards.max(1);
 		Arc::new(Self {
-			inner: State::new(init, commit, release).into(),
+			dispatched: init.into(),
+			reserved: init.into(),
+			reserving: Mutex::new(()),
+			commit,
+			shards: (0..shards)
+				.map(|_| Mutex::new(VecDeque::new()))
+				.collect(),
+			next_shard: 0.into(),
+			publisher: Mutex::new(()),
+			dirty: false.into(),
+			released: init.into(),
+			release,
 		})
 	}
 
 	/// Obtain a sequence number to conduct write operations for the scope.
 	pub fn next(self: &Arc<Self>) -> Result<Permit<F>> {
-		let (retired, id) = self.inner.write()?.dispatch()?;
+		let shard = self
+			.next_shard
+			.fetch_add(1, Ordering::Relaxed)
+			.checked_rem(self.shards.len())
+			.unwrap_or(0);
+
+		let retired = self.released.load(Ordering::Acquire);
+		let id = self.dispatch(shard)?;
 
-		Ok(Permit::<F> { state: self.clone(), retired, id })
+		Ok(Permit::<F> { state: self.clone(), retired, id, shard })
 	}
 
 	/// Load the current and dispatched values simultaneously
 	#[inline]
 	pub fn range(&self) -> Range<u64> {
-		let inner = self.inner.read().expect("locked for reading");
+		let end = self.dispatched();
 
-		Range {
-			start: inner.retired(),
-			end: inner.dispatched,
-		}
+		Range { start: self.retired(end), end }
 	}
 
 	/// Load the highest sequence number safe for reading, also known as the
 	/// retirement value with writes "globally visible."
 	#[inline]
-	pub fn current(&self) -> u64 {
-		self.inner
-			.read()
-			.expect("locked for reading")
-			.retired()
-	}
+	pub fn current(&self) -> u64 { self.retired(self.dispatched()) }
 
 	/// Load the highest sequence number (dispatched); may still be pending or
 	/// may be retired.
 	#[inline]
-	pub fn dispatched(&self) -> u64 {
-		self.inner
-			.read()
-			.expect("locked for reading")
-			.dispatched
-	}
-}
+	pub fn dispatched(&self) -> u64 { self.dispatched.load(Ordering::Acquire) }
+
+	/// Dispatch the next sequence number as pending in `shard`. The number is
+	/// drawn under the shard's lock so that it is pending before it can be
+	/// observed as dispatched. It is persisted before it is drawn, so that
+	/// nothing is dispatched when persisting fails.
+	fn dispatch(&self, shard: usize) -> Result<u64> {
+		let mut pending = self.shards[shard].lock()?;
+
+		// The lock of the only shard serializes the writers.
+		if self.shards.len() == 1 {
+			let id = checked!(self.dispatched() + 1)?;
+			(self.commit)(id)?;
+			self.dispatched.store(id, Ordering::Release);
+			pending.push_back(id);
+
+			return Ok(id);
+		}
 
-impl<F: Fn(u64) -> Result + Sync> State<F> {
-	/// Create new state, starting from `init`. The next sequence number
-	/// dispatched will be one greater than `init`.
-	fn new(dispatched: u64, commit: F, release: F) -> Self {
-		Self {
-			dispatched,
-			commit,
-			pending: VecDeque::new(),
-			release,
+		loop {
+			let prev = self.dispatched();
+			let id = checked!(prev + 1)?;
+			self.reserve(id)?;
+
+			if self
+				.dispatched
+				.compare_exchange(prev, id, Ordering::AcqRel, Ordering::Acquire)
+				.is_ok()
+			{
+				debug_assert!(
+					!pending.iter().any(is_equal_to!(&id)),
+					"sequence number cannot already be pending",
+				);
+
+				pending.push_back(id);
+				return Ok(id);
+			}
 		}
 	}
 
-	/// Dispatch the next sequence number as pending. The retired value is
-	/// calculated as a courtesy while the state is under lock.
-	fn dispatch(&mut self) -> Result<(u64, u64)> {
-		let prev = self.dispatched;
-		let retired = self.retired();
-		let dispatched = checked!(prev + 1)?;
-		debug_assert!(
-			!self.check_pending(dispatched),
-			"sequence number cannot already be pending",
-		);
-
-		(self.commit)(dispatched)?;
-		self.dispatched = dispatched;
-		self.pending.push_back(self.dispatched);
-		Ok((retired, self.dispatched))
+	/// Persist sequence numbers up to at least `id` unless already persisted.
+	fn reserve(&self, id: u64) -> Result {
+		if id <= self.reserved.load(Ordering::Acquire) {
+			return Ok(());
+		}
+
+		let _reserving = self.reserving.lock()?;
+		let reserved = self.reserved.load(Ordering::Acquire);
+		if id <= reserved {
+			return Ok(());
+		}
+
+		let block = u64::try_from(self.shards.len())?;
+		let reserve = id.max(checked!(reserved + block)?);
+		(self.commit)(reserve)?;
+		self.reserved.store(reserve, Ordering::Release);
+
+		Ok(())
 	}
 
-	/// Retire the sequence number `id`.
-	fn retire(&mut self, id: u64) {
-		debug_assert!(self.check_pending(id), "sequence number must be currently pending",);
+	/// Retire the sequence number `id` pending in `shard`.
+	fn retire(&self, shard: usize, id: u64) {
+		let mut pending = self.shards[shard]
+			.lock()
+			.expect("locked for writing");
 
-		let index = self
-			.pending_index(id)
+		let index = pending
+			.binary_search(&id)
 			.expect("sequence number must be found as pending");
 
-		let removed = self
-			.pending
+		let removed = pending
 			.remove(index)
 			.expect("sequence number at index must be removed");
 
 		debug_assert_eq!(removed, id, "sequence number removed must match id");
 
-		// release only occurs when the oldest value retires
-		if index != 0 {
+		// With one shard the retirement value is known under its lock; only the
+		// oldest value advances it.
+		if self.shards.len() == 1 {
+			if index == 0 {
+				let retired = pending
+					.front()
+					.map_or_else(|| self.dispatched(), |val| val.saturating_sub(1));
+
+				self.released.store(retired, Ordering::SeqCst);
+				(self.release)(retired).expect("release callback should not error");
+			}
+
 			return;
 		}
 
-		// release occurs for the maximum retired value
-		let release = if self.pending.is_empty() { self.dispatched } else { id };
+		drop(pending);
+		self.publish(id);
+	}
 
-		debug_assert!(release >= id, "sequence number released must not be less than id");
+	/// Publish the retirement value after `id` is no longer pending. Only the
+	/// oldest pending value advances it; when another retirement is publishing,
+	/// it is left to publish on behalf of this one.
+	fn publish(&self, id: u64) {
+		self.dirty.store(true, Ordering::SeqCst);
+		if id
+			> self
+				.released
+				.load(Ordering::SeqCst)
+				.saturating_add(1)
+		{
+			return;
+		}
 
-		(self.release)(release).expect("release callback should not error");
+		while let Ok(publisher) = self.publisher.try_lock() {
+			while self.dirty.swap(false, Ordering::SeqCst) {
+				let retired = self.retired(self.dispatched());
+				if retired > self.released.load(Ordering::SeqCst) {
+					self.released.store(retired, Ordering::SeqCst);
+					(self.release)(retired).expect("release callback should not error");
+				}
+			}
+
+			drop(publisher);
+			if !self.dirty.load(Ordering::SeqCst) {
+				break;
+			}
+		}
 	}
 
 	/// Calculate the retired sequence number, one less than the lowest pending
 	/// sequence number. If nothing is pending the value of `dispatched` has
-	/// been previously retired and is returned.
-	fn retired(&self) -> u64 {
-		debug_assert!(
-			self.pending.iter().is_sorted(),
-			"Pending values should be naturally sorted"
-		);
-
-		self.pending
-			.front()
-			.map(|val| val.saturating_sub(1))
-			.unwrap_or(self.dispatched)
+	/// been previously retired and is returned. `dispatched` must be loaded
+	/// before the shards are.
+	fn retired(&self, dispatched: u64) -> u64 {
+		self.shards
+			.iter()
+			.filter_map(|shard| {
+				let pending = shard.lock().expect("locked for reading");
+				debug_assert!(
+					pending.iter().is_sorted(),
+					"Pending values should be naturally sorted"
+				);
+
+				pending.front().copied()
+			})
+			.min()
+			.map_or(dispatched, |val| val.saturating_sub(1).min(dispatched))
 	}
-
-	/// Get the position of `id` in the pending list.
-	fn pending_index(&self, id: u64) -> Option<usize> {
-		debug_assert!(
-			self.pending.iter().is_sorted(),
-			"Pending values should be naturally sorted"
-		);
-
-		self.pending.binary_search(&id).ok()
-	}
-
-	/// Check for `id` in the pending list sequentially (for debug and assertion
-	/// purposes only)
-	fn check_pending(&self, id: u64) -> bool { self.pending.iter().any(is_equal_to!(&id)) }
 }
 
 impl<F: Fn(u64) -> Result + Sync> Permit<F> {
@@ -216,11 +311,5 @@ fn deref(&self) -> &Self::Target { self.id() }
 }
 
 impl<F: Fn(u64) -> Result + Sync> Drop for Permit<F> {
-	fn drop(&mut self) {
-		self.state
-			.inner
-			.write()
-			.expect("locked for writing")
-			.retire(self.id);
-	}
+	fn drop(&mut self) { self.state.retire(self.shard, self.id); }
 }
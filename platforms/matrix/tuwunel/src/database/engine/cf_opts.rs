@@ -6,7 +6,7 @@
 use tuwunel_core::{Config, Result, err, utils::math::Expected};
 
 use super::descriptor::{CacheDisp, Descriptor};
-use crate::{Context, util::map_err};
+use crate::{Context, ttl, util::map_err};
 
 pub(super) const SENTINEL_COMPRESSION_LEVEL: i32 = 32767;
 
@@ -38,6 +38,9 @@ fn descriptor_cf_options(
 	opts.set_level_zero_file_num_compaction_trigger(desc.level0_width);
 	opts.set_level_compaction_dynamic_level_bytes(false);
 	opts.set_ttl(desc.ttl);
+	if desc.expiry {
+		opts.set_compaction_filter(ttl::FILTER_NAME, ttl::compaction_filter);
+	}
 
 	opts.set_max_bytes_for_level_base(desc.level_size);
 	opts.set_max_bytes_for_level_multiplier(1.0);
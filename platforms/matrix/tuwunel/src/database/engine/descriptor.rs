@@ -27,6 +27,7 @@ pub(crate) struct Descriptor {
 	pub(crate) merge_width: (i32, i32),
 	pub(crate) limit_size: u64,
 	pub(crate) ttl: u64,
+	pub(crate) expiry: bool,
 	pub(crate) compaction: CompactionStyle,
 	pub(crate) compaction_pri: CompactionPri,
 	pub(crate) compression: CompressionType,
@@ -70,6 +71,7 @@ pub(crate) enum CacheDisp {
 	merge_width: (2, 16),
 	limit_size: 0,
 	ttl: 60 * 60 * 24 * 21,
+	expiry: false,
 	compaction: CompactionStyle::Level,
 	compaction_pri: CompactionPri::MinOverlappingRatio,
 	compression: CompressionType::Zstd,
@@ -153,6 +155,15 @@ pub(crate) enum CacheDisp {
 	..SEQUENTIAL
 };
 
+/// Descriptor for small datasets of expiring entries (see ttl.rs). Expired
+/// entries are dropped by compaction; the short ttl makes sure every file is
+/// periodically revisited so stale rows do not linger.
+pub(crate) static RANDOM_SMALL_EXPIRY: Descriptor = Descriptor {
+	expiry: true,
+	ttl: 60 * 60 * 6,
+	..RANDOM_SMALL
+};
+
 /// Descriptor for large persistent caches with random updates. Oldest entries
 /// are deleted after limit_size reached.
 pub(crate) static RANDOM_CACHE: Descriptor = Descriptor {
@@ -30,7 +30,10 @@
 	ffi::CStr,
 	fmt,
 	fmt::{Debug, Display},
-	sync::Arc,
+	sync::{
+		Arc,
+		atomic::{AtomicU64, Ordering},
+	},
 };
 
 use rocksdb::{AsColumnFamilyRef, ColumnFamily, ReadOptions, WriteOptions};
@@ -52,6 +55,8 @@ pub struct Map {
 	read_options: ReadOptions,
 	cache_read_options: ReadOptions,
 	write_options: WriteOptions,
+	reads: AtomicU64,
+	writes: AtomicU64,
 }
 
 impl Map {
@@ -64,6 +69,8 @@ pub(crate) fn open(engine: &Arc<Engine>, name: &'static str) -> Result<Arc<Self>
 			read_options: read_options_default(engine),
 			cache_read_options: cache_read_options_default(engine),
 			write_options: write_options_default(engine),
+			reads: AtomicU64::new(0),
+			writes: AtomicU64::new(0),
 		}))
 	}
 
@@ -80,6 +87,27 @@ pub fn property(&self, name: &str) -> Result<String> {
 	#[inline]
 	pub fn name(&self) -> &str { self.name }
 
+	/// Number of keys queried by point-reads since the map was opened.
+	#[inline]
+	pub fn reads(&self) -> u64 { self.reads.load(Ordering::Relaxed) }
+
+	/// Number of keys written or removed since the map was opened.
+	#[inline]
+	pub fn writes(&self) -> u64 { self.writes.load(Ordering::Relaxed) }
+
+	#[inline]
+	fn count_reads(&self, num: usize) {
+		let num = num.try_into().unwrap_or(u64::MAX);
+		self.reads.fetch_add(num, Ordering::Relaxed);
+		tuwunel_core::metrics::count_db_reads(num);
+	}
+
+	#[inline]
+	fn count_writes(&self, num: usize) {
+		let num = num.try_into().unwrap_or(u64::MAX);
+		self.writes.fetch_add(num, Ordering::Relaxed);
+	}
+
 	#[inline]
 	pub(crate) fn engine(&self) -> &Arc<Engine> { &self.engine }
 
@@ -60,3 +60,9 @@ pub fn compact_blocking(&self, opts: Options) -> Result {
 
 	Ok(())
 }
+
+/// Total size of the table files of the column, which compaction reduces.
+#[implement(super::Map)]
+pub fn sst_files_size(&self) -> Result<u64> {
+	self.property_integer(c"rocksdb.total-sst-files-size")
+}
@@ -26,6 +26,7 @@ pub fn get<K>(
 {
 	use crate::pool::Get;
 
+	self.count_reads(1);
 	let cached = self.get_cached(key);
 	if matches!(cached, Err(_) | Ok(Some(_))) {
 		return Either::Left(
@@ -48,6 +48,7 @@ pub(crate) fn get_batch<'a, S, K>(
 
 	keys.ready_chunks(automatic_amplification())
 		.widen_then(automatic_width(), |chunk| {
+			self.count_reads(chunk.len());
 			self.engine.pool.execute_get(Get {
 				map: self.clone(),
 				res: None,
@@ -21,6 +21,7 @@ pub fn insert<K, V>(&self, key: &K, val: V)
 	K: AsRef<[u8]> + ?Sized,
 	V: AsRef<[u8]>,
 {
+	self.count_writes(1);
 	let write_options = &self.write_options;
 	self.engine
 		.db
@@ -48,6 +49,8 @@ pub fn insert_batch<'a, I, K, V>(&'a self, iter: I)
 		batch.put_cf(&self.cf(), key.as_ref(), val.as_ref());
 	}
 
+	self.count_writes(batch.len());
+
 	let write_options = &self.write_options;
 	self.engine
 		.db
@@ -10,6 +10,7 @@ pub fn remove<K>(&self, key: &K)
 where
 	K: AsRef<[u8]> + ?Sized + Debug,
 {
+	self.count_writes(1);
 	let write_options = &self.write_options;
 	self.engine
 		.db
@@ -34,6 +34,18 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "aliasid_alias",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "appserviceidcount_todevice",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "appserviceidcount_userid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "auditpattern_since",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "authchainkey_authchain",
 		cache_disp: CacheDisp::SharedWith("shorteventid_authchain"),
@@ -98,6 +110,10 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		index_size: 512,
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "exportcount_pduid",
+		..descriptor::SEQUENTIAL_SMALL
+	},
 	Descriptor {
 		name: "global",
 		..descriptor::RANDOM_SMALL
@@ -106,6 +122,10 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "id_appserviceregistrations",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "jobid_job",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "keychangeid_userid",
 		..descriptor::RANDOM
@@ -119,13 +139,33 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		..descriptor::RANDOM_SMALL
 	},
 	Descriptor {
-		name: "logintoken_expiresatuserid",
+		name: "lazyloadedshorteventids",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "logintoken_expiresatuserid",
+		..descriptor::RANDOM_SMALL_EXPIRY
+	},
 	Descriptor {
 		name: "mediaid_file",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "mediaid_flags",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "mediaid_quarantine",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "mediaid_reporter",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "mediaid_roomid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "mediaid_user",
 		..descriptor::RANDOM_SMALL
@@ -144,7 +184,7 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 	},
 	Descriptor {
 		name: "openidtoken_expiresatuserid",
-		..descriptor::RANDOM_SMALL
+		..descriptor::RANDOM_SMALL_EXPIRY
 	},
 	Descriptor {
 		name: "pduid_pdu",
@@ -155,6 +195,10 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		index_size: 512,
 		..descriptor::SEQUENTIAL
 	},
+	Descriptor {
+		name: "publicationid_roomid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "publicroomids",
 		..descriptor::RANDOM_SMALL
@@ -163,10 +207,18 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "pushkey_deviceid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "pushkeyeventid_retry",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "presenceid_presence",
 		..descriptor::SEQUENTIAL_SMALL
 	},
+	Descriptor {
+		name: "ratelimitkey_offender",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "readreceiptid_readreceipt",
 		..descriptor::RANDOM
@@ -179,6 +231,18 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "registrationtoken_info",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "rendezvousid_session",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "reportid_report",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "roomid_backfillbackoff",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_knockedcount",
 		..descriptor::RANDOM_SMALL
@@ -199,10 +263,26 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "roomid_maxremotepowerlevel",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "roomid_mediausage",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "roomid_mirrorspace",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_pduleaves",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "roomid_purgedbefore",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "roomid_sharelinkid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_shortroomid",
 		val_size_hint: Some(8),
@@ -213,6 +293,14 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		val_size_hint: Some(8),
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "roomid_statesample",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "roomid_summary",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomserverids",
 		..descriptor::RANDOM_SMALL
@@ -252,6 +340,10 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		val_size_hint: Some(8),
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "roomuserid_mirrorinvite",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomuserid_privateread",
 		..descriptor::RANDOM_SMALL
@@ -280,10 +372,18 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "servername_destination",
 		..descriptor::RANDOM_SMALL_CACHE
 	},
+	Descriptor {
+		name: "servername_devicelistcount",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "servername_educount",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "servername_mediausage",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "servername_override",
 		..descriptor::RANDOM_SMALL_CACHE
@@ -298,6 +398,18 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "serverroomids",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "serveruserid_presence",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "sharelinkid_redemption",
+		..descriptor::SEQUENTIAL_SMALL
+	},
+	Descriptor {
+		name: "sharelinkid_sharelink",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "shorteventid_authchain",
 		cache_disp: CacheDisp::SharedWith("authchainkey_authchain"),
@@ -339,6 +451,10 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		key_size_hint: Some(48),
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "spaceuserid_mirrordeclined",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "statehash_shortstatehash",
 		val_size_hint: Some(8),
@@ -355,6 +471,14 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "threadid_userids",
 		..descriptor::SEQUENTIAL_SMALL
 	},
+	Descriptor {
+		name: "threepid_userid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "threepidsessionid_session",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "timeredacted_eventid",
 		key_size_hint: Some(57),
@@ -383,6 +507,14 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "url_previews",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "user_threepids",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userdevicealgorithm_fallbackkey",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userdeviceid_metadata",
 		..descriptor::RANDOM_SMALL
@@ -396,6 +528,14 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "userdeviceid_refresh",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userdeviceid_rotatedrefresh",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userdeviceid_session",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userdeviceid_token",
 		..descriptor::RANDOM_SMALL
@@ -440,6 +580,10 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "userid_masterkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_mediausage",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_oauthid",
 		..descriptor::RANDOM_SMALL
@@ -460,15 +604,35 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "userid_selfsigningkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_shadowbanned",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_suspended",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_servernoticeroomid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_usersigningkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "useridcount_devicelistchange",
+		..descriptor::SEQUENTIAL_SMALL
+	},
 	Descriptor {
 		name: "useridcount_notification",
 		limit_size: 1024 * 1024 * 256,
 		..descriptor::RANDOM_SMALL_CACHE
 	},
+	Descriptor {
+		name: "useridopenidtoken_info",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "useridprofilekey_value",
 		..descriptor::RANDOM_SMALL
@@ -485,6 +649,10 @@ pub(super) fn open_list(engine: &Arc<Engine>, maps: &[Descriptor]) -> Result<Map
 		name: "userroomid_joined",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "userroomid_ldappowerlevel",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userroomid_leftstate",
 		..descriptor::RANDOM
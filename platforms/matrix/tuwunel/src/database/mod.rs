@@ -19,6 +19,7 @@
 mod stream;
 #[cfg(test)]
 mod tests;
+pub mod ttl;
 pub(crate) mod util;
 
 use std::{ops::Index, sync::Arc};
@@ -32,7 +33,9 @@
 	handle::Handle,
 	keyval::{KeyVal, Slice, serialize_key, serialize_val},
 	map::{Get, Map, Qry, compact},
+	pool::ReadClass,
 	ser::{Cbor, Interfix, Json, SEP, Separator, serialize, serialize_to, serialize_to_vec},
+	ttl::Ttl,
 };
 pub(crate) use self::{
 	engine::{Engine, context::Context},
@@ -73,6 +76,23 @@ pub fn iter(&self) -> impl Iterator<Item = (&MapsKey, &MapsVal)> + Send + '_ {
 	#[inline]
 	pub fn keys(&self) -> impl Iterator<Item = &MapsKey> + Send + '_ { self.maps.keys() }
 
+	/// For each request class, by name: the requests submitted to the pool and
+	/// not yet answered, and those submitted since startup.
+	pub fn read_class_utilization(
+		&self,
+	) -> impl Iterator<Item = (&'static str, usize, u64)> + Send + '_ {
+		ReadClass::ALL.into_iter().map(|class| {
+			let (active, total) = self.engine.pool.utilization(class);
+			(class.name(), active, total)
+		})
+	}
+
+	/// Requests waiting for a worker of the pool, and the number which can
+	/// wait before submitting more blocks.
+	#[inline]
+	#[must_use]
+	pub fn queue_depth(&self) -> (usize, usize) { self.engine.pool.queued() }
+
 	#[inline]
 	#[must_use]
 	pub fn is_read_only(&self) -> bool { self.engine.is_read_only() }
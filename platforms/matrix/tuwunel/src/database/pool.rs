@@ -1,3 +1,4 @@
+mod class;
 mod configure;
 
 use std::{
@@ -14,6 +15,7 @@
 use futures::{TryFutureExt, channel::oneshot};
 use oneshot::Sender as ResultSender;
 use rocksdb::Direction;
+use tokio::sync::Semaphore;
 use tuwunel_core::{
 	Error, Result, Server, debug, err, error, implement,
 	result::DebugInspect,
@@ -22,7 +24,8 @@
 	utils::sys::compute::{get_affinity, set_affinity},
 };
 
-use self::configure::configure;
+pub use self::class::ReadClass;
+use self::{class::Classes, configure::configure};
 use crate::{Handle, Map, keyval::KeyBuf, stream};
 
 /// Frontend thread-pool. Operating system threads are used to make database
@@ -35,6 +38,8 @@ pub(crate) struct Pool {
 	topology: Vec<usize>,
 	busy: AtomicUsize,
 	queued_max: AtomicUsize,
+	background: Option<Semaphore>,
+	classes: Classes,
 }
 
 /// Operations which can be submitted to the pool.
@@ -91,6 +96,8 @@ pub(crate) fn new(server: &Arc<Server>) -> Result<Arc<Self>> {
 		topology,
 		busy: AtomicUsize::default(),
 		queued_max: AtomicUsize::default(),
+		background: class::background_semaphore(server.config.db_pool_background_limit),
+		classes: Classes::default(),
 	});
 
 	for (chan_id, &count) in workers.iter().enumerate() {
@@ -205,6 +212,7 @@ pub(crate) async fn execute_get(self: &Arc<Self>, mut cmd: Get) -> Result<BatchR
 	let (send, recv) = oneshot::channel();
 	_ = cmd.res.insert(send);
 
+	let _admission = self.admit().await?;
 	let queue = self.select_queue();
 	self.execute(queue, Cmd::Get(cmd))
 		.and_then(move |()| {
@@ -220,6 +228,7 @@ pub(crate) async fn execute_iter(self: &Arc<Self>, mut cmd: Seek) -> Result<stre
 	let (send, recv) = oneshot::channel();
 	_ = cmd.res.insert(send);
 
+	let _admission = self.admit().await?;
 	let queue = self.select_queue();
 	self.execute(queue, Cmd::Iter(cmd))
 		.and_then(|()| {
@@ -229,6 +238,23 @@ pub(crate) async fn execute_iter(self: &Arc<Self>, mut cmd: Seek) -> Result<stre
 		.await
 }
 
+/// Requests of the class submitted and not yet answered, and submitted since
+/// startup.
+#[implement(Pool)]
+pub(crate) fn utilization(&self, class: ReadClass) -> (usize, u64) {
+	self.classes.utilization(class)
+}
+
+/// Requests waiting in the queues for a worker, and the capacity of the
+/// queues.
+#[implement(Pool)]
+pub(crate) fn queued(&self) -> (usize, usize) {
+	self.queues
+		.iter()
+		.map(|queue| (queue.len(), queue.capacity().unwrap_or(0)))
+		.fold((0, 0), |(len, cap), (l, c)| (len.saturating_add(l), cap.saturating_add(c)))
+}
+
 #[implement(Pool)]
 fn select_queue(&self) -> &Sender<Cmd> {
 	let core_id = get_affinity()
@@ -0,0 +1,120 @@
+//! Priority classes of database requests, so that maintenance work cannot
+//! occupy the pool while clients wait on it.
+
+use std::{
+	future::Future,
+	sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tuwunel_core::{Result, err, implement};
+
+use super::Pool;
+
+/// Class of the database requests made by a task.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReadClass {
+	/// Requests on behalf of clients and remote servers.
+	#[default]
+	Interactive,
+
+	/// Requests of maintenance work, bounded by `db_pool_background_limit`.
+	Background,
+}
+
+/// Utilization of the pool by each class.
+#[derive(Default)]
+pub(super) struct Classes {
+	stats: [ClassStats; 2],
+}
+
+#[derive(Default)]
+struct ClassStats {
+	/// Requests submitted and not yet answered.
+	active: AtomicUsize,
+
+	/// Requests submitted since startup.
+	total: AtomicU64,
+}
+
+/// Held while a request is submitted and not yet answered.
+pub(super) struct Admission<'a> {
+	stats: &'a ClassStats,
+	_permit: Option<SemaphorePermit<'a>>,
+}
+
+tokio::task_local! {
+	static READ_CLASS: ReadClass;
+}
+
+impl ReadClass {
+	pub const ALL: [Self; 2] = [Self::Interactive, Self::Background];
+
+	/// Runs the future with its database requests in this class. Tasks it
+	/// spawns are not included.
+	pub fn scope<F: Future>(self, fut: F) -> impl Future<Output = F::Output> {
+		READ_CLASS.scope(self, fut)
+	}
+
+	/// Class of the calling task.
+	#[must_use]
+	pub fn current() -> Self {
+		READ_CLASS
+			.try_with(|class| *class)
+			.unwrap_or_default()
+	}
+
+	#[must_use]
+	pub fn name(self) -> &'static str {
+		match self {
+			| Self::Interactive => "interactive",
+			| Self::Background => "background",
+		}
+	}
+
+	fn index(self) -> usize {
+		match self {
+			| Self::Interactive => 0,
+			| Self::Background => 1,
+		}
+	}
+}
+
+impl Classes {
+	/// Requests of the class submitted and not yet answered, and submitted
+	/// since startup.
+	pub(super) fn utilization(&self, class: ReadClass) -> (usize, u64) {
+		let stats = &self.stats[class.index()];
+		(stats.active.load(Ordering::Relaxed), stats.total.load(Ordering::Relaxed))
+	}
+}
+
+impl Drop for Admission<'_> {
+	fn drop(&mut self) { self.stats.active.fetch_sub(1, Ordering::Relaxed); }
+}
+
+/// Waits until a request of the calling task's class may be submitted.
+#[implement(Pool)]
+pub(super) async fn admit(&self) -> Result<Admission<'_>> {
+	let class = ReadClass::current();
+	let permit = match (class, &self.background) {
+		| (ReadClass::Background, Some(background)) => Some(
+			background
+				.acquire()
+				.await
+				.map_err(|e| err!(error!("background semaphore closed {e:?}")))?,
+		),
+		| _ => None,
+	};
+
+	let stats = &self.classes.stats[class.index()];
+	stats.active.fetch_add(1, Ordering::Relaxed);
+	stats.total.fetch_add(1, Ordering::Relaxed);
+
+	Ok(Admission { stats, _permit: permit })
+}
+
+/// Bounds requests of the background class, unless `limit` is zero.
+pub(super) fn background_semaphore(limit: usize) -> Option<Semaphore> {
+	(limit > 0).then(|| Semaphore::new(limit))
+}
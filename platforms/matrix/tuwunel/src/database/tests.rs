@@ -9,8 +9,9 @@
 };
 
 use crate::{
-	Cbor, Ignore, Interfix, de, ser,
+	Cbor, Ignore, Interfix, SEP, de, ser,
 	ser::{Json, serialize_to_vec},
+	ttl,
 };
 
 #[test]
@@ -813,3 +814,37 @@ fn serde_tuple_option_none_none_none() {
 	assert_eq!(None, cc.0);
 	assert_eq!(bb, cc);
 }
+
+#[test]
+fn ttl_layout() {
+	let user_id: &UserId = "@user:example.com".try_into().unwrap();
+
+	let val = serialize_to_vec((1_u64 << 40, user_id)).expect("failed to serialize entry");
+	assert_eq!(ttl::expires_at(&val), Some(1_u64 << 40));
+	assert_eq!(val[size_of::<u64>()], SEP);
+
+	let (expires_at, de_user_id): (u64, &UserId) =
+		de::from_slice(&val).expect("failed to deserialize entry");
+
+	assert_eq!(expires_at, 1_u64 << 40);
+	assert_eq!(de_user_id, user_id);
+	assert_eq!(ttl::expires_at(b"short"), None);
+}
+
+#[test]
+fn ttl_compaction_filter() {
+	use rocksdb::CompactionDecision;
+
+	let expired = serialize_to_vec((1_u64, "val")).expect("failed to serialize entry");
+	let unexpired = serialize_to_vec((u64::MAX, "val")).expect("failed to serialize entry");
+
+	assert!(matches!(
+		ttl::compaction_filter(0, b"key", &expired),
+		CompactionDecision::Remove
+	));
+	assert!(matches!(
+		ttl::compaction_filter(0, b"key", &unexpired),
+		CompactionDecision::Keep
+	));
+	assert!(matches!(ttl::compaction_filter(0, b"key", b""), CompactionDecision::Keep));
+}
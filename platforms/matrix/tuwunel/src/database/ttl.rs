@@ -0,0 +1,100 @@
+//! Key-value maps whose entries expire.
+//!
+//! Values are stored as the tuple `(expires_at, val)` where `expires_at` is a
+//! big-endian u64 of milliseconds since the unix epoch. Columns described with
+//! `expiry: true` install [`compaction_filter`] which drops expired entries as
+//! compaction visits them; reads check the expiration as well, so callers never
+//! observe an expired entry regardless of whether it was swept yet.
+
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use rocksdb::CompactionDecision;
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{Err, Result, utils::time::now_millis};
+
+use crate::{Deserialized, Map};
+
+/// Name the compaction filter is registered under.
+pub(crate) const FILTER_NAME: &str = "expiry";
+
+/// Map of entries carrying an expiration time.
+#[derive(Clone)]
+pub struct Ttl {
+	map: Arc<Map>,
+}
+
+impl From<Arc<Map>> for Ttl {
+	fn from(map: Arc<Map>) -> Self { Self { map } }
+}
+
+impl Ttl {
+	/// Insert an entry expiring after `ttl`; returns the expiration time in
+	/// milliseconds since the unix epoch.
+	pub fn insert<K, V>(&self, key: &K, val: V, ttl: Duration) -> u64
+	where
+		K: AsRef<[u8]> + ?Sized,
+		V: Serialize,
+	{
+		let ttl: u64 = ttl.as_millis().try_into().unwrap_or(u64::MAX);
+		let expires_at = now_millis().saturating_add(ttl);
+		self.map.raw_put(key, (expires_at, val));
+
+		expires_at
+	}
+
+	/// Fetch an unexpired entry. Expired entries are removed and reported as
+	/// not found.
+	pub async fn get<K, T>(&self, key: &K) -> Result<T>
+	where
+		K: AsRef<[u8]> + Debug + ?Sized,
+		T: for<'de> Deserialize<'de>,
+	{
+		let (expires_at, val): (u64, T) = self.map.get(key).await.deserialized()?;
+		if expires_at < now_millis() {
+			self.map.remove(key);
+			return Err!(Request(NotFound("Entry has expired.")));
+		}
+
+		Ok(val)
+	}
+
+	/// Fetch and remove an unexpired entry, for single-use values.
+	pub async fn take<K, T>(&self, key: &K) -> Result<T>
+	where
+		K: AsRef<[u8]> + Debug + ?Sized,
+		T: for<'de> Deserialize<'de>,
+	{
+		let val = self.get(key).await;
+		self.map.remove(key);
+
+		val
+	}
+
+	#[inline]
+	pub fn remove<K>(&self, key: &K)
+	where
+		K: AsRef<[u8]> + Debug + ?Sized,
+	{
+		self.map.remove(key);
+	}
+
+	#[inline]
+	#[must_use]
+	pub fn map(&self) -> &Arc<Map> { &self.map }
+}
+
+/// Compaction filter shared by all expiring columns. Entries too short to
+/// carry an expiration are kept; they are not ours to judge.
+pub(crate) fn compaction_filter(_level: u32, _key: &[u8], val: &[u8]) -> CompactionDecision {
+	match expires_at(val) {
+		| Some(expires_at) if expires_at < now_millis() => CompactionDecision::Remove,
+		| _ => CompactionDecision::Keep,
+	}
+}
+
+/// Extract the expiration time from a raw value.
+#[inline]
+#[must_use]
+pub fn expires_at(val: &[u8]) -> Option<u64> {
+	val.first_chunk().copied().map(u64::from_be_bytes)
+}
@@ -78,6 +78,7 @@ fn generate_example(input: &ItemStruct, args: &[Meta], write: bool) -> Result<To
 	}
 
 	let mut summary: Vec<TokenStream2> = Vec::new();
+	let mut sensitive: Vec<String> = Vec::new();
 	if let Fields::Named(FieldsNamed { named, .. }) = &input.fields {
 		for field in named {
 			let Some(ident) = &field.ident else {
@@ -130,6 +131,11 @@ fn generate_example(input: &ItemStruct, args: &[Meta], write: bool) -> Result<To
 					.any(|directive| directive == key)
 			};
 
+			let name = ident.to_string();
+			if display_directive("sensitive") {
+				sensitive.push(name.clone());
+			}
+
 			if !display_directive("hidden") {
 				let value = if display_directive("sensitive") {
 					quote! { "***********" }
@@ -137,7 +143,6 @@ fn generate_example(input: &ItemStruct, args: &[Meta], write: bool) -> Result<To
 					quote! { format_args!("{:?}", self.#ident) }
 				};
 
-				let name = ident.to_string();
 				summary.push(quote! {
 					writeln!(out, "| {} | {} |", #name, #value)?;
 				});
@@ -162,6 +167,25 @@ fn fmt(&self, out: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 				Ok(())
 			}
 		}
+
+		impl #struct_name {
+			/// The effective values of the section as a TOML table, every
+			/// field included, with sensitive values redacted.
+			pub fn effective_table(
+				&self,
+			) -> ::std::result::Result<::toml::Table, ::toml::ser::Error> {
+				const SENSITIVE: &[&str] = &[#( #sensitive ),*];
+
+				let mut table = ::toml::Table::try_from(self)?;
+				for key in SENSITIVE {
+					if let Some(value) = table.get_mut(*key) {
+						*value = "***********".into();
+					}
+				}
+
+				Ok(table)
+			}
+		}
 	};
 
 	Ok(display)
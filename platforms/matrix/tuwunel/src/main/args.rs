@@ -27,6 +27,12 @@ pub struct Args {
 	#[arg(long, short('O'))]
 	pub option: Vec<String>,
 
+	/// Print the effective configuration, merged from the defaults, the config
+	/// files, the environment and the command line, with sensitive values
+	/// redacted, then exit.
+	#[arg(long)]
+	pub print_config: bool,
+
 	/// Run in a stricter read-only --maintenance mode.
 	#[arg(long)]
 	pub read_only: bool,
@@ -35,6 +41,11 @@ pub struct Args {
 	#[arg(long)]
 	pub maintenance: bool,
 
+	/// Start only the named services and those they depend on, e.g.
+	/// `--services users,media`. Implies --maintenance.
+	#[arg(long, value_delimiter = ',')]
+	pub services: Vec<String>,
+
 	#[cfg(feature = "console")]
 	/// Activate admin command console automatically after startup.
 	#[arg(long, num_args(0))]
@@ -193,7 +204,23 @@ pub fn update(mut config: Figment, args: &Args) -> Result<Figment> {
 		config = config.join(("rocksdb_read_only", true));
 	}
 
-	if args.maintenance || args.read_only {
+	if !args.services.is_empty() {
+		let mut services = args.services.clone();
+
+		// Commands are executed by the admin service.
+		if !args.execute.is_empty() {
+			services.push("admin".into());
+		}
+
+		#[cfg(feature = "console")]
+		if args.console {
+			services.push("admin".into());
+		}
+
+		config = config.merge(("startup_services", services));
+	}
+
+	if args.maintenance || args.read_only || !args.services.is_empty() {
 		config = config.join(("startup_netburst", false));
 		config = config.join(("listening", false));
 	}
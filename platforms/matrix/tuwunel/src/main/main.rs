@@ -1,10 +1,14 @@
 use std::sync::atomic::Ordering;
 
-use tuwunel::{Server, args, restart, runtime};
+use tuwunel::{Server, args, restart, runtime, server};
 use tuwunel_core::{Result, debug_info};
 
 fn main() -> Result {
 	let args = args::parse();
+	if args.print_config {
+		return server::print_config(&args);
+	}
+
 	let runtime = runtime::new(Some(&args))?;
 	let server = Server::new(Some(&args), Some(runtime.handle()))?;
 
@@ -33,16 +33,7 @@ pub fn new(args: Option<&Args>, runtime: Option<&runtime::Handle>) -> Result<Arc
 
 	let args_default = args.is_none().then(Args::default);
 	let args = args.unwrap_or_else(|| args_default.as_ref().expect("default arguments"));
-	let config_paths = args
-		.config
-		.as_deref()
-		.into_iter()
-		.flat_map(<[_]>::iter)
-		.map(PathBuf::as_path);
-
-	let config = Config::load(config_paths)
-		.and_then(|raw| args::update(raw, args))
-		.and_then(|raw| Config::new(&raw))?;
+	let config = load_config(args)?;
 
 	let (tracing_flame_guard, logger) = crate::logging::init(&config)?;
 
@@ -80,3 +71,24 @@ pub fn new(args: Option<&Args>, runtime: Option<&runtime::Handle>) -> Result<Arc
 		mods: tokio::sync::RwLock::new(Vec::new()),
 	}))
 }
+
+/// Prints the effective configuration for `--print-config`.
+pub fn print_config(args: &Args) -> Result {
+	let config = load_config(args)?;
+	print!("{}", config.effective()?);
+
+	Ok(())
+}
+
+fn load_config(args: &Args) -> Result<Config> {
+	let config_paths = args
+		.config
+		.as_deref()
+		.into_iter()
+		.flat_map(<[_]>::iter)
+		.map(PathBuf::as_path);
+
+	Config::load(config_paths)
+		.and_then(|raw| args::update(raw, args))
+		.and_then(|raw| Config::new(&raw))
+}
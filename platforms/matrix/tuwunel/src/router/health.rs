@@ -0,0 +1,53 @@
+//! Liveness and readiness probes for load balancers and orchestrators. These
+//! are answered from before the database is opened until shutdown.
+
+use std::sync::Arc;
+
+use axum::{Json, Router, response::IntoResponse, routing::get};
+use http::StatusCode;
+use serde_json::json;
+use tuwunel_core::{Phase, Server};
+
+pub(crate) fn router<S>(server: &Arc<Server>) -> Router<S>
+where
+	S: Clone + Send + Sync + 'static,
+{
+	let (live_server, ready_server) = (server.clone(), server.clone());
+
+	Router::new()
+		.route("/health/live", get(move || live(live_server.clone())))
+		.route("/health/ready", get(move || ready(ready_server.clone())))
+}
+
+/// Answers requests for anything else while the server is starting.
+pub(crate) async fn starting() -> impl IntoResponse {
+	(StatusCode::SERVICE_UNAVAILABLE, "Server is starting.")
+}
+
+/// # `GET /health/live`
+///
+/// The process is up and answering; always succeeds.
+async fn live(server: Arc<Server>) -> impl IntoResponse { Json(status(&server)) }
+
+/// # `GET /health/ready`
+///
+/// Succeeds only once the server is serving requests; unavailable while it
+/// is starting, migrating the database or stopping.
+async fn ready(server: Arc<Server>) -> impl IntoResponse {
+	let code = match server.phase() {
+		| Phase::Ready => StatusCode::OK,
+		| _ => StatusCode::SERVICE_UNAVAILABLE,
+	};
+
+	(code, Json(status(&server)))
+}
+
+fn status(server: &Server) -> serde_json::Value {
+	let phase = server.phase();
+	let mut status = json!({ "phase": phase.as_str() });
+	if phase == Phase::Migrating {
+		status["progress"] = server.migration_progress().into();
+	}
+
+	status
+}
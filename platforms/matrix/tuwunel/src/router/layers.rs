@@ -21,7 +21,7 @@
 };
 use tracing::Level;
 use tuwunel_api::router::state::Guard;
-use tuwunel_core::{Result, Server, debug, error};
+use tuwunel_core::{Result, Server, debug, err, error};
 use tuwunel_service::Services;
 
 use crate::{request, router};
@@ -50,6 +50,14 @@ pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 	))]
 	let layers = layers.layer(compression_layer(server));
 
+	#[cfg(any(feature = "zstd_compression", feature = "gzip_compression"))]
+	let layers = layers
+		.layer(SetResponseHeaderLayer::if_not_present(
+			header::ACCEPT_ENCODING,
+			HeaderValue::from_str(&accepted_encodings().join(", "))?,
+		))
+		.layer(tower_http::decompression::RequestDecompressionLayer::new());
+
 	let services_ = services.clone();
 	let layers = layers
 		.layer(SetSensitiveHeadersLayer::new([header::AUTHORIZATION]))
@@ -61,7 +69,7 @@ pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 				.on_response(DefaultOnResponse::new().level(Level::DEBUG)),
 		)
 		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), request::handle))
-		.layer(SecureClientIpSource::ConnectInfo.into_extension())
+		.layer(client_ip_source(server)?.into_extension())
 		.layer(ResponseBodyTimeoutLayer::new(Duration::from_secs(
 			server.config.client_response_timeout,
 		)))
@@ -143,6 +151,20 @@ fn compression_layer(server: &Server) -> tower_http::compression::CompressionLay
 	compression_layer
 }
 
+/// Encodings of request bodies accepted, advertised to servers so that they
+/// may compress their requests (RFC 7694).
+#[cfg(any(feature = "zstd_compression", feature = "gzip_compression"))]
+fn accepted_encodings() -> Vec<&'static str> {
+	[
+		cfg!(feature = "zstd_compression").then_some("zstd"),
+		cfg!(feature = "gzip_compression").then_some("gzip"),
+		Some("identity"),
+	]
+	.into_iter()
+	.flatten()
+	.collect()
+}
+
 fn cors_layer(server: &Server) -> CorsLayer {
 	const METHODS: [Method; 7] = [
 		Method::DELETE,
@@ -154,14 +176,19 @@ fn cors_layer(server: &Server) -> CorsLayer {
 		Method::PUT,
 	];
 
-	let headers: [HeaderName; 5] = [
+	let headers: [HeaderName; 7] = [
 		header::ACCEPT,
 		header::AUTHORIZATION,
 		header::CONTENT_TYPE,
+		header::IF_MATCH,
+		header::IF_NONE_MATCH,
 		header::ORIGIN,
 		HeaderName::from_lowercase(b"x-requested-with").unwrap(),
 	];
 
+	// Read by clients of the rendezvous sessions of MSC4108.
+	let exposed: [HeaderName; 3] = [header::ETAG, header::EXPIRES, header::LAST_MODIFIED];
+
 	let allow_origin_list = server
 		.config
 		.access_control_allow_origin
@@ -184,6 +211,7 @@ fn cors_layer(server: &Server) -> CorsLayer {
 		.max_age(Duration::from_secs(86400))
 		.allow_methods(METHODS)
 		.allow_headers(headers)
+		.expose_headers(exposed)
 		.allow_origin(allow_origin)
 }
 
@@ -253,3 +281,11 @@ fn truncated_matched_path(path: &MatchedPath) -> &str {
 		.rsplit_once('{')
 		.map_or(path.as_str(), |path| path.0.strip_suffix('/').unwrap_or(path.0))
 }
+
+fn client_ip_source(server: &Server) -> Result<SecureClientIpSource> {
+	server
+		.config
+		.client_ip_source
+		.parse()
+		.map_err(|_| err!(Config("client_ip_source", "Unknown source of client addresses.")))
+}
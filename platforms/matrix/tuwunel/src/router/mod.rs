@@ -2,6 +2,7 @@
 #![expect(clippy::duration_suboptimal_units)] // remove after MSRV 1.91
 
 mod handle;
+mod health;
 mod layers;
 mod request;
 mod router;
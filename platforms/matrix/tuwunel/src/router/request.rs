@@ -1,7 +1,7 @@
 use std::{
 	fmt::Debug,
 	sync::{Arc, atomic::Ordering},
-	time::Duration,
+	time::{Duration, SystemTime},
 };
 
 use axum::{
@@ -12,7 +12,11 @@
 use http::{Method, StatusCode, Uri};
 use tokio::{task, time::sleep};
 use tracing::Span;
-use tuwunel_core::{Result, debug, debug_error, debug_warn, err, error, trace};
+use tuwunel_core::{
+	Result, debug, debug_error, debug_warn, err, error,
+	metrics::{Budget, SlowRequest},
+	trace, warn,
+};
 use tuwunel_service::Services;
 
 #[tracing::instrument(
@@ -77,7 +81,6 @@ pub(crate) async fn handle(
 		task = %task::id(),
 	)
 )]
-#[cfg_attr(not(debug_assertions), expect(unused_variables))]
 async fn execute(
 	// we made a safety contract that Services will not go out of scope
 	// during the request; this ensures a reference is accounted for at
@@ -106,7 +109,51 @@ async fn execute(
 			.fetch_sub(1, Ordering::Relaxed);
 	}};
 
-	next.run(req).await
+	let received = SystemTime::now();
+	let method = req.method().clone();
+	let path = req.uri().path().to_owned();
+	let (response, budget) = Budget::measure(next.run(req)).await;
+
+	let config = &services.server.config;
+	let slow_duration = if is_long_poll(&path) {
+		Duration::ZERO
+	} else {
+		Duration::from_millis(config.slow_request_duration)
+	};
+
+	if budget.exceeds(slow_duration, config.slow_request_db_reads, config.slow_request_allocated)
+	{
+		let status = response.status().as_u16();
+		warn!(
+			%method,
+			%path,
+			status,
+			elapsed = ?budget.elapsed,
+			db_reads = budget.db_reads,
+			allocated = budget.allocated,
+			"slow request"
+		);
+
+		services.server.metrics.slow_requests.push(
+			SlowRequest {
+				received,
+				method: method.to_string(),
+				path,
+				status,
+				budget,
+			},
+			config.slow_requests_kept,
+		);
+	}
+
+	response
+}
+
+/// Whether the request waits for new data to respond, so its duration says
+/// nothing of its cost.
+fn is_long_poll(path: &str) -> bool {
+	path.strip_prefix("/_matrix/client/")
+		.is_some_and(|path| path.ends_with("/sync") || matches!(path, "v3/events" | "r0/events"))
 }
 
 fn handle_result(method: &Method, uri: &Uri, result: Response) -> Result<Response, StatusCode> {
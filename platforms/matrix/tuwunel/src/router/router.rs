@@ -1,17 +1,23 @@
 use std::sync::Arc;
 
-use axum::{Router, response::IntoResponse, routing::get};
+use axum::{Router, middleware::from_fn_with_state, response::IntoResponse, routing::get};
 use http::{StatusCode, Uri};
 use ruma::api::client::error::ErrorKind;
 use tuwunel_api::router::{state, state::Guard};
 use tuwunel_core::Error;
 use tuwunel_service::Services;
 
+use crate::health;
+
 pub(crate) fn build(services: &Arc<Services>) -> (Router, Guard) {
 	let router = Router::<state::State>::new();
 	let (state, guard) = state::create(services.clone());
 	let router = tuwunel_api::router::build(router, &services.server)
+		.route_layer(from_fn_with_state(state, tuwunel_api::router::media_stream))
+		.route_layer(from_fn_with_state(state, tuwunel_api::router::ratelimit))
+		.route_layer(from_fn_with_state(state, tuwunel_api::router::disabled_endpoints))
 		.route("/", get(it_works))
+		.merge(health::router(&services.server))
 		.fallback(not_found)
 		.with_state(state);
 
@@ -3,9 +3,10 @@
 	time::Duration,
 };
 
+use axum_server::Handle;
 use futures::{FutureExt, pin_mut};
 use tuwunel_core::{
-	Error, Result, Server, debug, debug_error, debug_info, error, info,
+	Error, Phase, Result, Server, debug, debug_error, debug_info, error, info,
 	utils::{BoolExt, future::OptionFutureExt},
 };
 use tuwunel_service::Services;
@@ -61,8 +62,29 @@ pub(crate) async fn run(services: Arc<Services>) -> Result {
 #[tracing::instrument(skip_all)]
 pub(crate) async fn start(server: Arc<Server>) -> Result<Arc<Services>> {
 	debug!("Starting...");
+	server.set_phase(Phase::Starting);
+
+	// Answer health checks while migrating; the listener is released before
+	// the server proper binds the addresses.
+	let health_handle = Handle::new();
+	let health = if server.config.listening {
+		Some(serve::serve_health(&server, &health_handle).await?)
+	} else {
+		None
+	};
+
+	let services = match Services::build(server.clone()).await {
+		| Ok(services) => services.start().await,
+		| Err(e) => Err(e),
+	};
+
+	if let Some(health) = health {
+		health_handle.shutdown();
+		health.join_all().await;
+	}
 
-	let services = Services::build(server).await?.start().await?;
+	let services = services?;
+	server.set_phase(Phase::Ready);
 
 	#[cfg(all(feature = "systemd", target_os = "linux"))]
 	sd_notify::notify(true, &[sd_notify::NotifyState::Ready])
@@ -3,14 +3,18 @@
 mod tls;
 mod unix;
 
-use std::sync::{Arc, atomic::Ordering};
+use std::{
+	net::SocketAddr,
+	sync::{Arc, atomic::Ordering},
+};
 
+use axum_server::Handle;
 use tokio::task::JoinSet;
-use tuwunel_core::{Result, debug_info};
+use tuwunel_core::{Result, Server, debug_info};
 use tuwunel_service::Services;
 
 use super::layers;
-use crate::handle::ServerHandle;
+use crate::{handle::ServerHandle, health};
 
 /// Serve clients
 pub(super) async fn serve(services: Arc<Services>, handle: ServerHandle) -> Result {
@@ -75,3 +79,33 @@ pub(super) async fn serve(services: Arc<Services>, handle: ServerHandle) -> Resu
 
 	Ok(())
 }
+
+/// Answer health checks on the listening addresses while the server is
+/// starting, until the handle is shut down.
+pub(super) async fn serve_health(
+	server: &Arc<Server>,
+	handle: &Handle<SocketAddr>,
+) -> Result<JoinSet<Result<(), std::io::Error>>> {
+	let config = &server.config;
+	let mut join_set = JoinSet::new();
+
+	let addrs = config.get_bind_addrs();
+	if addrs.is_empty() {
+		return Ok(join_set);
+	}
+
+	let app = health::router(server).fallback(health::starting);
+	if config.tls.certs.is_some() {
+		// The services installing the crypto provider are not built yet; the
+		// first to install it wins.
+		#[cfg(feature = "direct_tls")]
+		{
+			_ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+			tls::serve(server, &app, handle, &mut join_set, &addrs).await?;
+		}
+	} else {
+		plain::serve(server, &app, handle, &mut join_set, &addrs);
+	}
+
+	Ok(join_set)
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+
+use futures::{Stream, StreamExt};
+use ruma::{OwnedRoomId, RoomId, UserId, events::AnyRawAccountDataEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tuwunel_core::{Result, err, implement, utils::result::LogErr};
+
+/// The account data of a user as whole events, keyed by room for room
+/// account data. Push rules are carried as the global `m.push_rules` event.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Export {
+	#[serde(default)]
+	pub global: Vec<JsonValue>,
+
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub rooms: BTreeMap<OwnedRoomId, Vec<JsonValue>>,
+}
+
+/// Collects the global account data of a user and their account data in
+/// each of `rooms`.
+#[implement(super::Service)]
+pub async fn export<'a, Rooms>(&self, user_id: &UserId, rooms: Rooms) -> Export
+where
+	Rooms: Stream<Item = &'a RoomId> + Send,
+{
+	let global = self.export_events(None, user_id).await;
+
+	let rooms = rooms
+		.then(async |room_id| (room_id, self.export_events(Some(room_id), user_id).await))
+		.filter_map(async |(room_id, events)| {
+			(!events.is_empty()).then(|| (room_id.to_owned(), events))
+		})
+		.collect()
+		.await;
+
+	Export { global, rooms }
+}
+
+/// Places each event of an export in the account data of a user, replacing
+/// any of the same type. Returns the number of events imported.
+#[implement(super::Service)]
+pub async fn import(&self, user_id: &UserId, export: &Export) -> Result<usize> {
+	let rooms = export.rooms.iter().flat_map(|(room_id, events)| {
+		events
+			.iter()
+			.map(move |event| (Some(&**room_id), event))
+	});
+
+	let events = export
+		.global
+		.iter()
+		.map(|event| (None, event))
+		.chain(rooms);
+
+	let mut imported = 0_usize;
+	for (room_id, event) in events {
+		let event_type = event
+			.get("type")
+			.and_then(JsonValue::as_str)
+			.ok_or_else(|| err!("Account data event is missing its type."))?;
+
+		self.update(room_id, user_id, event_type.into(), event)
+			.await?;
+
+		imported = imported.saturating_add(1);
+	}
+
+	Ok(imported)
+}
+
+#[implement(super::Service)]
+async fn export_events(&self, room_id: Option<&RoomId>, user_id: &UserId) -> Vec<JsonValue> {
+	self.changes_since(room_id, user_id, 0, None)
+		.filter_map(async |event| {
+			match event {
+				| AnyRawAccountDataEvent::Global(raw) => raw.deserialize_as::<JsonValue>(),
+				| AnyRawAccountDataEvent::Room(raw) => raw.deserialize_as::<JsonValue>(),
+			}
+			.map_err(|e| err!(Database("Invalid account data of {user_id}: {e}")))
+			.log_err()
+			.ok()
+		})
+		.collect()
+		.await
+}
@@ -1,4 +1,5 @@
 mod direct;
+mod export;
 mod room_tags;
 
 use std::sync::Arc;
@@ -19,6 +20,8 @@
 };
 use tuwunel_database::{Deserialized, Handle, Ignore, Interfix, Json, Map};
 
+pub use self::export::Export;
+
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
 	db: Data,
@@ -12,7 +12,7 @@
 pub use create::create_admin_room;
 use futures::{Future, FutureExt, TryFutureExt};
 use ruma::{
-	OwnedEventId, OwnedRoomAliasId, OwnedRoomId, RoomId, UserId,
+	OwnedEventId, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId, UserId,
 	events::room::message::{Relation, RoomMessageEventContent},
 };
 use tokio::sync::{RwLock, mpsc};
@@ -37,6 +37,9 @@ pub struct Service {
 pub struct CommandInput {
 	pub command: String,
 	pub reply_id: Option<OwnedEventId>,
+
+	/// Admin who issued the command; None from the console or the config.
+	pub sender: Option<OwnedUserId>,
 }
 
 /// Prototype of the tab-completer. The input is buffered text when tab
@@ -121,6 +124,8 @@ async fn interrupt(&self) {
 	}
 
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+
+	fn dependencies(&self) -> &'static [&'static str] { &["appservice", "emergency"] }
 }
 
 impl Service {
@@ -152,7 +157,12 @@ pub async fn send_message(&self, message_content: RoomMessageEventContent) -> Re
 	/// Posts a command to the command processor queue and returns. Processing
 	/// will take place on the service worker's task asynchronously. Errors if
 	/// the queue is full.
-	pub async fn command(&self, command: String, reply_id: Option<OwnedEventId>) -> Result {
+	pub async fn command(
+		&self,
+		command: String,
+		reply_id: Option<OwnedEventId>,
+		sender: Option<OwnedUserId>,
+	) -> Result {
 		let Some(sender) = self
 			.channel
 			.read()
@@ -163,7 +173,7 @@ pub async fn command(&self, command: String, reply_id: Option<OwnedEventId>) ->
 		};
 
 		sender
-			.send(CommandInput { command, reply_id })
+			.send(CommandInput { command, reply_id, sender })
 			.await
 			.map_err(|e| err!("Failed to enqueue admin command: {e:?}"))
 	}
@@ -175,7 +185,7 @@ pub async fn command_in_place(
 		command: String,
 		reply_id: Option<OwnedEventId>,
 	) -> ProcessorResult {
-		self.process_command(CommandInput { command, reply_id })
+		self.process_command(CommandInput { command, reply_id, sender: None })
 			.await
 	}
 
@@ -0,0 +1,112 @@
+//! Device list changes of interest to each appservice, recorded in a stream
+//! of its own rather than derived from the global one. An appservice resumes
+//! from the position it last saw after a restart and is given only the
+//! changes to users in its namespace or sharing an encrypted room with its
+//! users, so it can resync encryption state without walking every room.
+
+use std::collections::BTreeSet;
+
+use futures::{Stream, StreamExt};
+use ruma::{RoomId, UserId};
+use tuwunel_core::{
+	implement,
+	utils::{ReadyExt, stream::TryIgnore},
+};
+use tuwunel_database::{Ignore, Interfix};
+
+/// Records a change to the device list of `user_id` at `count` for each
+/// appservice receiving ephemeral data which is interested in the user: the
+/// user is in its namespace or any of the encrypted `rooms` the user is joined
+/// to has one of its users.
+#[implement(super::Service)]
+pub async fn mark_device_list_update(&self, user_id: &UserId, rooms: &[&RoomId], count: u64) {
+	let registrations: Vec<_> = self
+		.read()
+		.await
+		.values()
+		.filter(|info| info.registration.receive_ephemeral)
+		.cloned()
+		.collect();
+
+	let mut interested: BTreeSet<_> = registrations
+		.iter()
+		.filter(|info| info.is_user_match(user_id))
+		.map(|info| info.registration.id.as_str())
+		.collect();
+
+	for room_id in rooms {
+		if interested.len() == registrations.len() {
+			break;
+		}
+
+		interested.extend(
+			self.services
+				.state_cache
+				.appservices_in_room(room_id, &registrations)
+				.await
+				.into_iter()
+				.map(|info| info.registration.id.as_str()),
+		);
+	}
+
+	for appservice_id in interested {
+		self.db
+			.appserviceidcount_userid
+			.put_raw((appservice_id, count), user_id);
+	}
+}
+
+/// Returns the users whose device lists changed after `from` and up to and
+/// including `to` which are of interest to an appservice, along with the
+/// count of each change. A user changing more than once appears more than
+/// once.
+#[implement(super::Service)]
+pub fn device_list_changes<'a>(
+	&'a self,
+	appservice_id: &'a str,
+	from: u64,
+	to: Option<u64>,
+) -> impl Stream<Item = (&'a UserId, u64)> + Send + 'a {
+	type KeyVal<'a> = ((&'a str, u64), &'a UserId);
+
+	let to = to.unwrap_or(u64::MAX);
+	let start = (appservice_id, from.saturating_add(1));
+	self.db
+		.appserviceidcount_userid
+		.stream_from(&start)
+		.ignore_err()
+		.ready_take_while(move |((id, count), _): &KeyVal<'_>| {
+			*id == appservice_id && *count <= to
+		})
+		.map(|((_, count), user_id): KeyVal<'_>| (user_id, count))
+}
+
+/// Forgets the device list changes recorded for an appservice up to and
+/// including `to`, once it has seen them.
+#[implement(super::Service)]
+pub async fn prune_device_list_changes(&self, appservice_id: &str, to: u64) {
+	let prefix = (appservice_id, Interfix);
+	self.db
+		.appserviceidcount_userid
+		.keys_prefix(&prefix)
+		.ignore_err()
+		.ready_take_while(|(_, count): &(Ignore, u64)| *count <= to)
+		.ready_for_each(|(_, count)| {
+			self.db
+				.appserviceidcount_userid
+				.del((appservice_id, count));
+		})
+		.await;
+}
+
+/// Forgets the device list changes recorded for an appservice.
+#[implement(super::Service)]
+pub(super) async fn clear_device_list_changes(&self, appservice_id: &str) {
+	let prefix = (appservice_id, Interfix);
+	self.db
+		.appserviceidcount_userid
+		.keys_prefix_raw(&prefix)
+		.ignore_err()
+		.ready_for_each(|key| self.db.appserviceidcount_userid.remove(key))
+		.await;
+}
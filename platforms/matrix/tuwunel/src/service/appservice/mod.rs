@@ -1,7 +1,9 @@
 mod append;
+mod device_lists;
 mod namespace_regex;
 mod registration_info;
 pub(crate) mod request;
+mod to_device;
 
 use std::{
 	collections::{BTreeMap, HashSet},
@@ -25,6 +27,8 @@ pub struct Service {
 }
 
 struct Data {
+	appserviceidcount_todevice: Arc<Map>,
+	appserviceidcount_userid: Arc<Map>,
 	id_appserviceregistrations: Arc<Map>,
 }
 
@@ -37,6 +41,8 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 			registration_info: RwLock::new(BTreeMap::new()),
 			services: args.services.clone(),
 			db: Data {
+				appserviceidcount_todevice: args.db["appserviceidcount_todevice"].clone(),
+				appserviceidcount_userid: args.db["appserviceidcount_userid"].clone(),
 				id_appserviceregistrations: args.db["id_appserviceregistrations"].clone(),
 			},
 		}))
@@ -154,6 +160,11 @@ pub async fn unregister_appservice(&self, appservice_id: &str) -> Result {
 			.id_appserviceregistrations
 			.remove(appservice_id);
 
+		self.clear_device_list_changes(appservice_id)
+			.await;
+
+		self.clear_to_device_events(appservice_id).await;
+
 		// deletes all active requests for the appservice if there are any so we stop
 		// sending to the URL
 		self.services
@@ -1,4 +1,4 @@
-use std::{fmt::Debug, mem};
+use std::fmt::Debug;
 
 use bytes::BytesMut;
 use ruma::api::{
@@ -66,7 +66,7 @@ pub async fn send_request<T>(
 
 	let reqwest_request = reqwest::Request::try_from(http_request)?;
 
-	let mut response = client
+	let response = client
 		.execute(reqwest_request)
 		.await
 		.map_err(|e| {
@@ -77,34 +77,20 @@ pub async fn send_request<T>(
 			e
 		})?;
 
-	// reqwest::Response -> http::Response conversion
+	let response = crate::client::into_http_response(response).await?; // TODO: handle timeout
 	let status = response.status();
-	let mut http_response_builder = http::Response::builder()
-		.status(status)
-		.version(response.version());
-
-	mem::swap(
-		response.headers_mut(),
-		http_response_builder
-			.headers_mut()
-			.expect("http::response::Builder is usable"),
-	);
-
-	let body = response.bytes().await?; // TODO: handle timeout
-
 	if !status.is_success() {
-		debug_error!("Appservice response bytes: {:?}", utils::string_from_bytes(&body));
+		debug_error!(
+			"Appservice response bytes: {:?}",
+			utils::string_from_bytes(response.body())
+		);
 		return Err!(BadServerResponse(warn!(
 			"Appservice \"{}\" returned unsuccessful HTTP response {status} at {dest}",
 			registration.id
 		)));
 	}
 
-	let response = T::IncomingResponse::try_from_http_response(
-		http_response_builder
-			.body(body)
-			.expect("reqwest body is valid http body"),
-	);
+	let response = T::IncomingResponse::try_from_http_response(response);
 
 	response.map(Some).map_err(|e| {
 		err!(BadServerResponse(warn!(
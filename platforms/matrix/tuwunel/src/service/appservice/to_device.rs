@@ -0,0 +1,122 @@
+//! To-device events sent to users in the namespace of an appservice, pushed to
+//! the appservice in its transactions per MSC4203 rather than waiting for its
+//! users to sync. Each event is queued for the appservice by reference to the
+//! device queue holding it, and leaves both once the appservice accepts it.
+
+use futures::StreamExt;
+use ruma::{DeviceId, UserId, events::AnyToDeviceEvent, serde::Raw};
+use serde_json::{Map as JsonMap, Value as JsonValue, value::to_raw_value};
+use tuwunel_core::{
+	implement,
+	result::LogErr,
+	utils::{ReadyExt, stream::TryIgnore},
+};
+use tuwunel_database::Interfix;
+
+/// Maximum number of to-device events in one transaction to an appservice.
+const TO_DEVICE_LIMIT: usize = 100;
+
+/// Queues the to-device event at `count` for `device_id` of `user_id` to each
+/// appservice receiving ephemeral data with the user in its namespace, and
+/// wakes their senders.
+#[implement(super::Service)]
+pub async fn mark_to_device_event(&self, user_id: &UserId, device_id: &DeviceId, count: u64) {
+	let ids: Vec<_> = self
+		.read()
+		.await
+		.values()
+		.filter(|info| info.registration.receive_ephemeral && info.is_user_match(user_id))
+		.map(|info| info.registration.id.clone())
+		.collect();
+
+	for id in ids {
+		let key = (id.as_str(), count);
+		self.db
+			.appserviceidcount_todevice
+			.put(key, (user_id, device_id));
+
+		self.services
+			.sending
+			.flush_appservice(id)
+			.log_err()
+			.ok();
+	}
+}
+
+/// Returns the oldest to-device events queued for an appservice with their
+/// counts, each carrying the `to_user_id` and `to_device_id` it was sent to.
+/// Events no longer held by their device, having been synced or pruned, are
+/// dropped from the queue.
+#[implement(super::Service)]
+pub async fn to_device_events(&self, appservice_id: &str) -> Vec<(u64, Raw<AnyToDeviceEvent>)> {
+	type KeyVal<'a> = ((&'a str, u64), (&'a UserId, &'a DeviceId));
+
+	let prefix = (appservice_id, Interfix);
+	self.db
+		.appserviceidcount_todevice
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.take(TO_DEVICE_LIMIT)
+		.filter_map(async |((_, count), (user_id, device_id)): KeyVal<'_>| {
+			let Ok(event) = self
+				.services
+				.users
+				.get_to_device_event(user_id, device_id, count)
+				.await
+			else {
+				self.db
+					.appserviceidcount_todevice
+					.del((appservice_id, count));
+
+				return None;
+			};
+
+			let mut event: JsonMap<String, JsonValue> = event.deserialize_as().ok()?;
+			event.insert("to_user_id".into(), user_id.as_str().into());
+			event.insert("to_device_id".into(), device_id.as_str().into());
+
+			to_raw_value(&event)
+				.map(Raw::from_json)
+				.ok()
+				.map(|event| (count, event))
+		})
+		.collect()
+		.await
+}
+
+/// Removes the to-device events up to and including `until` which were
+/// pushed to an appservice, from its queue and those of their devices.
+#[implement(super::Service)]
+pub async fn mark_to_device_delivered(&self, appservice_id: &str, until: u64) {
+	type KeyVal<'a> = ((&'a str, u64), (&'a UserId, &'a DeviceId));
+
+	let prefix = (appservice_id, Interfix);
+	self.db
+		.appserviceidcount_todevice
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.ready_take_while(|((_, count), _): &KeyVal<'_>| *count <= until)
+		.ready_for_each(|((_, count), (user_id, device_id)): KeyVal<'_>| {
+			self.services
+				.users
+				.remove_to_device_event(user_id, device_id, count);
+
+			self.db
+				.appserviceidcount_todevice
+				.del((appservice_id, count));
+		})
+		.await;
+}
+
+/// Forgets the to-device events queued for an appservice. The events remain
+/// queued for their devices.
+#[implement(super::Service)]
+pub(super) async fn clear_to_device_events(&self, appservice_id: &str) {
+	let prefix = (appservice_id, Interfix);
+	self.db
+		.appserviceidcount_todevice
+		.keys_prefix_raw(&prefix)
+		.ignore_err()
+		.ready_for_each(|key| self.db.appserviceidcount_todevice.remove(key))
+		.await;
+}
@@ -1,12 +1,19 @@
 use std::{
+	mem,
+	net::IpAddr,
 	ops::Deref,
 	sync::{Arc, LazyLock},
 	time::Duration,
 };
 
+use bytes::Bytes;
 use ipaddress::IPAddress;
+use regex::Regex;
 use reqwest::{Certificate, Client, ClientBuilder, dns::Resolve, redirect};
-use tuwunel_core::{Config, Result, either::Either, err, implement, trace};
+use ruma::ServerName;
+use tuwunel_core::{
+	Config, Err, Result, config::proxy::ProxyConfig, either::Either, err, implement, trace,
+};
 
 use crate::{Services, service};
 
@@ -21,8 +28,32 @@ pub struct Clients {
 	pub appservice: Client,
 	pub pusher: Client,
 	pub oauth: Client,
+	pub destinations: Vec<DestinationClients>,
 }
 
+/// Clients for the federation destinations matching a pattern of
+/// `federation_bound_interface`, bound to its source address or interface.
+pub struct DestinationClients {
+	pub destination: Regex,
+	pub federation: Client,
+	pub synapse: Client,
+	pub sender: Client,
+}
+
+/// Names of the clients which can be bound by `client_bound_interface`.
+const CLIENT_CLASSES: &[&str] = &[
+	"default",
+	"url_preview",
+	"extern_media",
+	"well_known",
+	"federation",
+	"synapse",
+	"sender",
+	"appservice",
+	"pusher",
+	"oauth",
+];
+
 pub struct Service {
 	pub clients: LazyLock<Clients, Box<dyn FnOnce() -> Clients + Send>>,
 
@@ -38,6 +69,13 @@ fn deref(&self) -> &Self::Target { &self.clients }
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		let config = &args.server.config;
+		if let Some(class) = config
+			.client_bound_interface
+			.keys()
+			.find(|class| !CLIENT_CLASSES.contains(&class.as_str()))
+		{
+			return Err!(Config("client_bound_interface", "Unknown client {class:?}"));
+		}
 
 		Ok(Arc::new(Self {
 			clients: LazyLock::new(Box::new({
@@ -61,20 +99,34 @@ fn name(&self) -> &str { service::make_name(std::module_path!()) }
 
 fn make_clients(services: &Services) -> Result<Clients> {
 	macro_rules! with {
-		($builder:ident => $make:expr) => {{
-			let $builder = base(&services.config, None)?;
+		($class:ident, $builder:ident => $make:expr) => {{
+			let $builder = base(&services.config, None, None)?;
+			let $builder = bind_to($builder, client_bind(services, stringify!($class)))?;
 			$make.build()?
 		}};
-		($name:literal, $builder:ident => $make:expr) => {{
-			let $builder = base(&services.config, Some($name))?;
+		($class:ident, $name:literal, $builder:ident => $make:expr) => {{
+			let $builder = base(&services.config, Some($name), None)?;
+			let $builder = bind_to($builder, client_bind(services, stringify!($class)))?;
+			$make.build()?
+		}};
+		($class:ident,proxy: $proxy:ident, $builder:ident => $make:expr) => {{
+			let proxy = services.config.$proxy.as_ref();
+			let $builder = base(&services.config, None, proxy)?;
+			let $builder = bind_to($builder, client_bind(services, stringify!($class)))?;
+			$make.build()?
+		}};
+		($class:ident, $name:literal,proxy: $proxy:ident, $builder:ident => $make:expr) => {{
+			let proxy = services.config.$proxy.as_ref();
+			let $builder = base(&services.config, Some($name), proxy)?;
+			let $builder = bind_to($builder, client_bind(services, stringify!($class)))?;
 			$make.build()?
 		}};
 	}
 
 	Ok(Clients {
-		default: with!(cb => cb.dns_resolver(Arc::clone(&services.resolver.resolver))),
+		default: with!(default, cb => cb.dns_resolver(Arc::clone(&services.resolver.resolver))),
 
-		url_preview: with!("preview", cb => {
+		url_preview: with!(url_preview, "preview", proxy: url_preview_proxy, cb => {
 			let interface = &services
 				.config
 				.url_preview_bound_interface;
@@ -82,17 +134,20 @@ macro_rules! with {
 			let bind_addr = interface.clone().and_then(Either::left);
 			let bind_iface = interface.clone().and_then(Either::right);
 
-			builder_interface(cb, bind_iface.as_deref())?
-				.local_address(bind_addr)
-				.dns_resolver(Arc::clone(&services.resolver.resolver))
-				.redirect(redirect::Policy::limited(3))
+			let cb = builder_interface(cb, bind_iface.as_deref())?;
+			match bind_addr {
+				| Some(bind_addr) => cb.local_address(bind_addr),
+				| None => cb,
+			}
+			.dns_resolver(Arc::clone(&services.resolver.resolver))
+			.redirect(redirect::Policy::limited(3))
 		}),
 
-		extern_media: with!(cb => cb
+		extern_media: with!(extern_media, cb => cb
 			.dns_resolver(Arc::clone(&services.resolver.resolver))
 			.redirect(redirect::Policy::limited(3))),
 
-		well_known: with!(cb => cb
+		well_known: with!(well_known, proxy: federation_proxy, cb => cb
 			.dns_resolver(Arc::clone(&services.resolver.resolver))
 			.connect_timeout(Duration::from_secs(
 				services.config.well_known_conn_timeout,
@@ -102,32 +157,13 @@ macro_rules! with {
 			.pool_max_idle_per_host(0)
 			.redirect(redirect::Policy::limited(4))),
 
-		federation: with!(cb => cb
-			.dns_resolver(Arc::clone(&services.resolver.resolver.hooked))
-			.read_timeout(Duration::from_secs(services.config.federation_timeout))
-			.pool_max_idle_per_host(services.config.federation_idle_per_host.into())
-			.pool_idle_timeout(Duration::from_secs(
-				services.config.federation_idle_timeout,
-			))
-			.redirect(redirect::Policy::limited(3))),
+		federation: federation_client(services, client_bind(services, "federation"))?,
 
-		synapse: with!(cb => cb
-			.dns_resolver(Arc::clone(&services.resolver.resolver.hooked))
-			.read_timeout(Duration::from_secs(305))
-			.pool_max_idle_per_host(0)
-			.redirect(redirect::Policy::limited(3))),
+		synapse: synapse_client(services, client_bind(services, "synapse"))?,
 
-		sender: with!(cb => cb
-			.dns_resolver(Arc::clone(&services.resolver.resolver.hooked))
-			.read_timeout(Duration::from_secs(services.config.sender_timeout))
-			.timeout(Duration::from_secs(services.config.sender_timeout))
-			.pool_max_idle_per_host(1)
-			.pool_idle_timeout(Duration::from_secs(
-				services.config.sender_idle_timeout,
-			))
-			.redirect(redirect::Policy::limited(2))),
+		sender: sender_client(services, client_bind(services, "sender"))?,
 
-		appservice: with!(cb => cb
+		appservice: with!(appservice, cb => cb
 			.dns_resolver(appservice_resolver(services))
 			.connect_timeout(Duration::from_secs(5))
 			.read_timeout(Duration::from_secs(services.config.appservice_timeout))
@@ -138,7 +174,7 @@ macro_rules! with {
 			))
 			.redirect(redirect::Policy::limited(2))),
 
-		pusher: with!(cb => cb
+		pusher: with!(pusher, proxy: pusher_proxy, cb => cb
 			.dns_resolver(Arc::clone(&services.resolver.resolver))
 			.pool_max_idle_per_host(1)
 			.pool_idle_timeout(Duration::from_secs(
@@ -146,14 +182,95 @@ macro_rules! with {
 			))
 			.redirect(redirect::Policy::limited(2))),
 
-		oauth: with!(cb => cb
+		oauth: with!(oauth, proxy: oauth_proxy, cb => cb
 			.dns_resolver(Arc::clone(&services.resolver.resolver))
 			.redirect(redirect::Policy::limited(0))
 			.pool_max_idle_per_host(1)),
+
+		destinations: services
+			.config
+			.federation_bound_interface
+			.iter()
+			.map(|binding| {
+				let bind = Some(binding.bind.as_str());
+
+				Ok(DestinationClients {
+					destination: binding.destination.clone(),
+					federation: federation_client(services, bind)?,
+					synapse: synapse_client(services, bind)?,
+					sender: sender_client(services, bind)?,
+				})
+			})
+			.collect::<Result<_>>()?,
 	})
 }
 
-fn base(config: &Config, name: Option<&str>) -> Result<ClientBuilder> {
+fn federation_client(services: &Services, bind: Option<&str>) -> Result<Client> {
+	let proxy = services.config.federation_proxy.as_ref();
+	let builder = bind_to(base(&services.config, None, proxy)?, bind)?;
+
+	Ok(builder
+		.dns_resolver(Arc::clone(&services.resolver.resolver.hooked))
+		.read_timeout(Duration::from_secs(services.config.federation_timeout))
+		.pool_max_idle_per_host(services.config.federation_idle_per_host.into())
+		.pool_idle_timeout(Duration::from_secs(services.config.federation_idle_timeout))
+		.redirect(redirect::Policy::limited(3))
+		.build()?)
+}
+
+fn synapse_client(services: &Services, bind: Option<&str>) -> Result<Client> {
+	let proxy = services.config.federation_proxy.as_ref();
+	let builder = bind_to(base(&services.config, None, proxy)?, bind)?;
+
+	Ok(builder
+		.dns_resolver(Arc::clone(&services.resolver.resolver.hooked))
+		.read_timeout(Duration::from_secs(305))
+		.pool_max_idle_per_host(0)
+		.redirect(redirect::Policy::limited(3))
+		.build()?)
+}
+
+fn sender_client(services: &Services, bind: Option<&str>) -> Result<Client> {
+	let proxy = services.config.federation_proxy.as_ref();
+	let builder = bind_to(base(&services.config, None, proxy)?, bind)?;
+
+	Ok(builder
+		.dns_resolver(Arc::clone(&services.resolver.resolver.hooked))
+		.read_timeout(Duration::from_secs(services.config.sender_timeout))
+		.timeout(Duration::from_secs(services.config.sender_timeout))
+		.pool_max_idle_per_host(1)
+		.pool_idle_timeout(Duration::from_secs(services.config.sender_idle_timeout))
+		.redirect(redirect::Policy::limited(2))
+		.build()?)
+}
+
+/// The source address or interface configured by `client_bound_interface` for
+/// the client of `class`.
+fn client_bind<'a>(services: &'a Services, class: &str) -> Option<&'a str> {
+	services
+		.config
+		.client_bound_interface
+		.get(class)
+		.map(String::as_str)
+}
+
+/// Binds the client to a source IP address, or else to a network interface by
+/// name.
+fn bind_to(builder: ClientBuilder, bind: Option<&str>) -> Result<ClientBuilder> {
+	match bind.map(|bind| bind.parse::<IpAddr>().map_err(|_| bind)) {
+		| None => Ok(builder),
+		| Some(Ok(addr)) => Ok(builder.local_address(addr)),
+		| Some(Err(iface)) => builder_interface(builder, Some(iface)),
+	}
+}
+
+/// Common client configuration. The client's own `proxy`, when configured,
+/// takes precedence over the global proxy.
+fn base(
+	config: &Config,
+	name: Option<&str>,
+	proxy: Option<&ProxyConfig>,
+) -> Result<ClientBuilder> {
 	let mut user_agent = tuwunel_core::version::user_agent();
 	let user_agent_with_name;
 	if let Some(name) = name {
@@ -222,7 +339,7 @@ fn base(config: &Config, name: Option<&str>) -> Result<ClientBuilder> {
 		builder = builder.no_zstd();
 	};
 
-	match config.proxy.to_proxy()? {
+	match proxy.unwrap_or(&config.proxy).to_proxy()? {
 		| Some(proxy) => Ok(builder.proxy(proxy)),
 		| _ => Ok(builder),
 	}
@@ -272,3 +389,57 @@ pub fn valid_cidr_range(&self, ip: &IPAddress) -> bool {
 		.iter()
 		.all(|cidr| !cidr.includes(ip))
 }
+
+/// Reads a response of reqwest, body included, into one of http for ruma to
+/// parse.
+pub async fn into_http_response(
+	mut response: reqwest::Response,
+) -> Result<http::Response<Bytes>> {
+	let mut http_response = http::Response::builder()
+		.status(response.status())
+		.version(response.version());
+
+	mem::swap(
+		response.headers_mut(),
+		http_response
+			.headers_mut()
+			.expect("http::response::Builder is usable"),
+	);
+
+	let body = response.bytes().await?;
+
+	Ok(http_response
+		.body(body)
+		.expect("reqwest body is valid http body"))
+}
+
+/// The federation client for requests to `dest`.
+#[implement(Service)]
+#[must_use]
+pub fn federation_for(&self, dest: &ServerName) -> &Client {
+	self.destination(dest)
+		.map_or(&self.federation, |clients| &clients.federation)
+}
+
+/// The federation client with a large timeout for requests to `dest`.
+#[implement(Service)]
+#[must_use]
+pub fn synapse_for(&self, dest: &ServerName) -> &Client {
+	self.destination(dest)
+		.map_or(&self.synapse, |clients| &clients.synapse)
+}
+
+/// The transaction sender's client for requests to `dest`.
+#[implement(Service)]
+#[must_use]
+pub fn sender_for(&self, dest: &ServerName) -> &Client {
+	self.destination(dest)
+		.map_or(&self.sender, |clients| &clients.sender)
+}
+
+#[implement(Service)]
+fn destination(&self, dest: &ServerName) -> Option<&DestinationClients> {
+	self.destinations
+		.iter()
+		.find(|clients| clients.destination.is_match(dest.host()))
+}
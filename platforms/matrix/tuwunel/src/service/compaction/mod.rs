@@ -0,0 +1,133 @@
+//! Manual compaction of the database while online, at the interval of
+//! `rocksdb_compaction_interval`.
+
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::time::sleep;
+use tuwunel_core::{Result, debug_info, err, implement, info, warn};
+use tuwunel_database::{Map, compact::Options, keyval::KeyBuf};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+}
+
+/// Outcome of compacting one column.
+#[derive(Debug)]
+pub struct Compacted {
+	pub name: String,
+
+	/// Size of the table files before compaction, in bytes.
+	pub before: u64,
+
+	/// Size of the table files after compaction, in bytes.
+	pub after: u64,
+
+	pub elapsed: Duration,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self { services: args.services.clone() }))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let interval = self.services.config.rocksdb_compaction_interval;
+		if interval == 0 {
+			return Ok(());
+		}
+
+		let interval = Duration::from_secs(interval);
+		loop {
+			tokio::select! {
+				() = sleep(interval) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			debug_info!("Compacting the database");
+			match self.compact(None, (None, None)).await {
+				| Ok(compacted) => {
+					let (before, after) =
+						compacted
+							.iter()
+							.fold((0_u64, 0_u64), |(before, after), map| {
+								(
+									before.saturating_add(map.before),
+									after.saturating_add(map.after),
+								)
+							});
+
+					info!(columns = compacted.len(), before, after, "Compacted the database");
+				},
+				| Err(e) => warn!("Failed to compact the database: {e}"),
+			}
+		}
+	}
+
+	fn background(&self) -> bool { true }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Compacts the named columns, or all columns when None, over the key range
+/// given, one column at a time. Compaction stops between columns on shutdown.
+#[implement(Service)]
+async fn compact(
+	&self,
+	maps: Option<&[String]>,
+	range: (Option<KeyBuf>, Option<KeyBuf>),
+) -> Result<Vec<Compacted>> {
+	let maps: Vec<Arc<Map>> = match maps {
+		| Some(names) => names
+			.iter()
+			.map(|name| {
+				self.services
+					.db
+					.get(name)
+					.cloned()
+					.map_err(|_| err!(Request(NotFound("Column {name:?} not found."))))
+			})
+			.collect::<Result<_>>()?,
+		| None => self
+			.services
+			.db
+			.iter()
+			.map(|(_, map)| map.clone())
+			.collect(),
+	};
+
+	let mut compacted = Vec::with_capacity(maps.len());
+	for map in maps {
+		if !self.services.server.running() {
+			break;
+		}
+
+		let options = Options {
+			range: range.clone(),
+			exhaustive: true,
+			..Default::default()
+		};
+
+		let before = map.sst_files_size()?;
+		let timer = Instant::now();
+		let map = self
+			.services
+			.server
+			.runtime()
+			.spawn_blocking(move || map.compact_blocking(options).map(|()| map))
+			.await??;
+
+		compacted.push(Compacted {
+			name: map.name().to_owned(),
+			before,
+			after: map.sst_files_size()?,
+			elapsed: timer.elapsed(),
+		});
+	}
+
+	Ok(compacted)
+}
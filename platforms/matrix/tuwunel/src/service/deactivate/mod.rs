@@ -1,21 +1,44 @@
-use std::sync::Arc;
+use std::{fmt::Write, sync::Arc, time::Duration};
 
+use async_trait::async_trait;
 use futures::{FutureExt, StreamExt};
 use ruma::{
-	OwnedRoomId, UserId,
+	OwnedRoomId, OwnedUserId, UserId,
 	events::{StateEventType, room::power_levels::RoomPowerLevelsEventContent},
 };
-use tuwunel_core::{Event, Result, info, pdu::PduBuilder, utils::ReadyExt, warn};
+use tokio::time::sleep;
+use tuwunel_core::{Err, Event, Result, info, pdu::PduBuilder, utils::ReadyExt, warn};
 
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self { services: args.services.clone() }))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		let config = &self.services.config.ldap;
+		if !config.enable || config.sync_interval == 0 {
+			return Ok(());
+		}
+
+		let interval = Duration::from_secs(config.sync_interval);
+		loop {
+			tokio::select! {
+				() = sleep(interval) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			match self.sync_ldap_users().await {
+				| Ok(count) => info!(removed = count, "Synchronized users with LDAP"),
+				| Err(e) => warn!("Failed to synchronize users with LDAP: {e}"),
+			}
+		}
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -44,7 +67,7 @@ pub async fn full_deactivate(&self, user_id: &UserId) -> Result {
 		self.services
 			.users
 			.update_displayname(user_id, None, &all_joined_rooms)
-			.await;
+			.await?;
 		self.services
 			.users
 			.update_avatar_url(user_id, None, None, &all_joined_rooms)
@@ -161,4 +184,65 @@ pub async fn full_deactivate(&self, user_id: &UserId) -> Result {
 
 		Ok(())
 	}
+
+	/// Deactivates, or suspends as configured by `sync_removed_action`, the
+	/// users created by LDAP login which are no longer found under `base_dn`.
+	/// Suspended users are listed in the admin room, so that an admin decides
+	/// whether to deactivate them. Nothing is done unless the search returned
+	/// every user. Returns the number of users deactivated or suspended.
+	pub async fn sync_ldap_users(&self) -> Result<usize> {
+		let localparts = self.services.users.ldap_localparts().await?;
+		if localparts.is_empty() {
+			return Err!("LDAP search found no users; not deactivating any.");
+		}
+
+		let suspend = self.services.config.ldap.sync_removed_action == "suspend";
+		let removed: Vec<OwnedUserId> = self
+			.services
+			.users
+			.list_local_users()
+			.filter_map(async |user_id| {
+				let removed = !localparts.contains(user_id.localpart())
+					&& self
+						.services
+						.users
+						.origin(user_id)
+						.await
+						.is_ok_and(|origin| origin == "ldap")
+					&& !(suspend && self.services.users.is_suspended(user_id).await);
+
+				removed.then(|| user_id.to_owned())
+			})
+			.collect()
+			.await;
+
+		if !suspend {
+			for user_id in &removed {
+				self.full_deactivate(user_id).boxed().await?;
+				info!(%user_id, "Deactivated user no longer found in LDAP");
+			}
+
+			return Ok(removed.len());
+		}
+
+		if removed.is_empty() {
+			return Ok(0);
+		}
+
+		let mut notice = String::from(
+			"Suspended users created by LDAP login which are no longer found in LDAP. \
+			 Deactivate them with `!admin users deactivate`, or lift the suspension with \
+			 `!admin users unsuspend`:\n",
+		);
+
+		for user_id in &removed {
+			self.services.users.suspend(user_id);
+			info!(%user_id, "Suspended user no longer found in LDAP");
+			writeln!(notice, "- {user_id}")?;
+		}
+
+		self.services.admin.notice(&notice).await;
+
+		Ok(removed.len())
+	}
 }
@@ -0,0 +1,201 @@
+//! Export of the events appended to room timelines, including redactions, to
+//! an external message bus for indexing, analytics or compliance pipelines.
+//!
+//! Appending an event records its global count in `exportcount_pduid`, an
+//! outbox written alongside the event itself. The worker publishes the outbox
+//! in count order up to the retired count, so an event still being written is
+//! never passed over, and then persists the last count published as the
+//! high-water mark before removing the published entries. A failure before
+//! the mark is persisted publishes the batch again: delivery is at-least-once
+//! and consumers deduplicate by event ID.
+
+mod sink;
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::time::sleep;
+use tuwunel_core::{
+	Result, debug, implement,
+	matrix::pdu::{PduEvent, RawPduId},
+	utils::stream::{IterStream, ReadyExt, TryIgnore},
+	warn,
+};
+use tuwunel_database::{Deserialized, Map};
+
+use self::sink::{Record, Sink};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+	exportcount_pduid: Arc<Map>,
+	global: Arc<Map>,
+}
+
+/// Message published for each event.
+#[derive(Serialize)]
+struct Message<'a> {
+	/// Global count of the event on this server, increasing in the order
+	/// events were appended.
+	count: u64,
+	event: &'a PduEvent,
+}
+
+const HIGH_WATER_MARK: &[u8] = b"event_export_high_water_mark";
+
+/// Delay before reconnecting or publishing again after a failure.
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			exportcount_pduid: args.db["exportcount_pduid"].clone(),
+			global: args.db["global"].clone(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let config = &self.services.config.event_export;
+		if !config.enable {
+			return Ok(());
+		}
+
+		let sink = loop {
+			match sink::connect(config).await {
+				| Ok(sink) => break sink,
+				| Err(e) =>
+					warn!("Failed to connect to {} for event export: {e}", config.backend),
+			}
+
+			if !self.retry().await {
+				return Ok(());
+			}
+		};
+
+		let mut cursor = self.high_water_mark().await;
+		debug!(cursor, "Exporting events to {}", config.backend);
+		loop {
+			match self.export_batch(sink.as_ref(), cursor).await {
+				| Ok((next, 0)) => {
+					cursor = next;
+					let next = cursor.saturating_add(1);
+					tokio::select! {
+						_ = self.services.globals.wait_count(&next) => {},
+						() = self.services.server.until_shutdown() => return Ok(()),
+					}
+				},
+				| Ok((next, _)) => cursor = next,
+				| Err(e) => {
+					warn!("Failed to export events after {cursor}: {e}");
+					if !self.retry().await {
+						return Ok(());
+					}
+				},
+			}
+		}
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let high_water_mark = self.high_water_mark().await;
+		writeln!(out, "event_export_high_water_mark: {high_water_mark}")?;
+
+		Ok(())
+	}
+
+	fn background(&self) -> bool { true }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Records an event appended to a timeline for export. Called while the
+/// event's count is still pending, so it is never retired before being
+/// recorded.
+#[implement(Service)]
+pub fn append_pdu(&self, count: u64, pdu_id: &RawPduId) {
+	if !self.services.config.event_export.enable {
+		return;
+	}
+
+	const BUFSIZE: usize = size_of::<u64>();
+
+	self.exportcount_pduid
+		.aput_raw::<BUFSIZE, _, _>(count, pdu_id);
+}
+
+/// Publishes the events recorded after `cursor` up to the retired count, at
+/// most `batch_size` at once. Returns the count to continue from and the
+/// number of events published.
+#[implement(Service)]
+async fn export_batch(&self, sink: &dyn Sink, cursor: u64) -> Result<(u64, usize)> {
+	let config = &self.services.config.event_export;
+	let retired = self.services.globals.current_count();
+	let batch: Vec<(u64, RawPduId)> = self
+		.exportcount_pduid
+		.stream_from(&cursor.saturating_add(1))
+		.ignore_err()
+		.ready_take_while(|&(count, _): &(u64, &[u8])| count <= retired)
+		.take(config.batch_size.max(1))
+		.map(|(count, pdu_id)| (count, pdu_id.into()))
+		.collect()
+		.await;
+
+	let Some(&(last, _)) = batch.last() else {
+		return Ok((retired.max(cursor), 0));
+	};
+
+	let records: Vec<Record> = batch
+		.iter()
+		.stream()
+		.filter_map(async |&(count, ref pdu_id)| {
+			let event = self
+				.services
+				.timeline
+				.get_pdu_from_id(pdu_id)
+				.await
+				.ok()?;
+
+			let payload = serde_json::to_vec(&Message { count, event: &event }).ok()?;
+
+			Some(Record {
+				id: event.event_id.to_string(),
+				key: event.room_id.to_string(),
+				payload,
+			})
+		})
+		.collect()
+		.await;
+
+	sink.publish(&records).await?;
+
+	self.global
+		.insert(HIGH_WATER_MARK, last.to_be_bytes());
+	for (count, _) in &batch {
+		self.exportcount_pduid.del(count);
+	}
+
+	debug!(last, published = records.len(), "Exported events");
+
+	Ok((last, records.len()))
+}
+
+/// The count of the last event published.
+#[implement(Service)]
+pub async fn high_water_mark(&self) -> u64 {
+	self.global
+		.get(HIGH_WATER_MARK)
+		.await
+		.deserialized()
+		.unwrap_or(0)
+}
+
+/// Waits before trying again; false when the server is shutting down.
+#[implement(Service)]
+async fn retry(&self) -> bool {
+	tokio::select! {
+		() = sleep(RETRY_INTERVAL) => true,
+		() = self.services.server.until_shutdown() => false,
+	}
+}
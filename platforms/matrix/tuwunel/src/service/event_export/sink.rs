@@ -0,0 +1,181 @@
+//! Message buses events are published to, each behind its own feature.
+
+use async_trait::async_trait;
+use tuwunel_core::{Err, Result, config::EventExportConfig};
+
+/// An event ready for publishing.
+#[cfg_attr(
+	not(any(
+		feature = "event_export_nats",
+		feature = "event_export_kafka"
+	)),
+	expect(dead_code)
+)]
+pub(super) struct Record {
+	/// Event ID, for consumers to deduplicate by.
+	pub(super) id: String,
+
+	/// Room ID, for consumers to partition by.
+	pub(super) key: String,
+
+	/// JSON message.
+	pub(super) payload: Vec<u8>,
+}
+
+/// A message bus connection.
+#[async_trait]
+pub(super) trait Sink: Send + Sync {
+	/// Publishes the records in order, returning once the bus has
+	/// acknowledged all of them.
+	async fn publish(&self, records: &[Record]) -> Result;
+}
+
+/// Connects to the message bus of the configured backend.
+#[cfg_attr(
+	not(any(
+		feature = "event_export_nats",
+		feature = "event_export_kafka"
+	)),
+	expect(clippy::unused_async)
+)]
+pub(super) async fn connect(config: &EventExportConfig) -> Result<Box<dyn Sink>> {
+	match config.backend.as_str() {
+		#[cfg(feature = "event_export_nats")]
+		| "nats" => Ok(Box::new(nats::Sink::connect(config).await?)),
+
+		#[cfg(not(feature = "event_export_nats"))]
+		| "nats" => Err!(FeatureDisabled("event_export_nats")),
+
+		#[cfg(feature = "event_export_kafka")]
+		| "kafka" => Ok(Box::new(kafka::Sink::connect(config).await?)),
+
+		#[cfg(not(feature = "event_export_kafka"))]
+		| "kafka" => Err!(FeatureDisabled("event_export_kafka")),
+
+		| backend => Err!(Config("event_export.backend", "Unknown backend {backend:?}")),
+	}
+}
+
+#[cfg(feature = "event_export_nats")]
+mod nats {
+	use async_nats::{HeaderMap, jetstream};
+	use async_trait::async_trait;
+	use tuwunel_core::{Result, config::EventExportConfig, err};
+
+	use super::Record;
+
+	pub(super) struct Sink {
+		context: jetstream::Context,
+		subject: String,
+	}
+
+	impl Sink {
+		pub(super) async fn connect(config: &EventExportConfig) -> Result<Self> {
+			let client = async_nats::connect(config.servers.join(","))
+				.await
+				.map_err(|e| err!("NATS connection failed: {e}"))?;
+
+			Ok(Self {
+				context: jetstream::new(client),
+				subject: config.topic.clone(),
+			})
+		}
+	}
+
+	#[async_trait]
+	impl super::Sink for Sink {
+		async fn publish(&self, records: &[Record]) -> Result {
+			let mut acks = Vec::with_capacity(records.len());
+			for record in records {
+				let mut headers = HeaderMap::new();
+				headers.insert("Nats-Msg-Id", record.id.as_str());
+
+				let ack = self
+					.context
+					.publish_with_headers(
+						self.subject.clone(),
+						headers,
+						record.payload.clone().into(),
+					)
+					.await
+					.map_err(|e| err!("NATS publish failed: {e}"))?;
+
+				acks.push(ack);
+			}
+
+			for ack in acks {
+				ack.await
+					.map_err(|e| err!("NATS publish not acknowledged: {e}"))?;
+			}
+
+			Ok(())
+		}
+	}
+}
+
+#[cfg(feature = "event_export_kafka")]
+mod kafka {
+	use std::collections::BTreeMap;
+
+	use async_trait::async_trait;
+	use rskafka::{
+		client::{
+			ClientBuilder,
+			partition::{Compression, PartitionClient, UnknownTopicHandling},
+		},
+		record::Record as KafkaRecord,
+	};
+	use tuwunel_core::{Result, config::EventExportConfig, err};
+
+	use super::Record;
+
+	pub(super) struct Sink {
+		partition: PartitionClient,
+	}
+
+	impl Sink {
+		pub(super) async fn connect(config: &EventExportConfig) -> Result<Self> {
+			let client = ClientBuilder::new(config.servers.clone())
+				.build()
+				.await
+				.map_err(|e| err!("Kafka connection failed: {e}"))?;
+
+			let partition = client
+				.partition_client(
+					config.topic.clone(),
+					config.kafka_partition,
+					UnknownTopicHandling::Retry,
+				)
+				.await
+				.map_err(|e| err!("Kafka partition unavailable: {e}"))?;
+
+			Ok(Self { partition })
+		}
+	}
+
+	#[async_trait]
+	impl super::Sink for Sink {
+		async fn publish(&self, records: &[Record]) -> Result {
+			let timestamp = chrono::Utc::now();
+			let records = records
+				.iter()
+				.map(|record| KafkaRecord {
+					key: Some(record.key.clone().into_bytes()),
+					value: Some(record.payload.clone()),
+					headers: BTreeMap::from([(
+						"event_id".to_owned(),
+						record.id.clone().into_bytes(),
+					)]),
+					timestamp,
+				})
+				.collect();
+
+			self.partition
+				.produce(records, Compression::NoCompression)
+				.await
+				.map_err(|e| err!("Kafka produce failed: {e}"))?;
+
+			Ok(())
+		}
+	}
+}
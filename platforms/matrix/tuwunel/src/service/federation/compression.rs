@@ -0,0 +1,144 @@
+//! Compression of request bodies sent to servers which advertise accepting
+//! them, by the `Accept-Encoding` header of their responses (RFC 7694).
+
+use std::{collections::HashMap, sync::RwLock};
+
+use http::{
+	HeaderMap, HeaderValue,
+	header::{ACCEPT_ENCODING, CONTENT_ENCODING},
+};
+use reqwest::Request;
+use ruma::{OwnedServerName, ServerName};
+use tuwunel_core::{Result, implement};
+
+/// Bodies smaller than this are sent as they are.
+const MIN_COMPRESSED_LEN: usize = 1024;
+
+/// Encodings each server was last seen to accept.
+pub(super) type Encodings = RwLock<HashMap<OwnedServerName, Encoding>>;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum Encoding {
+	#[cfg(feature = "zstd_compression")]
+	Zstd,
+
+	#[cfg(feature = "gzip_compression")]
+	Gzip,
+}
+
+impl Encoding {
+	/// Encodings supported, the preferred first.
+	const ALL: &[Self] = &[
+		#[cfg(feature = "zstd_compression")]
+		Self::Zstd,
+		#[cfg(feature = "gzip_compression")]
+		Self::Gzip,
+	];
+
+	fn name(self) -> &'static str {
+		match self {
+			#[cfg(feature = "zstd_compression")]
+			| Self::Zstd => "zstd",
+			#[cfg(feature = "gzip_compression")]
+			| Self::Gzip => "gzip",
+		}
+	}
+
+	fn encode(self, body: &[u8]) -> Result<Vec<u8>> {
+		match self {
+			#[cfg(feature = "zstd_compression")]
+			| Self::Zstd => Ok(zstd::encode_all(body, 0)?),
+			#[cfg(feature = "gzip_compression")]
+			| Self::Gzip => {
+				use std::io::Write;
+
+				let mut encoder =
+					flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+				encoder.write_all(body)?;
+				Ok(encoder.finish()?)
+			},
+		}
+	}
+}
+
+/// Copy of the request with its body compressed, if the server accepts an
+/// encoding and the body is worth compressing.
+#[implement(super::Service)]
+pub(super) fn compress(&self, dest: &ServerName, request: &Request) -> Result<Option<Request>> {
+	if !self
+		.services
+		.server
+		.config
+		.federation_request_compression
+	{
+		return Ok(None);
+	}
+
+	let Some(encoding) = self.encodings.read()?.get(dest).copied() else {
+		return Ok(None);
+	};
+
+	let Some(body) = request
+		.body()
+		.and_then(reqwest::Body::as_bytes)
+		.filter(|body| body.len() >= MIN_COMPRESSED_LEN)
+	else {
+		return Ok(None);
+	};
+
+	let Some(mut compressed) = request.try_clone() else {
+		return Ok(None);
+	};
+
+	*compressed.body_mut() = Some(encoding.encode(body)?.into());
+	compressed
+		.headers_mut()
+		.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+
+	Ok(Some(compressed))
+}
+
+/// Remembers the encodings the server accepts by the headers of its response.
+#[implement(super::Service)]
+pub(super) fn remember_encoding(&self, dest: &ServerName, headers: &HeaderMap) {
+	let accepted = headers
+		.get_all(ACCEPT_ENCODING)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.map(|encoding| {
+			encoding
+				.split(';')
+				.next()
+				.unwrap_or_default()
+				.trim()
+		})
+		.collect::<Vec<_>>();
+
+	let encoding = Encoding::ALL
+		.iter()
+		.find(|encoding| accepted.contains(&encoding.name()))
+		.copied();
+
+	let mut encodings = self
+		.encodings
+		.write()
+		.expect("locked for writing");
+	match encoding {
+		| Some(encoding) => {
+			encodings.insert(dest.to_owned(), encoding);
+		},
+		| None => {
+			encodings.remove(dest);
+		},
+	}
+}
+
+/// Forgets the server accepts compressed requests, after it refused one.
+#[implement(super::Service)]
+pub(super) fn forget_encoding(&self, dest: &ServerName) {
+	self.encodings
+		.write()
+		.expect("locked for writing")
+		.remove(dest);
+}
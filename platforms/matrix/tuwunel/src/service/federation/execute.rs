@@ -27,7 +27,7 @@ pub async fn execute<T>(&self, dest: &ServerName, request: T) -> Result<T::Incom
 where
 	T: OutgoingRequest + Debug + Send,
 {
-	let client = &self.services.client.federation;
+	let client = self.services.client.federation_for(dest);
 	self.execute_on(client, dest, request).await
 }
 
@@ -42,7 +42,7 @@ pub async fn execute_synapse<T>(
 where
 	T: OutgoingRequest + Debug + Send,
 {
-	let client = &self.services.client.synapse;
+	let client = self.services.client.synapse_for(dest);
 	self.execute_on(client, dest, request).await
 }
 
@@ -82,6 +82,21 @@ pub async fn execute_on<T>(
 		.await?;
 
 	let request = self.prepare(&actual, dest, request)?;
+
+	#[cfg(any(feature = "zstd_compression", feature = "gzip_compression"))]
+	if let Some(compressed) = self.compress(dest, &request)? {
+		match self
+			.perform::<T>(&actual, dest, compressed, client)
+			.await
+		{
+			| Err(e) if e.status_code() == http::StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+				debug_warn!("{dest} refused a compressed request; sending it uncompressed.");
+				self.forget_encoding(dest);
+			},
+			| result => return result,
+		}
+	}
+
 	self.perform::<T>(&actual, dest, request, client)
 		.await
 }
@@ -102,7 +117,12 @@ async fn perform<T>(
 
 	debug!(?method, ?url, "Sending request");
 	match client.execute(request).await {
-		| Ok(response) => handle_response::<T>(actual, dest, &method, &url, response).await,
+		| Ok(response) => {
+			#[cfg(any(feature = "zstd_compression", feature = "gzip_compression"))]
+			self.remember_encoding(dest, response.headers());
+
+			handle_response::<T>(actual, dest, &method, &url, response).await
+		},
 		| Err(error) => Err(self
 			.handle_error(dest, actual, &method, &url, error)
 			.expect_err("always returns error")),
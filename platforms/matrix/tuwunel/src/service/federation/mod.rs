@@ -1,3 +1,5 @@
+#[cfg(any(feature = "zstd_compression", feature = "gzip_compression"))]
+mod compression;
 mod execute;
 mod format;
 
@@ -9,11 +11,19 @@
 
 pub struct Service {
 	services: Arc<OnceServices>,
+
+	#[cfg(any(feature = "zstd_compression", feature = "gzip_compression"))]
+	encodings: compression::Encodings,
 }
 
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
-		Ok(Arc::new(Self { services: args.services.clone() }))
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+
+			#[cfg(any(feature = "zstd_compression", feature = "gzip_compression"))]
+			encodings: Default::default(),
+		}))
 	}
 
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
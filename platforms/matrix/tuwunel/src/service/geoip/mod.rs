@@ -0,0 +1,78 @@
+//! Location of client addresses, looked up in the MaxMind database configured
+//! by `geoip_database` when built with the `geoip` feature.
+
+use std::{net::IpAddr, sync::Arc};
+
+use async_trait::async_trait;
+use tuwunel_core::Result;
+
+pub struct Service {
+	#[cfg(feature = "geoip")]
+	reader: Option<maxminddb::Reader<Vec<u8>>>,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+
+		#[cfg(feature = "geoip")]
+		let reader = config
+			.geoip_database
+			.as_ref()
+			.map(maxminddb::Reader::open_readfile)
+			.transpose()
+			.map_err(|e| {
+				tuwunel_core::err!(Config(
+					"geoip_database",
+					"Failed to open the GeoIP database: {e}"
+				))
+			})?;
+
+		#[cfg(not(feature = "geoip"))]
+		if config.geoip_database.is_some() {
+			tuwunel_core::warn!(
+				"geoip_database is set but tuwunel was built without the geoip feature; \
+				 locations will not be shown."
+			);
+		}
+
+		Ok(Arc::new(Self {
+			#[cfg(feature = "geoip")]
+			reader,
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// The city and country the address is located in, as far as known.
+	#[cfg(feature = "geoip")]
+	#[must_use]
+	pub fn locate(&self, ip: IpAddr) -> Option<String> {
+		use maxminddb::geoip2::City;
+
+		let city: City<'_> = self.reader.as_ref()?.lookup(ip).ok()?;
+		let name = |names: Option<&std::collections::BTreeMap<&str, &str>>| {
+			names.and_then(|names| names.get("en").copied())
+		};
+
+		let city_name = city
+			.city
+			.as_ref()
+			.and_then(|city| name(city.names.as_ref()));
+
+		let country = city.country.as_ref()?;
+		let country_name = name(country.names.as_ref()).or(country.iso_code)?;
+
+		Some(match city_name {
+			| Some(city_name) => format!("{city_name}, {country_name}"),
+			| None => country_name.to_owned(),
+		})
+	}
+
+	#[cfg(not(feature = "geoip"))]
+	#[must_use]
+	pub fn locate(&self, _ip: IpAddr) -> Option<String> { None }
+}
@@ -30,8 +30,9 @@ pub(super) fn new(args: &crate::Args<'_>) -> Self {
 			db: args.db.clone(),
 			global: args.db["global"].clone(),
 			retires: retires.clone(),
-			counter: Counter::new(
+			counter: Counter::with_shards(
 				count,
+				args.server.config.counter_shards,
 				Box::new(move |count| Self::store_count(&db, &db["global"], count)),
 				Box::new(move |count| Self::handle_retire(&retires, count)),
 			),
@@ -96,6 +97,18 @@ fn stored_count(global: &Arc<Map>) -> Result<u64> {
 }
 
 impl Data {
+	/// Server secret stored under `name`; generated on first use.
+	pub fn secret(&self, name: &[u8]) -> Vec<u8> {
+		self.global
+			.get_blocking(name)
+			.map(|secret| secret.to_vec())
+			.unwrap_or_else(|_| {
+				let secret = utils::rand::string(64);
+				self.global.insert(name, &secret);
+				secret.into_bytes()
+			})
+	}
+
 	pub fn bump_database_version(&self, new_version: u64) {
 		self.global.raw_put(b"version", new_version);
 	}
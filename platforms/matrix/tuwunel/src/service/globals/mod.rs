@@ -1,19 +1,26 @@
 mod data;
 
-use std::{ops::Range, sync::Arc};
+use std::{fmt::Display, ops::Range, str::FromStr, sync::Arc};
 
 use data::Data;
-use ruma::{OwnedUserId, RoomAliasId, ServerName, UserId};
-use tuwunel_core::{Result, Server, err, error};
+use ruma::{OwnedUserId, RoomAliasId, ServerName, UserId, api::Direction};
+use tuwunel_core::{
+	Err, Result, Server, err, error,
+	pagination::{self, Token},
+};
 
 use crate::service;
 
+/// Name of the secret pagination tokens are signed with.
+const PAGINATION_SECRET: &[u8] = b"pagination_secret";
+
 pub struct Service {
 	pub db: Data,
 	server: Arc<Server>,
 
 	pub server_user: OwnedUserId,
 	pub turn_secret: Option<String>,
+	pagination_key: pagination::Key,
 }
 
 impl crate::Service for Service {
@@ -33,6 +40,8 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 			})
 			.or_else(|| config.turn_secret.clone());
 
+		let pagination_key = pagination::key(&db.secret(PAGINATION_SECRET));
+
 		Ok(Arc::new(Self {
 			db,
 			server: args.server.clone(),
@@ -42,6 +51,7 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 			)
 			.expect("@conduit:server_name is valid"),
 			turn_secret,
+			pagination_key,
 		}))
 	}
 
@@ -79,6 +89,44 @@ pub fn current_count(&self) -> u64 { self.db.current_count() }
 	#[must_use]
 	pub fn pending_count(&self) -> Range<u64> { self.db.pending_count() }
 
+	/// Issue a signed pagination token for a stream position.
+	#[must_use]
+	pub fn pagination_token<T: Display>(&self, pos: T, dir: Direction) -> String {
+		Token::new(pos, dir).sign(&self.pagination_key)
+	}
+
+	/// Decode a pagination token supplied by a client to paginate in `dir`;
+	/// tokens issued for the other direction are rejected. Unsigned legacy
+	/// tokens are accepted while `allow_unsigned_pagination_tokens` is enabled.
+	pub fn parse_pagination_token<T: FromStr>(
+		&self,
+		token: &str,
+		dir: Direction,
+	) -> Result<Token<T>> {
+		self.parse_pagination_bound(token, dir)?
+			.check_dir(dir)
+	}
+
+	/// Decode a pagination token supplied by a client as the bound of a
+	/// pagination in `dir`, which may have been issued for either direction.
+	pub fn parse_pagination_bound<T: FromStr>(
+		&self,
+		token: &str,
+		dir: Direction,
+	) -> Result<Token<T>> {
+		if pagination::is_signed(token) {
+			Token::verify(token, &self.pagination_key)
+		} else if self
+			.server
+			.config
+			.allow_unsigned_pagination_tokens
+		{
+			Token::legacy(token, dir)
+		} else {
+			Err!(Request(InvalidParam("Pagination token is not signed.")))
+		}
+	}
+
 	#[inline]
 	#[must_use]
 	pub fn server_name(&self) -> &ServerName { self.server.name.as_ref() }
@@ -0,0 +1,58 @@
+//! Liveness reporting for service workers. A worker beats its heartbeat as it
+//! makes progress; the service manager restarts workers whose heartbeats stop
+//! for longer than `worker_watchdog_timeout`.
+
+use std::{
+	sync::atomic::{AtomicU64, Ordering},
+	time::Duration,
+};
+
+use tokio::time::{Interval, MissedTickBehavior, interval};
+use tuwunel_core::utils::millis_since_unix_epoch;
+
+/// Interval at which idle workers beat.
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+pub(crate) struct Heartbeat {
+	/// Milliseconds since the epoch of the last beat; zero before the first.
+	last: AtomicU64,
+}
+
+impl Heartbeat {
+	/// Reports that the worker is making progress.
+	#[inline]
+	pub(crate) fn beat(&self) {
+		self.last
+			.store(millis_since_unix_epoch(), Ordering::Relaxed);
+	}
+
+	/// Time since the last beat, or None before the first.
+	#[must_use]
+	pub(crate) fn elapsed(&self) -> Option<Duration> {
+		let last = self.last.load(Ordering::Relaxed);
+		(last != 0).then(|| Duration::from_millis(millis_since_unix_epoch().saturating_sub(last)))
+	}
+
+	/// Sleeps for `duration`, beating while idle.
+	pub(crate) async fn sleep(&self, duration: Duration) {
+		let deadline = tokio::time::sleep(duration);
+		let mut ticks = Self::interval();
+		tokio::pin!(deadline);
+		loop {
+			tokio::select! {
+				() = &mut deadline => break,
+				_ = ticks.tick() => self.beat(),
+			}
+		}
+	}
+
+	/// Ticks at [`HEARTBEAT_INTERVAL`], for worker loops to beat on while
+	/// waiting for work. The first tick completes immediately.
+	#[must_use]
+	pub(crate) fn interval() -> Interval {
+		let mut ticks = interval(HEARTBEAT_INTERVAL);
+		ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		ticks
+	}
+}
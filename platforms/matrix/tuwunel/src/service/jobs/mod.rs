@@ -0,0 +1,455 @@
+//! Background jobs: long-running maintenance recorded in the database so that
+//! it is resumed after a restart, retried with backoff when it fails, and run
+//! at most `job_concurrency` at a time.
+
+mod run;
+
+use std::{
+	collections::HashMap,
+	fmt,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use ruma::{OwnedEventId, OwnedRoomId, OwnedServerName, OwnedUserId};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	sync::Notify,
+	task::{AbortHandle, JoinError, JoinSet},
+	time::sleep,
+};
+use tuwunel_core::{
+	Err, Result, debug, debug_info, implement,
+	utils::{
+		self,
+		stream::{ReadyExt, TryIgnore},
+		time::now_secs,
+	},
+	warn,
+};
+use tuwunel_database::{Deserialized, Json, Map};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+	jobid_job: Arc<Map>,
+	global: Arc<Map>,
+
+	/// ID of the last job queued, stored under `LAST_JOB_ID`.
+	last_id: Mutex<u64>,
+
+	/// Jobs being run, by ID.
+	running: Mutex<HashMap<u64, AbortHandle>>,
+
+	/// Wakes the worker when a job is queued.
+	queued: Notify,
+}
+
+/// A unit of background work.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "task", rename_all = "snake_case")]
+pub enum Task {
+	/// Purge the messages of every room past its retention policy.
+	RetentionPurge,
+
+	/// Delete remote media older than `media_retention_remote_seconds`.
+	MediaRetention,
+
+	/// Delete remote media older than `max_age` seconds.
+	PurgeRemoteMedia {
+		max_age: u64,
+	},
+
+	/// Purge the history of a room sent before `before`, in milliseconds since
+	/// the Unix epoch.
+	PurgeHistory {
+		room_id: OwnedRoomId,
+		before: u64,
+		keep_state: bool,
+	},
+
+	/// Purge the messages of a room sent by users of a server, taken when the
+	/// job was queued so that later messages are not purged.
+	PurgeServerEvents {
+		room_id: OwnedRoomId,
+		server_name: OwnedServerName,
+		event_ids: Vec<OwnedEventId>,
+	},
+
+	/// Purge the messages of a room sent by a user, taken when the job was
+	/// queued.
+	PurgeUserEvents {
+		room_id: OwnedRoomId,
+		user_id: OwnedUserId,
+		event_ids: Vec<OwnedEventId>,
+	},
+
+	/// Invite the members of a space to a room added to it, or only `user_id`
+	/// when they knocked on it.
+	MirrorSpaceMembers {
+		space_id: OwnedRoomId,
+		room_id: OwnedRoomId,
+		user_id: Option<OwnedUserId>,
+	},
+}
+
+/// A job and its progress.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Job {
+	pub task: Task,
+	pub state: State,
+
+	/// Number of times the job was started.
+	pub attempts: u32,
+
+	/// When the job was queued, in seconds since the epoch.
+	pub created_at: u64,
+
+	/// When the job is next due to start, in seconds since the epoch.
+	pub run_after: u64,
+
+	/// When the job last finished, in seconds since the epoch.
+	pub finished_at: Option<u64>,
+
+	/// Outcome of the last attempt, or its error.
+	pub result: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+	Queued,
+	Running,
+	Succeeded,
+	Failed,
+	Cancelled,
+}
+
+/// Interval at which queued jobs are checked for being due.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Finished jobs are forgotten after this long.
+const FINISHED_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+const LAST_JOB_ID: &[u8] = b"last_job_id";
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		let global = args.db["global"].clone();
+		let last_id = global
+			.get_blocking(LAST_JOB_ID)
+			.as_deref()
+			.map_or(Ok(0_u64), utils::u64_from_bytes)?;
+
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			jobid_job: args.db["jobid_job"].clone(),
+			global,
+			last_id: Mutex::new(last_id),
+			running: Mutex::default(),
+			queued: Notify::new(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		self.requeue_interrupted().await;
+
+		let mut tasks = JoinSet::new();
+		loop {
+			self.start_due(&mut tasks).await;
+
+			tokio::select! {
+				() = sleep(POLL_INTERVAL) => self.forget_finished().await,
+				() = self.queued.notified() => {},
+				Some(result) = tasks.join_next_with_id(), if !tasks.is_empty() => {
+					self.finished(result).await;
+				},
+				() = self.services.server.until_shutdown() => break,
+			}
+		}
+
+		// Jobs interrupted here are still recorded as running; they are queued
+		// again on the next start.
+		tasks.shutdown().await;
+
+		Ok(())
+	}
+
+	fn background(&self) -> bool { true }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Queues a job. Returns its ID.
+#[implement(Service)]
+pub fn enqueue(&self, task: Task) -> u64 {
+	let now = now_secs();
+	let job = Job {
+		task,
+		state: State::Queued,
+		attempts: 0,
+		created_at: now,
+		run_after: now,
+		finished_at: None,
+		result: None,
+	};
+
+	let id = self.next_id();
+	self.jobid_job.put(id, Json(&job));
+
+	self.queued.notify_one();
+
+	debug!(id, task = %job.task, "Queued job");
+	id
+}
+
+/// Allocates the ID of a new job. IDs are only ever used once, even after
+/// the jobs holding them are forgotten.
+#[implement(Service)]
+fn next_id(&self) -> u64 {
+	let mut last_id = self.last_id.lock().expect("locked");
+	*last_id = last_id.saturating_add(1);
+
+	// Jobs queued before IDs had their own sequence were numbered from the
+	// global count.
+	while self
+		.jobid_job
+		.exists_blocking(&last_id.to_be_bytes())
+		.is_ok()
+	{
+		*last_id = last_id.saturating_add(1);
+	}
+
+	self.global
+		.insert(LAST_JOB_ID, last_id.to_be_bytes());
+
+	*last_id
+}
+
+/// Queues a job unless the same task is already queued or running. Returns
+/// the ID of the job doing the task.
+#[implement(Service)]
+pub async fn enqueue_unique(&self, task: Task) -> u64 {
+	let pending = self
+		.jobs()
+		.ready_find(|(_, job)| {
+			job.task == task && matches!(job.state, State::Queued | State::Running)
+		})
+		.await;
+
+	match pending {
+		| Some((id, _)) => id,
+		| None => self.enqueue(task),
+	}
+}
+
+/// Cancels a queued or running job.
+#[implement(Service)]
+pub async fn cancel(&self, id: u64) -> Result {
+	let mut job = self.get(id).await?;
+	if !matches!(job.state, State::Queued | State::Running) {
+		return Err!(Request(InvalidParam("Job {id} is not queued or running.")));
+	}
+
+	job.state = State::Cancelled;
+	job.finished_at = Some(now_secs());
+	self.jobid_job.put(id, Json(&job));
+
+	if let Some(handle) = self.running.lock().expect("locked").remove(&id) {
+		handle.abort();
+	}
+
+	Ok(())
+}
+
+/// Queues a failed or cancelled job again, with its attempts reset.
+#[implement(Service)]
+pub async fn retry(&self, id: u64) -> Result {
+	let mut job = self.get(id).await?;
+	if !matches!(job.state, State::Failed | State::Cancelled) {
+		return Err!(Request(InvalidParam("Job {id} has not failed or been cancelled.")));
+	}
+
+	job.state = State::Queued;
+	job.attempts = 0;
+	job.run_after = now_secs();
+	job.finished_at = None;
+	self.jobid_job.put(id, Json(&job));
+	self.queued.notify_one();
+
+	Ok(())
+}
+
+#[implement(Service)]
+pub async fn get(&self, id: u64) -> Result<Job> { self.jobid_job.qry(&id).await.deserialized() }
+
+/// All the jobs, oldest first.
+#[implement(Service)]
+pub fn jobs(&self) -> impl Stream<Item = (u64, Job)> + Send + '_ {
+	self.jobid_job.stream().ignore_err()
+}
+
+/// Starts due jobs while fewer than `job_concurrency` are running.
+#[implement(Service)]
+async fn start_due(&self, tasks: &mut JoinSet<(u64, Result<String>)>) {
+	let concurrency = self.services.config.job_concurrency;
+	let free = concurrency.saturating_sub(self.running.lock().expect("locked").len());
+	if free == 0 {
+		return;
+	}
+
+	let now = now_secs();
+	let due: Vec<_> = self
+		.jobs()
+		.ready_filter(|(_, job)| job.state == State::Queued && job.run_after <= now)
+		.take(free)
+		.collect()
+		.await;
+
+	for (id, mut job) in due {
+		job.state = State::Running;
+		job.attempts = job.attempts.saturating_add(1);
+		self.jobid_job.put(id, Json(&job));
+
+		debug_info!(id, task = %job.task, attempt = job.attempts, "Starting job");
+		let services = self.services.clone();
+		let handle = tasks.spawn(async move { (id, run::run(&services, &job.task).await) });
+
+		self.running
+			.lock()
+			.expect("locked")
+			.insert(id, handle);
+	}
+}
+
+/// Records the outcome of a job, queueing it again after a backoff when it
+/// failed and has attempts left.
+#[implement(Service)]
+async fn finished(&self, result: Result<(tokio::task::Id, (u64, Result<String>)), JoinError>) {
+	let (id, result) = match result {
+		| Ok((_, (id, result))) => (id, result),
+		| Err(e) if e.is_cancelled() => return,
+		| Err(e) => {
+			let task_id = e.id();
+			let running = self.running.lock().expect("locked");
+			let Some(id) = running
+				.iter()
+				.find(|(_, handle)| handle.id() == task_id)
+				.map(|(id, _)| *id)
+			else {
+				return;
+			};
+
+			(id, Err!("Job panicked: {e}"))
+		},
+	};
+
+	self.running.lock().expect("locked").remove(&id);
+
+	let Ok(mut job) = self.get(id).await else {
+		return;
+	};
+
+	// Cancelled while running.
+	if job.state != State::Running {
+		return;
+	}
+
+	let config = &self.services.config;
+	let now = now_secs();
+	match result {
+		| Ok(output) => {
+			debug_info!(id, task = %job.task, "Job succeeded: {output}");
+			job.state = State::Succeeded;
+			job.finished_at = Some(now);
+			job.result = Some(output);
+		},
+		| Err(e) if job.attempts < config.job_max_attempts => {
+			let backoff = config
+				.job_retry_backoff_min
+				.saturating_mul(job.attempts.into())
+				.saturating_mul(job.attempts.into())
+				.min(config.job_retry_backoff_max);
+
+			warn!(id, task = %job.task, attempt = job.attempts, "Job failed; retrying in {backoff}s: {e}");
+			job.state = State::Queued;
+			job.run_after = now.saturating_add(backoff);
+			job.result = Some(e.to_string());
+		},
+		| Err(e) => {
+			warn!(id, task = %job.task, attempts = job.attempts, "Job failed: {e}");
+			job.state = State::Failed;
+			job.finished_at = Some(now);
+			job.result = Some(e.to_string());
+		},
+	}
+
+	self.jobid_job.put(id, Json(&job));
+}
+
+/// Queues the jobs which were running when the server stopped.
+#[implement(Service)]
+async fn requeue_interrupted(&self) {
+	let interrupted: Vec<_> = self
+		.jobs()
+		.ready_filter(|(_, job)| job.state == State::Running)
+		.collect()
+		.await;
+
+	for (id, mut job) in interrupted {
+		debug_info!(id, task = %job.task, "Resuming interrupted job");
+		job.state = State::Queued;
+		self.jobid_job.put(id, Json(&job));
+	}
+}
+
+/// Forgets the jobs which finished longer than [`FINISHED_RETENTION`] ago.
+#[implement(Service)]
+async fn forget_finished(&self) {
+	let cutoff = now_secs().saturating_sub(FINISHED_RETENTION.as_secs());
+	self.jobs()
+		.ready_filter(|(_, job)| {
+			job.finished_at
+				.is_some_and(|finished| finished < cutoff)
+		})
+		.ready_for_each(|(id, _)| self.jobid_job.del(id))
+		.await;
+}
+
+impl fmt::Display for Task {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::RetentionPurge => write!(f, "retention purge"),
+			| Self::MediaRetention => write!(f, "media retention"),
+			| Self::PurgeRemoteMedia { max_age } =>
+				write!(f, "purge remote media older than {max_age}s"),
+			| Self::PurgeHistory { room_id, before, .. } =>
+				write!(f, "purge the history of {room_id} before {before}"),
+			| Self::PurgeServerEvents { room_id, server_name, .. } =>
+				write!(f, "purge the messages of {server_name} in {room_id}"),
+			| Self::PurgeUserEvents { room_id, user_id, .. } =>
+				write!(f, "purge the messages of {user_id} in {room_id}"),
+			| Self::MirrorSpaceMembers { space_id, room_id, user_id: None } =>
+				write!(f, "invite the members of {space_id} to {room_id}"),
+			| Self::MirrorSpaceMembers {
+				space_id,
+				room_id,
+				user_id: Some(user_id),
+			} => write!(f, "invite {user_id} of {space_id} to {room_id}"),
+		}
+	}
+}
+
+impl fmt::Display for State {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::Queued => write!(f, "queued"),
+			| Self::Running => write!(f, "running"),
+			| Self::Succeeded => write!(f, "succeeded"),
+			| Self::Failed => write!(f, "failed"),
+			| Self::Cancelled => write!(f, "cancelled"),
+		}
+	}
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use ruma::{MilliSecondsSinceUnixEpoch, UInt};
+use tuwunel_core::Result;
+
+use super::Task;
+
+/// Does the work of a task. Returns a summary of the outcome.
+pub(super) async fn run(services: &crate::services::OnceServices, task: &Task) -> Result<String> {
+	match task {
+		| Task::RetentionPurge => {
+			let count = services.retention.purge_all().await;
+
+			Ok(format!("Purged {count} messages past retention."))
+		},
+		| Task::MediaRetention => {
+			let count = services.media.enforce_retention().await?;
+
+			Ok(format!("Purged {count} expired remote media."))
+		},
+		| Task::PurgeRemoteMedia { max_age } => {
+			let count = services
+				.media
+				.purge_remote_media(Duration::from_secs(*max_age))
+				.await?;
+
+			Ok(format!("Purged {count} remote media."))
+		},
+		| Task::PurgeHistory { room_id, before, keep_state } => {
+			let before = MilliSecondsSinceUnixEpoch(UInt::try_from(*before)?);
+			let (purged, outliers) = services
+				.timeline
+				.purge_history(room_id, before, *keep_state)
+				.await?;
+
+			Ok(format!(
+				"Purged {purged} events; took {outliers} state events out of the timeline."
+			))
+		},
+		| Task::PurgeServerEvents { room_id, event_ids, .. }
+		| Task::PurgeUserEvents { room_id, event_ids, .. } => {
+			let count = services
+				.timeline
+				.purge_pdus(room_id, event_ids)
+				.await?;
+
+			Ok(format!("Purged {count} messages."))
+		},
+		| Task::MirrorSpaceMembers { space_id, room_id, user_id } => {
+			let (invited, failed) = services
+				.spaces
+				.mirror_members(space_id, room_id, user_id.as_deref())
+				.await?;
+
+			Ok(format!("Invited {invited} members of the space; {failed} invites failed."))
+		},
+	}
+}
@@ -0,0 +1,104 @@
+//! Sends emails through the SMTP server of `[global.smtp]`.
+
+use std::sync::Arc;
+
+#[cfg(feature = "email")]
+use lettre::{
+	AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::header::ContentType,
+};
+use tuwunel_core::{Err, Result, implement};
+#[cfg(feature = "email")]
+use tuwunel_core::{debug, err};
+
+pub struct Service {
+	#[cfg(feature = "email")]
+	transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+	#[cfg_attr(not(feature = "email"), expect(dead_code))]
+	services: Arc<crate::services::OnceServices>,
+}
+
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		#[cfg(feature = "email")]
+		let transport = args
+			.server
+			.config
+			.smtp
+			.url
+			.as_ref()
+			.map(|url| AsyncSmtpTransport::<Tokio1Executor>::from_url(url.as_str()))
+			.transpose()
+			.map_err(|e| err!(Config("url", "Invalid SMTP server URL: {e}")))?
+			.map(|builder| builder.build());
+
+		Ok(Arc::new(Self {
+			#[cfg(feature = "email")]
+			transport,
+			services: args.services.clone(),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether emails can be sent: an SMTP server is configured, and the server was
+/// built with the `email` feature.
+#[implement(Service)]
+#[must_use]
+pub fn enabled(&self) -> bool {
+	#[cfg(feature = "email")]
+	let enabled = self.transport.is_some();
+
+	#[cfg(not(feature = "email"))]
+	let enabled = false;
+
+	enabled
+}
+
+/// Sends an email of plain text.
+#[cfg(feature = "email")]
+#[implement(Service)]
+pub async fn send(&self, to: &str, subject: &str, body: String) -> Result {
+	let Some(transport) = self.transport.as_ref() else {
+		return Err!(Request(ThreepidDenied("This server does not send emails.")));
+	};
+
+	let from = self
+		.services
+		.server
+		.config
+		.smtp
+		.from
+		.clone()
+		.unwrap_or_else(|| format!("noreply@{}", self.services.globals.server_name()));
+
+	let message = Message::builder()
+		.from(
+			from.parse()
+				.map_err(|e| err!(Config("from", "Invalid sender of emails: {e}")))?,
+		)
+		.to(to
+			.parse()
+			.map_err(|e| err!(Request(InvalidParam("Invalid email address: {e}"))))?)
+		.subject(subject)
+		.header(ContentType::TEXT_PLAIN)
+		.body(body)
+		.map_err(|e| err!(error!("Failed to build email to {to}: {e}")))?;
+
+	transport
+		.send(message)
+		.await
+		.map_err(|e| err!(error!("Failed to send email to {to}: {e}")))?;
+
+	debug!(%to, "Sent email");
+	Ok(())
+}
+
+#[cfg(not(feature = "email"))]
+#[implement(Service)]
+#[expect(clippy::unused_async)]
+pub async fn send(&self, _to: &str, _subject: &str, _body: String) -> Result {
+	Err!(Request(ThreepidDenied(
+		"This server was built without support for sending emails."
+	)))
+}
@@ -1,35 +1,66 @@
-use std::{panic::AssertUnwindSafe, sync::Arc, time::Duration};
+use std::{
+	collections::HashMap,
+	panic::AssertUnwindSafe,
+	sync::{Arc, Mutex as StdMutex},
+	time::{Duration, Instant},
+};
 
 use futures::{FutureExt, TryFutureExt};
 use tokio::{
 	sync::{Mutex, MutexGuard},
-	task::{JoinHandle, JoinSet},
-	time::sleep,
+	task::{AbortHandle, Id, JoinError, JoinHandle, JoinSet},
+	time::{interval, sleep},
 };
 use tuwunel_core::{
-	Err, Error, Result, Server, debug, debug_warn, error, trace, utils::time, warn,
+	Err, Error, Result, Server, debug, debug_warn, err, error, info, trace, utils::time, warn,
 };
+use tuwunel_database::ReadClass;
 
 use crate::{Services, service::Service};
 
 pub(crate) struct Manager {
 	manager: Mutex<Option<JoinHandle<Result>>>,
 	workers: Mutex<Workers>,
+	supervised: StdMutex<HashMap<Id, Supervised>>,
 	server: Arc<Server>,
 	services: Arc<Services>,
 }
 
+/// A running worker, by the task running it.
+struct Supervised {
+	service: Arc<dyn Service>,
+	abort: AbortHandle,
+
+	/// When the worker started, or is due to after a restart delay.
+	started: Instant,
+
+	/// Restarts in a row, without the worker running for
+	/// [`RESTART_RESET`] in between.
+	restarts: u32,
+}
+
 type Workers = JoinSet<WorkerResult>;
 type WorkerResult = (Arc<dyn Service>, Result);
 type WorkersLocked<'a> = MutexGuard<'a, Workers>;
 
 const RESTART_DELAY_MS: u64 = 2500;
 
+/// Longest delay before restarting a worker.
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// A worker running this long is considered recovered; its next restart is
+/// not delayed further.
+const RESTART_RESET: Duration = Duration::from_secs(10 * 60);
+
+/// Interval at which the heartbeats of the workers are checked.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(15);
+
 impl Manager {
 	pub(super) fn new(services: &Arc<Services>) -> Arc<Self> {
 		Arc::new(Self {
 			manager: Mutex::new(None),
 			workers: Mutex::new(JoinSet::new()),
+			supervised: StdMutex::default(),
 			server: services.server.clone(),
 			services: services.clone(),
 		})
@@ -56,13 +87,64 @@ pub(super) async fn start(self: Arc<Self>) -> Result {
 		);
 
 		debug!("Starting service workers...");
-		for service in self.services.services() {
-			self.start_worker(&mut workers, &service)?;
+		for service in self.startup_services()? {
+			self.start_worker(&mut workers, &service, Duration::ZERO, 0)?;
 		}
 
 		Ok(())
 	}
 
+	/// Services whose workers are started: all of them, or those named by
+	/// `startup_services` and their dependencies. Dependencies are ordered
+	/// before the services depending on them.
+	fn startup_services(&self) -> Result<Vec<Arc<dyn Service>>> {
+		let services: Vec<_> = self.services.services().collect();
+		let index: HashMap<&str, usize> = services
+			.iter()
+			.enumerate()
+			.map(|(i, service)| (service.name(), i))
+			.collect();
+
+		let requested = &self.server.config.startup_services;
+		let roots: Vec<usize> = if requested.is_empty() {
+			(0..services.len()).collect()
+		} else {
+			requested
+				.iter()
+				.map(|name| {
+					index.get(name.as_str()).copied().ok_or_else(|| {
+						err!(Config("startup_services", "Unknown service {name:?}."))
+					})
+				})
+				.collect::<Result<_>>()?
+		};
+
+		let mut marks = vec![Mark::Unvisited; services.len()];
+		let mut order = Vec::with_capacity(services.len());
+		for root in roots {
+			visit(&services, &index, &mut marks, &mut order, root)?;
+		}
+
+		if !requested.is_empty() {
+			let names: Vec<_> = order
+				.iter()
+				.map(|&i| services[i].name())
+				.collect();
+
+			info!(
+				"Starting {} of {} services: {}",
+				order.len(),
+				services.len(),
+				names.join(", ")
+			);
+		}
+
+		Ok(order
+			.into_iter()
+			.map(|i| services[i].clone())
+			.collect())
+	}
+
 	pub(super) async fn stop(&self) {
 		if let Some(manager) = self.manager.lock().await.take() {
 			debug!("Waiting for service manager...");
@@ -73,14 +155,16 @@ pub(super) async fn stop(&self) {
 	}
 
 	async fn worker(&self) -> Result {
+		let mut watchdog = interval(WATCHDOG_INTERVAL);
 		loop {
 			let mut workers = self.workers.lock().await;
 			tokio::select! {
-				result = workers.join_next() => match result {
-					Some(Ok(result)) => self.handle_result(&mut workers, result).await?,
-					Some(Err(error)) => self.handle_abort(&mut workers, &Error::from(error))?,
+				result = workers.join_next_with_id() => match result {
+					Some(Ok((id, result))) => self.handle_result(&mut workers, id, result).await?,
+					Some(Err(error)) => self.handle_abort(&mut workers, error).await?,
 					None => break,
-				}
+				},
+				_ = watchdog.tick() => self.check_heartbeats(),
 			}
 		}
 
@@ -88,21 +172,80 @@ async fn worker(&self) -> Result {
 		Ok(())
 	}
 
-	#[allow(clippy::unused_self)]
-	fn handle_abort(&self, _workers: &mut WorkersLocked<'_>, error: &Error) -> Result {
-		// not supported until service can be associated with abort
-		unimplemented!("unexpected worker task abort {error:?}");
+	/// Aborts the workers whose heartbeats stopped for longer than
+	/// `worker_watchdog_timeout`; they are restarted as their tasks end.
+	fn check_heartbeats(&self) {
+		let timeout = Duration::from_secs(self.server.config.worker_watchdog_timeout);
+		if timeout.is_zero() || !self.server.running() {
+			return;
+		}
+
+		let now = Instant::now();
+		let supervised = self.supervised.lock().expect("locked");
+		for worker in supervised.values() {
+			if now.saturating_duration_since(worker.started) < timeout {
+				continue;
+			}
+
+			let stuck = worker
+				.service
+				.heartbeats()
+				.iter()
+				.any(|heartbeat| {
+					heartbeat
+						.elapsed()
+						.is_none_or(|elapsed| elapsed > timeout)
+				});
+
+			if stuck && !worker.abort.is_finished() {
+				error!(
+					"service {:?} worker stopped heartbeating for over {}; aborting",
+					worker.service.name(),
+					time::pretty(timeout)
+				);
+				worker.abort.abort();
+			}
+		}
+	}
+
+	async fn handle_abort(&self, workers: &mut WorkersLocked<'_>, error: JoinError) -> Result {
+		let Some(worker) = self
+			.supervised
+			.lock()
+			.expect("locked")
+			.remove(&error.id())
+		else {
+			return Err!("Unexpected abort of an unknown worker task: {error:?}");
+		};
+
+		if !error.is_cancelled() {
+			return Err(error.into());
+		}
+
+		if !self.server.running() {
+			return Ok(());
+		}
+
+		self.restart_worker(workers, &worker, "stopped heartbeating")
+			.await
 	}
 
 	async fn handle_result(
 		&self,
 		workers: &mut WorkersLocked<'_>,
+		id: Id,
 		result: WorkerResult,
 	) -> Result {
+		let worker = self
+			.supervised
+			.lock()
+			.expect("locked")
+			.remove(&id);
 		let (service, result) = result;
-		match result {
-			| Ok(()) => self.handle_finished(workers, &service),
-			| Err(error) => self.handle_error(workers, &service, error).await,
+		match (result, worker) {
+			| (Ok(()), _) => self.handle_finished(workers, &service),
+			| (Err(error), Some(worker)) => self.handle_error(workers, &worker, error).await,
+			| (Err(error), None) => Err(error),
 		}
 	}
 
@@ -119,10 +262,10 @@ fn handle_finished(
 	async fn handle_error(
 		&self,
 		workers: &mut WorkersLocked<'_>,
-		service: &Arc<dyn Service>,
+		worker: &Supervised,
 		error: Error,
 	) -> Result {
-		let name = service.name();
+		let name = worker.service.name();
 		error!("service {name:?} aborted: {error}");
 
 		if !self.server.running() {
@@ -134,18 +277,50 @@ async fn handle_error(
 			return Err(error);
 		}
 
-		let delay = Duration::from_millis(RESTART_DELAY_MS);
+		self.restart_worker(workers, worker, "panicked")
+			.await
+	}
+
+	/// Restart a worker which panicked or got stuck, after a delay doubling
+	/// with each restart in a row, and tell the admin room.
+	async fn restart_worker(
+		&self,
+		workers: &mut WorkersLocked<'_>,
+		worker: &Supervised,
+		reason: &str,
+	) -> Result {
+		let restarts = if worker.started.elapsed() > RESTART_RESET {
+			0
+		} else {
+			worker.restarts.saturating_add(1)
+		};
+
+		let delay = Duration::from_millis(RESTART_DELAY_MS)
+			.saturating_mul(2_u32.saturating_pow(restarts.saturating_sub(1)))
+			.min(RESTART_DELAY_MAX);
+
+		let name = worker.service.name();
 		warn!("service {name:?} worker restarting after {} delay", time::pretty(delay));
-		sleep(delay).await;
 
-		self.start_worker(workers, service)
+		let notice = format!(
+			"Service `{name}` worker {reason}; restarting it in {}.",
+			time::pretty(delay)
+		);
+		let services = self.services.clone();
+		self.server.runtime().spawn(async move {
+			services.admin.notice(&notice).await;
+		});
+
+		self.start_worker(workers, &worker.service, delay, restarts)
 	}
 
-	/// Start the worker in a task for the service.
+	/// Start the worker in a task for the service, after `delay`.
 	fn start_worker(
 		&self,
 		workers: &mut WorkersLocked<'_>,
 		service: &Arc<dyn Service>,
+		delay: Duration,
+		restarts: u32,
 	) -> Result {
 		if !self.server.running() {
 			return Err!(
@@ -155,12 +330,80 @@ fn start_worker(
 		}
 
 		debug!("Service {:?} worker starting...", service.name());
-		workers.spawn_on(worker(service.clone()), self.server.runtime());
+		let abort = workers.spawn_on(
+			delayed_worker(self.server.clone(), service.clone(), delay),
+			self.server.runtime(),
+		);
+
+		self.supervised
+			.lock()
+			.expect("locked")
+			.insert(abort.id(), Supervised {
+				service: service.clone(),
+				abort,
+				started: Instant::now()
+					.checked_add(delay)
+					.unwrap_or_else(Instant::now),
+				restarts,
+			});
 
 		Ok(())
 	}
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mark {
+	Unvisited,
+	Visiting,
+	Visited,
+}
+
+/// Depth-first traversal of the dependencies of `services[i]`, appending each
+/// service to `order` after those it depends on.
+fn visit(
+	services: &[Arc<dyn Service>],
+	index: &HashMap<&str, usize>,
+	marks: &mut [Mark],
+	order: &mut Vec<usize>,
+	i: usize,
+) -> Result {
+	let name = services[i].name();
+	match marks[i] {
+		| Mark::Visited => return Ok(()),
+		| Mark::Visiting => return Err!("Service {name:?} depends on itself."),
+		| Mark::Unvisited => marks[i] = Mark::Visiting,
+	}
+
+	for dependency in services[i].dependencies() {
+		let Some(&j) = index.get(dependency) else {
+			return Err!("Service {name:?} depends on unknown service {dependency:?}.");
+		};
+
+		visit(services, index, marks, order, j)?;
+	}
+
+	marks[i] = Mark::Visited;
+	order.push(i);
+
+	Ok(())
+}
+
+/// Runs the worker after `delay`, unless the server shuts down first.
+async fn delayed_worker(
+	server: Arc<Server>,
+	service: Arc<dyn Service>,
+	delay: Duration,
+) -> WorkerResult {
+	if !delay.is_zero() {
+		tokio::select! {
+			() = sleep(delay) => {},
+			() = server.until_shutdown() => return (service, Ok(())),
+		}
+	}
+
+	worker(service).await
+}
+
 /// Base frame for service worker. This runs in a tokio::task. All errors and
 /// panics from the worker are caught and returned cleanly. The JoinHandle
 /// should never error with a panic, and if so it should propagate, but it may
@@ -174,8 +417,14 @@ fn start_worker(
 )]
 async fn worker(service: Arc<dyn Service>) -> WorkerResult {
 	let service_ = Arc::clone(&service);
-	let result = AssertUnwindSafe(service_.worker())
-		.catch_unwind()
+	let class = if service.background() {
+		ReadClass::Background
+	} else {
+		ReadClass::Interactive
+	};
+
+	let result = class
+		.scope(AssertUnwindSafe(service_.worker()).catch_unwind())
 		.map_err(Error::from_panic);
 
 	let result = if service.unconstrained() {
@@ -1,19 +1,29 @@
 use std::{sync::Arc, time::Duration};
 
-use futures::{StreamExt, pin_mut};
-use ruma::{Mxc, OwnedMxcUri, UserId, http_headers::ContentDisposition};
+use futures::{Stream, StreamExt, pin_mut};
+use ruma::{
+	Mxc, OwnedMxcUri, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
+	http_headers::ContentDisposition,
+};
 use tuwunel_core::{
 	Err, Result, debug, debug_info, err,
 	utils::{ReadyExt, str_from_bytes, stream::TryIgnore, string_from_bytes},
 };
-use tuwunel_database::{Database, Interfix, Map, serialize_key};
+use tuwunel_database::{Database, Deserialized, Interfix, Json, Map, serialize_key};
 
-use super::{preview::UrlPreviewData, thumbnail::Dim};
+use super::{preview::UrlPreviewData, quarantine::Flags, thumbnail::Dim, usage::Owner};
 
 pub(crate) struct Data {
 	mediaid_file: Arc<Map>,
+	mediaid_flags: Arc<Map>,
+	mediaid_quarantine: Arc<Map>,
+	mediaid_reporter: Arc<Map>,
+	mediaid_roomid: Arc<Map>,
 	mediaid_user: Arc<Map>,
+	roomid_mediausage: Arc<Map>,
+	servername_mediausage: Arc<Map>,
 	url_previews: Arc<Map>,
+	userid_mediausage: Arc<Map>,
 }
 
 #[derive(Debug)]
@@ -27,8 +37,15 @@ impl Data {
 	pub(super) fn new(db: &Arc<Database>) -> Self {
 		Self {
 			mediaid_file: db["mediaid_file"].clone(),
+			mediaid_flags: db["mediaid_flags"].clone(),
+			mediaid_quarantine: db["mediaid_quarantine"].clone(),
+			mediaid_reporter: db["mediaid_reporter"].clone(),
+			mediaid_roomid: db["mediaid_roomid"].clone(),
 			mediaid_user: db["mediaid_user"].clone(),
+			roomid_mediausage: db["roomid_mediausage"].clone(),
+			servername_mediausage: db["servername_mediausage"].clone(),
 			url_previews: db["url_previews"].clone(),
+			userid_mediausage: db["userid_mediausage"].clone(),
 		}
 	}
 
@@ -156,6 +173,188 @@ pub(super) async fn get_all_user_mxcs(&self, user_id: &UserId) -> Vec<OwnedMxcUr
 			.await
 	}
 
+	/// Gets the user associated with an MXC; the uploader for local media.
+	pub(super) async fn get_media_user(&self, mxc: &Mxc<'_>) -> Option<OwnedUserId> {
+		let prefix = (mxc, Interfix);
+		let users = self
+			.mediaid_user
+			.stream_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_filter_map(|(_, val)| str_from_bytes(val).ok())
+			.ready_filter_map(|user| UserId::parse(user).ok());
+
+		pin_mut!(users);
+		users.next().await
+	}
+
+	/// Gets the number of bytes of media counted against an owner
+	pub(super) async fn get_usage(&self, owner: &Owner) -> u64 {
+		match owner {
+			| Owner::User(user_id) => self.userid_mediausage.get(user_id).await,
+			| Owner::Server(server_name) => self.servername_mediausage.get(server_name).await,
+			| Owner::Room(room_id) => self.roomid_mediausage.get(room_id).await,
+		}
+		.deserialized()
+		.unwrap_or(0)
+	}
+
+	pub(super) fn set_usage(&self, owner: &Owner, bytes: u64) {
+		let (map, key) = match owner {
+			| Owner::User(user_id) => (&self.userid_mediausage, user_id.as_bytes()),
+			| Owner::Server(server_name) => (&self.servername_mediausage, server_name.as_bytes()),
+			| Owner::Room(room_id) => (&self.roomid_mediausage, room_id.as_bytes()),
+		};
+
+		if bytes == 0 {
+			map.remove(key);
+		} else {
+			map.raw_put(key, bytes);
+		}
+	}
+
+	pub(super) fn users_usage(&self) -> impl Stream<Item = (OwnedUserId, u64)> + Send + '_ {
+		self.userid_mediausage
+			.stream()
+			.ignore_err()
+			.map(|(user_id, bytes): (&UserId, u64)| (user_id.to_owned(), bytes))
+	}
+
+	pub(super) fn servers_usage(&self) -> impl Stream<Item = (OwnedServerName, u64)> + Send + '_ {
+		self.servername_mediausage
+			.stream()
+			.ignore_err()
+			.map(|(server_name, bytes): (&ServerName, u64)| (server_name.to_owned(), bytes))
+	}
+
+	pub(super) fn rooms_usage(&self) -> impl Stream<Item = (OwnedRoomId, u64)> + Send + '_ {
+		self.roomid_mediausage
+			.stream()
+			.ignore_err()
+			.map(|(room_id, bytes): (&RoomId, u64)| (room_id.to_owned(), bytes))
+	}
+
+	/// Whether an MXC is counted against the room.
+	pub(super) async fn room_has_media(&self, mxc: &Mxc<'_>, room_id: &RoomId) -> bool {
+		self.mediaid_roomid
+			.qry(&(mxc, room_id))
+			.await
+			.is_ok()
+	}
+
+	/// Records that an MXC is counted against the room.
+	pub(super) fn add_room_media(&self, mxc: &Mxc<'_>, room_id: &RoomId) {
+		self.mediaid_roomid.put_raw((mxc, room_id), []);
+	}
+
+	/// Removes the rooms an MXC is counted against, returning them.
+	pub(super) async fn remove_media_rooms(&self, mxc: &Mxc<'_>) -> Vec<OwnedRoomId> {
+		let rooms: Vec<OwnedRoomId> = self
+			.mediaid_roomid
+			.keys_prefix(&(mxc, Interfix))
+			.ignore_err()
+			.map(|(_, room_id): (&str, &RoomId)| room_id.to_owned())
+			.collect()
+			.await;
+
+		for room_id in &rooms {
+			self.mediaid_roomid.del((mxc, room_id));
+		}
+
+		rooms
+	}
+
+	/// All MXCs counted against rooms, with the room.
+	pub(super) fn media_rooms(
+		&self,
+	) -> impl Stream<Item = (OwnedMxcUri, OwnedRoomId)> + Send + '_ {
+		self.mediaid_roomid
+			.keys()
+			.ignore_err()
+			.map(|(mxc, room_id): (&str, &RoomId)| (mxc.into(), room_id.to_owned()))
+	}
+
+	/// Marks an MXC quarantined, recording the user who quarantined it.
+	pub(super) fn quarantine(&self, mxc: &Mxc<'_>, by: &UserId) {
+		self.mediaid_quarantine
+			.raw_put(mxc.to_string(), by.as_str());
+	}
+
+	pub(super) fn unquarantine(&self, mxc: &Mxc<'_>) {
+		self.mediaid_quarantine.remove(&mxc.to_string());
+	}
+
+	pub(super) async fn is_quarantined(&self, mxc: &Mxc<'_>) -> bool {
+		self.mediaid_quarantine
+			.exists(&mxc.to_string())
+			.await
+			.is_ok()
+	}
+
+	pub(super) fn quarantined(
+		&self,
+	) -> impl Stream<Item = (OwnedMxcUri, OwnedUserId)> + Send + '_ {
+		self.mediaid_quarantine
+			.stream()
+			.ignore_err()
+			.map(|(mxc, by): (&str, &UserId)| (mxc.into(), by.to_owned()))
+	}
+
+	/// Records that the user reported an event referencing the MXC. Returns
+	/// the number of users who did.
+	pub(super) async fn add_reporter(&self, mxc: &Mxc<'_>, reporter: &UserId) -> u64 {
+		self.mediaid_reporter.put_raw((mxc, reporter), []);
+		self.mediaid_reporter
+			.keys_prefix_raw(&(mxc, Interfix))
+			.ignore_err()
+			.count()
+			.await
+			.try_into()
+			.unwrap_or(u64::MAX)
+	}
+
+	pub(super) async fn remove_reporters(&self, mxc: &Mxc<'_>) {
+		self.mediaid_reporter
+			.keys_prefix_raw(&(mxc, Interfix))
+			.ignore_err()
+			.ready_for_each(|key| self.mediaid_reporter.remove(key))
+			.await;
+	}
+
+	pub(super) async fn get_flags(&self, mxc: &str) -> Flags {
+		self.mediaid_flags
+			.get(mxc)
+			.await
+			.deserialized()
+			.unwrap_or_default()
+	}
+
+	pub(super) fn set_flags(&self, mxc: &str, flags: &Flags) {
+		if *flags == Flags::default() {
+			self.mediaid_flags.remove(mxc);
+		} else {
+			self.mediaid_flags.raw_put(mxc, Json(flags));
+		}
+	}
+
+	pub(super) fn flagged(&self) -> impl Stream<Item = (OwnedMxcUri, Flags)> + Send + '_ {
+		self.mediaid_flags
+			.stream()
+			.ignore_err()
+			.map(|(mxc, flags): (&str, Flags)| (mxc.into(), flags))
+	}
+
+	pub(super) async fn usage_is_empty(&self) -> bool {
+		self.userid_mediausage.count().await == 0
+			&& self.servername_mediausage.count().await == 0
+			&& self.roomid_mediausage.count().await == 0
+	}
+
+	pub(super) async fn clear_usage(&self) {
+		self.userid_mediausage.clear().await;
+		self.servername_mediausage.clear().await;
+		self.roomid_mediausage.clear().await;
+	}
+
 	/// Gets all the media keys in our database (this includes all the metadata
 	/// associated with it such as width, height, content-type, etc)
 	pub(crate) async fn get_all_media_keys(&self) -> Vec<Vec<u8>> {
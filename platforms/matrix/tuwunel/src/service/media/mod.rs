@@ -2,10 +2,18 @@
 mod data;
 pub(super) mod migrations;
 mod preview;
+mod quarantine;
 mod remote;
+mod stream;
 mod tests;
 mod thumbnail;
-use std::{path::PathBuf, sync::Arc, time::SystemTime};
+mod usage;
+
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
 
 use async_trait::async_trait;
 use base64::{Engine as _, engine::general_purpose};
@@ -21,7 +29,13 @@
 };
 
 use self::data::{Data, Metadata};
-pub use self::thumbnail::Dim;
+pub use self::{
+	quarantine::{Flagged, Flags},
+	stream::FileRange,
+	thumbnail::Dim,
+	usage::{Owner, referenced_media},
+};
+use crate::jobs::Task;
 
 #[derive(Debug)]
 pub struct FileMeta {
@@ -32,6 +46,7 @@ pub struct FileMeta {
 
 pub struct Service {
 	url_preview_mutex: MutexMap<String, ()>,
+	usage_mutex: MutexMap<Owner, ()>,
 	pub(super) db: Data,
 	services: Arc<crate::services::OnceServices>,
 }
@@ -50,6 +65,7 @@ impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			url_preview_mutex: MutexMap::new(),
+			usage_mutex: MutexMap::new(),
 			db: Data::new(args.db),
 			services: args.services.clone(),
 		}))
@@ -58,9 +74,35 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 	async fn worker(self: Arc<Self>) -> Result {
 		self.create_media_dir().await?;
 
-		Ok(())
+		if self.db.usage_is_empty().await
+			&& let Err(e) = self.recount_usage().await
+		{
+			warn!("Failed to count media usage: {e}");
+		}
+
+		loop {
+			if self
+				.services
+				.server
+				.config
+				.media_retention_remote_seconds
+				!= 0
+			{
+				self.services
+					.jobs
+					.enqueue_unique(Task::MediaRetention)
+					.await;
+			}
+
+			tokio::select! {
+				() = tokio::time::sleep(Duration::from_secs(60 * 60)) => {},
+				() = self.services.server.until_shutdown() => return Ok(())
+			};
+		}
 	}
 
+	fn background(&self) -> bool { true }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -86,6 +128,7 @@ pub async fn create(
 		//TODO: Dangling metadata in database if creation fails
 		let mut f = self.create_media_file(&key).await?;
 		f.write_all(file).await?;
+		self.add_usage(mxc, user, file.len()).await;
 
 		Ok(())
 	}
@@ -94,10 +137,17 @@ pub async fn create(
 	pub async fn delete(&self, mxc: &Mxc<'_>) -> Result {
 		match self.db.search_mxc_metadata_prefix(mxc).await {
 			| Ok(keys) => {
+				let owner = self.media_owner(mxc, None).await;
+				let mut freed: u64 = 0;
+
 				for key in keys {
 					trace!(?mxc, "MXC Key: {key:?}");
 					debug_info!(?mxc, "Deleting from filesystem");
 
+					if let Ok(metadata) = fs::metadata(self.get_media_file(&key)).await {
+						freed = freed.saturating_add(metadata.len());
+					}
+
 					if let Err(e) = self.remove_media_file(&key).await {
 						debug_error!(?mxc, "Failed to remove media file: {e}");
 					}
@@ -106,6 +156,12 @@ pub async fn delete(&self, mxc: &Mxc<'_>) -> Result {
 					self.db.delete_file_mxc(mxc).await;
 				}
 
+				if let Some(owner) = owner.as_ref() {
+					self.sub_usage(owner, freed).await;
+				}
+
+				self.remove_room_media(mxc, freed).await;
+
 				Ok(())
 			},
 			| _ => {
@@ -146,6 +202,8 @@ pub async fn delete_from_user(&self, user: &UserId) -> Result<usize> {
 
 	/// Downloads a file.
 	pub async fn get(&self, mxc: &Mxc<'_>) -> Result<Option<FileMeta>> {
+		self.check_quarantined(mxc).await?;
+
 		match self
 			.db
 			.search_file_metadata(mxc, &Dim::default())
@@ -351,19 +409,27 @@ async fn create_media_file(&self, key: &[u8]) -> Result<fs::File> {
 		debug!(?key, ?path, "Creating media file");
 
 		let file = fs::File::create(&path).await?;
-		if self.services.server.config.media_compat_file_link {
-			let legacy = self.get_media_file_b64(key);
-			if let Err(e) = fs::symlink(&path, &legacy).await {
-				debug_error!(
-					key = ?encode_key(key), ?path, ?legacy,
-					"Failed to create legacy media symlink: {e}"
-				);
-			}
-		}
+		self.link_legacy_media_file(key, &path).await;
 
 		Ok(file)
 	}
 
+	/// Links the file under its legacy name when `media_compat_file_link` is
+	/// enabled.
+	async fn link_legacy_media_file(&self, key: &[u8], path: &Path) {
+		if !self.services.server.config.media_compat_file_link {
+			return;
+		}
+
+		let legacy = self.get_media_file_b64(key);
+		if let Err(e) = fs::symlink(path, &legacy).await {
+			debug_error!(
+				key = ?encode_key(key), ?path, ?legacy,
+				"Failed to create legacy media symlink: {e}"
+			);
+		}
+	}
+
 	#[inline]
 	pub async fn get_metadata(&self, mxc: &Mxc<'_>) -> Option<FileMeta> {
 		self.db
@@ -0,0 +1,170 @@
+//! Quarantined media is kept on disk but is neither served to clients nor
+//! fetched again from its origin, until it is unquarantined. Media may also be
+//! flagged for review by the admins: as NSFW, or as referenced by reported
+//! events.
+
+use std::collections::BTreeMap;
+
+use ruma::{EventId, Mxc, OwnedMxcUri, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{Err, Result, debug_error, implement, info, utils::ReadyExt};
+
+use super::referenced_media;
+
+/// Review flags of media, besides quarantine.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Flags {
+	/// Not served to clients unless `media_serve_nsfw` is enabled.
+	#[serde(default)]
+	pub nsfw: bool,
+
+	/// Number of users who reported events referencing the media.
+	#[serde(default)]
+	pub reports: u64,
+}
+
+/// Flags of media flagged or quarantined; see [`Service::flagged`].
+#[derive(Debug, Default)]
+pub struct Flagged {
+	pub flags: Flags,
+
+	/// The admin who quarantined the media, if quarantined.
+	pub quarantined_by: Option<OwnedUserId>,
+}
+
+/// Quarantines the media, on behalf of the given admin.
+#[implement(super::Service)]
+pub fn quarantine(&self, mxc: &Mxc<'_>, by: &UserId) {
+	info!(%mxc, %by, "Quarantining media");
+	self.db.quarantine(mxc, by);
+}
+
+#[implement(super::Service)]
+pub fn unquarantine(&self, mxc: &Mxc<'_>) {
+	info!(%mxc, "Unquarantining media");
+	self.db.unquarantine(mxc);
+}
+
+/// Unquarantines the media and clears its flags.
+#[implement(super::Service)]
+pub async fn release(&self, mxc: &Mxc<'_>) {
+	self.unquarantine(mxc);
+	self.db.remove_reporters(mxc).await;
+	self.db
+		.set_flags(&mxc.to_string(), &Flags::default());
+}
+
+#[implement(super::Service)]
+pub(super) async fn check_quarantined(&self, mxc: &Mxc<'_>) -> Result {
+	if self.is_quarantined(mxc).await {
+		return Err!(Request(NotFound("Media is quarantined.")));
+	}
+
+	if !self.services.server.config.media_serve_nsfw && self.flags(mxc).await.nsfw {
+		return Err!(Request(NotFound("Media is not available on this server.")));
+	}
+
+	Ok(())
+}
+
+#[implement(super::Service)]
+pub async fn flags(&self, mxc: &Mxc<'_>) -> Flags { self.db.get_flags(&mxc.to_string()).await }
+
+#[implement(super::Service)]
+pub async fn set_nsfw(&self, mxc: &Mxc<'_>, nsfw: bool) {
+	let key = mxc.to_string();
+	let flags = Flags { nsfw, ..self.db.get_flags(&key).await };
+	info!(%mxc, nsfw, "Flagging media");
+	self.db.set_flags(&key, &flags);
+}
+
+/// Flags the media referenced by an event the user reported, quarantining each
+/// once `media_report_quarantine_threshold` users have reported it. Returns the
+/// media flagged.
+#[implement(super::Service)]
+pub async fn flag_reported_event(
+	&self,
+	event_id: &EventId,
+	reporter: &UserId,
+) -> Vec<OwnedMxcUri> {
+	let Ok(event) = self
+		.services
+		.timeline
+		.get_pdu_json(event_id)
+		.await
+	else {
+		return Vec::new();
+	};
+
+	let content = event
+		.get("content")
+		.and_then(|content| serde_json::to_value(content).ok())
+		.unwrap_or_default();
+
+	let threshold = self
+		.services
+		.server
+		.config
+		.media_report_quarantine_threshold;
+
+	let mxcs = referenced_media(&content);
+	for mxc in &mxcs {
+		let Ok(parsed) = mxc.as_str().try_into() else {
+			continue;
+		};
+
+		let key = mxc.to_string();
+		let reports = self.db.add_reporter(&parsed, reporter).await;
+		let flags = Flags { reports, ..self.db.get_flags(&key).await };
+		self.db.set_flags(&key, &flags);
+		info!(%mxc, %event_id, reports = flags.reports, "Flagging reported media");
+
+		if threshold > 0 && flags.reports >= threshold && !self.is_quarantined(&parsed).await {
+			self.quarantine(&parsed, &self.services.globals.server_user);
+		}
+	}
+
+	mxcs
+}
+
+/// All the media flagged or quarantined.
+#[implement(super::Service)]
+pub async fn flagged(&self) -> BTreeMap<OwnedMxcUri, Flagged> {
+	let mut flagged = BTreeMap::<OwnedMxcUri, Flagged>::new();
+	self.db
+		.flagged()
+		.ready_for_each(|(mxc, flags)| {
+			flagged.entry(mxc).or_default().flags = flags;
+		})
+		.await;
+
+	self.db
+		.quarantined()
+		.ready_for_each(|(mxc, by)| {
+			flagged.entry(mxc).or_default().quarantined_by = Some(by);
+		})
+		.await;
+
+	flagged
+}
+
+#[implement(super::Service)]
+pub async fn is_quarantined(&self, mxc: &Mxc<'_>) -> bool { self.db.is_quarantined(mxc).await }
+
+/// Quarantines all the media uploaded by the user, returning how many.
+#[implement(super::Service)]
+pub async fn quarantine_from_user(&self, user: &UserId, by: &UserId) -> usize {
+	let mut count: usize = 0;
+	for mxc in self.db.get_all_user_mxcs(user).await {
+		let Ok(mxc) = mxc.as_str().try_into().inspect_err(|e| {
+			debug_error!(?mxc, "Failed to parse MXC URI from database: {e}");
+		}) else {
+			continue;
+		};
+
+		self.quarantine(&mxc, by);
+		count = count.saturating_add(1);
+	}
+
+	count
+}
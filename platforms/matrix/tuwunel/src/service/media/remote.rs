@@ -30,6 +30,7 @@ pub async fn fetch_remote_thumbnail(
 	dim: &Dim,
 ) -> Result<FileMeta> {
 	self.check_fetch_authorized(mxc)?;
+	self.check_quarantined(mxc).await?;
 
 	let result = self
 		.fetch_thumbnail_authenticated(mxc, user, server, timeout_ms, dim)
@@ -55,6 +56,7 @@ pub async fn fetch_remote_content(
 	timeout_ms: Duration,
 ) -> Result<FileMeta> {
 	self.check_fetch_authorized(mxc)?;
+	self.check_quarantined(mxc).await?;
 
 	let result = self
 		.fetch_content_authenticated(mxc, user, server, timeout_ms)
@@ -372,6 +374,7 @@ pub async fn fetch_remote_thumbnail_legacy(
 
 	self.check_legacy_freeze()?;
 	self.check_fetch_authorized(&mxc)?;
+	self.check_quarantined(&mxc).await?;
 	let response = self
 		.services
 		.federation
@@ -412,6 +415,7 @@ pub async fn fetch_remote_content_legacy(
 ) -> Result<media::get_content::v3::Response, Error> {
 	self.check_legacy_freeze()?;
 	self.check_fetch_authorized(mxc)?;
+	self.check_quarantined(mxc).await?;
 	let response = self
 		.services
 		.federation
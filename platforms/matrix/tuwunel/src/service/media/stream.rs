@@ -0,0 +1,165 @@
+//! Uploads written to disk as they are received, and downloads of part of a
+//! file, so that large files are not held in memory whole.
+
+use std::{io::SeekFrom, ops::RangeInclusive};
+
+use bytes::{Bytes, BytesMut};
+use futures::{
+	Stream, StreamExt, pin_mut,
+	stream::{self, BoxStream},
+};
+use ruma::{Mxc, UserId, http_headers::ContentDisposition};
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter},
+};
+use tuwunel_core::{Err, Error, Result, debug_error, implement, utils};
+
+use super::{FileMeta, thumbnail::Dim, usage::Owner};
+
+/// Size of the chunks the content of a range is read in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Part of a file; see [`Service::get_range`].
+pub struct FileRange {
+	/// Metadata of the file, without content.
+	pub meta: FileMeta,
+
+	/// Content of the range, read from the file a chunk at a time as it is
+	/// polled.
+	pub content: BoxStream<'static, Result<Bytes>>,
+
+	/// Offsets of the first and last bytes of the range.
+	pub range: RangeInclusive<u64>,
+
+	/// Length of the whole file.
+	pub len: u64,
+}
+
+/// Uploads a file received as a stream of chunks. The upload fails once it
+/// exceeds `max_size` bytes or the quota of the user, without keeping any of
+/// it. Returns the length of the file.
+#[implement(super::Service)]
+pub async fn create_stream<S, E>(
+	&self,
+	mxc: &Mxc<'_>,
+	user: Option<&UserId>,
+	content_disposition: Option<&ContentDisposition>,
+	content_type: Option<&str>,
+	body: S,
+	max_size: usize,
+) -> Result<usize>
+where
+	S: Stream<Item = Result<Bytes, E>> + Send,
+	E: Into<Error>,
+{
+	let quota = self.services.server.config.media_quota_user_bytes;
+	let remaining = match user {
+		| Some(user) if quota > 0 =>
+			quota.saturating_sub(self.usage(&Owner::User(user.to_owned())).await),
+		| _ => u64::MAX,
+	};
+
+	let upload = self
+		.get_media_dir()
+		.join(format!("upload-{}", utils::random_string(super::MXC_LENGTH)));
+
+	let written = async {
+		let mut file = BufWriter::new(fs::File::create(&upload).await?);
+		let mut written = 0_usize;
+
+		pin_mut!(body);
+		while let Some(chunk) = body.next().await {
+			let chunk = chunk.map_err(Into::into)?;
+			written = written.saturating_add(chunk.len());
+			if written > max_size {
+				return Err!(Request(TooLarge("File is larger than {max_size} bytes.")));
+			}
+
+			if u64::try_from(written).unwrap_or(u64::MAX) > remaining
+				&& let Some(user) = user
+			{
+				self.check_user_quota(user, written).await?;
+			}
+
+			file.write_all(&chunk).await?;
+		}
+
+		file.flush().await?;
+		Ok(written)
+	}
+	.await;
+
+	let written = match written {
+		| Ok(written) => written,
+		| Err(e) => {
+			if let Err(e) = fs::remove_file(&upload).await {
+				debug_error!(?upload, "Failed to remove incomplete upload: {e}");
+			}
+
+			return Err(e);
+		},
+	};
+
+	let key = self.db.create_file_metadata(
+		mxc,
+		user,
+		&Dim::default(),
+		content_disposition,
+		content_type,
+	)?;
+
+	let path = self.get_media_file(&key);
+	fs::rename(&upload, &path).await?;
+	self.link_legacy_media_file(&key, &path).await;
+	self.add_usage(mxc, user, written).await;
+
+	Ok(written)
+}
+
+/// Gets part of a file, to be read as it is sent. The range is clamped to the
+/// end of the file; None if there is no such file or the range starts past its
+/// end.
+#[implement(super::Service)]
+pub async fn get_range(
+	&self,
+	mxc: &Mxc<'_>,
+	range: RangeInclusive<u64>,
+) -> Result<Option<FileRange>> {
+	self.check_quarantined(mxc).await?;
+
+	let Ok(metadata) = self
+		.db
+		.search_file_metadata(mxc, &Dim::default())
+		.await
+	else {
+		return Ok(None);
+	};
+
+	let mut file = fs::File::open(self.get_media_file(&metadata.key)).await?;
+	let len = file.metadata().await?.len();
+	let (start, end) = (*range.start(), (*range.end()).min(len.saturating_sub(1)));
+	if start >= len || start > end {
+		return Ok(None);
+	}
+
+	file.seek(SeekFrom::Start(start)).await?;
+	let file = file.take(end.saturating_sub(start).saturating_add(1));
+	let content = stream::try_unfold(file, async |mut file| {
+		let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+		let read = file.read_buf(&mut chunk).await?;
+
+		Ok::<_, Error>((read > 0).then(|| (chunk.freeze(), file)))
+	});
+
+	Ok(Some(FileRange {
+		content: content.boxed(),
+		meta: FileMeta {
+			content: None,
+			content_type: metadata.content_type,
+			content_disposition: metadata.content_disposition,
+		},
+		range: start..=end,
+		len,
+	}))
+}
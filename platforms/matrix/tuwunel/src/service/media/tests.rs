@@ -103,3 +103,60 @@ fn get_url_preview(&self, _url: &str) -> Option<UrlPreviewData> { todo!() }
 		r.to_str().unwrap().len()
 	);
 }
+
+#[test]
+fn referenced_media_paths() {
+	use serde_json::json;
+
+	use super::referenced_media;
+
+	let content = json!({
+		"msgtype": "m.image",
+		"body": "cat.png",
+		"url": "mxc://example.com/image",
+		"info": {
+			"thumbnail_url": "mxc://example.com/thumbnail",
+		},
+	});
+
+	assert_eq!(referenced_media(&content), [
+		"mxc://example.com/image",
+		"mxc://example.com/thumbnail"
+	]);
+}
+
+#[test]
+fn referenced_media_encrypted_attachment() {
+	use serde_json::json;
+
+	use super::referenced_media;
+
+	let content = json!({
+		"msgtype": "m.file",
+		"body": "notes.txt",
+		"file": { "url": "mxc://example.com/file" },
+		"info": {
+			"thumbnail_file": { "url": "mxc://example.com/file" },
+		},
+	});
+
+	assert_eq!(referenced_media(&content), ["mxc://example.com/file"]);
+}
+
+#[test]
+fn referenced_media_none() {
+	use serde_json::json;
+
+	use super::referenced_media;
+
+	let content = json!({
+		"msgtype": "m.text",
+		"body": "mxc://example.com/text",
+		"url": 42,
+		"info": {
+			"thumbnail_url": "https://example.com/thumbnail.png",
+		},
+	});
+
+	assert!(referenced_media(&content).is_empty());
+}
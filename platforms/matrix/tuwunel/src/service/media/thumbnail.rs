@@ -42,6 +42,7 @@ pub async fn upload_thumbnail(
 		//TODO: Dangling metadata in database if creation fails
 		let mut f = self.create_media_file(&key).await?;
 		f.write_all(file).await?;
+		self.add_usage(mxc, user, file.len()).await;
 
 		Ok(())
 	}
@@ -61,6 +62,8 @@ pub async fn upload_thumbnail(
 	/// which crops the image afterwards.
 	#[tracing::instrument(skip(self), name = "thumbnail", level = "debug")]
 	pub async fn get_thumbnail(&self, mxc: &Mxc<'_>, dim: &Dim) -> Result<Option<FileMeta>> {
+		self.check_quarantined(mxc).await?;
+
 		// 0, 0 because that's the original file
 		let dim = dim.normalized();
 
@@ -138,6 +141,8 @@ async fn get_thumbnail_generate(
 
 	let mut f = self.create_media_file(&thumbnail_key).await?;
 	f.write_all(&thumbnail_bytes).await?;
+	self.add_usage(mxc, None, thumbnail_bytes.len())
+		.await;
 
 	Ok(Some(into_filemeta(data, thumbnail_bytes)))
 }
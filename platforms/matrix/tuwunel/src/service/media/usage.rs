@@ -0,0 +1,370 @@
+//! Disk usage accounting and retention for media.
+//!
+//! Local media counts against the user which uploaded it, including thumbnails
+//! generated from it; remote media counts against the server it was fetched
+//! from. Local media attached to messages also counts against each room it is
+//! sent into. Usage is maintained as files are created and deleted, and
+//! recounted from the media directory when no usage has been recorded yet.
+
+use std::{
+	collections::BTreeMap,
+	time::{Duration, SystemTime},
+};
+
+use futures::{Stream, StreamExt};
+use ruma::{Mxc, OwnedMxcUri, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, UserId};
+use serde_json::Value as JsonValue;
+use tokio::fs;
+use tuwunel_core::{
+	Err, Result, debug, debug_info, debug_warn, implement,
+	utils::{self, time::timepoint_ago},
+};
+
+use super::thumbnail::Dim;
+
+/// Party whose disk usage a media file counts against.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Owner {
+	/// Local user which uploaded the media.
+	User(OwnedUserId),
+
+	/// Remote server the media was fetched from.
+	Server(OwnedServerName),
+
+	/// Room local media was sent into.
+	Room(OwnedRoomId),
+}
+
+/// Paths in message content at which attachments and their thumbnails are
+/// referenced, unencrypted or encrypted.
+const MEDIA_PATHS: [&str; 4] =
+	["url", "file.url", "info.thumbnail_url", "info.thumbnail_file.url"];
+
+/// Determine who a media file counts against. `user` is the user creating the
+/// file, if known; otherwise local media is attributed to its uploader.
+#[implement(super::Service)]
+pub(super) async fn media_owner(&self, mxc: &Mxc<'_>, user: Option<&UserId>) -> Option<Owner> {
+	if !self
+		.services
+		.globals
+		.server_is_ours(mxc.server_name)
+	{
+		return Some(Owner::Server(mxc.server_name.to_owned()));
+	}
+
+	match user {
+		| Some(user) => Some(user.to_owned()),
+		| None => self.db.get_media_user(mxc).await,
+	}
+	.map(Owner::User)
+}
+
+/// Count a newly created file of `len` bytes.
+#[implement(super::Service)]
+pub(super) async fn add_usage(&self, mxc: &Mxc<'_>, user: Option<&UserId>, len: usize) {
+	let Some(owner) = self.media_owner(mxc, user).await else {
+		return;
+	};
+
+	let len: u64 = len.try_into().unwrap_or(u64::MAX);
+	let _lock = self.usage_mutex.lock(&owner).await;
+	let bytes = self.db.get_usage(&owner).await;
+	self.db
+		.set_usage(&owner, bytes.saturating_add(len));
+}
+
+/// Release `len` bytes of deleted files.
+#[implement(super::Service)]
+pub(super) async fn sub_usage(&self, owner: &Owner, len: u64) {
+	let _lock = self.usage_mutex.lock(owner).await;
+	let bytes = self.db.get_usage(owner).await;
+	self.db
+		.set_usage(owner, bytes.saturating_sub(len));
+}
+
+/// Bytes of media counted against an owner.
+#[implement(super::Service)]
+pub async fn usage(&self, owner: &Owner) -> u64 { self.db.get_usage(owner).await }
+
+/// Bytes of media counted against each local user.
+#[implement(super::Service)]
+pub fn users_usage(&self) -> impl Stream<Item = (OwnedUserId, u64)> + Send + '_ {
+	self.db.users_usage()
+}
+
+/// Bytes of media counted against each room.
+#[implement(super::Service)]
+pub fn rooms_usage(&self) -> impl Stream<Item = (OwnedRoomId, u64)> + Send + '_ {
+	self.db.rooms_usage()
+}
+
+/// Bytes of media cached from each remote server.
+#[implement(super::Service)]
+pub fn servers_usage(&self) -> impl Stream<Item = (OwnedServerName, u64)> + Send + '_ {
+	self.db.servers_usage()
+}
+
+/// Reject an upload of `len` bytes which would take the user over
+/// `media_quota_user_bytes`.
+#[implement(super::Service)]
+pub async fn check_user_quota(&self, user_id: &UserId, len: usize) -> Result {
+	let quota = self.services.server.config.media_quota_user_bytes;
+	if quota == 0 {
+		return Ok(());
+	}
+
+	let len: u64 = len.try_into().unwrap_or(u64::MAX);
+	let usage = self.usage(&Owner::User(user_id.to_owned())).await;
+
+	if usage.saturating_add(len) > quota {
+		self.services
+			.server_notices
+			.notify_media_quota(user_id, usage, quota)
+			.await;
+
+		return Err!(Request(TooLarge(
+			"Media quota exceeded; {usage} of {quota} bytes are in use."
+		)));
+	}
+
+	Ok(())
+}
+
+/// Reject a message attaching `mxcs` which would take the room over
+/// `media_quota_room_bytes`. Media already counted against the room is free.
+#[implement(super::Service)]
+pub async fn check_room_quota(&self, room_id: &RoomId, mxcs: &[OwnedMxcUri]) -> Result {
+	let quota = self.services.server.config.media_quota_room_bytes;
+	if quota == 0 {
+		return Ok(());
+	}
+
+	let len = self.uncounted_room_media(room_id, mxcs).await.1;
+	let usage = self.usage(&Owner::Room(room_id.to_owned())).await;
+
+	if usage.saturating_add(len) > quota {
+		return Err!(Request(TooLarge(
+			"Media quota of the room exceeded; {usage} of {quota} bytes are in use."
+		)));
+	}
+
+	Ok(())
+}
+
+/// Count the media attached to a message sent into the room against it.
+#[implement(super::Service)]
+pub async fn add_room_media(&self, room_id: &RoomId, mxcs: &[OwnedMxcUri]) {
+	let owner = Owner::Room(room_id.to_owned());
+	let _lock = self.usage_mutex.lock(&owner).await;
+
+	let (mxcs, len) = self.uncounted_room_media(room_id, mxcs).await;
+	if mxcs.is_empty() {
+		return;
+	}
+
+	for mxc in &mxcs {
+		self.db.add_room_media(mxc, room_id);
+	}
+
+	let bytes = self.db.get_usage(&owner).await;
+	self.db
+		.set_usage(&owner, bytes.saturating_add(len));
+}
+
+/// The local media among `mxcs` not yet counted against the room, and its
+/// size in bytes.
+#[implement(super::Service)]
+async fn uncounted_room_media<'a>(
+	&self,
+	room_id: &RoomId,
+	mxcs: &'a [OwnedMxcUri],
+) -> (Vec<Mxc<'a>>, u64) {
+	let mut uncounted = Vec::new();
+	let mut len: u64 = 0;
+
+	for mxc in mxcs {
+		let Ok(mxc) = Mxc::try_from(mxc.as_str()) else {
+			continue;
+		};
+
+		if !self
+			.services
+			.globals
+			.server_is_ours(mxc.server_name)
+			|| self.db.room_has_media(&mxc, room_id).await
+		{
+			continue;
+		}
+
+		len = len.saturating_add(self.media_len(&mxc).await);
+		uncounted.push(mxc);
+	}
+
+	(uncounted, len)
+}
+
+/// Bytes of the files of an MXC, including thumbnails.
+#[implement(super::Service)]
+async fn media_len(&self, mxc: &Mxc<'_>) -> u64 {
+	let mut len: u64 = 0;
+	for key in self
+		.db
+		.search_mxc_metadata_prefix(mxc)
+		.await
+		.unwrap_or_default()
+	{
+		if let Ok(metadata) = fs::metadata(self.get_media_file(&key)).await {
+			len = len.saturating_add(metadata.len());
+		}
+	}
+
+	len
+}
+
+/// Release the usage of a deleted MXC from the rooms it was counted against.
+#[implement(super::Service)]
+pub(super) async fn remove_room_media(&self, mxc: &Mxc<'_>, len: u64) {
+	for room_id in self.db.remove_media_rooms(mxc).await {
+		self.sub_usage(&Owner::Room(room_id), len).await;
+	}
+}
+
+/// The media referenced by message content, as attachments or their
+/// thumbnails, whether unencrypted or encrypted. Values which are not MXC
+/// URIs are ignored.
+#[must_use]
+pub fn referenced_media(content: &JsonValue) -> Vec<OwnedMxcUri> {
+	let mut mxcs: Vec<OwnedMxcUri> = MEDIA_PATHS
+		.iter()
+		.filter_map(|path| {
+			path.split('.')
+				.try_fold(content, |value, key| value.get(key))
+		})
+		.filter_map(JsonValue::as_str)
+		.filter(|url| url.starts_with("mxc://"))
+		.map(OwnedMxcUri::from)
+		.collect();
+
+	mxcs.sort_unstable();
+	mxcs.dedup();
+	mxcs
+}
+
+/// Discard all recorded usage and count it again from the files in the media
+/// directory. Returns the number of files counted.
+#[implement(super::Service)]
+pub async fn recount_usage(&self) -> Result<usize> {
+	let mut usage = BTreeMap::<Owner, u64>::new();
+	let mut files = BTreeMap::<OwnedMxcUri, u64>::new();
+	let mut count: usize = 0;
+
+	for key in self.db.get_all_media_keys().await {
+		let Some(mxc) = key
+			.split(|&b| b == 0xFF)
+			.next()
+			.and_then(|bytes| utils::str_from_bytes(bytes).ok())
+			.map(OwnedMxcUri::from)
+		else {
+			continue;
+		};
+
+		let Ok(parsed) = mxc.as_str().try_into() else {
+			debug_warn!(?mxc, "Invalid MXC in database, skipping");
+			continue;
+		};
+
+		let Some(owner) = self.media_owner(&parsed, None).await else {
+			continue;
+		};
+
+		let Ok(metadata) = fs::metadata(self.get_media_file(&key)).await else {
+			continue;
+		};
+
+		let bytes = usage.entry(owner).or_default();
+		*bytes = bytes.saturating_add(metadata.len());
+
+		let bytes = files.entry(mxc).or_default();
+		*bytes = bytes.saturating_add(metadata.len());
+		count = count.saturating_add(1);
+	}
+
+	let media_rooms: Vec<_> = self.db.media_rooms().collect().await;
+	for (mxc, room_id) in media_rooms {
+		let bytes = usage.entry(Owner::Room(room_id)).or_default();
+		*bytes = bytes.saturating_add(files.get(&mxc).copied().unwrap_or(0));
+	}
+
+	self.db.clear_usage().await;
+	for (owner, bytes) in &usage {
+		self.db.set_usage(owner, *bytes);
+	}
+
+	debug_info!(?count, owners = usage.len(), "Recounted media usage");
+
+	Ok(count)
+}
+
+/// Delete remote media last modified longer than `max_age` ago. Returns the
+/// number of MXCs deleted.
+#[implement(super::Service)]
+pub async fn purge_remote_media(&self, max_age: Duration) -> Result<usize> {
+	let cutoff = timepoint_ago(max_age)?;
+	let mut count: usize = 0;
+
+	for mxc in self.get_all_mxcs().await? {
+		let Ok(mxc) = mxc.as_str().try_into() else {
+			continue;
+		};
+
+		if self
+			.services
+			.globals
+			.server_is_ours(mxc.server_name)
+		{
+			continue;
+		}
+
+		let Ok(metadata) = self
+			.db
+			.search_file_metadata(&mxc, &Dim::default())
+			.await
+		else {
+			continue;
+		};
+
+		let modified = fs::metadata(self.get_media_file(&metadata.key))
+			.await
+			.and_then(|file| file.modified())
+			.unwrap_or(SystemTime::UNIX_EPOCH);
+
+		if modified > cutoff {
+			continue;
+		}
+
+		debug!(%mxc, ?modified, "Purging expired remote media");
+		match self.delete(&mxc).await {
+			| Ok(()) => count = count.saturating_add(1),
+			| Err(e) => debug_warn!(%mxc, "Failed to purge remote media: {e}"),
+		}
+	}
+
+	Ok(count)
+}
+
+/// Apply `media_retention_remote_seconds`. Returns the number of MXCs deleted.
+#[implement(super::Service)]
+pub async fn enforce_retention(&self) -> Result<usize> {
+	let max_age = self
+		.services
+		.server
+		.config
+		.media_retention_remote_seconds;
+
+	if max_age == 0 {
+		return Ok(0);
+	}
+
+	self.purge_remote_media(Duration::from_secs(max_age))
+		.await
+}
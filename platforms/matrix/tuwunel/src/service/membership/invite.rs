@@ -28,6 +28,18 @@ pub async fn invite(
 		self.local_invite(sender_user, user_id, room_id, reason, is_direct)
 			.boxed()
 			.await?;
+	} else if !self.services.config.allow_federation {
+		return Err!(Request(Forbidden(
+			"Federation is disabled on this server; remote users cannot be invited."
+		)));
+	} else if self
+		.services
+		.users
+		.is_shadow_banned(sender_user)
+		.await
+	{
+		// Invites of shadow-banned users are not sent to other servers.
+		return Ok(());
 	} else {
 		self.remote_invite(sender_user, user_id, room_id, reason, is_direct)
 			.boxed()
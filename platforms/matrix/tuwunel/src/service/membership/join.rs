@@ -116,6 +116,10 @@ pub async fn join(
 		self.join_local(sender_user, room_id, reason, &servers, state_lock)
 			.boxed()
 			.await?;
+	} else if !self.services.config.allow_federation {
+		return Err!(Request(Forbidden(
+			"Federation is disabled on this server; remote rooms cannot be joined."
+		)));
 	} else {
 		// Ask a remote server if we are not participating in this room
 		self.join_remote(sender_user, room_id, reason, &servers, state_lock)
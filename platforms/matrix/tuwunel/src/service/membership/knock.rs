@@ -103,6 +103,10 @@ pub async fn knock(
 		self.knock_room_helper_local(sender_user, room_id, reason, &servers, state_lock)
 			.boxed()
 			.await
+	} else if !self.services.config.allow_federation {
+		Err!(Request(Forbidden(
+			"Federation is disabled on this server; remote rooms cannot be knocked on."
+		)))
 	} else {
 		self.knock_room_helper_remote(sender_user, room_id, reason, &servers, state_lock)
 			.boxed()
@@ -47,10 +47,14 @@ pub(crate) async fn migrations(services: &Services) -> Result {
 	}
 
 	if users_count > 0 {
-		migrate(services).await
+		migrate(services).await?;
 	} else {
-		fresh(services).await
+		fresh(services).await?;
 	}
+
+	progress(services, MIGRATE_STEPS);
+
+	Ok(())
 }
 
 async fn fresh(services: &Services) -> Result {
@@ -66,6 +70,7 @@ async fn fresh(services: &Services) -> Result {
 	db["global"].insert(b"retroactively_fix_bad_data_from_roomuserid_joined", []);
 	db["global"].insert(b"fix_referencedevents_missing_sep", []);
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
+	db["global"].insert(b"fix_token_expiry_layout", []);
 
 	// Create the admin room and server user on first run
 	if services.config.create_admin_room {
@@ -79,6 +84,15 @@ async fn fresh(services: &Services) -> Result {
 	Ok(())
 }
 
+/// Number of steps of `migrate` reported as progress to health checks.
+const MIGRATE_STEPS: usize = 9;
+
+fn progress(services: &Services, step: usize) {
+	services
+		.server
+		.set_migration_progress(step, MIGRATE_STEPS);
+}
+
 /// Apply any migrations
 async fn migrate(services: &Services) -> Result {
 	let db = &services.db;
@@ -95,12 +109,16 @@ async fn migrate(services: &Services) -> Result {
 		db_lt_12(services).await?;
 	}
 
+	progress(services, 1);
+
 	// This migration can be reused as-is anytime the server-default rules are
 	// updated.
 	if services.globals.db.database_version().await < 13 {
 		db_lt_13(services).await?;
 	}
 
+	progress(services, 2);
+
 	if db["global"]
 		.get(b"feat_sha256_media")
 		.await
@@ -111,6 +129,8 @@ async fn migrate(services: &Services) -> Result {
 		media::migrations::checkup_sha256_media(services).await?;
 	}
 
+	progress(services, 3);
+
 	if db["global"]
 		.get(b"fix_bad_double_separator_in_state_cache")
 		.await
@@ -119,6 +139,8 @@ async fn migrate(services: &Services) -> Result {
 		fix_bad_double_separator_in_state_cache(services).await?;
 	}
 
+	progress(services, 4);
+
 	if db["global"]
 		.get(b"retroactively_fix_bad_data_from_roomuserid_joined")
 		.await
@@ -127,6 +149,8 @@ async fn migrate(services: &Services) -> Result {
 		retroactively_fix_bad_data_from_roomuserid_joined(services).await?;
 	}
 
+	progress(services, 5);
+
 	if db["global"]
 		.get(b"fix_referencedevents_missing_sep")
 		.await
@@ -136,6 +160,8 @@ async fn migrate(services: &Services) -> Result {
 		fix_referencedevents_missing_sep(services).await?;
 	}
 
+	progress(services, 6);
+
 	if db["global"]
 		.get(b"fix_readreceiptid_readreceipt_duplicates")
 		.await
@@ -145,11 +171,25 @@ async fn migrate(services: &Services) -> Result {
 		fix_readreceiptid_readreceipt_duplicates(services).await?;
 	}
 
+	progress(services, 7);
+
+	if db["global"]
+		.get(b"fix_token_expiry_layout")
+		.await
+		.is_not_found()
+	{
+		fix_token_expiry_layout(services).await?;
+	}
+
+	progress(services, 8);
+
 	if services.globals.db.database_version().await < 17 {
 		services.globals.db.bump_database_version(17);
 		info!("Migration: Bumped database version to 17");
 	}
 
+	progress(services, 9);
+
 	assert_eq!(
 		services.globals.db.database_version().await,
 		DATABASE_VERSION,
@@ -582,3 +622,54 @@ async fn fix_readreceiptid_readreceipt_duplicates(services: &Services) -> Result
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
 	db.engine.sort()
 }
+
+/// OpenID tokens were stored as `expires_at` immediately followed by the user
+/// id without the separator used by expiring maps; rewrite the live ones.
+/// Expired login and OpenID tokens left behind by the former lazy expiry are
+/// dropped.
+async fn fix_token_expiry_layout(services: &Services) -> Result {
+	use tuwunel_core::utils::time::now_millis;
+	use tuwunel_database::{SEP, ttl::expires_at};
+
+	warn!("Fixing layout of expiring token entries...");
+
+	let db = &services.db;
+	let cork = db.cork_and_sync();
+	let now = now_millis();
+
+	let openidtoken = db["openidtoken_expiresatuserid"].clone();
+	let (mut rewritten, mut removed): (usize, usize) = (0, 0);
+	openidtoken
+		.raw_stream()
+		.expect_ok()
+		.ready_for_each(|(token, val)| match expires_at(val) {
+			| Some(expires_at) if expires_at >= now => {
+				let mut fixed = val.to_vec();
+				fixed.insert(size_of::<u64>(), SEP);
+				openidtoken.insert(token, fixed);
+				rewritten = rewritten.saturating_add(1);
+			},
+			| _ => {
+				openidtoken.remove(token);
+				removed = removed.saturating_add(1);
+			},
+		})
+		.await;
+
+	let logintoken = db["logintoken_expiresatuserid"].clone();
+	logintoken
+		.raw_stream()
+		.expect_ok()
+		.ready_filter(|(_, val)| expires_at(val).is_none_or(|expires_at| expires_at < now))
+		.ready_for_each(|(token, _)| {
+			logintoken.remove(token);
+			removed = removed.saturating_add(1);
+		})
+		.await;
+
+	drop(cork);
+	info!(?rewritten, ?removed, "Fixed layout of expiring token entries.");
+
+	db["global"].insert(b"fix_token_expiry_layout", []);
+	db.engine.sort()
+}
@@ -3,6 +3,7 @@
 #![expect(refining_impl_trait)]
 #![expect(clippy::duration_suboptimal_units)] // remove after MSRV 1.91
 
+mod heartbeat;
 mod manager;
 mod migrations;
 mod once_services;
@@ -13,27 +14,39 @@
 pub mod admin;
 pub mod appservice;
 pub mod client;
+pub mod compaction;
 pub mod config;
 pub mod deactivate;
 pub mod emergency;
+pub mod event_export;
 pub mod federation;
+pub mod geoip;
 pub mod globals;
+pub mod jobs;
 pub mod key_backups;
+pub mod mailer;
 pub mod media;
 pub mod membership;
 pub mod oauth;
 pub mod presence;
 pub mod pusher;
+pub mod ratelimit;
 pub mod registration_tokens;
+pub mod rendezvous;
+pub mod reports;
 pub mod resolver;
 pub mod rooms;
+pub mod sanitize;
 pub mod sending;
 pub mod server_keys;
+pub mod server_notices;
 pub mod sync;
+pub mod threepid;
 pub mod transaction_ids;
 pub mod uiaa;
 pub mod users;
 
+pub(crate) use heartbeat::Heartbeat;
 pub(crate) use once_services::OnceServices;
 pub(crate) use service::{Args, Service};
 
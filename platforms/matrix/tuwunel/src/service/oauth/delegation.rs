@@ -0,0 +1,418 @@
+//! Delegation of all authentication to an OpenID Connect provider per MSC3861,
+//! as deployed with Matrix Authentication Service. Clients obtain access
+//! tokens from the provider; we accept them by introspecting each with the
+//! provider and caching the result briefly, including rejections. The `sub`
+//! claim maps to a local user through an existing association with the
+//! provider. Otherwise the `username` claim is taken as the localpart of a new
+//! user, created and associated with the subject on first use; an existing user
+//! must be associated by an admin first, so that the provider cannot hand out
+//! accounts it does not own. Devices unknown to us are created on first use.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, SystemTime},
+};
+
+use http::StatusCode;
+use reqwest::Method;
+use ruma::{
+	DeviceId, OwnedDeviceId, OwnedUserId, ServerName, UserId, api::client::error::ErrorKind,
+};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{
+	Err, Error, Result, debug_info, err, implement,
+	utils::time::{timepoint_from_epoch, timepoint_from_now, timepoint_has_passed},
+};
+
+use super::{Provider, unique_id_sub};
+use crate::users::Register;
+
+/// Introspected tokens are trusted for at most this long before asking the
+/// provider again, so revocation takes effect promptly.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Number of cached tokens above which expired entries are swept.
+const CACHE_SWEEP: usize = 4096;
+
+/// Scope granting access to the client-server API.
+const API_SCOPES: [&str; 2] =
+	["urn:matrix:client:api:*", "urn:matrix:org.matrix.msc2967.client:api:*"];
+
+/// Scope prefixes naming the device of the token.
+const DEVICE_SCOPES: [&str; 2] =
+	["urn:matrix:client:device:", "urn:matrix:org.matrix.msc2967.client:device:"];
+
+pub(super) type Cache = std::sync::Mutex<HashMap<String, Cached>>;
+
+type DelegatedUser = (OwnedUserId, OwnedDeviceId, Option<SystemTime>);
+
+pub(super) struct Cached {
+	user: Result<DelegatedUser, Rejection>,
+	until: SystemTime,
+}
+
+/// Error a token was rejected with, kept to answer it again.
+#[derive(Clone, Debug)]
+struct Rejection(ErrorKind, String, StatusCode);
+
+/// How the subject of a token maps to a local user.
+#[derive(Debug, Eq, PartialEq)]
+enum Mapping {
+	/// User associated with the subject.
+	Associated(OwnedUserId),
+
+	/// User to create for the subject, and associate with it.
+	Create(OwnedUserId),
+}
+
+/// Selection of an RFC 7662 token introspection response.
+#[derive(Debug, Deserialize)]
+struct Introspection {
+	#[serde(default)]
+	active: bool,
+	sub: Option<String>,
+	username: Option<String>,
+	scope: Option<String>,
+	exp: Option<u64>,
+}
+
+/// Whether authentication is delegated to a provider.
+#[implement(super::Service)]
+#[must_use]
+pub fn delegated(&self) -> bool {
+	self.services
+		.config
+		.auth_delegation_provider
+		.is_some()
+}
+
+/// The provider authentication is delegated to, after discovery.
+#[implement(super::Service)]
+pub async fn delegated_provider(&self) -> Result<Provider> {
+	let Some(id) = self
+		.services
+		.config
+		.auth_delegation_provider
+		.as_deref()
+	else {
+		return Err!(Request(NotFound("Authentication is not delegated.")));
+	};
+
+	self.providers.get(id).await
+}
+
+/// Finds the user and device of an access token issued by the delegated
+/// provider, along with when the token expires. Errs NotFound when the
+/// provider does not recognize the token as active. Rejected tokens are cached
+/// like accepted ones, so that they are not introspected again each time.
+#[implement(super::Service)]
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn find_from_delegated_token(&self, token: &str) -> Result<DelegatedUser> {
+	if let Some(cached) = self
+		.delegated_tokens
+		.lock()
+		.expect("locked")
+		.get(token)
+		.filter(|cached| !timepoint_has_passed(cached.until))
+	{
+		return cached.user.clone().map_err(Into::into);
+	}
+
+	let provider = self.delegated_provider().await?;
+	let introspection = self.introspect(&provider, token).await?;
+	let user = match self
+		.delegated_token_user(&provider, &introspection)
+		.await
+	{
+		| Ok(user) => Ok(user),
+		| Err(Error::Request(kind, message, code)) => Err(Rejection(kind, message.into(), code)),
+		| Err(e) => return Err(e),
+	};
+
+	let until = timepoint_from_now(CACHE_TTL)?;
+	let until = match &user {
+		| Ok((.., Some(expires_at))) => until.min(*expires_at),
+		| _ => until,
+	};
+
+	let mut cache = self.delegated_tokens.lock().expect("locked");
+	if cache.len() >= CACHE_SWEEP {
+		cache.retain(|_, cached| !timepoint_has_passed(cached.until));
+	}
+
+	cache.insert(token.to_owned(), Cached { user: user.clone(), until });
+
+	user.map_err(Into::into)
+}
+
+/// The user and device an introspected token authenticates, along with when
+/// it expires.
+#[implement(super::Service)]
+async fn delegated_token_user(
+	&self,
+	provider: &Provider,
+	introspection: &Introspection,
+) -> Result<DelegatedUser> {
+	if !introspection.active {
+		return Err!(Request(NotFound("Access token is not active.")));
+	}
+
+	let device_id = token_device(introspection.scope.as_deref().unwrap_or_default())?;
+	let user_id = self
+		.delegated_user(provider, introspection)
+		.await?;
+
+	self.ensure_device(&user_id, &device_id).await?;
+
+	let expires_at = introspection
+		.exp
+		.map(Duration::from_secs)
+		.map(timepoint_from_epoch)
+		.transpose()?;
+
+	Ok((user_id, device_id, expires_at))
+}
+
+/// Network request to the Provider's introspection endpoint for a token.
+#[implement(super::Service)]
+#[tracing::instrument(level = "debug", skip_all)]
+async fn introspect(&self, provider: &Provider, token: &str) -> Result<Introspection> {
+	#[derive(Debug, Serialize)]
+	struct IntrospectQuery<'a> {
+		token: &'a str,
+		token_type_hint: &'a str,
+		client_id: &'a str,
+		client_secret: &'a str,
+	}
+
+	let client_secret = provider.get_client_secret().await?;
+
+	let query = IntrospectQuery {
+		token,
+		token_type_hint: "access_token",
+		client_id: &provider.client_id,
+		client_secret: &client_secret,
+	};
+
+	let url = provider
+		.introspection_url
+		.clone()
+		.ok_or_else(|| {
+			err!(Config("introspection_url", "Missing introspection URL in config"))
+		})?;
+
+	self.request((Some(provider), None), Method::POST, url, Some(query))
+		.await
+		.and_then(|value| serde_json::from_value(value).map_err(Into::into))
+}
+
+/// Maps the subject of an introspected token to a local user, creating it if
+/// it does not exist.
+#[implement(super::Service)]
+async fn delegated_user(
+	&self,
+	provider: &Provider,
+	introspection: &Introspection,
+) -> Result<OwnedUserId> {
+	let Some(sub) = introspection.sub.as_deref() else {
+		return Err!(Request(NotFound("Access token has no subject.")));
+	};
+
+	let associated = self
+		.sessions
+		.get_by_unique_id(&unique_id_sub((provider, sub))?)
+		.await
+		.ok()
+		.and_then(|session| session.user_id);
+
+	let named = introspection
+		.username
+		.as_deref()
+		.map(|username| username_user(username, self.services.globals.server_name()))
+		.transpose()?;
+
+	let named_exists = match &named {
+		| Some(user_id) => self.services.users.exists(user_id).await,
+		| None => false,
+	};
+
+	let user_id = match mapping(associated, named, named_exists)? {
+		| Mapping::Associated(user_id) => user_id,
+		| Mapping::Create(user_id) => {
+			if !self.services.globals.user_is_local(&user_id) {
+				return Err!(Request(Forbidden("Delegated user {user_id} is not local.")));
+			}
+
+			if self
+				.services
+				.users
+				.is_reserved_localpart(user_id.localpart())
+			{
+				return Err!(Request(Forbidden(
+					"Username of {user_id} is reserved by the server."
+				)));
+			}
+
+			debug_info!(%user_id, %sub, "Creating user for delegated authentication");
+			self.services
+				.users
+				.full_register(Register {
+					user_id: Some(&user_id),
+					password: Some("*"),
+					origin: Some("sso"),
+					..Default::default()
+				})
+				.await?;
+
+			// Associate the user created for the subject with it, as through
+			// SSO, so that signing in at the provider identifies them later.
+			self.sessions
+				.associate(provider, &user_id, sub)
+				.await?;
+
+			user_id
+		},
+	};
+
+	if self
+		.services
+		.users
+		.is_deactivated(&user_id)
+		.await?
+	{
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	Ok(user_id)
+}
+
+/// Creates the device of a delegated token if it does not exist yet.
+#[implement(super::Service)]
+async fn ensure_device(&self, user_id: &UserId, device_id: &DeviceId) -> Result {
+	if self
+		.services
+		.users
+		.device_exists(user_id, device_id)
+		.await
+	{
+		return Ok(());
+	}
+
+	self.services
+		.users
+		.create_device(user_id, Some(device_id), (None, None), None, None, None)
+		.await
+		.map(|_| ())
+}
+
+/// Maps the subject of a token to a local user: the user associated with it,
+/// or else a new user named by the `username` claim. Existing users are only
+/// accepted through an association, which the provider cannot forge.
+fn mapping(
+	associated: Option<OwnedUserId>,
+	named: Option<OwnedUserId>,
+	named_exists: bool,
+) -> Result<Mapping> {
+	match (associated, named) {
+		| (Some(user_id), _) => Ok(Mapping::Associated(user_id)),
+		| (None, Some(user_id)) if named_exists => Err!(Request(Forbidden(
+			"{user_id} is not associated with this account of the identity provider."
+		))),
+		| (None, Some(user_id)) => Ok(Mapping::Create(user_id)),
+		| (None, None) =>
+			Err!(Request(NotFound("No local user is associated with the subject."))),
+	}
+}
+
+/// The local user named by the `username` claim.
+fn username_user(username: &str, server_name: &ServerName) -> Result<OwnedUserId> {
+	UserId::parse_with_server_name(username.to_lowercase(), server_name).map_err(Into::into)
+}
+
+/// The device named by the scopes of a token, which must grant access to the
+/// client-server API.
+fn token_device(scope: &str) -> Result<OwnedDeviceId> {
+	let scopes: Vec<_> = scope.split_whitespace().collect();
+
+	if !scopes
+		.iter()
+		.any(|scope| API_SCOPES.contains(scope))
+	{
+		return Err!(Request(NotFound("Access token lacks the client API scope.")));
+	}
+
+	scopes
+		.iter()
+		.find_map(|scope| {
+			DEVICE_SCOPES
+				.iter()
+				.find_map(|prefix| scope.strip_prefix(prefix))
+		})
+		.map(Into::into)
+		.ok_or_else(|| err!(Request(NotFound("Access token lacks a device scope."))))
+}
+
+impl From<Rejection> for Error {
+	fn from(Rejection(kind, message, code): Rejection) -> Self {
+		Self::Request(kind, message.into(), code)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::{owned_user_id, server_name};
+
+	use super::{Mapping, mapping, token_device, username_user};
+
+	#[test]
+	fn associated_user_accepted() {
+		let alice = owned_user_id!("@alice:example.com");
+		let mallory = owned_user_id!("@mallory:example.com");
+
+		assert_eq!(
+			mapping(Some(alice.clone()), Some(mallory), true).ok(),
+			Some(Mapping::Associated(alice))
+		);
+	}
+
+	#[test]
+	fn existing_user_by_username_refused() {
+		let alice = owned_user_id!("@alice:example.com");
+
+		assert!(mapping(None, Some(alice), true).is_err());
+	}
+
+	#[test]
+	fn new_user_by_username_created() {
+		let alice = owned_user_id!("@alice:example.com");
+
+		assert_eq!(mapping(None, Some(alice.clone()), false).ok(), Some(Mapping::Create(alice)));
+		assert!(mapping(None, None, false).is_err());
+	}
+
+	#[test]
+	fn username_lowercased() {
+		let user_id = username_user("Alice", server_name!("example.com")).expect("valid");
+
+		assert_eq!(user_id, "@alice:example.com");
+	}
+
+	#[test]
+	fn device_scope() {
+		let scope = "openid urn:matrix:client:api:* urn:matrix:client:device:ABCDEF";
+
+		assert_eq!(
+			token_device(scope)
+				.ok()
+				.as_deref()
+				.map(AsRef::as_ref),
+			Some("ABCDEF")
+		);
+	}
+
+	#[test]
+	fn device_scope_requires_api_scope() {
+		assert!(token_device("urn:matrix:client:device:ABCDEF").is_err());
+		assert!(token_device("urn:matrix:client:api:*").is_err());
+	}
+}
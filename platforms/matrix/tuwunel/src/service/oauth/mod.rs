@@ -1,13 +1,14 @@
+mod delegation;
 pub mod providers;
 pub mod sessions;
 pub mod user_info;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as b64encode};
 use futures::{Stream, StreamExt, TryStreamExt};
 use reqwest::{
-	Method,
+	Method, StatusCode,
 	header::{ACCEPT, CONTENT_TYPE},
 };
 use ruma::UserId;
@@ -15,7 +16,12 @@
 use serde_json::Value as JsonValue;
 use tuwunel_core::{
 	Err, Result, err, implement,
-	utils::{hash::sha256, result::LogErr, stream::ReadyExt},
+	utils::{
+		hash::sha256,
+		result::LogErr,
+		stream::ReadyExt,
+		time::{timepoint_from_now, timepoint_has_passed},
+	},
 };
 use url::Url;
 
@@ -31,6 +37,7 @@ pub struct Service {
 	services: SelfServices,
 	pub providers: Arc<Providers>,
 	pub sessions: Arc<Sessions>,
+	delegated_tokens: delegation::Cache,
 }
 
 impl crate::Service for Service {
@@ -41,6 +48,7 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 			services: args.services.clone(),
 			sessions,
 			providers,
+			delegated_tokens: delegation::Cache::default(),
 		}))
 	}
 
@@ -90,7 +98,8 @@ pub fn user_sessions(
 }
 
 /// Network request to a Provider returning userinfo for a Session. The session
-/// must have a valid access token.
+/// must have a valid access token; when the provider rejects it and the session
+/// has a refresh token, the session is refreshed and the request retried once.
 #[implement(Service)]
 #[tracing::instrument(level = "debug", skip_all, ret)]
 pub async fn request_userinfo(
@@ -105,12 +114,119 @@ pub async fn request_userinfo(
 		.clone()
 		.ok_or_else(|| err!(Config("userinfo_url", "Missing userinfo URL in config")))?;
 
-	self.request((Some(provider), Some(session)), Method::GET, url, Option::<Query>::None)
-		.await
+	let response = self
+		.request((Some(provider), Some(session)), Method::GET, url.clone(), Option::<Query>::None)
+		.await;
+
+	let response = match response {
+		| Err(e)
+			if e.status_code() == StatusCode::UNAUTHORIZED && session.refresh_token.is_some() =>
+		{
+			let session = self.refresh_session((provider, session)).await?;
+
+			self.request(
+				(Some(provider), Some(&session)),
+				Method::GET,
+				url,
+				Option::<Query>::None,
+			)
+			.await
+		},
+		| response => response,
+	};
+
+	response
 		.and_then(|value| serde_json::from_value(value).map_err(Into::into))
 		.log_err()
 }
 
+/// Network request to a Provider exchanging a Session's refresh token for a
+/// new access token. The refreshed Session is persisted and returned.
+#[implement(Service)]
+#[tracing::instrument(level = "debug", skip_all, ret)]
+pub async fn refresh_session(
+	&self,
+	(provider, session): (&Provider, &Session),
+) -> Result<Session> {
+	#[derive(Debug, Serialize)]
+	struct RefreshQuery<'a> {
+		client_id: &'a str,
+		client_secret: &'a str,
+		grant_type: &'a str,
+		refresh_token: &'a str,
+	}
+
+	let Some(refresh_token) = session.refresh_token.as_deref() else {
+		return Err!(Request(Unauthorized("Session has no refresh token.")));
+	};
+
+	if session
+		.refresh_token_expires_at
+		.is_some_and(timepoint_has_passed)
+	{
+		return Err!(Request(Unauthorized("Session refresh token has expired.")));
+	}
+
+	let client_secret = provider.get_client_secret().await?;
+
+	let query = RefreshQuery {
+		client_id: &provider.client_id,
+		client_secret: &client_secret,
+		grant_type: "refresh_token",
+		refresh_token,
+	};
+
+	let url = provider
+		.token_url
+		.clone()
+		.ok_or_else(|| err!(Config("token_url", "Missing token URL in config")))?;
+
+	let response: Session = self
+		.request((Some(provider), None), Method::POST, url, Some(query))
+		.await
+		.and_then(|value| serde_json::from_value(value).map_err(Into::into))
+		.log_err()?;
+
+	let expires_at = response
+		.expires_in
+		.map(Duration::from_secs)
+		.map(timepoint_from_now)
+		.transpose()?;
+
+	// Providers which do not rotate refresh tokens omit them from the response;
+	// the existing refresh token and its expiration remain in effect.
+	let (refresh_token, refresh_token_expires_at) = match response.refresh_token {
+		| Some(refresh_token) => (
+			Some(refresh_token),
+			response
+				.refresh_token_expires_in
+				.map(Duration::from_secs)
+				.map(timepoint_from_now)
+				.transpose()?,
+		),
+		| None => (session.refresh_token.clone(), session.refresh_token_expires_at),
+	};
+
+	let session = Session {
+		token_type: response
+			.token_type
+			.or_else(|| session.token_type.clone()),
+		access_token: response.access_token,
+		expires_in: response.expires_in,
+		expires_at,
+		refresh_token,
+		refresh_token_expires_at,
+		scope: response.scope.or_else(|| session.scope.clone()),
+		..session.clone()
+	};
+
+	if session.sess_id.is_some() {
+		self.sessions.put(&session).await;
+	}
+
+	Ok(session)
+}
+
 /// Network request to a Provider returning information for a Session based on
 /// its access token.
 #[implement(Service)]
@@ -11,12 +11,15 @@
 use serde::{Deserialize, Serialize};
 use tuwunel_core::{
 	Err, Result, at, implement,
-	utils::stream::{IterStream, ReadyExt, TryExpect},
+	utils::{
+		self,
+		stream::{IterStream, ReadyExt, TryExpect},
+	},
 };
 use tuwunel_database::{Cbor, Deserialized, Ignore, Map};
 use url::Url;
 
-use super::{Provider, Providers, UserInfo, unique_id};
+use super::{Provider, Providers, UserInfo, unique_id, unique_id_sub};
 use crate::SelfServices;
 
 pub struct Sessions {
@@ -91,6 +94,10 @@ pub struct Session {
 
 	/// Last userinfo response persisted here.
 	pub user_info: Option<UserInfo>,
+
+	/// Token of the cross-signing reset this authorization approves, rather
+	/// than logging in.
+	pub cross_signing_reset: Option<String>,
 }
 
 /// Session Identifier type.
@@ -193,6 +200,33 @@ pub async fn put(&self, session: &Session) {
 	}
 }
 
+/// Associates a local user with a subject of the provider ahead of their first
+/// login, as though they had signed in through it before.
+#[implement(Sessions)]
+pub async fn associate(&self, provider: &Provider, user_id: &UserId, sub: &str) -> Result {
+	let idp_id = provider.id();
+	let unique_id = unique_id_sub((provider, sub))?;
+	if let Ok(session) = self.get_by_unique_id(&unique_id).await
+		&& session.user_id.as_deref() != Some(user_id)
+	{
+		return Err!(Request(UserInUse(
+			"Subject {sub:?} of {idp_id:?} is already associated with another user."
+		)));
+	}
+
+	let session = Session {
+		idp_id: Some(idp_id.into()),
+		sess_id: Some(utils::random_string(SESSION_ID_LENGTH)),
+		user_id: Some(user_id.into()),
+		user_info: Some(UserInfo { sub: sub.into(), ..Default::default() }),
+		..Default::default()
+	};
+
+	self.put(&session).await;
+
+	Ok(())
+}
+
 /// Fetch database state for a session from its associated `(iss,sub)`, in case
 /// `sess_id` is not known.
 #[implement(Sessions)]
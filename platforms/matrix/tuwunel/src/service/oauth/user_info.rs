@@ -34,6 +34,9 @@ pub struct UserInfo {
 	/// Email address (`email` scope).
 	pub email: Option<String>,
 
+	/// Whether the provider verified the email address.
+	pub email_verified: Option<bool>,
+
 	/// URL to pfp (github/gitlab)
 	pub avatar_url: Option<String>,
 
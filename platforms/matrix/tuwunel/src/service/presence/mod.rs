@@ -18,6 +18,7 @@
 use tuwunel_core::{Result, checked, debug, debug_warn, result::LogErr, trace};
 
 use self::{aggregate::PresenceAggregator, data::Data, presence::Presence};
+use crate::Heartbeat;
 
 pub struct Service {
 	timer_channel: (Sender<TimerType>, Receiver<TimerType>),
@@ -28,6 +29,7 @@ pub struct Service {
 	services: Arc<crate::services::OnceServices>,
 	last_sync_seen: RwLock<HashMap<OwnedUserId, u64>>,
 	device_presence: PresenceAggregator,
+	heartbeat: Heartbeat,
 }
 
 type TimerType = (OwnedUserId, Duration, u64);
@@ -48,6 +50,7 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 			services: args.services.clone(),
 			last_sync_seen: RwLock::new(HashMap::new()),
 			device_presence: PresenceAggregator::new(),
+			heartbeat: Heartbeat::default(),
 		}))
 	}
 
@@ -64,8 +67,10 @@ async fn worker(self: Arc<Self>) -> Result {
 
 		let mut presence_timers: FuturesUnordered<_> = FuturesUnordered::new();
 		let mut timer_handles: HashMap<OwnedUserId, (u64, AbortHandle)> = HashMap::new();
+		let mut heartbeats = Heartbeat::interval();
 		while !receiver.is_closed() && self.services.server.running() {
 			tokio::select! {
+				_ = heartbeats.tick() => self.heartbeat.beat(),
 				Some(result) = presence_timers.next() => {
 					let Ok((user_id, count)) = result else {
 						continue;
@@ -114,6 +119,8 @@ async fn worker(self: Arc<Self>) -> Result {
 		Ok(())
 	}
 
+	fn heartbeats(&self) -> &[Heartbeat] { std::slice::from_ref(&self.heartbeat) }
+
 	async fn interrupt(&self) {
 		let (timer_sender, _) = &self.timer_channel;
 		if !timer_sender.is_closed() {
@@ -122,6 +129,8 @@ async fn interrupt(&self) {
 	}
 
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+
+	fn dependencies(&self) -> &'static [&'static str] { &["sending"] }
 }
 
 impl Service {
@@ -43,6 +43,16 @@ pub struct Notified {
 #[implement(super::Service)]
 #[tracing::instrument(name = "append", level = "debug", skip_all)]
 pub(crate) async fn append_pdu(&self, pdu_id: RawPduId, pdu: &Pdu) -> Result {
+	// Events of shadow-banned users do not notify nor push to anyone.
+	if self
+		.services
+		.users
+		.is_shadow_banned(pdu.sender())
+		.await
+	{
+		return Ok(());
+	}
+
 	// Don't notify the sender of their own events, and dont send from ignored users
 	let push_target = self
 		.services
@@ -75,6 +85,11 @@ pub(crate) async fn append_pdu(&self, pdu_id: RawPduId, pdu: &Pdu) -> Result {
 			.users
 			.is_active_local(target_user_id)
 			.await
+		&& !self
+			.services
+			.users
+			.user_is_ignored(pdu.sender(), target_user_id)
+			.await
 	{
 		push_target.insert(target_user_id.to_owned());
 	}
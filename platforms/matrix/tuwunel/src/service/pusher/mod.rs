@@ -1,11 +1,16 @@
 mod append;
 mod notification;
 mod request;
+mod retry;
 mod send;
 mod suppressed;
 
-use std::sync::Arc;
+use std::{
+	collections::BTreeSet,
+	sync::{Arc, atomic::AtomicU64},
+};
 
+use async_trait::async_trait;
 use futures::{Stream, StreamExt, TryFutureExt, future::join};
 use ipaddress::IPAddress;
 use ruma::{
@@ -16,6 +21,7 @@
 	serde::Raw,
 	uint,
 };
+use tokio::time::sleep;
 use tuwunel_core::{
 	Err, Result, err, implement,
 	utils::{
@@ -26,7 +32,7 @@
 };
 use tuwunel_database::{Database, Deserialized, Ignore, Interfix, Json, Map};
 
-pub use self::append::Notified;
+pub use self::{append::Notified, retry::Retry};
 
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
@@ -34,18 +40,23 @@ pub struct Service {
 	highlight_increment_mutex: MutexMap<(OwnedRoomId, OwnedUserId), ()>,
 	db: Data,
 	suppressed: suppressed::SuppressedQueue,
+
+	/// When the retry queue is next scanned, in seconds since the epoch.
+	retry_next_scan: AtomicU64,
 }
 
 struct Data {
 	db: Arc<Database>,
 	senderkey_pusher: Arc<Map>,
 	pushkey_deviceid: Arc<Map>,
+	pushkeyeventid_retry: Arc<Map>,
 	useridcount_notification: Arc<Map>,
 	userroomid_highlightcount: Arc<Map>,
 	userroomid_notificationcount: Arc<Map>,
 	roomuserid_lastnotificationread: Arc<Map>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
@@ -56,6 +67,7 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 				db: args.db.clone(),
 				senderkey_pusher: args.db["senderkey_pusher"].clone(),
 				pushkey_deviceid: args.db["pushkey_deviceid"].clone(),
+				pushkeyeventid_retry: args.db["pushkeyeventid_retry"].clone(),
 				useridcount_notification: args.db["useridcount_notification"].clone(),
 				userroomid_highlightcount: args.db["userroomid_highlightcount"].clone(),
 				userroomid_notificationcount: args.db["userroomid_notificationcount"].clone(),
@@ -63,9 +75,21 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 					.clone(),
 			},
 			suppressed: suppressed::SuppressedQueue::default(),
+			retry_next_scan: AtomicU64::new(0),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		loop {
+			tokio::select! {
+				() = sleep(retry::RETRY_INTERVAL) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			self.retry_due().await;
+		}
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -143,6 +167,7 @@ pub async fn delete_pusher(&self, sender: &UserId, pushkey: &str) {
 	self.db.senderkey_pusher.del(key);
 	self.db.pushkey_deviceid.remove(pushkey);
 	self.clear_suppressed_pushkey(sender, pushkey);
+	self.clear_retries(sender, pushkey).await;
 
 	self.services
 		.sending
@@ -228,6 +253,36 @@ pub fn get_notifications<'a>(
 		.map(|((_, count), notified)| (count, notified))
 }
 
+/// Forgets the notifications local users were given for the pdus at `counts`
+/// in a room. Each member's notifications are scanned once over the range of
+/// `counts`, rather than looked up for every pdu.
+#[implement(Service)]
+pub async fn delete_notifications(&self, room_id: &RoomId, counts: &BTreeSet<u64>) {
+	let (Some(&first), Some(&last)) = (counts.first(), counts.last()) else {
+		return;
+	};
+
+	self.services
+		.state_cache
+		.room_useroncejoined(room_id)
+		.ready_filter(|user_id| self.services.globals.user_is_local(user_id))
+		.for_each(async |user_id| {
+			self.db
+				.useridcount_notification
+				.keys_from(&(user_id, first))
+				.ignore_err()
+				.ready_take_while(|&(user, count): &(&UserId, u64)| {
+					user == user_id && count <= last
+				})
+				.ready_filter(|(_, count)| counts.contains(count))
+				.ready_for_each(|key| self.db.useridcount_notification.del(key))
+				.await;
+		})
+		.await;
+}
+
+/// The actions the push rules of a user call for on an event. Callers leave
+/// out the users ignoring its sender.
 #[implement(Service)]
 #[tracing::instrument(level = "debug", skip_all)]
 pub async fn get_actions<'a>(
@@ -238,6 +293,22 @@ pub async fn get_actions<'a>(
 	pdu: &Raw<AnySyncTimelineEvent>,
 	room_id: &RoomId,
 ) -> &'a [Action] {
+	let ctx = self
+		.push_context(user, power_levels, room_id)
+		.await;
+
+	ruleset.get_actions(pdu, &ctx).await
+}
+
+/// The context in which push rule conditions are evaluated for a user in a
+/// room.
+#[implement(Service)]
+pub async fn push_context(
+	&self,
+	user: &UserId,
+	power_levels: Option<&RoomPowerLevels>,
+	room_id: &RoomId,
+) -> PushConditionRoomCtx {
 	let user_display_name = self
 		.services
 		.users
@@ -261,13 +332,11 @@ pub async fn get_actions<'a>(
 		rules: power_levels.rules.clone(),
 	});
 
-	let ctx = PushConditionRoomCtx {
+	PushConditionRoomCtx {
 		room_id: room_id.to_owned(),
 		member_count: room_joined_count,
 		user_id: user.to_owned(),
 		user_display_name,
 		power_levels,
-	};
-
-	ruleset.get_actions(pdu, &ctx).await
+	}
 }
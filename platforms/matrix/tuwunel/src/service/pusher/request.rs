@@ -1,4 +1,4 @@
-use std::{fmt::Debug, mem};
+use std::fmt::Debug;
 
 use bytes::BytesMut;
 use ipaddress::IPAddress;
@@ -51,9 +51,7 @@ pub(super) async fn send_request<T>(&self, dest: &str, request: T) -> Result<T::
 		.await;
 
 	match response {
-		| Ok(mut response) => {
-			// reqwest::Response -> http::Response conversion
-
+		| Ok(response) => {
 			trace!("Checking response destination's IP");
 			if let Some(remote_addr) = response.remote_addr()
 				&& let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string())
@@ -62,32 +60,19 @@ pub(super) async fn send_request<T>(&self, dest: &str, request: T) -> Result<T::
 				return Err!(BadServerResponse("Not allowed to send requests to this IP"));
 			}
 
+			let response = crate::client::into_http_response(response).await?; // TODO: handle timeout
 			let status = response.status();
-			let mut http_response_builder = http::Response::builder()
-				.status(status)
-				.version(response.version());
-
-			mem::swap(
-				response.headers_mut(),
-				http_response_builder
-					.headers_mut()
-					.expect("http::response::Builder is usable"),
-			);
-
-			let body = response.bytes().await?; // TODO: handle timeout
-
 			if !status.is_success() {
-				debug_warn!("Push gateway response body: {:?}", string_from_bytes(&body));
+				debug_warn!(
+					"Push gateway response body: {:?}",
+					string_from_bytes(response.body())
+				);
 				return Err!(BadServerResponse(warn!(
 					"Push gateway {dest} returned unsuccessful HTTP response: {status}"
 				)));
 			}
 
-			let response = T::IncomingResponse::try_from_http_response(
-				http_response_builder
-					.body(body)
-					.expect("reqwest body is valid http body"),
-			);
+			let response = T::IncomingResponse::try_from_http_response(response);
 
 			response.map_err(|e| {
 				err!(BadServerResponse(warn!(
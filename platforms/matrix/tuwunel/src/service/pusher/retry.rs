@@ -0,0 +1,229 @@
+//! Pushes the notification gateway failed to accept, retried with exponential
+//! backoff until `pusher_retry_max_attempts` is reached, after which they are
+//! dead-lettered for admins to inspect. Pushes failing for a reason a retry
+//! cannot change are dead-lettered at once. Dead letters are kept for
+//! [`DEAD_LETTER_TTL`].
+
+use std::{sync::atomic::Ordering, time::Duration};
+
+use futures::{Stream, StreamExt};
+use ruma::{
+	EventId, OwnedEventId, OwnedUserId, UserId,
+	api::client::error::ErrorKind,
+	events::{GlobalAccountDataEventType, push_rules::PushRulesEvent},
+	push::Ruleset,
+};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{
+	Error, Result, debug, implement,
+	matrix::Event,
+	utils::{
+		stream::{ReadyExt, TryIgnore},
+		time::now_secs,
+	},
+	warn,
+};
+use tuwunel_database::{Deserialized, Interfix, Json};
+
+/// A push which failed to be delivered.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Retry {
+	/// Number of failed deliveries so far.
+	pub attempts: u32,
+
+	/// When the last delivery failed, in seconds since the epoch.
+	pub failed_at: u64,
+
+	/// Error of the last failed delivery.
+	pub error: String,
+
+	/// When the push was given up on, in seconds since the epoch.
+	pub dead_lettered_at: Option<u64>,
+}
+
+/// Interval at which the queue is checked for pushes due to be retried.
+pub(super) const RETRY_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Time for which a dead-lettered push is kept.
+const DEAD_LETTER_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Longest interval between scans of the queue, which drop the expired dead
+/// letters, when no push is due sooner.
+const SCAN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Queues a failed push to be retried, or dead-letters it once it has failed
+/// `pusher_retry_max_attempts` times.
+#[implement(super::Service)]
+pub async fn queue_retry(
+	&self,
+	user_id: &UserId,
+	pushkey: &str,
+	event_id: &EventId,
+	error: &Error,
+) {
+	let max_attempts = self.services.config.pusher_retry_max_attempts;
+	if max_attempts == 0 {
+		return;
+	}
+
+	let key = (user_id, pushkey, event_id);
+	let attempts = self
+		.db
+		.pushkeyeventid_retry
+		.qry(&key)
+		.await
+		.deserialized::<Retry>()
+		.map_or(0, |retry| retry.attempts)
+		.saturating_add(1);
+
+	// The push itself or its pusher is at fault; it would fail again.
+	let permanent = matches!(error.kind(), ErrorKind::BadJson | ErrorKind::InvalidParam);
+
+	let now = now_secs();
+	let dead_lettered_at = (permanent || attempts >= max_attempts).then_some(now);
+	if dead_lettered_at.is_some() {
+		warn!(%user_id, pushkey, %event_id, attempts, "Giving up on push: {error}");
+	} else {
+		debug!(%user_id, pushkey, %event_id, attempts, "Queued push for retry: {error}");
+	}
+
+	let retry = Retry {
+		attempts,
+		failed_at: now,
+		error: error.to_string(),
+		dead_lettered_at,
+	};
+
+	if dead_lettered_at.is_none() {
+		self.retry_next_scan
+			.fetch_min(self.due_at(&retry), Ordering::Relaxed);
+	}
+
+	self.db
+		.pushkeyeventid_retry
+		.put(key, Json(&retry));
+}
+
+/// Retries each queued push whose backoff has expired. Pushes whose pusher or
+/// event no longer exists are dropped, as are dead letters older than
+/// [`DEAD_LETTER_TTL`]. The queue is only scanned once the earliest push is
+/// due, or [`SCAN_INTERVAL`] after the last scan.
+#[implement(super::Service)]
+pub(super) async fn retry_due(&self) {
+	let now = now_secs();
+	if now < self.retry_next_scan.load(Ordering::Relaxed) {
+		return;
+	}
+
+	// Retries queued during the scan lower it again.
+	self.retry_next_scan
+		.store(u64::MAX, Ordering::Relaxed);
+
+	let expired = now.saturating_sub(DEAD_LETTER_TTL.as_secs());
+	let mut next_scan = now.saturating_add(SCAN_INTERVAL.as_secs());
+	let mut due = Vec::new();
+	self.retries()
+		.ready_for_each(|(user_id, pushkey, event_id, retry)| match retry.dead_lettered_at {
+			| Some(dead_lettered_at) if dead_lettered_at < expired => {
+				self.db
+					.pushkeyeventid_retry
+					.del((&user_id, &pushkey, &event_id));
+			},
+			| Some(_) => {},
+			| None if self.due_at(&retry) <= now => due.push((user_id, pushkey, event_id)),
+			| None => next_scan = next_scan.min(self.due_at(&retry)),
+		})
+		.await;
+
+	self.retry_next_scan
+		.fetch_min(next_scan, Ordering::Relaxed);
+
+	for (user_id, pushkey, event_id) in due {
+		let key = (&user_id, &pushkey, &event_id);
+		let Ok(pusher) = self.get_pusher(&user_id, &pushkey).await else {
+			self.db.pushkeyeventid_retry.del(key);
+			continue;
+		};
+
+		let Ok(pdu) = self.services.timeline.get_pdu(&event_id).await else {
+			self.db.pushkeyeventid_retry.del(key);
+			continue;
+		};
+
+		if pdu.is_redacted() {
+			self.db.pushkeyeventid_retry.del(key);
+			continue;
+		}
+
+		let ruleset = self
+			.services
+			.account_data
+			.get_global::<PushRulesEvent>(&user_id, GlobalAccountDataEventType::PushRules)
+			.await
+			.map_or_else(|_| Ruleset::server_default(&user_id), |ev| ev.content.global);
+
+		match self
+			.send_push_notice(&user_id, &pusher, &ruleset, &pdu)
+			.await
+		{
+			| Ok(()) => self.db.pushkeyeventid_retry.del(key),
+			| Err(e) =>
+				self.queue_retry(&user_id, &pushkey, &event_id, &e)
+					.await,
+		}
+	}
+}
+
+/// When a queued push is due to be retried, in seconds since the epoch; its
+/// backoff grows with the square of its failed attempts.
+#[implement(super::Service)]
+fn due_at(&self, retry: &Retry) -> u64 {
+	let config = &self.services.config;
+	let attempts = u64::from(retry.attempts);
+	let backoff = config
+		.pusher_retry_backoff_min
+		.saturating_mul(attempts)
+		.saturating_mul(attempts)
+		.min(config.pusher_retry_backoff_max);
+
+	retry.failed_at.saturating_add(backoff)
+}
+
+/// Pushes which were given up on, optionally only those of one user.
+#[implement(super::Service)]
+pub fn dead_letters<'a>(
+	&'a self,
+	user_id: Option<&'a UserId>,
+) -> impl Stream<Item = (OwnedUserId, String, OwnedEventId, Retry)> + Send + 'a {
+	self.retries().filter(move |(sender, .., retry)| {
+		let matches = retry.dead_lettered_at.is_some()
+			&& user_id.is_none_or(|user_id| *user_id == **sender);
+
+		async move { matches }
+	})
+}
+
+/// Drops the queued and dead-lettered pushes of a pusher.
+#[implement(super::Service)]
+pub(super) async fn clear_retries(&self, user_id: &UserId, pushkey: &str) {
+	let prefix = (user_id, pushkey, Interfix);
+	self.db
+		.pushkeyeventid_retry
+		.keys_prefix_raw(&prefix)
+		.ignore_err()
+		.ready_for_each(|key| self.db.pushkeyeventid_retry.remove(key))
+		.await;
+}
+
+#[implement(super::Service)]
+fn retries(&self) -> impl Stream<Item = (OwnedUserId, String, OwnedEventId, Retry)> + Send + '_ {
+	type KeyVal<'a> = ((&'a UserId, &'a str, &'a EventId), Retry);
+
+	self.db
+		.pushkeyeventid_retry
+		.stream()
+		.ignore_err()
+		.map(|((user_id, pushkey, event_id), retry): KeyVal<'_>| {
+			(user_id.to_owned(), pushkey.to_owned(), event_id.to_owned(), retry)
+		})
+}
@@ -1,3 +1,5 @@
+use std::sync::atomic::Ordering;
+
 use ipaddress::IPAddress;
 use ruma::{
 	UInt, UserId,
@@ -26,6 +28,16 @@ pub async fn send_push_notice<E>(
 where
 	E: Event,
 {
+	// The sender may have been ignored since the push was queued.
+	if self
+		.services
+		.users
+		.user_is_ignored(event.sender(), user_id)
+		.await
+	{
+		return Ok(());
+	}
+
 	let mut notify = None;
 	let mut tweaks = Vec::new();
 
@@ -181,10 +193,21 @@ async fn send_notice<Pdu: Event>(
 					.ok();
 			}
 
-			self.send_request(&http.url, send_event_notification::v1::Request::new(notify))
-				.await?;
-
-			Ok(())
+			let result = self
+				.send_request(&http.url, send_event_notification::v1::Request::new(notify))
+				.await;
+
+			let metrics = &self.services.server.metrics;
+			match result {
+				| Ok(_) => metrics
+					.pusher_success
+					.fetch_add(1, Ordering::Relaxed),
+				| Err(_) => metrics
+					.pusher_failure
+					.fetch_add(1, Ordering::Relaxed),
+			};
+
+			result.map(|_| ())
 		},
 		// TODO: Handle email
 		//PusherKind::Email(_) => Ok(()),
@@ -0,0 +1,270 @@
+//! Rate limiting of requests by class of endpoint, keyed by the user making
+//! them or else their address.
+//!
+//! Each key has a token bucket per class, refilled at the configured rate up
+//! to its burst size, and a request is refused while its bucket is empty.
+//! Buckets are kept in memory only. Keys refused persistently are recorded as
+//! offenders and refused outright for a penalty period, which is persisted so
+//! that it outlasts a restart.
+
+use std::{
+	collections::HashMap,
+	fmt::{self, Write},
+	net::IpAddr,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use ruma::OwnedUserId;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tuwunel_core::{Result, config::RateLimit, debug, implement, info, utils::stream::TryIgnore};
+use tuwunel_database::{Deserialized, Json, Map};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+	buckets: Mutex<HashMap<(Class, Key), Bucket>>,
+	ratelimitkey_offender: Arc<Map>,
+}
+
+/// Class of endpoints sharing a limit.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Class {
+	Login,
+	Register,
+	Join,
+	Messages,
+	Federation,
+	Email,
+	Rendezvous,
+}
+
+/// What a limit applies to: the authenticated user, else the address. Emails
+/// are limited by the address they are sent to as well.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Key {
+	User(OwnedUserId),
+	Ip(IpAddr),
+	Email(String),
+}
+
+/// A key refused outright until the end of its penalty.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Offender {
+	pub until: SystemTime,
+
+	/// Number of times the key has been made an offender.
+	pub count: u64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+
+	/// Requests refused since the bucket was created.
+	refused: u64,
+
+	/// Penalty the key was last known to be under; checked against the
+	/// database when the bucket is created.
+	penalty_until: Option<SystemTime>,
+}
+
+/// Interval between the pruning of idle buckets.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			buckets: Mutex::default(),
+			ratelimitkey_offender: args.db["ratelimitkey_offender"].clone(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		loop {
+			tokio::select! {
+				() = sleep(PRUNE_INTERVAL) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			self.prune();
+		}
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let buckets = self.buckets.lock().expect("locked").len();
+		writeln!(out, "ratelimit_buckets: {buckets}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) { self.buckets.lock().expect("locked").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Takes a token from the bucket of `key` for `class`. When the bucket is
+/// empty, or the key is an offender, returns how long until a request would
+/// be allowed. Emails and rendezvous sessions are limited even when
+/// `rate_limit` is disabled.
+#[implement(Service)]
+pub async fn check(&self, class: Class, key: &Key) -> Result<(), Duration> {
+	let config = &self.services.config;
+	let limit = self.limit(class);
+	let always = matches!(class, Class::Email | Class::Rendezvous);
+	if (!config.rate_limit && !always) || limit.per_second <= 0.0 {
+		return Ok(());
+	}
+
+	let known = self
+		.buckets
+		.lock()
+		.expect("locked")
+		.contains_key(&(class, key.clone()));
+
+	let penalty_until = if known {
+		None
+	} else {
+		self.get_offender(key)
+			.await
+			.ok()
+			.map(|offender| offender.until)
+	};
+
+	let now = Instant::now();
+	let mut buckets = self.buckets.lock().expect("locked");
+	let bucket = buckets
+		.entry((class, key.clone()))
+		.or_insert_with(|| Bucket {
+			tokens: f64::from(limit.burst_count),
+			updated: now,
+			refused: 0,
+			penalty_until,
+		});
+
+	if let Some(remaining) = bucket
+		.penalty_until
+		.and_then(|until| until.duration_since(SystemTime::now()).ok())
+	{
+		return Err(remaining);
+	}
+
+	let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+	bucket.tokens = elapsed
+		.mul_add(limit.per_second, bucket.tokens)
+		.min(f64::from(limit.burst_count));
+	bucket.updated = now;
+
+	if bucket.tokens >= 1.0 {
+		bucket.tokens -= 1.0;
+		return Ok(());
+	}
+
+	bucket.refused = bucket.refused.saturating_add(1);
+	let threshold = config.rate_limit_offender_threshold;
+	if threshold > 0 && bucket.refused >= threshold {
+		let penalty = Duration::from_secs(config.rate_limit_offender_penalty);
+		let until = SystemTime::now()
+			.checked_add(penalty)
+			.unwrap_or(SystemTime::UNIX_EPOCH);
+
+		bucket.penalty_until = Some(until);
+		bucket.refused = 0;
+		drop(buckets);
+
+		self.mark_offender(key, until).await;
+		return Err(penalty);
+	}
+
+	Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_second))
+}
+
+#[implement(Service)]
+async fn mark_offender(&self, key: &Key, until: SystemTime) {
+	let count = self
+		.get_offender(key)
+		.await
+		.map_or(0, |offender| offender.count);
+
+	let offender = Offender { until, count: count.saturating_add(1) };
+	info!(%key, count = offender.count, "Rate limit offender refused until {until:?}");
+	self.ratelimitkey_offender
+		.raw_put(key.to_string(), Json(&offender));
+}
+
+#[implement(Service)]
+pub async fn get_offender(&self, key: &Key) -> Result<Offender> {
+	self.ratelimitkey_offender
+		.get(&key.to_string())
+		.await
+		.deserialized()
+}
+
+/// Keys recorded as offenders, including those whose penalty has ended.
+#[implement(Service)]
+pub fn offenders(&self) -> impl Stream<Item = (String, Offender)> + Send + '_ {
+	self.ratelimitkey_offender
+		.stream()
+		.ignore_err()
+		.map(|(key, offender): (&str, Offender)| (key.to_owned(), offender))
+}
+
+/// Forgets an offender, lifting any penalty it is under.
+#[implement(Service)]
+pub fn clear_offender(&self, key: &str) {
+	self.ratelimitkey_offender.remove(key);
+	self.buckets
+		.lock()
+		.expect("locked")
+		.retain(|(_, bucket_key), _| bucket_key.to_string() != key);
+}
+
+/// Drops the buckets which have refilled and are under no penalty.
+#[implement(Service)]
+fn prune(&self) {
+	let now = Instant::now();
+	let mut buckets = self.buckets.lock().expect("locked");
+	let before = buckets.len();
+	buckets.retain(|(class, _), bucket| {
+		let limit = self.limit(*class);
+		let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+		let full =
+			elapsed.mul_add(limit.per_second, bucket.tokens) >= f64::from(limit.burst_count);
+
+		!full
+			|| bucket
+				.penalty_until
+				.is_some_and(|until| until > SystemTime::now())
+	});
+
+	debug!(pruned = before.saturating_sub(buckets.len()), "Pruned rate limit buckets");
+}
+
+#[implement(Service)]
+fn limit(&self, class: Class) -> &RateLimit {
+	let config = &self.services.config;
+	match class {
+		| Class::Login => &config.rate_limit_login,
+		| Class::Register => &config.rate_limit_register,
+		| Class::Join => &config.rate_limit_join,
+		| Class::Messages => &config.rate_limit_messages,
+		| Class::Federation => &config.rate_limit_federation,
+		| Class::Email => &config.rate_limit_email,
+		| Class::Rendezvous => &config.rate_limit_rendezvous,
+	}
+}
+
+impl fmt::Display for Key {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::User(user_id) => write!(f, "{user_id}"),
+			| Self::Ip(ip) => write!(f, "{ip}"),
+			| Self::Email(email) => write!(f, "{email}"),
+		}
+	}
+}
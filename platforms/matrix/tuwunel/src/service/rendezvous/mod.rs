@@ -0,0 +1,271 @@
+//! Rendezvous sessions of MSC4108, through which a signed-in client and a new
+//! one exchange the messages of their secure channel when signing in with a
+//! QR code. The payloads are opaque to the server. Sessions are kept in memory
+//! and written through to the database so that they survive a restart.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use http::StatusCode;
+use ruma::api::client::error::ErrorKind;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tuwunel_core::{
+	Err, Error, Result, debug, implement,
+	utils::{
+		self,
+		stream::{ReadyExt, TryIgnore},
+		time::now_secs,
+	},
+};
+use tuwunel_database::{Deserialized, Json, Map};
+
+pub struct Service {
+	sessions: Mutex<HashMap<String, Session>>,
+	db: Data,
+	services: Arc<crate::services::OnceServices>,
+}
+
+struct Data {
+	rendezvousid_session: Arc<Map>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Session {
+	pub content_type: String,
+	pub data: String,
+
+	/// Changes whenever the payload is replaced.
+	pub etag: String,
+
+	/// Seconds since the epoch.
+	pub expires_at: u64,
+
+	/// Seconds since the epoch.
+	pub last_modified: u64,
+}
+
+/// Largest payload accepted, in bytes.
+pub const MAX_PAYLOAD: usize = 4096;
+
+/// Most sessions open at once.
+const MAX_SESSIONS: usize = 4096;
+
+const SESSION_ID_LENGTH: usize = 32;
+
+const ETAG_LENGTH: usize = 16;
+
+/// Interval between the removals of expired sessions.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			sessions: Mutex::default(),
+			db: Data {
+				rendezvousid_session: args.db["rendezvousid_session"].clone(),
+			},
+			services: args.services.clone(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		self.remove_stored_expired().await;
+
+		loop {
+			tokio::select! {
+				() = sleep(PRUNE_INTERVAL) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			self.remove_expired();
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether signing in with a QR code is enabled.
+#[implement(Service)]
+#[inline]
+pub fn enabled(&self) -> bool { self.services.server.config.login_via_qr_code }
+
+/// Opens a session holding the payload. Returns its id.
+#[implement(Service)]
+pub fn create(&self, content_type: &str, data: String) -> Result<(String, Session)> {
+	let mut open = self.sessions.lock().expect("locked").len();
+	if open >= MAX_SESSIONS {
+		self.remove_expired();
+		open = self.sessions.lock().expect("locked").len();
+	}
+
+	if open >= MAX_SESSIONS {
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded { retry_after: None },
+			"Too many rendezvous sessions are open.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	let now = now_secs();
+	let ttl = self.services.server.config.rendezvous_session_ttl;
+	let session = Session {
+		content_type: content_type.to_owned(),
+		data,
+		etag: utils::random_string(ETAG_LENGTH),
+		expires_at: now.saturating_add(ttl),
+		last_modified: now,
+	};
+
+	let id = utils::random_string(SESSION_ID_LENGTH);
+	self.put(&id, &session);
+
+	Ok((id, session))
+}
+
+/// The session, unless it does not exist or has expired.
+#[implement(Service)]
+pub async fn get(&self, id: &str) -> Result<Session> {
+	let cached = self
+		.sessions
+		.lock()
+		.expect("locked")
+		.get(id)
+		.cloned();
+
+	let session = match cached {
+		| Some(session) => session,
+		| None => {
+			let session: Session = self
+				.db
+				.rendezvousid_session
+				.get(id)
+				.await
+				.deserialized::<Session>()
+				.or_else(|_| Err!(Request(NotFound("Rendezvous session not found."))))?;
+
+			// Another request may have loaded and replaced it meanwhile.
+			self.sessions
+				.lock()
+				.expect("locked")
+				.entry(id.to_owned())
+				.or_insert(session)
+				.clone()
+		},
+	};
+
+	if session.expires_at <= now_secs() {
+		self.delete(id);
+		return Err!(Request(NotFound("Rendezvous session has expired.")));
+	}
+
+	Ok(session)
+}
+
+/// Replaces the payload of the session, provided `precondition` holds for it.
+/// The check and the write are made under the same lock, so of concurrent
+/// writers expecting the same payload only one succeeds. None when the
+/// precondition failed.
+#[implement(Service)]
+pub async fn update<F>(
+	&self,
+	id: &str,
+	precondition: F,
+	content_type: &str,
+	data: String,
+) -> Result<Option<Session>>
+where
+	F: FnOnce(&Session) -> bool + Send,
+{
+	// Brings a session from before a restart into memory.
+	self.get(id).await?;
+
+	let mut sessions = self.sessions.lock().expect("locked");
+	let Some(session) = sessions.get_mut(id) else {
+		return Err!(Request(NotFound("Rendezvous session not found.")));
+	};
+
+	if !precondition(session) {
+		return Ok(None);
+	}
+
+	session.content_type = content_type.to_owned();
+	session.data = data;
+	session.etag = utils::random_string(ETAG_LENGTH);
+	session.last_modified = now_secs();
+	self.db
+		.rendezvousid_session
+		.raw_put(id, Json(&*session));
+
+	Ok(Some(session.clone()))
+}
+
+#[implement(Service)]
+pub fn delete(&self, id: &str) {
+	self.sessions.lock().expect("locked").remove(id);
+	self.db.rendezvousid_session.remove(id);
+}
+
+#[implement(Service)]
+fn put(&self, id: &str, session: &Session) {
+	self.db
+		.rendezvousid_session
+		.raw_put(id, Json(session));
+
+	self.sessions
+		.lock()
+		.expect("locked")
+		.insert(id.to_owned(), session.clone());
+}
+
+/// Removes the expired sessions held in memory.
+#[implement(Service)]
+fn remove_expired(&self) {
+	let now = now_secs();
+	let mut sessions = self.sessions.lock().expect("locked");
+	let before = sessions.len();
+	sessions.retain(|id, session| {
+		let expired = session.expires_at <= now;
+		if expired {
+			self.db.rendezvousid_session.remove(id);
+		}
+
+		!expired
+	});
+
+	debug!(
+		removed = before.saturating_sub(sessions.len()),
+		"Removed expired rendezvous sessions"
+	);
+}
+
+/// Removes the expired sessions left in the database from before a restart.
+#[implement(Service)]
+async fn remove_stored_expired(&self) {
+	let now = now_secs();
+	let expired: Vec<String> = self
+		.db
+		.rendezvousid_session
+		.raw_stream()
+		.ignore_err()
+		.ready_filter_map(|(key, val)| {
+			let expired = serde_json::from_slice::<Session>(val)
+				.is_ok_and(|session| session.expires_at <= now);
+
+			expired
+				.then(|| String::from_utf8(key.to_vec()).ok())
+				.flatten()
+		})
+		.collect()
+		.await;
+
+	for id in &expired {
+		self.delete(id);
+	}
+}
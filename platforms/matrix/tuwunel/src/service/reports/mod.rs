@@ -0,0 +1,161 @@
+//! Reports of rooms and events made by users, queued for the server admins to
+//! review and resolve.
+
+use std::sync::Arc;
+
+use futures::Stream;
+use ruma::{OwnedEventId, OwnedRoomId, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{
+	Err, Result, implement, info,
+	utils::{stream::TryIgnore, time::now_secs},
+};
+use tuwunel_database::{Deserialized, Json, Map};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+	reportid_report: Arc<Map>,
+}
+
+/// A room or event reported by a user.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Report {
+	pub reporter: OwnedUserId,
+	pub room_id: OwnedRoomId,
+
+	/// The event reported; the whole room is reported when absent.
+	pub event_id: Option<OwnedEventId>,
+
+	/// Sender of the reported event.
+	pub sender: Option<OwnedUserId>,
+
+	/// Offensiveness from -100 (most offensive) to 0, as scored by the
+	/// reporter.
+	pub score: Option<i64>,
+
+	pub reason: Option<String>,
+
+	/// When the report was received, in seconds since the epoch.
+	pub received_at: u64,
+
+	pub resolution: Option<Resolution>,
+}
+
+/// When a report was resolved, and how.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Resolution {
+	pub resolved_at: u64,
+
+	/// The admin's note on the action taken.
+	pub note: Option<String>,
+}
+
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			reportid_report: args.db["reportid_report"].clone(),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Queues a report, announcing it in the admin room unless disabled by
+/// `admin_report_notices`. Media referenced by a reported event is flagged for
+/// review. Returns the ID of the report.
+#[implement(Service)]
+pub async fn report(
+	&self,
+	reporter: &UserId,
+	room_id: OwnedRoomId,
+	event: Option<(OwnedEventId, OwnedUserId)>,
+	score: Option<i64>,
+	reason: Option<String>,
+) -> u64 {
+	let (event_id, sender) = event.unzip();
+	if let Some(event_id) = &event_id {
+		self.services
+			.media
+			.flag_reported_event(event_id, reporter)
+			.await;
+	}
+
+	let report = Report {
+		reporter: reporter.to_owned(),
+		room_id,
+		event_id,
+		sender,
+		score,
+		reason,
+		received_at: now_secs(),
+		resolution: None,
+	};
+
+	let count = self.services.globals.next_count();
+	let id = *count;
+	self.reportid_report.put(id, Json(&report));
+	drop(count);
+	info!(id, %reporter, room_id = %report.room_id, "Received report");
+
+	if self.services.config.admin_report_notices {
+		self.services
+			.admin
+			.send_text(&format!("@room {}", describe(id, &report)))
+			.await;
+	}
+
+	id
+}
+
+/// Marks a report resolved, with a note on the action taken.
+#[implement(Service)]
+pub async fn resolve(&self, id: u64, note: Option<String>) -> Result {
+	let mut report = self.get(id).await?;
+	if report.resolution.is_some() {
+		return Err!(Request(InvalidParam("Report {id} is already resolved.")));
+	}
+
+	report.resolution = Some(Resolution { resolved_at: now_secs(), note });
+
+	self.reportid_report.put(id, Json(&report));
+
+	Ok(())
+}
+
+#[implement(Service)]
+pub async fn get(&self, id: u64) -> Result<Report> {
+	self.reportid_report.qry(&id).await.deserialized()
+}
+
+/// All the reports, oldest first.
+#[implement(Service)]
+pub fn reports(&self) -> impl Stream<Item = (u64, Report)> + Send + '_ {
+	self.reportid_report.stream().ignore_err()
+}
+
+#[must_use]
+pub fn describe(id: u64, report: &Report) -> String {
+	let Report {
+		reporter,
+		room_id,
+		event_id,
+		sender,
+		score,
+		reason,
+		..
+	} = report;
+	let reason = reason.as_deref().unwrap_or("");
+
+	match (event_id, sender) {
+		| (Some(event_id), Some(sender)) => format!(
+			"Event report {id} received from {reporter} -\n\nEvent ID: {event_id}\nRoom ID: \
+			 {room_id}\nSent By: {sender}\n\nReport Score: {}\nReport Reason: {reason}",
+			score.unwrap_or(0),
+		),
+		| _ => format!(
+			"Room report {id} received from {reporter} -\n\nRoom ID: {room_id}\n\nReport \
+			 Reason: {reason}",
+		),
+	}
+}
@@ -374,6 +374,10 @@ fn handle_resolve_error(e: &ResolveError, host: &'_ str) -> Result {
 	}
 
 	fn validate_dest(&self, dest: &ServerName) -> Result {
+		if !self.services.server.config.allow_federation {
+			return Err!(Config("allow_federation", "Federation is disabled."));
+		}
+
 		if dest == self.services.server.name && !self.services.server.config.federation_loopback {
 			return Err!("Won't send federation request to ourselves");
 		}
@@ -9,7 +9,10 @@
 use reqwest::dns::{Addrs, Name, Resolve, Resolving};
 use tuwunel_core::{Result, Server, err, trace};
 
-use super::cache::{Cache, CachedOverride};
+use super::{
+	cache::{Cache, CachedOverride},
+	eyeballs::Eyeballs,
+};
 
 pub struct Resolver {
 	pub(crate) resolver: Arc<TokioResolver>,
@@ -22,6 +25,7 @@ pub(crate) struct Hooked {
 	resolver: Arc<TokioResolver>,
 	passthru: Arc<Passthru>,
 	cache: Arc<Cache>,
+	eyeballs: Arc<Eyeballs>,
 	server: Arc<Server>,
 }
 
@@ -33,7 +37,11 @@ pub(crate) struct Passthru {
 type ResolvingResult = Result<Addrs, Box<dyn std::error::Error + Send + Sync>>;
 
 impl Resolver {
-	pub(super) fn build(server: &Arc<Server>, cache: Arc<Cache>) -> Result<Arc<Self>> {
+	pub(super) fn build(
+		server: &Arc<Server>,
+		cache: Arc<Cache>,
+		eyeballs: Arc<Eyeballs>,
+	) -> Result<Arc<Self>> {
 		let config = &server.config;
 
 		// Create the primary resolver.
@@ -59,6 +67,7 @@ pub(super) fn build(server: &Arc<Server>, cache: Arc<Cache>) -> Result<Arc<Self>
 				passthru: passthru.clone(),
 				server: server.clone(),
 				cache,
+				eyeballs,
 			}),
 			server: server.clone(),
 			passthru,
@@ -171,7 +180,14 @@ fn resolve(&self, name: Name) -> Resolving {
 			&self.resolver
 		};
 
-		hooked_resolve(self.cache.clone(), self.server.clone(), resolver.clone(), name).boxed()
+		hooked_resolve(
+			self.cache.clone(),
+			self.eyeballs.clone(),
+			self.server.clone(),
+			resolver.clone(),
+			name,
+		)
+		.boxed()
 	}
 }
 
@@ -189,14 +205,18 @@ fn resolve(&self, name: Name) -> Resolving {
 )]
 async fn hooked_resolve(
 	cache: Arc<Cache>,
+	eyeballs: Arc<Eyeballs>,
 	server: Arc<Server>,
 	resolver: Arc<TokioResolver>,
 	name: Name,
 ) -> Result<Addrs, Box<dyn std::error::Error + Send + Sync>> {
-	match cache.get_override(name.as_str()).await {
-		| Ok(cached) if cached.valid() => cached_to_reqwest(cached),
-		| Ok(CachedOverride { overriding, .. }) if overriding.is_some() =>
-			resolve_to_reqwest(
+	let (addrs, port): (Vec<SocketAddr>, _) = match cache.get_override(name.as_str()).await {
+		| Ok(cached) if cached.valid() => {
+			let port = cached.port;
+			(cached_addrs(cached).collect(), port)
+		},
+		| Ok(CachedOverride { overriding, port, .. }) if overriding.is_some() => {
+			let addrs = resolve_to_reqwest(
 				server,
 				resolver,
 				overriding
@@ -206,13 +226,26 @@ async fn hooked_resolve(
 					.expect("overriding is a valid internet name"),
 			)
 			.boxed()
-			.await,
+			.await?
+			.collect();
 
-		| _ =>
-			resolve_to_reqwest(server, resolver, name)
+			(addrs, port)
+		},
+
+		| _ => {
+			let addrs = resolve_to_reqwest(server, resolver, name.clone())
 				.boxed()
-				.await,
-	}
+				.await?
+				.collect();
+
+			// Destinations without an override are reached on the default port.
+			(addrs, 8448)
+		},
+	};
+
+	let addrs = eyeballs.order(name.as_str(), addrs, port);
+
+	Ok(Box::new(addrs.into_iter()))
 }
 
 async fn resolve_to_reqwest(
@@ -237,11 +270,9 @@ async fn resolve_to_reqwest(
 	}
 }
 
-fn cached_to_reqwest(cached: CachedOverride) -> ResolvingResult {
-	let addrs = cached
+fn cached_addrs(cached: CachedOverride) -> impl Iterator<Item = SocketAddr> {
+	cached
 		.ips
 		.into_iter()
-		.map(move |ip| SocketAddr::new(ip, cached.port));
-
-	Ok(Box::new(addrs))
+		.map(move |ip| SocketAddr::new(ip, cached.port))
 }
@@ -0,0 +1,275 @@
+//! Ordering of the addresses of federation destinations resolving to both IPv6
+//! and IPv4, so that a family which does not accept connections (e.g. a broken
+//! AAAA record) is not tried first until the connection times out.
+//!
+//! The preferred family is configured per destination, else it is the family
+//! of the first address resolved. The families are raced as in Happy Eyeballs
+//! (RFC 8305): the other family is probed after
+//! `federation_happy_eyeballs_delay` and the first to connect is tried first
+//! for the next few minutes. Races run in the background, so that resolving a
+//! name never waits on a connection; until one is won, the preferred family is
+//! tried first.
+
+use std::{
+	collections::{HashMap, HashSet},
+	fmt, io,
+	net::SocketAddr,
+	sync::{Arc, Mutex, RwLock},
+	time::{Duration, Instant},
+};
+
+use futures::{FutureExt, future::select_ok};
+use tokio::{net::TcpStream, time::timeout};
+use tuwunel_core::{Server, debug, trace};
+
+pub struct Eyeballs {
+	destinations: RwLock<HashMap<String, FamilyStats>>,
+
+	/// Destinations being raced to.
+	racing: Mutex<HashSet<String>>,
+
+	server: Arc<Server>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Family {
+	Ipv4,
+	Ipv6,
+}
+
+/// Outcome of the connections raced to a destination.
+#[derive(Clone, Debug, Default)]
+pub struct FamilyStats {
+	pub ipv4_attempts: u64,
+	pub ipv4_failures: u64,
+	pub ipv6_attempts: u64,
+	pub ipv6_failures: u64,
+
+	/// Family which connected first in the last race, and when.
+	pub winner: Option<(Family, Instant)>,
+
+	/// When a connection was last attempted.
+	pub last_attempt: Option<Instant>,
+}
+
+/// Time the winner of a race is tried first for before racing again.
+const WINNER_TTL: Duration = Duration::from_secs(300);
+
+/// Time a connection is given to be established before being considered
+/// failed.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most destinations whose statistics are kept; those attempted least
+/// recently are forgotten first.
+const MAX_DESTINATIONS: usize = 4096;
+
+impl Eyeballs {
+	pub(super) fn new(server: &Arc<Server>) -> Arc<Self> {
+		Arc::new(Self {
+			destinations: RwLock::default(),
+			racing: Mutex::default(),
+			server: server.clone(),
+		})
+	}
+
+	/// Orders the addresses resolved for `name` with those of the family to
+	/// be tried first at the front. Connections are raced to `port` in the
+	/// background when no family has won for the destination yet.
+	pub(super) fn order(
+		self: &Arc<Self>,
+		name: &str,
+		addrs: Vec<SocketAddr>,
+		port: u16,
+	) -> Vec<SocketAddr> {
+		let Some(first) = addrs.first() else {
+			return addrs;
+		};
+
+		let config = &self.server.config;
+		let winner = self.winner(name);
+		let preferred = winner.unwrap_or_else(|| {
+			if config.federation_prefer_ipv4.is_match(name) {
+				Family::Ipv4
+			} else if config.federation_prefer_ipv6.is_match(name) {
+				Family::Ipv6
+			} else {
+				Family::of(first)
+			}
+		});
+
+		let (preferred_addrs, fallback_addrs): (Vec<_>, Vec<_>) = addrs
+			.into_iter()
+			.partition(|addr| Family::of(addr) == preferred);
+
+		let delay = config.federation_happy_eyeballs_delay;
+		if let (Some(primary), Some(secondary)) =
+			(preferred_addrs.first(), fallback_addrs.first())
+			&& delay > 0
+			&& port > 0
+			&& winner.is_none()
+		{
+			let primary = SocketAddr::new(primary.ip(), port);
+			let secondary = SocketAddr::new(secondary.ip(), port);
+			self.spawn_race(name, primary, secondary, Duration::from_millis(delay));
+		}
+
+		preferred_addrs
+			.into_iter()
+			.chain(fallback_addrs)
+			.collect()
+	}
+
+	/// Races `primary` and `secondary` in the background unless a race to
+	/// `name` is already running.
+	fn spawn_race(
+		self: &Arc<Self>,
+		name: &str,
+		primary: SocketAddr,
+		secondary: SocketAddr,
+		delay: Duration,
+	) {
+		if !self
+			.racing
+			.lock()
+			.expect("locked for writing")
+			.insert(name.to_owned())
+		{
+			return;
+		}
+
+		let self_ = self.clone();
+		let name = name.to_owned();
+		self.server.runtime().spawn(async move {
+			self_.race(&name, primary, secondary, delay).await;
+			self_
+				.racing
+				.lock()
+				.expect("locked for writing")
+				.remove(&name);
+		});
+	}
+
+	/// Connects to `primary`, and to `secondary` should `primary` not have
+	/// connected after `delay`. Returns the family which connected first.
+	async fn race(
+		&self,
+		name: &str,
+		primary: SocketAddr,
+		secondary: SocketAddr,
+		delay: Duration,
+	) -> Option<Family> {
+		let primary = self.probe(name, primary).boxed();
+		let secondary = async move {
+			tokio::time::sleep(delay).await;
+			self.probe(name, secondary).await
+		}
+		.boxed();
+
+		let (family, _) = select_ok([primary, secondary]).await.ok()?;
+		debug!(?name, ?family, "Won the connection race");
+
+		self.update(name, |stats| stats.winner = Some((family, Instant::now())));
+
+		Some(family)
+	}
+
+	async fn probe(&self, name: &str, addr: SocketAddr) -> io::Result<Family> {
+		let family = Family::of(&addr);
+		self.record(name, family, false);
+
+		trace!(?name, ?addr, "Connecting");
+		let result = match timeout(PROBE_TIMEOUT, TcpStream::connect(addr)).await {
+			| Ok(Ok(_)) => Ok(family),
+			| Ok(Err(e)) => Err(e),
+			| Err(_) => Err(io::ErrorKind::TimedOut.into()),
+		};
+
+		if let Err(e) = &result {
+			debug!(?name, ?addr, "Failed to connect: {e}");
+			self.record(name, family, true);
+		}
+
+		result
+	}
+
+	/// Counts a connection attempt, or else its failure.
+	fn record(&self, name: &str, family: Family, failed: bool) {
+		self.update(name, |stats| {
+			let counter = match (family, failed) {
+				| (Family::Ipv4, false) => &mut stats.ipv4_attempts,
+				| (Family::Ipv4, true) => &mut stats.ipv4_failures,
+				| (Family::Ipv6, false) => &mut stats.ipv6_attempts,
+				| (Family::Ipv6, true) => &mut stats.ipv6_failures,
+			};
+
+			*counter = counter.saturating_add(1);
+			if !failed {
+				stats.last_attempt = Some(Instant::now());
+			}
+		});
+	}
+
+	/// Updates the statistics of `name`, first forgetting the destination
+	/// attempted least recently when `MAX_DESTINATIONS` are kept.
+	fn update<F>(&self, name: &str, f: F)
+	where
+		F: FnOnce(&mut FamilyStats),
+	{
+		let mut destinations = self
+			.destinations
+			.write()
+			.expect("locked for writing");
+
+		if !destinations.contains_key(name)
+			&& destinations.len() >= MAX_DESTINATIONS
+			&& let Some(oldest) = destinations
+				.iter()
+				.min_by_key(|(_, stats)| stats.last_attempt)
+				.map(|(name, _)| name.clone())
+		{
+			destinations.remove(&oldest);
+		}
+
+		f(destinations.entry(name.to_owned()).or_default());
+	}
+
+	/// The family which won the last race to `name`, unless it has expired.
+	fn winner(&self, name: &str) -> Option<Family> {
+		self.destinations
+			.read()
+			.expect("locked for reading")
+			.get(name)?
+			.winner
+			.filter(|(_, at)| at.elapsed() < WINNER_TTL)
+			.map(|(family, _)| family)
+	}
+
+	/// The connection statistics of every destination raced to.
+	#[must_use]
+	pub fn stats(&self) -> Vec<(String, FamilyStats)> {
+		self.destinations
+			.read()
+			.expect("locked for reading")
+			.iter()
+			.map(|(name, stats)| (name.clone(), stats.clone()))
+			.collect()
+	}
+}
+
+impl Family {
+	fn of(addr: &SocketAddr) -> Self {
+		match addr {
+			| SocketAddr::V4(_) => Self::Ipv4,
+			| SocketAddr::V6(_) => Self::Ipv6,
+		}
+	}
+}
+
+impl fmt::Display for Family {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::Ipv4 => write!(f, "IPv4"),
+			| Self::Ipv6 => write!(f, "IPv6"),
+		}
+	}
+}
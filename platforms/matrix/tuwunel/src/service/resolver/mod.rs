@@ -1,6 +1,7 @@
 pub mod actual;
 pub mod cache;
 mod dns;
+pub mod eyeballs;
 pub mod fed;
 #[cfg(test)]
 mod tests;
@@ -12,10 +13,11 @@
 use ruma::OwnedServerName;
 use tuwunel_core::{Result, smallstr::SmallString, utils::MutexMap};
 
-use self::{cache::Cache, dns::Resolver, fed::FedDest};
+use self::{cache::Cache, dns::Resolver, eyeballs::Eyeballs, fed::FedDest};
 
 pub struct Service {
 	pub cache: Arc<Cache>,
+	pub eyeballs: Arc<Eyeballs>,
 	pub resolver: Arc<Resolver>,
 	resolving: Resolving,
 	services: Arc<crate::services::OnceServices>,
@@ -28,9 +30,11 @@ pub struct Service {
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		let cache = Cache::new(args);
+		let eyeballs = Eyeballs::new(args.server);
 		Ok(Arc::new(Self {
 			cache: cache.clone(),
-			resolver: Resolver::build(args.server, cache)?,
+			eyeballs: eyeballs.clone(),
+			resolver: Resolver::build(args.server, cache, eyeballs)?,
 			resolving: MutexMap::new(),
 			services: args.services.clone(),
 		}))
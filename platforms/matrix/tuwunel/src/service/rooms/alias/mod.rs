@@ -79,25 +79,71 @@ pub async fn remove_alias(&self, alias: &RoomAliasId, user_id: &UserId) -> Resul
 			return Err!(Request(Forbidden("User is not permitted to remove this alias.")));
 		}
 
-		let alias = alias.alias();
-		let Ok(room_id) = self.db.alias_roomid.get(&alias).await else {
+		let Ok(room_id) = self
+			.db
+			.alias_roomid
+			.get(alias.alias())
+			.await
+			.deserialized::<OwnedRoomId>()
+		else {
 			return Err!(Request(NotFound("Alias does not exist or is invalid.")));
 		};
 
-		let prefix = (&room_id, Interfix);
-		self.db
-			.aliasid_alias
-			.keys_prefix_raw(&prefix)
-			.ignore_err()
-			.ready_for_each(|key| self.db.aliasid_alias.remove(key))
-			.await;
+		self.unindex_alias(&room_id, alias).await;
 
+		let alias = alias.alias();
 		self.db.alias_roomid.remove(alias.as_bytes());
 		self.db.alias_userid.remove(alias.as_bytes());
 
 		Ok(())
 	}
 
+	/// Points a local alias at another room, keeping who created it. The alias
+	/// resolves to either room throughout, so that it never appears missing
+	/// e.g. during a room upgrade. The user must be allowed to remove the alias
+	/// and to set the canonical alias of the new room. Returns the room the
+	/// alias pointed to.
+	#[tracing::instrument(skip(self))]
+	pub async fn repoint_alias(
+		&self,
+		alias: &RoomAliasId,
+		room_id: &RoomId,
+		user_id: &UserId,
+	) -> Result<OwnedRoomId> {
+		if !self.user_can_remove_alias(alias, user_id).await? {
+			return Err!(Request(Forbidden("User is not permitted to move this alias.")));
+		}
+
+		if !self.user_can_alias_room(room_id, user_id).await? {
+			return Err!(Request(Forbidden(
+				"User is not permitted to set aliases of the target room."
+			)));
+		}
+
+		let old_room_id = self.resolve_local_alias(alias).await?;
+		if old_room_id == room_id {
+			return Ok(old_room_id);
+		}
+
+		let count = self.services.globals.next_count();
+
+		let mut aliasid = room_id.as_bytes().to_vec();
+		aliasid.push(0xFF);
+		aliasid.extend_from_slice(&count.to_be_bytes());
+
+		self.db
+			.aliasid_alias
+			.insert(&aliasid, alias.as_bytes());
+
+		self.db
+			.alias_roomid
+			.insert(alias.alias().as_bytes(), room_id.as_bytes());
+
+		self.unindex_alias(&old_room_id, alias).await;
+
+		Ok(old_room_id)
+	}
+
 	#[inline]
 	pub async fn maybe_resolve(&self, room: &RoomOrAliasId) -> Result<OwnedRoomId> {
 		match <&RoomId>::try_from(room) {
@@ -138,6 +184,12 @@ pub async fn resolve_alias(
 			return Err!(Request(NotFound("Room with alias not found.")));
 		}
 
+		if !self.services.config.allow_federation {
+			return Err!(Request(Forbidden(
+				"Federation is disabled on this server; remote aliases cannot be resolved."
+			)));
+		}
+
 		return self.remote_resolve(room_alias).await;
 	}
 
@@ -190,6 +242,18 @@ pub fn all_local_aliases(&self) -> impl Stream<Item = (&RoomId, &str)> + Send +
 			.map(|(alias_localpart, room_id): (&str, &RoomId)| (room_id, alias_localpart))
 	}
 
+	/// Removes an alias from the aliases listed for a room.
+	async fn unindex_alias(&self, room_id: &RoomId, alias: &RoomAliasId) {
+		let prefix = (room_id, Interfix);
+		self.db
+			.aliasid_alias
+			.stream_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_filter(|(_, val)| *val == alias.as_bytes())
+			.ready_for_each(|(key, _)| self.db.aliasid_alias.remove(key))
+			.await;
+	}
+
 	async fn user_can_remove_alias(&self, alias: &RoomAliasId, user_id: &UserId) -> Result<bool> {
 		self.check_alias_local(alias)?;
 
@@ -212,11 +276,40 @@ async fn user_can_remove_alias(&self, alias: &RoomAliasId, user_id: &UserId) ->
 			return Ok(true);
 		}
 
+		self.user_can_set_canonical_alias(&room_id, user_id)
+			.await
+	}
+
+	/// Whether a user may point aliases at a room: the server user always may,
+	/// others must be joined and allowed to set its canonical alias.
+	async fn user_can_alias_room(&self, room_id: &RoomId, user_id: &UserId) -> Result<bool> {
+		if *user_id == self.services.globals.server_user {
+			return Ok(true);
+		}
+
+		if !self
+			.services
+			.state_cache
+			.is_joined(user_id, room_id)
+			.await
+		{
+			return Ok(false);
+		}
+
+		self.user_can_set_canonical_alias(room_id, user_id)
+			.await
+	}
+
+	async fn user_can_set_canonical_alias(
+		&self,
+		room_id: &RoomId,
+		user_id: &UserId,
+	) -> Result<bool> {
 		// Checking whether the user is able to change canonical aliases of the room
 		if let Ok(power_levels) = self
 			.services
 			.state_accessor
-			.get_power_levels(&room_id)
+			.get_power_levels(room_id)
 			.await
 		{
 			return Ok(
@@ -229,7 +322,7 @@ async fn user_can_remove_alias(&self, alias: &RoomAliasId, user_id: &UserId) ->
 		if let Ok(event) = self
 			.services
 			.state_accessor
-			.room_state_get(&room_id, &StateEventType::RoomCreate, "")
+			.room_state_get(room_id, &StateEventType::RoomCreate, "")
 			.await
 		{
 			return Ok(event.sender() == user_id);
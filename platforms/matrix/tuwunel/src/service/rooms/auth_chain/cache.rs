@@ -0,0 +1,138 @@
+//! Visibility into, and invalidation of, the cached auth chains.
+
+use std::sync::{
+	Arc,
+	atomic::{AtomicU64, Ordering},
+};
+
+use futures::TryStreamExt;
+use ruma::{EventId, OwnedEventId, RoomId};
+use tuwunel_core::{Result, implement, matrix::Event, utils};
+use tuwunel_database::Map;
+
+use crate::rooms::short::ShortEventId;
+
+/// Lookups of the cache since startup.
+#[derive(Default)]
+pub(super) struct Counters {
+	hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+/// Snapshot of the cache for the admin room.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+	/// Chains cached for sets of starting events.
+	pub chunk_entries: usize,
+	pub chunk_bytes: usize,
+
+	/// Chains stored for single events.
+	pub event_entries: usize,
+	pub event_bytes: usize,
+
+	pub hits: u64,
+	pub misses: u64,
+}
+
+impl Counters {
+	pub(super) fn record(&self, hit: bool) {
+		let counter = if hit { &self.hits } else { &self.misses };
+		counter.fetch_add(1, Ordering::Relaxed);
+	}
+}
+
+#[implement(super::Service)]
+pub async fn cache_stats(&self) -> Result<CacheStats> {
+	let (chunk_entries, chunk_bytes) = map_size(&self.db.authchainkey_authchain).await?;
+	let (event_entries, event_bytes) = map_size(&self.db.shorteventid_authchain).await?;
+
+	Ok(CacheStats {
+		chunk_entries,
+		chunk_bytes,
+		event_entries,
+		event_bytes,
+		hits: self.counters.hits.load(Ordering::Relaxed),
+		misses: self.counters.misses.load(Ordering::Relaxed),
+	})
+}
+
+/// Removes the cached chains starting from the event. Returns the number of
+/// entries removed.
+#[implement(super::Service)]
+pub async fn invalidate_event(&self, event_id: &EventId) -> Result<usize> {
+	let shorteventid = self
+		.services
+		.short
+		.get_shorteventid(event_id)
+		.await?;
+
+	self.invalidate(async |key: &[ShortEventId]| key.contains(&shorteventid))
+		.await
+}
+
+/// Removes the cached chains starting from events of the room. Returns the
+/// number of entries removed.
+#[implement(super::Service)]
+pub async fn invalidate_room(&self, room_id: &RoomId) -> Result<usize> {
+	self.invalidate(async |key: &[ShortEventId]| {
+		// The starting events of an entry are all in the same room.
+		let Some(&shorteventid) = key.first() else {
+			return false;
+		};
+
+		let Ok(event_id) = self
+			.services
+			.short
+			.get_eventid_from_short::<OwnedEventId>(shorteventid)
+			.await
+		else {
+			return false;
+		};
+
+		self.services
+			.timeline
+			.get_pdu(&event_id)
+			.await
+			.is_ok_and(|pdu| pdu.room_id() == room_id)
+	})
+	.await
+}
+
+#[implement(super::Service)]
+async fn invalidate<F>(&self, matches: F) -> Result<usize>
+where
+	F: AsyncFn(&[ShortEventId]) -> bool,
+{
+	let mut removed = 0_usize;
+	for map in [&self.db.authchainkey_authchain, &self.db.shorteventid_authchain] {
+		let keys: Vec<Vec<u8>> = map
+			.raw_keys()
+			.map_ok(|key| key.to_vec())
+			.try_collect()
+			.await?;
+
+		for key in keys {
+			let shorteventids: Vec<ShortEventId> = key
+				.chunks_exact(size_of::<ShortEventId>())
+				.map(utils::u64_from_u8)
+				.collect();
+
+			if matches(&shorteventids).await {
+				map.remove(&key);
+				removed = removed.saturating_add(1);
+			}
+		}
+	}
+
+	Ok(removed)
+}
+
+/// Number of entries in the map and bytes of their keys and values.
+async fn map_size(map: &Arc<Map>) -> Result<(usize, usize)> {
+	map.raw_stream()
+		.try_fold((0_usize, 0_usize), async |(entries, bytes), (key, val)| {
+			let size = key.len().saturating_add(val.len());
+			Ok((entries.saturating_add(1), bytes.saturating_add(size)))
+		})
+		.await
+}
@@ -1,3 +1,5 @@
+mod cache;
+
 use std::{
 	collections::{BTreeSet, HashSet},
 	fmt::Debug,
@@ -25,10 +27,12 @@
 };
 use tuwunel_database::Map;
 
+pub use self::cache::CacheStats;
 use crate::rooms::short::ShortEventId;
 
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
+	counters: cache::Counters,
 	db: Data,
 }
 
@@ -44,6 +48,7 @@ impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			services: args.services.clone(),
+			counters: Default::default(),
 			db: Data {
 				authchainkey_authchain: args.db["authchainkey_authchain"].clone(),
 				shorteventid_authchain: args.db["shorteventid_authchain"].clone(),
@@ -328,7 +333,9 @@ async fn get_cached_auth_chain(&self, key: &[u64]) -> Result<Vec<ShortEventId>>
 				.map_err(|_| err!(Request(NotFound("auth_chain not found"))))
 				.await
 		})
-		.await?;
+		.await
+		.inspect(|_| self.counters.record(true))
+		.inspect_err(|_| self.counters.record(false))?;
 
 	let chain = chain
 		.chunks_exact(size_of::<u64>())
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use futures::{FutureExt, StreamExt};
-use ruma::RoomId;
+use ruma::{OwnedUserId, RoomId};
 use tuwunel_core::{
 	Result, debug,
 	result::LogErr,
@@ -62,13 +62,16 @@ pub async fn delete_if_empty_local(&self, room_id: &RoomId, state_lock: RoomMute
 			.expect("unhandled error during room deletion");
 	}
 
+	/// Makes the local users leave the room and forgets it. Returns the users
+	/// who failed to leave.
 	pub async fn delete_room(
 		&self,
 		room_id: &RoomId,
 		force: bool,
 		state_lock: RoomMutexGuard,
-	) -> Result {
+	) -> Result<Vec<OwnedUserId>> {
 		debug!("Making all users leave the room {room_id} and forgetting it");
+		let mut failed_to_leave = Vec::new();
 		let mut users = self
 			.services
 			.state_cache
@@ -90,6 +93,7 @@ pub async fn delete_room(
 				.await
 			{
 				warn!("Failed to leave room: {e}");
+				failed_to_leave.push(user_id.to_owned());
 			}
 		}
 
@@ -192,6 +196,6 @@ pub async fn delete_room(
 			.ok();
 
 		debug!("Successfully deleted room {room_id} from our database");
-		Ok(())
+		Ok(failed_to_leave)
 	}
 }
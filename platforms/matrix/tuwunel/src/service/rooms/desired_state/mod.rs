@@ -0,0 +1,226 @@
+//! Declarative room state.
+//!
+//! The operator declares the desired content of state events for chosen rooms
+//! under `[global.room_state]`. A worker compares the current state of each
+//! room with what is declared and sends the events needed to restore it as the
+//! server user, reporting each correction to the admin room. Only the fields
+//! declared are enforced, including within nested objects; other fields of an
+//! event are kept as found.
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use ruma::{RoomId, RoomOrAliasId, events::StateEventType};
+use serde_json::{Value as JsonValue, value::to_raw_value};
+use tokio::time::sleep;
+use tuwunel_core::{
+	Err, Result, config::RoomStateConfig, debug, implement, matrix::pdu::PduBuilder, warn,
+};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self { services: args.services.clone() }))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		if self.services.config.room_state.is_empty() {
+			return Ok(());
+		}
+
+		let interval = Duration::from_secs(self.services.config.room_state_interval);
+		loop {
+			tokio::select! {
+				() = sleep(interval) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			let report = self.reconcile_all().await;
+			if !report.is_empty() {
+				self.services
+					.admin
+					.notice(&format!("Restored the configured room state:\n{report}"))
+					.await;
+			}
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+
+	fn dependencies(&self) -> &'static [&'static str] { &["admin"] }
+}
+
+/// Restores the configured state of every configured room. Returns a report
+/// of the corrections made and failures met, empty when no room drifted.
+#[implement(Service)]
+pub async fn reconcile_all(&self) -> String {
+	let mut report = String::new();
+	for (room, desired) in &self.services.config.room_state {
+		match self.reconcile(room, desired).await {
+			| Ok(changes) =>
+				for event_type in changes {
+					writeln!(report, "- {room}: {event_type}").expect("written to string");
+				},
+			| Err(e) => {
+				warn!(%room, "Failed to restore configured room state: {e}");
+				writeln!(report, "- {room}: failed: {e}").expect("written to string");
+			},
+		}
+	}
+
+	report
+}
+
+/// Restores the configured state of one room. Returns the types of the state
+/// events sent.
+#[implement(Service)]
+pub async fn reconcile(
+	&self,
+	room: &RoomOrAliasId,
+	desired: &RoomStateConfig,
+) -> Result<Vec<String>> {
+	let room_id = self.services.alias.maybe_resolve(room).await?;
+
+	if !self
+		.services
+		.state_cache
+		.server_in_room(self.services.globals.server_name(), &room_id)
+		.await
+	{
+		return Err!("This server is not in the room.");
+	}
+
+	let mut changes = Vec::new();
+	for (event_type, content) in desired.desired() {
+		let Some(content) = self.drifted(&room_id, event_type, content).await else {
+			continue;
+		};
+
+		debug!(%room_id, %event_type, "Restoring configured room state");
+
+		let state_lock = self.services.state.mutex.lock(&room_id).await;
+		self.services
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					event_type: event_type.into(),
+					content: to_raw_value(&content)?,
+					state_key: Some(String::new().into()),
+					..Default::default()
+				},
+				&self.services.globals.server_user,
+				&room_id,
+				&state_lock,
+			)
+			.await?;
+
+		changes.push(event_type.to_owned());
+	}
+
+	Ok(changes)
+}
+
+/// Returns the content to send for an event which has drifted from its
+/// desired content: the current content with the desired fields overlaid.
+#[implement(Service)]
+async fn drifted(
+	&self,
+	room_id: &RoomId,
+	event_type: &str,
+	desired: &JsonValue,
+) -> Option<JsonValue> {
+	let current: Option<JsonValue> = self
+		.services
+		.state_accessor
+		.room_state_get_content(room_id, &StateEventType::from(event_type), "")
+		.await
+		.ok();
+
+	let content = match current.clone() {
+		| Some(mut content) => {
+			overlay(&mut content, desired);
+			content
+		},
+		| None => desired.clone(),
+	};
+
+	(current.as_ref() != Some(&content)).then_some(content)
+}
+
+/// Overlays the fields of `desired` onto `content`. Objects are merged
+/// recursively, so declaring one entry of a map such as the `users` of the
+/// power levels keeps the others; any other value replaces the current one.
+fn overlay(content: &mut JsonValue, desired: &JsonValue) {
+	match (content, desired) {
+		| (JsonValue::Object(content), JsonValue::Object(desired)) =>
+			for (key, desired) in desired {
+				match content.get_mut(key) {
+					| Some(current) => overlay(current, desired),
+					| None => {
+						content.insert(key.clone(), desired.clone());
+					},
+				}
+			},
+		| (content, desired) => *content = desired.clone(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::overlay;
+
+	#[test]
+	fn overlay_nested() {
+		let mut content = json!({
+			"users": { "@alice:example.com": 100, "@bob:example.com": 50 },
+			"events_default": 0,
+		});
+
+		overlay(
+			&mut content,
+			&json!({
+				"users": { "@bob:example.com": 0, "@carol:example.com": 50 },
+				"events_default": 50,
+			}),
+		);
+
+		assert_eq!(
+			content,
+			json!({
+				"users": {
+					"@alice:example.com": 100,
+					"@bob:example.com": 0,
+					"@carol:example.com": 50,
+				},
+				"events_default": 50,
+			})
+		);
+	}
+
+	#[test]
+	fn overlay_replaces_other_values() {
+		let mut content = json!({ "aliases": ["#a:example.com"], "users": 5 });
+
+		overlay(
+			&mut content,
+			&json!({
+				"aliases": ["#b:example.com"],
+				"users": { "@alice:example.com": 100 },
+			}),
+		);
+
+		assert_eq!(
+			content,
+			json!({
+				"aliases": ["#b:example.com"],
+				"users": { "@alice:example.com": 100 },
+			})
+		);
+	}
+}
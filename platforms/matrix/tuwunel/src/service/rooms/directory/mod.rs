@@ -1,35 +1,96 @@
-use std::sync::Arc;
+mod pagination;
+mod publication;
 
+use std::{
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
 use futures::Stream;
+use lru_cache::LruCache;
 use ruma::{RoomId, api::client::room::Visibility};
+use tokio::time::sleep;
 use tuwunel_core::{Result, implement, utils::stream::TryIgnore};
 use tuwunel_database::Map;
 
+use self::pagination::LISTING_CACHE_CAPACITY;
+pub use self::pagination::{Listing, listing_key};
+
 pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+	listings: Mutex<LruCache<u64, Arc<Listing>>>,
 	db: Data,
 }
 
 struct Data {
+	publicationid_roomid: Arc<Map>,
 	publicroomids: Arc<Map>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			listings: Mutex::new(LruCache::new(LISTING_CACHE_CAPACITY)),
 			db: Data {
+				publicationid_roomid: args.db["publicationid_roomid"].clone(),
 				publicroomids: args.db["publicroomids"].clone(),
 			},
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		if self
+			.services
+			.config
+			.directory_publication
+			.is_empty()
+		{
+			return Ok(());
+		}
+
+		let interval = Duration::from_secs(
+			self.services
+				.config
+				.directory_publication_interval,
+		);
+
+		loop {
+			self.publish_all().await;
+
+			tokio::select! {
+				() = sleep(interval) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+		}
+	}
+
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		let listings = self.listings.lock().expect("locked").len();
+		writeln!(out, "public_room_listings: {listings}")?;
+
+		Ok(())
+	}
+
+	async fn clear_cache(&self) { self.clear_listings(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 #[implement(Service)]
-pub fn set_public(&self, room_id: &RoomId) { self.db.publicroomids.insert(room_id, []); }
+pub fn set_public(&self, room_id: &RoomId) {
+	self.db.publicroomids.insert(room_id, []);
+	self.clear_listings();
+}
 
 #[implement(Service)]
-pub fn set_not_public(&self, room_id: &RoomId) { self.db.publicroomids.remove(room_id); }
+pub fn set_not_public(&self, room_id: &RoomId) {
+	self.db.publicroomids.remove(room_id);
+	self.clear_listings();
+}
 
 #[implement(Service)]
 pub fn public_rooms(&self) -> impl Stream<Item = &RoomId> + Send {
@@ -0,0 +1,63 @@
+//! Listings of the public room directory, cached in memory per filter. Later
+//! pages of a query are listed from the same listing, so that paging through
+//! it with `since` tokens neither repeats nor skips rooms as their member
+//! counts change, and the directory is not walked again for each page.
+
+use std::{
+	hash::{DefaultHasher, Hash, Hasher},
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use ruma::{OwnedRoomId, directory::Filter};
+use tuwunel_core::implement;
+
+/// Rooms matching a filter, in the order they are listed.
+pub struct Listing {
+	pub room_ids: Vec<OwnedRoomId>,
+	created: Instant,
+}
+
+/// Time after which a listing is built again.
+const LISTING_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// Number of listings cached.
+pub(super) const LISTING_CACHE_CAPACITY: usize = 256;
+
+/// Key of the listing of the rooms matching a filter.
+#[must_use]
+pub fn listing_key(filter: &Filter) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	serde_json::to_vec(filter)
+		.unwrap_or_default()
+		.hash(&mut hasher);
+
+	hasher.finish()
+}
+
+/// The listing cached for a filter, unless it has expired.
+#[implement(super::Service)]
+pub fn listing(&self, key: u64) -> Option<Arc<Listing>> {
+	self.listings
+		.lock()
+		.expect("locked")
+		.get_mut(&key)
+		.filter(|listing| listing.created.elapsed() < LISTING_LIFETIME)
+		.cloned()
+}
+
+/// Caches the rooms listed for a filter.
+#[implement(super::Service)]
+pub fn cache_listing(&self, key: u64, room_ids: Vec<OwnedRoomId>) -> Arc<Listing> {
+	let listing = Arc::new(Listing { room_ids, created: Instant::now() });
+	self.listings
+		.lock()
+		.expect("locked")
+		.insert(key, listing.clone());
+
+	listing
+}
+
+/// Forgets the cached listings, which no longer reflect the directory.
+#[implement(super::Service)]
+pub(super) fn clear_listings(&self) { self.listings.lock().expect("locked").clear(); }
@@ -0,0 +1,194 @@
+//! Publication of local rooms to the room directories of remote homeservers.
+//!
+//! There is no federation API for listing a room in another server's
+//! directory, so an account on each remote homeserver configured under
+//! `[global.directory_publication]` joins the rooms and publishes them through
+//! the client-server API of its homeserver. The rooms published are tracked so
+//! that they can be unpublished once they leave the local directory or the
+//! configured selection.
+
+use std::{collections::BTreeSet, fmt::Debug};
+
+use bytes::BytesMut;
+use futures::StreamExt;
+use ruma::{
+	OwnedRoomId, RoomId,
+	api::{
+		IncomingResponse, MatrixVersion, OutgoingRequest, SendAccessToken, SupportedVersions,
+		client::{
+			directory::set_room_visibility, membership::join_room_by_id_or_alias,
+			room::Visibility,
+		},
+	},
+};
+use tuwunel_core::{
+	Err, Result,
+	config::DirectoryPublicationConfig,
+	debug, debug_warn, err, implement, info,
+	utils::{
+		stream::{IterStream, TryIgnore},
+		string_from_bytes,
+	},
+	warn,
+};
+use tuwunel_database::Interfix;
+
+use crate::client;
+
+/// Publishes the local rooms to each configured remote directory, and
+/// unpublishes those no longer to be published.
+#[implement(super::Service)]
+pub async fn publish_all(&self) {
+	for (id, target) in &self.services.config.directory_publication {
+		match self.publish(id, target).await {
+			| Ok((published, unpublished)) => {
+				debug!(%id, published, unpublished, "Published rooms to remote directory");
+			},
+			| Err(e) => warn!(%id, "Failed to publish rooms to remote directory: {e}"),
+		}
+	}
+}
+
+/// Publishes the rooms selected for a remote directory which are in the local
+/// directory and were not published yet, and unpublishes those previously
+/// published which no longer are. Returns the number of rooms published and
+/// unpublished.
+#[implement(super::Service)]
+pub async fn publish(
+	&self,
+	id: &str,
+	target: &DirectoryPublicationConfig,
+) -> Result<(usize, usize)> {
+	let wanted: BTreeSet<OwnedRoomId> = if target.rooms.is_empty() {
+		self.public_rooms()
+			.map(ToOwned::to_owned)
+			.collect()
+			.await
+	} else {
+		target
+			.rooms
+			.iter()
+			.stream()
+			.filter(|&room_id| self.is_public_room(room_id))
+			.map(ToOwned::to_owned)
+			.collect()
+			.await
+	};
+
+	let prefix = (id, Interfix);
+	let published: BTreeSet<OwnedRoomId> = self
+		.db
+		.publicationid_roomid
+		.keys_prefix(&prefix)
+		.ignore_err()
+		.map(|(_, room_id): (&str, &RoomId)| room_id.to_owned())
+		.collect()
+		.await;
+
+	let mut unpublished = 0_usize;
+	for room_id in published
+		.iter()
+		.filter(|room_id| !wanted.contains(*room_id))
+	{
+		match self
+			.set_remote_visibility(target, room_id, Visibility::Private)
+			.await
+		{
+			| Ok(()) => {
+				info!(%id, %room_id, "Unpublished room from remote directory");
+				self.db.publicationid_roomid.del((id, room_id));
+				unpublished = unpublished.saturating_add(1);
+			},
+			| Err(e) => warn!(%id, %room_id, "Failed to unpublish room: {e}"),
+		}
+	}
+
+	let mut count = 0_usize;
+	for room_id in wanted
+		.iter()
+		.filter(|room_id| !published.contains(*room_id))
+	{
+		match self.publish_room(target, room_id).await {
+			| Ok(()) => {
+				self.db
+					.publicationid_roomid
+					.put_raw((id, room_id), []);
+				count = count.saturating_add(1);
+			},
+			| Err(e) => warn!(%id, %room_id, "Failed to publish room: {e}"),
+		}
+	}
+
+	Ok((count, unpublished))
+}
+
+/// Joins the remote account to a room and publishes the room to the directory
+/// of its homeserver.
+#[implement(super::Service)]
+async fn publish_room(&self, target: &DirectoryPublicationConfig, room_id: &RoomId) -> Result {
+	let mut join = join_room_by_id_or_alias::v3::Request::new(room_id.to_owned().into());
+	join.via = vec![self.services.globals.server_name().to_owned()];
+
+	self.send_remote(target, join).await?;
+	self.set_remote_visibility(target, room_id, Visibility::Public)
+		.await
+}
+
+#[implement(super::Service)]
+async fn set_remote_visibility(
+	&self,
+	target: &DirectoryPublicationConfig,
+	room_id: &RoomId,
+	visibility: Visibility,
+) -> Result {
+	let request = set_room_visibility::v3::Request::new(room_id.to_owned(), visibility);
+
+	self.send_remote(target, request)
+		.await
+		.map(|_| ())
+}
+
+/// Sends a client-server API request to a remote homeserver as its account.
+#[implement(super::Service)]
+#[tracing::instrument(level = "debug", skip_all, fields(homeserver = %target.homeserver))]
+async fn send_remote<T>(
+	&self,
+	target: &DirectoryPublicationConfig,
+	request: T,
+) -> Result<T::IncomingResponse>
+where
+	T: OutgoingRequest + Debug + Send,
+{
+	const VERSIONS: [MatrixVersion; 1] = [MatrixVersion::V1_7];
+	let supported = SupportedVersions {
+		versions: VERSIONS.into(),
+		features: Default::default(),
+	};
+
+	let dest = target.homeserver.as_str();
+	let http_request = request
+		.try_into_http_request::<BytesMut>(
+			dest,
+			SendAccessToken::Always(&target.access_token),
+			&supported,
+		)
+		.map_err(|e| err!(BadServerResponse("Failed to make request to {dest}: {e}")))?
+		.map(BytesMut::freeze);
+
+	let response = self
+		.services
+		.client
+		.default
+		.execute(reqwest::Request::try_from(http_request)?)
+		.await?;
+
+	let response = client::into_http_response(response).await?;
+	let status = response.status();
+	if !status.is_success() {
+		debug_warn!("Remote homeserver response body: {:?}", string_from_bytes(response.body()));
+		return Err!(BadServerResponse("{dest} returned unsuccessful HTTP response {status}"));
+	}
+
+	T::IncomingResponse::try_from_http_response(response)
+		.map_err(|e| err!(BadServerResponse("{dest} returned invalid response: {e}")))
+}
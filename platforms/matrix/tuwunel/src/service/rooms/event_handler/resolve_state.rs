@@ -3,7 +3,7 @@
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
 use ruma::{OwnedEventId, RoomId, RoomVersionId};
 use tuwunel_core::{
-	Result, err, implement,
+	Err, Result, err, implement,
 	matrix::room_version,
 	state_res::{self, AuthSet, StateMap},
 	trace,
@@ -42,8 +42,96 @@ pub async fn resolve_state(
 		.collect()
 		.await;
 
+	let state = self
+		.resolve_forks(room_id, room_version, &[current_state_ids, incoming_state])
+		.await?;
+
+	Ok(self.compress_state(&state).await)
+}
+
+/// Resolves the state of a room anew from the states at its forward
+/// extremities, to repair a current state which became inconsistent. The
+/// current state itself is not taken into account.
+#[implement(super::Service)]
+#[tracing::instrument(name = "room_state", level = "debug", skip(self))]
+pub async fn resolve_room_state(&self, room_id: &RoomId) -> Result<HashMap<u64, OwnedEventId>> {
+	let room_version = self
+		.services
+		.state
+		.get_room_version(room_id)
+		.await?;
+
+	let extremities: Vec<OwnedEventId> = self
+		.services
+		.state
+		.get_forward_extremities(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	if extremities.is_empty() {
+		return Err!(Database("No forward extremities for {room_id}"));
+	}
+
+	trace!(?extremities, "Loading states at forward extremities");
+	let mut fork_states = Vec::with_capacity(extremities.len());
+	for event_id in &extremities {
+		let shortstatehash = self
+			.services
+			.state
+			.pdu_shortstatehash(event_id)
+			.map_err(|e| err!(Database("No state at {event_id}: {e}")))
+			.await?;
+
+		let mut state: HashMap<_, _> = self
+			.services
+			.state_accessor
+			.state_full_ids(shortstatehash)
+			.collect()
+			.await;
+
+		// The state at an event is that before it; a state event is part of the
+		// state after it.
+		let pdu = self.services.timeline.get_pdu(event_id).await?;
+		if let Some(state_key) = &pdu.state_key {
+			let shortstatekey = self
+				.services
+				.short
+				.get_or_create_shortstatekey(&pdu.kind.to_string().into(), state_key)
+				.await;
+
+			state.insert(shortstatekey, event_id.clone());
+		}
+
+		fork_states.push(state);
+	}
+
+	self.resolve_forks(room_id, &room_version, &fork_states)
+		.await
+}
+
+/// Compresses a state keyed by shortstatekey for saving.
+#[implement(super::Service)]
+pub async fn compress_state(&self, state: &HashMap<u64, OwnedEventId>) -> Arc<CompressedState> {
+	trace!("Compressing state...");
+	let new_room_state: CompressedState = self
+		.services
+		.state_compressor
+		.compress_state_events(state.iter().map(|(ssk, eid)| (ssk, &**eid)))
+		.collect()
+		.await;
+
+	Arc::new(new_room_state)
+}
+
+#[implement(super::Service)]
+async fn resolve_forks(
+	&self,
+	room_id: &RoomId,
+	room_version: &RoomVersionId,
+	fork_states: &[HashMap<u64, OwnedEventId>],
+) -> Result<HashMap<u64, OwnedEventId>> {
 	trace!("Loading fork states");
-	let fork_states = [current_state_ids, incoming_state];
 	let auth_chain_sets = fork_states
 		.iter()
 		.try_stream()
@@ -75,31 +163,19 @@ pub async fn resolve_state(
 		.await?;
 
 	trace!("State resolution done.");
-	let state_events: Vec<_> = state
+	let state = state
 		.iter()
 		.stream()
 		.wide_then(|((event_type, state_key), event_id)| {
 			self.services
 				.short
 				.get_or_create_shortstatekey(event_type, state_key)
-				.map(move |shortstatekey| (shortstatekey, event_id))
+				.map(move |shortstatekey| (shortstatekey, event_id.clone()))
 		})
 		.collect()
 		.await;
 
-	trace!("Compressing state...");
-	let new_room_state: CompressedState = self
-		.services
-		.state_compressor
-		.compress_state_events(
-			state_events
-				.iter()
-				.map(|(ssk, eid)| (ssk, (*eid).borrow())),
-		)
-		.collect()
-		.await;
-
-	Ok(Arc::new(new_room_state))
+	Ok(state)
 }
 
 #[implement(super::Service)]
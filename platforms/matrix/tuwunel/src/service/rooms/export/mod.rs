@@ -0,0 +1,190 @@
+//! Export of a room's current state and timeline as JSON, for archiving or for
+//! review by moderators. Exports can be pseudonymized for sharing outside the
+//! server; see [`Pseudonymizer`]. Exports made on behalf of a user only hold
+//! the events and media the room's history visibility lets them see.
+
+mod pseudonymize;
+
+use std::{
+	collections::BTreeSet,
+	sync::{Arc, OnceLock},
+};
+
+use futures::{StreamExt, TryStreamExt};
+use ring::hmac;
+use ruma::{OwnedMxcUri, OwnedRoomId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tuwunel_core::{Err, Result, implement, matrix::Event, utils::stream::TryIgnore};
+
+pub use self::pseudonymize::Pseudonymizer;
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+	key: OnceLock<hmac::Key>,
+}
+
+/// Version of the export format written by `export_room`.
+pub const EXPORT_VERSION: u64 = 1;
+
+const SECRET: &[u8] = b"export_pseudonym_secret";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Export {
+	pub version: u64,
+	pub room_id: OwnedRoomId,
+
+	/// Whether user IDs were replaced by pseudonyms and profiles stripped.
+	pub pseudonymized: bool,
+
+	/// Current state events of the room.
+	pub state: Vec<JsonValue>,
+
+	/// Timeline events of the room, oldest first.
+	pub timeline: Vec<JsonValue>,
+
+	/// Media referenced by the exported events.
+	#[serde(default)]
+	pub media: Vec<OwnedMxcUri>,
+}
+
+#[derive(Debug, Default)]
+pub struct Options<'a> {
+	/// Replace user IDs by pseudonyms and strip display names and avatars.
+	pub pseudonymize: bool,
+
+	/// Mixed into the pseudonyms so that exports given to different parties
+	/// cannot be correlated with each other. Exports with the same salt, or
+	/// none, give each user the same pseudonym.
+	pub salt: Option<&'a str>,
+
+	/// Most recent number of timeline events to include; all when None.
+	pub limit: Option<usize>,
+
+	/// Only include the events this user could see by the room's history
+	/// visibility at the time of each event, and the media they reference.
+	pub visible_to: Option<&'a UserId>,
+}
+
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			key: OnceLock::new(),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Collects the current state and the timeline of a room known to the server.
+#[implement(Service)]
+pub async fn export_room(&self, room_id: &RoomId, options: &Options<'_>) -> Result<Export> {
+	if !self.services.metadata.exists(room_id).await {
+		return Err!(Request(NotFound("Room {room_id} is not known to this server.")));
+	}
+
+	let pseudonymizer = options
+		.pseudonymize
+		.then(|| self.pseudonymizer(options.salt));
+
+	let transform = |mut event: JsonValue| {
+		if let Some(pseudonymizer) = &pseudonymizer {
+			pseudonymizer.event(&mut event);
+		}
+
+		event
+	};
+
+	let state_visible = match options.visible_to {
+		| Some(user_id) =>
+			self.services
+				.state_accessor
+				.user_can_see_state_events(user_id, room_id)
+				.await,
+		| None => true,
+	};
+
+	let state: Vec<_> = if state_visible {
+		self.services
+			.state_accessor
+			.room_state_full_pdus(room_id)
+			.map_ok(Event::into_value)
+			.map_ok(transform)
+			.try_collect()
+			.await?
+	} else {
+		Vec::new()
+	};
+
+	let mut timeline: Vec<_> = self
+		.services
+		.timeline
+		.pdus_rev(None, room_id, None)
+		.ignore_err()
+		.filter_map(async |(_, pdu)| match options.visible_to {
+			| Some(user_id) => self
+				.services
+				.state_accessor
+				.user_can_see_event(user_id, room_id, pdu.event_id())
+				.await
+				.then_some(pdu),
+			| None => Some(pdu),
+		})
+		.take(options.limit.unwrap_or(usize::MAX))
+		.map(Event::into_value)
+		.collect()
+		.await;
+
+	timeline.reverse();
+	let timeline: Vec<_> = timeline.into_iter().map(transform).collect();
+
+	let mut media = BTreeSet::new();
+	state
+		.iter()
+		.chain(timeline.iter())
+		.for_each(|event| collect_media(event, &mut media));
+
+	Ok(Export {
+		version: EXPORT_VERSION,
+		room_id: room_id.to_owned(),
+		pseudonymized: pseudonymizer.is_some(),
+		state,
+		timeline,
+		media: media.into_iter().collect(),
+	})
+}
+
+/// Adds the `mxc://` URIs found anywhere in an event to `media`.
+fn collect_media(value: &JsonValue, media: &mut BTreeSet<OwnedMxcUri>) {
+	match value {
+		| JsonValue::String(s) if s.starts_with("mxc://") => {
+			media.insert(s.as_str().into());
+		},
+		| JsonValue::Array(values) => values
+			.iter()
+			.for_each(|value| collect_media(value, media)),
+		| JsonValue::Object(map) => map
+			.values()
+			.for_each(|value| collect_media(value, media)),
+		| _ => {},
+	}
+}
+
+/// Pseudonymizer keyed by the server's secret and the optional salt.
+#[implement(Service)]
+#[must_use]
+pub fn pseudonymizer(&self, salt: Option<&str>) -> Pseudonymizer {
+	let key = self.key.get_or_init(|| {
+		let secret = self.services.globals.db.secret(SECRET);
+		hmac::Key::new(hmac::HMAC_SHA256, &secret)
+	});
+
+	match salt {
+		| None => Pseudonymizer::new(key.clone()),
+		| Some(salt) => {
+			let salted = hmac::sign(key, salt.as_bytes());
+			Pseudonymizer::new(hmac::Key::new(hmac::HMAC_SHA256, salted.as_ref()))
+		},
+	}
+}
@@ -0,0 +1,178 @@
+use std::fmt::Write;
+
+use ring::hmac;
+use ruma::{OwnedUserId, UserId};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+
+/// Number of bytes of the HMAC kept in a pseudonym.
+const PSEUDONYM_LEN: usize = 10;
+
+/// Profile fields stripped from membership events.
+const PROFILE_KEYS: [&str; 3] = ["displayname", "avatar_url", "xyz.amorgan.blurhash"];
+
+/// Replaces user IDs in events by pseudonyms derived from an HMAC of the user
+/// ID, so the same user has the same pseudonym throughout an export and across
+/// exports made with the same key. The server name is kept so moderators can
+/// still tell where users come from. Display names and avatars are stripped
+/// from membership events, including those in `unsigned.prev_content`.
+///
+/// Only whole strings and object keys which are user IDs are replaced; user
+/// IDs or names mentioned within message bodies are left as written.
+pub struct Pseudonymizer {
+	key: hmac::Key,
+}
+
+impl Pseudonymizer {
+	#[must_use]
+	pub fn new(key: hmac::Key) -> Self { Self { key } }
+
+	/// Pseudonym of a user, e.g. `@anon_1f2e3d4c5b6a79880716:example.com`.
+	#[must_use]
+	pub fn user_id(&self, user_id: &UserId) -> OwnedUserId {
+		let mac = hmac::sign(&self.key, user_id.as_bytes());
+		let mut localpart = String::from("anon_");
+		for byte in &mac.as_ref()[..PSEUDONYM_LEN] {
+			write!(localpart, "{byte:02x}").expect("writing to String");
+		}
+
+		UserId::parse(format!("@{localpart}:{}", user_id.server_name()))
+			.expect("pseudonym is a valid user ID")
+	}
+
+	/// Pseudonymizes an event in its client or federation format.
+	pub fn event(&self, event: &mut JsonValue) {
+		let Some(object) = event.as_object_mut() else {
+			return;
+		};
+
+		if object.get("type").and_then(JsonValue::as_str) == Some("m.room.member") {
+			strip_profile(object.get_mut("content"));
+			strip_profile(
+				object
+					.get_mut("unsigned")
+					.and_then(|unsigned| unsigned.get_mut("prev_content")),
+			);
+		}
+
+		self.value(event);
+	}
+
+	fn value(&self, value: &mut JsonValue) {
+		match value {
+			| JsonValue::String(string) =>
+				if let Some(user_id) = self.parse(string) {
+					*string = user_id.into();
+				},
+			| JsonValue::Array(array) => array
+				.iter_mut()
+				.for_each(|value| self.value(value)),
+			| JsonValue::Object(object) => self.object(object),
+			| _ => {},
+		}
+	}
+
+	fn object(&self, object: &mut JsonMap<String, JsonValue>) {
+		let keys: Vec<_> = object
+			.keys()
+			.filter(|key| key.starts_with('@'))
+			.cloned()
+			.collect();
+
+		for key in keys {
+			if let Some(user_id) = self.parse(&key) {
+				let value = object.remove(&key).expect("key is present");
+				object.insert(user_id.into(), value);
+			}
+		}
+
+		object
+			.values_mut()
+			.for_each(|value| self.value(value));
+	}
+
+	fn parse(&self, string: &str) -> Option<OwnedUserId> {
+		string
+			.starts_with('@')
+			.then(|| UserId::parse(string).ok())
+			.flatten()
+			.map(|user_id| self.user_id(&user_id))
+	}
+}
+
+fn strip_profile(content: Option<&mut JsonValue>) {
+	if let Some(content) = content.and_then(JsonValue::as_object_mut) {
+		for key in PROFILE_KEYS {
+			content.remove(key);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::user_id;
+	use serde_json::json;
+
+	use super::*;
+
+	fn pseudonymizer(secret: &[u8]) -> Pseudonymizer {
+		Pseudonymizer::new(hmac::Key::new(hmac::HMAC_SHA256, secret))
+	}
+
+	#[test]
+	fn pseudonyms_are_deterministic_per_key() {
+		let alice = user_id!("@alice:example.com");
+		let a = pseudonymizer(b"a");
+
+		assert_eq!(a.user_id(alice), a.user_id(alice));
+		assert_ne!(a.user_id(alice), a.user_id(user_id!("@bob:example.com")));
+		assert_ne!(a.user_id(alice), pseudonymizer(b"b").user_id(alice));
+		assert_eq!(a.user_id(alice).server_name(), alice.server_name());
+	}
+
+	#[test]
+	fn member_event_is_pseudonymized() {
+		let p = pseudonymizer(b"secret");
+		let alice = user_id!("@alice:example.com");
+		let mut event = json!({
+			"type": "m.room.member",
+			"sender": "@alice:example.com",
+			"state_key": "@alice:example.com",
+			"content": {
+				"membership": "join",
+				"displayname": "Alice",
+				"avatar_url": "mxc://example.com/alice",
+			},
+			"unsigned": {
+				"prev_content": { "membership": "invite", "displayname": "Alice" },
+			},
+		});
+
+		p.event(&mut event);
+
+		let pseudonym = p.user_id(alice);
+		assert_eq!(event["sender"], pseudonym.as_str());
+		assert_eq!(event["state_key"], pseudonym.as_str());
+		assert_eq!(event["content"], json!({ "membership": "join" }));
+		assert_eq!(event["unsigned"]["prev_content"], json!({ "membership": "invite" }));
+	}
+
+	#[test]
+	fn user_id_keys_are_pseudonymized() {
+		let p = pseudonymizer(b"secret");
+		let mut event = json!({
+			"type": "m.room.power_levels",
+			"sender": "@alice:example.com",
+			"content": { "users": { "@alice:example.com": 100 } },
+		});
+
+		p.event(&mut event);
+
+		let pseudonym = p.user_id(user_id!("@alice:example.com"));
+		assert_eq!(event["content"]["users"][pseudonym.as_str()], 100);
+		assert!(
+			event["content"]["users"]
+				.get("@alice:example.com")
+				.is_none()
+		);
+	}
+}
@@ -1,4 +1,8 @@
 //! Lazy Loading
+//!
+//! Senders witnessed by a device are recorded so that their membership is sent
+//! once. For sync, the membership events sent to each device in a room are
+//! recorded too, so a sender's membership is sent again only once it changed.
 
 use std::{collections::HashSet, sync::Arc};
 
@@ -6,6 +10,7 @@
 use ruma::{DeviceId, OwnedUserId, RoomId, UserId, api::client::filter::LazyLoadOptions};
 use tuwunel_core::{
 	Result, implement,
+	matrix::ShortEventId,
 	utils::{IterStream, ReadyExt, stream::TryIgnore},
 };
 use tuwunel_database::{Database, Deserialized, Handle, Interfix, Map, Qry};
@@ -16,6 +21,7 @@ pub struct Service {
 
 struct Data {
 	lazyloadedids: Arc<Map>,
+	lazyloadedshorteventids: Arc<Map>,
 	db: Arc<Database>,
 }
 
@@ -41,12 +47,14 @@ pub enum Status {
 
 pub type Witness = HashSet<OwnedUserId>;
 type Key<'a> = (&'a UserId, Option<&'a DeviceId>, &'a RoomId, &'a UserId);
+type SentKey<'a> = (&'a UserId, Option<&'a DeviceId>, &'a RoomId, ShortEventId);
 
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			db: Data {
 				lazyloadedids: args.db["lazyloadedids"].clone(),
+				lazyloadedshorteventids: args.db["lazyloadedshorteventids"].clone(),
 				db: args.db.clone(),
 			},
 		}))
@@ -65,6 +73,65 @@ pub async fn reset(&self, ctx: &Context<'_>) {
 		.ignore_err()
 		.ready_for_each(|key| self.db.lazyloadedids.remove(key))
 		.await;
+
+	self.db
+		.lazyloadedshorteventids
+		.keys_prefix_raw(&prefix)
+		.ignore_err()
+		.ready_for_each(|key| self.db.lazyloadedshorteventids.remove(key))
+		.await;
+}
+
+/// Retains the membership events not yet sent to the device, recording them
+/// as sent at `ctx.token`. Those recorded at `ctx.token` are retained too, as
+/// the response which carried them may not have reached the client. All are
+/// retained when the options include redundant members.
+#[implement(Service)]
+#[tracing::instrument(name = "retain_unsent", level = "debug", skip_all)]
+pub async fn retain_unsent(
+	&self,
+	shorteventids: Vec<ShortEventId>,
+	ctx: &Context<'_>,
+) -> Vec<ShortEventId> {
+	let include_redundant = cfg!(feature = "element_hacks")
+		|| ctx
+			.options
+			.is_some_and(Options::include_redundant_members);
+
+	let make_key = |shorteventid: ShortEventId| -> SentKey<'_> {
+		(ctx.user_id, ctx.device_id, ctx.room_id, shorteventid)
+	};
+
+	let sent = shorteventids
+		.iter()
+		.copied()
+		.stream()
+		.map(make_key)
+		.qry(&self.db.lazyloadedshorteventids)
+		.map(into_status)
+		.zip(shorteventids.iter().copied().stream());
+
+	pin_mut!(sent);
+	let _cork = self.db.db.cork();
+	let mut unsent = Vec::with_capacity(shorteventids.len());
+	while let Some((status, shorteventid)) = sent.next().await {
+		let resend = match status {
+			| Status::Unseen => true,
+			| Status::Seen(seen) => seen == 0 || ctx.token == Some(seen),
+		};
+
+		if matches!(status, Status::Unseen | Status::Seen(0)) {
+			self.db
+				.lazyloadedshorteventids
+				.put_aput::<8, _, _>(make_key(shorteventid), ctx.token.unwrap_or(0_u64));
+		}
+
+		if include_redundant || resend {
+			unsent.push(shorteventid);
+		}
+	}
+
+	unsent
 }
 
 #[implement(Service)]
@@ -1,18 +1,22 @@
 pub mod alias;
 pub mod auth_chain;
 pub mod delete;
+pub mod desired_state;
 pub mod directory;
 pub mod event_handler;
+pub mod export;
 pub mod lazy_loading;
 pub mod metadata;
 pub mod pdu_metadata;
 pub mod read_receipt;
 pub mod retention;
 pub mod search;
+pub mod share_links;
 pub mod short;
 pub mod spaces;
 pub mod state;
 pub mod state_accessor;
+pub mod state_bloat;
 pub mod state_cache;
 pub mod state_compressor;
 pub mod threads;
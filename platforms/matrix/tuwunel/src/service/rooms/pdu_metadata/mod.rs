@@ -60,6 +60,39 @@ pub fn add_relation(&self, from: PduCount, to: PduCount) {
 	}
 }
 
+/// Removes the relation of the event at `from` to the event at `to`.
+#[implement(Service)]
+#[tracing::instrument(skip(self, from, to), level = "debug")]
+pub fn delete_relation(&self, from: PduCount, to: PduCount) {
+	if let (PduCount::Normal(_), PduCount::Normal(_)) = (from, to) {
+		let mut key = ArrayVec::<u8, 16>::new();
+		key.extend(to.to_be_bytes());
+		key.extend(from.to_be_bytes());
+		self.db.tofrom_relation.remove(key.as_slice());
+	}
+}
+
+/// Removes the relations of every event to the event at `to`.
+#[implement(Service)]
+#[tracing::instrument(skip(self, to), level = "debug")]
+pub async fn delete_relations_to(&self, to: PduCount) {
+	if !matches!(to, PduCount::Normal(_)) {
+		return;
+	}
+
+	let prefix = to.to_be_bytes();
+	self.db
+		.tofrom_relation
+		.raw_keys_from(&prefix)
+		.ignore_err()
+		.ready_take_while(|key| key.starts_with(&prefix))
+		.ready_for_each(|key| {
+			trace!(?key, "Removing key");
+			self.db.tofrom_relation.remove(key);
+		})
+		.await;
+}
+
 #[implement(Service)]
 pub fn get_relations<'a>(
 	&'a self,
@@ -126,6 +159,12 @@ pub fn mark_as_referenced<'a, I>(&self, room_id: &RoomId, event_ids: I)
 	}
 }
 
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub fn unmark_as_referenced(&self, room_id: &RoomId, event_id: &EventId) {
+	self.db.referencedevents.del((room_id, event_id));
+}
+
 #[implement(Service)]
 #[tracing::instrument(skip(self), level = "debug")]
 pub async fn is_event_referenced(&self, room_id: &RoomId, event_id: &EventId) -> bool {
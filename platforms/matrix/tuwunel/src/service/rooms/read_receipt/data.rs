@@ -1,8 +1,8 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use futures::{Stream, StreamExt};
 use ruma::{
-	CanonicalJsonObject, RoomId, UserId,
+	CanonicalJsonObject, CanonicalJsonValue, EventId, OwnedEventId, RoomId, UserId,
 	events::{AnySyncEphemeralRoomEvent, receipt::ReceiptEvent},
 	serde::Raw,
 };
@@ -107,6 +107,56 @@ pub(super) async fn last_receipt_count<'a>(
 			.ok_or_else(|| err!(Request(NotFound("No receipts found in room"))))
 	}
 
+	pub(super) async fn receipted_events(&self, room_id: &RoomId) -> HashSet<OwnedEventId> {
+		type KeyVal<'a> = ((&'a RoomId, u64, &'a UserId), CanonicalJsonObject);
+
+		let prefix = (room_id, Interfix);
+		self.readreceiptid_readreceipt
+			.stream_prefix(&prefix)
+			.ignore_err()
+			.ready_fold(HashSet::new(), |mut event_ids, (_, json): KeyVal<'_>| {
+				let content = json
+					.get("content")
+					.and_then(CanonicalJsonValue::as_object);
+
+				event_ids.extend(
+					content
+						.into_iter()
+						.flat_map(|content| content.keys())
+						.filter_map(|event_id| OwnedEventId::try_from(event_id.as_str()).ok()),
+				);
+
+				event_ids
+			})
+			.await
+	}
+
+	pub(super) async fn delete_receipts_to(
+		&self,
+		room_id: &RoomId,
+		event_ids: &HashSet<OwnedEventId>,
+	) {
+		type Key<'a> = (&'a RoomId, u64, &'a UserId);
+		type KeyVal<'a> = (Key<'a>, CanonicalJsonObject);
+
+		let prefix = (room_id, Interfix);
+		self.readreceiptid_readreceipt
+			.stream_prefix(&prefix)
+			.ignore_err()
+			.ready_filter(|(_, json): &KeyVal<'_>| {
+				json.get("content")
+					.and_then(CanonicalJsonValue::as_object)
+					.is_some_and(|content| {
+						content.keys().any(|event_id| {
+							<&EventId>::try_from(event_id.as_str())
+								.is_ok_and(|event_id| event_ids.contains(event_id))
+						})
+					})
+			})
+			.ready_for_each(|(key, _): KeyVal<'_>| self.readreceiptid_readreceipt.del(key))
+			.await;
+	}
+
 	#[inline]
 	pub(super) fn private_read_set(&self, room_id: &RoomId, user_id: &UserId, pdu_count: u64) {
 		let key = (room_id, user_id);
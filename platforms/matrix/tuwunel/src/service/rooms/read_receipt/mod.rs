@@ -1,6 +1,9 @@
 mod data;
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+	collections::{BTreeMap, HashSet},
+	sync::Arc,
+};
 
 use futures::{Stream, TryFutureExt, try_join};
 use ruma::{
@@ -179,6 +182,22 @@ pub async fn last_receipt_count(
 	pub async fn delete_all_read_receipts(&self, room_id: &RoomId) -> Result {
 		self.db.delete_all_read_receipts(room_id).await
 	}
+
+	/// Events of the room which read receipts point at.
+	pub async fn receipted_events(&self, room_id: &RoomId) -> HashSet<OwnedEventId> {
+		self.db.receipted_events(room_id).await
+	}
+
+	/// Deletes the read receipts pointing at any of the events.
+	pub async fn delete_receipts_to(&self, room_id: &RoomId, event_ids: &HashSet<OwnedEventId>) {
+		if event_ids.is_empty() {
+			return;
+		}
+
+		self.db
+			.delete_receipts_to(room_id, event_ids)
+			.await;
+	}
 }
 
 #[must_use]
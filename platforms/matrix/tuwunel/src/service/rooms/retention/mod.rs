@@ -1,3 +1,5 @@
+mod purge;
+
 use std::{
 	sync::Arc,
 	time::{Duration, SystemTime, UNIX_EPOCH},
@@ -10,12 +12,13 @@
 };
 use tuwunel_database::{Deserialized, Json, Map};
 
-use crate::rooms::timeline::RoomMutexGuard;
+use crate::{Heartbeat, jobs::Task, rooms::timeline::RoomMutexGuard};
 
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
 	eventid_originalpdu: Arc<Map>,
 	timeredacted_eventid: Arc<Map>,
+	heartbeat: Heartbeat,
 }
 
 #[async_trait]
@@ -25,11 +28,13 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 			services: args.services.clone(),
 			eventid_originalpdu: args.db["eventid_originalpdu"].clone(),
 			timeredacted_eventid: args.db["timeredacted_eventid"].clone(),
+			heartbeat: Heartbeat::default(),
 		}))
 	}
 
 	async fn worker(self: Arc<Self>) -> Result {
 		loop {
+			self.heartbeat.beat();
 			let retention_seconds = self.services.config.redaction_retention_seconds;
 
 			if retention_seconds != 0 {
@@ -48,6 +53,7 @@ async fn worker(self: Arc<Self>) -> Result {
 						Ok(expected!(time_redacted + retention_seconds) < now)
 					})
 					.ready_try_fold_default(|count: usize, (time_redacted, event_id)| {
+						self.heartbeat.beat();
 						self.eventid_originalpdu.remove(event_id);
 						self.timeredacted_eventid
 							.del((time_redacted, event_id));
@@ -58,13 +64,24 @@ async fn worker(self: Arc<Self>) -> Result {
 				debug_info!(?count, "Finished cleaning up retained events");
 			}
 
+			if self.services.config.room_retention {
+				self.services
+					.jobs
+					.enqueue_unique(Task::RetentionPurge)
+					.await;
+			}
+
 			tokio::select! {
-				() = tokio::time::sleep(Duration::from_secs(60 * 60)) => {},
+				() = self.heartbeat.sleep(Duration::from_secs(60 * 60)) => {},
 				() = self.services.server.until_shutdown() => return Ok(())
 			};
 		}
 	}
 
+	fn heartbeats(&self) -> &[Heartbeat] { std::slice::from_ref(&self.heartbeat) }
+
+	fn background(&self) -> bool { true }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
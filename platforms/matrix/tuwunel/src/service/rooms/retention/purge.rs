@@ -0,0 +1,127 @@
+//! Purging of messages past the retention policy of their room, per the
+//! `m.room.retention` state event of MSC1763 or the server default.
+
+use std::time::{Duration, SystemTime};
+
+use futures::StreamExt;
+use ruma::{MilliSecondsSinceUnixEpoch, RoomId, events::StateEventType};
+use serde::Deserialize;
+use tuwunel_core::{
+	Result, at, debug, err, implement,
+	matrix::{
+		Event,
+		pdu::{PduId, RawPduId},
+	},
+	warn,
+};
+
+/// Number of messages purged under one hold of the room's state lock.
+const PURGE_BATCH: usize = 1024;
+
+#[derive(Debug, Deserialize)]
+struct RoomRetentionContent {
+	/// Milliseconds.
+	max_lifetime: Option<u64>,
+}
+
+/// Lifetime of messages in a room: its `m.room.retention` policy, otherwise
+/// the server default, and never below the server minimum. None when its
+/// messages are kept forever.
+#[implement(super::Service)]
+pub async fn max_lifetime(&self, room_id: &RoomId) -> Option<Duration> {
+	let config = &self.services.config;
+	let default = (config.room_retention_default_max_lifetime > 0)
+		.then(|| Duration::from_secs(config.room_retention_default_max_lifetime));
+
+	let max_lifetime = self
+		.services
+		.state_accessor
+		.room_state_get_content(room_id, &StateEventType::from("m.room.retention"), "")
+		.await
+		.ok()
+		.and_then(|content: RoomRetentionContent| content.max_lifetime)
+		.map(Duration::from_millis)
+		.or(default)?;
+
+	Some(max_lifetime.max(Duration::from_secs(config.room_retention_min_lifetime)))
+}
+
+/// Purges the messages of a room older than its lifetime. State events and the
+/// latest event of the room are kept. Messages are visited in timeline order
+/// until past those older than the lifetime. Returns the number purged.
+#[implement(super::Service)]
+pub async fn purge_room(&self, room_id: &RoomId) -> Result<usize> {
+	let Some(max_lifetime) = self.max_lifetime(room_id).await else {
+		return Ok(0);
+	};
+
+	let cutoff = SystemTime::now()
+		.checked_sub(max_lifetime)
+		.and_then(MilliSecondsSinceUnixEpoch::from_system_time)
+		.ok_or_else(|| err!("Retention lifetime {max_lifetime:?} out of range."))?;
+
+	let shortroomid = self
+		.services
+		.short
+		.get_shortroomid(room_id)
+		.await?;
+	let latest = self
+		.services
+		.timeline
+		.latest_pdu_in_room(room_id)
+		.await?;
+
+	let (mut purged, mut from) = (0_usize, None);
+	loop {
+		let state_lock = self.services.state.mutex.lock(room_id).await;
+		let (batch, next) = self
+			.services
+			.timeline
+			.pdus_before(room_id, cutoff, from, PURGE_BATCH)
+			.await;
+
+		let batch: Vec<_> = batch
+			.into_iter()
+			.filter(|(_, pdu)| pdu.state_key().is_none() && pdu.event_id() != latest.event_id())
+			.collect();
+
+		for (count, pdu) in &batch {
+			let pdu_id: RawPduId = PduId { shortroomid, count: *count }.into();
+			self.services
+				.timeline
+				.purge_pdu(shortroomid, &pdu_id, pdu, &state_lock)
+				.await?;
+		}
+
+		self.services
+			.timeline
+			.purge_notifications(room_id, batch.iter().map(at!(0)))
+			.await;
+
+		purged = purged.saturating_add(batch.len());
+		from = next;
+		if from.is_none() {
+			break;
+		}
+	}
+
+	debug!(%room_id, ?max_lifetime, purged, "Purged messages past retention");
+	Ok(purged)
+}
+
+/// Purges the messages of every room older than its lifetime. Returns the
+/// number purged.
+#[implement(super::Service)]
+pub async fn purge_all(&self) -> usize {
+	self.services
+		.metadata
+		.iter_ids()
+		.fold(0_usize, async |purged, room_id| match self.purge_room(room_id).await {
+			| Ok(count) => purged.saturating_add(count),
+			| Err(e) => {
+				warn!(%room_id, "Failed to purge messages past retention: {e}");
+				purged
+			},
+		})
+		.await
+}
@@ -0,0 +1,326 @@
+//! Expiring links granting entry to invite-only rooms.
+//!
+//! A moderator mints a link for a room; whoever presents its token is invited
+//! on the moderator's behalf while the link is unexpired, unrevoked and under
+//! its usage cap. Tokens are the link id followed by an HMAC of it, so forged
+//! tokens are rejected before the database is consulted. Revoked and expired
+//! links are kept along with their redemptions for auditing.
+
+use std::{
+	sync::{Arc, OnceLock},
+	time::{Duration, SystemTime},
+};
+
+use futures::{Stream, StreamExt};
+use ring::hmac;
+use ruma::{
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
+	events::room::member::MembershipState,
+	serde::{Base64, base64::UrlSafe},
+};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{
+	Err, Result, err, implement,
+	utils::{self, MutexMap, stream::TryIgnore, time::now_millis},
+};
+use tuwunel_database::{Deserialized, Ignore, Interfix, Json, Map};
+
+pub struct Service {
+	db: Data,
+	services: Arc<crate::services::OnceServices>,
+	redeem_mutex: MutexMap<String, ()>,
+	key: OnceLock<hmac::Key>,
+}
+
+struct Data {
+	roomid_sharelinkid: Arc<Map>,
+	sharelinkid_redemption: Arc<Map>,
+	sharelinkid_sharelink: Arc<Map>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShareLink {
+	/// Room the link grants entry to.
+	pub room_id: OwnedRoomId,
+
+	/// Moderator which minted the link; invites are sent on their behalf.
+	pub creator: OwnedUserId,
+
+	pub created_at: SystemTime,
+
+	pub expires_at: Option<SystemTime>,
+
+	/// Number of redemptions after which the link stops working.
+	pub max_uses: Option<u64>,
+
+	/// Number of times the link has been redeemed.
+	pub uses: u64,
+
+	pub revoked_at: Option<SystemTime>,
+}
+
+/// A user which entered a room through a link.
+#[derive(Clone, Debug)]
+pub struct Redemption {
+	pub user_id: OwnedUserId,
+
+	/// Milliseconds since the unix epoch.
+	pub redeemed_at: u64,
+}
+
+/// Length of generated link ids.
+const ID_LENGTH: usize = 24;
+
+/// Global key the signing secret is stored under.
+const SECRET: &[u8] = b"share_link_secret";
+
+const SEP: char = '.';
+
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				roomid_sharelinkid: args.db["roomid_sharelinkid"].clone(),
+				sharelinkid_redemption: args.db["sharelinkid_redemption"].clone(),
+				sharelinkid_sharelink: args.db["sharelinkid_sharelink"].clone(),
+			},
+			services: args.services.clone(),
+			redeem_mutex: MutexMap::new(),
+			key: OnceLock::new(),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl ShareLink {
+	/// Whether the link can still be redeemed.
+	#[must_use]
+	pub fn is_valid(&self) -> bool {
+		self.revoked_at.is_none()
+			&& self
+				.expires_at
+				.is_none_or(|expires_at| SystemTime::now() < expires_at)
+			&& self
+				.max_uses
+				.is_none_or(|max_uses| self.uses < max_uses)
+	}
+}
+
+/// Mint a link to `room_id`. The creator must be joined to the room with the
+/// power to invite. Returns the link id and the token to share.
+#[implement(Service)]
+pub async fn create(
+	&self,
+	room_id: &RoomId,
+	creator: &UserId,
+	expires_in: Option<Duration>,
+	max_uses: Option<u64>,
+) -> Result<(String, String)> {
+	self.check_moderator(room_id, creator).await?;
+
+	if max_uses == Some(0) {
+		return Err!(Request(InvalidParam("max_uses must be greater than zero.")));
+	}
+
+	let created_at = SystemTime::now();
+	let expires_at = expires_in
+		.map(|expires_in| {
+			created_at
+				.checked_add(expires_in)
+				.ok_or_else(|| err!(Request(InvalidParam("Expiration is too far away."))))
+		})
+		.transpose()?;
+
+	let link = ShareLink {
+		room_id: room_id.to_owned(),
+		creator: creator.to_owned(),
+		created_at,
+		expires_at,
+		max_uses,
+		uses: 0,
+		revoked_at: None,
+	};
+
+	let id = utils::random_string(ID_LENGTH);
+	self.db
+		.sharelinkid_sharelink
+		.raw_put(&id, Json(&link));
+
+	self.db
+		.roomid_sharelinkid
+		.put_raw((room_id, &id), []);
+
+	let token = self.token(&id);
+
+	Ok((id, token))
+}
+
+/// Revoke a link. The link remains listed with its redemptions.
+#[implement(Service)]
+pub async fn revoke(&self, room_id: &RoomId, id: &str) -> Result {
+	let _lock = self.redeem_mutex.lock(id).await;
+	let mut link = self.get(id).await?;
+	if link.room_id != room_id {
+		return Err!(Request(NotFound("Share link not found.")));
+	}
+
+	if link.revoked_at.is_none() {
+		link.revoked_at = Some(SystemTime::now());
+		self.db
+			.sharelinkid_sharelink
+			.raw_put(id, Json(&link));
+	}
+
+	Ok(())
+}
+
+/// Invite `user_id` to the room of the link identified by `token` on behalf of
+/// the link's creator, counting a use of the link. Users already invited or
+/// joined are admitted without counting a use. Returns the room.
+#[implement(Service)]
+pub async fn redeem(&self, token: &str, user_id: &UserId) -> Result<OwnedRoomId> {
+	let id = self.verify(token)?;
+
+	let _lock = self.redeem_mutex.lock(id).await;
+	let mut link = self.get(id).await?;
+	if !link.is_valid() {
+		return Err!(Request(Forbidden("This share link has expired or been revoked.")));
+	}
+
+	let membership = self
+		.services
+		.state_accessor
+		.get_member(&link.room_id, user_id)
+		.await
+		.map(|content| content.membership);
+
+	match membership {
+		| Ok(MembershipState::Invite | MembershipState::Join) => return Ok(link.room_id),
+		| Ok(MembershipState::Ban) => {
+			return Err!(Request(Forbidden("You are banned from the room.")));
+		},
+		| _ => {},
+	}
+
+	// The creator's authority to invite is re-checked by the invite itself.
+	let reason = String::from("Invited with a share link");
+	self.services
+		.membership
+		.invite(&link.creator, user_id, &link.room_id, Some(&reason), false)
+		.await?;
+
+	link.uses = link.uses.saturating_add(1);
+	self.db
+		.sharelinkid_sharelink
+		.raw_put(id, Json(&link));
+
+	self.db
+		.sharelinkid_redemption
+		.put_raw((id, now_millis(), user_id), []);
+
+	Ok(link.room_id)
+}
+
+/// Fail unless `user_id` is joined to the room with the power to invite.
+#[implement(Service)]
+pub async fn check_moderator(&self, room_id: &RoomId, user_id: &UserId) -> Result {
+	if !self
+		.services
+		.state_cache
+		.is_joined(user_id, room_id)
+		.await
+	{
+		return Err!(Request(Forbidden("You are not joined to this room.")));
+	}
+
+	let power_levels = self
+		.services
+		.state_accessor
+		.get_power_levels(room_id)
+		.await?;
+
+	if power_levels.for_user(user_id) < power_levels.invite {
+		return Err!(Request(Forbidden("You do not have permission to invite to this room.")));
+	}
+
+	Ok(())
+}
+
+/// The room a token grants entry to, whether or not the link is still valid.
+#[implement(Service)]
+pub async fn room_id(&self, token: &str) -> Result<OwnedRoomId> {
+	let id = self.verify(token)?;
+
+	self.get(id).await.map(|link| link.room_id)
+}
+
+#[implement(Service)]
+pub async fn get(&self, id: &str) -> Result<ShareLink> {
+	self.db
+		.sharelinkid_sharelink
+		.get(id)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(NotFound("Share link not found."))))
+}
+
+/// Links minted for a room, including revoked and expired ones.
+#[implement(Service)]
+pub fn room_links<'a>(
+	&'a self,
+	room_id: &'a RoomId,
+) -> impl Stream<Item = (String, ShareLink)> + Send + 'a {
+	self.db
+		.roomid_sharelinkid
+		.keys_prefix(&(room_id, Interfix))
+		.ignore_err()
+		.map(|(_, id): (Ignore, &str)| id.to_owned())
+		.filter_map(async |id| {
+			let link = self.get(&id).await.ok()?;
+			Some((id, link))
+		})
+}
+
+/// Users which redeemed a link, oldest first.
+#[implement(Service)]
+pub fn redemptions<'a>(&'a self, id: &'a str) -> impl Stream<Item = Redemption> + Send + 'a {
+	self.db
+		.sharelinkid_redemption
+		.keys_prefix(&(id, Interfix))
+		.ignore_err()
+		.map(|(_, redeemed_at, user_id): (Ignore, u64, &UserId)| Redemption {
+			user_id: user_id.to_owned(),
+			redeemed_at,
+		})
+}
+
+/// The token to share for a link.
+#[implement(Service)]
+#[must_use]
+pub fn token(&self, id: &str) -> String {
+	let mac = hmac::sign(self.key(), id.as_bytes());
+	let mac = Base64::<UrlSafe, _>::new(mac.as_ref()).encode();
+
+	format!("{id}{SEP}{mac}")
+}
+
+/// Check the signature of a token, returning the link id it carries.
+#[implement(Service)]
+fn verify<'a>(&self, token: &'a str) -> Result<&'a str> {
+	let invalid = || err!(Request(Forbidden("Invalid share link.")));
+
+	let (id, mac) = token.split_once(SEP).ok_or_else(invalid)?;
+	let mac = Base64::<UrlSafe>::parse(mac).map_err(|_| invalid())?;
+	hmac::verify(self.key(), id.as_bytes(), mac.as_bytes()).map_err(|_| invalid())?;
+
+	Ok(id)
+}
+
+#[implement(Service)]
+fn key(&self) -> &hmac::Key {
+	self.key.get_or_init(|| {
+		let secret = self.services.globals.db.secret(SECRET);
+		hmac::Key::new(hmac::HMAC_SHA256, &secret)
+	})
+}
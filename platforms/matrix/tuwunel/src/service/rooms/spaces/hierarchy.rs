@@ -0,0 +1,368 @@
+//! Space hierarchies flattened into the depth-first order in which their rooms
+//! are returned to clients. They are cached per `(space, suggested_only,
+//! max_depth)` and kept for each paginated request, so that its later pages
+//! follow the same hierarchy while the space changes.
+
+use std::{
+	collections::{HashMap, HashSet},
+	sync::{Arc, atomic::Ordering},
+	time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use ruma::{
+	OwnedRoomId, OwnedServerName, RoomId, UInt, UserId,
+	api::{
+		client::space::SpaceHierarchyRoomsChunk, federation::space::SpaceHierarchyParentSummary,
+	},
+	events::space::child::SpaceChildEventContent,
+};
+use tuwunel_core::{Err, Event, Result, implement, utils::stream::ReadyExt};
+
+use super::{PaginationToken, SummaryAccessibility, get_parent_children_via, summary_to_chunk};
+
+/// A space and the rooms below it, depth-first.
+pub struct Hierarchy {
+	rooms: Vec<Entry>,
+
+	/// When a hierarchy including remote rooms is to be built again; local
+	/// hierarchies are invalidated as their m.space.child state changes
+	/// instead.
+	expires: Option<Instant>,
+}
+
+struct Entry {
+	room_id: OwnedRoomId,
+	via: Vec<OwnedServerName>,
+
+	/// Index of the space this room is a child of; None for the root.
+	parent: Option<usize>,
+}
+
+pub(super) type HierarchyKey = (OwnedRoomId, bool, usize);
+
+/// Most rooms in a hierarchy; larger spaces are cut off.
+const MAX_ROOMS: usize = 10_000;
+
+/// Most remote spaces whose children are looked up in one walk. Below any
+/// further ones, only the summaries already cached are followed, so that one
+/// request cannot make the server query thousands of spaces over federation.
+const MAX_REMOTE_SPACES: usize = 64;
+
+/// Number of hierarchies cached.
+pub(super) const HIERARCHY_CACHE_CAPACITY: usize = 256;
+
+/// Number of hierarchies kept for paginated requests.
+pub(super) const SNAPSHOT_CAPACITY: usize = 1024;
+
+/// A page of the hierarchy of a space as seen by a user, and the token of the
+/// next page. Rooms the user cannot access are left out with the rooms below
+/// them.
+#[implement(super::Service)]
+pub async fn get_client_hierarchy(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	limit: usize,
+	max_depth: usize,
+	suggested_only: bool,
+	from: Option<&PaginationToken>,
+) -> Result<(Vec<SpaceHierarchyRoomsChunk>, Option<PaginationToken>)> {
+	let snapshot = match from {
+		| Some(token) => self
+			.snapshot(token.snapshot, room_id)
+			.await
+			.map(|hierarchy| (token.snapshot, hierarchy)),
+		| None => None,
+	};
+
+	let (snapshot, hierarchy) = match snapshot {
+		| Some((id, hierarchy)) => (Some(id), hierarchy),
+		| None => (
+			None,
+			self.hierarchy(room_id, suggested_only, max_depth)
+				.await,
+		),
+	};
+
+	let mut visible = HashMap::new();
+	match self
+		.visible(&hierarchy, 0, user_id, suggested_only, &mut visible)
+		.await
+	{
+		| Ok(Some(_)) => {},
+		| Ok(None) => return Err!(Request(Forbidden("The requested room is inaccessible"))),
+		| Err(_) => return Err!(Request(Forbidden("The requested room was not found"))),
+	}
+
+	let offset = from.map_or(0, |token| token.offset);
+	let mut rooms = Vec::with_capacity(limit);
+	let mut next = None;
+	for index in offset..hierarchy.rooms.len() {
+		if rooms.len() >= limit {
+			next = Some(index);
+			break;
+		}
+
+		if let Ok(Some(summary)) = self
+			.visible(&hierarchy, index, user_id, suggested_only, &mut visible)
+			.await
+		{
+			rooms.push(summary_to_chunk(summary));
+		}
+	}
+
+	let Some(offset) = next else {
+		return Ok((rooms, None));
+	};
+
+	let snapshot = match snapshot {
+		| Some(id) => id,
+		| None => self.snapshot_insert(hierarchy).await,
+	};
+
+	let token = PaginationToken {
+		snapshot,
+		offset,
+		limit: limit.try_into().unwrap_or(UInt::MAX),
+		max_depth: max_depth.try_into().unwrap_or(UInt::MAX),
+		suggested_only,
+	};
+
+	Ok((rooms, Some(token)))
+}
+
+/// The summary of the room at `index` when the user can access it and every
+/// space above it, memoized in `visible`. Fails when the summary of a room
+/// could not be found.
+#[implement(super::Service)]
+async fn visible(
+	&self,
+	hierarchy: &Hierarchy,
+	index: usize,
+	user_id: &UserId,
+	suggested_only: bool,
+	visible: &mut HashMap<usize, Option<SpaceHierarchyParentSummary>>,
+) -> Result<Option<SpaceHierarchyParentSummary>> {
+	let mut ancestry = vec![index];
+	while let Some(parent) = ancestry
+		.last()
+		.and_then(|&index| hierarchy.rooms[index].parent)
+	{
+		ancestry.push(parent);
+	}
+
+	for index in ancestry.into_iter().rev() {
+		if let Some(summary) = visible.get(&index) {
+			if summary.is_none() {
+				return Ok(None);
+			}
+
+			continue;
+		}
+
+		let Entry { room_id, via, .. } = &hierarchy.rooms[index];
+		let summary = match self
+			.get_summary_and_children_client(room_id, suggested_only, user_id, via)
+			.await?
+		{
+			| Some(SummaryAccessibility::Accessible(summary)) => Some(summary),
+			| Some(SummaryAccessibility::Inaccessible) => None,
+			| None if index == 0 => return Err!(Request(NotFound("Room not found."))),
+			| None => None,
+		};
+
+		let accessible = summary.is_some();
+		visible.insert(index, summary);
+		if !accessible {
+			return Ok(None);
+		}
+	}
+
+	Ok(visible.get(&index).cloned().flatten())
+}
+
+/// The hierarchy of a space, from the cache or built.
+#[implement(super::Service)]
+async fn hierarchy(
+	&self,
+	room_id: &RoomId,
+	suggested_only: bool,
+	max_depth: usize,
+) -> Arc<Hierarchy> {
+	let key: HierarchyKey = (room_id.to_owned(), suggested_only, max_depth);
+	if let Some(hierarchy) = self.cached_hierarchy(&key).await {
+		return hierarchy;
+	}
+
+	// Concurrent requests for the hierarchy wait for the one build; it may have
+	// been cached meanwhile.
+	let _lock = self.hierarchy_build_mutex.lock(&key).await;
+	if let Some(hierarchy) = self.cached_hierarchy(&key).await {
+		return hierarchy;
+	}
+
+	let hierarchy = Arc::new(
+		self.build_hierarchy(room_id, suggested_only, max_depth)
+			.await,
+	);
+
+	self.hierarchy_cache
+		.lock()
+		.await
+		.insert(key, hierarchy.clone());
+
+	hierarchy
+}
+
+#[implement(super::Service)]
+async fn cached_hierarchy(&self, key: &HierarchyKey) -> Option<Arc<Hierarchy>> {
+	self.hierarchy_cache
+		.lock()
+		.await
+		.get_mut(key)
+		.filter(|hierarchy| !hierarchy.expired())
+		.cloned()
+}
+
+/// Walks the space depth-first, regardless of which rooms any user can access,
+/// down to `max_depth` below it.
+#[implement(super::Service)]
+async fn build_hierarchy(
+	&self,
+	room_id: &RoomId,
+	suggested_only: bool,
+	max_depth: usize,
+) -> Hierarchy {
+	let via: Vec<_> = room_id
+		.server_name()
+		.map(ToOwned::to_owned)
+		.into_iter()
+		.collect();
+
+	let mut remote = 0_usize;
+	let mut rooms = Vec::new();
+	let mut visited = HashSet::from([room_id.to_owned()]);
+	let mut stack = vec![(room_id.to_owned(), via, None, 0_usize)];
+	while let Some((room_id, via, parent, depth)) = stack.pop() {
+		if rooms.len() >= MAX_ROOMS {
+			break;
+		}
+
+		let index = rooms.len();
+		if depth < max_depth {
+			let federation = remote < MAX_REMOTE_SPACES;
+			let (children, is_remote) = self
+				.children_of(&room_id, &via, suggested_only, federation)
+				.await;
+
+			remote = remote.saturating_add(is_remote.into());
+			stack.extend(
+				children
+					.into_iter()
+					.rev()
+					.filter(|(child, _)| visited.insert(child.clone()))
+					.map(|(child, via)| (child, via, Some(index), depth.saturating_add(1))),
+			);
+		}
+
+		rooms.push(Entry { room_id, via, parent });
+	}
+
+	let ttl = Duration::from_secs(self.services.config.space_hierarchy_remote_ttl);
+	Hierarchy {
+		rooms,
+		expires: (remote > 0)
+			.then(|| Instant::now().checked_add(ttl))
+			.flatten(),
+	}
+}
+
+/// The children of a space and the servers to reach them through, from its
+/// state when this server is in it, or else over federation unless
+/// `federation` is false, leaving only a cached summary. Also returns whether
+/// the space is remote.
+#[implement(super::Service)]
+async fn children_of(
+	&self,
+	room_id: &RoomId,
+	via: &[OwnedServerName],
+	suggested_only: bool,
+	federation: bool,
+) -> (Vec<(OwnedRoomId, Vec<OwnedServerName>)>, bool) {
+	let server_name = self.services.globals.server_name();
+	if self
+		.services
+		.state_cache
+		.server_in_room(server_name, room_id)
+		.await
+	{
+		let children = self
+			.get_space_child_events(room_id)
+			.ready_filter_map(|pdu| {
+				let content: SpaceChildEventContent = pdu.get_content().ok()?;
+				let child = OwnedRoomId::parse(pdu.state_key()?).ok()?;
+
+				(!suggested_only || content.suggested).then_some((child, content.via))
+			})
+			.collect()
+			.await;
+
+		return (children, false);
+	}
+
+	let summary = match self.cached_summary(room_id).await {
+		| Some(summary) => summary,
+		| None if federation =>
+			self.fetch_summary_federation(room_id, suggested_only, via)
+				.await,
+		| None => None,
+	};
+
+	let children = summary
+		.iter()
+		.flat_map(|summary| get_parent_children_via(summary, suggested_only))
+		.map(|(child, via)| (child, via.collect()))
+		.collect();
+
+	(children, true)
+}
+
+/// The hierarchy kept for a paginated request on the space, unless forgotten.
+#[implement(super::Service)]
+async fn snapshot(&self, id: u64, room_id: &RoomId) -> Option<Arc<Hierarchy>> {
+	self.snapshots
+		.lock()
+		.await
+		.get_mut(&id)
+		.filter(|hierarchy| {
+			hierarchy
+				.rooms
+				.first()
+				.is_some_and(|root| root.room_id == room_id)
+		})
+		.cloned()
+}
+
+/// Keeps a hierarchy for the following pages of a request. Returns its ID.
+#[implement(super::Service)]
+async fn snapshot_insert(&self, hierarchy: Arc<Hierarchy>) -> u64 {
+	let id = self.next_snapshot.fetch_add(1, Ordering::Relaxed);
+	self.snapshots.lock().await.insert(id, hierarchy);
+
+	id
+}
+
+impl Hierarchy {
+	/// Whether the room is in the hierarchy.
+	pub(super) fn contains(&self, room_id: &RoomId) -> bool {
+		self.rooms
+			.iter()
+			.any(|entry| entry.room_id == room_id)
+	}
+
+	fn expired(&self) -> bool {
+		self.expires
+			.is_some_and(|expires| expires <= Instant::now())
+	}
+}
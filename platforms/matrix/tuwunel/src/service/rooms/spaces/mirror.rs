@@ -0,0 +1,276 @@
+//! Mirroring the membership of spaces into their children. When a local user
+//! adds a room to a space, the members of the space are invited to it on their
+//! behalf; in rooms entered by knocking, the knocks of the members of the space
+//! are approved instead. The invites are sent by a background job at a limited
+//! rate. Members who decline such an invite are not invited to the rooms added
+//! to the space afterwards.
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use ruma::{
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
+	events::{
+		room::{join_rules::JoinRule, member::MembershipState},
+		space::child::SpaceChildEventContent,
+	},
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tuwunel_core::{
+	Result, debug, debug_warn, implement, matrix::Event, utils::stream::ReadyExt,
+};
+use tuwunel_database::{Deserialized, Json};
+
+use crate::jobs::Task;
+
+/// A room added to a space whose membership is mirrored into it.
+#[derive(Debug, Deserialize, Serialize)]
+struct MirroredChild {
+	space_id: OwnedRoomId,
+
+	/// Local user which added the room; invites are sent on their behalf.
+	inviter: OwnedUserId,
+}
+
+/// Queues the invites of the members of the space to a room added to it by a
+/// local user, and forgets rooms removed from it.
+#[implement(super::Service)]
+pub async fn mirror_child_changed<Pdu: Event>(&self, pdu: &Pdu) {
+	if !self.services.config.space_membership_mirroring {
+		return;
+	}
+
+	let Some(room_id) = pdu
+		.state_key()
+		.and_then(|state_key| RoomId::parse(state_key).ok())
+	else {
+		return;
+	};
+
+	let space_id = pdu.room_id();
+	let mirrored = self.mirrored_child(room_id).await;
+	let added = pdu
+		.get_content::<SpaceChildEventContent>()
+		.is_ok_and(|content| !content.via.is_empty());
+
+	if !added {
+		if mirrored.is_some_and(|child| child.space_id == space_id) {
+			self.db.roomid_mirrorspace.remove(room_id);
+		}
+
+		return;
+	}
+
+	// Already mirrored; the event only changed the order or suggestion of the
+	// room.
+	if mirrored.is_some_and(|child| child.space_id == space_id) {
+		return;
+	}
+
+	if !self.services.globals.user_is_local(pdu.sender()) {
+		return;
+	}
+
+	let child = MirroredChild {
+		space_id: space_id.to_owned(),
+		inviter: pdu.sender().to_owned(),
+	};
+
+	self.db
+		.roomid_mirrorspace
+		.raw_put(room_id, Json(&child));
+
+	self.services
+		.jobs
+		.enqueue(Task::MirrorSpaceMembers {
+			space_id: child.space_id,
+			room_id: room_id.to_owned(),
+			user_id: None,
+		});
+}
+
+/// Follows the membership of users in rooms whose space is mirrored: approves
+/// the knocks of members of the space, and records who declined an invite
+/// sent by mirroring.
+#[implement(super::Service)]
+pub async fn mirror_membership_changed(
+	&self,
+	room_id: &RoomId,
+	user_id: &UserId,
+	sender: &UserId,
+	membership: &MembershipState,
+) {
+	if !self.services.config.space_membership_mirroring {
+		return;
+	}
+
+	match membership {
+		| MembershipState::Knock => {
+			let Some(child) = self.mirrored_child(room_id).await else {
+				return;
+			};
+
+			if !self
+				.services
+				.state_cache
+				.is_joined(user_id, &child.space_id)
+				.await
+			{
+				return;
+			}
+
+			self.services
+				.jobs
+				.enqueue_unique(Task::MirrorSpaceMembers {
+					space_id: child.space_id,
+					room_id: room_id.to_owned(),
+					user_id: Some(user_id.to_owned()),
+				})
+				.await;
+		},
+		| MembershipState::Join => {
+			self.db
+				.roomuserid_mirrorinvite
+				.del((room_id, user_id));
+		},
+		| MembershipState::Leave if sender == user_id => {
+			let Ok(space_id) = self
+				.db
+				.roomuserid_mirrorinvite
+				.qry(&(room_id, user_id))
+				.await
+				.deserialized::<OwnedRoomId>()
+			else {
+				return;
+			};
+
+			debug!(%user_id, %room_id, %space_id, "Declined a mirrored space invite");
+			self.db
+				.spaceuserid_mirrordeclined
+				.put_raw((&space_id, user_id), []);
+
+			self.db
+				.roomuserid_mirrorinvite
+				.del((room_id, user_id));
+		},
+		| _ => {},
+	}
+}
+
+/// Invites the members of the space to a room added to it, or only `user_id`
+/// when given, waiting `space_membership_mirroring_interval_ms` between
+/// invites. In rooms entered by knocking only the members which knocked are
+/// invited. Returns the numbers of invites sent and failed.
+#[implement(super::Service)]
+pub async fn mirror_members(
+	&self,
+	space_id: &RoomId,
+	room_id: &RoomId,
+	user_id: Option<&UserId>,
+) -> Result<(usize, usize)> {
+	// Removed from the space since.
+	let Some(child) = self
+		.mirrored_child(room_id)
+		.await
+		.filter(|child| child.space_id == space_id)
+	else {
+		return Ok((0, 0));
+	};
+
+	let knock = matches!(
+		self.services
+			.state_accessor
+			.get_join_rules(room_id)
+			.await,
+		JoinRule::Knock | JoinRule::KnockRestricted(_)
+	);
+
+	let candidates: Vec<OwnedUserId> = match user_id {
+		| Some(user_id) => vec![user_id.to_owned()],
+		| None if knock =>
+			self.services
+				.state_cache
+				.room_members_knocked(room_id)
+				.map(ToOwned::to_owned)
+				.collect()
+				.await,
+		| None =>
+			self.services
+				.state_cache
+				.room_members(space_id)
+				.ready_filter(|member| *member != child.inviter)
+				.map(ToOwned::to_owned)
+				.collect()
+				.await,
+	};
+
+	let interval = Duration::from_millis(
+		self.services
+			.config
+			.space_membership_mirroring_interval_ms,
+	);
+
+	let (mut invited, mut failed) = (0_usize, 0_usize);
+	for user_id in candidates {
+		if !self
+			.mirror_invitable(space_id, room_id, &user_id)
+			.await
+		{
+			continue;
+		}
+
+		if invited > 0 || failed > 0 {
+			sleep(interval).await;
+		}
+
+		match self
+			.services
+			.membership
+			.invite(&child.inviter, &user_id, room_id, None, false)
+			.await
+		{
+			| Ok(()) => {
+				self.db
+					.roomuserid_mirrorinvite
+					.put((room_id, &user_id), space_id);
+
+				invited = invited.saturating_add(1);
+			},
+			| Err(e) => {
+				debug_warn!(%user_id, %room_id, "Failed to mirror space membership: {e}");
+				failed = failed.saturating_add(1);
+			},
+		}
+	}
+
+	Ok((invited, failed))
+}
+
+/// Whether a member of the space is to be invited: they are still in the
+/// space, did not decline an invite to one of its rooms, and have not joined,
+/// been invited to or left the room.
+#[implement(super::Service)]
+async fn mirror_invitable(&self, space_id: &RoomId, room_id: &RoomId, user_id: &UserId) -> bool {
+	let state_cache = &self.services.state_cache;
+
+	state_cache.is_joined(user_id, space_id).await
+		&& !self
+			.db
+			.spaceuserid_mirrordeclined
+			.contains(&(space_id, user_id))
+			.await
+		&& !state_cache.is_joined(user_id, room_id).await
+		&& !state_cache.is_invited(user_id, room_id).await
+		&& !state_cache.is_left(user_id, room_id).await
+}
+
+#[implement(super::Service)]
+async fn mirrored_child(&self, room_id: &RoomId) -> Option<MirroredChild> {
+	self.db
+		.roomid_mirrorspace
+		.get(room_id)
+		.await
+		.deserialized()
+		.ok()
+}
@@ -1,8 +1,14 @@
+mod hierarchy;
+mod mirror;
 mod pagination_token;
 #[cfg(test)]
 mod tests;
 
-use std::{fmt::Write, sync::Arc};
+use std::{
+	fmt::Write,
+	sync::{Arc, atomic::AtomicU64},
+	time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, pin_mut, stream::FuturesUnordered};
@@ -24,24 +30,53 @@
 use tuwunel_core::{
 	Err, Error, Event, Result, implement,
 	utils::{
-		IterStream,
+		IterStream, MutexMap,
 		future::{BoolExt, TryExtExt},
 		math::usize_from_f64,
+		millis_since_unix_epoch,
 		stream::{BroadbandExt, ReadyExt, TryReadyExt},
 	},
 };
+use tuwunel_database::Map;
 
-pub use self::pagination_token::PaginationToken;
+use self::hierarchy::{HIERARCHY_CACHE_CAPACITY, HierarchyKey, SNAPSHOT_CAPACITY};
+pub use self::{hierarchy::Hierarchy, pagination_token::PaginationToken};
 
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
-	pub roomid_spacehierarchy_cache: Mutex<Cache>,
+	roomid_spacehierarchy_cache: Mutex<Cache>,
+	hierarchy_cache: Mutex<LruCache<HierarchyKey, Arc<Hierarchy>>>,
+
+	/// Held while a hierarchy is built, so that concurrent requests for it
+	/// wait for the one build.
+	hierarchy_build_mutex: MutexMap<HierarchyKey, ()>,
+
+	/// Hierarchies being paginated, by the ID in their pagination tokens.
+	snapshots: Mutex<LruCache<u64, Arc<Hierarchy>>>,
+	next_snapshot: AtomicU64,
+	db: Data,
+}
+
+struct Data {
+	roomid_mirrorspace: Arc<Map>,
+	roomuserid_mirrorinvite: Arc<Map>,
+	spaceuserid_mirrordeclined: Arc<Map>,
 }
 
 pub struct CachedSpaceHierarchySummary {
 	summary: SpaceHierarchyParentSummary,
 }
 
+struct CacheEntry {
+	/// None for a room which could not be found.
+	summary: Option<CachedSpaceHierarchySummary>,
+
+	/// When the summary of a remote room is to be fetched again; summaries of
+	/// local rooms are invalidated as their m.space.child state changes
+	/// instead.
+	expires: Option<Instant>,
+}
+
 #[expect(clippy::large_enum_variant)]
 pub enum SummaryAccessibility {
 	Accessible(SpaceHierarchyParentSummary),
@@ -55,7 +90,7 @@ pub enum Identifier<'a> {
 	ServerName(&'a ServerName),
 }
 
-type Cache = LruCache<OwnedRoomId, Option<CachedSpaceHierarchySummary>>;
+type Cache = LruCache<OwnedRoomId, CacheEntry>;
 
 #[async_trait]
 impl crate::Service for Service {
@@ -66,6 +101,15 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			services: args.services.clone(),
 			roomid_spacehierarchy_cache: Mutex::new(LruCache::new(usize_from_f64(cache_size)?)),
+			hierarchy_cache: Mutex::new(LruCache::new(HIERARCHY_CACHE_CAPACITY)),
+			hierarchy_build_mutex: MutexMap::new(),
+			snapshots: Mutex::new(LruCache::new(SNAPSHOT_CAPACITY)),
+			next_snapshot: AtomicU64::new(millis_since_unix_epoch()),
+			db: Data {
+				roomid_mirrorspace: args.db["roomid_mirrorspace"].clone(),
+				roomuserid_mirrorinvite: args.db["roomuserid_mirrorinvite"].clone(),
+				spaceuserid_mirrordeclined: args.db["spaceuserid_mirrordeclined"].clone(),
+			},
 		}))
 	}
 
@@ -78,6 +122,9 @@ async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
 
 		writeln!(out, "roomid_spacehierarchy_cache: {roomid_spacehierarchy_cache}")?;
 
+		let hierarchy_cache = self.hierarchy_cache.lock().await.len();
+		writeln!(out, "space_hierarchy_cache: {hierarchy_cache}")?;
+
 		Ok(())
 	}
 
@@ -86,6 +133,8 @@ async fn clear_cache(&self) {
 			.lock()
 			.await
 			.clear();
+
+		self.hierarchy_cache.lock().await.clear();
 	}
 
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
@@ -121,17 +170,11 @@ pub async fn get_summary_and_children_local(
 	current_room: &RoomId,
 	identifier: &Identifier<'_>,
 ) -> Result<Option<SummaryAccessibility>> {
-	match self
-		.roomid_spacehierarchy_cache
-		.lock()
-		.await
-		.get_mut(current_room)
-		.as_ref()
-	{
+	match self.cached_summary(current_room).await {
 		| None => (), // cache miss
 		| Some(None) => return Ok(None),
-		| Some(Some(cached)) => {
-			let join_rule = &cached.summary.summary.join_rule;
+		| Some(Some(summary)) => {
+			let join_rule = &summary.summary.join_rule;
 			let is_accessible_child = self.is_accessible_child(
 				current_room,
 				join_rule,
@@ -140,7 +183,7 @@ pub async fn get_summary_and_children_local(
 			);
 
 			let accessibility = if is_accessible_child.await {
-				SummaryAccessibility::Accessible(cached.summary.clone())
+				SummaryAccessibility::Accessible(summary.clone())
 			} else {
 				SummaryAccessibility::Inaccessible
 			};
@@ -166,10 +209,10 @@ pub async fn get_summary_and_children_local(
 	self.roomid_spacehierarchy_cache
 		.lock()
 		.await
-		.insert(
-			current_room.to_owned(),
-			Some(CachedSpaceHierarchySummary { summary: summary.clone() }),
-		);
+		.insert(current_room.to_owned(), CacheEntry {
+			summary: Some(CachedSpaceHierarchySummary { summary: summary.clone() }),
+			expires: None,
+		});
 
 	Ok(Some(SummaryAccessibility::Accessible(summary)))
 }
@@ -184,6 +227,39 @@ async fn get_summary_and_children_federation(
 	user_id: &UserId,
 	via: &[OwnedServerName],
 ) -> Result<Option<SummaryAccessibility>> {
+	let Some(summary) = self
+		.fetch_summary_federation(current_room, suggested_only, via)
+		.await
+	else {
+		return Ok(None);
+	};
+
+	let identifier = Identifier::UserId(user_id);
+	let join_rule = &summary.summary.join_rule;
+	let allowed_room_ids = join_rule.allowed_room_ids();
+
+	let is_accessible_child = self
+		.is_accessible_child(current_room, join_rule, &identifier, allowed_room_ids)
+		.await;
+
+	let accessibility = if is_accessible_child {
+		SummaryAccessibility::Accessible(summary)
+	} else {
+		SummaryAccessibility::Inaccessible
+	};
+
+	Ok(Some(accessibility))
+}
+
+/// Fetches the summary of a remote space from the first of `via` to answer,
+/// caching it, or its absence, for `space_hierarchy_remote_ttl`.
+#[implement(Service)]
+async fn fetch_summary_federation(
+	&self,
+	current_room: &RoomId,
+	suggested_only: bool,
+	via: &[OwnedServerName],
+) -> Option<SpaceHierarchyParentSummary> {
 	let request = federation::space::get_hierarchy::v1::Request {
 		room_id: current_room.to_owned(),
 		suggested_only,
@@ -198,23 +274,25 @@ async fn get_summary_and_children_federation(
 		})
 		.collect();
 
+	let ttl = Duration::from_secs(self.services.config.space_hierarchy_remote_ttl);
+	let expires = Instant::now().checked_add(ttl);
 	let Some(Ok(response)) = requests.next().await else {
 		self.roomid_spacehierarchy_cache
 			.lock()
 			.await
-			.insert(current_room.to_owned(), None);
+			.insert(current_room.to_owned(), CacheEntry { summary: None, expires });
 
-		return Ok(None);
+		return None;
 	};
 
 	let summary = response.room;
 	self.roomid_spacehierarchy_cache
 		.lock()
 		.await
-		.insert(
-			current_room.to_owned(),
-			Some(CachedSpaceHierarchySummary { summary: summary.clone() }),
-		);
+		.insert(current_room.to_owned(), CacheEntry {
+			summary: Some(CachedSpaceHierarchySummary { summary: summary.clone() }),
+			expires,
+		});
 
 	response
 		.children
@@ -228,24 +306,53 @@ async fn get_summary_and_children_federation(
 		.ready_filter_map(|(child, mut cache)| {
 			(!cache.contains_key(current_room)).then_some((child, cache))
 		})
-		.for_each(|(child, cache)| self.cache_insert(cache, current_room, child))
+		.for_each(|(child, cache)| self.cache_insert(cache, current_room, child, expires))
 		.await;
 
-	let identifier = Identifier::UserId(user_id);
-	let join_rule = &summary.summary.join_rule;
-	let allowed_room_ids = join_rule.allowed_room_ids();
+	Some(summary)
+}
 
-	let is_accessible_child = self
-		.is_accessible_child(current_room, join_rule, &identifier, allowed_room_ids)
-		.await;
+/// The cached summary of a room, unless missing or expired. `Some(None)` is a
+/// room which could not be found.
+#[implement(Service)]
+async fn cached_summary(&self, room_id: &RoomId) -> Option<Option<SpaceHierarchyParentSummary>> {
+	let mut cache = self.roomid_spacehierarchy_cache.lock().await;
+	let entry = cache.get_mut(room_id)?;
+	if entry
+		.expires
+		.is_some_and(|expires| expires <= Instant::now())
+	{
+		cache.remove(room_id);
+		return None;
+	}
 
-	let accessibility = if is_accessible_child {
-		SummaryAccessibility::Accessible(summary)
-	} else {
-		SummaryAccessibility::Inaccessible
-	};
+	Some(
+		entry
+			.summary
+			.as_ref()
+			.map(|cached| cached.summary.clone()),
+	)
+}
 
-	Ok(Some(accessibility))
+/// Forgets the cached summary of a room and the cached hierarchies including
+/// it, as its m.space.child state changed.
+#[implement(Service)]
+pub async fn invalidate(&self, room_id: &RoomId) {
+	self.roomid_spacehierarchy_cache
+		.lock()
+		.await
+		.remove(room_id);
+
+	let mut hierarchies = self.hierarchy_cache.lock().await;
+	let stale: Vec<_> = hierarchies
+		.iter()
+		.filter(|(_, hierarchy)| hierarchy.contains(room_id))
+		.map(|(key, _)| key.clone())
+		.collect();
+
+	for key in stale {
+		hierarchies.remove(&key);
+	}
 }
 
 #[implement(Service)]
@@ -467,6 +574,7 @@ async fn cache_insert(
 	mut cache: MutexGuard<'_, Cache>,
 	current_room: &RoomId,
 	child: RoomSummary,
+	expires: Option<Instant>,
 ) {
 	let summary = SpaceHierarchyParentSummary {
 		children_state: self
@@ -478,7 +586,10 @@ async fn cache_insert(
 		summary: child,
 	};
 
-	cache.insert(current_room.to_owned(), Some(CachedSpaceHierarchySummary { summary }));
+	cache.insert(current_room.to_owned(), CacheEntry {
+		summary: Some(CachedSpaceHierarchySummary { summary }),
+		expires,
+	});
 }
 
 /// Simply returns the stripped m.space.child events of a room
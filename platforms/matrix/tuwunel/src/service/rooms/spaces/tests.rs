@@ -96,28 +96,33 @@ fn invalid_pagination_tokens() {
 	token_is_err("11_4_true_");
 	token_is_err("___");
 	token_is_err("__false");
+	token_is_err("5383,42934_0_20_1_true");
+	token_is_err("1_2_3_4_true_");
+	token_is_err("1_-2_3_4_false");
 }
 
 #[test]
 fn valid_pagination_tokens() {
 	assert_eq!(
 		PaginationToken {
-			short_room_ids: vec![5383, 42934, 283, 423],
+			snapshot: 5383,
+			offset: 42,
 			limit: UInt::from(20_u32),
 			max_depth: UInt::from(1_u32),
 			suggested_only: true
 		},
-		PaginationToken::from_str("5383,42934,283,423_20_1_true").unwrap()
+		PaginationToken::from_str("5383_42_20_1_true").unwrap()
 	);
 
 	assert_eq!(
 		PaginationToken {
-			short_room_ids: vec![740],
+			snapshot: 740,
+			offset: 0,
 			limit: UInt::from(97_u32),
 			max_depth: UInt::from(10539_u32),
 			suggested_only: false
 		},
-		PaginationToken::from_str("740_97_10539_false").unwrap()
+		PaginationToken::from_str("740_0_97_10539_false").unwrap()
 	);
 }
 
@@ -125,23 +130,25 @@ fn valid_pagination_tokens() {
 fn pagination_token_to_string() {
 	assert_eq!(
 		PaginationToken {
-			short_room_ids: vec![740],
+			snapshot: 740,
+			offset: 3,
 			limit: UInt::from(97_u32),
 			max_depth: UInt::from(10539_u32),
 			suggested_only: false
 		}
 		.to_string(),
-		"740_97_10539_false"
+		"740_3_97_10539_false"
 	);
 
 	assert_eq!(
 		PaginationToken {
-			short_room_ids: vec![9, 34],
+			snapshot: 9,
+			offset: 34,
 			limit: UInt::from(3_u32),
 			max_depth: UInt::from(1_u32),
 			suggested_only: true
 		}
 		.to_string(),
-		"9,34_3_1_true"
+		"9_34_3_1_true"
 	);
 }
@@ -144,10 +144,8 @@ pub async fn force_state(
 			| TimelineEventType::SpaceChild => {
 				self.services
 					.spaces
-					.roomid_spacehierarchy_cache
-					.lock()
-					.await
-					.remove(&pdu.room_id);
+					.invalidate(&pdu.room_id)
+					.await;
 
 				Ok(())
 			},
@@ -0,0 +1,146 @@
+//! Reading the events of a state matching a selection of types and state keys,
+//! without reading the events of the whole state.
+
+use std::collections::BTreeSet;
+
+use futures::{StreamExt, TryFutureExt};
+use ruma::{OwnedEventId, RoomId, events::StateEventType};
+use serde::Deserialize;
+use tuwunel_core::{
+	Result, at, err, implement,
+	matrix::Pdu,
+	utils::stream::{BroadbandExt, IterStream, ReadyExt},
+};
+
+use crate::rooms::{
+	short::ShortStateHash,
+	state_compressor::{compress_state_event, parse_compressed_state_event},
+};
+
+/// Selection of state events of a type.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StateFilter {
+	#[serde(rename = "type")]
+	pub event_type: StateEventType,
+
+	/// State key of the event, or a prefix of the state keys of the events
+	/// followed by `*`. A lone `*` selects all events of the type.
+	#[serde(default)]
+	pub state_key: String,
+}
+
+impl StateFilter {
+	/// The state key when the filter selects a single event.
+	#[must_use]
+	pub fn exact(&self) -> Option<&str> {
+		(!self.state_key.ends_with('*')).then_some(self.state_key.as_str())
+	}
+
+	#[must_use]
+	pub fn matches(&self, event_type: &StateEventType, state_key: &str) -> bool {
+		self.event_type == *event_type
+			&& match self.state_key.strip_suffix('*') {
+				| Some(prefix) => state_key.starts_with(prefix),
+				| None => state_key == self.state_key,
+			}
+	}
+}
+
+/// The events of the current state of the room matching any of the filters.
+#[implement(super::Service)]
+pub async fn room_state_get_filtered(
+	&self,
+	room_id: &RoomId,
+	filters: &[StateFilter],
+) -> Result<Vec<Pdu>> {
+	self.services
+		.state
+		.get_room_shortstatehash(room_id)
+		.map_err(|e| err!(Database("Missing state for {room_id:?}: {e:?}")))
+		.and_then(|shortstatehash| self.state_get_filtered(shortstatehash, filters))
+		.await
+}
+
+/// The events of the state matching any of the filters. Events selected by
+/// their state key are looked up directly; the state keys of the whole state
+/// are read only for filters selecting by prefix.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn state_get_filtered(
+	&self,
+	shortstatehash: ShortStateHash,
+	filters: &[StateFilter],
+) -> Result<Vec<Pdu>> {
+	let full_state = self.load_full_state(shortstatehash).await?;
+	let mut shorteventids = BTreeSet::new();
+
+	for filter in filters {
+		let Some(state_key) = filter.exact() else {
+			continue;
+		};
+
+		let Ok(shortstatekey) = self
+			.services
+			.short
+			.get_shortstatekey(&filter.event_type, state_key)
+			.await
+		else {
+			continue;
+		};
+
+		let start = compress_state_event(shortstatekey, 0);
+		let end = compress_state_event(shortstatekey, u64::MAX);
+		shorteventids.extend(
+			full_state
+				.range(start..=end)
+				.copied()
+				.map(parse_compressed_state_event)
+				.map(at!(1)),
+		);
+	}
+
+	if filters
+		.iter()
+		.any(|filter| filter.exact().is_none())
+	{
+		let (shortstatekeys, ids): (Vec<_>, Vec<_>) = full_state
+			.iter()
+			.copied()
+			.map(parse_compressed_state_event)
+			.unzip();
+
+		let matching: Vec<_> = self
+			.services
+			.short
+			.multi_get_statekey_from_short(shortstatekeys.into_iter().stream())
+			.zip(ids.into_iter().stream())
+			.ready_filter_map(|(key, shorteventid)| {
+				let (event_type, state_key) = key.ok()?;
+				filters
+					.iter()
+					.any(|filter| filter.matches(&event_type, &state_key))
+					.then_some(shorteventid)
+			})
+			.collect()
+			.await;
+
+		shorteventids.extend(matching);
+	}
+
+	let pdus = self
+		.services
+		.short
+		.multi_get_eventid_from_short(shorteventids.into_iter().stream())
+		.ready_filter_map(Result::ok)
+		.broad_filter_map(async |event_id: OwnedEventId| {
+			self.services
+				.timeline
+				.get_pdu(&event_id)
+				.await
+				.ok()
+		})
+		.collect()
+		.await;
+
+	Ok(pdus)
+}
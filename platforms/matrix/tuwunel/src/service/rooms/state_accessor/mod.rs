@@ -1,9 +1,15 @@
+mod filtered;
 mod room_state;
+mod server_acl;
 mod server_can;
 mod state;
+mod summary;
 mod user_can;
 
-use std::sync::Arc;
+use std::{
+	fmt::Write,
+	sync::{Arc, Mutex},
+};
 
 use async_trait::async_trait;
 use futures::{FutureExt, TryFutureExt, future::try_join};
@@ -30,18 +36,42 @@
 use tuwunel_core::{
 	Result, err, is_true,
 	matrix::{Pdu, room_version, state_res::events::RoomCreateEvent},
+	utils::math::usize_from_f64,
+};
+use tuwunel_database::Map;
+
+pub use self::{
+	filtered::StateFilter,
+	server_acl::ServerAcl,
+	summary::{CachedSummary, Hero, MAX_HEROES, Summary, heroes_name},
 };
 
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
+	roomid_summary: Arc<Map>,
+	server_acl_cache: Mutex<server_acl::Cache>,
 }
 
 #[async_trait]
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
-		Ok(Arc::new(Self { services: args.services.clone() }))
+		let config = &args.server.config;
+		let cache_capacity =
+			f64::from(config.server_acl_cache_capacity) * config.cache_capacity_modifier;
+
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			roomid_summary: args.db["roomid_summary"].clone(),
+			server_acl_cache: server_acl::Cache::new(usize_from_f64(cache_capacity)?).into(),
+		}))
 	}
 
+	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+		self.server_acl_cache_usage(out)
+	}
+
+	async fn clear_cache(&self) { self.forget_server_acls(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
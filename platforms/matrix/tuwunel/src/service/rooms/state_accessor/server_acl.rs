@@ -0,0 +1,296 @@
+//! Compiled `m.room.server_acl`, so that the globs of an ACL are not parsed
+//! again for every event received over federation. ACLs are cached by the state
+//! they are found in, and by their event so that a state changing something
+//! else reuses the ACL already compiled. A changed ACL is part of a new state,
+//! so it is compiled anew; only a redaction changes an ACL in place.
+
+use std::{fmt::Write, sync::Arc};
+
+use lru_cache::LruCache;
+use regex::RegexSet;
+use ruma::{
+	EventId, OwnedEventId, RoomId, ServerName,
+	events::{StateEventType, room::server_acl::RoomServerAclEventContent},
+};
+use tuwunel_core::{Err, Result, debug, implement, matrix::Event, trace, warn};
+
+use crate::rooms::short::ShortStateHash;
+
+/// The allowed and denied servers of a room, with their globs compiled.
+#[derive(Debug)]
+pub struct ServerAcl {
+	content: RoomServerAclEventContent,
+
+	/// The allowed and denied globs; None when they could not be compiled, in
+	/// which case ruma matches them one by one.
+	compiled: Option<(RegexSet, RegexSet)>,
+}
+
+pub(super) struct Cache {
+	/// By the state the ACL is found in; None when the state has no ACL in
+	/// force.
+	by_state: LruCache<ShortStateHash, Option<Arc<ServerAcl>>>,
+
+	/// By the event of the ACL; None when it is ignored for being broken.
+	by_event: LruCache<OwnedEventId, Option<Arc<ServerAcl>>>,
+}
+
+impl ServerAcl {
+	/// Compiles the content of an ACL. None when it is broken, in which case it
+	/// is ignored.
+	#[must_use]
+	pub fn compile(content: RoomServerAclEventContent) -> Option<Self> {
+		if content.allow.is_empty() {
+			warn!("Ignoring broken ACL event (allow key is empty)");
+			return None;
+		}
+
+		let wildcard = |globs: &[String]| globs.iter().any(|glob| glob == "*");
+		if wildcard(&content.allow) && wildcard(&content.deny) {
+			warn!(
+				"Ignoring broken ACL event (allow key and deny key both contain wildcard \"*\")"
+			);
+			return None;
+		}
+
+		let regex_set =
+			|globs: &[String]| RegexSet::new(globs.iter().map(String::as_str).map(glob_regex));
+
+		let compiled = regex_set(&content.allow)
+			.and_then(|allow| Ok((allow, regex_set(&content.deny)?)))
+			.inspect_err(|e| warn!("Matching ACL globs uncompiled: {e}"))
+			.ok();
+
+		Some(Self { content, compiled })
+	}
+
+	/// Whether the server is allowed. Its port is not considered.
+	#[must_use]
+	pub fn is_allowed(&self, server_name: &ServerName) -> bool {
+		let Some((allow, deny)) = &self.compiled else {
+			return self.content.is_allowed(server_name);
+		};
+
+		if !self.content.allow_ip_literals && server_name.is_ip_literal() {
+			return false;
+		}
+
+		let host = server_name.host();
+
+		!deny.is_match(host) && allow.is_match(host)
+	}
+}
+
+impl Cache {
+	pub(super) fn new(capacity: usize) -> Self {
+		Self {
+			by_state: LruCache::new(capacity),
+			by_event: LruCache::new(capacity),
+		}
+	}
+
+	pub(super) fn clear(&mut self) {
+		self.by_state.clear();
+		self.by_event.clear();
+	}
+}
+
+/// Returns Ok if the ACL in force in the room allows the server.
+#[implement(super::Service)]
+#[tracing::instrument(skip_all, level = "debug")]
+pub async fn server_acl_check(&self, server_name: &ServerName, room_id: &RoomId) -> Result {
+	let Ok(shortstatehash) = self
+		.services
+		.state
+		.get_room_shortstatehash(room_id)
+		.await
+	else {
+		return Ok(());
+	};
+
+	let Some(acl) = self.server_acl(shortstatehash).await else {
+		trace!(%room_id, "No ACL in force");
+		return Ok(());
+	};
+
+	if acl.is_allowed(server_name) {
+		trace!("server {server_name} is allowed by ACL");
+		Ok(())
+	} else {
+		debug!("Server {server_name} was denied by room ACL in {room_id}");
+		Err!(Request(Forbidden("Server was denied by room ACL")))
+	}
+}
+
+/// The ACL in force in a state, compiled. None when there is none or it is
+/// broken.
+#[implement(super::Service)]
+pub async fn server_acl(&self, shortstatehash: ShortStateHash) -> Option<Arc<ServerAcl>> {
+	if let Some(acl) = self
+		.server_acl_cache
+		.lock()
+		.expect("locked")
+		.by_state
+		.get_mut(&shortstatehash)
+	{
+		return acl.clone();
+	}
+
+	let acl = match self
+		.state_get_id(shortstatehash, &StateEventType::RoomServerAcl, "")
+		.await
+	{
+		| Ok(event_id) => self.server_acl_event(&event_id).await,
+		| Err(_) => None,
+	};
+
+	self.server_acl_cache
+		.lock()
+		.expect("locked")
+		.by_state
+		.insert(shortstatehash, acl.clone());
+
+	acl
+}
+
+#[implement(super::Service)]
+async fn server_acl_event(&self, event_id: &EventId) -> Option<Arc<ServerAcl>> {
+	if let Some(acl) = self
+		.server_acl_cache
+		.lock()
+		.expect("locked")
+		.by_event
+		.get_mut(event_id)
+	{
+		return acl.clone();
+	}
+
+	let acl = self
+		.services
+		.timeline
+		.get_pdu(event_id)
+		.await
+		.and_then(|pdu| pdu.get_content::<RoomServerAclEventContent>())
+		.inspect_err(|e| trace!(%event_id, "Invalid ACL content: {e:?}"))
+		.ok()
+		.and_then(ServerAcl::compile)
+		.map(Arc::new);
+
+	self.server_acl_cache
+		.lock()
+		.expect("locked")
+		.by_event
+		.insert(event_id.to_owned(), acl.clone());
+
+	acl
+}
+
+/// Forgets the compiled ACLs after an ACL was changed in place by a redaction.
+#[implement(super::Service)]
+pub fn forget_server_acls(&self) {
+	self.server_acl_cache
+		.lock()
+		.expect("locked")
+		.clear();
+}
+
+#[implement(super::Service)]
+pub(super) fn server_acl_cache_usage(&self, out: &mut (dyn Write + Send)) -> Result {
+	let (by_state, by_event) = {
+		let cache = self.server_acl_cache.lock().expect("locked");
+		(cache.by_state.len(), cache.by_event.len())
+	};
+
+	writeln!(out, "server_acl_cache: {by_state} {by_event}")?;
+
+	Ok(())
+}
+
+/// The anchored regex matching what a glob of an ACL matches: `*` matches any
+/// characters and `?` any single one.
+fn glob_regex(glob: &str) -> String {
+	let mut regex = String::with_capacity(glob.len().saturating_add(2));
+	regex.push('^');
+	for c in glob.chars() {
+		match c {
+			| '*' => regex.push_str(".*"),
+			| '?' => regex.push('.'),
+			| c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+		}
+	}
+
+	regex.push('$');
+	regex
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::{ServerName, events::room::server_acl::RoomServerAclEventContent};
+
+	use super::ServerAcl;
+
+	const SERVERS: &[&str] = &[
+		"example.com",
+		"example.com:8448",
+		"matrix.example.com",
+		"example.org",
+		"evil.example.org",
+		"exampleXcom",
+		"a.b",
+		"ab.b",
+		"1.2.3.4",
+		"[::1]",
+		"[::1]:8448",
+	];
+
+	fn content(allow: &[&str], deny: &[&str], ip_literals: bool) -> RoomServerAclEventContent {
+		let globs = |globs: &[&str]| globs.iter().map(ToString::to_string).collect();
+
+		RoomServerAclEventContent::new(ip_literals, globs(allow), globs(deny))
+	}
+
+	fn assert_matches_ruma(content: RoomServerAclEventContent) {
+		let acl = ServerAcl::compile(content.clone()).expect("not broken");
+		assert!(acl.compiled.is_some(), "compiled");
+
+		for server in SERVERS {
+			let server = <&ServerName>::try_from(*server).expect("valid server name");
+			assert_eq!(
+				acl.is_allowed(server),
+				content.is_allowed(server),
+				"{server} with allow {:?} deny {:?}",
+				content.allow,
+				content.deny
+			);
+		}
+	}
+
+	#[test]
+	fn globs_match_ruma() {
+		assert_matches_ruma(content(&["*"], &[], false));
+		assert_matches_ruma(content(&["*"], &[], true));
+		assert_matches_ruma(content(&["*"], &["*.example.org"], false));
+		assert_matches_ruma(content(&["*.example.com", "example.com"], &[], false));
+		assert_matches_ruma(content(&["example.com"], &["example?com"], false));
+		assert_matches_ruma(content(&["?.b", "a*"], &[], false));
+		assert_matches_ruma(content(&["*"], &["1.2.*", "evil*"], true));
+		assert_matches_ruma(content(&["exa?ple.*"], &["*.org"], false));
+	}
+
+	#[test]
+	fn broken_ignored() {
+		assert!(ServerAcl::compile(content(&[], &[], false)).is_none());
+		assert!(ServerAcl::compile(content(&["*"], &["*"], false)).is_none());
+	}
+
+	#[test]
+	fn uncompiled_falls_back_to_ruma() {
+		let content = content(&["*"], &["evil*"], false);
+		let acl = ServerAcl { content: content.clone(), compiled: None };
+
+		for server in SERVERS {
+			let server = <&ServerName>::try_from(*server).expect("valid server name");
+			assert_eq!(acl.is_allowed(server), content.is_allowed(server), "{server}");
+		}
+	}
+}
@@ -6,10 +6,11 @@
 		room::history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
 	},
 };
-use tuwunel_core::{implement, utils::stream::ReadyExt};
+use tuwunel_core::{implement, matrix::Event, utils::stream::ReadyExt};
 
 /// Whether a server is allowed to see an event through federation, based on
-/// the room's history_visibility at that event's state.
+/// the room's history_visibility at that event's state. Events of shadow-banned
+/// users are never seen.
 #[implement(super::Service)]
 #[tracing::instrument(skip_all, level = "trace")]
 pub async fn server_can_see_event(
@@ -18,6 +19,16 @@ pub async fn server_can_see_event(
 	room_id: &RoomId,
 	event_id: &EventId,
 ) -> bool {
+	if let Ok(pdu) = self.services.timeline.get_pdu(event_id).await
+		&& self
+			.services
+			.users
+			.is_shadow_banned(pdu.sender())
+			.await
+	{
+		return false;
+	}
+
 	let Ok(shortstatehash) = self
 		.services
 		.state
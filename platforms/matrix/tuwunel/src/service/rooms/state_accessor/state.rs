@@ -413,7 +413,10 @@ pub fn state_full_shortids(
 
 #[implement(super::Service)]
 #[tracing::instrument(name = "load", level = "debug", skip(self))]
-async fn load_full_state(&self, shortstatehash: ShortStateHash) -> Result<Arc<CompressedState>> {
+pub(super) async fn load_full_state(
+	&self,
+	shortstatehash: ShortStateHash,
+) -> Result<Arc<CompressedState>> {
 	self.services
 		.state_compressor
 		.load_shortstatehash_info(shortstatehash)
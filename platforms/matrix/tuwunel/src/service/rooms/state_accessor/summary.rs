@@ -0,0 +1,264 @@
+use futures::{
+	StreamExt, TryFutureExt,
+	future::{join, join3},
+};
+use ruma::{OwnedMxcUri, OwnedUserId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{
+	implement,
+	utils::{
+		BoolExt,
+		future::TryExtExt,
+		stream::{IterStream, ReadyExt},
+	},
+};
+use tuwunel_database::{Deserialized, Json};
+
+/// Maximum number of heroes calculated for a room.
+pub const MAX_HEROES: usize = 5;
+
+/// Number of hero candidates cached for a room: one more than `MAX_HEROES` so
+/// that enough remain once the user viewing the room is left out.
+const HERO_CANDIDATES: usize = MAX_HEROES + 1;
+
+/// Member counts and hero candidates of a room, kept in `roomid_summary` and
+/// updated on membership changes so syncs need not stream the members.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CachedSummary {
+	pub joined_member_count: u64,
+	pub invited_member_count: u64,
+
+	/// Joined or invited members, of which the first `MAX_HEROES` other than
+	/// the viewing user are its heroes.
+	pub heroes: Vec<OwnedUserId>,
+}
+
+/// Server-computed naming of a room, as presented to a specific user.
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+	/// The room's `m.room.name`, else its canonical alias, else a name
+	/// calculated from the heroes.
+	pub name: Option<String>,
+
+	/// The room's `m.room.avatar`, else the avatar of the first hero for rooms
+	/// without a name.
+	pub avatar: Option<OwnedMxcUri>,
+
+	/// Members representing the room when it has no name or alias.
+	pub heroes: Vec<Hero>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Hero {
+	pub user_id: OwnedUserId,
+	pub name: Option<String>,
+	pub avatar: Option<OwnedMxcUri>,
+}
+
+/// Calculate the display name, avatar and heroes of a room for `user_id`,
+/// following the order of the client-server specification: `m.room.name`,
+/// then `m.room.canonical_alias`, then the heroes. Heroes are only calculated
+/// when the room has neither, and when `calculate_heroes` is set.
+#[implement(super::Service)]
+#[tracing::instrument(name = "summary", level = "trace", skip(self))]
+pub async fn room_summary(
+	&self,
+	room_id: &RoomId,
+	user_id: &UserId,
+	calculate_heroes: bool,
+) -> Summary {
+	let name = self.get_name(room_id).ok();
+
+	let alias = self.get_canonical_alias(room_id).ok();
+
+	let avatar = self
+		.get_avatar(room_id)
+		.map_ok(|content| content.url)
+		.ok();
+
+	let (name, alias, avatar) = join3(name, alias, avatar).await;
+
+	let name = name
+		.filter(|name| !name.is_empty())
+		.or_else(|| alias.map(String::from));
+
+	let heroes = calculate_heroes
+		.and_is(name.is_none())
+		.then_async(|| self.room_heroes(room_id, user_id))
+		.await
+		.unwrap_or_default();
+
+	let avatar = avatar.flatten().or_else(|| {
+		heroes
+			.first()
+			.filter(|_| name.is_none())
+			.and_then(|hero| hero.avatar.clone())
+	});
+
+	let name = name.or_else(|| heroes_name(&heroes));
+
+	Summary { name, avatar, heroes }
+}
+
+/// Select up to `MAX_HEROES` joined or invited members of the room other than
+/// `user_id`, with their room-specific display names and avatars falling back
+/// to their global profile.
+#[implement(super::Service)]
+pub async fn room_heroes(&self, room_id: &RoomId, user_id: &UserId) -> Vec<Hero> {
+	self.cached_summary(room_id)
+		.await
+		.heroes
+		.into_iter()
+		.stream()
+		.ready_filter(|member| member.as_str() != user_id.as_str())
+		.filter_map(async |member: OwnedUserId| {
+			let content = self.get_member(room_id, &member).await.ok()?;
+
+			let name = content
+				.displayname
+				.is_none()
+				.then_async(|| self.services.users.displayname(&member).ok());
+
+			let avatar = content
+				.avatar_url
+				.is_none()
+				.then_async(|| self.services.users.avatar_url(&member).ok());
+
+			let (name, avatar) = join(name, avatar).await;
+			let name = name.flatten().or(content.displayname);
+			let avatar = avatar.flatten().or(content.avatar_url);
+
+			Some(Hero { user_id: member, name, avatar })
+		})
+		.take(MAX_HEROES)
+		.collect()
+		.await
+}
+
+/// The cached member counts and hero candidates of a room, calculated and
+/// cached first if the room has none.
+#[implement(super::Service)]
+pub async fn cached_summary(&self, room_id: &RoomId) -> CachedSummary {
+	if let Ok(summary) = self
+		.roomid_summary
+		.get(room_id)
+		.await
+		.deserialized()
+	{
+		return summary;
+	}
+
+	let state_cache = &self.services.state_cache;
+	let (joined, invited) = join(
+		state_cache
+			.room_joined_count(room_id)
+			.unwrap_or(0),
+		state_cache
+			.room_invited_count(room_id)
+			.unwrap_or(0),
+	)
+	.await;
+
+	self.update_summary(room_id, joined, invited)
+		.await
+}
+
+/// Updates the cached summary of a room after a membership change. Hero
+/// candidates which are no longer joined or invited are dropped, and only
+/// when too few remain are the members streamed for replacements.
+#[implement(super::Service)]
+#[tracing::instrument(level = "debug", skip(self))]
+pub async fn update_summary(
+	&self,
+	room_id: &RoomId,
+	joined_member_count: u64,
+	invited_member_count: u64,
+) -> CachedSummary {
+	let state_cache = &self.services.state_cache;
+	let cached: Vec<OwnedUserId> = self
+		.roomid_summary
+		.get(room_id)
+		.await
+		.deserialized()
+		.map(|summary: CachedSummary| summary.heroes)
+		.unwrap_or_default();
+
+	let mut heroes: Vec<OwnedUserId> = cached
+		.into_iter()
+		.stream()
+		.filter(async |member| {
+			state_cache.is_joined(member, room_id).await
+				|| state_cache.is_invited(member, room_id).await
+		})
+		.collect()
+		.await;
+
+	if heroes.len() < HERO_CANDIDATES {
+		let needed = HERO_CANDIDATES.saturating_sub(heroes.len());
+		let members: Vec<OwnedUserId> = state_cache
+			.room_members(room_id)
+			.chain(state_cache.room_members_invited(room_id))
+			.ready_filter(|member| {
+				!heroes
+					.iter()
+					.any(|hero| hero.as_str() == member.as_str())
+			})
+			.take(needed)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		heroes.extend(members);
+	}
+
+	let summary = CachedSummary {
+		joined_member_count,
+		invited_member_count,
+		heroes,
+	};
+
+	self.roomid_summary
+		.raw_put(room_id, Json(&summary));
+
+	summary
+}
+
+/// Recalculates the cached summary of a room from its members.
+#[implement(super::Service)]
+pub async fn recalculate_summary(&self, room_id: &RoomId) -> CachedSummary {
+	self.delete_summary(room_id);
+	self.services
+		.state_cache
+		.update_joined_count(room_id)
+		.await;
+
+	self.cached_summary(room_id).await
+}
+
+#[implement(super::Service)]
+pub fn delete_summary(&self, room_id: &RoomId) { self.roomid_summary.remove(room_id); }
+
+/// Name a room after its heroes, e.g. "Alice", "Alice and Bob" or
+/// "Alice, Bob and Carol". Members without a display name are named by their
+/// user id.
+#[must_use]
+pub fn heroes_name(heroes: &[Hero]) -> Option<String> {
+	let name = |hero: &Hero| {
+		hero.name
+			.clone()
+			.unwrap_or_else(|| hero.user_id.to_string())
+	};
+
+	let (last, firsts) = heroes.split_last()?;
+	if firsts.is_empty() {
+		return Some(name(last));
+	}
+
+	let firsts = firsts
+		.iter()
+		.map(name)
+		.collect::<Vec<_>>()
+		.join(", ");
+
+	Some(format!("{firsts} and {}", name(last)))
+}
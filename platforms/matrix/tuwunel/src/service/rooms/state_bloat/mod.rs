@@ -0,0 +1,177 @@
+//! Periodic sampling of the size of each room's state, to alert the admin room
+//! when a room's state grows abnormally fast (e.g. membership spam) and to
+//! report the rooms with the largest state.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use ruma::{OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tuwunel_core::{
+	Result, debug_info, debug_warn, implement,
+	utils::{
+		stream::TryIgnore,
+		time::{now_secs, pretty},
+	},
+	warn,
+};
+use tuwunel_database::{Deserialized, Json, Map};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+	roomid_statesample: Arc<Map>,
+}
+
+/// The size of a room's state when last sampled.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Sample {
+	/// When the sample was taken, in seconds since the epoch.
+	pub sampled_at: u64,
+
+	/// Number of entries in the room's current state.
+	pub state_events: usize,
+
+	/// Number of state compressor layers the current state is stored in.
+	pub layers: usize,
+
+	/// Entries added per hour since the previous sample.
+	pub growth: u64,
+
+	/// Whether the admin room was alerted of this growth; it is alerted again
+	/// only once the growth has subsided.
+	alarmed: bool,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			roomid_statesample: args.db["roomid_statesample"].clone(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let interval = self.services.config.state_bloat_check_interval;
+		if interval == 0 {
+			return Ok(());
+		}
+
+		let interval = Duration::from_secs(interval);
+		loop {
+			tokio::select! {
+				() = sleep(interval) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			debug_info!("Sampling the state size of rooms");
+			self.services
+				.metadata
+				.iter_ids()
+				.for_each(async |room_id| {
+					if let Err(e) = self.sample(room_id).await {
+						debug_warn!(?room_id, "Failed to sample state size: {e}");
+					}
+				})
+				.await;
+		}
+	}
+
+	fn background(&self) -> bool { true }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Records the size of the room's current state, alerting the admin room when
+/// it grew faster than `state_growth_alarm_threshold` since the last sample.
+#[implement(Service)]
+#[tracing::instrument(level = "debug", skip(self))]
+pub async fn sample(&self, room_id: &RoomId) -> Result<Sample> {
+	let shortstatehash = self
+		.services
+		.state
+		.get_room_shortstatehash(room_id)
+		.await?;
+
+	let layers = self
+		.services
+		.state_compressor
+		.load_shortstatehash_info(shortstatehash)
+		.await?;
+
+	let state_events = layers
+		.last()
+		.map_or(0, |layer| layer.full_state.len());
+
+	let previous: Option<Sample> = self.get_sample(room_id).await.ok();
+
+	let sampled_at = now_secs();
+	let growth = previous.as_ref().map_or(0, |previous| {
+		let added = state_events.saturating_sub(previous.state_events);
+		let elapsed = sampled_at
+			.saturating_sub(previous.sampled_at)
+			.max(1);
+
+		u64::try_from(added)
+			.unwrap_or(u64::MAX)
+			.saturating_mul(3600)
+			.checked_div(elapsed)
+			.unwrap_or(0)
+	});
+
+	let threshold = self.services.config.state_growth_alarm_threshold;
+	let alarmed = threshold > 0 && growth >= threshold;
+	if alarmed
+		&& let Some(previous) = previous
+			.as_ref()
+			.filter(|previous| !previous.alarmed)
+	{
+		let elapsed = Duration::from_secs(sampled_at.saturating_sub(previous.sampled_at));
+		let elapsed = pretty(elapsed);
+		let previous_events = previous.state_events;
+
+		warn!(?room_id, growth, state_events, "Abnormal room state growth");
+		self.services
+			.admin
+			.notice(&format!(
+				"The state of room {room_id} grew from {previous_events} to {state_events} \
+				 entries in the last {elapsed} ({growth} per hour), now stored in {} state \
+				 layers.",
+				layers.len()
+			))
+			.await;
+	}
+
+	let sample = Sample {
+		sampled_at,
+		state_events,
+		layers: layers.len(),
+		growth,
+		alarmed,
+	};
+
+	self.roomid_statesample
+		.raw_put(room_id, Json(&sample));
+
+	Ok(sample)
+}
+
+/// The last sample of the room's state size.
+#[implement(Service)]
+pub async fn get_sample(&self, room_id: &RoomId) -> Result<Sample> {
+	self.roomid_statesample
+		.get(room_id)
+		.await
+		.deserialized()
+}
+
+/// The last sample of every room's state size.
+#[implement(Service)]
+pub fn samples(&self) -> impl Stream<Item = (OwnedRoomId, Sample)> + Send + '_ {
+	self.roomid_statesample
+		.stream()
+		.ignore_err()
+		.map(|(room_id, sample): (&RoomId, Sample)| (room_id.to_owned(), sample))
+}
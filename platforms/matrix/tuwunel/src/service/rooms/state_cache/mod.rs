@@ -1,3 +1,4 @@
+mod rebuild;
 mod update;
 mod via;
 
@@ -25,6 +26,7 @@
 };
 use tuwunel_database::{Deserialized, Ignore, Interfix, Map};
 
+pub use self::rebuild::Rebuilt;
 use crate::appservice::RegistrationInfo;
 
 pub struct Service {
@@ -122,6 +124,71 @@ pub async fn appservice_in_room(&self, room_id: &RoomId, appservice: &Registrati
 	in_room
 }
 
+/// The appservices among `appservices` with a user in the room. The members of
+/// the room are read once for all of the appservices not yet cached rather
+/// than once for each.
+#[implement(Service)]
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn appservices_in_room<'a>(
+	&self,
+	room_id: &RoomId,
+	appservices: &'a [RegistrationInfo],
+) -> Vec<&'a RegistrationInfo> {
+	let (cached, uncached): (Vec<_>, Vec<_>) = {
+		let cache = self
+			.appservice_in_room_cache
+			.read()
+			.expect("locked");
+
+		let room = cache.get(room_id);
+		appservices
+			.iter()
+			.map(|appservice| {
+				let in_room = room
+					.and_then(|map| map.get(&appservice.registration.id))
+					.copied();
+
+				(appservice, in_room)
+			})
+			.partition(|(_, in_room)| in_room.is_some())
+	};
+
+	let mut in_room: Vec<_> = cached
+		.into_iter()
+		.filter_map(|(appservice, in_room)| in_room?.then_some(appservice))
+		.collect();
+
+	if uncached.is_empty() {
+		return in_room;
+	}
+
+	let mut found = vec![false; uncached.len()];
+	self.room_members(room_id)
+		.ready_for_each(|user_id| {
+			let local = self.services.globals.user_is_local(user_id);
+			for ((appservice, _), found) in uncached.iter().zip(found.iter_mut()) {
+				*found |= appservice.users.is_match(user_id.as_str())
+					|| (local && appservice.registration.sender_localpart == user_id.localpart());
+			}
+		})
+		.await;
+
+	let mut cache = self
+		.appservice_in_room_cache
+		.write()
+		.expect("locked");
+
+	let room = cache.entry(room_id.into()).or_default();
+	for ((appservice, _), found) in uncached.into_iter().zip(found) {
+		room.insert(appservice.registration.id.clone(), found);
+		if found {
+			in_room.push(appservice);
+		}
+	}
+
+	in_room
+}
+
 #[implement(Service)]
 pub fn get_appservice_in_room_cache_usage(&self) -> (usize, usize) {
 	let cache = self
@@ -700,6 +767,10 @@ pub async fn delete_room_join_counts(&self, room_id: &RoomId, force: bool) -> Re
 
 	self.db.roomid_joinedcount.remove(room_id);
 
+	self.services
+		.state_accessor
+		.delete_summary(room_id);
+
 	self.db
 		.roomserverids
 		.keys_prefix(&prefix)
@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+
+use futures::{StreamExt, future::join3, pin_mut};
+use ruma::{
+	OwnedUserId, RoomId, UserId,
+	events::{
+		StateEventType,
+		room::member::{MembershipState, RoomMemberEventContent},
+	},
+};
+use tuwunel_core::{
+	Result, debug, implement,
+	matrix::{Event, PduCount},
+	utils::ReadyExt,
+};
+
+/// Number of members rebuilt before yielding to other tasks.
+const REBUILD_BATCH: usize = 256;
+
+/// Outcome of [`super::Service::rebuild_room`].
+#[derive(Debug, Default)]
+pub struct Rebuilt {
+	/// Members with a membership event in the room's current state.
+	pub members: usize,
+
+	/// Memberships which were recorded otherwise and were corrected.
+	pub corrected: usize,
+}
+
+/// Recomputes the memberships recorded for a room, and the servers in it, from
+/// the membership events of its current state. Memberships recorded for users
+/// without a membership event are marked left.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn rebuild_room(&self, room_id: &RoomId) -> Result<Rebuilt> {
+	let mut rebuilt = Rebuilt::default();
+	let mut in_state = HashSet::new();
+
+	let members = self
+		.services
+		.state_accessor
+		.room_state_type_pdus(room_id, &StateEventType::RoomMember)
+		.ready_chunks(REBUILD_BATCH);
+
+	pin_mut!(members);
+	while let Some(batch) = members.next().await {
+		for pdu in batch {
+			let pdu = pdu?;
+			let Some(Ok(user_id)) = pdu.state_key().map(UserId::parse) else {
+				continue;
+			};
+
+			let Ok(content) = pdu.get_content::<RoomMemberEventContent>() else {
+				continue;
+			};
+
+			rebuilt.members = rebuilt.members.saturating_add(1);
+			if self
+				.rebuild_membership(room_id, &user_id, &pdu, &content.membership)
+				.await
+			{
+				rebuilt.corrected = rebuilt.corrected.saturating_add(1);
+			}
+
+			in_state.insert(user_id);
+		}
+
+		tokio::task::yield_now().await;
+	}
+
+	let stale: Vec<OwnedUserId> = self
+		.room_members(room_id)
+		.chain(self.room_members_invited(room_id))
+		.chain(self.room_members_knocked(room_id))
+		.ready_filter(|user_id| !in_state.contains(*user_id))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for user_id in &stale {
+		debug!(%user_id, "Membership recorded without a membership event");
+		let count = self.services.globals.next_count();
+		self.mark_as_left(user_id, room_id, PduCount::Normal(*count));
+		rebuilt.corrected = rebuilt.corrected.saturating_add(1);
+	}
+
+	self.update_joined_count(room_id).await;
+
+	Ok(rebuilt)
+}
+
+/// Records a member's membership as that of their membership event, unless
+/// it already is. Returns whether it was corrected.
+#[implement(super::Service)]
+async fn rebuild_membership<Pdu: Event>(
+	&self,
+	room_id: &RoomId,
+	user_id: &UserId,
+	pdu: &Pdu,
+	membership: &MembershipState,
+) -> bool {
+	let (joined, invited, knocked) = join3(
+		self.is_joined(user_id, room_id),
+		self.is_invited(user_id, room_id),
+		self.is_knocked(user_id, room_id),
+	)
+	.await;
+
+	let consistent = match membership {
+		| MembershipState::Join => joined && !invited && !knocked,
+		| MembershipState::Invite => invited && !joined && !knocked,
+		| MembershipState::Knock => knocked && !joined && !invited,
+		| _ => !joined && !invited && !knocked,
+	};
+
+	if consistent {
+		return false;
+	}
+
+	debug!(%user_id, ?membership, joined, invited, knocked, "Correcting membership");
+	let count = self.services.globals.next_count();
+	let count = PduCount::Normal(*count);
+	match membership {
+		| MembershipState::Join => {
+			self.mark_as_once_joined(user_id, room_id);
+			self.mark_as_joined(user_id, room_id, count);
+		},
+		| MembershipState::Invite => {
+			let invite_state = self.services.state.summary_stripped(pdu).await;
+			self.mark_as_invited(user_id, room_id, count, Some(invite_state), None)
+				.await;
+		},
+		| MembershipState::Knock => {
+			let knock_state = self.services.state.summary_stripped(pdu).await;
+			self.mark_as_knocked(user_id, room_id, count, Some(knock_state));
+		},
+		| _ => self.mark_as_left(user_id, room_id, count),
+	}
+
+	true
+}
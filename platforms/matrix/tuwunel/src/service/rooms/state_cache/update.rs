@@ -7,16 +7,41 @@
 		AnyStrippedStateEvent, AnySyncStateEvent, GlobalAccountDataEventType,
 		RoomAccountDataEventType, StateEventType,
 		direct::DirectEvent,
+		push_rules::PushRulesEvent,
 		room::{
 			create::RoomCreateEventContent,
 			member::{MembershipState, RoomMemberEventContent},
 		},
 	},
+	push::{
+		Action, NewConditionalPushRule, NewPushRule, NewSimplePushRule, PushCondition, RuleKind,
+	},
 	serde::Raw,
 };
+use serde::Deserialize;
 use tuwunel_core::{Result, implement, is_not_empty, matrix::PduCount, utils::ReadyExt, warn};
 use tuwunel_database::{Json, serialize_key};
 
+/// State event by which room admins suggest how members are notified of
+/// messages in the room, e.g. `{"notify": "mentions_only"}` for a busy
+/// announcement room.
+const NOTIFICATION_DEFAULTS: &str = "org.tuwunel.room.notification_defaults";
+
+#[derive(Debug, Deserialize)]
+struct NotificationDefaultsContent {
+	notify: NotifyDefault,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum NotifyDefault {
+	/// Notified of every message.
+	All,
+
+	/// Notified only of mentions and keywords.
+	MentionsOnly,
+}
+
 /// Update current membership data.
 #[implement(super::Service)]
 #[tracing::instrument(
@@ -122,6 +147,19 @@ pub async fn update_membership(
 								.await?;
 						}
 					}
+
+					self.copy_room_push_rules(user_id, &predecessor.room_id, room_id)
+						.await;
+				}
+
+				if self
+					.services
+					.config
+					.apply_room_notification_defaults
+					&& self.services.globals.user_is_local(user_id)
+				{
+					self.apply_notification_defaults(user_id, room_id)
+						.await;
 				}
 			}
 
@@ -203,6 +241,11 @@ pub async fn update_joined_count(&self, room_id: &RoomId) {
 		.roomid_knockedcount
 		.raw_put(room_id, knockedcount);
 
+	self.services
+		.state_accessor
+		.update_summary(room_id, joinedcount, invitedcount)
+		.await;
+
 	self.room_servers(room_id)
 		.ready_for_each(|old_joined_server| {
 			if joined_servers.remove(old_joined_server) {
@@ -323,7 +366,7 @@ pub(crate) fn mark_as_left(&self, user_id: &UserId, room_id: &RoomId, count: Pdu
 /// `update_membership` instead
 #[implement(super::Service)]
 #[tracing::instrument(skip(self), level = "debug")]
-pub(crate) fn _mark_as_knocked(
+pub(crate) fn mark_as_knocked(
 	&self,
 	user_id: &UserId,
 	room_id: &RoomId,
@@ -376,7 +419,7 @@ pub fn forget(&self, room_id: &RoomId, user_id: &UserId) {
 
 #[implement(super::Service)]
 #[tracing::instrument(level = "debug", skip(self))]
-fn mark_as_once_joined(&self, user_id: &UserId, room_id: &RoomId) {
+pub(super) fn mark_as_once_joined(&self, user_id: &UserId, room_id: &RoomId) {
 	let key = (user_id, room_id);
 	self.db.roomuseroncejoinedids.put_raw(key, []);
 }
@@ -426,3 +469,176 @@ pub(crate) async fn mark_as_invited(
 			.await;
 	}
 }
+
+/// Copies the user's push rules scoped to a room, such as a mute, to the room
+/// which replaced it in an upgrade: the room rule, and the override and
+/// underride rules matching the room's ID. Content and sender rules are not
+/// scoped to a room.
+#[implement(super::Service)]
+async fn copy_room_push_rules(&self, user_id: &UserId, from: &RoomId, to: &RoomId) {
+	let Ok(mut event) = self
+		.services
+		.account_data
+		.get_global::<PushRulesEvent>(user_id, GlobalAccountDataEventType::PushRules)
+		.await
+	else {
+		return;
+	};
+
+	let ruleset = &mut event.content.global;
+	let mut new_rules = Vec::new();
+	if let Some(rule) = ruleset
+		.room
+		.iter()
+		.find(|rule| rule.rule_id == from)
+	{
+		let new_rule = NewSimplePushRule::new(to.to_owned(), rule.actions.clone());
+		new_rules.push((
+			NewPushRule::Room(new_rule),
+			RuleKind::Room,
+			to.to_string(),
+			rule.enabled,
+		));
+	}
+
+	let conditional = [
+		(RuleKind::Override, &ruleset.override_),
+		(RuleKind::Underride, &ruleset.underride),
+	];
+	for (kind, rules) in conditional {
+		for rule in rules.iter().filter(|rule| !rule.default) {
+			let Some(conditions) = rescope_conditions(&rule.conditions, from, to) else {
+				continue;
+			};
+
+			let rule_id = rule.rule_id.replace(from.as_str(), to.as_str());
+			let new_rule =
+				NewConditionalPushRule::new(rule_id.clone(), conditions, rule.actions.clone());
+
+			let new_rule = match kind {
+				| RuleKind::Override => NewPushRule::Override(new_rule),
+				| _ => NewPushRule::Underride(new_rule),
+			};
+
+			new_rules.push((new_rule, kind, rule_id, rule.enabled));
+		}
+	}
+
+	let mut copied = false;
+	for (new_rule, kind, rule_id, enabled) in new_rules {
+		if ruleset.insert(new_rule, None, None).is_err() {
+			continue;
+		}
+
+		ruleset.set_enabled(kind, &rule_id, enabled).ok();
+		copied = true;
+	}
+
+	if !copied {
+		return;
+	}
+
+	self.services
+		.account_data
+		.update(
+			None,
+			user_id,
+			GlobalAccountDataEventType::PushRules
+				.to_string()
+				.into(),
+			&serde_json::to_value(&event).expect("to json always works"),
+		)
+		.await
+		.ok();
+}
+
+/// The conditions of a rule scoped to the room `from` made to match the room
+/// `to` instead, or None when the rule is not scoped to `from`.
+fn rescope_conditions(
+	conditions: &[PushCondition],
+	from: &RoomId,
+	to: &RoomId,
+) -> Option<Vec<PushCondition>> {
+	let scoped = |condition: &PushCondition| {
+		matches!(
+			condition,
+			PushCondition::EventMatch { key, pattern }
+				if key == "room_id" && pattern == from.as_str()
+		)
+	};
+
+	conditions.iter().any(scoped).then(|| {
+		conditions
+			.iter()
+			.map(|condition| {
+				if scoped(condition) {
+					PushCondition::EventMatch {
+						key: "room_id".to_owned(),
+						pattern: to.as_str().to_owned(),
+					}
+				} else {
+					condition.clone()
+				}
+			})
+			.collect()
+	})
+}
+
+/// Gives the user the push rule for the room suggested by its
+/// `NOTIFICATION_DEFAULTS` state event, unless the user has one for the room.
+#[implement(super::Service)]
+async fn apply_notification_defaults(&self, user_id: &UserId, room_id: &RoomId) {
+	let Ok(content) = self
+		.services
+		.state_accessor
+		.room_state_get_content::<NotificationDefaultsContent>(
+			room_id,
+			&StateEventType::from(NOTIFICATION_DEFAULTS),
+			"",
+		)
+		.await
+	else {
+		return;
+	};
+
+	let Ok(mut event) = self
+		.services
+		.account_data
+		.get_global::<PushRulesEvent>(user_id, GlobalAccountDataEventType::PushRules)
+		.await
+	else {
+		return;
+	};
+
+	let ruleset = &mut event.content.global;
+	if ruleset
+		.room
+		.iter()
+		.any(|rule| rule.rule_id == room_id)
+	{
+		return;
+	}
+
+	let actions = match content.notify {
+		| NotifyDefault::All => vec![Action::Notify],
+		| NotifyDefault::MentionsOnly => vec![],
+	};
+
+	let new_rule = NewPushRule::Room(NewSimplePushRule::new(room_id.to_owned(), actions));
+	if ruleset.insert(new_rule, None, None).is_err() {
+		return;
+	}
+
+	self.services
+		.account_data
+		.update(
+			None,
+			user_id,
+			GlobalAccountDataEventType::PushRules
+				.to_string()
+				.into(),
+			&serde_json::to_value(&event).expect("to json always works"),
+		)
+		.await
+		.ok();
+}
@@ -175,6 +175,10 @@ pub async fn append_pdu<'a, Leafs>(
 	// Insert pdu
 	self.append_pdu_json(&pdu_id, pdu, &pdu_json, count);
 
+	self.services
+		.event_export
+		.append_pdu(*next_count1, &pdu_id);
+
 	drop(insert_lock);
 
 	self.services
@@ -242,10 +246,13 @@ async fn append_pdu_effects(
 			if let Some(_state_key) = pdu.state_key() {
 				self.services
 					.spaces
-					.roomid_spacehierarchy_cache
-					.lock()
-					.await
-					.remove(pdu.room_id());
+					.invalidate(pdu.room_id())
+					.await;
+
+				self.services
+					.spaces
+					.mirror_child_changed(pdu)
+					.await;
 			},
 		| TimelineEventType::RoomMember => {
 			if let Some(state_key) = pdu.state_key() {
@@ -267,6 +274,16 @@ async fn append_pdu_effects(
 				// Update our membership info, we do this here incase a user is invited or
 				// knocked and immediately leaves we need the DB to record the invite or
 				// knock event for auth
+				self.services
+					.spaces
+					.mirror_membership_changed(
+						pdu.room_id(),
+						target_user_id,
+						pdu.sender(),
+						&content.membership,
+					)
+					.await;
+
 				self.services
 					.state_cache
 					.update_membership(
@@ -297,7 +314,7 @@ async fn append_pdu_effects(
 				{
 					self.services
 						.admin
-						.command(body, Some((pdu.event_id()).into()))
+						.command(body, Some(pdu.event_id().into()), Some(pdu.sender().into()))
 						.await?;
 				}
 			}
@@ -11,10 +11,10 @@
 };
 use serde_json::value::RawValue as RawJsonValue;
 use tuwunel_core::{
-	Result, at, debug, debug_info, debug_warn, implement, is_false,
+	Err, Result, at, debug, debug_info, debug_warn, implement, is_false,
 	matrix::{
 		event::Event,
-		pdu::{PduCount, PduId, RawPduId},
+		pdu::{PduCount, PduEvent, PduId, RawPduId},
 	},
 	utils::{
 		BoolExt, IterStream, ReadyExt,
@@ -39,9 +39,29 @@ pub async fn backfill_if_required(&self, room_id: &RoomId, from: PduCount) -> Re
 		return Ok(());
 	}
 
+	match self.backfill(room_id, &first_pdu).await {
+		| Ok(0) => {},
+		| Ok(_) => self.prefetch_backfill(room_id),
+		| Err(e) => warn!("{e}"),
+	}
+
+	Ok(())
+}
+
+/// Backfills the history preceding `first_pdu`, the earliest event in the
+/// room. Returns the number of events received, zero when there is nothing
+/// to backfill, or an error when no server could backfill.
+#[implement(super::Service)]
+#[tracing::instrument(level = "debug", skip(self, first_pdu))]
+pub(super) async fn backfill(&self, room_id: &RoomId, first_pdu: &PduEvent) -> Result<usize> {
 	// No backfill required, reached the end.
 	if *first_pdu.event_type() == TimelineEventType::RoomCreate {
-		return Ok(());
+		return Ok(0);
+	}
+
+	// The history before the first event was purged on purpose.
+	if self.history_purged(room_id).await {
+		return Ok(0);
 	}
 
 	let empty_room = self
@@ -58,7 +78,7 @@ pub async fn backfill_if_required(&self, room_id: &RoomId, from: PduCount) -> Re
 
 	// Room is empty (1 user or none), there is no one that can backfill
 	if empty_room.and(not_world_readable).await {
-		return Ok(());
+		return Ok(0);
 	}
 
 	let canonical_alias = self
@@ -151,7 +171,8 @@ pub async fn backfill_if_required(&self, room_id: &RoomId, from: PduCount) -> Re
 			})
 			.await
 		{
-			return response
+			let received = response.pdus.len();
+			response
 				.pdus
 				.into_iter()
 				.stream()
@@ -163,14 +184,13 @@ pub async fn backfill_if_required(&self, room_id: &RoomId, from: PduCount) -> Re
 						debug_warn!("Failed to add backfilled pdu in room {room_id}: {e}");
 					}
 				})
-				.map(Ok)
 				.await;
+
+			return Ok(received);
 		}
 	}
 
-	warn!("No servers could backfill, but backfill was needed in room {room_id}");
-
-	Ok(())
+	Err!("No servers could backfill, but backfill was needed in room {room_id}")
 }
 
 #[implement(super::Service)]
@@ -143,6 +143,16 @@ pub async fn build_and_append_pdu(
 		.state
 		.set_room_state(pdu.room_id(), statehashid, state_lock);
 
+	// Events of shadow-banned users stay on this server.
+	if self
+		.services
+		.users
+		.is_shadow_banned(pdu.sender())
+		.await
+	{
+		return Ok(pdu.event_id().to_owned());
+	}
+
 	let mut servers: HashSet<OwnedServerName> = self
 		.services
 		.state_cache
@@ -43,6 +43,11 @@ pub async fn create_hash_and_sign_event(
 		timestamp,
 	} = pdu_builder;
 
+	let content = self
+		.services
+		.sanitize
+		.event_content(&event_type, content)?;
+
 	let prev_events: PrevEvents = self
 		.services
 		.state
@@ -2,9 +2,16 @@
 mod backfill;
 mod build;
 mod create;
+mod prefetch;
+mod purge;
 mod redact;
 
-use std::{borrow::Borrow, fmt::Write, sync::Arc};
+use std::{
+	borrow::Borrow,
+	collections::HashSet,
+	fmt::Write,
+	sync::{Arc, Mutex},
+};
 
 use async_trait::async_trait;
 use futures::{
@@ -43,12 +50,16 @@ pub struct Service {
 	services: Arc<crate::services::OnceServices>,
 	db: Data,
 	pub mutex_insert: RoomMutexMap,
+	prefetch_channel: (loole::Sender<OwnedRoomId>, loole::Receiver<OwnedRoomId>),
+	prefetching: Mutex<HashSet<OwnedRoomId>>,
 }
 
 struct Data {
 	eventid_outlierpdu: Arc<Map>,
 	eventid_pduid: Arc<Map>,
 	pduid_pdu: Arc<Map>,
+	roomid_backfillbackoff: Arc<Map>,
+	roomid_purgedbefore: Arc<Map>,
 	db: Arc<Database>,
 }
 
@@ -87,19 +98,37 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 				eventid_outlierpdu: args.db["eventid_outlierpdu"].clone(),
 				eventid_pduid: args.db["eventid_pduid"].clone(),
 				pduid_pdu: args.db["pduid_pdu"].clone(),
+				roomid_backfillbackoff: args.db["roomid_backfillbackoff"].clone(),
+				roomid_purgedbefore: args.db["roomid_purgedbefore"].clone(),
 				db: args.db.clone(),
 			},
 			mutex_insert: RoomMutexMap::new(),
+			prefetch_channel: loole::unbounded(),
+			prefetching: Mutex::new(HashSet::new()),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result { self.prefetch_worker().await }
+
+	async fn interrupt(&self) {
+		let (sender, _) = &self.prefetch_channel;
+		if !sender.is_closed() {
+			sender.close();
+		}
+	}
+
 	async fn memory_usage(&self, out: &mut (dyn Write + Send)) -> Result {
 		let mutex_insert = self.mutex_insert.len();
 		writeln!(out, "insert_mutex: {mutex_insert}")?;
 
+		let prefetching = self.prefetching.lock().expect("locked").len();
+		writeln!(out, "backfill_prefetching: {prefetching}")?;
+
 		Ok(())
 	}
 
+	fn background(&self) -> bool { true }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
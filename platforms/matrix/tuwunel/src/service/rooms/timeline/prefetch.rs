@@ -0,0 +1,136 @@
+//! Background backfill of the history preceding a room's earliest local event,
+//! queued once a client has paginated past it, so /messages for a freshly
+//! joined room returns full chunks rather than waiting on federation for each
+//! page. Rooms no server could backfill are retried with an exponential
+//! backoff recorded in `roomid_backfillbackoff`.
+
+use std::time::Duration;
+
+use futures::{StreamExt, stream};
+use ruma::{OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{
+	Result, debug, debug_warn, implement,
+	utils::{continue_exponential_backoff_secs, time::now_secs},
+};
+use tuwunel_database::{Deserialized, Json};
+
+/// Failures to backfill a room while prefetching.
+#[derive(Deserialize, Serialize)]
+struct Backoff {
+	tries: u32,
+	failed_at: u64,
+}
+
+/// Queues the room for its history to be backfilled in the background.
+#[implement(super::Service)]
+pub fn prefetch_backfill(&self, room_id: &RoomId) {
+	if !self.services.config.backfill_prefetch {
+		return;
+	}
+
+	if !self
+		.prefetching
+		.lock()
+		.expect("locked")
+		.insert(room_id.to_owned())
+	{
+		return;
+	}
+
+	let (sender, _) = &self.prefetch_channel;
+	if sender.send(room_id.to_owned()).is_err() {
+		self.prefetching
+			.lock()
+			.expect("locked")
+			.remove(room_id);
+	}
+}
+
+/// Prefetches the queued rooms, a bounded number at once, until the channel
+/// is closed.
+#[implement(super::Service)]
+pub(super) async fn prefetch_worker(&self) -> Result {
+	let concurrency = self
+		.services
+		.config
+		.backfill_prefetch_concurrency
+		.max(1);
+
+	let (_, receiver) = &self.prefetch_channel;
+	stream::unfold(receiver.clone(), async |receiver| {
+		let room_id = receiver.recv_async().await.ok()?;
+		Some((room_id, receiver))
+	})
+	.for_each_concurrent(concurrency, async |room_id: OwnedRoomId| {
+		self.prefetch(&room_id).await;
+		self.prefetching
+			.lock()
+			.expect("locked")
+			.remove(&room_id);
+	})
+	.await;
+
+	Ok(())
+}
+
+#[implement(super::Service)]
+#[tracing::instrument(name = "prefetch", level = "debug", skip(self))]
+async fn prefetch(&self, room_id: &RoomId) {
+	let backoff: Option<Backoff> = self
+		.db
+		.roomid_backfillbackoff
+		.get(room_id)
+		.await
+		.deserialized()
+		.ok();
+
+	let config = &self.services.config;
+	if let Some(Backoff { tries, failed_at }) = backoff {
+		let elapsed = Duration::from_secs(now_secs().saturating_sub(failed_at));
+		let min = config.backfill_prefetch_backoff_min;
+		let max = config.backfill_prefetch_backoff_max;
+		if continue_exponential_backoff_secs(min, max, elapsed, tries) {
+			debug!(tries, "Backing off prefetching room");
+			return;
+		}
+	}
+
+	let mut received = 0_usize;
+	for _ in 0..config.backfill_prefetch_depth {
+		if !self.services.server.running() {
+			return;
+		}
+
+		let Ok((_, first_pdu)) = self.first_item_in_room(room_id).await else {
+			return;
+		};
+
+		match self.backfill(room_id, &first_pdu).await {
+			| Ok(0) => break,
+			| Ok(count) => received = received.saturating_add(count),
+			| Err(e) => {
+				debug_warn!("Prefetching failed: {e}");
+				let tries = backoff
+					.as_ref()
+					.map_or(0, |backoff| backoff.tries);
+				let backoff = Backoff {
+					tries: tries.saturating_add(1),
+					failed_at: now_secs(),
+				};
+
+				self.db
+					.roomid_backfillbackoff
+					.raw_put(room_id, Json(&backoff));
+
+				return;
+			},
+		}
+	}
+
+	if backoff.is_some() {
+		self.db.roomid_backfillbackoff.remove(room_id);
+	}
+
+	debug!(received, "Prefetched room history");
+}
@@ -0,0 +1,389 @@
+use std::collections::{BTreeSet, HashSet};
+
+use futures::{FutureExt, Stream, StreamExt};
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedEventId, RoomId, ServerName, UserId,
+	api::Direction,
+	events::{TimelineEventType, room::encrypted::Relation},
+};
+use tuwunel_core::{
+	Result, at, debug, implement,
+	matrix::{
+		Event,
+		pdu::{PduCount, PduEvent, PduId},
+	},
+	trace,
+	utils::stream::{BroadbandExt, IterStream, ReadyExt, TryIgnore},
+};
+
+use super::{
+	ExtractBody, ExtractRelatesTo, ExtractRelatesToEventId, PdusIterItem, RawPduId,
+	RoomMutexGuard,
+};
+use crate::rooms::short::ShortRoomId;
+
+/// Number of pdus purged under one hold of the room's state lock.
+const PURGE_BATCH: usize = 1024;
+
+/// Number of consecutive events at or after the cutoff which end a walk for
+/// the older events of a timeline; a few events with skewed timestamps among
+/// the older ones do not end it.
+const SKEW_TOLERANCE: usize = 128;
+
+/// Removes a pdu from the timeline along with its entries in the search index,
+/// its relations and its references. The pdu can no longer be fetched by its
+/// id. The room state is left untouched; callers must not purge state events.
+/// The notifications it raised are left to the caller, which forgets those of
+/// a whole batch at once with `purge_notifications`.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, pdu, _state_lock), level = "debug")]
+pub async fn purge_pdu(
+	&self,
+	shortroomid: ShortRoomId,
+	pdu_id: &RawPduId,
+	pdu: &PduEvent,
+	_state_lock: &RoomMutexGuard,
+) -> Result {
+	debug_assert!(pdu.state_key().is_none(), "State events must not be purged");
+
+	self.unlink_pdu(shortroomid, pdu_id, pdu).await;
+
+	trace!(event_id = ?pdu.event_id(), "Purging PDU");
+	self.db.pduid_pdu.remove(pdu_id);
+	self.db.eventid_pduid.remove(pdu.event_id());
+	self.db.eventid_outlierpdu.remove(pdu.event_id());
+
+	Ok(())
+}
+
+/// Takes a state event out of the timeline, keeping it as an outlier so that
+/// it can still be fetched by its id for the auth chains and the state of the
+/// remaining events.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, pdu, _state_lock), level = "debug")]
+pub async fn outlier_pdu(
+	&self,
+	shortroomid: ShortRoomId,
+	pdu_id: &RawPduId,
+	pdu: &PduEvent,
+	_state_lock: &RoomMutexGuard,
+) -> Result {
+	let pdu_json = self.get_pdu_json_from_id(pdu_id).await?;
+
+	self.unlink_pdu(shortroomid, pdu_id, pdu).await;
+
+	trace!(event_id = ?pdu.event_id(), "Taking PDU out of the timeline");
+	self.add_pdu_outlier(pdu.event_id(), &pdu_json);
+	self.db.pduid_pdu.remove(pdu_id);
+	self.db.eventid_pduid.remove(pdu.event_id());
+
+	Ok(())
+}
+
+/// Removes the entries of a pdu in the search index, its relations and its
+/// references.
+#[implement(super::Service)]
+async fn unlink_pdu(&self, shortroomid: ShortRoomId, pdu_id: &RawPduId, pdu: &PduEvent) {
+	if *pdu.kind() == TimelineEventType::RoomMessage
+		&& let Ok(ExtractBody { body: Some(body) }) = pdu.get_content()
+	{
+		self.services
+			.search
+			.deindex_pdu(shortroomid, pdu_id, &body);
+	}
+
+	let count = pdu_id.pdu_count();
+	self.services
+		.pdu_metadata
+		.delete_relations_to(count)
+		.await;
+
+	if let Ok(content) = pdu.get_content::<ExtractRelatesToEventId>()
+		&& let Ok(related) = self
+			.get_pdu_count(&content.relates_to.event_id)
+			.await
+	{
+		self.services
+			.pdu_metadata
+			.delete_relation(count, related);
+	}
+
+	if let Ok(ExtractRelatesTo {
+		relates_to: Relation::Reply { in_reply_to },
+	}) = pdu.get_content()
+		&& let Ok(related) = self.get_pdu_count(&in_reply_to.event_id).await
+	{
+		self.services
+			.pdu_metadata
+			.delete_relation(count, related);
+	}
+
+	self.services
+		.pdu_metadata
+		.unmark_as_referenced(pdu.room_id(), pdu.event_id());
+}
+
+/// Forgets the notifications raised by the pdus of a room purged at `counts`.
+#[implement(super::Service)]
+pub async fn purge_notifications<I>(&self, room_id: &RoomId, counts: I)
+where
+	I: IntoIterator<Item = PduCount> + Send,
+{
+	let counts: BTreeSet<u64> = counts
+		.into_iter()
+		.filter(|count| matches!(count, PduCount::Normal(_)))
+		.map(PduCount::into_unsigned)
+		.collect();
+
+	self.services
+		.pusher
+		.delete_notifications(room_id, &counts)
+		.await;
+}
+
+/// The pdus of a room sent by users of a server, at or after `since` when
+/// given, which can be purged: state events and the forward extremities of
+/// the room are left out.
+#[implement(super::Service)]
+pub fn server_pdus<'a>(
+	&'a self,
+	room_id: &'a RoomId,
+	server_name: &'a ServerName,
+	since: Option<MilliSecondsSinceUnixEpoch>,
+) -> impl Stream<Item = (PduCount, PduEvent)> + Send + 'a {
+	self.purgeable_pdus(room_id, since, move |sender| sender.server_name() == server_name)
+}
+
+/// The pdus of a room sent by a user which can be purged; see `server_pdus`.
+#[implement(super::Service)]
+pub fn user_pdus<'a>(
+	&'a self,
+	room_id: &'a RoomId,
+	user_id: &'a UserId,
+	since: Option<MilliSecondsSinceUnixEpoch>,
+) -> impl Stream<Item = (PduCount, PduEvent)> + Send + 'a {
+	self.purgeable_pdus(room_id, since, move |sender| sender == user_id)
+}
+
+#[implement(super::Service)]
+fn purgeable_pdus<'a, F>(
+	&'a self,
+	room_id: &'a RoomId,
+	since: Option<MilliSecondsSinceUnixEpoch>,
+	is_sender: F,
+) -> impl Stream<Item = (PduCount, PduEvent)> + Send + 'a
+where
+	F: Fn(&UserId) -> bool + Send + 'a,
+{
+	self.services
+		.state
+		.get_forward_extremities(room_id)
+		.map(ToOwned::to_owned)
+		.collect::<HashSet<OwnedEventId>>()
+		.map(move |extremities| {
+			self.pdus(None, room_id, None)
+				.ignore_err()
+				.ready_filter(move |(_, pdu)| {
+					is_sender(pdu.sender())
+						&& pdu.state_key().is_none()
+						&& since.is_none_or(|since| pdu.origin_server_ts() >= since)
+						&& !extremities.contains(pdu.event_id())
+				})
+		})
+		.flatten_stream()
+}
+
+/// Purges the pdus of a room with the given ids, taking its state lock for
+/// each batch; see `purge_pdu`. Pdus already purged, of another room or state
+/// events are skipped. Returns the number purged. A pdu is unlinked before it
+/// is removed, so purging again after an interruption leaves no dangling
+/// entries.
+#[implement(super::Service)]
+pub async fn purge_pdus(&self, room_id: &RoomId, event_ids: &[OwnedEventId]) -> Result<usize> {
+	let shortroomid = self
+		.services
+		.short
+		.get_shortroomid(room_id)
+		.await?;
+
+	let mut purged = 0_usize;
+	for batch in event_ids.chunks(PURGE_BATCH) {
+		let state_lock = self.services.state.mutex.lock(room_id).await;
+		let pdus: Vec<(RawPduId, PduEvent)> = batch
+			.iter()
+			.stream()
+			.broad_filter_map(async |event_id| {
+				let pdu_id = self.get_pdu_id(event_id).await.ok()?;
+				let pdu = self.get_pdu_from_id(&pdu_id).await.ok()?;
+
+				(pdu.room_id() == room_id && pdu.state_key().is_none()).then_some((pdu_id, pdu))
+			})
+			.collect()
+			.await;
+
+		for (pdu_id, pdu) in &pdus {
+			self.purge_pdu(shortroomid, pdu_id, pdu, &state_lock)
+				.await?;
+		}
+
+		self.purge_notifications(room_id, pdus.iter().map(|(pdu_id, _)| pdu_id.pdu_count()))
+			.await;
+
+		purged = purged.saturating_add(pdus.len());
+	}
+
+	Ok(purged)
+}
+
+/// Purges the history of a room before `before`, along with the read receipts
+/// pointing into it. The events of the current state, the forward extremities
+/// and the latest event are kept. Other state events are taken out of the
+/// timeline but kept as outliers, unless `keep_state`, in which case they stay
+/// in the timeline. The room is no longer backfilled, so that clients
+/// paginating back reach the end of its history at the purge point. Returns
+/// the numbers of events purged and of state events taken out of the timeline.
+#[implement(super::Service)]
+pub async fn purge_history(
+	&self,
+	room_id: &RoomId,
+	before: MilliSecondsSinceUnixEpoch,
+	keep_state: bool,
+) -> Result<(usize, usize)> {
+	let shortroomid = self
+		.services
+		.short
+		.get_shortroomid(room_id)
+		.await?;
+
+	let shortstatehash = self
+		.services
+		.state
+		.get_room_shortstatehash(room_id)
+		.await?;
+
+	let mut kept: HashSet<OwnedEventId> = self
+		.services
+		.state_accessor
+		.state_full_ids(shortstatehash)
+		.map(at!(1))
+		.collect()
+		.await;
+
+	self.services
+		.state
+		.get_forward_extremities(room_id)
+		.ready_for_each(|event_id| {
+			kept.insert(event_id.to_owned());
+		})
+		.await;
+
+	kept.insert(self.latest_pdu_in_room(room_id).await?.event_id);
+
+	self.db
+		.roomid_purgedbefore
+		.put(room_id, u64::from(before.get()));
+
+	let receipted = self
+		.services
+		.read_receipt
+		.receipted_events(room_id)
+		.await;
+
+	let (mut purged, mut outliers, mut from) = (0_usize, 0_usize, None);
+	let mut purged_receipted = HashSet::new();
+	loop {
+		let state_lock = self.services.state.mutex.lock(room_id).await;
+		let (batch, next) = self
+			.pdus_before(room_id, before, from, PURGE_BATCH)
+			.await;
+
+		let mut purged_counts = Vec::new();
+		for (count, pdu) in &batch {
+			if kept.contains(pdu.event_id()) || (keep_state && pdu.state_key().is_some()) {
+				continue;
+			}
+
+			let pdu_id: RawPduId = PduId { shortroomid, count: *count }.into();
+			if pdu.state_key().is_some() {
+				self.outlier_pdu(shortroomid, &pdu_id, pdu, &state_lock)
+					.await?;
+
+				outliers = outliers.saturating_add(1);
+			} else {
+				self.purge_pdu(shortroomid, &pdu_id, pdu, &state_lock)
+					.await?;
+
+				if receipted.contains(pdu.event_id()) {
+					purged_receipted.insert(pdu.event_id().to_owned());
+				}
+
+				purged_counts.push(*count);
+			}
+		}
+
+		purged = purged.saturating_add(purged_counts.len());
+		self.purge_notifications(room_id, purged_counts)
+			.await;
+
+		from = next;
+		if from.is_none() {
+			break;
+		}
+	}
+
+	self.services
+		.read_receipt
+		.delete_receipts_to(room_id, &purged_receipted)
+		.await;
+
+	debug!(%room_id, ?before, purged, outliers, "Purged room history");
+	Ok((purged, outliers))
+}
+
+/// Events of the room older than `before` in timeline order from `from`, at
+/// most `limit` of them, with the count from which to resume the walk; None
+/// once it went past the older events. Events at or after `before` are
+/// skipped, the walk only ending after [`SKEW_TOLERANCE`] of them in a row.
+#[implement(super::Service)]
+pub async fn pdus_before(
+	&self,
+	room_id: &RoomId,
+	before: MilliSecondsSinceUnixEpoch,
+	from: Option<PduCount>,
+	limit: usize,
+) -> (Vec<PdusIterItem>, Option<PduCount>) {
+	let mut pdus = self
+		.pdus(None, room_id, from)
+		.ignore_err()
+		.boxed();
+
+	let (mut batch, mut skipped) = (Vec::new(), 0_usize);
+	while let Some((count, pdu)) = pdus.next().await {
+		if pdu.origin_server_ts() >= before {
+			skipped = skipped.saturating_add(1);
+			if skipped >= SKEW_TOLERANCE {
+				break;
+			}
+
+			continue;
+		}
+
+		skipped = 0;
+		batch.push((count, pdu));
+		if batch.len() >= limit {
+			return (batch, Some(count.saturating_inc(Direction::Forward)));
+		}
+	}
+
+	(batch, None)
+}
+
+/// Whether the history of the room was purged, in which case it is not
+/// backfilled.
+#[implement(super::Service)]
+pub async fn history_purged(&self, room_id: &RoomId) -> bool {
+	self.db
+		.roomid_purgedbefore
+		.contains(room_id)
+		.await
+}
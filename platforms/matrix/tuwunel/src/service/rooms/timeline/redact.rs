@@ -67,5 +67,12 @@ pub async fn redact_pdu<Pdu: Event + Send + Sync>(
 	)
 	.map_err(|err| err!("invalid event: {err}"))?;
 
-	self.replace_pdu(&pdu_id, &pdu).await
+	self.replace_pdu(&pdu_id, &pdu).await?;
+
+	// The ACL changed in place; its compiled form is stale.
+	if pdu["type"].as_str() == Some("m.room.server_acl") {
+		self.services.state_accessor.forget_server_acls();
+	}
+
+	Ok(())
 }
@@ -1,71 +1,150 @@
-use std::{collections::BTreeMap, sync::Arc};
+//! Typing notifications.
+//!
+//! Typing state is ephemeral and held only in memory, sharded by room. Entries
+//! expire on a timer rather than being swept when read, and rooms nobody is
+//! typing in are dropped a few minutes after their last change. Changes are
+//! delivered to syncing clients over a broadcast channel, and changes made by
+//! local users are collected per remote server until the sender selects them
+//! into the next federation transaction to that server. Bursts of changes in a
+//! room thereby coalesce into the latest state of each user. Nothing about
+//! typing is written to the database beyond advancing the global count when the
+//! set of typing users changes, which sync requires to order it; refreshing the
+//! timeout of a typing user does not. A federation transaction which fails is
+//! retried without its typing notifications, which remote servers time out
+//! anyway.
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	hash::{DefaultHasher, Hash, Hasher},
+	sync::{
+		Arc, Mutex, RwLock,
+		atomic::{AtomicU64, Ordering},
+	},
+	time::Duration,
+};
 
+use async_trait::async_trait;
 use futures::StreamExt;
 use ruma::{
-	OwnedRoomId, OwnedUserId, RoomId, UserId,
+	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
 	api::federation::transactions::edu::{Edu, TypingContent},
 };
-use tokio::sync::{RwLock, broadcast};
+use tokio::{
+	sync::{Notify, broadcast},
+	time::sleep,
+};
 use tuwunel_core::{
 	Result, Server, debug_info, trace,
-	utils::{self, IterStream},
+	utils::{self, IterStream, ReadyExt, math::usize_from_u64_truncated},
 };
 
-use crate::sending::EduBuf;
+use crate::sending::{EduBuf, EduVec};
 
 pub struct Service {
 	server: Arc<Server>,
 	services: Arc<crate::services::OnceServices>,
-	/// u64 is unix timestamp of timeout
-	pub typing: RwLock<BTreeMap<OwnedRoomId, BTreeMap<OwnedUserId, u64>>>,
-	/// timestamp of the last change to typing users
-	pub last_typing_update: RwLock<BTreeMap<OwnedRoomId, u64>>,
-	pub typing_update_sender: broadcast::Sender<OwnedRoomId>,
+	shards: Box<[RwLock<HashMap<OwnedRoomId, Room>>]>,
+	/// Typing changes of local users awaiting a transaction to each server;
+	/// the value is whether the user is typing.
+	outgoing: Mutex<HashMap<OwnedServerName, BTreeMap<(OwnedRoomId, OwnedUserId), bool>>>,
+	updates: broadcast::Sender<OwnedRoomId>,
+	/// Wakes the expiry timer when a timeout earlier than `deadline` is set.
+	expiry: Notify,
+	/// When the expiry timer is next due.
+	deadline: AtomicU64,
+}
+
+#[derive(Default)]
+struct Room {
+	/// Typing users with the unix timestamp in milliseconds their typing
+	/// expires.
+	users: BTreeMap<OwnedUserId, u64>,
+	/// Count of the last change to the set of typing users.
+	last_update: u64,
+	/// Unix timestamp in milliseconds of the last change.
+	updated_at: u64,
 }
 
+const SHARDS: usize = 64;
+
+/// Longest the expiry timer sleeps when nobody is typing.
+const EXPIRY_IDLE: Duration = Duration::from_secs(60);
+
+/// How long a room nobody is typing in is kept after its last change, so that
+/// clients syncing since before the change still learn that typing stopped.
+const EMPTY_RETAIN_MS: u64 = 5 * 60 * 1000;
+
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			server: args.server.clone(),
 			services: args.services.clone(),
-			typing: RwLock::new(BTreeMap::new()),
-			last_typing_update: RwLock::new(BTreeMap::new()),
-			typing_update_sender: broadcast::channel(100).0,
+			shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+			outgoing: Mutex::default(),
+			updates: broadcast::channel(100).0,
+			expiry: Notify::new(),
+			deadline: AtomicU64::new(u64::MAX),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		while self.server.running() {
+			// Any timeout set while expiring must wake the timer again.
+			self.deadline.store(u64::MAX, Ordering::Release);
+
+			let now = utils::millis_since_unix_epoch();
+			let timeout = self
+				.expire(now)
+				.await
+				.map_or(EXPIRY_IDLE, |next| Duration::from_millis(next.saturating_sub(now)))
+				.min(EXPIRY_IDLE);
+
+			let deadline = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+			self.deadline
+				.store(now.saturating_add(deadline), Ordering::Release);
+
+			tokio::select! {
+				() = sleep(timeout) => {},
+				() = self.expiry.notified() => {},
+				() = self.server.until_shutdown() => return Ok(()),
+			}
+		}
+
+		Ok(())
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+
+	fn dependencies(&self) -> &'static [&'static str] { &["sending"] }
 }
 
 impl Service {
 	/// Sets a user as typing until the timeout timestamp is reached or
-	/// roomtyping_remove is called.
+	/// typing_remove is called.
 	pub async fn typing_add(&self, user_id: &UserId, room_id: &RoomId, timeout: u64) -> Result {
 		debug_info!("typing started {user_id:?} in {room_id:?} timeout:{timeout:?}");
 
-		// update clients
-		self.typing
+		let started = self
+			.shard(room_id)
 			.write()
-			.await
+			.expect("locked")
 			.entry(room_id.to_owned())
 			.or_default()
-			.insert(user_id.to_owned(), timeout);
+			.users
+			.insert(user_id.to_owned(), timeout)
+			.is_none();
 
-		let count = self.services.globals.next_count();
-		self.last_typing_update
-			.write()
-			.await
-			.insert(room_id.to_owned(), *count);
+		if timeout < self.deadline.load(Ordering::Acquire) {
+			self.expiry.notify_one();
+		}
 
-		if self
-			.typing_update_sender
-			.send(room_id.to_owned())
-			.is_err()
-		{
-			trace!("receiver found what it was looking for and is no longer interested");
+		// update clients; extending the timeout of a typing user is not a change
+		if started {
+			self.updated(room_id);
 		}
 
-		// update federation
+		// update federation, which has its own timeout to extend
 		if self.services.globals.user_is_local(user_id) {
 			self.federation_send(room_id, user_id, true)
 				.await?;
@@ -78,28 +157,21 @@ pub async fn typing_add(&self, user_id: &UserId, room_id: &RoomId, timeout: u64)
 	pub async fn typing_remove(&self, user_id: &UserId, room_id: &RoomId) -> Result {
 		debug_info!("typing stopped {user_id:?} in {room_id:?}");
 
-		// update clients
-		self.typing
-			.write()
-			.await
-			.entry(room_id.to_owned())
-			.or_default()
-			.remove(user_id);
-
-		let count = self.services.globals.next_count();
-		self.last_typing_update
+		let stopped = self
+			.shard(room_id)
 			.write()
-			.await
-			.insert(room_id.to_owned(), *count);
+			.expect("locked")
+			.get_mut(room_id)
+			.and_then(|room| room.users.remove(user_id))
+			.is_some();
 
-		if self
-			.typing_update_sender
-			.send(room_id.to_owned())
-			.is_err()
-		{
-			trace!("receiver found what it was looking for and is no longer interested");
+		if !stopped {
+			return Ok(());
 		}
 
+		// update clients
+		self.updated(room_id);
+
 		// update federation
 		if self.services.globals.user_is_local(user_id) {
 			self.federation_send(room_id, user_id, false)
@@ -109,94 +181,51 @@ pub async fn typing_remove(&self, user_id: &UserId, room_id: &RoomId) -> Result
 		Ok(())
 	}
 
-	pub async fn wait_for_update(&self, room_id: &RoomId) {
-		let mut receiver = self.typing_update_sender.subscribe();
-		while let Ok(next) = receiver.recv().await {
-			if next == room_id {
-				break;
-			}
-		}
-	}
-
-	/// Makes sure that typing events with old timestamps get removed.
-	async fn typings_maintain(&self, room_id: &RoomId) -> Result {
-		let current_timestamp = utils::millis_since_unix_epoch();
-		let mut removable = Vec::new();
-
-		{
-			let typing = self.typing.read().await;
-			let Some(room) = typing.get(room_id) else {
-				return Ok(());
-			};
-
-			for (user, timeout) in room {
-				if *timeout < current_timestamp {
-					removable.push(user.clone());
-				}
-			}
-		};
-
-		if !removable.is_empty() {
-			let typing = &mut self.typing.write().await;
-			let room = typing.entry(room_id.to_owned()).or_default();
-
-			for user in &removable {
-				debug_info!("typing timeout {user:?} in {room_id:?}");
-				room.remove(user);
-			}
-
-			// update clients
-			let count = self.services.globals.next_count();
-			self.last_typing_update
-				.write()
-				.await
-				.insert(room_id.to_owned(), *count);
-
-			if self
-				.typing_update_sender
-				.send(room_id.to_owned())
-				.is_err()
-			{
-				trace!("receiver found what it was looking for and is no longer interested");
-			}
-
-			// update federation
-			for user in &removable {
-				if self.services.globals.user_is_local(user) {
-					self.federation_send(room_id, user, false).await?;
+	/// Completes on the next typing update in this room. Updates are watched
+	/// for from the call rather than the first poll.
+	pub fn wait_for_update(&self, room_id: &RoomId) -> impl Future<Output = ()> + Send + use<> {
+		let mut receiver = self.updates.subscribe();
+		let room_id = room_id.to_owned();
+		async move {
+			while let Ok(next) = receiver.recv().await {
+				if next == room_id {
+					break;
 				}
 			}
 		}
-
-		Ok(())
 	}
 
 	/// Returns the count of the last typing update in this room.
-	pub async fn last_typing_update(&self, room_id: &RoomId) -> Result<u64> {
-		self.typings_maintain(room_id).await?;
-		Ok(self
-			.last_typing_update
+	#[must_use]
+	pub fn last_typing_update(&self, room_id: &RoomId) -> u64 {
+		self.shard(room_id)
 			.read()
-			.await
+			.expect("locked")
 			.get(room_id)
-			.copied()
-			.unwrap_or(0))
+			.map_or(0, |room| room.last_update)
 	}
 
-	/// Returns a new typing EDU.
+	/// Returns the users typing in a room, less those ignored by
+	/// `sender_user`.
 	pub async fn typing_users_for_user(
 		&self,
 		room_id: &RoomId,
 		sender_user: &UserId,
 	) -> Result<Vec<OwnedUserId>> {
-		let room_typing_indicators = self.typing.read().await.get(room_id).cloned();
-
-		let Some(typing_indicators) = room_typing_indicators else {
-			return Ok(Vec::new());
-		};
-
-		let user_ids: Vec<_> = typing_indicators
-			.into_keys()
+		let now = utils::millis_since_unix_epoch();
+		let typing_users: Vec<_> = self
+			.shard(room_id)
+			.read()
+			.expect("locked")
+			.get(room_id)
+			.into_iter()
+			.flat_map(|room| room.users.iter())
+			.filter(|&(_, timeout)| *timeout >= now)
+			.map(|(user_id, _)| user_id.clone())
+			.collect();
+
+		let user_ids: Vec<_> = typing_users
+			.into_iter()
 			.stream()
 			.filter_map(async |typing_user_id| {
 				self.services
@@ -212,27 +241,160 @@ pub async fn typing_users_for_user(
 		Ok(user_ids)
 	}
 
+	/// Takes up to `limit` typing EDUs pending for a server. Any remaining go
+	/// with the next transaction.
+	pub(crate) fn select_edus(&self, server_name: &ServerName, limit: usize) -> EduVec {
+		let mut outgoing = self.outgoing.lock().expect("locked");
+		let Some(pending) = outgoing.get_mut(server_name) else {
+			return EduVec::new();
+		};
+
+		let mut edus = EduVec::new();
+		while edus.len() < limit {
+			let Some(((room_id, user_id), typing)) = pending.pop_first() else {
+				break;
+			};
+
+			let edu = Edu::Typing(TypingContent::new(room_id, user_id, typing));
+
+			let mut buf = EduBuf::new();
+			serde_json::to_writer(&mut buf, &edu).expect("Serialized Edu::Typing");
+			edus.push(buf);
+		}
+
+		if pending.is_empty() {
+			outgoing.remove(server_name);
+		}
+
+		edus
+	}
+
+	/// Removes typing users whose timeout has passed. Returns the earliest
+	/// timeout remaining.
+	async fn expire(&self, now: u64) -> Option<u64> {
+		let mut expired = Vec::new();
+		let mut next = None::<u64>;
+
+		for shard in &self.shards {
+			let mut shard = shard.write().expect("locked");
+			shard.retain(|_, room| {
+				!room.users.is_empty() || now.saturating_sub(room.updated_at) < EMPTY_RETAIN_MS
+			});
+
+			for (room_id, room) in shard.iter_mut() {
+				let mut removed = Vec::new();
+				room.users.retain(|user_id, timeout| {
+					let typing = *timeout >= now;
+					if !typing {
+						removed.push(user_id.clone());
+					}
+
+					typing
+				});
+
+				if let Some(earliest) = room.users.values().min() {
+					next = Some(next.map_or(*earliest, |next| next.min(*earliest)));
+				}
+
+				if !removed.is_empty() {
+					expired.push((room_id.clone(), removed));
+				}
+			}
+		}
+
+		for (room_id, users) in expired {
+			// update clients
+			self.updated(&room_id);
+
+			// update federation
+			for user_id in &users {
+				debug_info!("typing timeout {user_id:?} in {room_id:?}");
+				if self.services.globals.user_is_local(user_id) {
+					self.federation_send(&room_id, user_id, false)
+						.await
+						.ok();
+				}
+			}
+		}
+
+		next
+	}
+
+	/// Records a change to the typing users of a room and wakes its watchers.
+	fn updated(&self, room_id: &RoomId) {
+		let count = self.services.globals.next_count();
+		if let Some(room) = self
+			.shard(room_id)
+			.write()
+			.expect("locked")
+			.get_mut(room_id)
+		{
+			room.last_update = *count;
+			room.updated_at = utils::millis_since_unix_epoch();
+		}
+
+		if self.updates.send(room_id.to_owned()).is_err() {
+			trace!("receiver found what it was looking for and is no longer interested");
+		}
+	}
+
+	/// Queues a typing change of a local user for the servers in the room and
+	/// prompts a transaction to each.
 	async fn federation_send(&self, room_id: &RoomId, user_id: &UserId, typing: bool) -> Result {
 		debug_assert!(
 			self.services.globals.user_is_local(user_id),
 			"tried to broadcast typing status of remote user",
 		);
 
-		if !self.server.config.allow_outgoing_typing {
+		if !self.server.config.allow_outgoing_typing || !self.server.config.allow_federation {
 			return Ok(());
 		}
 
-		let content = TypingContent::new(room_id.to_owned(), user_id.to_owned(), typing);
-		let edu = Edu::Typing(content);
+		let max_members = self
+			.server
+			.config
+			.typing_federation_max_room_members;
+		if max_members > 0
+			&& self
+				.services
+				.state_cache
+				.room_joined_count(room_id)
+				.await
+				.is_ok_and(|count| count >= max_members)
+		{
+			return Ok(());
+		}
+
+		let servers: Vec<OwnedServerName> = self
+			.services
+			.state_cache
+			.room_servers(room_id)
+			.ready_filter(|server_name| !self.services.globals.server_is_ours(server_name))
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
 
-		let mut buf = EduBuf::new();
-		serde_json::to_writer(&mut buf, &edu).expect("Serialized Edu::Typing");
+		{
+			let mut outgoing = self.outgoing.lock().expect("locked");
+			for server_name in &servers {
+				outgoing
+					.entry(server_name.clone())
+					.or_default()
+					.insert((room_id.to_owned(), user_id.to_owned()), typing);
+			}
+		}
 
 		self.services
 			.sending
-			.send_edu_room(room_id, buf)
-			.await?;
+			.flush_servers(servers.iter().map(AsRef::as_ref).stream())
+			.await
+	}
 
-		Ok(())
+	fn shard(&self, room_id: &RoomId) -> &RwLock<HashMap<OwnedRoomId, Room>> {
+		let mut hash = DefaultHasher::default();
+		room_id.hash(&mut hash);
+
+		let hash = usize_from_u64_truncated(hash.finish());
+		&self.shards[hash.overflowing_rem(self.shards.len()).0]
 	}
 }
@@ -0,0 +1,155 @@
+//! Validation of the text local users choose for others to see: display names,
+//! the names and topics of rooms, and the localparts of room aliases. Control
+//! and invisible characters are removed, then text exceeding the configured
+//! lengths is refused, so that every way of setting it is held to the same
+//! limits. Events the server builds are cut to the limits instead.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use ruma::{RoomAliasId, events::TimelineEventType};
+use serde_json::{
+	Value as JsonValue,
+	value::{RawValue as RawJsonValue, to_raw_value},
+};
+use tuwunel_core::{
+	Err, Result, implement,
+	utils::string::sanitize::{is_emoji_only, sanitize, truncate_chars},
+};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self { services: args.services.clone() }))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// The display name a local user chose for their profile, sanitized.
+#[implement(Service)]
+pub fn displayname(&self, displayname: &str) -> Result<String> {
+	let config = &self.services.config;
+	let displayname = sanitize(displayname, false);
+	if displayname.is_empty() {
+		return Err!(Request(InvalidParam("Display names cannot be empty or invisible.")));
+	}
+
+	let max = config.max_displayname_length;
+	if displayname.chars().count() > max {
+		return Err!(Request(TooLarge("Display names cannot be longer than {max} characters.")));
+	}
+
+	if config.reject_emoji_only_displaynames && is_emoji_only(&displayname) {
+		return Err!(Request(InvalidParam("Display names cannot consist only of emoji.")));
+	}
+
+	Ok(displayname)
+}
+
+/// The name of a room set by a local user, sanitized. It may be empty,
+/// removing the name.
+#[implement(Service)]
+pub fn room_name(&self, name: &str) -> Result<String> {
+	let name = sanitize(name, false);
+	let max = self.services.config.max_room_name_length;
+	if name.chars().count() > max {
+		return Err!(Request(TooLarge("Room names cannot be longer than {max} characters.")));
+	}
+
+	Ok(name)
+}
+
+/// The topic of a room set by a local user, sanitized. Unlike names, topics
+/// keep their newlines.
+#[implement(Service)]
+pub fn room_topic(&self, topic: &str) -> Result<String> {
+	let topic = sanitize(topic, true);
+	let max = self.services.config.max_room_topic_length;
+	if topic.chars().count() > max {
+		return Err!(Request(TooLarge("Room topics cannot be longer than {max} characters.")));
+	}
+
+	Ok(topic)
+}
+
+/// Returns Ok if a local user may create the alias: its localpart is within
+/// the configured length, and has no control or invisible characters.
+#[implement(Service)]
+pub fn check_alias(&self, alias: &RoomAliasId) -> Result {
+	let localpart = alias.alias();
+	let max = self.services.config.max_alias_localpart_length;
+	if localpart.chars().count() > max {
+		return Err!(Request(InvalidParam(
+			"Room alias localparts cannot be longer than {max} characters."
+		)));
+	}
+
+	if sanitize(localpart, false) != localpart {
+		return Err!(Request(InvalidParam(
+			"Room alias localparts cannot contain control or invisible characters."
+		)));
+	}
+
+	Ok(())
+}
+
+/// Returns Ok if the name or topic in the content of a state event sent by a
+/// local user is within the configured lengths once sanitized. Other events
+/// are left to `event_content`.
+#[implement(Service)]
+pub fn check_content(&self, event_type: &TimelineEventType, content: &RawJsonValue) -> Result {
+	let key = match event_type {
+		| TimelineEventType::RoomName => "name",
+		| TimelineEventType::RoomTopic => "topic",
+		| _ => return Ok(()),
+	};
+
+	let value: JsonValue = serde_json::from_str(content.get())?;
+	let Some(text) = value.get(key).and_then(JsonValue::as_str) else {
+		return Ok(());
+	};
+
+	match event_type {
+		| TimelineEventType::RoomName => self.room_name(text).map(drop),
+		| _ => self.room_topic(text).map(drop),
+	}
+}
+
+/// Sanitizes the content of an event built locally: the name or topic of a
+/// room and the display name of a member are cut to the configured lengths.
+/// Client requests are refused beforehand with `check_content`; the copies
+/// the server makes, such as the state of an upgraded room, and existing
+/// profiles never fail.
+#[implement(Service)]
+pub fn event_content(
+	&self,
+	event_type: &TimelineEventType,
+	content: Box<RawJsonValue>,
+) -> Result<Box<RawJsonValue>> {
+	let config = &self.services.config;
+	let (key, max, newlines) = match event_type {
+		| TimelineEventType::RoomMember => ("displayname", config.max_displayname_length, false),
+		| TimelineEventType::RoomName => ("name", config.max_room_name_length, false),
+		| TimelineEventType::RoomTopic => ("topic", config.max_room_topic_length, true),
+		| _ => return Ok(content),
+	};
+
+	let mut value: JsonValue = serde_json::from_str(content.get())?;
+	let Some(text) = value.get(key).and_then(JsonValue::as_str) else {
+		return Ok(content);
+	};
+
+	let sanitized = truncate_chars(&sanitize(text, newlines), max).to_owned();
+	if sanitized == text {
+		return Ok(content);
+	}
+
+	value[key] = sanitized.into();
+
+	Ok(to_raw_value(&value)?)
+}
@@ -0,0 +1,124 @@
+//! Audit mode. Transactions to destinations matching an audited pattern are
+//! composed and logged, with the ids of their PDUs and the types of their
+//! EDUs, but not sent; the events are taken off the queue as if they had been.
+//! This lets operators review what would federate to a server before allowing
+//! it.
+//!
+//! The EDUs selected for an audited transaction count as sent: read receipts,
+//! presence and device list changes up to then are not sent once the server is
+//! taken out of audit mode. The server learns of the device lists it missed
+//! from the `prev_id` of the next update, and resyncs them.
+
+use futures::{Stream, StreamExt};
+use regex::{Regex, RegexSet};
+use ruma::{OwnedEventId, ServerName, api::federation::transactions::edu::Edu, serde::Raw};
+use tuwunel_core::{
+	Result, err, error, info,
+	utils::{IterStream, stream::TryIgnore, time::now_secs},
+};
+
+use super::{SendingEvent, Service};
+
+impl Service {
+	/// Puts the destinations matching a pattern into audit mode, or takes them
+	/// out of it. Fails when the pattern is invalid, or too large to be matched
+	/// along with the other patterns.
+	pub async fn set_audit(&self, pattern: &str, audit: bool) -> Result {
+		Regex::new(pattern).map_err(|e| err!("Invalid pattern {pattern:?}: {e}"))?;
+
+		// Held while the patterns are rebuilt so that a concurrent load cannot
+		// store the patterns from before the change.
+		let mut audited = self.audited.write().await;
+		let mut patterns = self.audited_patterns().await;
+		patterns.retain(|other| other != pattern);
+		if audit {
+			patterns.push(pattern.to_owned());
+		}
+
+		let set = RegexSet::new(&patterns)
+			.map_err(|e| err!("Pattern {pattern:?} cannot be audited: {e}"))?;
+
+		if audit {
+			self.db
+				.auditpattern_since
+				.raw_put(pattern, now_secs());
+		} else {
+			self.db.auditpattern_since.remove(pattern);
+		}
+
+		*audited = Some(set);
+
+		Ok(())
+	}
+
+	/// The audited patterns, with when they were put into audit mode.
+	pub fn audited(&self) -> impl Stream<Item = (&str, u64)> + Send + '_ {
+		self.db.auditpattern_since.stream().ignore_err()
+	}
+
+	/// Whether transactions to a server are only logged. Should the stored
+	/// patterns not compile, every server is audited rather than none.
+	pub(super) async fn is_audited(&self, server: &ServerName) -> bool {
+		if let Some(audited) = &*self.audited.read().await {
+			return audited.is_match(server.as_str());
+		}
+
+		let mut audited = self.audited.write().await;
+		if audited.is_none() {
+			let patterns = self.audited_patterns().await;
+			*audited = Some(RegexSet::new(&patterns).unwrap_or_else(|e| {
+				error!("Auditing every server, the audited patterns are invalid: {e}");
+				RegexSet::new([""]).expect("empty pattern matches every server")
+			}));
+		}
+
+		audited
+			.as_ref()
+			.is_some_and(|audited| audited.is_match(server.as_str()))
+	}
+
+	async fn audited_patterns(&self) -> Vec<String> {
+		self.audited()
+			.map(|(pattern, _)| pattern.to_owned())
+			.collect()
+			.await
+	}
+
+	/// Logs a transaction held back from an audited server.
+	pub(super) async fn audit_transaction(
+		&self,
+		server: &ServerName,
+		txn_id: &str,
+		events: &[SendingEvent],
+		edus: &[Raw<Edu>],
+	) {
+		let pdu_ids: Vec<OwnedEventId> = events
+			.iter()
+			.stream()
+			.filter_map(async |event| match event {
+				| SendingEvent::Pdu(pdu_id) => self
+					.services
+					.timeline
+					.get_pdu_from_id(pdu_id)
+					.await
+					.ok()
+					.map(|pdu| pdu.event_id),
+				| _ => None,
+			})
+			.collect()
+			.await;
+
+		let edu_types: Vec<String> = edus
+			.iter()
+			.filter_map(|edu| edu.get_field("edu_type").ok().flatten())
+			.collect();
+
+		info!(
+			%server,
+			%txn_id,
+			?pdu_ids,
+			?edu_types,
+			"Audit mode: transaction composed but not sent"
+		);
+	}
+}
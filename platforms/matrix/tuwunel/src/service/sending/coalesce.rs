@@ -0,0 +1,136 @@
+//! Receipts held back per destination so that a user's receipts in a room
+//! within `receipt_coalesce_window_ms` are sent as one, at their latest
+//! position. Rooms with at least `large_room_receipt_members` joined members
+//! are held separately for `large_room_receipt_coalesce_window_ms`.
+
+use std::{
+	collections::{BTreeMap, btree_map::Entry},
+	time::{Duration, Instant},
+};
+
+use ruma::{
+	OwnedRoomId, OwnedServerName, ServerName, api::federation::transactions::edu::ReceiptMap,
+};
+use tokio::time::sleep;
+use tuwunel_core::{Result, debug, result::LogErr};
+
+use super::{Destination, Msg, SendingEvent, Service};
+
+/// Receipts awaiting the end of their window for one destination.
+pub(super) struct Coalesced {
+	/// When the first of the receipts was held back.
+	started: Instant,
+
+	/// Whether a flush is scheduled for the end of the window.
+	scheduled: bool,
+
+	receipts: BTreeMap<OwnedRoomId, ReceiptMap>,
+}
+
+impl Service {
+	/// Merges newly selected receipts into those held back for a destination.
+	/// Returns the receipts to send once the window has passed.
+	pub(super) fn coalesce_receipts(
+		&self,
+		server_name: &ServerName,
+		receipts: BTreeMap<OwnedRoomId, ReceiptMap>,
+		large: bool,
+	) -> Option<BTreeMap<OwnedRoomId, ReceiptMap>> {
+		let window = Duration::from_millis(if large {
+			self.server
+				.config
+				.large_room_receipt_coalesce_window_ms
+		} else {
+			self.server.config.receipt_coalesce_window_ms
+		});
+
+		if window.is_zero() {
+			return (!receipts.is_empty()).then_some(receipts);
+		}
+
+		let key = (server_name.to_owned(), large);
+		let mut coalesced = self.receipts.lock().expect("locked");
+		if receipts.is_empty() && !coalesced.contains_key(&key) {
+			return None;
+		}
+
+		let held = coalesced
+			.entry(key.clone())
+			.or_insert_with(|| Coalesced {
+				started: Instant::now(),
+				scheduled: false,
+				receipts: BTreeMap::new(),
+			});
+
+		// Receipts are selected in order, so later ones replace earlier ones.
+		for (room_id, receipt_map) in receipts {
+			match held.receipts.entry(room_id) {
+				| Entry::Vacant(entry) => {
+					entry.insert(receipt_map);
+				},
+				| Entry::Occupied(mut entry) => {
+					entry.get_mut().read.extend(receipt_map.read);
+				},
+			}
+		}
+
+		let elapsed = held.started.elapsed();
+		if elapsed < window {
+			if !held.scheduled {
+				held.scheduled = true;
+				self.schedule_flush(key, window.saturating_sub(elapsed));
+			}
+
+			return None;
+		}
+
+		coalesced.remove(&key).map(|held| held.receipts)
+	}
+
+	/// Whether receipts are held back for a destination.
+	pub(super) fn has_coalesced_receipts(&self, dest: &Destination) -> bool {
+		let Destination::Federation(server_name) = dest else {
+			return false;
+		};
+
+		let coalesced = self.receipts.lock().expect("locked");
+		[false, true]
+			.into_iter()
+			.any(|large| coalesced.contains_key(&(server_name.clone(), large)))
+	}
+
+	fn schedule_flush(&self, key: (OwnedServerName, bool), delay: Duration) {
+		let services = self.services.clone();
+		self.server.runtime().spawn(async move {
+			tokio::select! {
+				() = sleep(delay) => {},
+				() = services.server.until_shutdown() => return,
+			}
+
+			services
+				.sending
+				.flush_coalesced(key)
+				.log_err()
+				.ok();
+		});
+	}
+
+	fn flush_coalesced(&self, key: (OwnedServerName, bool)) -> Result {
+		if let Some(held) = self
+			.receipts
+			.lock()
+			.expect("locked")
+			.get_mut(&key)
+		{
+			held.scheduled = false;
+		}
+
+		let (server_name, large) = key;
+		debug!(%server_name, large, "Flushing coalesced receipts");
+		self.dispatch(Msg {
+			dest: Destination::Federation(server_name),
+			event: SendingEvent::Flush,
+			queue_id: Vec::new(),
+		})
+	}
+}
@@ -1,12 +1,16 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, sync::Arc, time::Duration};
 
 use futures::{Stream, StreamExt};
-use ruma::{OwnedServerName, ServerName, UserId};
+use ruma::{
+	OwnedServerName, ServerName, UserId, api::federation::transactions::edu::PresenceUpdate,
+	presence::PresenceState,
+};
+use serde::{Deserialize, Serialize};
 use tuwunel_core::{
 	Error, Result, at, utils,
-	utils::{ReadyExt, stream::TryIgnore},
+	utils::{ReadyExt, stream::TryIgnore, time::now_millis},
 };
-use tuwunel_database::{Database, Deserialized, Map};
+use tuwunel_database::{Database, Deserialized, Json, Map};
 
 use super::{Destination, SendingEvent};
 
@@ -15,10 +19,24 @@
 pub(super) type QueueItem = (Key, SendingEvent);
 pub(super) type Key = Vec<u8>;
 
+/// Presence of a user last sent to a server.
+#[derive(Deserialize, Serialize)]
+struct SentPresence {
+	presence: PresenceState,
+	currently_active: bool,
+	status_msg: Option<String>,
+
+	/// Milliseconds since the epoch when it was sent.
+	sent_at: u64,
+}
+
 pub struct Data {
+	pub(super) auditpattern_since: Arc<Map>,
 	servercurrentevent_data: Arc<Map>,
 	servernameevent_data: Arc<Map>,
+	servername_devicelistcount: Arc<Map>,
 	servername_educount: Arc<Map>,
+	serveruserid_presence: Arc<Map>,
 	pub(super) db: Arc<Database>,
 	services: Arc<crate::services::OnceServices>,
 }
@@ -27,9 +45,12 @@ impl Data {
 	pub(super) fn new(args: &crate::Args<'_>) -> Self {
 		let db = &args.db;
 		Self {
+			auditpattern_since: db["auditpattern_since"].clone(),
 			servercurrentevent_data: db["servercurrentevent_data"].clone(),
 			servernameevent_data: db["servernameevent_data"].clone(),
+			servername_devicelistcount: db["servername_devicelistcount"].clone(),
 			servername_educount: db["servername_educount"].clone(),
+			serveruserid_presence: db["serveruserid_presence"].clone(),
 			db: args.db.clone(),
 			services: args.services.clone(),
 		}
@@ -176,6 +197,78 @@ pub async fn get_latest_educount(&self, server_name: &ServerName) -> u64 {
 			.deserialized()
 			.unwrap_or(0)
 	}
+
+	pub(super) fn set_latest_devicelistcount(&self, server_name: &ServerName, count: u64) {
+		self.servername_devicelistcount
+			.raw_put(server_name, count);
+	}
+
+	/// Count up to which the changes to the device lists of local users were
+	/// delivered to the server; its EDU count until any were.
+	pub async fn get_latest_devicelistcount(&self, server_name: &ServerName) -> u64 {
+		match self
+			.servername_devicelistcount
+			.get(server_name)
+			.await
+			.deserialized()
+		{
+			| Ok(count) => count,
+			| Err(_) => self.get_latest_educount(server_name).await,
+		}
+	}
+
+	/// Whether the presence update would repeat the state last sent to the
+	/// server within `window`.
+	pub(super) async fn presence_sent_recently(
+		&self,
+		server_name: &ServerName,
+		update: &PresenceUpdate,
+		window: Duration,
+	) -> bool {
+		let window = u64::try_from(window.as_millis()).unwrap_or(u64::MAX);
+		self.serveruserid_presence
+			.qry(&(server_name, &update.user_id))
+			.await
+			.deserialized::<SentPresence>()
+			.is_ok_and(|last| {
+				last.presence == update.presence
+					&& last.currently_active == update.currently_active
+					&& last.status_msg == update.status_msg
+					&& now_millis().saturating_sub(last.sent_at) < window
+			})
+	}
+
+	/// Records the presence updates as delivered to the server.
+	pub(super) fn set_presence_sent(&self, server_name: &ServerName, updates: &[PresenceUpdate]) {
+		let now = now_millis();
+		for update in updates {
+			let sent = SentPresence {
+				presence: update.presence.clone(),
+				currently_active: update.currently_active,
+				status_msg: update.status_msg.clone(),
+				sent_at: now,
+			};
+
+			self.serveruserid_presence
+				.put((server_name, &update.user_id), Json(&sent));
+		}
+	}
+
+	/// Removes the records of presence sent longer than `window` ago, which
+	/// no longer withhold any update.
+	pub(super) async fn prune_presence_sent(&self, window: Duration) {
+		let window = u64::try_from(window.as_millis()).unwrap_or(u64::MAX);
+		let expired = now_millis().saturating_sub(window);
+		self.serveruserid_presence
+			.raw_stream()
+			.ignore_err()
+			.ready_filter_map(|(key, val)| {
+				let sent: SentPresence = serde_json::from_slice(val).ok()?;
+				(sent.sent_at < expired).then_some(key)
+			})
+			.ready_for_each(|key| self.serveruserid_presence.remove(key))
+			.await;
+	}
 }
 
 fn parse_servercurrentevent(key: &[u8], value: &[u8]) -> Result<(Destination, SendingEvent)> {
@@ -1,18 +1,27 @@
+mod audit;
+mod coalesce;
 mod data;
 mod dest;
+mod pacing;
 mod sender;
 
 use std::{
+	collections::HashMap,
 	fmt::Debug,
 	hash::{DefaultHasher, Hash, Hasher},
 	iter::once,
-	sync::Arc,
+	sync::{Arc, Mutex},
+	time::Duration,
 };
 
 use async_trait::async_trait;
 use futures::{FutureExt, Stream, StreamExt};
-use ruma::{RoomId, ServerName, UserId};
-use tokio::{task, task::JoinSet};
+use regex::RegexSet;
+use ruma::{
+	OwnedServerName, RoomId, ServerName, UserId,
+	api::federation::transactions::edu::PresenceUpdate,
+};
+use tokio::{sync::RwLock, task, task::JoinSet};
 use tuwunel_core::{
 	Result, Server, debug, debug_warn, err, error,
 	smallvec::SmallVec,
@@ -25,13 +34,33 @@
 	dest::Destination,
 	sender::{EDU_LIMIT, PDU_LIMIT},
 };
-use crate::rooms::timeline::RawPduId;
+use crate::{Heartbeat, rooms::timeline::RawPduId};
 
 pub struct Service {
 	pub db: Data,
 	server: Arc<Server>,
 	services: Arc<crate::services::OnceServices>,
 	channels: Vec<(loole::Sender<Msg>, loole::Receiver<Msg>)>,
+	heartbeats: Vec<Heartbeat>,
+	pacing: pacing::Pacing,
+	receipts: Mutex<HashMap<(OwnedServerName, bool), coalesce::Coalesced>>,
+
+	/// What was selected for the transaction running to each server, recorded
+	/// as delivered once it succeeds.
+	selected: Mutex<HashMap<OwnedServerName, Selected>>,
+
+	/// Audited patterns, loaded on first use and rebuilt when they change.
+	audited: RwLock<Option<RegexSet>>,
+}
+
+/// Selected for a transaction, recorded as delivered once it succeeds.
+#[derive(Default)]
+struct Selected {
+	/// Count up to which device list changes were selected.
+	device_list_count: Option<u64>,
+
+	/// Presence updates subject to deduplication.
+	presence: Vec<PresenceUpdate>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -66,10 +95,20 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 			channels: (0..num_senders)
 				.map(|_| loole::unbounded())
 				.collect(),
+			heartbeats: (0..num_senders)
+				.map(|_| Heartbeat::default())
+				.collect(),
+			pacing: pacing::Pacing::new(&args.server.config),
+			receipts: Mutex::default(),
+			selected: Mutex::default(),
+			audited: RwLock::default(),
 		}))
 	}
 
 	async fn worker(self: Arc<Self>) -> Result {
+		let dedup_window = Duration::from_secs(self.server.config.presence_federation_dedup_s);
+		self.db.prune_presence_sent(dedup_window).await;
+
 		let mut senders =
 			self.channels
 				.iter()
@@ -93,6 +132,11 @@ async fn worker(self: Arc<Self>) -> Result {
 				| Ok((id, _)) => {
 					debug!(?id, "sender worker finished");
 				},
+				| Err(error) if error.is_panic() && self.server.running() => {
+					// The manager restarts the service, with every shard.
+					error!(id = ?error.id(), ?error, "sender worker panicked");
+					return Err(error.into());
+				},
 				| Err(error) => {
 					error!(id = ?error.id(), ?error, "sender worker finished");
 				},
@@ -102,6 +146,8 @@ async fn worker(self: Arc<Self>) -> Result {
 		Ok(())
 	}
 
+	fn heartbeats(&self) -> &[Heartbeat] { &self.heartbeats }
+
 	async fn interrupt(&self) {
 		for (sender, _) in &self.channels {
 			if !sender.is_closed() {
@@ -112,6 +158,10 @@ async fn interrupt(&self) {
 
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 
+	fn dependencies(&self) -> &'static [&'static str] {
+		&["federation", "resolver", "server_keys"]
+	}
+
 	fn unconstrained(&self) -> bool { true }
 }
 
@@ -148,6 +198,17 @@ pub fn send_pdu_appservice(&self, appservice_id: String, pdu_id: RawPduId) -> Re
 		})
 	}
 
+	/// Wakes the sender of an appservice to push what it has pending, such as
+	/// to-device events, without queueing anything new.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub fn flush_appservice(&self, appservice_id: String) -> Result {
+		self.dispatch(Msg {
+			dest: Destination::Appservice(appservice_id),
+			event: SendingEvent::Flush,
+			queue_id: Vec::<u8>::new(),
+		})
+	}
+
 	#[tracing::instrument(skip(self, room_id, pdu_id), level = "debug")]
 	pub async fn send_pdu_room(&self, room_id: &RoomId, pdu_id: &RawPduId) -> Result {
 		let servers = self
@@ -164,6 +225,10 @@ pub async fn send_pdu_servers<'a, S>(&self, servers: S, pdu_id: &RawPduId) -> Re
 	where
 		S: Stream<Item = &'a ServerName> + Send + 'a,
 	{
+		if !self.server.config.allow_federation {
+			return Ok(());
+		}
+
 		let requests = servers
 			.map(|server| {
 				(Destination::Federation(server.into()), SendingEvent::Pdu(pdu_id.to_owned()))
@@ -185,6 +250,10 @@ pub async fn send_pdu_servers<'a, S>(&self, servers: S, pdu_id: &RawPduId) -> Re
 
 	#[tracing::instrument(skip(self, server, serialized), level = "debug")]
 	pub fn send_edu_server(&self, server: &ServerName, serialized: EduBuf) -> Result {
+		if !self.server.config.allow_federation {
+			return Ok(());
+		}
+
 		let dest = Destination::Federation(server.to_owned());
 		let event = SendingEvent::Edu(serialized);
 		let _cork = self.db.db.cork();
@@ -215,6 +284,10 @@ pub async fn send_edu_servers<'a, S>(&self, servers: S, serialized: EduBuf) -> R
 	where
 		S: Stream<Item = &'a ServerName> + Send + 'a,
 	{
+		if !self.server.config.allow_federation {
+			return Ok(());
+		}
+
 		let requests = servers
 			.map(|server| {
 				(
@@ -253,6 +326,10 @@ pub async fn flush_servers<'a, S>(&self, servers: S) -> Result
 	where
 		S: Stream<Item = &'a ServerName> + Send + 'a,
 	{
+		if !self.server.config.allow_federation {
+			return Ok(());
+		}
+
 		servers
 			.map(ToOwned::to_owned)
 			.map(Destination::Federation)
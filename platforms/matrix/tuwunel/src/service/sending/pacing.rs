@@ -0,0 +1,116 @@
+//! Pacing of outbound federation transactions, so that a burst of events in a
+//! large room neither starves slow destinations of the sender nor trips the
+//! rate limits of remote servers.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use ruma::{OwnedServerName, ServerName};
+use tokio::{
+	sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit},
+	time::sleep_until,
+};
+use tuwunel_core::{Config, implement};
+
+pub(super) struct Pacing {
+	/// Bounds the transactions in flight to all servers.
+	global: Semaphore,
+
+	/// Bounds the transactions in flight to each family of servers.
+	families: Mutex<HashMap<String, Arc<Semaphore>>>,
+
+	/// When the next transaction to each server may be started.
+	next_send: Mutex<HashMap<OwnedServerName, Instant>>,
+}
+
+/// Held while a transaction is in flight.
+pub(super) struct Permit<'a> {
+	_global: SemaphorePermit<'a>,
+	_family: Option<OwnedSemaphorePermit>,
+}
+
+impl Pacing {
+	pub(super) fn new(config: &Config) -> Self {
+		Self {
+			global: Semaphore::new(config.sender_max_inflight.max(1)),
+			families: Mutex::default(),
+			next_send: Mutex::default(),
+		}
+	}
+}
+
+/// Waits until a transaction may be sent to the server: no sooner than
+/// `sender_transaction_interval_ms` after the previous one, and while fewer
+/// than `sender_max_inflight_per_family`, when set, and `sender_max_inflight`
+/// are in flight to its family and to all servers.
+#[implement(super::Service)]
+pub(super) async fn pace(&self, server: &ServerName) -> Permit<'_> {
+	let config = &self.services.config;
+	let interval = Duration::from_millis(config.sender_transaction_interval_ms);
+	if !interval.is_zero() {
+		let now = Instant::now();
+		let due = {
+			let mut next_send = self.pacing.next_send.lock().expect("locked");
+			next_send.retain(|_, next| *next > now);
+
+			let due = next_send.get(server).copied().unwrap_or(now);
+
+			if let Some(next) = due.checked_add(interval) {
+				next_send.insert(server.to_owned(), next);
+			}
+
+			due
+		};
+
+		sleep_until(due.into()).await;
+	}
+
+	let max_per_family = config.sender_max_inflight_per_family;
+	let family = if max_per_family > 0 {
+		let family = {
+			let mut families = self.pacing.families.lock().expect("locked");
+			families.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+			families
+				.entry(family(server).to_owned())
+				.or_insert_with(|| Arc::new(Semaphore::new(max_per_family)))
+				.clone()
+		};
+
+		let permit = family
+			.acquire_owned()
+			.await
+			.expect("family semaphore is never closed");
+
+		Some(permit)
+	} else {
+		None
+	};
+
+	let global = self
+		.pacing
+		.global
+		.acquire()
+		.await
+		.expect("global semaphore is never closed");
+
+	Permit { _global: global, _family: family }
+}
+
+/// The family of a server: the last two labels of its hostname, so that
+/// servers hosted under one domain are paced together. Addresses are their own
+/// family. Without the public suffix list, unrelated servers under a suffix
+/// such as co.uk are also grouped, hence the limit is off by default.
+fn family(server: &ServerName) -> &str {
+	let host = server.host();
+	if server.is_ip_literal() {
+		return host;
+	}
+
+	host.rmatch_indices('.')
+		.nth(1)
+		.and_then(|(dot, _)| host.get(dot.saturating_add(1)..))
+		.unwrap_or(host)
+}
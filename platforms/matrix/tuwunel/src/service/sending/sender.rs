@@ -1,6 +1,7 @@
 use std::{
-	collections::{BTreeMap, HashMap, HashSet},
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 	fmt::Debug,
+	mem,
 	sync::{
 		Arc,
 		atomic::{AtomicU64, AtomicUsize, Ordering},
@@ -23,7 +24,7 @@
 		federation::transactions::{
 			edu::{
 				DeviceListUpdateContent, Edu, PresenceContent, PresenceUpdate, ReceiptContent,
-				ReceiptData, ReceiptMap,
+				ReceiptData, ReceiptMap, SigningKeyUpdateContent,
 			},
 			send_transaction_message,
 		},
@@ -39,7 +40,7 @@
 	uint,
 };
 use tuwunel_core::{
-	Error, Event, Result, debug, err, error, extract_variant,
+	Error, Event, Result, at, debug, err, error, extract_variant,
 	result::LogErr,
 	trace,
 	utils::{
@@ -51,7 +52,7 @@
 };
 
 use super::{Destination, EduBuf, EduVec, Msg, SendingEvent, Service, data::QueueItem};
-use crate::rooms::timeline::RawPduId;
+use crate::{Heartbeat, rooms::timeline::RawPduId};
 
 #[derive(Debug)]
 enum TransactionStatus {
@@ -115,8 +116,15 @@ async fn work_loop<'a>(
 			.map(|(_, receiver)| receiver.clone())
 			.expect("Missing channel for sender worker");
 
+		let heartbeat = self
+			.heartbeats
+			.get(id)
+			.expect("Missing heartbeat for sender worker");
+
+		let mut heartbeats = Heartbeat::interval();
 		while !receiver.is_closed() {
 			tokio::select! {
+				_ = heartbeats.tick() => heartbeat.beat(),
 				Some(response) = futures.next() => {
 					self.handle_response(response, futures, statuses).await;
 				},
@@ -136,15 +144,29 @@ async fn handle_response<'a>(
 		statuses: &mut CurTransactionStatus,
 	) {
 		match response {
-			| Err((dest, e)) => Self::handle_response_err(dest, statuses, &e),
+			| Err((dest, e)) => self.handle_response_err(dest, statuses, &e),
 			| Ok(dest) =>
 				self.handle_response_ok(&dest, futures, statuses)
 					.await,
 		}
 	}
 
-	fn handle_response_err(dest: Destination, statuses: &mut CurTransactionStatus, e: &Error) {
+	fn handle_response_err(
+		&self,
+		dest: Destination,
+		statuses: &mut CurTransactionStatus,
+		e: &Error,
+	) {
 		debug!(dest = ?dest, "{e:?}");
+
+		// What the transaction carried is selected again.
+		if let Destination::Federation(server_name) = &dest {
+			self.selected
+				.lock()
+				.expect("locked")
+				.remove(server_name);
+		}
+
 		statuses.entry(dest).and_modify(|e| {
 			*e = match e {
 				| TransactionStatus::Running => TransactionStatus::Failed(1, Instant::now()),
@@ -169,6 +191,22 @@ async fn handle_response_ok<'a>(
 		let _cork = self.db.db.cork();
 		self.db.delete_all_active_requests_for(dest).await;
 
+		if let Destination::Federation(server_name) = dest
+			&& let Some(selected) = self
+				.selected
+				.lock()
+				.expect("locked")
+				.remove(server_name)
+		{
+			if let Some(count) = selected.device_list_count {
+				self.db
+					.set_latest_devicelistcount(server_name, count);
+			}
+
+			self.db
+				.set_presence_sent(server_name, &selected.presence);
+		}
+
 		// Find events that have been added since starting the last request
 		let new_events = self
 			.db
@@ -189,6 +227,18 @@ async fn handle_response_ok<'a>(
 			futures.push(self.send_events(dest.clone(), new_events_vec));
 		} else {
 			statuses.remove(dest);
+
+			// Receipts held back while the transaction was running are sent
+			// once their window has passed.
+			if self.has_coalesced_receipts(dest) {
+				self.dispatch(Msg {
+					dest: dest.clone(),
+					event: SendingEvent::Flush,
+					queue_id: Vec::new(),
+				})
+				.log_err()
+				.ok();
+			}
 		}
 	}
 
@@ -274,6 +324,13 @@ async fn startup_netburst<'a>(
 		}
 
 		for (dest, events) in txns {
+			// Requests queued while federation was enabled are kept for when it is
+			// enabled again.
+			if matches!(dest, Destination::Federation(_)) && !self.server.config.allow_federation
+			{
+				continue;
+			}
+
 			if self.server.config.startup_netburst && !events.is_empty() {
 				statuses.insert(dest.clone(), TransactionStatus::Running);
 				futures.push(self.send_events(dest.clone(), events));
@@ -306,13 +363,25 @@ async fn select_events(
 		let _cork = self.db.db.cork();
 		let mut events = Vec::new();
 
-		// Must retry any previous transaction for this remote.
+		// Must retry any previous transaction for this remote. Its EDUs are not
+		// kept, but the device list changes are selected again as they were not
+		// recorded as delivered.
 		if retry {
 			self.db
 				.active_requests_for(dest)
 				.ready_for_each(|(_, e)| events.push(e))
 				.await;
 
+			if let Destination::Federation(server_name) = dest {
+				let upper = self.services.globals.current_count();
+				let events_len = AtomicUsize::default();
+				let device_changes = self
+					.select_edus_device_changes(server_name, upper, &events_len)
+					.await;
+
+				events.extend(device_changes.into_iter().map(SendingEvent::Edu));
+			}
+
 			return Ok(Some(events));
 		}
 
@@ -387,7 +456,7 @@ async fn select_edus(&self, server_name: &ServerName) -> Result<(EduVec, u64)> {
 		let max_edu_count = AtomicU64::new(since);
 
 		let device_changes =
-			self.select_edus_device_changes(server_name, batch, &max_edu_count, &events_len);
+			self.select_edus_device_changes(server_name, since_upper, &events_len);
 
 		let receipts = self
 			.server
@@ -408,72 +477,144 @@ async fn select_edus(&self, server_name: &ServerName) -> Result<(EduVec, u64)> {
 		events.extend(presence.into_iter().flatten());
 		events.extend(receipts.into_iter().flatten());
 
+		// Typing is held in memory rather than counted, so it fills what remains.
+		let typing_limit = EDU_LIMIT.saturating_sub(events.len());
+		events.extend(
+			self.services
+				.typing
+				.select_edus(server_name, typing_limit),
+		);
+
 		Ok((events, max_edu_count.load(Ordering::Acquire)))
 	}
 
-	/// Look for device changes
+	/// Updates of the device lists of local users changed since those last
+	/// delivered to the server, up to `upper`, in the order of the changes. The
+	/// count up to which they were selected is recorded as delivered once the
+	/// transaction succeeds, so that those of a failed one are selected again.
 	#[tracing::instrument(
 		name = "device_changes",
 		level = "trace",
-		skip(self, server_name, max_edu_count)
+		skip(self, server_name, events_len)
 	)]
 	async fn select_edus_device_changes(
 		&self,
 		server_name: &ServerName,
-		since: (u64, u64),
-		max_edu_count: &AtomicU64,
+		upper: u64,
 		events_len: &AtomicUsize,
 	) -> EduVec {
-		let mut events = EduVec::new();
+		let since = self
+			.db
+			.get_latest_devicelistcount(server_name)
+			.await;
+
 		let server_rooms = self
 			.services
 			.state_cache
 			.server_rooms(server_name);
 
 		pin_mut!(server_rooms);
-		let mut device_list_changes = HashSet::<OwnedUserId>::new();
+		let mut changes = BTreeSet::<(u64, OwnedUserId)>::new();
 		while let Some(room_id) = server_rooms.next().await {
-			let keys_changed = self
-				.services
+			self.services
 				.users
-				.room_keys_changed(room_id, since.0, Some(since.1))
-				.ready_filter(|(user_id, _)| self.services.globals.user_is_local(user_id));
+				.room_keys_changed(room_id, since, Some(upper))
+				.ready_filter(|(user_id, _)| self.services.globals.user_is_local(user_id))
+				.ready_for_each(|(user_id, count)| {
+					debug_assert!(count <= upper, "exceeds upper-bound");
+					changes.insert((count, user_id.to_owned()));
+				})
+				.await;
+		}
 
-			pin_mut!(keys_changed);
-			while let Some((user_id, count)) = keys_changed.next().await {
-				debug_assert!(count <= since.1, "exceeds upper-bound");
+		let mut events = EduVec::new();
+		let mut selected = upper;
+		let mut signing_key_users = HashSet::<OwnedUserId>::new();
+		let mut resync_users = HashSet::<OwnedUserId>::new();
+		for (count, user_id) in changes {
+			// The changes from this one on are left for the next transaction.
+			if events_len.load(Ordering::Relaxed) >= SELECT_EDU_LIMIT {
+				selected = count.saturating_sub(1);
+				break;
+			}
 
-				max_edu_count.fetch_max(count, Ordering::Relaxed);
-				if !device_list_changes.insert(user_id.into()) {
-					continue;
-				}
+			let edu = match self
+				.services
+				.users
+				.device_list_update(&user_id, count)
+				.await
+			{
+				| Ok(Some(update)) => Edu::DeviceListUpdate(update),
+				| Ok(None) if !signing_key_users.insert(user_id.clone()) => continue,
+				| Ok(None) =>
+					self.signing_key_update(server_name, &user_id)
+						.await,
+				| Err(_) if !resync_users.insert(user_id.clone()) => continue,
+				| Err(_) => Self::resync_update(&user_id),
+			};
 
-				// Empty prev id forces synapse to resync; because synapse resyncs,
-				// we can just insert placeholder data
-				let edu = Edu::DeviceListUpdate(DeviceListUpdateContent {
-					user_id: user_id.into(),
-					device_id: device_id!("placeholder").to_owned(),
-					device_display_name: Some("Placeholder".to_owned()),
-					stream_id: uint!(1),
-					prev_id: Vec::new(),
-					deleted: None,
-					keys: None,
-				});
-
-				let mut buf = EduBuf::new();
-				serde_json::to_writer(&mut buf, &edu)
-					.expect("failed to serialize device list update to JSON");
-
-				events.push(buf);
-				if events_len.fetch_add(1, Ordering::Relaxed) >= SELECT_EDU_LIMIT - 1 {
-					return events;
-				}
-			}
+			let mut buf = EduBuf::new();
+			serde_json::to_writer(&mut buf, &edu)
+				.expect("failed to serialize device list update to JSON");
+
+			events.push(buf);
+			events_len.fetch_add(1, Ordering::Relaxed);
 		}
 
+		self.selected
+			.lock()
+			.expect("locked")
+			.entry(server_name.to_owned())
+			.or_default()
+			.device_list_count = Some(selected);
+
 		events
 	}
 
+	/// Update for a change to the keys of a user concerning none of their
+	/// devices: their cross-signing keys, or when they have none, an update
+	/// making the destination resync the device list.
+	async fn signing_key_update(&self, server_name: &ServerName, user_id: &UserId) -> Edu {
+		let allowed_signatures = |user_id: &UserId| user_id.server_name() == server_name;
+		let master_key = self
+			.services
+			.users
+			.get_master_key(None, user_id, &allowed_signatures)
+			.await
+			.ok();
+
+		let self_signing_key = self
+			.services
+			.users
+			.get_self_signing_key(None, user_id, &allowed_signatures)
+			.await
+			.ok();
+
+		if master_key.is_some() {
+			return Edu::SigningKeyUpdate(SigningKeyUpdateContent {
+				user_id: user_id.to_owned(),
+				master_key,
+				self_signing_key,
+			});
+		}
+
+		Self::resync_update(user_id)
+	}
+
+	/// Update of a placeholder device, whose empty `prev_id` makes the
+	/// destination resync the device list of the user.
+	fn resync_update(user_id: &UserId) -> Edu {
+		Edu::DeviceListUpdate(DeviceListUpdateContent {
+			user_id: user_id.to_owned(),
+			device_id: device_id!("placeholder").to_owned(),
+			device_display_name: Some("Placeholder".to_owned()),
+			stream_id: uint!(1),
+			prev_id: Vec::new(),
+			deleted: None,
+			keys: None,
+		})
+	}
+
 	/// Look for read receipts in this room
 	#[tracing::instrument(
 		name = "receipts",
@@ -487,7 +628,8 @@ async fn select_edus_receipts(
 		max_edu_count: &AtomicU64,
 	) -> Option<EduBuf> {
 		let num = AtomicUsize::new(0);
-		let receipts: BTreeMap<OwnedRoomId, ReceiptMap> = self
+		let large_members = self.server.config.large_room_receipt_members;
+		let selected: Vec<(OwnedRoomId, ReceiptMap, bool)> = self
 			.services
 			.state_cache
 			.server_rooms(server_name)
@@ -497,16 +639,39 @@ async fn select_edus_receipts(
 					.select_edus_receipts_room(&room_id, since, max_edu_count, &num)
 					.await;
 
-				receipt_map
-					.read
-					.is_empty()
-					.eq(&false)
-					.then_some((room_id, receipt_map))
+				if receipt_map.read.is_empty() {
+					return None;
+				}
+
+				let large = large_members > 0
+					&& self
+						.services
+						.state_cache
+						.room_joined_count(&room_id)
+						.await
+						.is_ok_and(|count| count >= large_members);
+
+				Some((room_id, receipt_map, large))
 			})
 			.collect()
 			.boxed()
 			.await;
 
+		let (mut receipts, mut large_receipts) = (BTreeMap::new(), BTreeMap::new());
+		for (room_id, receipt_map, large) in selected {
+			let batch = if large { &mut large_receipts } else { &mut receipts };
+			batch.insert(room_id, receipt_map);
+		}
+
+		let mut receipts = self
+			.coalesce_receipts(server_name, receipts, false)
+			.unwrap_or_default();
+
+		receipts.extend(
+			self.coalesce_receipts(server_name, large_receipts, true)
+				.unwrap_or_default(),
+		);
+
 		if receipts.is_empty() {
 			return None;
 		}
@@ -544,7 +709,13 @@ async fn select_edus_receipts_room(
 			debug_assert!(count <= since.1, "exceeds upper-bound");
 
 			max_edu_count.fetch_max(count, Ordering::Relaxed);
-			if !self.services.globals.user_is_local(user_id) {
+			if !self.services.globals.user_is_local(user_id)
+				|| self
+					.services
+					.users
+					.is_shadow_banned(user_id)
+					.await
+			{
 				continue;
 			}
 
@@ -608,12 +779,19 @@ async fn select_edus_presence(
 			.presence_since(since.0, Some(since.1));
 
 		pin_mut!(presence_since);
+		let dedup_window = Duration::from_secs(self.server.config.presence_federation_dedup_s);
 		let mut presence_updates = HashMap::<OwnedUserId, PresenceUpdate>::new();
 		while let Some((user_id, count, presence_bytes)) = presence_since.next().await {
 			debug_assert!(count <= since.1, "exceeded upper-bound");
 
 			max_edu_count.fetch_max(count, Ordering::Relaxed);
-			if !self.services.globals.user_is_local(user_id) {
+			if !self.services.globals.user_is_local(user_id)
+				|| self
+					.services
+					.users
+					.is_shadow_banned(user_id)
+					.await
+			{
 				continue;
 			}
 
@@ -650,6 +828,15 @@ async fn select_edus_presence(
 					.unwrap_or_else(|| uint!(0)),
 			};
 
+			if dedup_window > Duration::ZERO
+				&& self
+					.db
+					.presence_sent_recently(server_name, &update, dedup_window)
+					.await
+			{
+				continue;
+			}
+
 			presence_updates.insert(user_id.into(), update);
 			if presence_updates.len() >= SELECT_PRESENCE_LIMIT {
 				break;
@@ -660,9 +847,17 @@ async fn select_edus_presence(
 			return None;
 		}
 
-		let presence_content = Edu::Presence(PresenceContent {
-			push: presence_updates.into_values().collect(),
-		});
+		let push: Vec<_> = presence_updates.into_values().collect();
+		if dedup_window > Duration::ZERO {
+			self.selected
+				.lock()
+				.expect("locked")
+				.entry(server_name.to_owned())
+				.or_default()
+				.presence = push.clone();
+		}
+
+		let presence_content = Edu::Presence(PresenceContent { push });
 
 		let mut buf = EduBuf::new();
 		serde_json::to_writer(&mut buf, &presence_content)
@@ -747,29 +942,71 @@ async fn send_events_dest_appservice(
 			}
 		}
 
-		let txn_hash = calculate_hash(events.iter().filter_map(|e| match e {
-			| SendingEvent::Edu(b) => Some(b.as_ref()),
-			| SendingEvent::Pdu(b) => Some(b.as_ref()),
-			| SendingEvent::Flush => None,
-		}));
+		// To-device events are sent along, at most a limited number in each
+		// transaction; those remaining follow in transactions of their own.
+		let mut events = events.as_slice();
+		loop {
+			let to_device = if appservice.receive_ephemeral {
+				self.services
+					.appservice
+					.to_device_events(&id)
+					.await
+			} else {
+				Vec::new()
+			};
 
-		let txn_id = &*URL_SAFE_NO_PAD.encode(txn_hash);
+			if pdu_jsons.is_empty() && edu_jsons.is_empty() && to_device.is_empty() {
+				return Ok(Destination::Appservice(id));
+			}
 
-		//debug_assert!(pdu_jsons.len() + edu_jsons.len() > 0, "sending empty
-		// transaction");
-		match self
-			.services
-			.appservice
-			.send_request(appservice, ruma::api::appservice::event::push_events::v1::Request {
+			let to_device_until = to_device.last().map(at!(0));
+			let to_device_counts: Vec<_> = to_device
+				.iter()
+				.map(|(count, _)| count.to_be_bytes())
+				.collect();
+
+			let txn_hash = calculate_hash(
+				events
+					.iter()
+					.filter_map(|e| match e {
+						| SendingEvent::Edu(b) => Some(b.as_ref()),
+						| SendingEvent::Pdu(b) => Some(b.as_ref()),
+						| SendingEvent::Flush => None,
+					})
+					.chain(
+						to_device_counts
+							.iter()
+							.map(|count| count.as_slice()),
+					),
+			);
+
+			let txn_id = &*URL_SAFE_NO_PAD.encode(txn_hash);
+			let request = ruma::api::appservice::event::push_events::v1::Request {
 				txn_id: txn_id.into(),
-				events: pdu_jsons,
-				ephemeral: edu_jsons,
-				to_device: Vec::new(), // TODO
-			})
-			.await
-		{
-			| Ok(_) => Ok(Destination::Appservice(id)),
-			| Err(e) => Err((Destination::Appservice(id), e)),
+				events: mem::take(&mut pdu_jsons),
+				ephemeral: mem::take(&mut edu_jsons),
+				to_device: to_device.into_iter().map(at!(1)).collect(),
+			};
+
+			if let Err(e) = self
+				.services
+				.appservice
+				.send_request(appservice.clone(), request)
+				.await
+			{
+				return Err((Destination::Appservice(id), e));
+			}
+
+			let Some(until) = to_device_until else {
+				return Ok(Destination::Appservice(id));
+			};
+
+			self.services
+				.appservice
+				.mark_to_device_delivered(&id, until)
+				.await;
+
+			events = &[];
 		}
 	}
 
@@ -847,12 +1084,20 @@ async fn send_events_dest_push(
 			})
 			.ready_filter(|pdu| !pdu.is_redacted())
 			.wide_filter_map(async |pdu| {
-				self.services
+				let result = self
+					.services
 					.pusher
 					.send_push_notice(&user_id, &pusher, &rules_for_user, &pdu)
-					.await
-					.map_err(|e| (Destination::Push(user_id.clone(), pushkey.clone()), e))
-					.ok()
+					.await;
+
+				if let Err(e) = &result {
+					self.services
+						.pusher
+						.queue_retry(&user_id, &pushkey, pdu.event_id(), e)
+						.await;
+				}
+
+				result.ok()
 			})
 			.count()
 			.await;
@@ -1202,12 +1447,22 @@ async fn send_events_dest_federation(
 			edus,
 		};
 
+		if self.is_audited(&server).await {
+			self.audit_transaction(&server, txn_id, &events, &request.edus)
+				.await;
+
+			return Ok(Destination::Federation(server));
+		}
+
+		let permit = self.pace(&server).await;
 		let result = self
 			.services
 			.federation
-			.execute_on(&self.services.client.sender, &server, request)
+			.execute_on(self.services.client.sender_for(&server), &server, request)
 			.await;
 
+		drop(permit);
+
 		for (event_id, result) in result.iter().flat_map(|resp| resp.pdus.iter()) {
 			if let Err(e) = result {
 				warn!(
@@ -1217,9 +1472,20 @@ async fn send_events_dest_federation(
 			}
 		}
 
+		let metrics = &self.server.metrics;
 		match result {
-			| Err(error) => Err((Destination::Federation(server), error)),
-			| Ok(_) => Ok(Destination::Federation(server)),
+			| Err(error) => {
+				metrics
+					.federation_send_failure
+					.fetch_add(1, Ordering::Relaxed);
+				Err((Destination::Federation(server), error))
+			},
+			| Ok(_) => {
+				metrics
+					.federation_send_success
+					.fetch_add(1, Ordering::Relaxed);
+				Ok(Destination::Federation(server))
+			},
 		}
 	}
 }
@@ -143,14 +143,25 @@ async fn acquire_locals<'a, S, K>(&self, batch: S) -> Batch
 {
 	let mut missing = Batch::new();
 	for (server, key_ids) in batch {
+		let mut refetch = false;
 		for key_id in key_ids {
-			if !self.verify_key_exists(server, key_id).await {
+			let expired = self.verify_key_expired(server, key_id).await;
+			if expired && self.refetch_backed_off(server) {
+				continue;
+			}
+
+			if expired || !self.verify_key_exists(server, key_id).await {
+				refetch |= expired;
 				missing
 					.entry(server.into())
 					.or_default()
 					.push(key_id.into());
 			}
 		}
+
+		if refetch {
+			self.back_off_refetch(server);
+		}
 	}
 
 	missing
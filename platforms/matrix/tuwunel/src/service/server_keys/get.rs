@@ -1,12 +1,13 @@
 use std::borrow::Borrow;
 
 use ruma::{
-	CanonicalJsonObject, ServerName, ServerSigningKeyId, api::federation::discovery::VerifyKey,
+	CanonicalJsonObject, ServerName, ServerSigningKeyId,
+	api::federation::discovery::{ServerSigningKeys, VerifyKey},
 	room_version_rules::RoomVersionRules,
 };
-use tuwunel_core::{Err, Result, implement};
+use tuwunel_core::{Err, Result, debug_warn, implement};
 
-use super::{PubKeyMap, PubKeys, extract_key};
+use super::{PubKeyMap, PubKeys, extract_key, keys_expired};
 
 #[implement(super::Service)]
 pub async fn get_event_keys(
@@ -78,11 +79,27 @@ pub async fn get_verify_key(
 		.config
 		.only_query_trusted_key_servers;
 
-	if let Some(result) = self.verify_keys_for(origin).await.remove(key_id) {
+	// Current keys past their expiry are fetched again before being used.
+	let expired = self
+		.signing_keys_for(origin)
+		.await
+		.ok()
+		.filter(|keys| keys.verify_keys.contains_key(key_id) && keys_expired(keys));
+
+	if expired.is_none()
+		&& let Some(result) = self.verify_keys_for(origin).await.remove(key_id)
+	{
 		return Ok(result);
 	}
 
-	if notary_first
+	// Expired keys which recently failed to be fetched again are not asked for.
+	let backed_off = expired.is_some() && self.refetch_backed_off(origin);
+	if expired.is_some() && !backed_off {
+		self.back_off_refetch(origin);
+	}
+
+	if !backed_off
+		&& notary_first
 		&& let Ok(result) = self
 			.get_verify_key_from_notaries(origin, key_id)
 			.await
@@ -90,7 +107,8 @@ pub async fn get_verify_key(
 		return Ok(result);
 	}
 
-	if !notary_only
+	if !backed_off
+		&& !notary_only
 		&& let Ok(result) = self
 			.get_verify_key_from_origin(origin, key_id)
 			.await
@@ -98,7 +116,8 @@ pub async fn get_verify_key(
 		return Ok(result);
 	}
 
-	if !notary_first
+	if !backed_off
+		&& !notary_first
 		&& let Ok(result) = self
 			.get_verify_key_from_notaries(origin, key_id)
 			.await
@@ -106,6 +125,22 @@ pub async fn get_verify_key(
 		return Ok(result);
 	}
 
+	if let Some(keys) = expired {
+		if self.expired_keys_accepted(&keys) {
+			debug_warn!(?key_id, ?origin, "Using expired federation signing-key");
+			if let Some(result) = extract_key(keys, key_id) {
+				return Ok(result);
+			}
+		} else {
+			return Err!(BadServerResponse(debug_error!(
+				?key_id,
+				?origin,
+				valid_until_ts = ?keys.valid_until_ts,
+				"Federation signing-key expired and could not be fetched again"
+			)));
+		}
+	}
+
 	Err!(BadServerResponse(debug_error!(
 		?key_id,
 		?origin,
@@ -113,6 +148,47 @@ pub async fn get_verify_key(
 	)))
 }
 
+/// Discards the cached keys of `origin` and fetches them again from the
+/// origin, else from the first notary to answer. The cache is left untouched
+/// when no server answers.
+#[implement(super::Service)]
+pub async fn refetch_signing_keys(&self, origin: &ServerName) -> Result<ServerSigningKeys> {
+	let config = &self.services.config;
+	let mut fetched = Vec::new();
+	if !config.only_query_trusted_key_servers {
+		match self.server_request(origin).await {
+			| Ok(server_keys) => fetched.push(server_keys),
+			| Err(e) => debug_warn!(?origin, "Failed to fetch signing-keys from origin: {e}"),
+		}
+	}
+
+	if fetched.is_empty() {
+		for notary in &config.trusted_servers {
+			match self.notary_request(notary, origin).await {
+				| Ok(server_keys) => fetched.extend(server_keys),
+				| Err(e) => debug_warn!(?origin, ?notary, "Failed to fetch signing-keys: {e}"),
+			}
+
+			if !fetched.is_empty() {
+				break;
+			}
+		}
+	}
+
+	if fetched.is_empty() {
+		return Err!(Request(NotFound(
+			"Failed to fetch signing-keys of {origin} from it or any notary"
+		)));
+	}
+
+	self.db.server_signingkeys.remove(origin);
+	for server_keys in fetched {
+		self.add_signing_keys(server_keys).await;
+	}
+
+	self.signing_keys_for(origin).await
+}
+
 #[implement(super::Service)]
 async fn get_verify_key_from_notaries(
 	&self,
@@ -1,29 +1,71 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
 
-use ruma::{api::federation::discovery::VerifyKey, serde::Base64, signatures::Ed25519KeyPair};
+use ruma::{
+	MilliSecondsSinceUnixEpoch,
+	api::federation::discovery::{OldVerifyKey, VerifyKey},
+	serde::Base64,
+	signatures::Ed25519KeyPair,
+};
 use tuwunel_core::{Result, debug, debug_info, err, error, utils, utils::string_from_bytes};
-use tuwunel_database::Database;
+use tuwunel_database::{Deserialized, Json, Map};
 
-use super::VerifyKeys;
+use super::{OldVerifyKeys, VerifyKeys};
 
-pub(super) fn init(db: &Arc<Database>) -> Result<(Box<Ed25519KeyPair>, VerifyKeys)> {
-	let keypair = load(db).inspect_err(|_e| {
+pub(super) fn init(global: &Map) -> Result<(Box<Ed25519KeyPair>, VerifyKeys, OldVerifyKeys)> {
+	let keypair = load(global).inspect_err(|_e| {
 		error!("Keypair invalid. Deleting...");
-		remove(db);
+		remove(global);
 	})?;
 
+	let verify_keys = verify_keys(&keypair)?;
+	let old_verify_keys = global
+		.get_blocking(b"old_verify_keys")
+		.deserialized()
+		.unwrap_or_else(|_| BTreeMap::new());
+
+	Ok((keypair, verify_keys, old_verify_keys))
+}
+
+/// Replaces the keypair with a new one, adding the verify keys of the previous
+/// one to the old verify keys, expiring now. The old verify keys are persisted
+/// before the new keypair, so the previous key is never lost.
+pub(super) fn rotate(
+	global: &Map,
+	previous: VerifyKeys,
+	old_verify_keys: &mut OldVerifyKeys,
+) -> Result<(Box<Ed25519KeyPair>, VerifyKeys)> {
+	let value = generate()?;
+	let (version, key) = value.clone();
+	let keypair = Ed25519KeyPair::from_der(&key, version)
+		.map_err(|e| err!("Failed to load ed25519 keypair from der: {e:?}"))?;
+
+	let expired_ts = MilliSecondsSinceUnixEpoch::now();
+	old_verify_keys.extend(
+		previous
+			.into_iter()
+			.map(|(id, verify_key)| (id, OldVerifyKey::new(expired_ts, verify_key.key))),
+	);
+
+	global.raw_put(b"old_verify_keys", Json(&*old_verify_keys));
+	global.raw_put(b"keypair", &value);
+
+	let verify_keys = verify_keys(&keypair)?;
+
+	Ok((Box::new(keypair), verify_keys))
+}
+
+fn verify_keys(keypair: &Ed25519KeyPair) -> Result<VerifyKeys> {
 	let verify_key = VerifyKey {
 		key: Base64::new(keypair.public_key().to_vec()),
 	};
 
 	let id = format!("ed25519:{}", keypair.version());
-	let verify_keys: VerifyKeys = [(id.try_into()?, verify_key)].into();
 
-	Ok((keypair, verify_keys))
+	Ok([(id.try_into()?, verify_key)].into())
 }
 
-fn load(db: &Arc<Database>) -> Result<Box<Ed25519KeyPair>> {
-	let (version, key) = db["global"]
+fn load(global: &Map) -> Result<Box<Ed25519KeyPair>> {
+	let (version, key) = global
 		.get_blocking(b"keypair")
 		.map(|ref val| {
 			// database deserializer is having trouble with this so it's manual for now
@@ -36,7 +78,7 @@ fn load(db: &Arc<Database>) -> Result<Box<Ed25519KeyPair>> {
 		})
 		.or_else(|e| {
 			assert!(e.is_not_found(), "unexpected error fetching keypair");
-			create(db)
+			create(global)
 		})?;
 
 	let key = Ed25519KeyPair::from_der(&key, version)
@@ -45,21 +87,22 @@ fn load(db: &Arc<Database>) -> Result<Box<Ed25519KeyPair>> {
 	Ok(Box::new(key))
 }
 
-fn create(db: &Arc<Database>) -> Result<(String, Vec<u8>)> {
+fn create(global: &Map) -> Result<(String, Vec<u8>)> {
+	let value = generate()?;
+	global.raw_put(b"keypair", &value);
+
+	Ok(value)
+}
+
+fn generate() -> Result<(String, Vec<u8>)> {
 	let keypair = Ed25519KeyPair::generate()
 		.map_err(|e| err!("Failed to generate new ed25519 keypair: {e:?}"))?;
 
 	let id = utils::rand::string(8);
 	debug_info!("Generated new Ed25519 keypair: {id:?}");
 
-	let value: (String, Vec<u8>) = (id, keypair.to_vec());
-	db["global"].raw_put(b"keypair", &value);
-
-	Ok(value)
+	Ok((id, keypair.to_vec()))
 }
 
 #[inline]
-fn remove(db: &Arc<Database>) {
-	let global = &db["global"];
-	global.remove(b"keypair");
-}
+fn remove(global: &Map) { global.remove(b"keypair"); }
@@ -5,53 +5,72 @@
 mod sign;
 mod verify;
 
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+	collections::{BTreeMap, HashMap, hash_map},
+	sync::{Arc, RwLock},
+	time::{Duration, Instant},
+};
 
 use futures::StreamExt;
 use ruma::{
-	CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedServerSigningKeyId, ServerName,
-	ServerSigningKeyId,
-	api::federation::discovery::{ServerSigningKeys, VerifyKey},
+	CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedServerName, OwnedServerSigningKeyId,
+	ServerName, ServerSigningKeyId,
+	api::federation::discovery::{OldVerifyKey, ServerSigningKeys, VerifyKey},
 	room_version_rules::RoomVersionRules,
 	serde::Raw,
 	signatures::{Ed25519KeyPair, PublicKeyMap, PublicKeySet},
 };
 use serde_json::value::RawValue as RawJsonValue;
 use tuwunel_core::{
-	Result, implement,
-	utils::{IterStream, timepoint_from_now},
+	Result, implement, info,
+	utils::{IterStream, continue_exponential_backoff, timepoint_from_now},
 };
 use tuwunel_database::{Deserialized, Json, Map};
 
 pub struct Service {
-	keypair: Box<Ed25519KeyPair>,
-	verify_keys: VerifyKeys,
+	keypair: RwLock<Arc<Ed25519KeyPair>>,
+	verify_keys: RwLock<VerifyKeys>,
+	old_verify_keys: RwLock<OldVerifyKeys>,
 	minimum_valid: Duration,
+	refetch_ratelimiter: RwLock<HashMap<OwnedServerName, RateLimitState>>,
 	services: Arc<crate::services::OnceServices>,
 	db: Data,
 }
 
 struct Data {
+	global: Arc<Map>,
 	server_signingkeys: Arc<Map>,
 }
 
 pub type VerifyKeys = BTreeMap<OwnedServerSigningKeyId, VerifyKey>;
+pub type OldVerifyKeys = BTreeMap<OwnedServerSigningKeyId, OldVerifyKey>;
 pub type PubKeyMap = PublicKeyMap;
 pub type PubKeys = PublicKeySet;
 
+type RateLimitState = (Instant, u32); // Time of last refetch, number of refetches
+
+/// Bounds of the backoff between attempts to fetch again the expired keys of
+/// a server.
+const REFETCH_BACKOFF: (Duration, Duration) =
+	(Duration::from_secs(5 * 60), Duration::from_secs(24 * 60 * 60));
+
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
-		let minimum_valid = Duration::from_secs(3600);
+		let minimum_valid = Duration::from_secs(args.server.config.key_minimum_valid_duration);
 
-		let (keypair, verify_keys) = keypair::init(args.db)?;
+		let global = args.db["global"].clone();
+		let (keypair, verify_keys, old_verify_keys) = keypair::init(&global)?;
 		debug_assert!(verify_keys.len() == 1, "only one active verify_key supported");
 
 		Ok(Arc::new(Self {
-			keypair,
-			verify_keys,
+			keypair: RwLock::new(keypair.into()),
+			verify_keys: RwLock::new(verify_keys),
+			old_verify_keys: RwLock::new(old_verify_keys),
 			minimum_valid,
+			refetch_ratelimiter: RwLock::new(HashMap::new()),
 			services: args.services.clone(),
 			db: Data {
+				global,
 				server_signingkeys: args.db["server_signingkeys"].clone(),
 			},
 		}))
@@ -63,25 +82,58 @@ fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 #[implement(Service)]
 #[inline]
 #[must_use]
-pub fn keypair(&self) -> &Ed25519KeyPair { &self.keypair }
+pub fn keypair(&self) -> Arc<Ed25519KeyPair> { self.keypair.read().expect("locked").clone() }
 
 #[implement(Service)]
 #[inline]
 #[must_use]
-pub fn active_key_id(&self) -> &ServerSigningKeyId { self.active_verify_key().0 }
+pub fn active_key_id(&self) -> OwnedServerSigningKeyId { self.active_verify_key().0 }
 
 #[implement(Service)]
-#[inline]
 #[must_use]
-pub fn active_verify_key(&self) -> (&ServerSigningKeyId, &VerifyKey) {
-	debug_assert!(self.verify_keys.len() <= 1, "more than one active verify_key");
-	self.verify_keys
+pub fn active_verify_key(&self) -> (OwnedServerSigningKeyId, VerifyKey) {
+	let verify_keys = self.verify_keys.read().expect("locked");
+	debug_assert!(verify_keys.len() <= 1, "more than one active verify_key");
+	verify_keys
 		.iter()
 		.next()
-		.map(|(id, key)| (id.as_ref(), key))
+		.map(|(id, key)| (id.clone(), key.clone()))
 		.expect("missing active verify_key")
 }
 
+/// The keys this server signed with before rotating to the active one, and
+/// when they expired.
+#[implement(Service)]
+#[must_use]
+pub fn old_verify_keys(&self) -> OldVerifyKeys {
+	self.old_verify_keys
+		.read()
+		.expect("locked")
+		.clone()
+}
+
+/// Replaces the signing key of this server with a new one. The previous key
+/// joins the old verify keys, expiring now, so that what it signed can still
+/// be verified. Returns the ID of the new key.
+#[implement(Service)]
+pub fn rotate_keypair(&self) -> Result<OwnedServerSigningKeyId> {
+	let mut verify_keys = self.verify_keys.write().expect("locked");
+	let mut old_verify_keys = self.old_verify_keys.write().expect("locked");
+	let (keypair, new_verify_keys) =
+		keypair::rotate(&self.db.global, verify_keys.clone(), &mut old_verify_keys)?;
+
+	*self.keypair.write().expect("locked") = keypair.into();
+	*verify_keys = new_verify_keys;
+
+	let (key_id, _) = verify_keys
+		.iter()
+		.next()
+		.expect("missing active verify_key");
+
+	info!(%key_id, "Rotated the server signing key");
+	Ok(key_id.clone())
+}
+
 #[implement(Service)]
 async fn add_signing_keys(&self, new_keys: ServerSigningKeys) {
 	let origin = &new_keys.server_name;
@@ -98,10 +150,18 @@ async fn add_signing_keys(&self, new_keys: ServerSigningKeys) {
 			ServerSigningKeys::new(origin.to_owned(), MilliSecondsSinceUnixEpoch::now())
 		});
 
+	keys.valid_until_ts = keys.valid_until_ts.max(new_keys.valid_until_ts);
 	keys.verify_keys.extend(new_keys.verify_keys);
 	keys.old_verify_keys
 		.extend(new_keys.old_verify_keys);
 
+	if !keys_expired(&keys) {
+		self.refetch_ratelimiter
+			.write()
+			.expect("locked")
+			.remove(origin);
+	}
+
 	self.db
 		.server_signingkeys
 		.raw_put(origin, Json(&keys));
@@ -156,6 +216,53 @@ pub async fn verify_key_exists(&self, origin: &ServerName, key_id: &ServerSignin
 	false
 }
 
+/// Whether `key_id` is a current key of `origin` which has expired.
+#[implement(Service)]
+pub async fn verify_key_expired(&self, origin: &ServerName, key_id: &ServerSigningKeyId) -> bool {
+	self.signing_keys_for(origin)
+		.await
+		.is_ok_and(|keys| keys.verify_keys.contains_key(key_id) && keys_expired(&keys))
+}
+
+/// Whether fetching the expired keys of `origin` again should wait, as
+/// previous attempts did not yield current keys.
+#[implement(Service)]
+fn refetch_backed_off(&self, origin: &ServerName) -> bool {
+	let Some((time, tries)) = self
+		.refetch_ratelimiter
+		.read()
+		.expect("locked")
+		.get(origin)
+		.copied()
+	else {
+		return false;
+	};
+
+	let (min, max) = REFETCH_BACKOFF;
+	continue_exponential_backoff(min, max, time.elapsed(), tries)
+}
+
+/// Records an attempt to fetch the expired keys of `origin` again; it is
+/// forgotten once current keys are added.
+#[implement(Service)]
+fn back_off_refetch(&self, origin: &ServerName) {
+	use hash_map::Entry::{Occupied, Vacant};
+
+	match self
+		.refetch_ratelimiter
+		.write()
+		.expect("locked")
+		.entry(origin.into())
+	{
+		| Vacant(e) => {
+			e.insert((Instant::now(), 1));
+		},
+		| Occupied(mut e) => {
+			*e.get_mut() = (Instant::now(), e.get().1.saturating_add(1));
+		},
+	}
+}
+
 #[implement(Service)]
 pub async fn verify_keys_for(&self, origin: &ServerName) -> VerifyKeys {
 	let mut keys = self
@@ -165,7 +272,12 @@ pub async fn verify_keys_for(&self, origin: &ServerName) -> VerifyKeys {
 		.unwrap_or(BTreeMap::new());
 
 	if self.services.globals.server_is_ours(origin) {
-		keys.extend(self.verify_keys.clone().into_iter());
+		keys.extend(
+			self.old_verify_keys()
+				.into_iter()
+				.map(|(key_id, old)| (key_id, VerifyKey::new(old.key))),
+		);
+		keys.extend(self.verify_keys.read().expect("locked").clone());
 	}
 
 	keys
@@ -188,6 +300,33 @@ fn minimum_valid_ts(&self) -> MilliSecondsSinceUnixEpoch {
 	MilliSecondsSinceUnixEpoch::from_system_time(timepoint).expect("UInt should not overflow")
 }
 
+/// Whether signatures by the expired current keys of a server are accepted
+/// under the configured `key_expiry_policy`, once fetching them again failed.
+#[implement(Service)]
+#[must_use]
+pub fn expired_keys_accepted(&self, keys: &ServerSigningKeys) -> bool {
+	let config = &self.services.config;
+	if config.key_expiry_policy == "strict" {
+		return false;
+	}
+
+	let Some(grace_period) = config.key_expiry_grace_period else {
+		return true;
+	};
+
+	let accepted_until =
+		u64::from(keys.valid_until_ts.get()).saturating_add(grace_period.saturating_mul(1000));
+
+	accepted_until > u64::from(MilliSecondsSinceUnixEpoch::now().get())
+}
+
+/// Whether the current keys of a server have expired. Old keys have expired
+/// by definition and remain usable for the signatures they made before.
+#[must_use]
+pub fn keys_expired(keys: &ServerSigningKeys) -> bool {
+	keys.valid_until_ts < MilliSecondsSinceUnixEpoch::now()
+}
+
 fn merge_old_keys(mut keys: ServerSigningKeys) -> ServerSigningKeys {
 	keys.verify_keys.extend(
 		keys.old_verify_keys
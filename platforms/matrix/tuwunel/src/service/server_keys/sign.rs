@@ -69,7 +69,7 @@ pub fn hash_and_sign_event(
 
 	hash_and_sign_event(
 		server_name.as_str(),
-		self.keypair(),
+		&self.keypair(),
 		object,
 		&room_version_rules.redaction,
 	)
@@ -90,5 +90,5 @@ pub fn sign_json(&self, object: &mut CanonicalJsonObject) -> Result {
 
 	let server_name = self.services.globals.server_name().as_str();
 
-	sign_json(server_name, self.keypair(), object).map_err(Into::into)
+	sign_json(server_name, &self.keypair(), object).map_err(Into::into)
 }
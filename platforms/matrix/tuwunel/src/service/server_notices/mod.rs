@@ -0,0 +1,265 @@
+//! Server notices: messages from the server to a local user, posted by the
+//! server user in a room kept for that user and tagged `m.server_notice` so
+//! clients can set it apart. The user is invited to the room and cannot post
+//! in it. A new room is created once the user has left the previous one.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use futures::FutureExt;
+use ruma::{
+	OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId, UserId,
+	events::{
+		room::{
+			create::RoomCreateEventContent,
+			guest_access::{GuestAccess, RoomGuestAccessEventContent},
+			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+			message::RoomMessageEventContent,
+			name::RoomNameEventContent,
+			power_levels::RoomPowerLevelsEventContent,
+		},
+		tag::TagName,
+	},
+};
+use tuwunel_core::{
+	Err, Result, debug_info, implement, matrix::pdu::PduBuilder, utils::MutexMap, warn,
+};
+use tuwunel_database::{Deserialized, Map};
+
+pub struct Service {
+	services: Arc<crate::services::OnceServices>,
+	userid_servernoticeroomid: Arc<Map>,
+	room_mutex: MutexMap<OwnedUserId, ()>,
+	quota_notified: Mutex<HashMap<OwnedUserId, Instant>>,
+}
+
+/// Minimum time between notices to a user that their media quota is exceeded.
+const QUOTA_NOTICE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: args.services.clone(),
+			userid_servernoticeroomid: args.db["userid_servernoticeroomid"].clone(),
+			room_mutex: MutexMap::new(),
+			quota_notified: Mutex::default(),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Sends a server notice in Markdown to a local user.
+#[implement(Service)]
+pub async fn send_text(&self, user_id: &UserId, body: &str) -> Result<OwnedEventId> {
+	self.send(user_id, RoomMessageEventContent::text_markdown(body))
+		.await
+}
+
+/// Sends a message to a local user in their server notices room, creating the
+/// room first if they have none.
+#[implement(Service)]
+pub async fn send(
+	&self,
+	user_id: &UserId,
+	content: RoomMessageEventContent,
+) -> Result<OwnedEventId> {
+	let room_id = self.room(user_id).await?;
+	self.post(&room_id, &content).await
+}
+
+#[implement(Service)]
+async fn post(
+	&self,
+	room_id: &RoomId,
+	content: &RoomMessageEventContent,
+) -> Result<OwnedEventId> {
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::timeline(content),
+			&self.services.globals.server_user,
+			room_id,
+			&state_lock,
+		)
+		.boxed()
+		.await
+}
+
+/// Notifies a user that an upload was refused for exceeding their media quota,
+/// at most once per day.
+#[implement(Service)]
+pub async fn notify_media_quota(&self, user_id: &UserId, usage: u64, quota: u64) {
+	if !self.services.config.server_notices_automatic {
+		return;
+	}
+
+	{
+		let mut notified = self.quota_notified.lock().expect("locked");
+		let now = Instant::now();
+		if notified
+			.get(user_id)
+			.is_some_and(|last| now.duration_since(*last) < QUOTA_NOTICE_INTERVAL)
+		{
+			return;
+		}
+
+		notified.retain(|_, last| now.duration_since(*last) < QUOTA_NOTICE_INTERVAL);
+		notified.insert(user_id.to_owned(), now);
+	}
+
+	let body = format!(
+		"Your media quota is exceeded: {usage} of {quota} bytes are in use. Uploads will be \
+		 refused until some of your media is deleted."
+	);
+
+	if let Err(e) = self.send_text(user_id, &body).await {
+		warn!(%user_id, "Failed to send media quota notice: {e}");
+	}
+}
+
+/// Notifies a user that their account is being deactivated by an
+/// administrator. Sent ahead of the deactivation of a user keeping their rooms,
+/// so the notice awaits them should the account be reactivated; it is only
+/// posted in a server notices room they already have, never a new one.
+#[implement(Service)]
+pub async fn notify_deactivation(&self, user_id: &UserId) {
+	if !self.services.config.server_notices_automatic {
+		return;
+	}
+
+	let Ok(room_id) = self.get_room(user_id).await else {
+		return;
+	};
+
+	if !self.in_room(user_id, &room_id).await {
+		return;
+	}
+
+	let content = RoomMessageEventContent::text_markdown(
+		"Your account has been deactivated by the server administrator.",
+	);
+
+	if let Err(e) = self.post(&room_id, &content).await {
+		warn!(%user_id, "Failed to send deactivation notice: {e}");
+	}
+}
+
+/// The server notices room of a local user: the room they last had, if they
+/// are still joined or invited, otherwise a new one.
+#[implement(Service)]
+pub async fn room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+	if !self.services.globals.user_is_local(user_id) {
+		return Err!(Request(InvalidParam("Server notices can only be sent to local users.")));
+	}
+
+	if *user_id == self.services.globals.server_user {
+		return Err!(Request(InvalidParam("Server notices cannot be sent to the server user.")));
+	}
+
+	if !self.services.users.is_active(user_id).await {
+		return Err!(Request(NotFound("User {user_id} does not exist or is deactivated.")));
+	}
+
+	let _lock = self.room_mutex.lock(user_id).await;
+	if let Ok(room_id) = self.get_room(user_id).await
+		&& self.in_room(user_id, &room_id).await
+	{
+		return Ok(room_id);
+	}
+
+	let room_id = self.create_room(user_id).await?;
+	self.userid_servernoticeroomid
+		.insert(user_id, room_id.as_bytes());
+
+	Ok(room_id)
+}
+
+/// The server notices room last created for a user.
+#[implement(Service)]
+pub async fn get_room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+	self.userid_servernoticeroomid
+		.get(user_id)
+		.await
+		.deserialized()
+}
+
+/// Whether the user is still joined or invited to their server notices room.
+#[implement(Service)]
+async fn in_room(&self, user_id: &UserId, room_id: &RoomId) -> bool {
+	let state_cache = &self.services.state_cache;
+	state_cache.is_joined(user_id, room_id).await
+		|| state_cache.is_invited(user_id, room_id).await
+}
+
+#[implement(Service)]
+async fn create_room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+	let services = &self.services;
+	let server_user = services.globals.server_user.as_ref();
+	let room_id = RoomId::new_v1(services.globals.server_name());
+
+	let _short_id = services
+		.short
+		.get_or_create_shortroomid(&room_id)
+		.await;
+
+	debug_info!(%user_id, %room_id, "Creating server notices room");
+	let state_lock = services.state.mutex.lock(&room_id).await;
+	let users = [(server_user.to_owned(), 100.into())].into();
+	let state = [
+		PduBuilder::state(String::new(), &RoomCreateEventContent {
+			federate: false,
+			room_version: RoomVersionId::V11,
+			..RoomCreateEventContent::new_v11()
+		}),
+		PduBuilder::state(
+			String::from(server_user),
+			&RoomMemberEventContent::new(MembershipState::Join),
+		),
+		PduBuilder::state(String::new(), &RoomPowerLevelsEventContent {
+			users,
+			events_default: 100.into(),
+			invite: 100.into(),
+			..Default::default()
+		}),
+		PduBuilder::state(String::new(), &RoomJoinRulesEventContent::new(JoinRule::Invite)),
+		PduBuilder::state(
+			String::new(),
+			&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Shared),
+		),
+		PduBuilder::state(
+			String::new(),
+			&RoomGuestAccessEventContent::new(GuestAccess::Forbidden),
+		),
+		PduBuilder::state(
+			String::new(),
+			&RoomNameEventContent::new(services.config.server_notices_room_name.clone()),
+		),
+		PduBuilder::state(
+			String::from(user_id),
+			&RoomMemberEventContent::new(MembershipState::Invite),
+		),
+	];
+
+	for pdu in state {
+		services
+			.timeline
+			.build_and_append_pdu(pdu, server_user, &room_id, &state_lock)
+			.boxed()
+			.await?;
+	}
+
+	services
+		.account_data
+		.set_room_tag(user_id, &room_id, TagName::ServerNotice, None)
+		.await?;
+
+	Ok(room_id)
+}
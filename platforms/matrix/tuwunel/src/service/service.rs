@@ -4,7 +4,7 @@
 use tuwunel_core::{Result, Server, utils::string::SplitInfallible};
 use tuwunel_database::Database;
 
-use crate::services::OnceServices;
+use crate::{Heartbeat, services::OnceServices};
 
 /// Abstract interface for a Service
 #[async_trait]
@@ -36,10 +36,25 @@ async fn memory_usage(&self, _out: &mut (dyn Write + Send)) -> Result { Ok(()) }
 	/// i.e. `crate::service::make_name(std::module_path!())`
 	fn name(&self) -> &str;
 
+	/// Return the names of the services this service relies on at runtime.
+	/// When only some services are started, the workers of their dependencies
+	/// are started with them, and before them.
+	fn dependencies(&self) -> &'static [&'static str] { &[] }
+
+	/// Return the heartbeats of the service's worker. The service manager
+	/// restarts the worker when any of them stops beating for longer than
+	/// `worker_watchdog_timeout`. Workers without heartbeats are restarted
+	/// only when they panic.
+	fn heartbeats(&self) -> &[Heartbeat] { &[] }
+
 	/// Return true if the service worker opts out of the tokio cooperative
 	/// budgeting. This can reduce tail latency at the risk of event loop
 	/// starvation.
 	fn unconstrained(&self) -> bool { false }
+
+	/// Return true if the service worker does maintenance whose database
+	/// requests should yield to those made on behalf of clients.
+	fn background(&self) -> bool { false }
 }
 
 /// Args are passed to `Service::build` when a service is constructed. This
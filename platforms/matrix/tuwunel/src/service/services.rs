@@ -3,20 +3,21 @@
 use futures::{StreamExt, TryStreamExt};
 use tokio::sync::Mutex;
 use tuwunel_core::{
-	Result, Server, debug, debug_info, implement, info, trace, utils::stream::IterStream,
+	Phase, Result, Server, debug, debug_info, implement, info, trace, utils::stream::IterStream,
 };
 use tuwunel_database::Database;
 
 pub(crate) use crate::OnceServices;
 use crate::{
-	account_data, admin, appservice, client, config, deactivate, emergency, federation, globals,
-	key_backups,
+	account_data, admin, appservice, client, compaction, config, deactivate, emergency,
+	event_export, federation, geoip, globals, jobs, key_backups, mailer,
 	manager::Manager,
-	media, membership, oauth, presence, pusher, registration_tokens, resolver,
+	media, membership, oauth, presence, pusher, ratelimit, registration_tokens, rendezvous,
+	reports, resolver,
 	rooms::{self, retention},
-	sending, server_keys,
+	sanitize, sending, server_keys, server_notices,
 	service::{Args, Service},
-	sync, transaction_ids, uiaa, users,
+	sync, threepid, transaction_ids, uiaa, users,
 };
 
 pub struct Services {
@@ -25,27 +26,39 @@ pub struct Services {
 	pub appservice: Arc<appservice::Service>,
 	pub config: Arc<config::Service>,
 	pub client: Arc<client::Service>,
+	pub compaction: Arc<compaction::Service>,
 	pub emergency: Arc<emergency::Service>,
+	pub event_export: Arc<event_export::Service>,
+	pub geoip: Arc<geoip::Service>,
 	pub globals: Arc<globals::Service>,
+	pub jobs: Arc<jobs::Service>,
 	pub key_backups: Arc<key_backups::Service>,
+	pub mailer: Arc<mailer::Service>,
 	pub media: Arc<media::Service>,
 	pub presence: Arc<presence::Service>,
 	pub pusher: Arc<pusher::Service>,
+	pub ratelimit: Arc<ratelimit::Service>,
+	pub reports: Arc<reports::Service>,
 	pub resolver: Arc<resolver::Service>,
+	pub sanitize: Arc<sanitize::Service>,
 	pub alias: Arc<rooms::alias::Service>,
 	pub auth_chain: Arc<rooms::auth_chain::Service>,
 	pub delete: Arc<rooms::delete::Service>,
+	pub desired_state: Arc<rooms::desired_state::Service>,
 	pub directory: Arc<rooms::directory::Service>,
 	pub event_handler: Arc<rooms::event_handler::Service>,
+	pub export: Arc<rooms::export::Service>,
 	pub lazy_loading: Arc<rooms::lazy_loading::Service>,
 	pub metadata: Arc<rooms::metadata::Service>,
 	pub pdu_metadata: Arc<rooms::pdu_metadata::Service>,
 	pub read_receipt: Arc<rooms::read_receipt::Service>,
 	pub search: Arc<rooms::search::Service>,
+	pub share_links: Arc<rooms::share_links::Service>,
 	pub short: Arc<rooms::short::Service>,
 	pub spaces: Arc<rooms::spaces::Service>,
 	pub state: Arc<rooms::state::Service>,
 	pub state_accessor: Arc<rooms::state_accessor::Service>,
+	pub state_bloat: Arc<rooms::state_bloat::Service>,
 	pub state_cache: Arc<rooms::state_cache::Service>,
 	pub state_compressor: Arc<rooms::state_compressor::Service>,
 	pub threads: Arc<rooms::threads::Service>,
@@ -54,7 +67,10 @@ pub struct Services {
 	pub federation: Arc<federation::Service>,
 	pub sending: Arc<sending::Service>,
 	pub server_keys: Arc<server_keys::Service>,
+	pub server_notices: Arc<server_notices::Service>,
+	pub rendezvous: Arc<rendezvous::Service>,
 	pub sync: Arc<sync::Service>,
+	pub threepid: Arc<threepid::Service>,
 	pub transaction_ids: Arc<transaction_ids::Service>,
 	pub uiaa: Arc<uiaa::Service>,
 	pub users: Arc<users::Service>,
@@ -85,27 +101,39 @@ pub async fn build(server: Arc<Server>) -> Result<Arc<Self>> {
 		appservice: appservice::Service::build(&args)?,
 		resolver: resolver::Service::build(&args)?,
 		client: client::Service::build(&args)?,
+		compaction: compaction::Service::build(&args)?,
 		config: config::Service::build(&args)?,
 		emergency: emergency::Service::build(&args)?,
+		event_export: event_export::Service::build(&args)?,
+		geoip: geoip::Service::build(&args)?,
 		globals: globals::Service::build(&args)?,
+		jobs: jobs::Service::build(&args)?,
 		key_backups: key_backups::Service::build(&args)?,
+		mailer: mailer::Service::build(&args)?,
 		media: media::Service::build(&args)?,
 		presence: presence::Service::build(&args)?,
 		pusher: pusher::Service::build(&args)?,
+		ratelimit: ratelimit::Service::build(&args)?,
+		reports: reports::Service::build(&args)?,
+		sanitize: sanitize::Service::build(&args)?,
 		alias: rooms::alias::Service::build(&args)?,
 		auth_chain: rooms::auth_chain::Service::build(&args)?,
 		delete: rooms::delete::Service::build(&args)?,
+		desired_state: rooms::desired_state::Service::build(&args)?,
 		directory: rooms::directory::Service::build(&args)?,
 		event_handler: rooms::event_handler::Service::build(&args)?,
+		export: rooms::export::Service::build(&args)?,
 		lazy_loading: rooms::lazy_loading::Service::build(&args)?,
 		metadata: rooms::metadata::Service::build(&args)?,
 		pdu_metadata: rooms::pdu_metadata::Service::build(&args)?,
 		read_receipt: rooms::read_receipt::Service::build(&args)?,
 		search: rooms::search::Service::build(&args)?,
+		share_links: rooms::share_links::Service::build(&args)?,
 		short: rooms::short::Service::build(&args)?,
 		spaces: rooms::spaces::Service::build(&args)?,
 		state: rooms::state::Service::build(&args)?,
 		state_accessor: rooms::state_accessor::Service::build(&args)?,
+		state_bloat: rooms::state_bloat::Service::build(&args)?,
 		state_cache: rooms::state_cache::Service::build(&args)?,
 		state_compressor: rooms::state_compressor::Service::build(&args)?,
 		threads: rooms::threads::Service::build(&args)?,
@@ -114,7 +142,10 @@ pub async fn build(server: Arc<Server>) -> Result<Arc<Self>> {
 		federation: federation::Service::build(&args)?,
 		sending: sending::Service::build(&args)?,
 		server_keys: server_keys::Service::build(&args)?,
+		server_notices: server_notices::Service::build(&args)?,
+		rendezvous: rendezvous::Service::build(&args)?,
 		sync: sync::Service::build(&args)?,
+		threepid: threepid::Service::build(&args)?,
 		transaction_ids: transaction_ids::Service::build(&args)?,
 		uiaa: uiaa::Service::build(&args)?,
 		users: users::Service::build(&args)?,
@@ -146,27 +177,39 @@ macro_rules! cast {
 		cast!(self.appservice),
 		cast!(self.resolver),
 		cast!(self.client),
+		cast!(self.compaction),
 		cast!(self.config),
 		cast!(self.emergency),
+		cast!(self.event_export),
+		cast!(self.geoip),
 		cast!(self.globals),
+		cast!(self.jobs),
 		cast!(self.key_backups),
+		cast!(self.mailer),
 		cast!(self.media),
 		cast!(self.presence),
 		cast!(self.pusher),
+		cast!(self.ratelimit),
+		cast!(self.reports),
+		cast!(self.sanitize),
 		cast!(self.alias),
 		cast!(self.auth_chain),
 		cast!(self.delete),
+		cast!(self.desired_state),
 		cast!(self.directory),
 		cast!(self.event_handler),
+		cast!(self.export),
 		cast!(self.lazy_loading),
 		cast!(self.metadata),
 		cast!(self.pdu_metadata),
 		cast!(self.read_receipt),
 		cast!(self.search),
+		cast!(self.share_links),
 		cast!(self.short),
 		cast!(self.spaces),
 		cast!(self.state),
 		cast!(self.state_accessor),
+		cast!(self.state_bloat),
 		cast!(self.state_cache),
 		cast!(self.state_compressor),
 		cast!(self.threads),
@@ -175,7 +218,10 @@ macro_rules! cast {
 		cast!(self.federation),
 		cast!(self.sending),
 		cast!(self.server_keys),
+		cast!(self.server_notices),
+		cast!(self.rendezvous),
 		cast!(self.sync),
+		cast!(self.threepid),
 		cast!(self.transaction_ids),
 		cast!(self.uiaa),
 		cast!(self.users),
@@ -198,7 +244,10 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 pub async fn start(self: &Arc<Self>) -> Result<Arc<Self>> {
 	debug_info!("Starting services...");
 
+	self.server.set_phase(Phase::Migrating);
 	super::migrations::migrations(self).await?;
+	self.server.set_phase(Phase::Starting);
+
 	self.manager
 		.lock()
 		.await
@@ -1,11 +1,14 @@
+mod wakers;
 mod watch;
 
 use std::{
 	collections::{BTreeMap, btree_map::Entry},
 	sync::Arc,
+	time::{Duration, Instant},
 };
 
-use futures::{FutureExt, Stream};
+use async_trait::async_trait;
+use futures::{FutureExt, Stream, StreamExt};
 use ruma::{
 	DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, RoomId, UserId,
 	api::client::sync::sync_events::v5::{
@@ -14,17 +17,29 @@
 	},
 };
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex as TokioMutex;
-use tuwunel_core::{Result, at, debug, err, implement, is_equal_to, utils::stream::TryIgnore};
-use tuwunel_database::{Cbor, Deserialized, Map};
+use tokio::{sync::Mutex as TokioMutex, time::sleep};
+use tuwunel_core::{
+	Err, Result, at, debug, debug_info, err, implement, is_equal_to,
+	utils::{
+		stream::{IterStream, ReadyExt, TryIgnore},
+		time::now_millis,
+	},
+};
+use tuwunel_database::{
+	Cbor, Database, Deserialized, Interfix, Map, serialize_to_vec, serialize_val,
+};
+
+pub use self::watch::Watcher;
 
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
 	connections: Connections,
+	room_wakers: wakers::RoomWakers,
 	db: Data,
 }
 
 struct Data {
+	db: Arc<Database>,
 	userdeviceconnid_conn: Arc<Map>,
 	todeviceid_events: Arc<Map>,
 	userroomid_joined: Arc<Map>,
@@ -50,6 +65,33 @@ pub struct Connection {
 	pub extensions: request::Extensions,
 	pub subscriptions: Subscriptions,
 	pub rooms: Rooms,
+
+	/// Number of requests made on the connection.
+	#[serde(default)]
+	pub requests: u64,
+
+	/// Number of the request which last subscribed to each room, by which
+	/// subscriptions the client stopped refreshing are expired.
+	#[serde(default)]
+	pub subscribed: BTreeMap<OwnedRoomId, u64>,
+
+	/// Milliseconds since the unix epoch when the connection was last stored;
+	/// zero for connections stored before this was recorded.
+	#[serde(default)]
+	pub last_used: u64,
+
+	/// Whether the connection was loaded from the database since its last
+	/// response, e.g. after a restart. The receipts and typing extensions of
+	/// the next response then replay what the client may have missed.
+	#[serde(skip)]
+	pub reloaded: bool,
+
+	/// Watcher armed before `next_batch` was taken for the last response.
+	/// While it has not completed nothing synced to the connection changed,
+	/// so a request advancing to `next_batch` with the same parameters can
+	/// be answered without loading any rooms.
+	#[serde(skip)]
+	pub watcher: Option<Watcher>,
 }
 
 #[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
@@ -57,18 +99,48 @@ pub struct Room {
 	pub roomsince: u64,
 }
 
-type Connections = TokioMutex<BTreeMap<ConnectionKey, ConnectionVal>>;
+type Connections = TokioMutex<BTreeMap<ConnectionKey, Cached>>;
 pub type ConnectionVal = Arc<TokioMutex<Connection>>;
 pub type ConnectionKey = (OwnedUserId, Option<OwnedDeviceId>, Option<ConnectionId>);
 
+/// A loaded connection.
+struct Cached {
+	conn: ConnectionVal,
+	last_used: Instant,
+}
+
+/// Number of stored connections examined between flushes when pruning.
+const PRUNE_BATCH: usize = 256;
+
+/// Interval between expiring and evicting connections.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Loaded connections idle for this long are evicted regardless of capacity.
+const CACHE_IDLE: Duration = Duration::from_secs(1800);
+
+/// Outcome of [`Service::prune_connections`].
+#[derive(Debug, Default)]
+pub struct Pruned {
+	/// Connections removed because their user or device no longer exists.
+	pub connections: usize,
+
+	/// Rooms forgotten by the remaining connections.
+	pub rooms: usize,
+
+	/// Bytes of connection state removed from the database.
+	pub bytes: usize,
+}
+
 pub type Subscriptions = BTreeMap<OwnedRoomId, request::ListConfig>;
 pub type Lists = BTreeMap<ListId, request::List>;
 pub type Rooms = BTreeMap<OwnedRoomId, Room>;
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			db: Data {
+				db: args.db.clone(),
 				userdeviceconnid_conn: args.db["userdeviceconnid_conn"].clone(),
 				todeviceid_events: args.db["todeviceid_events"].clone(),
 				userroomid_joined: args.db["userroomid_joined"].clone(),
@@ -88,9 +160,31 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 			},
 			services: args.services.clone(),
 			connections: Default::default(),
+			room_wakers: Default::default(),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		loop {
+			tokio::select! {
+				() = sleep(SWEEP_INTERVAL) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			let capacity = self
+				.services
+				.config
+				.sync_connection_cache_capacity;
+			let evicted = self.evict_connections(capacity, CACHE_IDLE).await;
+			let expired = self.expire_connections().await;
+			if evicted > 0 || expired > 0 {
+				debug_info!(?evicted, ?expired, "Swept sliding sync connections");
+			}
+		}
+	}
+
+	async fn clear_cache(&self) { self.evict_connections(0, Duration::ZERO).await; }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -102,22 +196,129 @@ pub async fn clear_connections(
 	device_id: Option<&DeviceId>,
 	conn_id: Option<&ConnectionId>,
 ) {
+	let matches = |(conn_user_id, conn_device_id, conn_conn_id): &ConnectionKey| {
+		user_id.is_none_or(is_equal_to!(conn_user_id))
+			&& (device_id.is_none() || device_id == conn_device_id.as_deref())
+			&& (conn_id.is_none() || conn_id == conn_conn_id.as_ref())
+	};
+
 	self.connections
 		.lock()
 		.await
-		.retain(|(conn_user_id, conn_device_id, conn_conn_id), _| {
-			let retain = user_id.is_none_or(is_equal_to!(conn_user_id))
-				&& (device_id.is_none() || device_id == conn_device_id.as_deref())
-				&& (conn_id.is_none() || conn_id == conn_conn_id.as_ref());
-
-			if !retain {
-				self.db
-					.userdeviceconnid_conn
-					.del((conn_user_id, conn_device_id, conn_conn_id));
-			}
+		.retain(|key, _| !matches(key));
+
+	// Connections which are not loaded only exist in the database; those of a
+	// single user are found by prefix.
+	let keys = match user_id {
+		| Some(user_id) => self
+			.db
+			.userdeviceconnid_conn
+			.keys_prefix(&(user_id, Interfix))
+			.boxed(),
+		| None => self.db.userdeviceconnid_conn.keys().boxed(),
+	};
+
+	keys.ignore_err()
+		.ready_filter(|key: &ConnectionKey| matches(key))
+		.ready_for_each(|key| self.db.userdeviceconnid_conn.del(key))
+		.await;
+}
 
-			retain
-		});
+/// Remove stored connections whose user or device no longer exists, and
+/// forget rooms in the remaining connections which the user left before the
+/// connection last synced, or has forgotten. Loaded connections are in use
+/// and only removed when orphaned.
+#[implement(Service)]
+#[tracing::instrument(level = "info", skip(self))]
+pub async fn prune_connections(&self) -> Pruned {
+	let keys: Vec<ConnectionKey> = self.list_stored_connections().collect().await;
+
+	let mut pruned = Pruned::default();
+	for batch in keys.chunks(PRUNE_BATCH) {
+		let _cork = self.db.db.cork();
+		for key in batch {
+			self.prune_connection(key, &mut pruned).await;
+		}
+	}
+
+	pruned
+}
+
+#[implement(Service)]
+async fn prune_connection(&self, key: &ConnectionKey, pruned: &mut Pruned) {
+	let (user_id, device_id, _) = key;
+	let stored = self.db.userdeviceconnid_conn.qry(key).await;
+	let stored_len = stored.as_ref().map_or(0, |handle| handle.len());
+	let conn = stored
+		.deserialized::<Cbor<Connection>>()
+		.map(at!(0));
+
+	let orphaned = !self.services.users.exists(user_id).await
+		|| match device_id {
+			| Some(device_id) =>
+				!self
+					.services
+					.users
+					.device_exists(user_id, device_id)
+					.await,
+			| None => false,
+		};
+
+	if orphaned {
+		self.connections.lock().await.remove(key);
+		self.db.userdeviceconnid_conn.del(key);
+		pruned.connections = pruned.connections.saturating_add(1);
+		pruned.bytes = pruned.bytes.saturating_add(stored_len);
+		return;
+	}
+
+	let Ok(mut conn) = conn else {
+		return;
+	};
+
+	if self.is_connection_loaded(key).await {
+		return;
+	}
+
+	let globalsince = conn.globalsince;
+	let stale: Vec<OwnedRoomId> = conn
+		.rooms
+		.keys()
+		.stream()
+		.filter_map(async |room_id| {
+			let state_cache = &self.services.state_cache;
+			let stale = match state_cache.get_left_count(room_id, user_id).await {
+				| Ok(left_count) => left_count < globalsince,
+				| Err(_) =>
+					!state_cache.is_joined(user_id, room_id).await
+						&& !state_cache.is_invited(user_id, room_id).await
+						&& !state_cache.is_knocked(user_id, room_id).await,
+			};
+
+			stale.then(|| room_id.clone())
+		})
+		.collect()
+		.await;
+
+	if stale.is_empty() {
+		return;
+	}
+
+	for room_id in &stale {
+		conn.rooms.remove(room_id);
+	}
+
+	let Ok(val) = serialize_val(Cbor(&conn)) else {
+		return;
+	};
+
+	self.db
+		.userdeviceconnid_conn
+		.put(key, Cbor(&conn));
+	pruned.rooms = pruned.rooms.saturating_add(stale.len());
+	pruned.bytes = pruned
+		.bytes
+		.saturating_add(stored_len.saturating_sub(val.len()));
 }
 
 #[implement(Service)]
@@ -135,7 +336,7 @@ pub async fn load_or_init_connection(&self, key: &ConnectionKey) -> ConnectionVa
 	let mut cache = self.connections.lock().await;
 
 	match cache.entry(key.clone()) {
-		| Entry::Occupied(val) => val.get().clone(),
+		| Entry::Occupied(mut val) => val.get_mut().touch(),
 		| Entry::Vacant(val) => {
 			let conn = self
 				.db
@@ -145,11 +346,12 @@ pub async fn load_or_init_connection(&self, key: &ConnectionKey) -> ConnectionVa
 				.await
 				.deserialized::<Cbor<_>>()
 				.map(at!(0))
+				.map(Connection::into_reloaded)
 				.map(TokioMutex::new)
 				.map(Arc::new)
 				.unwrap_or_default();
 
-			val.insert(conn).clone()
+			val.insert(Cached::new(conn)).conn.clone()
 		},
 	}
 }
@@ -160,7 +362,7 @@ pub async fn load_connection(&self, key: &ConnectionKey) -> Result<ConnectionVal
 	let mut cache = self.connections.lock().await;
 
 	match cache.entry(key.clone()) {
-		| Entry::Occupied(val) => Ok(val.get().clone()),
+		| Entry::Occupied(mut val) => Ok(val.get_mut().touch()),
 		| Entry::Vacant(val) => self
 			.db
 			.userdeviceconnid_conn
@@ -168,9 +370,10 @@ pub async fn load_connection(&self, key: &ConnectionKey) -> Result<ConnectionVal
 			.await
 			.deserialized::<Cbor<_>>()
 			.map(at!(0))
+			.map(Connection::into_reloaded)
 			.map(TokioMutex::new)
 			.map(Arc::new)
-			.map(|conn| val.insert(conn).clone()),
+			.map(|conn| val.insert(Cached::new(conn)).conn.clone()),
 	}
 }
 
@@ -181,10 +384,99 @@ pub async fn get_loaded_connection(&self, key: &ConnectionKey) -> Result<Connect
 		.lock()
 		.await
 		.get(key)
-		.cloned()
+		.map(|cached| cached.conn.clone())
 		.ok_or_else(|| err!(Request(NotFound("Connection not found."))))
 }
 
+/// Unload idle connections, least recently used first, until at most
+/// `capacity` remain, and any which have been idle for longer than `idle`.
+/// Connections in use by a request are kept. Unloaded connections are
+/// reloaded from the database when next used. Returns the number unloaded.
+#[implement(Service)]
+#[tracing::instrument(level = "debug", skip(self))]
+pub async fn evict_connections(&self, capacity: usize, idle: Duration) -> usize {
+	let mut cache = self.connections.lock().await;
+
+	let mut candidates: Vec<_> = cache
+		.iter()
+		.filter(|(_, cached)| Arc::strong_count(&cached.conn) == 1)
+		.map(|(key, cached)| (cached.last_used, key.clone()))
+		.collect();
+
+	candidates.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+	let excess = cache.len().saturating_sub(capacity);
+	let evict: Vec<_> = candidates
+		.into_iter()
+		.enumerate()
+		.take_while(|(i, (last_used, _))| *i < excess || last_used.elapsed() >= idle)
+		.map(|(_, (_, key))| key)
+		.collect();
+
+	for key in &evict {
+		cache.remove(key);
+	}
+
+	evict.len()
+}
+
+/// Discard connections which have not been used within
+/// `sync_connection_ttl_seconds`. Connections in use by a request are kept.
+/// Returns the number discarded.
+#[implement(Service)]
+#[tracing::instrument(level = "debug", skip(self))]
+pub async fn expire_connections(&self) -> usize {
+	let ttl = self.services.config.sync_connection_ttl_seconds;
+	if ttl == 0 {
+		return 0;
+	}
+
+	let now = now_millis();
+	let cutoff = now.saturating_sub(ttl.saturating_mul(1000));
+	let keys: Vec<ConnectionKey> = self.list_stored_connections().collect().await;
+
+	let mut expired: usize = 0;
+	for key in &keys {
+		let Ok(mut conn) = self
+			.db
+			.userdeviceconnid_conn
+			.qry(key)
+			.await
+			.deserialized::<Cbor<Connection>>()
+			.map(at!(0))
+		else {
+			continue;
+		};
+
+		// Connections stored before use was recorded start their TTL now.
+		if conn.last_used == 0 {
+			conn.last_used = now;
+			self.db
+				.userdeviceconnid_conn
+				.put(key, Cbor(&conn));
+			continue;
+		}
+
+		if conn.last_used >= cutoff {
+			continue;
+		}
+
+		let mut cache = self.connections.lock().await;
+		if cache
+			.get(key)
+			.is_some_and(|cached| Arc::strong_count(&cached.conn) > 1)
+		{
+			continue;
+		}
+
+		cache.remove(key);
+		self.db.userdeviceconnid_conn.del(key);
+		expired = expired.saturating_add(1);
+	}
+
+	expired
+}
+
 #[implement(Service)]
 #[tracing::instrument(level = "trace", skip(self))]
 pub async fn list_loaded_connections(&self) -> Vec<ConnectionKey> {
@@ -214,6 +506,15 @@ pub async fn is_connection_stored(&self, key: &ConnectionKey) -> bool {
 	self.db.userdeviceconnid_conn.contains(key).await
 }
 
+impl Cached {
+	fn new(conn: ConnectionVal) -> Self { Self { conn, last_used: Instant::now() } }
+
+	fn touch(&mut self) -> ConnectionVal {
+		self.last_used = Instant::now();
+		self.conn.clone()
+	}
+}
+
 #[inline]
 pub fn into_connection_key<U, D, C>(
 	user_id: U,
@@ -230,7 +531,9 @@ pub fn into_connection_key<U, D, C>(
 
 #[implement(Connection)]
 #[tracing::instrument(level = "debug", skip(self, service))]
-pub fn store(&self, service: &Service, key: &ConnectionKey) {
+pub fn store(&mut self, service: &Service, key: &ConnectionKey) {
+	self.last_used = now_millis();
+	self.reloaded = false;
 	service
 		.db
 		.userdeviceconnid_conn
@@ -243,6 +546,23 @@ pub fn store(&self, service: &Service, key: &ConnectionKey) {
 	);
 }
 
+#[implement(Connection)]
+fn into_reloaded(mut self) -> Self {
+	self.reloaded = true;
+	self
+}
+
+/// Rooms of a reloaded connection whose receipts and typing are replayed in
+/// the next response, since the client may have missed updates to them while
+/// the connection was not loaded. Empty otherwise.
+#[implement(Connection)]
+pub fn replay_rooms(&self) -> impl Iterator<Item = &RoomId> + Send + '_ {
+	self.rooms
+		.keys()
+		.filter(|_| self.reloaded)
+		.map(AsRef::as_ref)
+}
+
 #[implement(Connection)]
 #[tracing::instrument(level = "debug", skip(self))]
 pub fn update_rooms_prologue(&mut self, retard_since: Option<u64>) {
@@ -268,12 +588,78 @@ pub fn update_rooms_epilogue<'a, Rooms>(&mut self, window: Rooms)
 	});
 }
 
+/// Updates the sticky parameters of the connection with those of a request.
+/// Returns whether they changed.
+///
+/// Room subscriptions not refreshed within `subscription_expiry` requests are
+/// dropped, unless it is zero. When more than `max_subscriptions` rooms would
+/// remain subscribed, those the client subscribed to least recently are
+/// dropped. Fails without updating anything if the request alone subscribes
+/// to more.
 #[implement(Connection)]
 #[tracing::instrument(level = "debug", skip_all)]
-pub fn update_cache(&mut self, request: &Request) {
+pub fn update_cache(
+	&mut self,
+	request: &Request,
+	max_subscriptions: usize,
+	subscription_expiry: u64,
+) -> Result<bool> {
+	if request.room_subscriptions.len() > max_subscriptions {
+		return Err!(Request(InvalidParam(
+			"Too many room subscriptions; at most {max_subscriptions} are allowed per \
+			 connection."
+		)));
+	}
+
+	let before = self.params();
+
+	// Subscriptions stored before their requests were counted.
+	for room_id in self.subscriptions.keys() {
+		if !self.subscribed.contains_key(room_id) {
+			self.subscribed
+				.insert(room_id.clone(), self.requests);
+		}
+	}
+
+	let requests = self.requests.saturating_add(1);
+	let expired = |subscribed: u64| {
+		subscription_expiry > 0 && requests.saturating_sub(subscribed) > subscription_expiry
+	};
+
+	self.requests = requests;
+	self.subscribed
+		.retain(|_, subscribed| !expired(*subscribed));
+
+	let mut kept: Vec<_> = self
+		.subscribed
+		.iter()
+		.filter(|(room_id, _)| !request.room_subscriptions.contains_key(*room_id))
+		.map(|(room_id, subscribed)| (*subscribed, room_id.clone()))
+		.collect();
+
+	let excess = kept
+		.len()
+		.saturating_add(request.room_subscriptions.len())
+		.saturating_sub(max_subscriptions);
+
+	kept.sort_unstable();
+	for (_, room_id) in kept.into_iter().take(excess) {
+		self.subscribed.remove(&room_id);
+	}
+
+	self.subscriptions
+		.retain(|room_id, _| self.subscribed.contains_key(room_id));
+
 	Self::update_cache_lists(request, self);
 	Self::update_cache_subscriptions(request, self);
 	Self::update_cache_extensions(request, self);
+
+	Ok(before.is_none() || before != self.params())
+}
+
+#[implement(Connection)]
+fn params(&self) -> Option<Vec<u8>> {
+	serialize_to_vec(Cbor((&self.lists, &self.subscriptions, &self.extensions))).ok()
 }
 
 #[implement(Connection)]
@@ -314,6 +700,13 @@ fn update_cache_subscriptions(request: &Request, cached: &mut Self) {
 	cached
 		.subscriptions
 		.extend(request.room_subscriptions.clone());
+
+	cached.subscribed.extend(
+		request
+			.room_subscriptions
+			.keys()
+			.map(|room_id| (room_id.clone(), cached.requests)),
+	);
 }
 
 #[implement(Connection)]
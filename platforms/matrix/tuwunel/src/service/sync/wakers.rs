@@ -0,0 +1,101 @@
+//! Wakers shared by every connection watching a room, so that changes to a
+//! room are watched for once rather than once per connection, and woken
+//! watches are held briefly to coalesce bursts of changes.
+
+use std::{collections::HashMap, future::pending, sync::Mutex, time::Duration};
+
+use futures::{
+	FutureExt,
+	future::{BoxFuture, Shared, select_all},
+};
+use ruma::{OwnedRoomId, RoomId};
+use tokio::time::sleep;
+use tuwunel_core::{implement, matrix::ShortRoomId};
+use tuwunel_database::{Interfix, serialize_key};
+
+/// Completes on the next change to a room's timeline, receipts, typing or
+/// key changes.
+pub(super) type RoomWaker = Shared<BoxFuture<'static, ()>>;
+
+pub(super) type RoomWakers = Mutex<HashMap<OwnedRoomId, RoomWaker>>;
+
+/// The waker for a room, shared with the other connections watching it.
+#[implement(super::Service)]
+pub(super) fn room_waker(&self, room_id: &RoomId, shortroomid: ShortRoomId) -> RoomWaker {
+	let mut wakers = self.room_wakers.lock().expect("locked");
+	if let Some(waker) = wakers
+		.get(room_id)
+		.filter(|waker| waker.peek().is_none())
+	{
+		return waker.clone();
+	}
+
+	// Wakers of rooms which changed since are replaced as they are requested.
+	wakers.retain(|_, waker| waker.peek().is_none());
+
+	let roomid_prefix = serialize_key((room_id, Interfix)).expect("failed to serialize prefix");
+	let watchers = [
+		// Key changes
+		self.db
+			.keychangeid_userid
+			.watch_raw_prefix(&roomid_prefix)
+			.boxed(),
+		// PDUs
+		self.db
+			.pduid_pdu
+			.watch_raw_prefix(&shortroomid.to_be_bytes())
+			.boxed(),
+		// EDUs
+		self.db
+			.readreceiptid_readreceipt
+			.watch_raw_prefix(&roomid_prefix)
+			.boxed(),
+		// Typing
+		self.services
+			.typing
+			.wait_for_update(room_id)
+			.boxed(),
+	];
+
+	let waker = select_all(watchers).map(|_| ()).boxed().shared();
+
+	wakers.insert(room_id.to_owned(), waker.clone());
+	waker
+}
+
+/// Holds a woken watch for `sync_wakeup_coalesce_ms`, so that a burst of
+/// changes is returned in one response rather than one per change, unless
+/// `sync_wakeup_coalesce_count` changes are made first.
+#[implement(super::Service)]
+pub(super) async fn coalesce_wakeup(&self) {
+	let config = &self.services.config;
+	if config.sync_wakeup_coalesce_ms == 0 || !self.services.server.running() {
+		return;
+	}
+
+	let window = sleep(Duration::from_millis(config.sync_wakeup_coalesce_ms));
+	let threshold = config.sync_wakeup_coalesce_count;
+	let depth = async {
+		if threshold == 0 {
+			pending::<()>().await;
+		}
+
+		let count = self
+			.services
+			.globals
+			.current_count()
+			.saturating_add(threshold);
+
+		self.services
+			.globals
+			.wait_count(&count)
+			.await
+			.ok();
+	};
+
+	tokio::select! {
+		() = window => {},
+		() = depth => {},
+		() = self.services.server.until_shutdown() => {},
+	}
+}
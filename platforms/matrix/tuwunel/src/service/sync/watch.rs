@@ -1,8 +1,25 @@
-use futures::{FutureExt, Stream, StreamExt, pin_mut, stream::FuturesUnordered};
+use std::{
+	fmt,
+	pin::Pin,
+	task::{Context, Poll},
+};
+
+use futures::{
+	FutureExt, Stream, StreamExt,
+	future::{BoxFuture, Shared},
+	pin_mut,
+	stream::FuturesUnordered,
+};
 use ruma::{DeviceId, RoomId, UserId};
 use tuwunel_core::{Result, implement, trace};
 use tuwunel_database::{Interfix, Separator, serialize_key};
 
+/// Completes on the first change to the data synced to a user since it was
+/// armed, and remains complete thereafter. Connections keep the watcher armed
+/// for their last response to tell whether anything changed since.
+#[derive(Clone)]
+pub struct Watcher(Shared<BoxFuture<'static, ()>>);
+
 #[implement(super::Service)]
 #[tracing::instrument(skip(self, rooms), level = "debug")]
 pub async fn watch<'a, Rooms>(
@@ -14,8 +31,42 @@ pub async fn watch<'a, Rooms>(
 where
 	Rooms: Stream<Item = &'a RoomId> + Send + 'a,
 {
-	let globaluserdata_prefix = (Separator, user_id, Interfix);
-	let roomuserdataid_prefix = (Option::<&RoomId>::None, user_id, Interfix);
+	let watcher = self.watcher(user_id, device_id, rooms).await;
+
+	self.wait(watcher).await;
+
+	Ok(())
+}
+
+/// Waits for a watcher to complete, then holds briefly to coalesce a burst of
+/// changes.
+#[implement(super::Service)]
+pub async fn wait(&self, watcher: Watcher) {
+	if !self.services.server.running() {
+		return;
+	}
+
+	trace!("watch started");
+	watcher.await;
+	self.coalesce_wakeup().await;
+	trace!("watch finished");
+}
+
+/// Arms a watcher for the changes synced to a user and the given rooms.
+#[implement(super::Service)]
+pub async fn watcher<'a, Rooms>(
+	&self,
+	user_id: &UserId,
+	device_id: Option<&DeviceId>,
+	rooms: Rooms,
+) -> Watcher
+where
+	Rooms: Stream<Item = &'a RoomId> + Send + 'a,
+{
+	let globaluserdata_prefix =
+		serialize_key((Separator, user_id, Interfix)).expect("failed to serialize watch prefix");
+	let roomuserdataid_prefix = serialize_key((Option::<&RoomId>::None, user_id, Interfix))
+		.expect("failed to serialize watch prefix");
 	let userid_prefix =
 		serialize_key((user_id, Interfix)).expect("failed to serialize watch prefix");
 
@@ -46,7 +97,7 @@ pub async fn watch<'a, Rooms>(
 			.boxed(),
 		self.db
 			.roomusertype_roomuserdataid
-			.watch_prefix(&globaluserdata_prefix)
+			.watch_raw_prefix(&globaluserdata_prefix)
 			.boxed(),
 		// More key changes (used when user is not joined to any rooms)
 		self.db
@@ -56,22 +107,23 @@ pub async fn watch<'a, Rooms>(
 		// One time keys
 		self.db
 			.userid_lastonetimekeyupdate
-			.watch_raw_prefix(&user_id)
+			.watch_raw_prefix(user_id.as_bytes())
 			.boxed(),
 		// User account data
 		self.db
 			.roomuserdataid_accountdata
-			.watch_prefix(&roomuserdataid_prefix)
+			.watch_raw_prefix(&roomuserdataid_prefix)
 			.boxed(),
 	];
 
 	let device_watchers = device_id.into_iter().map(|device_id| {
 		// Return when *any* user changed their key
 		// TODO: only send for user they share a room with
-		let userdeviceid_prefix = (user_id, device_id, Interfix);
+		let userdeviceid_prefix = serialize_key((user_id, device_id, Interfix))
+			.expect("failed to serialize watch prefix");
 		self.db
 			.todeviceid_events
-			.watch_prefix(&userdeviceid_prefix)
+			.watch_raw_prefix(&userdeviceid_prefix)
 			.boxed()
 	});
 
@@ -86,59 +138,52 @@ pub async fn watch<'a, Rooms>(
 			continue;
 		};
 
-		let roomid_prefix = (room_id, Interfix);
-		let roomuser_prefix = (room_id, user_id);
-		let typing_room_id = room_id.to_owned();
+		let roomuser_prefix =
+			serialize_key((room_id, user_id)).expect("failed to serialize watch prefix");
 		let watchers = [
 			// Notification clearance
 			self.db
 				.roomuserid_lastnotificationread
-				.watch_prefix(&roomuser_prefix)
-				.boxed(),
-			// Key changes
-			self.db
-				.keychangeid_userid
-				.watch_prefix(&roomid_prefix)
+				.watch_raw_prefix(&roomuser_prefix)
 				.boxed(),
 			// Room account data
 			self.db
 				.roomusertype_roomuserdataid
-				.watch_prefix(&roomuser_prefix)
-				.boxed(),
-			// PDUs
-			self.db
-				.pduid_pdu
-				.watch_prefix(&short_roomid)
-				.boxed(),
-			// EDUs
-			self.db
-				.readreceiptid_readreceipt
-				.watch_prefix(&roomid_prefix)
+				.watch_raw_prefix(&roomuser_prefix)
 				.boxed(),
-			// Typing
-			async move {
-				self.services
-					.typing
-					.wait_for_update(&typing_room_id)
-					.await;
-			}
-			.boxed(),
+			// PDUs, EDUs, typing and key changes
+			self.room_waker(room_id, short_roomid).boxed(),
 		];
 
 		futures.extend(watchers.into_iter());
 	}
 
 	// Server shutdown
-	futures.push(self.services.server.until_shutdown().boxed());
+	let server = self.services.server.clone();
+	futures.push(async move { server.until_shutdown().await }.boxed());
 
-	if !self.services.server.running() {
-		return Ok(());
+	// Wait until one of them finds something
+	let changed = async move {
+		futures.next().await;
+	};
+
+	Watcher(changed.boxed().shared())
+}
+
+impl Watcher {
+	/// Whether anything changed since the watcher was armed.
+	#[must_use]
+	pub fn changed(&self) -> bool { self.0.clone().now_or_never().is_some() }
+}
+
+impl fmt::Debug for Watcher {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Watcher").finish_non_exhaustive()
 	}
+}
 
-	// Wait until one of them finds something
-	trace!(futures = futures.len(), "watch started");
-	futures.next().await;
-	trace!(futures = futures.len(), "watch finished");
+impl Future for Watcher {
+	type Output = ();
 
-	Ok(())
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> { self.0.poll_unpin(cx) }
 }
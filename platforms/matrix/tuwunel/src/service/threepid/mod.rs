@@ -0,0 +1,371 @@
+//! Verification that a user controls an email address, by sending it a link.
+//! Verified addresses are associated with users by the users service.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use http::StatusCode;
+use ruma::{
+	ClientSecret, OwnedUserId, UInt, UserId,
+	api::client::error::{ErrorKind, RetryAfter},
+	thirdparty::Medium,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Mutex, time::sleep};
+use tuwunel_core::{
+	Err, Error, Result, debug, implement,
+	utils::{
+		self,
+		stream::{ReadyExt, TryIgnore},
+		time::now_millis,
+	},
+};
+use tuwunel_database::{Deserialized, Ignore, Interfix, Json, Map};
+use url::Url;
+
+use crate::ratelimit::{Class, Key};
+
+pub struct Service {
+	db: Data,
+	services: Arc<crate::services::OnceServices>,
+
+	/// Held while a session is used up, so that it authenticates only once.
+	claim: Mutex<()>,
+}
+
+struct Data {
+	threepidsessionid_session: Arc<Map>,
+}
+
+/// Verification of an email address requested by a client, keyed by its
+/// client secret and the address.
+#[derive(Deserialize, Serialize)]
+struct Session {
+	sid: String,
+	email: String,
+	token: String,
+	send_attempt: UInt,
+	next_link: Option<String>,
+
+	/// Milliseconds since the epoch when the email was sent.
+	sent_at: u64,
+
+	/// Milliseconds since the epoch when the link in the email was followed.
+	validated_at: Option<u64>,
+}
+
+pub const SESSION_ID_LENGTH: usize = 32;
+
+const TOKEN_LENGTH: usize = 32;
+
+/// Interval between the removals of expired sessions.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(600);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				threepidsessionid_session: args.db["threepidsessionid_session"].clone(),
+			},
+			services: args.services.clone(),
+			claim: Mutex::default(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		loop {
+			tokio::select! {
+				() = sleep(PRUNE_INTERVAL) => {},
+				() = self.services.server.until_shutdown() => return Ok(()),
+			}
+
+			self.remove_expired_sessions().await;
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Sends an email with a link verifying the address, unless one was already
+/// sent to it for the client secret and attempt. Returns the ID of the session
+/// the client then authenticates with. Emails sent to an address are limited
+/// by `rate_limit_email`; a `next_link` outside `next_link_domains` is ignored.
+#[implement(Service)]
+pub async fn request_email_token(
+	&self,
+	client_secret: &ClientSecret,
+	email: &str,
+	send_attempt: UInt,
+	next_link: Option<&str>,
+) -> Result<String> {
+	if !self.services.mailer.enabled() {
+		return Err!(Request(ThreepidDenied("This server does not verify email addresses.")));
+	}
+
+	let email = email.trim().to_lowercase();
+	let key = (client_secret.as_str(), email.as_str());
+	let existing: Option<Session> = self
+		.db
+		.threepidsessionid_session
+		.qry(&key)
+		.await
+		.deserialized()
+		.ok()
+		.filter(|session| !self.is_expired(session));
+
+	let sid = match existing {
+		| Some(session) if session.send_attempt >= send_attempt => return Ok(session.sid),
+		| Some(session) => session.sid,
+		| None => utils::random_string(SESSION_ID_LENGTH),
+	};
+
+	if let Err(retry_after) = self
+		.services
+		.ratelimit
+		.check(Class::Email, &Key::Email(email.clone()))
+		.await
+	{
+		debug!(?retry_after, "Rate limit of emails to {email} exceeded");
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded {
+				retry_after: Some(RetryAfter::Delay(retry_after)),
+			},
+			"Too many emails were sent to this address.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	let config = &self.services.server.config.smtp;
+	let next_link = next_link.and_then(|link| allowed_next_link(link, &config.next_link_domains));
+
+	let token = utils::random_string(TOKEN_LENGTH);
+	let link = self.validation_link(&sid, client_secret, &token)?;
+	let server_name = self.services.globals.server_name();
+	let body = format!(
+		"A request was made to use this email address with your Matrix account on \
+		 {server_name}. To confirm it, follow this link:\n\n{link}\n\nIf you did not make the \
+		 request, you can ignore this email."
+	);
+
+	self.services
+		.mailer
+		.send(&email, &format!("Confirm your email address for {server_name}"), body)
+		.await?;
+
+	let session = Session {
+		sid: sid.clone(),
+		email: email.clone(),
+		token,
+		send_attempt,
+		next_link,
+		sent_at: now_millis(),
+		validated_at: None,
+	};
+
+	self.db
+		.threepidsessionid_session
+		.put(key, Json(session));
+
+	Ok(sid)
+}
+
+/// Marks the address of the session as verified when the token is the one in
+/// the link sent. Returns where to send the user next, if the client asked.
+#[implement(Service)]
+pub async fn validate_email_token(
+	&self,
+	sid: &str,
+	client_secret: &str,
+	token: &str,
+) -> Result<Option<String>> {
+	let mut session = self.session(sid, client_secret).await?;
+	if session.token != token {
+		return Err!(Request(ThreepidAuthFailed("The link is invalid.")));
+	}
+
+	session
+		.validated_at
+		.get_or_insert_with(now_millis);
+	let next_link = session.next_link.clone();
+	self.db
+		.threepidsessionid_session
+		.put((client_secret, session.email.as_str()), Json(session));
+
+	Ok(next_link)
+}
+
+/// The address of the session, once the user followed the link sent to it.
+#[implement(Service)]
+pub async fn validated_email(&self, sid: &str, client_secret: &str) -> Result<String> {
+	let (email, _) = self.validated(sid, client_secret).await?;
+
+	Ok(email)
+}
+
+/// Ends the session once the user followed the link sent to it, so that it
+/// cannot authenticate again. Returns the address.
+#[implement(Service)]
+pub async fn claim_email(&self, sid: &str, client_secret: &str) -> Result<String> {
+	let _claim = self.claim.lock().await;
+	let (email, _) = self.validated(sid, client_secret).await?;
+	self.remove_session(client_secret, &email);
+
+	Ok(email)
+}
+
+/// Associates a verified address with the user, ending the session.
+#[implement(Service)]
+pub async fn bind_email(&self, user_id: &UserId, sid: &str, client_secret: &str) -> Result {
+	let _claim = self.claim.lock().await;
+	let (email, validated_at) = self.validated(sid, client_secret).await?;
+
+	self.services
+		.users
+		.add_threepid(user_id, &Medium::Email, &email, validated_at)
+		.await?;
+
+	self.remove_session(client_secret, &email);
+
+	Ok(())
+}
+
+/// The user an address is associated with.
+#[implement(Service)]
+pub async fn user_by_email(&self, email: &str) -> Result<OwnedUserId> {
+	self.services
+		.users
+		.find_from_threepid(&Medium::Email, email)
+		.await
+}
+
+/// The address of the session and when it was verified.
+#[implement(Service)]
+async fn validated(&self, sid: &str, client_secret: &str) -> Result<(String, u64)> {
+	let session = self.session(sid, client_secret).await?;
+	let Some(validated_at) = session.validated_at else {
+		return Err!(Request(ThreepidAuthFailed("The email address has not been verified.")));
+	};
+
+	Ok((session.email, validated_at))
+}
+
+#[implement(Service)]
+fn remove_session(&self, client_secret: &str, email: &str) {
+	self.db
+		.threepidsessionid_session
+		.del((client_secret, email));
+}
+
+/// The session among those of the client secret, one for each address.
+#[implement(Service)]
+async fn session(&self, sid: &str, client_secret: &str) -> Result<Session> {
+	let session = self
+		.db
+		.threepidsessionid_session
+		.stream_prefix(&(client_secret, Interfix))
+		.ignore_err()
+		.ready_find(|(_, session): &((Ignore, Ignore), Session)| session.sid == sid)
+		.await
+		.map(|(_, session)| session)
+		.filter(|session| !self.is_expired(session));
+
+	let Some(session) = session else {
+		return Err!(Request(ThreepidAuthFailed("Unknown or expired session.")));
+	};
+
+	Ok(session)
+}
+
+/// Removes the sessions which expired, or cannot be read.
+#[implement(Service)]
+async fn remove_expired_sessions(&self) {
+	let map = &self.db.threepidsessionid_session;
+	let expired: Vec<_> = map
+		.raw_stream()
+		.ignore_err()
+		.ready_filter(|(_, val)| {
+			serde_json::from_slice::<Session>(val)
+				.ok()
+				.is_none_or(|session| self.is_expired(&session))
+		})
+		.map(|(key, _)| key.to_vec())
+		.collect()
+		.await;
+
+	debug!(expired = expired.len(), "Removing expired email verification sessions");
+	for key in expired {
+		map.remove(&key);
+	}
+}
+
+/// Sessions expire once the link was sent, or followed, `token_ttl` ago.
+#[implement(Service)]
+fn is_expired(&self, session: &Session) -> bool {
+	let ttl = self
+		.services
+		.server
+		.config
+		.smtp
+		.token_ttl
+		.saturating_mul(1000);
+
+	let since = session.validated_at.unwrap_or(session.sent_at);
+
+	now_millis().saturating_sub(since) >= ttl
+}
+
+/// Link the user follows to verify the address, on the host clients are
+/// delegated to.
+#[implement(Service)]
+fn validation_link(&self, sid: &str, client_secret: &ClientSecret, token: &str) -> Result<Url> {
+	let config = &self.services.server.config;
+	let mut url = match config.well_known.client.clone() {
+		| Some(url) => url,
+		| None => Url::parse(&format!("https://{}", self.services.globals.server_name()))?,
+	};
+
+	url.set_path("/_tuwunel/email/validate");
+	url.query_pairs_mut()
+		.clear()
+		.append_pair("sid", sid)
+		.append_pair("client_secret", client_secret.as_str())
+		.append_pair("token", token);
+
+	Ok(url)
+}
+
+/// The `next_link` of a client if it is on one of the domains allowed.
+fn allowed_next_link(next_link: &str, domains: &[String]) -> Option<String> {
+	Url::parse(next_link)
+		.ok()
+		.filter(|url| matches!(url.scheme(), "http" | "https"))
+		.filter(|url| {
+			url.host_str()
+				.is_some_and(|host| domains.iter().any(|domain| domain == host))
+		})
+		.map(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::allowed_next_link;
+
+	#[test]
+	fn next_link_domains() {
+		let domains = ["app.element.io".to_owned()];
+		let allowed = |link| allowed_next_link(link, &domains);
+
+		assert_eq!(
+			allowed("https://app.element.io/#/register"),
+			Some("https://app.element.io/#/register".to_owned())
+		);
+		assert_eq!(allowed("https://evil.example.org/"), None);
+		assert_eq!(allowed("https://app.element.io.evil.example.org/"), None);
+		assert_eq!(allowed("https://evil.example.org/app.element.io"), None);
+		assert_eq!(allowed("javascript://app.element.io/%0aalert(1)"), None);
+		assert_eq!(allowed("not a url"), None);
+		assert_eq!(allowed_next_link("https://app.element.io/", &[]), None);
+	}
+}
@@ -0,0 +1,97 @@
+//! The `org.matrix.cross_signing_reset` stage (MSC2965), by which users who
+//! sign in through an identity provider, and so have no password to
+//! authenticate with, approve replacing their cross-signing keys at the
+//! provider before retrying the request.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use ruma::{OwnedUserId, UserId};
+use tuwunel_core::{Err, Result, implement, utils};
+
+use super::SESSION_ID_LENGTH;
+
+/// Type of the stage in UIAA flows.
+pub const CROSS_SIGNING_RESET: &str = "org.matrix.cross_signing_reset";
+
+/// How long a user has to approve a reset, and then to retry the request.
+const RESET_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Default)]
+pub(super) struct Resets {
+	/// Users to approve a reset, by the token of their approval URL.
+	pending: HashMap<String, (OwnedUserId, Instant)>,
+
+	/// Users who approved a reset, until when it may be done.
+	approved: HashMap<OwnedUserId, Instant>,
+}
+
+/// Starts a reset of the user's cross-signing keys, to be approved at the
+/// identity provider. Returns the token of its approval URL.
+#[implement(super::Service)]
+#[must_use]
+pub fn request_cross_signing_reset(&self, user_id: &UserId) -> String {
+	let token = utils::random_string(SESSION_ID_LENGTH);
+	let Some(expires) = Instant::now().checked_add(RESET_WINDOW) else {
+		return token;
+	};
+
+	let mut resets = self.cross_signing_resets.lock().expect("locked");
+	resets
+		.pending
+		.retain(|_, (_, expires)| *expires > Instant::now());
+
+	resets
+		.pending
+		.insert(token.clone(), (user_id.to_owned(), expires));
+
+	token
+}
+
+/// The user whose reset awaits approval under the token.
+#[implement(super::Service)]
+#[must_use]
+pub fn cross_signing_reset_user(&self, token: &str) -> Option<OwnedUserId> {
+	self.cross_signing_resets
+		.lock()
+		.expect("locked")
+		.pending
+		.get(token)
+		.filter(|(_, expires)| *expires > Instant::now())
+		.map(|(user_id, _)| user_id.clone())
+}
+
+/// Records that the user signed in at the identity provider to approve the
+/// reset under the token, allowing them to replace their cross-signing keys
+/// once for a while.
+#[implement(super::Service)]
+pub fn approve_cross_signing_reset(&self, token: &str, user_id: &UserId) -> Result {
+	let mut resets = self.cross_signing_resets.lock().expect("locked");
+	match resets.pending.remove(token) {
+		| Some((pending, expires)) if pending == user_id && expires > Instant::now() => {},
+		| _ => return Err!(Request(Forbidden("No cross-signing reset is awaiting approval."))),
+	}
+
+	resets
+		.approved
+		.retain(|_, until| *until > Instant::now());
+
+	if let Some(until) = Instant::now().checked_add(RESET_WINDOW) {
+		resets.approved.insert(user_id.to_owned(), until);
+	}
+
+	Ok(())
+}
+
+/// Consumes the user's approval of a reset. Returns whether there was one.
+#[implement(super::Service)]
+pub fn take_cross_signing_reset(&self, user_id: &UserId) -> bool {
+	self.cross_signing_resets
+		.lock()
+		.expect("locked")
+		.approved
+		.remove(user_id)
+		.is_some_and(|until| until > Instant::now())
+}
@@ -1,13 +1,15 @@
+mod cross_signing_reset;
+
 use std::{
 	collections::BTreeMap,
-	sync::{Arc, RwLock},
+	sync::{Arc, Mutex, RwLock},
 };
 
 use ruma::{
 	CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedUserId, UserId,
 	api::client::{
 		error::{ErrorKind, StandardErrorBody},
-		uiaa::{AuthData, AuthType, Password, UiaaInfo, UserIdentifier},
+		uiaa::{AuthData, AuthType, EmailIdentity, Password, UiaaInfo, UserIdentifier},
 	},
 };
 use tuwunel_core::{
@@ -16,8 +18,11 @@
 };
 use tuwunel_database::{Deserialized, Json, Map};
 
+pub use self::cross_signing_reset::CROSS_SIGNING_RESET;
+
 pub struct Service {
 	userdevicesessionid_uiaarequest: RwLock<RequestMap>,
+	cross_signing_resets: Mutex<cross_signing_reset::Resets>,
 	db: Data,
 	services: Arc<crate::services::OnceServices>,
 }
@@ -35,6 +40,7 @@ impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			userdevicesessionid_uiaarequest: RwLock::new(RequestMap::new()),
+			cross_signing_resets: Mutex::default(),
 			db: Data {
 				userdevicesessionid_uiaainfo: args.db["userdevicesessionid_uiaainfo"].clone(),
 			},
@@ -163,6 +169,43 @@ pub async fn try_auth(
 				return Ok((false, uiaainfo));
 			}
 		},
+		| AuthData::EmailIdentity(EmailIdentity { thirdparty_id_creds: creds, .. }) => {
+			let email = self
+				.services
+				.threepid
+				.validated_email(creds.sid.as_str(), creds.client_secret.as_str())
+				.await;
+
+			// Registration authenticates as the empty localpart; the address is
+			// associated with the user once created, unless it already is with
+			// another.
+			let verified = match email {
+				| Ok(email) if user_id.localpart().is_empty() => self
+					.services
+					.threepid
+					.user_by_email(&email)
+					.await
+					.is_err(),
+				| Ok(email) => self
+					.services
+					.threepid
+					.user_by_email(&email)
+					.await
+					.is_ok_and(|bound| bound == user_id),
+				| Err(_) => false,
+			};
+
+			if !verified {
+				uiaainfo.auth_error = Some(StandardErrorBody {
+					kind: ErrorKind::ThreepidAuthFailed,
+					message: "The email address has not been verified.".to_owned(),
+				});
+
+				return Ok((false, uiaainfo));
+			}
+
+			uiaainfo.completed.push(AuthType::EmailIdentity);
+		},
 		| AuthData::FallbackAcknowledgement(session) => {
 			debug_warn!("FallbackAcknowledgement: {session:?}");
 		},
@@ -18,7 +18,10 @@ pub struct DehydratedDevice {
 	pub device_data: Raw<DehydratedDeviceData>,
 }
 
-/// Creates or recreates the user's dehydrated device.
+/// Creates or recreates the user's dehydrated device. A dehydrated device
+/// replaced is removed with the to-device events queued for it, which only its
+/// keys decrypt and the client fetches beforehand; those of a dehydrated device
+/// claimed on login stay in place for it, under the same device ID.
 #[implement(super::Service)]
 #[tracing::instrument(
 	level = "info",
@@ -86,6 +89,18 @@ pub async fn set_dehydrated_device(&self, user_id: &UserId, request: Request) ->
 	Ok(())
 }
 
+/// Claims the user's dehydrated device for a login with its device_id, turning
+/// it into a regular device taken over by the new session. Its keys and queued
+/// to-device events are kept. Returns false when device_id is not the user's
+/// dehydrated device.
+#[implement(super::Service)]
+#[tracing::instrument(level = "info", skip(self))]
+pub async fn claim_dehydrated_device(&self, user_id: &UserId, device_id: &DeviceId) -> bool {
+	self.remove_dehydrated_device(user_id, Some(device_id))
+		.await
+		.is_ok()
+}
+
 /// Removes a user's dehydrated device.
 ///
 /// Calling this directly will remove the dehydrated data but leak the frontage
@@ -1,23 +1,21 @@
-use std::{
-	sync::Arc,
-	time::{Duration, SystemTime},
-};
+use std::time::{Duration, SystemTime};
 
 use futures::{FutureExt, Stream, StreamExt, future::join};
 use ruma::{
 	DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedUserId, UserId,
 	api::client::device::Device, events::AnyToDeviceEvent, serde::Raw,
 };
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tuwunel_core::{
-	Err, Result, implement,
+	Err, Result, debug_warn, implement, info,
 	utils::{
 		self, ReadyExt,
 		stream::{IterStream, TryIgnore},
-		time::{duration_since_epoch, timepoint_from_epoch, timepoint_from_now},
+		time::{duration_since_epoch, now_secs, timepoint_from_epoch, timepoint_from_now},
 	},
 };
-use tuwunel_database::{Deserialized, Ignore, Interfix, Json, Map};
+use tuwunel_database::{Deserialized, Ignore, Interfix, Json};
 
 /// generated device ID length
 const DEVICE_ID_LENGTH: usize = 10;
@@ -25,7 +23,12 @@
 /// generated user access token length
 pub const TOKEN_LENGTH: usize = 32;
 
-/// Adds a new device to a user.
+/// Adds a new device to a user, or hands an existing device over to a new
+/// login. On takeover the access and refresh tokens of the previous login are
+/// revoked so that it cannot linger as a ghost session, while the device's
+/// keys are kept: the new login is the same device as far as other users are
+/// concerned, and replaces them by uploading its own. The display name is kept
+/// unless a new one is given.
 #[implement(super::Service)]
 #[tracing::instrument(level = "info", skip(self, access_token))]
 pub async fn create_device(
@@ -47,13 +50,34 @@ pub async fn create_device(
 		))));
 	}
 
+	let ip = client_ip
+		.as_deref()
+		.and_then(|client_ip| client_ip.parse().ok());
+
+	let existing = self
+		.get_device_metadata(user_id, &device_id)
+		.await
+		.ok();
+
+	if existing.is_some() {
+		self.remove_tokens(user_id, &device_id).await;
+		info!(%user_id, %device_id, "Device taken over by a new login");
+	}
+
+	// Notifying marks the device list changed, so that other users' clients
+	// refetch the keys of a device which was taken over.
 	let notify = true;
 	self.put_device_metadata(user_id, notify, &Device {
 		device_id: device_id.clone(),
-		display_name: initial_device_display_name.map(Into::into),
+		display_name: initial_device_display_name
+			.map(Into::into)
+			.or_else(|| existing.and_then(|device| device.display_name)),
 		last_seen_ip: client_ip.map(Into::into),
 		last_seen_ts: Some(MilliSecondsSinceUnixEpoch::now()),
-	});
+	})
+	.await;
+
+	self.create_session(user_id, &device_id, ip);
 
 	if let Some(access_token) = access_token {
 		self.set_access_token(user_id, &device_id, access_token, expires_in, refresh_token)
@@ -94,6 +118,12 @@ pub async fn remove_device(&self, user_id: &UserId, device_id: &DeviceId) {
 		})
 		.await;
 
+	// Remove sliding sync connections
+	self.services
+		.sync
+		.clear_connections(Some(user_id), Some(device_id), None)
+		.await;
+
 	// Removes the dehydrated device if the ID matches, otherwise no-op
 	self.remove_dehydrated_device(user_id, Some(device_id))
 		.await
@@ -101,11 +131,16 @@ pub async fn remove_device(&self, user_id: &UserId, device_id: &DeviceId) {
 
 	// TODO: Remove onetimekeys
 
+	self.remove_fallback_keys(user_id, device_id)
+		.await;
+
 	let userdeviceid = (user_id, device_id);
 	self.db.userdeviceid_metadata.del(userdeviceid);
+	self.db.userdeviceid_session.del(userdeviceid);
+	self.forget_session(user_id, device_id);
 
-	self.mark_device_key_update(user_id).await;
-	increment(&self.db.userid_devicelistversion, user_id.as_bytes());
+	self.mark_device_change(user_id, device_id, true)
+		.await;
 }
 
 /// Returns an iterator over all device ids of this user.
@@ -184,6 +219,18 @@ pub async fn set_access_token(
 		.await
 		.ok();
 
+	// A token only authenticates one device; another device holding it would be
+	// left with a token resolving elsewhere, and revoking it later would log
+	// this device out.
+	if let Ok((other_user, other_device, _)) = self.find_from_token(access_token).await
+		&& (other_user != user_id || other_device != device_id)
+	{
+		debug_warn!(%other_user, %other_device, "Access token reassigned to {user_id} {device_id}");
+		self.db
+			.userdeviceid_token
+			.del((&other_user, &other_device));
+	}
+
 	let expires_at = expires_in
 		.map(timepoint_from_now)
 		.transpose()?
@@ -215,7 +262,20 @@ pub async fn remove_access_token(&self, user_id: &UserId, device_id: &DeviceId)
 		.await?;
 
 	self.db.userdeviceid_token.del(userdeviceid);
-	self.db.token_userdeviceid.remove(&access_token);
+
+	// Leave the token to the device it was reassigned to, if any.
+	let owner: Result<(OwnedUserId, OwnedDeviceId, Ignore)> = self
+		.db
+		.token_userdeviceid
+		.get(&access_token)
+		.await
+		.deserialized();
+
+	if owner.is_ok_and(|(owner_user, owner_device, _)| {
+		owner_user == user_id && owner_device == device_id
+	}) {
+		self.db.token_userdeviceid.remove(&access_token);
+	}
 
 	Ok(())
 }
@@ -240,6 +300,31 @@ pub fn generate_access_token(&self, expires: bool) -> (String, Option<Duration>)
 	(access_token, expires_in)
 }
 
+/// How a refresh token presented for exchange relates to its device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RefreshTokenUse {
+	/// The device's current refresh token.
+	Current,
+
+	/// The token last exchanged for the current one, within the grace period.
+	Grace,
+
+	/// The token last exchanged for the current one, after the grace period.
+	Reused,
+
+	/// Not a refresh token of the device.
+	Unknown,
+}
+
+/// A refresh token exchanged for a new one, kept to recognize its reuse.
+#[derive(Debug, Deserialize, Serialize)]
+struct RotatedRefresh {
+	token: String,
+
+	/// Seconds since the epoch at which it was exchanged.
+	rotated_at: u64,
+}
+
 /// Replaces the refresh token of one device.
 #[implement(super::Service)]
 #[tracing::instrument(level = "debug", skip(self))]
@@ -267,10 +352,107 @@ pub async fn set_refresh_token(
 	Ok(())
 }
 
+/// Exchanges the current refresh token of one device for `refresh_token`. The
+/// exchanged token is kept to be recognized by `check_refresh_token`, and
+/// the one exchanged before it is revoked.
+#[implement(super::Service)]
+#[tracing::instrument(level = "debug", skip(self, refresh_token))]
+pub async fn rotate_refresh_token(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	refresh_token: &str,
+) -> Result {
+	debug_assert!(refresh_token.starts_with("refresh_"), "refresh_token missing prefix");
+
+	let userdeviceid = (user_id, device_id);
+	self.remove_rotated_refresh_token(user_id, device_id)
+		.await;
+
+	if let Ok(current) = self.get_refresh_token(user_id, device_id).await {
+		let rotated = RotatedRefresh { token: current, rotated_at: now_secs() };
+		self.db
+			.userdeviceid_rotatedrefresh
+			.put(userdeviceid, Json(&rotated));
+	}
+
+	self.db
+		.token_userdeviceid
+		.raw_put(refresh_token, userdeviceid);
+	self.db
+		.userdeviceid_refresh
+		.put_raw(userdeviceid, refresh_token);
+
+	Ok(())
+}
+
+/// Determines whether `refresh_token` may be exchanged for the device it was
+/// found for.
+#[implement(super::Service)]
+pub async fn check_refresh_token(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	refresh_token: &str,
+) -> RefreshTokenUse {
+	if self
+		.get_refresh_token(user_id, device_id)
+		.await
+		.is_ok_and(|current| current == refresh_token)
+	{
+		return RefreshTokenUse::Current;
+	}
+
+	let key = (user_id, device_id);
+	let Ok(rotated) = self
+		.db
+		.userdeviceid_rotatedrefresh
+		.qry(&key)
+		.await
+		.deserialized::<RotatedRefresh>()
+	else {
+		return RefreshTokenUse::Unknown;
+	};
+
+	if rotated.token != refresh_token {
+		return RefreshTokenUse::Unknown;
+	}
+
+	let grace_period = self.services.config.refresh_token_grace_period;
+	if now_secs().saturating_sub(rotated.rotated_at) <= grace_period {
+		RefreshTokenUse::Grace
+	} else {
+		RefreshTokenUse::Reused
+	}
+}
+
+/// Soft-logout one device: its refresh tokens are revoked and its access
+/// token expired, so that its client is told to log in again to keep using
+/// the device.
+#[implement(super::Service)]
+#[tracing::instrument(level = "info", skip(self))]
+pub async fn soft_logout(&self, user_id: &UserId, device_id: &DeviceId) -> Result {
+	self.remove_refresh_token(user_id, device_id)
+		.await
+		.ok();
+
+	let access_token = self.get_access_token(user_id, device_id).await?;
+
+	let value = (user_id, device_id, Some(now_secs()));
+	self.db
+		.token_userdeviceid
+		.raw_put(access_token, value);
+
+	Ok(())
+}
+
 /// Revoke the refresh token without deleting the device. Take care to not leave
 /// dangling devices if using this method.
 #[implement(super::Service)]
 pub async fn remove_refresh_token(&self, user_id: &UserId, device_id: &DeviceId) -> Result {
+	self.remove_rotated_refresh_token(user_id, device_id)
+		.await;
+
 	let userdeviceid = (user_id, device_id);
 	let refresh_token = self
 		.db
@@ -284,6 +466,23 @@ pub async fn remove_refresh_token(&self, user_id: &UserId, device_id: &DeviceId)
 	Ok(())
 }
 
+#[implement(super::Service)]
+async fn remove_rotated_refresh_token(&self, user_id: &UserId, device_id: &DeviceId) {
+	let userdeviceid = (user_id, device_id);
+	if let Ok(rotated) = self
+		.db
+		.userdeviceid_rotatedrefresh
+		.qry(&userdeviceid)
+		.await
+		.deserialized::<RotatedRefresh>()
+	{
+		self.db.token_userdeviceid.remove(&rotated.token);
+		self.db
+			.userdeviceid_rotatedrefresh
+			.del(userdeviceid);
+	}
+}
+
 #[implement(super::Service)]
 pub async fn get_refresh_token(&self, user_id: &UserId, device_id: &DeviceId) -> Result<String> {
 	let key = (user_id, device_id);
@@ -299,8 +498,9 @@ pub fn generate_refresh_token() -> String {
 	format!("refresh_{}", utils::random_string(TOKEN_LENGTH))
 }
 
+/// Queues a to-device event for a device, unless its user ignores the sender.
 #[implement(super::Service)]
-pub fn add_to_device_event(
+pub async fn add_to_device_event(
 	&self,
 	sender: &UserId,
 	target_user_id: &UserId,
@@ -308,6 +508,10 @@ pub fn add_to_device_event(
 	event_type: &str,
 	content: &serde_json::Value,
 ) {
+	if self.user_is_ignored(sender, target_user_id).await {
+		return;
+	}
+
 	let count = self.services.globals.next_count();
 
 	let key = (target_user_id, target_device_id, *count);
@@ -319,6 +523,32 @@ pub fn add_to_device_event(
 			"content": content,
 		})),
 	);
+
+	self.services
+		.appservice
+		.mark_to_device_event(target_user_id, target_device_id, *count)
+		.await;
+}
+
+#[implement(super::Service)]
+pub async fn get_to_device_event(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	count: u64,
+) -> Result<Raw<AnyToDeviceEvent>> {
+	let key = (user_id, device_id, count);
+	self.db
+		.todeviceid_events
+		.qry(&key)
+		.await
+		.deserialized()
+}
+
+#[implement(super::Service)]
+pub fn remove_to_device_event(&self, user_id: &UserId, device_id: &DeviceId, count: u64) {
+	let key = (user_id, device_id, count);
+	self.db.todeviceid_events.del(key);
 }
 
 #[implement(super::Service)]
@@ -369,6 +599,65 @@ pub async fn remove_to_device_events<Until>(
 		.await;
 }
 
+/// To-device event types which are never discarded by
+/// `prune_to_device_events`. Without the withheld notice a client waits
+/// indefinitely for room keys which will never arrive.
+pub const RETAINED_TO_DEVICE_TYPES: &[&str] = &["m.room_key.withheld"];
+
+/// Discard to-device events queued for devices not seen within `max_age`,
+/// except those of [`RETAINED_TO_DEVICE_TYPES`]. Devices which never reported
+/// being seen are left alone. Returns the number of events discarded.
+#[implement(super::Service)]
+pub async fn prune_to_device_events(&self, max_age: Duration) -> usize {
+	type KeyVal<'a> = ((&'a UserId, &'a DeviceId), Device);
+
+	let Some(cutoff) = SystemTime::now().checked_sub(max_age) else {
+		return 0;
+	};
+
+	let stale: Vec<(OwnedUserId, OwnedDeviceId)> = self
+		.db
+		.userdeviceid_metadata
+		.stream()
+		.ignore_err()
+		.ready_filter_map(|((user_id, _), device): KeyVal<'_>| {
+			device
+				.last_seen_ts
+				.and_then(MilliSecondsSinceUnixEpoch::to_system_time)
+				.is_some_and(|last_seen| last_seen < cutoff)
+				.then(|| (user_id.to_owned(), device.device_id))
+		})
+		.collect()
+		.await;
+
+	let mut pruned: usize = 0;
+	for (user_id, device_id) in &stale {
+		pruned = self
+			.get_to_device_events(user_id, device_id, None, None)
+			.ready_filter(|(_, event)| !is_retained_to_device_event(event))
+			.ready_fold(pruned, |pruned, (count, _)| {
+				self.db
+					.todeviceid_events
+					.del((user_id, device_id, count));
+
+				pruned.saturating_add(1)
+			})
+			.await;
+	}
+
+	pruned
+}
+
+/// Whether the to-device event is of one of the [`RETAINED_TO_DEVICE_TYPES`].
+#[must_use]
+pub fn is_retained_to_device_event(event: &Raw<AnyToDeviceEvent>) -> bool {
+	event
+		.get_field::<String>("type")
+		.ok()
+		.flatten()
+		.is_some_and(|kind| RETAINED_TO_DEVICE_TYPES.contains(&kind.as_str()))
+}
+
 #[implement(super::Service)]
 pub async fn update_device_last_seen(
 	&self,
@@ -384,20 +673,24 @@ pub async fn update_device_last_seen(
 		.last_seen_ts
 		.replace(last_seen.unwrap_or_else(MilliSecondsSinceUnixEpoch::now));
 
-	self.put_device_metadata(user_id, false, &device);
+	self.put_device_metadata(user_id, false, &device)
+		.await;
 
 	Ok(())
 }
 
+/// Stores the metadata of a device. With `notify`, the change is recorded as a
+/// change to the device list of the user.
 #[implement(super::Service)]
-pub fn put_device_metadata(&self, user_id: &UserId, notify: bool, device: &Device) {
+pub async fn put_device_metadata(&self, user_id: &UserId, notify: bool, device: &Device) {
 	let key = (user_id, &device.device_id);
 	self.db
 		.userdeviceid_metadata
 		.put(key, Json(device));
 
 	if notify {
-		increment(&self.db.userid_devicelistversion, user_id.as_bytes());
+		self.mark_device_change(user_id, &device.device_id, false)
+			.await;
 	}
 }
 
@@ -447,10 +740,3 @@ pub fn all_devices_metadata<'a>(
 		.ignore_err()
 		.map(|(_, val): (Ignore, Device)| val)
 }
-
-//TODO: this is an ABA
-fn increment(db: &Arc<Map>, key: &[u8]) {
-	let old = db.get_blocking(key);
-	let new = utils::increment(old.ok().as_deref());
-	db.insert(key, new);
-}
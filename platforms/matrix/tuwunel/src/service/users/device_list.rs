@@ -0,0 +1,172 @@
+//! Incremental updates of the device lists of local users sent over
+//! federation. Each change to a device bumps the device list version of its
+//! user, which is the `stream_id` of the update, and is recorded under the
+//! count of the key change so that the sender finds it. Each update is chained
+//! by `prev_id` to the previous change to the devices of its user, so that a
+//! destination which missed one resyncs the device list.
+
+use futures::StreamExt;
+use ruma::{
+	DeviceId, OwnedDeviceId, UInt, UserId,
+	api::federation::transactions::edu::DeviceListUpdateContent,
+};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{Err, Result, implement, utils::stream::TryIgnore};
+use tuwunel_database::{Deserialized, Interfix};
+
+/// A change to a device of a local user.
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct DeviceListChange {
+	device_id: OwnedDeviceId,
+
+	/// Device list version of the user after the change.
+	stream_id: u64,
+
+	deleted: bool,
+}
+
+/// Most changes kept per user. Destinations which have not been sent the older
+/// ones by then are made to resync the device list instead.
+const MAX_DEVICE_LIST_CHANGES: usize = 256;
+
+/// Records a change to the keys or the metadata of a device of a local user,
+/// or its removal. Changes of a user are serialized so that each has its own
+/// version.
+#[implement(super::Service)]
+pub async fn mark_device_change(&self, user_id: &UserId, device_id: &DeviceId, deleted: bool) {
+	let _lock = self.device_list_mutex.lock(user_id).await;
+
+	let stream_id = self
+		.get_devicelist_version(user_id)
+		.await
+		.unwrap_or(0)
+		.saturating_add(1);
+
+	self.db
+		.userid_devicelistversion
+		.insert(user_id.as_bytes(), stream_id.to_be_bytes());
+
+	let change = DeviceListChange {
+		device_id: device_id.to_owned(),
+		stream_id,
+		deleted,
+	};
+
+	self.mark_key_change(user_id, Some(&change)).await;
+	self.prune_device_changes(user_id).await;
+}
+
+/// The update of a device of a local user recorded under the `count` of a key
+/// change, to send over federation. None when the change concerned no device.
+/// Fails when the change is older than those kept, so that it is unknown
+/// whether it concerned a device.
+#[implement(super::Service)]
+pub async fn device_list_update(
+	&self,
+	user_id: &UserId,
+	count: u64,
+) -> Result<Option<DeviceListUpdateContent>> {
+	let change: Option<DeviceListChange> = self
+		.db
+		.useridcount_devicelistchange
+		.qry(&(user_id, count))
+		.await
+		.deserialized()
+		.ok();
+
+	let Some(DeviceListChange { device_id, stream_id, deleted }) = change else {
+		let oldest = self
+			.db
+			.useridcount_devicelistchange
+			.keys_prefix(&(user_id, Interfix))
+			.ignore_err()
+			.map(|(_, count): (&UserId, u64)| count)
+			.boxed()
+			.next()
+			.await;
+
+		if oldest.is_some_and(|oldest| count < oldest) {
+			return Err!("Change {count} to the device list of {user_id} was pruned.");
+		}
+
+		return Ok(None);
+	};
+
+	let (keys, display_name) = if deleted {
+		(None, None)
+	} else {
+		let keys = self
+			.get_device_keys(user_id, &device_id)
+			.await
+			.ok();
+		let display_name = self
+			.get_device_metadata(user_id, &device_id)
+			.await
+			.ok()
+			.and_then(|device| device.display_name);
+
+		(keys, display_name)
+	};
+
+	Ok(Some(DeviceListUpdateContent {
+		user_id: user_id.to_owned(),
+		device_id,
+		device_display_name: self
+			.services
+			.config
+			.allow_device_name_federation
+			.then_some(display_name)
+			.flatten(),
+		stream_id: UInt::new_saturating(stream_id),
+		prev_id: prev_id(stream_id).into_iter().collect(),
+		deleted: deleted.then_some(true),
+		keys,
+	}))
+}
+
+/// Removes the changes of a user beyond the `MAX_DEVICE_LIST_CHANGES` most
+/// recent.
+#[implement(super::Service)]
+async fn prune_device_changes(&self, user_id: &UserId) {
+	let counts: Vec<u64> = self
+		.db
+		.useridcount_devicelistchange
+		.keys_prefix(&(user_id, Interfix))
+		.ignore_err()
+		.map(|(_, count): (&UserId, u64)| count)
+		.collect()
+		.await;
+
+	let excess = counts
+		.len()
+		.saturating_sub(MAX_DEVICE_LIST_CHANGES);
+
+	for count in counts.into_iter().take(excess) {
+		self.db
+			.useridcount_devicelistchange
+			.del((user_id, count));
+	}
+}
+
+/// The version of the device list preceding `stream_id`; none for the first.
+fn prev_id(stream_id: u64) -> Option<UInt> {
+	stream_id
+		.checked_sub(1)
+		.filter(|&prev_id| prev_id > 0)
+		.map(UInt::new_saturating)
+}
+
+#[cfg(test)]
+mod tests {
+	use ruma::uint;
+
+	use super::prev_id;
+
+	#[test]
+	fn chained_to_previous_version() {
+		assert_eq!(prev_id(0), None);
+		assert_eq!(prev_id(1), None);
+		assert_eq!(prev_id(2), Some(uint!(1)));
+		assert_eq!(prev_id(43), Some(uint!(42)));
+	}
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use futures::{Stream, StreamExt, future::join3};
+use ruma::{
+	OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomId, UserId,
+	api::client::{device::Device, filter::FilterDefinition},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use tuwunel_core::{
+	Err, Result, implement,
+	utils::{future::TryExtExt, stream::IterStream},
+};
+
+use crate::account_data;
+
+/// Version of the export format written by `export_account` and accepted by
+/// `import_account`.
+pub const EXPORT_VERSION: u64 = 1;
+
+/// Portable representation of a local account. Secrets such as the password
+/// hash, access tokens and encryption keys are not included.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Export {
+	pub version: u64,
+	pub user_id: OwnedUserId,
+
+	#[serde(default)]
+	pub profile: Profile,
+
+	#[serde(default)]
+	pub account_data: account_data::Export,
+
+	#[serde(default)]
+	pub devices: Vec<Device>,
+
+	#[serde(default)]
+	pub rooms: Rooms,
+
+	#[serde(default)]
+	pub filters: BTreeMap<String, FilterDefinition>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Profile {
+	pub displayname: Option<String>,
+	pub avatar_url: Option<OwnedMxcUri>,
+	pub blurhash: Option<String>,
+
+	/// Remaining profile fields such as the timezone.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub fields: BTreeMap<String, JsonValue>,
+}
+
+/// Rooms of the account by membership.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Rooms {
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub join: Vec<OwnedRoomId>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub invite: Vec<OwnedRoomId>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub knock: Vec<OwnedRoomId>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub leave: Vec<OwnedRoomId>,
+}
+
+/// What `import_account` recreated.
+#[derive(Debug, Default)]
+pub struct Imported {
+	pub account_data: usize,
+	pub devices: usize,
+	pub filters: usize,
+}
+
+/// Collects the profile, account data, devices, room memberships and filters
+/// of a local user.
+#[implement(super::Service)]
+pub async fn export_account(&self, user_id: &UserId) -> Result<Export> {
+	if !self.services.globals.user_is_local(user_id) {
+		return Err!(Request(InvalidParam("Only local users can be exported.")));
+	}
+
+	if !self.exists(user_id).await {
+		return Err!(Request(NotFound("User {user_id} does not exist.")));
+	}
+
+	let (displayname, avatar_url, blurhash) = join3(
+		self.displayname(user_id).ok(),
+		self.avatar_url(user_id).ok(),
+		self.blurhash(user_id).ok(),
+	)
+	.await;
+
+	let fields = self.all_profile_keys(user_id).collect().await;
+
+	let state_cache = &self.services.state_cache;
+	let rooms = Rooms {
+		join: owned(state_cache.rooms_joined(user_id)).await,
+		invite: owned(state_cache.rooms_invited(user_id)).await,
+		knock: owned(state_cache.rooms_knocked(user_id)).await,
+		leave: owned(state_cache.rooms_left(user_id)).await,
+	};
+
+	let all_rooms = rooms
+		.join
+		.iter()
+		.chain(&rooms.invite)
+		.chain(&rooms.knock)
+		.chain(&rooms.leave)
+		.map(AsRef::as_ref)
+		.stream();
+
+	let account_data = self
+		.services
+		.account_data
+		.export(user_id, all_rooms)
+		.await;
+
+	Ok(Export {
+		version: EXPORT_VERSION,
+		user_id: user_id.to_owned(),
+		profile: Profile {
+			displayname,
+			avatar_url,
+			blurhash,
+			fields,
+		},
+		account_data,
+		devices: self.all_devices_metadata(user_id).collect().await,
+		rooms,
+		filters: self.all_filters(user_id).collect().await,
+	})
+}
+
+/// Recreates an export in the account of an existing local user. Devices are
+/// restored without access tokens so their sessions must sign in again;
+/// existing devices are left as they are. Room memberships are not acted
+/// upon; joining the rooms again is left to the caller.
+#[implement(super::Service)]
+pub async fn import_account(&self, user_id: &UserId, export: &Export) -> Result<Imported> {
+	if export.version != EXPORT_VERSION {
+		return Err!(Request(InvalidParam(
+			"Unsupported export version {}; expected {EXPORT_VERSION}.",
+			export.version
+		)));
+	}
+
+	if !self.services.globals.user_is_local(user_id) {
+		return Err!(Request(InvalidParam("Only local users can be imported.")));
+	}
+
+	if !self.exists(user_id).await {
+		return Err!(Request(NotFound("User {user_id} does not exist.")));
+	}
+
+	let profile = &export.profile;
+	self.set_displayname(user_id, profile.displayname.as_deref());
+	self.set_avatar_url(user_id, profile.avatar_url.as_deref());
+	self.set_blurhash(user_id, profile.blurhash.as_deref());
+	for (key, value) in &profile.fields {
+		self.set_profile_key(user_id, key, Some(value));
+	}
+
+	let account_data = self
+		.services
+		.account_data
+		.import(user_id, &export.account_data)
+		.await?;
+
+	let mut devices = 0_usize;
+	for device in &export.devices {
+		if !self
+			.device_exists(user_id, &device.device_id)
+			.await
+		{
+			self.put_device_metadata(user_id, true, device)
+				.await;
+			devices = devices.saturating_add(1);
+		}
+	}
+
+	for (filter_id, filter) in &export.filters {
+		self.set_filter(user_id, filter_id, filter);
+	}
+
+	Ok(Imported {
+		account_data,
+		devices,
+		filters: export.filters.len(),
+	})
+}
+
+async fn owned<'a, S>(rooms: S) -> Vec<OwnedRoomId>
+where
+	S: Stream<Item = &'a RoomId>,
+{
+	rooms.map(ToOwned::to_owned).collect().await
+}
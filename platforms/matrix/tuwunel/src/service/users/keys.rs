@@ -2,17 +2,31 @@
 
 use futures::{Stream, StreamExt, TryFutureExt, pin_mut};
 use ruma::{
-	DeviceId, KeyId, OneTimeKeyAlgorithm, OneTimeKeyId, OneTimeKeyName, OwnedKeyId, RoomId, UInt,
-	UserId,
+	DeviceId, KeyId, OneTimeKeyAlgorithm, OneTimeKeyId, OneTimeKeyName, OwnedKeyId,
+	OwnedOneTimeKeyId, RoomId, UInt, UserId,
 	api::client::error::ErrorKind,
 	encryption::{CrossSigningKey, DeviceKeys, OneTimeKey},
 	serde::Raw,
 };
+use serde::{Deserialize, Serialize};
 use tuwunel_core::{
 	Err, Error, Result, debug_error, err, implement,
 	utils::{ReadyExt, stream::TryIgnore, string::Unquoted},
 };
-use tuwunel_database::{Deserialized, Ignore, Json};
+use tuwunel_database::{Deserialized, Ignore, Interfix, Json};
+
+use super::device_list::DeviceListChange;
+
+/// The fallback key of a device for an algorithm, claimed when its one-time
+/// keys of the algorithm run out.
+#[derive(Debug, Deserialize, Serialize)]
+struct FallbackKey {
+	key_id: OwnedOneTimeKeyId,
+	key: Raw<OneTimeKey>,
+
+	/// Whether the key was claimed since it was uploaded.
+	used: bool,
+}
 
 #[implement(super::Service)]
 pub async fn add_one_time_keys<'a, Keys>(
@@ -145,6 +159,118 @@ pub async fn take_one_time_key(
 		.ok_or_else(|| err!(Request(NotFound("No one-time-key found"))))
 }
 
+/// Stores the fallback keys of a device, replacing those of the same
+/// algorithms. A key uploaded again stays marked as used if it was.
+#[implement(super::Service)]
+pub async fn add_fallback_keys<'a, Keys>(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	keys: Keys,
+) -> Result
+where
+	Keys: Iterator<Item = (&'a OneTimeKeyId, &'a Raw<OneTimeKey>)> + Send + 'a,
+{
+	for (key_id, key) in keys {
+		if let Err(e) = key.deserialize() {
+			debug_error!(?key_id, ?key, "Invalid fallback key JSON submitted by client: {e}");
+			continue;
+		}
+
+		let algorithm = key_id.algorithm();
+		let dbkey = (user_id, device_id, algorithm.as_str());
+		let used = self
+			.db
+			.userdevicealgorithm_fallbackkey
+			.qry(&dbkey)
+			.await
+			.deserialized::<FallbackKey>()
+			.is_ok_and(|existing| existing.used && existing.key_id == *key_id);
+
+		let fallback_key = FallbackKey {
+			key_id: key_id.to_owned(),
+			key: key.clone(),
+			used,
+		};
+
+		self.db
+			.userdevicealgorithm_fallbackkey
+			.put(dbkey, Json(&fallback_key));
+	}
+
+	Ok(())
+}
+
+/// Claims the fallback key of a device for an algorithm, marking it used. The
+/// key is kept to be claimed again until the device replaces it.
+#[implement(super::Service)]
+pub async fn take_fallback_key(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	key_algorithm: &OneTimeKeyAlgorithm,
+) -> Result<(OwnedOneTimeKeyId, Raw<OneTimeKey>)> {
+	let dbkey = (user_id, device_id, key_algorithm.as_str());
+	let mut fallback_key: FallbackKey = self
+		.db
+		.userdevicealgorithm_fallbackkey
+		.qry(&dbkey)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(NotFound("No fallback key found"))))?;
+
+	if !fallback_key.used {
+		fallback_key.used = true;
+		self.db
+			.userdevicealgorithm_fallbackkey
+			.put(dbkey, Json(&fallback_key));
+
+		// Tells the device through sync that its fallback key was used.
+		let count = self.services.globals.next_count();
+		self.db
+			.userid_lastonetimekeyupdate
+			.raw_put(user_id, *count);
+	}
+
+	Ok((fallback_key.key_id, fallback_key.key))
+}
+
+/// The algorithms of the device's fallback keys which were not claimed yet.
+#[implement(super::Service)]
+pub async fn unused_fallback_key_types(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+) -> Vec<OneTimeKeyAlgorithm> {
+	type KeyVal<'a> = ((Ignore, Ignore, &'a str), FallbackKey);
+
+	let query = (user_id, device_id, Interfix);
+	self.db
+		.userdevicealgorithm_fallbackkey
+		.stream_prefix(&query)
+		.ignore_err()
+		.ready_filter_map(|((Ignore, Ignore, algorithm), key): KeyVal<'_>| {
+			(!key.used).then(|| algorithm.into())
+		})
+		.collect()
+		.await
+}
+
+#[implement(super::Service)]
+pub(super) async fn remove_fallback_keys(&self, user_id: &UserId, device_id: &DeviceId) {
+	let query = (user_id, device_id, Interfix);
+	self.db
+		.userdevicealgorithm_fallbackkey
+		.keys_prefix_raw(&query)
+		.ignore_err()
+		.ready_for_each(|key| {
+			self.db
+				.userdevicealgorithm_fallbackkey
+				.remove(key);
+		})
+		.await;
+}
+
 #[implement(super::Service)]
 pub async fn count_one_time_keys(
 	&self,
@@ -213,7 +339,8 @@ pub async fn add_device_keys(
 	let key = (user_id, device_id);
 
 	self.db.keyid_key.put(key, Json(device_keys));
-	self.mark_device_key_update(user_id).await;
+	self.mark_device_change(user_id, device_id, false)
+		.await;
 }
 
 #[implement(super::Service)]
@@ -337,7 +464,15 @@ pub async fn sign_key(
 		.keyid_key
 		.put(key, Json(cross_signing_key));
 
-	self.mark_device_key_update(target_id).await;
+	// Signatures of a device change its keys; those of cross-signing keys are
+	// sent as a signing key update.
+	let device_id: &DeviceId = key_id.into();
+	if self.device_exists(target_id, device_id).await {
+		self.mark_device_change(target_id, device_id, false)
+			.await;
+	} else {
+		self.mark_device_key_update(target_id).await;
+	}
 
 	Ok(())
 }
@@ -388,21 +523,46 @@ fn keys_changed_user_or_room<'a>(
 
 #[implement(super::Service)]
 pub async fn mark_device_key_update(&self, user_id: &UserId) {
+	self.mark_key_change(user_id, None).await;
+}
+
+/// Records a change to the keys of a user, with the change to one of their
+/// devices if it concerns one; both are written under the same count so that
+/// no reader sees one without the other.
+#[implement(super::Service)]
+pub(super) async fn mark_key_change(
+	&self,
+	user_id: &UserId,
+	device_change: Option<&DeviceListChange>,
+) {
 	let count = self.services.globals.next_count();
+	if let Some(change) = device_change {
+		self.db
+			.useridcount_devicelistchange
+			.put((user_id, *count), Json(change));
+	}
 
-	self.services
+	let rooms: Vec<_> = self
+		.services
 		.state_cache
 		.rooms_joined(user_id)
 		// Don't send key updates to unencrypted rooms
 		.filter(|room_id| self.services.state_accessor.is_encrypted_room(room_id))
-		.ready_for_each(|room_id| {
-			let key = (room_id, *count);
-			self.db.keychangeid_userid.put_raw(key, user_id);
-		})
+		.collect()
 		.await;
 
+	for room_id in &rooms {
+		let key = (*room_id, *count);
+		self.db.keychangeid_userid.put_raw(key, user_id);
+	}
+
 	let key = (user_id, *count);
 	self.db.keychangeid_userid.put_raw(key, user_id);
+
+	self.services
+		.appservice
+		.mark_device_list_update(user_id, &rooms, *count)
+		.await;
 }
 
 #[implement(super::Service)]
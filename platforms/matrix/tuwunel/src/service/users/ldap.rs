@@ -1,10 +1,21 @@
 #![cfg(feature = "ldap")]
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use ldap3::{LdapConnAsync, Scope, SearchEntry};
-use ruma::UserId;
-use tuwunel_core::{Result, debug, err, error, implement, result::LogErr, trace};
+use ldap3::{
+	Ldap, LdapConnAsync, Scope, SearchEntry,
+	adapters::{Adapter, EntriesOnly, PagedResults},
+};
+use ruma::{Int, RoomId, UserId, events::room::power_levels::RoomPowerLevelsEventContent};
+use tokio::task::JoinHandle;
+use tuwunel_core::{
+	Err, Result, debug, debug_info, err, error, implement, matrix::pdu::PduBuilder,
+	result::LogErr, trace, warn,
+};
+use tuwunel_database::Deserialized;
+
+/// Number of entries asked of the server at once when listing all users.
+const PAGE_SIZE: i32 = 500;
 
 /// Performs a LDAP search for the given user.
 ///
@@ -16,37 +27,8 @@ pub async fn search_ldap(&self, user_id: &UserId) -> Result<Vec<(String, bool)>>
 	let lowercased_localpart = localpart.to_lowercase();
 
 	let config = &self.services.server.config.ldap;
-	let uri = config
-		.uri
-		.as_ref()
-		.ok_or_else(|| err!(Ldap(error!("LDAP URI is not configured."))))?;
-
-	if uri.scheme().starts_with("ldaps") {
-		self.services.globals.init_rustls_provider()?;
-	}
-
-	debug!(?uri, "LDAP creating connection...");
-	let (conn, mut ldap) = LdapConnAsync::new(uri.as_str())
-		.await
-		.map_err(|e| err!(Ldap(error!(?user_id, "LDAP connection setup error: {e}"))))?;
-
-	let driver = self.services.server.runtime().spawn(async move {
-		match conn.drive().await {
-			| Err(e) => error!("LDAP connection error: {e}"),
-			| Ok(()) => debug!("LDAP connection completed."),
-		}
-	});
-
-	match (&config.bind_dn, &config.bind_password_file) {
-		| (Some(bind_dn), Some(bind_password_file)) => {
-			let bind_pw = String::from_utf8(std::fs::read(bind_password_file)?)?;
-			ldap.simple_bind(bind_dn, bind_pw.trim())
-				.await
-				.and_then(ldap3::LdapResult::success)
-				.map_err(|e| err!(Ldap(error!("LDAP bind error: {e}"))))?;
-		},
-		| (..) => {},
-	}
+	let (mut ldap, driver) = self.ldap_connect().await?;
+	self.ldap_bind(&mut ldap).await?;
 
 	let attr = [&config.uid_attribute, &config.name_attribute];
 
@@ -118,8 +100,217 @@ pub async fn search_ldap(&self, user_id: &UserId) -> Result<Vec<(String, bool)>>
 	Ok(dns.drain().collect())
 }
 
+/// Authenticates the user by binding as `user_dn`.
+///
+/// Returns the groups the user is a member of, read from the user's own entry
+/// as the values of `group_attribute`.
+#[implement(super::Service)]
+pub async fn auth_ldap(&self, user_dn: &str, password: &str) -> Result<Vec<String>> {
+	let config = &self.services.server.config.ldap;
+	let (mut ldap, driver) = self.ldap_connect().await?;
+
+	ldap.simple_bind(user_dn, password)
+		.await
+		.and_then(ldap3::LdapResult::success)
+		.map_err(|e| err!(Request(Forbidden(debug_error!("LDAP authentication error: {e}")))))?;
+
+	let groups = if config.group_attribute.is_empty() {
+		Vec::new()
+	} else {
+		let attr = [&config.group_attribute];
+		let (entries, _result) = ldap
+			.search(user_dn, Scope::Base, "(objectClass=*)", &attr)
+			.await
+			.and_then(ldap3::SearchResult::success)
+			.inspect(|(entries, result)| trace!(?entries, ?result, "LDAP Group Search"))
+			.map_err(|e| err!(Ldap(error!(?attr, ?user_dn, "LDAP group search error: {e}"))))?;
+
+		entries
+			.into_iter()
+			.map(SearchEntry::construct)
+			.filter_map(|mut entry| entry.attrs.remove(&config.group_attribute))
+			.flatten()
+			.collect()
+	};
+
+	ldap.unbind()
+		.await
+		.map_err(|e| err!(Ldap(error!("LDAP unbind error: {e}"))))?;
+
+	driver.await.log_err().ok();
+
+	Ok(groups)
+}
+
+/// Returns the lowercased localparts of every user found under `base_dn` with
+/// the search `filter`, for any username. The search is paged, and fails
+/// unless the server returned all the entries, as when it hit a size limit.
 #[implement(super::Service)]
-pub async fn auth_ldap(&self, user_dn: &str, password: &str) -> Result {
+pub async fn ldap_localparts(&self) -> Result<HashSet<String>> {
+	let config = &self.services.server.config.ldap;
+	let (mut ldap, driver) = self.ldap_connect().await?;
+	self.ldap_bind(&mut ldap).await?;
+
+	let attr = [&config.uid_attribute];
+	let user_filter = &config.filter.replace("{username}", "*");
+	let search_err =
+		|e: ldap3::LdapError| err!(Ldap(error!(?attr, ?user_filter, "LDAP search error: {e}")));
+
+	let adapters: Vec<Box<dyn Adapter<_, _>>> =
+		vec![Box::new(EntriesOnly::new()), Box::new(PagedResults::new(PAGE_SIZE))];
+
+	let mut search = ldap
+		.streaming_search_with(adapters, &config.base_dn, Scope::Subtree, user_filter, attr)
+		.await
+		.map_err(search_err)?;
+
+	let mut localparts = HashSet::new();
+	while let Some(entry) = search.next().await.map_err(search_err)? {
+		let mut entry = SearchEntry::construct(entry);
+		localparts.extend(
+			entry
+				.attrs
+				.remove(&config.uid_attribute)
+				.into_iter()
+				.flatten()
+				.map(|localpart| localpart.to_lowercase()),
+		);
+	}
+
+	search
+		.finish()
+		.await
+		.success()
+		.map_err(search_err)?;
+
+	drop(search);
+
+	ldap.unbind()
+		.await
+		.map_err(|e| err!(Ldap(error!("LDAP unbind error: {e}"))))?;
+
+	driver.await.log_err().ok();
+
+	Ok(localparts)
+}
+
+/// Sets the user's power level in the rooms of `group_power_levels` to the
+/// highest level granted by the user's groups. A level granted before is
+/// removed once none of the user's groups grant one, unless it was changed
+/// since; levels LDAP did not grant are left alone.
+#[implement(super::Service)]
+pub async fn apply_ldap_group_power_levels(&self, user_id: &UserId, groups: &[String]) {
+	let config = &self.services.server.config.ldap;
+	let is_member = |group: &String| {
+		groups
+			.iter()
+			.any(|g| g.eq_ignore_ascii_case(group))
+	};
+
+	let mut levels: BTreeMap<&RoomId, Option<i64>> = BTreeMap::new();
+	for (group, rooms) in &config.group_power_levels {
+		let member = is_member(group);
+		for (room_id, &level) in rooms {
+			let entry = levels.entry(room_id).or_default();
+			if member {
+				*entry = entry.max(Some(level));
+			}
+		}
+	}
+
+	for (room_id, level) in levels {
+		if let Err(e) = self
+			.set_ldap_power_level(user_id, room_id, level)
+			.await
+		{
+			warn!(%room_id, %user_id, "Failed to apply LDAP group power level: {e}");
+		}
+	}
+}
+
+#[implement(super::Service)]
+async fn set_ldap_power_level(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	level: Option<i64>,
+) -> Result {
+	let server_user = &self.services.globals.server_user;
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+
+	let power_levels = self
+		.services
+		.state_accessor
+		.get_power_levels(room_id)
+		.await?;
+
+	let key = (user_id, room_id);
+	let granted: Option<Int> = self
+		.db
+		.userroomid_ldappowerlevel
+		.qry(&key)
+		.await
+		.deserialized()
+		.ok()
+		.map(Int::new_saturating);
+
+	let current = power_levels.users.get(user_id).copied();
+	let level = match level.map(Int::new_saturating) {
+		| Some(level) => Some(level),
+		| None if granted.is_some() && current == granted => None,
+		| None => {
+			self.db.userroomid_ldappowerlevel.del(key);
+			return Ok(());
+		},
+	};
+
+	if current == level {
+		self.record_ldap_power_level(user_id, room_id, level);
+		return Ok(());
+	}
+
+	if !power_levels.user_can_change_user_power_level(server_user, user_id) {
+		return Err!(Request(Forbidden("The server user cannot change the user's power level")));
+	}
+
+	let mut content: RoomPowerLevelsEventContent = power_levels.try_into()?;
+	match level {
+		| Some(level) => content.users.insert(user_id.to_owned(), level),
+		| None => content.users.remove(user_id),
+	};
+
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(String::new(), &content),
+			server_user,
+			room_id,
+			&state_lock,
+		)
+		.await?;
+
+	self.record_ldap_power_level(user_id, room_id, level);
+	debug_info!(%room_id, %user_id, ?level, "Applied LDAP group power level");
+
+	Ok(())
+}
+
+#[implement(super::Service)]
+fn record_ldap_power_level(&self, user_id: &UserId, room_id: &RoomId, level: Option<Int>) {
+	let key = (user_id, room_id);
+	match level {
+		| Some(level) => self
+			.db
+			.userroomid_ldappowerlevel
+			.put(key, i64::from(level)),
+		| None => self.db.userroomid_ldappowerlevel.del(key),
+	}
+}
+
+/// Connects to the LDAP server, returning the connection and the task driving
+/// it.
+#[implement(super::Service)]
+async fn ldap_connect(&self) -> Result<(Ldap, JoinHandle<()>)> {
 	let config = &self.services.server.config.ldap;
 	let uri = config
 		.uri
@@ -131,9 +322,9 @@ pub async fn auth_ldap(&self, user_dn: &str, password: &str) -> Result {
 	}
 
 	debug!(?uri, "LDAP creating connection...");
-	let (conn, mut ldap) = LdapConnAsync::new(uri.as_str())
+	let (conn, ldap) = LdapConnAsync::new(uri.as_str())
 		.await
-		.map_err(|e| err!(Ldap(error!(?user_dn, "LDAP connection setup error: {e}"))))?;
+		.map_err(|e| err!(Ldap(error!("LDAP connection setup error: {e}"))))?;
 
 	let driver = self.services.server.runtime().spawn(async move {
 		match conn.drive().await {
@@ -142,16 +333,23 @@ pub async fn auth_ldap(&self, user_dn: &str, password: &str) -> Result {
 		}
 	});
 
-	ldap.simple_bind(user_dn, password)
-		.await
-		.and_then(ldap3::LdapResult::success)
-		.map_err(|e| err!(Request(Forbidden(debug_error!("LDAP authentication error: {e}")))))?;
-
-	ldap.unbind()
-		.await
-		.map_err(|e| err!(Ldap(error!("LDAP unbind error: {e}"))))?;
+	Ok((ldap, driver))
+}
 
-	driver.await.log_err().ok();
+/// Binds as `bind_dn` for searching, unless searching anonymously.
+#[implement(super::Service)]
+async fn ldap_bind(&self, ldap: &mut Ldap) -> Result {
+	let config = &self.services.server.config.ldap;
+	match (&config.bind_dn, &config.bind_password_file) {
+		| (Some(bind_dn), Some(bind_password_file)) => {
+			let bind_pw = String::from_utf8(std::fs::read(bind_password_file)?)?;
+			ldap.simple_bind(bind_dn, bind_pw.trim())
+				.await
+				.and_then(ldap3::LdapResult::success)
+				.map_err(|e| err!(Ldap(error!("LDAP bind error: {e}"))))?;
+		},
+		| (..) => {},
+	}
 
 	Ok(())
 }
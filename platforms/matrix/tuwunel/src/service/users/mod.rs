@@ -1,31 +1,60 @@
 mod dehydrated_device;
 pub mod device;
+mod device_list;
+mod export;
 mod keys;
 mod ldap;
+mod openid;
 mod profile;
 mod register;
+mod session;
+mod shadow_ban;
+mod suspend;
+mod threepid;
+
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 
-use std::sync::Arc;
-
+use async_trait::async_trait;
 use futures::{Stream, StreamExt, TryFutureExt};
 use ruma::{
-	OwnedRoomId, OwnedUserId, UserId,
+	OwnedDeviceId, OwnedRoomId, OwnedUserId, UserId,
 	api::client::filter::FilterDefinition,
 	events::{GlobalAccountDataEventType, ignored_user_list::IgnoredUserListEvent},
 };
 use tuwunel_core::{
-	Err, Result, debug_warn, err, is_equal_to,
+	Err, Result, debug_info, err, is_equal_to,
 	pdu::PduBuilder,
-	trace,
-	utils::{self, ReadyExt, stream::TryIgnore},
+	utils::{self, MutexMap, ReadyExt, stream::TryIgnore},
 	warn,
 };
-use tuwunel_database::{Deserialized, Json, Map};
-
-pub use self::{keys::parse_master_key, register::Register};
+use tuwunel_database::{Deserialized, Ignore, Interfix, Json, Map, Ttl};
+
+pub use self::{
+	export::{EXPORT_VERSION, Export, Imported},
+	keys::parse_master_key,
+	openid::{OPENID_TOKEN_PREFIX_LENGTH, OpenIdToken},
+	register::Register,
+	session::{Session, TokenKind},
+};
 
 pub struct Service {
 	services: Arc<crate::services::OnceServices>,
+	device_list_mutex: MutexMap<OwnedUserId, ()>,
+	threepid_mutex: MutexMap<String, ()>,
+
+	/// Sessions of devices as last written, sparing a read of the database for
+	/// each authenticated request.
+	sessions: Mutex<HashMap<OwnedUserId, HashMap<OwnedDeviceId, Session>>>,
+
+	/// Requests for the versions supported by the server made without
+	/// authentication, by address, with the user agent and when each was made.
+	versions_fetched: Mutex<HashMap<IpAddr, Vec<(Option<String>, u64)>>>,
+
 	db: Data,
 }
 
@@ -33,13 +62,18 @@ struct Data {
 	keychangeid_userid: Arc<Map>,
 	keyid_key: Arc<Map>,
 	onetimekeyid_onetimekeys: Arc<Map>,
-	openidtoken_expiresatuserid: Arc<Map>,
-	logintoken_expiresatuserid: Arc<Map>,
+	openidtoken_expiresatuserid: Ttl,
+	threepid_userid: Arc<Map>,
+	logintoken_expiresatuserid: Ttl,
 	todeviceid_events: Arc<Map>,
 	token_userdeviceid: Arc<Map>,
+	user_threepids: Arc<Map>,
+	userdevicealgorithm_fallbackkey: Arc<Map>,
 	userdeviceid_metadata: Arc<Map>,
 	userdeviceid_token: Arc<Map>,
 	userdeviceid_refresh: Arc<Map>,
+	userdeviceid_rotatedrefresh: Arc<Map>,
+	userdeviceid_session: Arc<Map>,
 	userfilterid_filter: Arc<Map>,
 	userid_avatarurl: Arc<Map>,
 	userid_blurhash: Arc<Map>,
@@ -51,25 +85,45 @@ struct Data {
 	userid_password: Arc<Map>,
 	userid_origin: Arc<Map>,
 	userid_selfsigningkeyid: Arc<Map>,
+	userid_shadowbanned: Arc<Map>,
+	userid_suspended: Arc<Map>,
 	userid_usersigningkeyid: Arc<Map>,
+	useridcount_devicelistchange: Arc<Map>,
+	useridopenidtoken_info: Arc<Map>,
 	useridprofilekey_value: Arc<Map>,
+	userroomid_ldappowerlevel: Arc<Map>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			services: args.services.clone(),
+			device_list_mutex: MutexMap::new(),
+			threepid_mutex: MutexMap::new(),
+			sessions: Mutex::default(),
+			versions_fetched: Mutex::default(),
 			db: Data {
 				keychangeid_userid: args.db["keychangeid_userid"].clone(),
 				keyid_key: args.db["keyid_key"].clone(),
 				onetimekeyid_onetimekeys: args.db["onetimekeyid_onetimekeys"].clone(),
-				openidtoken_expiresatuserid: args.db["openidtoken_expiresatuserid"].clone(),
-				logintoken_expiresatuserid: args.db["logintoken_expiresatuserid"].clone(),
+				openidtoken_expiresatuserid: args.db["openidtoken_expiresatuserid"]
+					.clone()
+					.into(),
+				logintoken_expiresatuserid: args.db["logintoken_expiresatuserid"]
+					.clone()
+					.into(),
+				threepid_userid: args.db["threepid_userid"].clone(),
 				todeviceid_events: args.db["todeviceid_events"].clone(),
 				token_userdeviceid: args.db["token_userdeviceid"].clone(),
+				user_threepids: args.db["user_threepids"].clone(),
+				userdevicealgorithm_fallbackkey: args.db["userdevicealgorithm_fallbackkey"]
+					.clone(),
 				userdeviceid_metadata: args.db["userdeviceid_metadata"].clone(),
 				userdeviceid_token: args.db["userdeviceid_token"].clone(),
 				userdeviceid_refresh: args.db["userdeviceid_refresh"].clone(),
+				userdeviceid_rotatedrefresh: args.db["userdeviceid_rotatedrefresh"].clone(),
+				userdeviceid_session: args.db["userdeviceid_session"].clone(),
 				userfilterid_filter: args.db["userfilterid_filter"].clone(),
 				userid_avatarurl: args.db["userid_avatarurl"].clone(),
 				userid_blurhash: args.db["userid_blurhash"].clone(),
@@ -81,13 +135,41 @@ fn build(args: &crate::Args<'_>) -> Result<Arc<Self>> {
 				userid_password: args.db["userid_password"].clone(),
 				userid_origin: args.db["userid_origin"].clone(),
 				userid_selfsigningkeyid: args.db["userid_selfsigningkeyid"].clone(),
+				userid_shadowbanned: args.db["userid_shadowbanned"].clone(),
+				userid_suspended: args.db["userid_suspended"].clone(),
 				userid_usersigningkeyid: args.db["userid_usersigningkeyid"].clone(),
+				useridcount_devicelistchange: args.db["useridcount_devicelistchange"].clone(),
+				useridopenidtoken_info: args.db["useridopenidtoken_info"].clone(),
 				useridprofilekey_value: args.db["useridprofilekey_value"].clone(),
+				userroomid_ldappowerlevel: args.db["userroomid_ldappowerlevel"].clone(),
 			},
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		loop {
+			let retention_seconds = self.services.config.to_device_retention_seconds;
+
+			if retention_seconds != 0 {
+				debug_info!("Pruning to-device events of stale devices");
+
+				let count = self
+					.prune_to_device_events(Duration::from_secs(retention_seconds))
+					.await;
+
+				debug_info!(?count, "Finished pruning to-device events");
+			}
+
+			tokio::select! {
+				() = tokio::time::sleep(Duration::from_secs(60 * 60)) => {},
+				() = self.services.server.until_shutdown() => return Ok(())
+			};
+		}
+	}
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+
+	fn dependencies(&self) -> &'static [&'static str] { &["appservice"] }
 }
 
 impl Service {
@@ -143,7 +225,9 @@ pub async fn deactivate_account(&self, user_id: &UserId) -> Result {
 		// account is deactivated.
 		self.set_password(user_id, None).await?;
 
-		// TODO: Unhook 3PID
+		// Unhook third-party identifiers, so that other users may use them
+		self.remove_threepids(user_id).await;
+
 		Ok(())
 	}
 
@@ -212,6 +296,16 @@ pub async fn password_hash(&self, user_id: &UserId) -> Result<String> {
 			.deserialized()
 	}
 
+	/// Whether the user has a password of their own. Users signing in only
+	/// through SSO, JWT or LDAP have the placeholder "*" instead.
+	pub async fn has_password(&self, user_id: &UserId) -> bool {
+		self.password_hash(user_id)
+			.await
+			.is_ok_and(|hash| {
+				!hash.is_empty() && utils::hash::verify_password("*", &hash).is_err()
+			})
+	}
+
 	/// Hash and set the user's password to the Argon2 hash
 	pub async fn set_password(&self, user_id: &UserId, password: Option<&str>) -> Result {
 		// Cannot change the password of a LDAP user. There are two special cases :
@@ -254,6 +348,21 @@ pub async fn set_password(&self, user_id: &UserId, password: Option<&str>) -> Re
 		Ok(())
 	}
 
+	/// Sets the user's password to a hash made elsewhere, such as by the system
+	/// the user is migrated from. It must be an Argon2 or bcrypt hash.
+	pub fn set_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result {
+		if !utils::hash::is_password_hash(password_hash) {
+			return Err!(Request(InvalidParam("Not an Argon2 or bcrypt password hash.")));
+		}
+
+		self.db
+			.userid_password
+			.insert(user_id, password_hash);
+		self.db.userid_origin.insert(user_id, "password");
+
+		Ok(())
+	}
+
 	/// Creates a new sync filter. Returns the filter id.
 	#[must_use]
 	pub fn create_filter(&self, user_id: &UserId, filter: &FilterDefinition) -> String {
@@ -278,70 +387,37 @@ pub async fn get_filter(
 			.deserialized()
 	}
 
-	/// Creates an OpenID token, which can be used to prove that a user has
-	/// access to an account (primarily for integrations)
-	pub fn create_openid_token(&self, user_id: &UserId, token: &str) -> Result<u64> {
-		use std::num::Saturating as Sat;
-
-		let expires_in = self.services.server.config.openid_token_ttl;
-		let expires_at = Sat(utils::millis_since_unix_epoch()) + Sat(expires_in) * Sat(1000);
-
-		let mut value = expires_at.0.to_be_bytes().to_vec();
-		value.extend_from_slice(user_id.as_bytes());
-
-		self.db
-			.openidtoken_expiresatuserid
-			.insert(token.as_bytes(), value.as_slice());
-
-		Ok(expires_in)
+	/// Stores a sync filter under the given id, replacing any existing one.
+	pub fn set_filter(&self, user_id: &UserId, filter_id: &str, filter: &FilterDefinition) {
+		let key = (user_id, filter_id);
+		self.db.userfilterid_filter.put(key, Json(filter));
 	}
 
-	/// Find out which user an OpenID access token belongs to.
-	pub async fn find_from_openid_token(&self, token: &str) -> Result<OwnedUserId> {
-		let Ok(value) = self
-			.db
-			.openidtoken_expiresatuserid
-			.get(token)
-			.await
-		else {
-			return Err!(Request(Unauthorized("OpenID token is unrecognised")));
-		};
-
-		let (expires_at_bytes, user_bytes) = value.split_at(0_u64.to_be_bytes().len());
-		let expires_at =
-			u64::from_be_bytes(expires_at_bytes.try_into().map_err(|e| {
-				err!(Database("expires_at in openid_userid is invalid u64. {e}"))
-			})?);
-
-		if expires_at < utils::millis_since_unix_epoch() {
-			debug_warn!("OpenID token is expired, removing");
-			self.db
-				.openidtoken_expiresatuserid
-				.remove(token.as_bytes());
-
-			return Err!(Request(Unauthorized("OpenID token is expired")));
-		}
-
-		let user_string = utils::string_from_bytes(user_bytes)
-			.map_err(|e| err!(Database("User ID in openid_userid is invalid unicode. {e}")))?;
+	/// Returns the sync filters of a user along with their ids.
+	pub fn all_filters<'a>(
+		&'a self,
+		user_id: &'a UserId,
+	) -> impl Stream<Item = (String, FilterDefinition)> + Send + 'a {
+		type KeyVal = ((Ignore, String), FilterDefinition);
 
-		OwnedUserId::try_from(user_string)
-			.map_err(|e| err!(Database("User ID in openid_userid is invalid. {e}")))
+		let prefix = (user_id, Interfix);
+		self.db
+			.userfilterid_filter
+			.stream_prefix(&prefix)
+			.ignore_err()
+			.map(|((_, filter_id), filter): KeyVal| (filter_id, filter))
 	}
 
 	/// Creates a short-lived login token, which can be used to log in using the
 	/// `m.login.token` mechanism.
 	#[must_use]
 	pub fn create_login_token(&self, user_id: &UserId, token: &str) -> u64 {
-		use std::num::Saturating as Sat;
-
 		let expires_in = self.services.server.config.login_token_ttl;
-		let expires_at = Sat(utils::millis_since_unix_epoch()) + Sat(expires_in);
-
-		let value = (expires_at.0, user_id);
-		self.db
-			.logintoken_expiresatuserid
-			.raw_put(token, value);
+		self.db.logintoken_expiresatuserid.insert(
+			token,
+			user_id,
+			Duration::from_millis(expires_in),
+		);
 
 		expires_in
 	}
@@ -349,27 +425,11 @@ pub fn create_login_token(&self, user_id: &UserId, token: &str) -> u64 {
 	/// Find out which user a login token belongs to.
 	/// Removes the token to prevent double-use attacks.
 	pub async fn find_from_login_token(&self, token: &str) -> Result<OwnedUserId> {
-		let Ok(value) = self
-			.db
+		self.db
 			.logintoken_expiresatuserid
-			.get(token)
+			.take(token)
 			.await
-		else {
-			return Err!(Request(Forbidden("Login token is unrecognised")));
-		};
-		let (expires_at, user_id): (u64, OwnedUserId) = value.deserialized()?;
-
-		if expires_at < utils::millis_since_unix_epoch() {
-			trace!(?user_id, ?token, "Removing expired login token");
-
-			self.db.logintoken_expiresatuserid.remove(token);
-
-			return Err!(Request(Forbidden("Login token is expired")));
-		}
-
-		self.db.logintoken_expiresatuserid.remove(token);
-
-		Ok(user_id)
+			.map_err(|_| err!(Request(Forbidden("Login token is unrecognised or expired"))))
 	}
 
 	#[cfg(not(feature = "ldap"))]
@@ -380,10 +440,20 @@ pub async fn search_ldap(&self, _user_id: &UserId) -> Result<Vec<(String, bool)>
 
 	#[cfg(not(feature = "ldap"))]
 	#[expect(clippy::unused_async)]
-	pub async fn auth_ldap(&self, _user_dn: &str, _password: &str) -> Result {
+	pub async fn auth_ldap(&self, _user_dn: &str, _password: &str) -> Result<Vec<String>> {
 		Err!(FeatureDisabled("ldap"))
 	}
 
+	#[cfg(not(feature = "ldap"))]
+	#[expect(clippy::unused_async)]
+	pub async fn ldap_localparts(&self) -> Result<std::collections::HashSet<String>> {
+		Err!(FeatureDisabled("ldap"))
+	}
+
+	#[cfg(not(feature = "ldap"))]
+	#[expect(clippy::unused_async)]
+	pub async fn apply_ldap_group_power_levels(&self, _user_id: &UserId, _groups: &[String]) {}
+
 	async fn update_all_rooms(&self, user_id: &UserId, rooms: Vec<(PduBuilder, &OwnedRoomId)>) {
 		for (pdu_builder, room_id) in rooms {
 			let state_lock = self.services.state.mutex.lock(room_id).await;
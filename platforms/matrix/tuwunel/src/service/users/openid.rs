@@ -0,0 +1,190 @@
+//! OpenID tokens, with which integrations verify the identity of a user, and
+//! the record of who requested and exchanged each of them for auditing.
+
+use std::{net::IpAddr, time::Duration};
+
+use futures::StreamExt;
+use ruma::{DeviceId, OwnedDeviceId, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{
+	Result, err, implement,
+	utils::{ReadyExt, stream::TryIgnore, time::now_secs},
+};
+use tuwunel_database::{Deserialized, Ignore, Interfix, Json};
+
+/// Record of an OpenID token.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OpenIdToken {
+	/// Device which requested the token.
+	pub device_id: Option<OwnedDeviceId>,
+
+	/// Seconds since the epoch.
+	pub created_at: u64,
+
+	/// Seconds since the epoch.
+	pub expires_at: u64,
+
+	/// Number of times an integration exchanged the token for the identity of
+	/// the user.
+	pub exchanges: u64,
+
+	/// When the token was last exchanged, in seconds since the epoch.
+	pub last_exchanged_at: Option<u64>,
+
+	/// Address of the integration which last exchanged the token.
+	pub last_exchanged_ip: Option<IpAddr>,
+
+	/// User agent of the integration which last exchanged the token.
+	pub last_exchanged_user_agent: Option<String>,
+}
+
+/// Number of leading characters of a token identifying it to admins.
+pub const OPENID_TOKEN_PREFIX_LENGTH: usize = 8;
+
+/// Creates an OpenID token, which can be used to prove that a user has
+/// access to an account (primarily for integrations). The records of the
+/// user's expired tokens are removed, so that they do not accumulate.
+#[implement(super::Service)]
+pub async fn create_openid_token(
+	&self,
+	user_id: &UserId,
+	device_id: Option<&DeviceId>,
+	token: &str,
+) -> Result<u64> {
+	let expires_in = self.services.server.config.openid_token_ttl;
+	self.db
+		.openidtoken_expiresatuserid
+		.insert(token, user_id, Duration::from_secs(expires_in));
+
+	let now = now_secs();
+	let record = OpenIdToken {
+		device_id: device_id.map(ToOwned::to_owned),
+		created_at: now,
+		expires_at: now.saturating_add(expires_in),
+		exchanges: 0,
+		last_exchanged_at: None,
+		last_exchanged_ip: None,
+		last_exchanged_user_agent: None,
+	};
+
+	self.db
+		.useridopenidtoken_info
+		.put((user_id, token), Json(&record));
+
+	self.unexpired_openid_tokens(user_id).await;
+
+	Ok(expires_in)
+}
+
+/// Find out which user an OpenID access token belongs to.
+#[implement(super::Service)]
+pub async fn find_from_openid_token(&self, token: &str) -> Result<OwnedUserId> {
+	self.db
+		.openidtoken_expiresatuserid
+		.get(token)
+		.await
+		.map_err(|_| err!(Request(Unauthorized("OpenID token is unrecognised or expired"))))
+}
+
+/// Find out which user an OpenID access token belongs to on behalf of an
+/// integration, recording its address and user agent.
+#[implement(super::Service)]
+pub async fn exchange_openid_token(
+	&self,
+	token: &str,
+	ip: IpAddr,
+	user_agent: Option<&str>,
+) -> Result<OwnedUserId> {
+	let user_id = self.find_from_openid_token(token).await?;
+
+	let key = (&user_id, token);
+	if let Ok(mut record) = self
+		.db
+		.useridopenidtoken_info
+		.qry(&key)
+		.await
+		.deserialized::<OpenIdToken>()
+	{
+		record.exchanges = record.exchanges.saturating_add(1);
+		record.last_exchanged_at = Some(now_secs());
+		record.last_exchanged_ip = Some(ip);
+		record.last_exchanged_user_agent = user_agent.map(ToOwned::to_owned);
+
+		self.db
+			.useridopenidtoken_info
+			.put(key, Json(&record));
+	}
+
+	Ok(user_id)
+}
+
+/// The unexpired OpenID tokens of a user, by the prefix of the token
+/// identifying them to admins. Records of expired tokens are removed.
+#[implement(super::Service)]
+pub async fn openid_tokens(&self, user_id: &UserId) -> Vec<(String, OpenIdToken)> {
+	self.unexpired_openid_tokens(user_id)
+		.await
+		.into_iter()
+		.map(|(token, record)| (token_prefix(&token), record))
+		.collect()
+}
+
+/// The unexpired OpenID tokens of a user, removing the records of the others.
+#[implement(super::Service)]
+async fn unexpired_openid_tokens(&self, user_id: &UserId) -> Vec<(String, OpenIdToken)> {
+	type KeyVal<'a> = ((Ignore, &'a str), OpenIdToken);
+
+	let now = now_secs();
+	let (tokens, expired): (Vec<_>, Vec<_>) = self
+		.db
+		.useridopenidtoken_info
+		.stream_prefix(&(user_id, Interfix))
+		.ignore_err()
+		.map(|((_, token), record): KeyVal<'_>| (token.to_owned(), record))
+		.collect::<Vec<_>>()
+		.await
+		.into_iter()
+		.partition(|(_, record)| record.expires_at > now);
+
+	for (token, _) in expired {
+		self.db
+			.useridopenidtoken_info
+			.del((user_id, &token));
+	}
+
+	tokens
+}
+
+/// Revokes the OpenID tokens of a user, or only those starting with
+/// `prefix`. Returns the number revoked.
+#[implement(super::Service)]
+pub async fn revoke_openid_tokens(&self, user_id: &UserId, prefix: Option<&str>) -> usize {
+	let tokens: Vec<String> = self
+		.db
+		.useridopenidtoken_info
+		.keys_prefix(&(user_id, Interfix))
+		.ignore_err()
+		.ready_filter_map(|(_, token): (Ignore, &str)| {
+			prefix
+				.is_none_or(|prefix| token.starts_with(prefix))
+				.then(|| token.to_owned())
+		})
+		.collect()
+		.await;
+
+	for token in &tokens {
+		self.db.openidtoken_expiresatuserid.remove(token);
+		self.db
+			.useridopenidtoken_info
+			.del((user_id, token));
+	}
+
+	tokens.len()
+}
+
+fn token_prefix(token: &str) -> String {
+	token
+		.chars()
+		.take(OPENID_TOKEN_PREFIX_LENGTH)
+		.collect()
+}
@@ -1,10 +1,20 @@
-use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt, future::join3};
+use std::collections::BTreeMap;
+
+use futures::{
+	FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt,
+	future::{join3, join4},
+};
 use ruma::{
 	MxcUri, OwnedMxcUri, OwnedRoomId, UserId,
-	events::room::member::{MembershipState, RoomMemberEventContent},
+	events::{
+		TimelineEventType,
+		room::member::{MembershipState, RoomMemberEventContent},
+	},
 };
+use serde::Serialize;
+use serde_json::{Value as JsonValue, value::to_raw_value};
 use tuwunel_core::{
-	Result, implement,
+	Err, Result, implement,
 	matrix::PduBuilder,
 	utils::{
 		future::TryExtExt,
@@ -13,13 +23,19 @@
 };
 use tuwunel_database::{Deserialized, Ignore, Interfix, Json};
 
+/// Sets the display name of a user, sanitized, and sends it into their rooms.
 #[implement(super::Service)]
 pub async fn update_displayname(
 	&self,
 	user_id: &UserId,
 	displayname: Option<&str>,
 	rooms: &[OwnedRoomId],
-) {
+) -> Result {
+	let displayname = displayname
+		.map(|displayname| self.services.sanitize.displayname(displayname))
+		.transpose()?;
+
+	let displayname = displayname.as_deref();
 	let (current_avatar_url, current_blurhash, current_displayname) = join3(
 		self.services.users.avatar_url(user_id).ok(),
 		self.services.users.blurhash(user_id).ok(),
@@ -28,7 +44,7 @@ pub async fn update_displayname(
 	.await;
 
 	if displayname == current_displayname.as_deref() {
-		return;
+		return Ok(());
 	}
 
 	self.services
@@ -36,6 +52,7 @@ pub async fn update_displayname(
 		.set_displayname(user_id, displayname);
 
 	// Send a new join membership event into rooms
+	let fields = &self.member_event_fields(user_id).await;
 	let avatar_url = &current_avatar_url;
 	let blurhash = &current_blurhash;
 	let displayname = &displayname;
@@ -43,16 +60,20 @@ pub async fn update_displayname(
 		.iter()
 		.try_stream()
 		.and_then(async |room_id: &OwnedRoomId| {
-			let pdu = PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
-				displayname: displayname.map(ToOwned::to_owned),
-				membership: MembershipState::Join,
-				avatar_url: avatar_url.clone(),
-				blurhash: blurhash.clone(),
-				join_authorized_via_users_server: None,
-				reason: None,
-				is_direct: None,
-				third_party_invite: None,
-			});
+			let pdu = member_pdu(
+				user_id,
+				&RoomMemberEventContent {
+					displayname: displayname.map(ToOwned::to_owned),
+					membership: MembershipState::Join,
+					avatar_url: avatar_url.clone(),
+					blurhash: blurhash.clone(),
+					join_authorized_via_users_server: None,
+					reason: None,
+					is_direct: None,
+					third_party_invite: None,
+				},
+				fields,
+			);
 
 			Ok((pdu, room_id))
 		})
@@ -63,6 +84,25 @@ pub async fn update_displayname(
 	self.update_all_rooms(user_id, rooms)
 		.boxed()
 		.await;
+
+	Ok(())
+}
+
+/// Rooms into which a change to a user's profile is sent as new membership
+/// events: their joined rooms, or none when `propagate_profile_changes` is
+/// disabled.
+#[implement(super::Service)]
+pub async fn profile_rooms(&self, user_id: &UserId) -> Vec<OwnedRoomId> {
+	if !self.services.config.propagate_profile_changes {
+		return Vec::new();
+	}
+
+	self.services
+		.state_cache
+		.rooms_joined(user_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await
 }
 
 /// Sets a new displayname or removes it if displayname is None. You still
@@ -115,20 +155,25 @@ pub async fn update_avatar_url(
 		.set_blurhash(user_id, blurhash);
 
 	// Send a new join membership event into rooms
+	let fields = &self.member_event_fields(user_id).await;
 	let rooms: Vec<_> = rooms
 		.iter()
 		.try_stream()
 		.and_then(async |room_id: &OwnedRoomId| {
-			let pdu = PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
-				avatar_url: avatar_url.map(ToOwned::to_owned),
-				blurhash: blurhash.map(ToOwned::to_owned),
-				membership: MembershipState::Join,
-				displayname: current_displayname.clone(),
-				join_authorized_via_users_server: None,
-				reason: None,
-				is_direct: None,
-				third_party_invite: None,
-			});
+			let pdu = member_pdu(
+				user_id,
+				&RoomMemberEventContent {
+					avatar_url: avatar_url.map(ToOwned::to_owned),
+					blurhash: blurhash.map(ToOwned::to_owned),
+					membership: MembershipState::Join,
+					displayname: current_displayname.clone(),
+					join_authorized_via_users_server: None,
+					reason: None,
+					is_direct: None,
+					third_party_invite: None,
+				},
+				fields,
+			);
 
 			Ok((pdu, room_id))
 		})
@@ -227,6 +272,151 @@ pub fn all_profile_keys<'a>(
 		.map(|((_, key), val): KeyVal| (key, val))
 }
 
+/// Sets a custom profile field of a local user, or removes it if value is
+/// None, within `max_profile_fields` and `max_profile_size`. When the field is
+/// one of `member_event_profile_fields`, a new membership event is sent into
+/// the user's [profile rooms](Self::profile_rooms).
+#[implement(super::Service)]
+pub async fn update_profile_key(
+	&self,
+	user_id: &UserId,
+	profile_key: &str,
+	profile_key_value: Option<&JsonValue>,
+) -> Result {
+	let current = self.profile_key(user_id, profile_key).await.ok();
+	if current.as_ref() == profile_key_value {
+		return Ok(());
+	}
+
+	if let Some(value) = profile_key_value {
+		self.check_profile_limits(user_id, profile_key, value)
+			.await?;
+	}
+
+	self.set_profile_key(user_id, profile_key, profile_key_value);
+
+	let config = &self.services.config;
+	if !config
+		.member_event_profile_fields
+		.iter()
+		.any(|field| field == profile_key)
+	{
+		return Ok(());
+	}
+
+	let (avatar_url, blurhash, displayname, fields) = join4(
+		self.avatar_url(user_id).ok(),
+		self.blurhash(user_id).ok(),
+		self.displayname(user_id).ok(),
+		self.member_event_fields(user_id),
+	)
+	.await;
+
+	let content = RoomMemberEventContent {
+		avatar_url,
+		blurhash,
+		displayname,
+		membership: MembershipState::Join,
+		join_authorized_via_users_server: None,
+		reason: None,
+		is_direct: None,
+		third_party_invite: None,
+	};
+
+	let rooms = self.profile_rooms(user_id).await;
+	let rooms: Vec<_> = rooms
+		.iter()
+		.map(|room_id| (member_pdu(user_id, &content, &fields), room_id))
+		.collect();
+
+	self.update_all_rooms(user_id, rooms)
+		.boxed()
+		.await;
+
+	Ok(())
+}
+
+/// Fails when setting a custom profile field would take a user's profile past
+/// `max_profile_fields` or `max_profile_size`.
+#[implement(super::Service)]
+async fn check_profile_limits(
+	&self,
+	user_id: &UserId,
+	profile_key: &str,
+	value: &JsonValue,
+) -> Result {
+	let config = &self.services.config;
+	let (displayname, avatar_url, mut fields) = join3(
+		self.displayname(user_id).ok(),
+		self.avatar_url(user_id).ok(),
+		self.all_profile_keys(user_id)
+			.collect::<BTreeMap<_, _>>(),
+	)
+	.await;
+
+	fields.insert(profile_key.to_owned(), value.clone());
+
+	let max_fields = config.max_profile_fields;
+	if fields.len() > max_fields {
+		return Err!(Request(TooLarge("Profiles cannot have more than {max_fields} fields.")));
+	}
+
+	let max_size = config.max_profile_size;
+	let size = serde_json::to_vec(&(displayname, avatar_url, fields))?.len();
+	if size > max_size {
+		return Err!(Request(TooLarge("Profiles cannot be larger than {max_size} bytes.")));
+	}
+
+	Ok(())
+}
+
+/// The custom profile fields of a user which are put into their membership
+/// events, per `member_event_profile_fields`.
+#[implement(super::Service)]
+async fn member_event_fields(&self, user_id: &UserId) -> BTreeMap<String, JsonValue> {
+	self.services
+		.config
+		.member_event_profile_fields
+		.iter()
+		.stream()
+		.filter_map(async |field| {
+			let value = self.profile_key(user_id, field).await.ok()?;
+
+			Some((field.clone(), value))
+		})
+		.collect()
+		.await
+}
+
+/// Builds a membership event with the custom profile fields of the user added
+/// to its content.
+fn member_pdu(
+	user_id: &UserId,
+	content: &RoomMemberEventContent,
+	fields: &BTreeMap<String, JsonValue>,
+) -> PduBuilder {
+	#[derive(Serialize)]
+	struct Content<'a> {
+		#[serde(flatten)]
+		fields: &'a BTreeMap<String, JsonValue>,
+
+		#[serde(flatten)]
+		content: &'a RoomMemberEventContent,
+	}
+
+	if fields.is_empty() {
+		return PduBuilder::state(user_id.to_string(), content);
+	}
+
+	PduBuilder {
+		event_type: TimelineEventType::RoomMember,
+		content: to_raw_value(&Content { fields, content })
+			.expect("Failed to serialize membership event content"),
+		state_key: Some(user_id.as_str().into()),
+		..PduBuilder::default()
+	}
+}
+
 /// Sets a new profile key value, removes the key if value is None
 #[implement(super::Service)]
 pub fn set_profile_key(
@@ -17,6 +17,23 @@ pub struct Register<'a> {
 	pub omit_displayname_suffix: bool,
 }
 
+/// Whether a localpart is reserved by `reserved_localparts` or
+/// `reserved_localpart_patterns`, so that it may only be created by admins.
+#[implement(super::Service)]
+#[must_use]
+pub fn is_reserved_localpart(&self, localpart: &str) -> bool {
+	let config = &self.services.config;
+	let localpart = localpart.to_lowercase();
+
+	config
+		.reserved_localparts
+		.iter()
+		.any(|reserved| reserved.eq_ignore_ascii_case(&localpart))
+		|| config
+			.reserved_localpart_patterns
+			.is_match(&localpart)
+}
+
 /// Fully register a local user
 ///
 /// Returns a device id and access token for the registered user
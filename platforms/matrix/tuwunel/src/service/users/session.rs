@@ -0,0 +1,294 @@
+//! Activity of each device's session: when it was created, the address and
+//! user agent it was last seen with, and the versions of the client API it
+//! uses, for admins investigating accounts.
+
+use std::{collections::BTreeSet, fmt, net::IpAddr, time::Duration};
+
+use ruma::{DeviceId, MilliSecondsSinceUnixEpoch, UserId};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{Result, implement, utils::time::now_secs};
+use tuwunel_database::{Deserialized, Json};
+
+/// Activity of the session of one device.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Session {
+	/// When the device was created, in seconds since the epoch; unknown for
+	/// devices created before sessions were recorded.
+	pub created_at: Option<u64>,
+
+	/// When the device last made an authenticated request, in seconds since
+	/// the epoch.
+	pub last_seen_at: u64,
+
+	pub last_seen_ip: Option<IpAddr>,
+
+	pub user_agent: Option<String>,
+
+	/// Versions of the client API the device made requests with, e.g. `r0`,
+	/// `v3` or `unstable/org.matrix.msc3575` for the unstable endpoints of a
+	/// feature. Clients still using `r0` are likely ancient.
+	#[serde(default)]
+	pub api_versions: BTreeSet<String>,
+
+	/// When the device last asked for the versions and features supported by
+	/// the server, in seconds since the epoch. Clients usually ask before
+	/// authenticating; such a request is credited to the device seen shortly
+	/// after from the same address and user agent.
+	#[serde(default)]
+	pub versions_fetched_at: Option<u64>,
+}
+
+/// How a device authenticates its requests.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenKind {
+	/// An access token without a refresh token.
+	Access,
+
+	/// An access token renewed by a refresh token.
+	Refresh,
+
+	/// A device of a user in the exclusive namespace of an appservice.
+	Appservice,
+}
+
+impl fmt::Display for TokenKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::Access => write!(f, "access"),
+			| Self::Refresh => write!(f, "refresh"),
+			| Self::Appservice => write!(f, "appservice"),
+		}
+	}
+}
+
+/// Interval within which a session seen again from the same address and
+/// user agent is not written again; it is then checked against its copy in
+/// memory only.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Most versions of the client API recorded for a device.
+const MAX_API_VERSIONS: usize = 32;
+
+/// Interval within which a request for the versions made without
+/// authentication is credited to a device seen from the same address and user
+/// agent.
+const VERSIONS_ATTRIBUTION: Duration = Duration::from_secs(300);
+
+/// Most addresses, and user agents per address, whose requests for the
+/// versions made without authentication are remembered at once.
+const MAX_VERSIONS_FETCHED: (usize, usize) = (4096, 8);
+
+/// Records a new session for a device just created.
+#[implement(super::Service)]
+pub(super) fn create_session(&self, user_id: &UserId, device_id: &DeviceId, ip: Option<IpAddr>) {
+	let now = now_secs();
+	let session = Session {
+		created_at: Some(now),
+		last_seen_at: now,
+		last_seen_ip: ip,
+		..Session::default()
+	};
+
+	self.db
+		.userdeviceid_session
+		.put((user_id, device_id), Json(&session));
+
+	self.cache_session(user_id, device_id, session);
+}
+
+/// Records an authenticated request made by the device, with the version of
+/// the client API in its path and whether it asked for the versions supported
+/// by the server, or recently did so without authentication. The device's
+/// `last_seen_ip` and `last_seen_ts` are updated along with the session.
+#[implement(super::Service)]
+pub async fn record_session(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	ip: IpAddr,
+	user_agent: Option<&str>,
+	api_version: Option<&str>,
+	versions: bool,
+) {
+	let now = now_secs();
+	let recent = |secs: u64| now.saturating_sub(secs) < UPDATE_INTERVAL.as_secs();
+	let versions_at = versions
+		.then_some(now)
+		.or_else(|| self.versions_fetched_by(ip, user_agent));
+
+	let versions_recorded = |session: &Session| {
+		versions_at.is_none_or(|at| {
+			session
+				.versions_fetched_at
+				.is_some_and(|fetched| fetched.saturating_add(UPDATE_INTERVAL.as_secs()) > at)
+		})
+	};
+
+	let unchanged = |session: &Session| {
+		session.last_seen_ip == Some(ip)
+			&& session.user_agent.as_deref() == user_agent
+			&& recent(session.last_seen_at)
+			&& api_version.is_none_or(|version| {
+				session.api_versions.contains(version)
+					|| session.api_versions.len() >= MAX_API_VERSIONS
+			}) && versions_recorded(session)
+	};
+
+	if self
+		.cached_session(user_id, device_id, unchanged)
+		.unwrap_or(false)
+	{
+		return;
+	}
+
+	let session = self.get_session(user_id, device_id).await.ok();
+	if let Some(session) = session.as_ref()
+		&& unchanged(session)
+	{
+		self.cache_session(user_id, device_id, session.clone());
+		return;
+	}
+
+	let Ok(mut device) = self.get_device_metadata(user_id, device_id).await else {
+		return;
+	};
+
+	let mut session = session.unwrap_or_default();
+	session.last_seen_at = now;
+	session.last_seen_ip = Some(ip);
+	session.user_agent = user_agent.map(ToOwned::to_owned);
+	if let Some(version) = api_version
+		&& session.api_versions.len() < MAX_API_VERSIONS
+	{
+		session.api_versions.insert(version.to_owned());
+	}
+
+	if let Some(at) = versions_at {
+		session.versions_fetched_at = session.versions_fetched_at.max(Some(at));
+	}
+
+	self.db
+		.userdeviceid_session
+		.put((user_id, device_id), Json(&session));
+
+	self.cache_session(user_id, device_id, session);
+
+	device.last_seen_ip = Some(ip.to_string());
+	device.last_seen_ts = Some(MilliSecondsSinceUnixEpoch::now());
+	self.put_device_metadata(user_id, false, &device)
+		.await;
+}
+
+/// Records a request for the versions supported by the server made without
+/// authentication, to be credited to the device next seen from the same
+/// address and user agent.
+#[implement(super::Service)]
+pub fn record_versions_fetched(&self, ip: IpAddr, user_agent: Option<&str>) {
+	let now = now_secs();
+	let (max_addresses, max_user_agents) = MAX_VERSIONS_FETCHED;
+	let current = |&(_, at): &(Option<String>, u64)| {
+		now.saturating_sub(at) < VERSIONS_ATTRIBUTION.as_secs()
+	};
+
+	let mut fetched = self.versions_fetched.lock().expect("locked");
+	if fetched.len() >= max_addresses && !fetched.contains_key(&ip) {
+		fetched.retain(|_, requests| {
+			requests.retain(current);
+			!requests.is_empty()
+		});
+
+		if fetched.len() >= max_addresses {
+			return;
+		}
+	}
+
+	let requests = fetched.entry(ip).or_default();
+	requests.retain(current);
+	if let Some((_, at)) = requests
+		.iter_mut()
+		.find(|(agent, _)| agent.as_deref() == user_agent)
+	{
+		*at = now;
+	} else if requests.len() < max_user_agents {
+		requests.push((user_agent.map(ToOwned::to_owned), now));
+	}
+}
+
+/// When the versions were last asked for without authentication from the
+/// address and user agent, if recently enough to be credited to a device.
+#[implement(super::Service)]
+fn versions_fetched_by(&self, ip: IpAddr, user_agent: Option<&str>) -> Option<u64> {
+	let now = now_secs();
+	self.versions_fetched
+		.lock()
+		.expect("locked")
+		.get(&ip)?
+		.iter()
+		.find(|(agent, _)| agent.as_deref() == user_agent)
+		.map(|&(_, at)| at)
+		.filter(|&at| now.saturating_sub(at) < VERSIONS_ATTRIBUTION.as_secs())
+}
+
+/// Applies `f` to the cached session of the device, if any.
+#[implement(super::Service)]
+fn cached_session<F, T>(&self, user_id: &UserId, device_id: &DeviceId, f: F) -> Option<T>
+where
+	F: FnOnce(&Session) -> T,
+{
+	self.sessions
+		.lock()
+		.expect("locked")
+		.get(user_id)
+		.and_then(|devices| devices.get(device_id))
+		.map(f)
+}
+
+#[implement(super::Service)]
+fn cache_session(&self, user_id: &UserId, device_id: &DeviceId, session: Session) {
+	self.sessions
+		.lock()
+		.expect("locked")
+		.entry(user_id.to_owned())
+		.or_default()
+		.insert(device_id.to_owned(), session);
+}
+
+#[implement(super::Service)]
+pub(super) fn forget_session(&self, user_id: &UserId, device_id: &DeviceId) {
+	let mut sessions = self.sessions.lock().expect("locked");
+	if let Some(devices) = sessions.get_mut(user_id) {
+		devices.remove(device_id);
+		if devices.is_empty() {
+			sessions.remove(user_id);
+		}
+	}
+}
+
+#[implement(super::Service)]
+pub async fn get_session(&self, user_id: &UserId, device_id: &DeviceId) -> Result<Session> {
+	self.db
+		.userdeviceid_session
+		.qry(&(user_id, device_id))
+		.await
+		.deserialized()
+}
+
+#[implement(super::Service)]
+pub async fn token_kind(&self, user_id: &UserId, device_id: &DeviceId) -> TokenKind {
+	if self
+		.services
+		.appservice
+		.is_exclusive_user_id(user_id)
+		.await
+	{
+		TokenKind::Appservice
+	} else if self
+		.get_refresh_token(user_id, device_id)
+		.await
+		.is_ok()
+	{
+		TokenKind::Refresh
+	} else {
+		TokenKind::Access
+	}
+}
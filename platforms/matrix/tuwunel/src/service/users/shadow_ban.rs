@@ -0,0 +1,53 @@
+//! Shadow-banning. The events of shadow-banned users are accepted and stored
+//! as usual, but not sent to other servers nor pushed to other local users,
+//! and other servers cannot fetch them. Their invites of remote users, typing,
+//! receipts and presence are not sent either. Unlike suspension, the user is
+//! not told.
+
+use futures::Stream;
+use ruma::UserId;
+use tuwunel_core::{
+	Result, implement,
+	utils::{stream::TryIgnore, time::now_secs},
+};
+use tuwunel_database::Deserialized;
+
+/// Shadow-bans a user.
+#[implement(super::Service)]
+pub fn shadow_ban(&self, user_id: &UserId) {
+	self.db
+		.userid_shadowbanned
+		.raw_put(user_id, now_secs());
+}
+
+/// Lifts the shadow-ban of a user. Events sent while shadow-banned are not
+/// sent to other servers afterwards.
+#[implement(super::Service)]
+pub fn unshadow_ban(&self, user_id: &UserId) { self.db.userid_shadowbanned.remove(user_id); }
+
+#[implement(super::Service)]
+pub async fn is_shadow_banned(&self, user_id: &UserId) -> bool {
+	self.services.globals.user_is_local(user_id)
+		&& self
+			.db
+			.userid_shadowbanned
+			.get(user_id)
+			.await
+			.is_ok()
+}
+
+/// When the user was shadow-banned, in seconds since the epoch.
+#[implement(super::Service)]
+pub async fn shadow_banned_at(&self, user_id: &UserId) -> Result<u64> {
+	self.db
+		.userid_shadowbanned
+		.get(user_id)
+		.await
+		.deserialized()
+}
+
+/// The shadow-banned users, with when they were shadow-banned.
+#[implement(super::Service)]
+pub fn shadow_banned(&self) -> impl Stream<Item = (&UserId, u64)> + Send {
+	self.db.userid_shadowbanned.stream().ignore_err()
+}
@@ -0,0 +1,48 @@
+//! Account suspension (MSC3823). Suspended users keep reading their rooms but
+//! may not write to them until they are unsuspended. Unlike deactivation,
+//! nothing is taken from the account.
+
+use futures::Stream;
+use ruma::UserId;
+use tuwunel_core::{
+	Result, implement,
+	utils::{stream::TryIgnore, time::now_secs},
+};
+use tuwunel_database::Deserialized;
+
+/// Suspends a user.
+#[implement(super::Service)]
+pub fn suspend(&self, user_id: &UserId) {
+	self.db
+		.userid_suspended
+		.raw_put(user_id, now_secs());
+}
+
+/// Lifts the suspension of a user.
+#[implement(super::Service)]
+pub fn unsuspend(&self, user_id: &UserId) { self.db.userid_suspended.remove(user_id); }
+
+#[implement(super::Service)]
+pub async fn is_suspended(&self, user_id: &UserId) -> bool {
+	self.db
+		.userid_suspended
+		.get(user_id)
+		.await
+		.is_ok()
+}
+
+/// When the user was suspended, in seconds since the epoch.
+#[implement(super::Service)]
+pub async fn suspended_at(&self, user_id: &UserId) -> Result<u64> {
+	self.db
+		.userid_suspended
+		.get(user_id)
+		.await
+		.deserialized()
+}
+
+/// The suspended users, with when they were suspended.
+#[implement(super::Service)]
+pub fn suspended(&self) -> impl Stream<Item = (&UserId, u64)> + Send {
+	self.db.userid_suspended.stream().ignore_err()
+}
@@ -0,0 +1,153 @@
+//! Third-party identifiers (email addresses and phone numbers) associated with
+//! users. Each is associated with at most one user.
+
+use futures::{Stream, StreamExt};
+use ruma::{
+	MilliSecondsSinceUnixEpoch, OwnedUserId, UInt, UserId,
+	thirdparty::{Medium, ThirdPartyIdentifier, ThirdPartyIdentifierInit},
+};
+use serde::{Deserialize, Serialize};
+use tuwunel_core::{
+	Err, Result, implement,
+	utils::{stream::TryIgnore, time::now_millis},
+};
+use tuwunel_database::{Deserialized, Ignore, Interfix, Json};
+
+#[derive(Deserialize, Serialize)]
+struct Record {
+	/// Milliseconds since the epoch when the user proved they own it.
+	validated_at: u64,
+
+	/// Milliseconds since the epoch when it was associated with the user.
+	added_at: u64,
+}
+
+/// Associates an identifier with the user, validated at the time given in
+/// milliseconds since the epoch. Fails when it is associated with another user.
+/// Changes to an identifier are serialized, so that two users cannot both be
+/// associated with it.
+#[implement(super::Service)]
+pub async fn add_threepid(
+	&self,
+	user_id: &UserId,
+	medium: &Medium,
+	address: &str,
+	validated_at: u64,
+) -> Result {
+	let address = normalize(medium, address);
+	let _lock = self
+		.threepid_mutex
+		.lock(&threepid_key(medium, &address))
+		.await;
+
+	if self
+		.find_from_threepid(medium, &address)
+		.await
+		.is_ok_and(|bound| bound != user_id)
+	{
+		return Err!(Request(ThreepidInUse("{address} is associated with another user.")));
+	}
+
+	let record = Record { validated_at, added_at: now_millis() };
+	self.db
+		.threepid_userid
+		.put((medium.as_str(), &address), user_id);
+
+	self.db
+		.user_threepids
+		.put((user_id, medium.as_str(), &address), Json(record));
+
+	Ok(())
+}
+
+/// Removes the association of an identifier with the user. Returns whether
+/// there was one.
+#[implement(super::Service)]
+pub async fn remove_threepid(&self, user_id: &UserId, medium: &Medium, address: &str) -> bool {
+	let address = normalize(medium, address);
+	let _lock = self
+		.threepid_mutex
+		.lock(&threepid_key(medium, &address))
+		.await;
+
+	let bound = self
+		.find_from_threepid(medium, &address)
+		.await
+		.is_ok_and(|bound| bound == user_id);
+
+	if bound {
+		self.db
+			.threepid_userid
+			.del((medium.as_str(), &address));
+
+		self.db
+			.user_threepids
+			.del((user_id, medium.as_str(), &address));
+	}
+
+	bound
+}
+
+/// Removes the associations of all identifiers with the user.
+#[implement(super::Service)]
+pub async fn remove_threepids(&self, user_id: &UserId) {
+	let threepids: Vec<_> = self.threepids(user_id).collect().await;
+	for threepid in threepids {
+		self.remove_threepid(user_id, &threepid.medium, &threepid.address)
+			.await;
+	}
+}
+
+/// The user an identifier is associated with.
+#[implement(super::Service)]
+pub async fn find_from_threepid(&self, medium: &Medium, address: &str) -> Result<OwnedUserId> {
+	let address = normalize(medium, address);
+	self.db
+		.threepid_userid
+		.qry(&(medium.as_str(), address.as_str()))
+		.await
+		.deserialized()
+}
+
+/// The identifiers associated with the user.
+#[implement(super::Service)]
+pub fn threepids<'a>(
+	&'a self,
+	user_id: &'a UserId,
+) -> impl Stream<Item = ThirdPartyIdentifier> + Send + 'a {
+	self.db
+		.user_threepids
+		.stream_prefix(&(user_id, Interfix))
+		.ignore_err()
+		.map(|((_, medium, address), record): ((Ignore, &str, &str), Record)| {
+			ThirdPartyIdentifierInit {
+				address: address.to_owned(),
+				medium: medium.into(),
+				validated_at: millis(record.validated_at),
+				added_at: millis(record.added_at),
+			}
+			.into()
+		})
+}
+
+/// Email addresses are compared case-insensitively, and phone numbers by their
+/// digits.
+fn normalize(medium: &Medium, address: &str) -> String {
+	match medium {
+		| Medium::Email => address.trim().to_lowercase(),
+		| Medium::Msisdn => address
+			.chars()
+			.filter(char::is_ascii_digit)
+			.collect(),
+		| _ => address.trim().to_owned(),
+	}
+}
+
+fn millis(millis: u64) -> MilliSecondsSinceUnixEpoch {
+	MilliSecondsSinceUnixEpoch(UInt::new_saturating(millis))
+}
+
+/// Key of the lock serializing the changes to an identifier.
+fn threepid_key(medium: &Medium, address: &str) -> String {
+	format!("{}:{address}", medium.as_str())
+}